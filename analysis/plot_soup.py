@@ -27,22 +27,46 @@ def get_soup_number(soup_path):
     nums = re.findall("[0-9]+", base)
     return int(nums[0])
 
+def load_stored_soup(ix):
+    """Reconstruct the soup at epoch ix from the content-addressed store:
+    follow the diffs back to a keyframe, then replay them."""
+    chain = []
+    epoch = ix
+    while epoch is not None:
+        with open(f"soup/epoch_{epoch}.json") as f:
+            record = json.load(f)
+        chain.append(record)
+        epoch = record["parent"]
+    keyframe = chain.pop()
+    with open(f"soup/objects/{keyframe['hash']}.json") as f:
+        soup = {json.dumps(k): v for k, v in json.load(f)}
+    for record in reversed(chain):
+        for k, v in record["changed"]:
+            soup[json.dumps(k)] = v
+        for k in record["removed"]:
+            soup.pop(json.dumps(k), None)
+    return soup
+
 def plot_soup(tsstart=0, tsend=-1, tsstep=1, scale="linear", title=""):
     plt.clf()
     # load the data
-    soupfiles = glob.glob("soup/soup_*.json")
+    stored = len(glob.glob("soup/epoch_*.json")) > 0
+    soupfiles = glob.glob("soup/epoch_*.json") if stored else glob.glob("soup/soup_*.json")
     assert(len(soupfiles)>0)
     soupindexes = sorted([get_soup_number(s) for s in soupfiles])
     soupdfs = []
-    x_label = "timestep"
+    x_label = "epoch" if stored else "timestep"
     for ix in soupindexes[tsstart:tsend:tsstep]:
-        try:
-            with open(f"soup/soup_{ix}.json") as f:
-                soup = dict(json.load(f))
-        except:
-            with open(f"soup/soup_at_epoch_{ix}.json") as f:
-                soup = dict(json.load(f))
-                x_label = "epoch"
+        if stored:
+            soup = load_stored_soup(ix)
+        else:
+            try:
+                with open(f"soup/soup_{ix}.json") as f:
+                    soup = dict(json.load(f))
+            except:
+                with open(f"soup/soup_at_epoch_{ix}.json") as f:
+                    soup = dict(json.load(f))
+                    x_label = "epoch"
         soupdf = pandas.DataFrame({k:soup[k] for k in soup if soup[k] >= 100}, index=[ix])
         soupdfs.append(soupdf)
     soup = pandas.concat(soupdfs)
@@ -0,0 +1,22 @@
+use std::process::Command;
+
+/// Records the commit berbalang was built from, for the run manifest.
+fn main() {
+    let commit = Command::new("git")
+        .args(&["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string());
+    if let Some(commit) = commit {
+        let dirty = Command::new("git")
+            .args(&["status", "--porcelain", "--untracked-files=no"])
+            .output()
+            .map(|out| !out.stdout.is_empty())
+            .unwrap_or(false);
+        let suffix = if dirty { "-dirty" } else { "" };
+        println!("cargo:rustc-env=BERBALANG_GIT_COMMIT={}{}", commit, suffix);
+    }
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+}
@@ -0,0 +1,319 @@
+//! Merges the reports of all the islands of a population into global
+//! statistics. Each island's observer posts a summary of its window when it
+//! reports, and once every island still running has posted since the last
+//! round, the global statistics are logged, and appended to
+//! `global_statistics.csv`, in the population's directory, just above the
+//! island subdirectories. An island that stops early is retired, and left
+//! out of the rounds that follow.
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::{Mutex, Once};
+
+use hashbrown::HashSet;
+use serde::Serialize;
+
+use crate::configure::Config;
+use crate::evolution::{Genome, Phenome};
+use crate::observer::{append_record_to, LogRecord};
+
+#[derive(Debug, Clone)]
+pub struct IslandSummary {
+    pub island: usize,
+    pub epoch: usize,
+    pub counter: usize,
+    pub best_fitness: Option<f64>,
+    pub best_name: Option<String>,
+    /// The number of specimens in the window native to each other island
+    pub immigrants: BTreeMap<usize, usize>,
+    /// The hashes of the alleles in the window
+    pub alleles: HashSet<u64>,
+}
+
+impl IslandSummary {
+    pub fn of<O: Phenome + Genome>(
+        frame: &[O],
+        best: Option<&O>,
+        config: &Config,
+        counter: usize,
+        epoch: usize,
+    ) -> Self {
+        let mut immigrants = BTreeMap::new();
+        let mut alleles = HashSet::new();
+        for specimen in frame {
+            let native = specimen.native_island();
+            if native != config.island_id {
+                *immigrants.entry(native).or_insert(0) += 1;
+            }
+            for allele in specimen.chromosome() {
+                let mut h = fnv::FnvHasher::default();
+                allele.hash(&mut h);
+                alleles.insert(h.finish());
+            }
+        }
+        Self {
+            island: config.island_id,
+            epoch,
+            counter,
+            best_fitness: best.and_then(|b| b.scalar_fitness(&config.fitness.weighting)),
+            best_name: best.map(|b| b.name().to_string()),
+            immigrants,
+            alleles,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GlobalStats {
+    pub round: usize,
+    pub global_epoch: usize,
+    pub best_fitness: f64,
+    pub best_island: Option<usize>,
+    pub best_name: Option<String>,
+    /// The number of specimens native to each island observed in the other
+    /// islands' windows, indexed by island
+    pub emigrants: Vec<usize>,
+    /// The mean Jaccard distance between the islands' sets of alleles
+    pub diversity: f64,
+}
+
+impl GlobalStats {
+    pub fn of(
+        summaries: &BTreeMap<usize, IslandSummary>,
+        num_islands: usize,
+        round: usize,
+    ) -> Self {
+        let best = summaries
+            .values()
+            .filter(|s| s.best_fitness.is_some())
+            .min_by(|a, b| {
+                a.best_fitness
+                    .partial_cmp(&b.best_fitness)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+        let mut emigrants = vec![0; num_islands];
+        for summary in summaries.values() {
+            for (native, n) in summary.immigrants.iter() {
+                if *native < num_islands {
+                    emigrants[*native] += n;
+                }
+            }
+        }
+        let islands = summaries.values().collect::<Vec<_>>();
+        let mut distance = 0.0;
+        let mut pairs = 0;
+        for i in 0..islands.len() {
+            for j in (i + 1)..islands.len() {
+                distance += jaccard_distance(&islands[i].alleles, &islands[j].alleles);
+                pairs += 1;
+            }
+        }
+        Self {
+            round,
+            global_epoch: crate::get_epoch_counter(),
+            best_fitness: best.and_then(|b| b.best_fitness).unwrap_or(std::f64::NAN),
+            best_island: best.map(|b| b.island),
+            best_name: best.and_then(|b| b.best_name.clone()),
+            emigrants,
+            diversity: if pairs > 0 {
+                distance / pairs as f64
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+impl LogRecord for GlobalStats {
+    fn header(&self) -> String {
+        let mut header =
+            "round,global_epoch,best_fitness,best_island,best_name,diversity".to_string();
+        for i in 0..self.emigrants.len() {
+            header.push_str(&format!(",emigrants_{}", i));
+        }
+        header
+    }
+
+    fn row(&self) -> String {
+        let mut row = format!(
+            "{},{},{},{},{},{}",
+            self.round,
+            self.global_epoch,
+            self.best_fitness,
+            self.best_island.map(|i| i.to_string()).unwrap_or_default(),
+            self.best_name.as_ref().map(|s| s.as_str()).unwrap_or(""),
+            self.diversity
+        );
+        for n in self.emigrants.iter() {
+            row.push_str(&format!(",{}", n));
+        }
+        row
+    }
+}
+
+fn jaccard_distance(a: &HashSet<u64>, b: &HashSet<u64>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    1.0 - a.intersection(b).count() as f64 / union as f64
+}
+
+#[derive(Default)]
+struct Aggregator {
+    round: usize,
+    summaries: BTreeMap<usize, IslandSummary>,
+    fresh: HashSet<usize>,
+    /// The islands that have stopped, and won't be posting again
+    retired: HashSet<usize>,
+}
+
+impl Aggregator {
+    fn post(&mut self, summary: IslandSummary, num_islands: usize) -> Option<GlobalStats> {
+        if self.retired.contains(&summary.island) {
+            return None;
+        }
+        self.fresh.insert(summary.island);
+        self.summaries.insert(summary.island, summary);
+        self.try_round(num_islands)
+    }
+
+    /// Drops the island, and its last summary, from the rounds to come. This
+    /// may complete the current round, if the island was all it waited on.
+    fn retire(&mut self, island: usize, num_islands: usize) -> Option<GlobalStats> {
+        self.retired.insert(island);
+        self.fresh.remove(&island);
+        self.summaries.remove(&island);
+        self.try_round(num_islands)
+    }
+
+    /// The global statistics, if every island still running has posted
+    /// since they were last emitted.
+    fn try_round(&mut self, num_islands: usize) -> Option<GlobalStats> {
+        let waiting = (0..num_islands)
+            .filter(|i| !self.retired.contains(i))
+            .any(|i| !self.fresh.contains(&i));
+        if waiting || self.fresh.is_empty() {
+            return None;
+        }
+        self.fresh.clear();
+        self.round += 1;
+        Some(GlobalStats::of(&self.summaries, num_islands, self.round))
+    }
+}
+
+static INIT_AGGREGATOR: Once = Once::new();
+static mut AGGREGATOR: Option<Mutex<Aggregator>> = None;
+
+fn aggregator() -> &'static Mutex<Aggregator> {
+    INIT_AGGREGATOR.call_once(|| unsafe { AGGREGATOR = Some(Mutex::new(Aggregator::default())) });
+    unsafe {
+        AGGREGATOR
+            .as_ref()
+            .expect("The aggregator has not been initialized")
+    }
+}
+
+/// Posts an island's summary, emitting the global statistics if every
+/// island still running has now reported since they were last emitted.
+pub fn post(summary: IslandSummary, config: &Config) {
+    let stats = aggregator()
+        .lock()
+        .expect("poisoned")
+        .post(summary, config.num_islands);
+    if let Some(stats) = stats {
+        emit(&stats, config);
+    }
+}
+
+/// Notes that an island has stopped, so that the global statistics are
+/// taken over the islands still running, rather than waiting on it.
+pub fn retire(config: &Config) {
+    let stats = aggregator()
+        .lock()
+        .expect("poisoned")
+        .retire(config.island_id, config.num_islands);
+    if let Some(stats) = stats {
+        emit(&stats, config);
+    }
+}
+
+fn emit(stats: &GlobalStats, config: &Config) {
+    log::info!("Global statistics: {:?}", stats);
+    let population_dir = Path::new(config.data_directory())
+        .parent()
+        .unwrap_or_else(|| Path::new("."));
+    let filename = population_dir.join("global_statistics.csv");
+    append_record_to(stats, &filename.to_string_lossy());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn summary(
+        island: usize,
+        best: f64,
+        immigrants: &[(usize, usize)],
+        alleles: &[u64],
+    ) -> IslandSummary {
+        IslandSummary {
+            island,
+            epoch: 1,
+            counter: 100,
+            best_fitness: Some(best),
+            best_name: Some(format!("island-{}-best", island)),
+            immigrants: immigrants.iter().cloned().collect(),
+            alleles: alleles.iter().cloned().collect(),
+        }
+    }
+
+    #[test]
+    fn test_global_stats() {
+        let summaries = vec![
+            summary(0, 0.5, &[(1, 3)], &[1, 2, 3, 4]),
+            summary(1, 0.25, &[(0, 2), (2, 1)], &[1, 2]),
+            summary(2, 0.75, &[], &[5, 6]),
+        ]
+        .into_iter()
+        .map(|s| (s.island, s))
+        .collect::<BTreeMap<_, _>>();
+        let stats = GlobalStats::of(&summaries, 3, 1);
+        assert_eq!(stats.best_island, Some(1));
+        assert_eq!(stats.best_fitness, 0.25);
+        assert_eq!(stats.emigrants, vec![2, 3, 1]);
+        // distances: 0-1 = 0.5, 0-2 = 1.0, 1-2 = 1.0
+        assert!((stats.diversity - 2.5 / 3.0).abs() < 1e-9);
+        assert_eq!(
+            stats.row(),
+            format!(
+                "1,{},0.25,1,island-1-best,{},2,3,1",
+                stats.global_epoch, stats.diversity
+            )
+        );
+    }
+
+    #[test]
+    fn test_retired_islands() {
+        let mut aggregator = Aggregator::default();
+        assert!(aggregator.post(summary(0, 0.5, &[], &[1]), 3).is_none());
+        assert!(aggregator.post(summary(1, 0.25, &[], &[1]), 3).is_none());
+        // the round was waiting on island 2 alone
+        let stats = aggregator
+            .retire(2, 3)
+            .expect("the round should be complete");
+        assert_eq!(stats.round, 1);
+        assert_eq!(stats.best_island, Some(1));
+        // and the next one waits on the islands still running, ignoring any
+        // word from a retired one
+        assert!(aggregator.post(summary(1, 0.25, &[], &[1]), 3).is_none());
+        assert!(aggregator.post(summary(2, 0.0, &[], &[1]), 3).is_none());
+        assert!(aggregator.retire(1, 3).is_none());
+        let stats = aggregator
+            .post(summary(0, 0.5, &[], &[1]), 3)
+            .expect("the last island makes up a round");
+        assert_eq!(stats.round, 2);
+        assert_eq!(stats.best_island, Some(0));
+        assert!(aggregator.retire(0, 3).is_none());
+    }
+}
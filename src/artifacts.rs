@@ -0,0 +1,162 @@
+//! Publishing a run's artifacts -- its champion bundles, as they're
+//! crowned, and its reports and manifest, when it ends -- apart from the
+//! working data in its data directory, to the `observer.artifact_sink`,
+//! which is either a local directory,
+//!
+//! ```text
+//! [observer]
+//! artifact_sink = "/mnt/shared/artifacts"
+//! ```
+//!
+//! or an S3 URL, uploaded to with the `aws` command line tool, which takes
+//! its credentials from the environment as it usually would:
+//!
+//! ```text
+//! [observer]
+//! artifact_sink = "s3://my-bucket/berbalang"
+//! ```
+//!
+//! Each artifact is published under the population's name, at the same
+//! path as it has beneath the run's directory, so that
+//! `{run}/island_0/champions/champion_100.bundle.json.gz` becomes
+//! `{sink}/{population_name}/island_0/champions/champion_100.bundle.json.gz`.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::configure::Config;
+use crate::error::Error;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Sink {
+    Local(PathBuf),
+    /// The bucket and key prefix, as an `s3://` URL, without a trailing
+    /// slash
+    S3(String),
+}
+
+impl Sink {
+    pub fn parse(sink: &str) -> Result<Self, Error> {
+        if sink.starts_with("s3://") {
+            let url = sink.trim_end_matches('/');
+            if url.len() <= "s3://".len() {
+                return Err(Error::Parsing(format!(
+                    "The artifact sink {:?} names no bucket",
+                    sink
+                )));
+            }
+            Ok(Sink::S3(url.to_string()))
+        } else if sink.contains("://") {
+            Err(Error::Parsing(format!(
+                "The artifact sink {:?} should be a local path or an s3:// URL",
+                sink
+            )))
+        } else {
+            let mut dir = sink.to_string();
+            if dir.starts_with('~') {
+                let home = std::env::var("HOME")
+                    .expect("No HOME environment variable found. Please set this.");
+                dir.replace_range(0..1, &home);
+            }
+            Ok(Sink::Local(PathBuf::from(dir)))
+        }
+    }
+
+    /// Where the artifact at `relative` -- its path beneath the run's
+    /// directory -- is published.
+    fn destination(&self, population_name: &str, relative: &Path) -> String {
+        match self {
+            Sink::Local(dir) => dir
+                .join(population_name)
+                .join(relative)
+                .to_string_lossy()
+                .into_owned(),
+            Sink::S3(url) => format!("{}/{}/{}", url, population_name, relative.display()),
+        }
+    }
+
+    fn copy(&self, path: &Path, destination: &str) -> Result<(), Error> {
+        match self {
+            Sink::Local(_) => {
+                if let Some(parent) = Path::new(destination).parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(path, destination)?;
+            }
+            Sink::S3(_) => {
+                let status = Command::new("aws")
+                    .args(&["s3", "cp", "--only-show-errors"])
+                    .arg(path)
+                    .arg(destination)
+                    .stdout(Stdio::null())
+                    .status()?;
+                if !status.success() {
+                    return Err(Error::Misc(format!("aws s3 cp exited with {}", status)));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Publishes the files at `paths` -- which should lie beneath the run's
+/// directory, the parent of the island directories -- to the config's
+/// artifact sink, if it has one, logging, rather than returning, any
+/// failure, so that a run is never stopped for want of a network.
+pub fn publish<P: AsRef<Path>>(config: &Config, paths: &[P]) {
+    let sink = match config.observer.artifact_sink {
+        Some(ref sink) => sink,
+        None => return,
+    };
+    let sink = match Sink::parse(sink) {
+        Ok(sink) => sink,
+        Err(e) => {
+            log::error!("Not publishing artifacts: {:?}", e);
+            return;
+        }
+    };
+    let run_directory = Path::new(config.data_directory())
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .to_path_buf();
+    for path in paths {
+        let path = path.as_ref();
+        if !path.exists() {
+            continue;
+        }
+        let relative = path.strip_prefix(&run_directory).unwrap_or(path);
+        let destination = sink.destination(&config.observer.population_name, relative);
+        match sink.copy(path, &destination) {
+            Ok(()) => log::info!("Published {} to {}", path.display(), destination),
+            Err(e) => log::error!(
+                "Failed to publish {} to {}: {:?}",
+                path.display(),
+                destination,
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sinks() {
+        let sink = Sink::parse("s3://bucket/prefix/").unwrap();
+        assert_eq!(sink, Sink::S3("s3://bucket/prefix".to_string()));
+        assert_eq!(
+            sink.destination("pop", Path::new("island_0/report.html")),
+            "s3://bucket/prefix/pop/island_0/report.html"
+        );
+        assert_eq!(
+            Sink::parse("/tmp/artifacts")
+                .unwrap()
+                .destination("pop", Path::new("manifest.json")),
+            "/tmp/artifacts/pop/manifest.json"
+        );
+        assert!(Sink::parse("s3://").is_err());
+        assert!(Sink::parse("gs://bucket").is_err());
+    }
+}
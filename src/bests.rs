@@ -0,0 +1,156 @@
+//! The best score seen for each fitness component, and for the scalar
+//! fitness, both over the whole run and within the current epoch. The
+//! window's statistics only say what's best among the specimens it holds,
+//! which forgets a good specimen as soon as it's pushed out, so the
+//! observer tracks these alongside, and writes them to
+//! `bests_statistics.csv` at the end of each epoch, one row per objective.
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::observer::LogRecord;
+
+/// The name under which the scalar fitness is tracked.
+pub const SCALAR: &str = "scalar";
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Best {
+    pub score: f64,
+    /// The specimen that scored it
+    pub name: String,
+    /// The local epoch, and counter, at which it was found
+    pub epoch: usize,
+    pub counter: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Bests {
+    pub all_time: BTreeMap<String, Best>,
+    pub this_epoch: BTreeMap<String, Best>,
+}
+
+fn improve(bests: &mut BTreeMap<String, Best>, key: &str, candidate: &Best) -> bool {
+    match bests.get_mut(key) {
+        Some(best) if best.score <= candidate.score => false,
+        Some(best) => {
+            *best = candidate.clone();
+            true
+        }
+        None => {
+            bests.insert(key.to_string(), candidate.clone());
+            true
+        }
+    }
+}
+
+impl Bests {
+    /// Takes account of a specimen's scores, lower being better, returning
+    /// the objectives on which it set an all-time best.
+    pub fn observe<'a, I>(
+        &mut self,
+        scores: I,
+        name: &str,
+        epoch: usize,
+        counter: usize,
+    ) -> Vec<String>
+    where
+        I: IntoIterator<Item = (&'a str, f64)>,
+    {
+        let mut improved = Vec::new();
+        for (key, score) in scores {
+            if score.is_nan() {
+                continue;
+            }
+            let candidate = Best {
+                score,
+                name: name.to_string(),
+                epoch,
+                counter,
+            };
+            improve(&mut self.this_epoch, key, &candidate);
+            if improve(&mut self.all_time, key, &candidate) {
+                improved.push(key.to_string());
+            }
+        }
+        improved
+    }
+
+    /// Returns a record of each objective's bests, for the epoch that has
+    /// just ended, and starts afresh on the next.
+    pub fn end_epoch(&mut self, island: usize, epoch: usize) -> Vec<BestRecord> {
+        let this_epoch = std::mem::replace(&mut self.this_epoch, BTreeMap::new());
+        self.all_time
+            .iter()
+            .map(|(key, all_time)| BestRecord {
+                island,
+                epoch,
+                objective: key.clone(),
+                epoch_best: this_epoch.get(key).map(|b| b.score),
+                best_so_far: all_time.clone(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BestRecord {
+    pub island: usize,
+    pub epoch: usize,
+    pub objective: String,
+    /// The best score on the objective among the specimens of the epoch,
+    /// if any were scored on it
+    pub epoch_best: Option<f64>,
+    pub best_so_far: Best,
+}
+
+impl LogRecord for BestRecord {
+    fn header(&self) -> String {
+        "island,epoch,objective,epoch_best,best_so_far,best_so_far_name,\
+         best_so_far_epoch,best_so_far_counter"
+            .to_string()
+    }
+
+    fn row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{}",
+            self.island,
+            self.epoch,
+            self.objective,
+            self.epoch_best.map(|s| s.to_string()).unwrap_or_default(),
+            self.best_so_far.score,
+            self.best_so_far.name,
+            self.best_so_far.epoch,
+            self.best_so_far.counter
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bests() {
+        let mut bests = Bests::default();
+        let improved = bests.observe(vec![("a", 2.0), (SCALAR, 5.0)], "alice", 0, 1);
+        assert_eq!(improved, vec!["a".to_string(), SCALAR.to_string()]);
+        let improved = bests.observe(vec![("a", 1.0), (SCALAR, 6.0)], "bob", 0, 2);
+        assert_eq!(improved, vec!["a".to_string()]);
+        let records = bests.end_epoch(0, 0);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].epoch_best, Some(1.0));
+        assert_eq!(records[0].best_so_far.name, "bob");
+
+        // a worse epoch leaves the all-time bests alone
+        assert!(bests
+            .observe(vec![("a", 3.0), ("b", std::f64::NAN)], "carol", 1, 3)
+            .is_empty());
+        let records = bests.end_epoch(0, 1);
+        assert_eq!(records[0].epoch_best, Some(3.0));
+        assert_eq!(records[0].best_so_far.score, 1.0);
+        assert_eq!(records[0].best_so_far.epoch, 0);
+        assert_eq!(records[1].objective, SCALAR);
+        assert_eq!(records[1].epoch_best, None);
+        assert_eq!(records[1].best_so_far.name, "alice");
+    }
+}
@@ -0,0 +1,29 @@
+use std::process::exit;
+
+use berbalib::genealogy;
+use berbalib::logger;
+
+/// Exports the ancestry of a specimen -- typically a champion -- from a
+/// genealogy log, as a GraphViz DOT graph, printed to stdout.
+pub fn main() {
+    let argv = std::env::args().collect::<Vec<String>>();
+    if argv.len() < 3 {
+        println!(
+            "Usage: {} <genealogy.jsonl> <specimen name> [<max depth>]",
+            argv[0]
+        );
+        exit(1);
+    }
+    logger::init("ancestry");
+    let births = genealogy::load(&argv[1]).expect("Failed to load genealogy");
+    let max_depth = argv
+        .get(3)
+        .map(|d| d.parse::<usize>().expect("Failed to parse max depth"));
+    let lineage = genealogy::ancestry(&births, &argv[2], max_depth);
+    if lineage.is_empty() {
+        log::error!("No specimen named {} in {}", argv[2], argv[1]);
+        exit(1);
+    }
+    log::info!("Found {} ancestors of {}", lineage.len() - 1, argv[2]);
+    print!("{}", genealogy::to_dot(&lineage, &argv[2]));
+}
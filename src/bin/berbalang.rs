@@ -1,46 +1,162 @@
-use berbalib::configure::{Config, Job};
-use berbalib::examples::{hello_world, linear_gp};
-use berbalib::{limit_threads, logger, roper, set_starting_timestamp, set_timeout};
+use std::path::Path;
+use std::time::Duration;
 
+use berbalib::configure::{Config, ConfigFormat};
+use berbalib::{experiments, logger, roper, schema, sweep, Berbalang};
+use structopt::StructOpt;
+
+/// Runs the experiment described by a config file. The subcommands
+/// `aggregate`, `default-config`, `replay`, and `sweep` are taken before
+/// these arguments are parsed.
+#[derive(StructOpt)]
+#[structopt(name = "berbalang")]
+struct Opt {
+    /// The config file, in TOML, YAML, or JSON
+    #[structopt(default_value = "./config.toml")]
+    config: String,
+    /// The format of the config file: "toml", "yaml", or "json". By
+    /// default, it's implied by the file's extension.
+    #[structopt(long, parse(try_from_str = parse_format))]
+    format: Option<ConfigFormat>,
+    /// The name of the population, in place of the one in the config
+    population_name: Option<String>,
+    /// Overrides a field of the config, by its dotted path, as in
+    /// `--set fitness.weighting="place + ret_count"`. May be repeated.
+    #[structopt(long = "set", number_of_values = 1)]
+    overrides: Vec<String>,
+    /// Runs the config this many times, each with a seed of its own, and
+    /// summarizes the results across the seeds. See `sweep::repeat`.
+    #[structopt(long, default_value = "1")]
+    repeat: usize,
+    /// The number of repeated runs at once
+    #[structopt(long, default_value = "1")]
+    parallel: usize,
+    /// Checks the config, and, for ROPER, that a population can be built
+    /// and evaluated from it, without running the experiment. See
+    /// `roper::dry_run`.
+    #[structopt(long)]
+    dry_run: bool,
+    /// Resumes the run laid out in this directory -- the parent of its
+    /// island directories -- as recorded in its manifest, restoring each
+    /// island from its last checkpoint. See `resume`.
+    #[structopt(long)]
+    resume: Option<String>,
+    /// Resumes the run even if the config has drifted from the one it was
+    /// started with
+    #[structopt(long)]
+    allow_config_drift: bool,
+}
+
+fn parse_format(s: &str) -> Result<ConfigFormat, String> {
+    s.parse().map_err(|e| format!("{:?}", e))
+}
 
 fn main() {
     coredump::register_panic_handler().expect("Failed to register panic handler.");
-    // TODO add standard cli
-    let config_file = std::env::args()
-        .nth(1)
-        .unwrap_or_else(|| "./config.toml".to_string());
-    let population_name = std::env::args().nth(2);
-    let mut config = Config::from_path(&config_file, population_name)
-        .unwrap_or_else(|e| panic!("Failed to generate Config from {:?}: {:?}", &config_file, e));
-    logger::init(&config.observer.population_name);
-    set_starting_timestamp();
-    if let Some(ref timeout) = config.timeout {
-        set_timeout(timeout);
+    let args = std::env::args().collect::<Vec<String>>();
+    if args.get(1).map(String::as_str) == Some("aggregate") {
+        logger::init("aggregate");
+        if let Err(e) = experiments::run_cli(&args[2..]) {
+            eprintln!("{:?}", e);
+            std::process::exit(1);
+        }
+        return;
     }
-
-    // config.roper.parse_register_patterns();
-    if let Ok(n) = std::env::var("BERBALANG_LIMIT_THREADS") {
-        limit_threads(
-            n.parse()
-                .expect("Invalid value for BERBALANG_LIMIT_THREADS"),
-            &mut config,
-        );
-    } else if cfg!(feature = "disassemble_trace") {
-        limit_threads(1, &mut config);
+    if args.get(1).map(String::as_str) == Some("default-config") {
+        if let Err(e) = schema::run_cli(&args[2..]) {
+            eprintln!("{:?}", e);
+            std::process::exit(1);
+        }
+        return;
     }
-
-    match config.job {
-        Job::LinearGp => {
-            linear_gp::run(config);
+    if args.get(1).map(String::as_str) == Some("replay") {
+        logger::init("replay");
+        if let Err(e) = roper::replay::run_cli(&args[2..]) {
+            eprintln!("{:?}", e);
+            std::process::exit(1);
         }
-        Job::Hello => {
-            hello_world::run(config);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("sweep") {
+        logger::init("sweep");
+        if let Err(e) = sweep::run_cli(&args[2..]) {
+            eprintln!("{:?}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let opt = Opt::from_args();
+    if opt.repeat > 1 {
+        logger::init("repeat");
+        if let Err(e) = sweep::repeat(
+            Path::new(&opt.config),
+            opt.format,
+            opt.population_name,
+            &opt.overrides,
+            opt.repeat,
+            opt.parallel,
+        ) {
+            eprintln!("{:?}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let runner = match opt.resume {
+        _ if opt.dry_run => Config::load(
+            &opt.config,
+            opt.format,
+            opt.population_name.clone(),
+            &opt.overrides,
+        )
+        .map(Berbalang::new)
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to load {}: {:?}", opt.config, e);
+            std::process::exit(1)
+        }),
+        Some(ref run_directory) => Berbalang::resume(
+            Path::new(&opt.config),
+            opt.format,
+            &opt.overrides,
+            Path::new(run_directory),
+            opt.allow_config_drift,
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to resume the run in {}: {:?}", run_directory, e);
+            std::process::exit(1)
+        }),
+        None => Berbalang::from_path(&opt.config, opt.format, opt.population_name, &opt.overrides)
+            .unwrap_or_else(|e| {
+                panic!("Failed to generate Config from {:?}: {:?}", &opt.config, e)
+            }),
+    };
+    let runner = runner.with_logger(true);
+    if opt.dry_run {
+        match runner.dry_run() {
+            Ok(report) => println!("{}Dry run passed", report),
+            Err(e) => {
+                eprintln!("Dry run failed: {:?}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    match runner.with_signal_handlers(true).run() {
+        Ok(outcome) => {
+            if let Some(champion) = outcome.champion() {
+                log::info!(
+                    "The champion of {} is {}, of scalar fitness {:?}",
+                    outcome.population_name,
+                    champion.name,
+                    champion.scalar_fitness
+                );
+            }
         }
-        Job::Roper => {
-            roper::run(config);
+        Err(e) => {
+            eprintln!("The run failed: {:?}", e);
+            std::process::exit(1);
         }
     }
 
     log::info!("Waiting 3 seconds for file writes to complete...");
-    std::thread::sleep(std::time::Duration::from_secs(3));
+    std::thread::sleep(Duration::from_secs(3));
 }
@@ -0,0 +1,27 @@
+use std::process::exit;
+
+use berbalib::logger;
+use berbalib::roper::soup::Soup;
+
+/// Merges the soups from several runs into a single structured soup, which
+/// can then be curated by hand and passed back in as the `gadget_file`.
+///
+/// Accepts structured `.soup.json` files, the soups dumped by the observer,
+/// and plain lists of words.
+pub fn main() {
+    let argv = std::env::args().collect::<Vec<String>>();
+    if argv.len() < 3 {
+        println!("Usage: {} <output.soup.json> <soup> [<soup> ...]", argv[0]);
+        exit(1);
+    }
+    logger::init("merge_soups");
+    let output = &argv[1];
+    let mut soup = Soup::new();
+    for path in &argv[2..] {
+        let other = Soup::load(path).expect("Failed to load soup");
+        log::info!("Loaded {} words from {}", other.len(), path);
+        soup.merge(other);
+    }
+    log::info!("Merged soup has {} words", soup.len());
+    soup.save(output).expect("Failed to save soup");
+}
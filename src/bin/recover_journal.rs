@@ -0,0 +1,44 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::process::exit;
+
+use berbalib::journal;
+use berbalib::logger;
+
+/// Recovers the intact records from an observation journal, as written by
+/// the "journal" sink, after a crash. The records are written, one JSON
+/// object per line, in the format of `observations.jsonl`, to the output
+/// file if one is given, or to stdout.
+pub fn main() {
+    let argv = std::env::args().collect::<Vec<String>>();
+    if argv.len() < 2 {
+        println!("Usage: {} <journal.log> [<output.jsonl>]", argv[0]);
+        exit(1);
+    }
+    logger::init("recover_journal");
+    let recovery = journal::recover(&argv[1]).expect("Failed to read journal");
+    log::info!(
+        "Recovered {} entries from {}",
+        recovery.entries.len(),
+        argv[1]
+    );
+    if !recovery.corrupt.is_empty() {
+        log::warn!(
+            "Dropped {} damaged entries, on lines {:?}",
+            recovery.corrupt.len(),
+            recovery.corrupt
+        );
+    }
+    if recovery.torn_tail {
+        log::warn!("The last entry was cut short, and has been dropped");
+    }
+    let mut out: Box<dyn Write> = match argv.get(2) {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).expect("Failed to create output file"),
+        )),
+        None => Box::new(BufWriter::new(std::io::stdout())),
+    };
+    for (_, record) in recovery.entries.iter() {
+        writeln!(out, "{}", record).expect("Failed to write record");
+    }
+}
@@ -94,6 +94,7 @@ fn emulate<C: 'static + Cpu<'static>>(config: Config, chain: Vec<u64>) {
         config
             .roper
             .registers_to_check()
+            .expect("Failed to find the registers to check")
             .into_iter()
             // running error through ok() because it can't be formatted with Debug
             .map(|r| r.parse().ok().expect("Failed to parse register name"))
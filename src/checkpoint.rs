@@ -0,0 +1,119 @@
+//! Checkpoints of each island's run, from which `--resume` takes it up
+//! again. See `resume`. Every `observer.checkpoint_every` epochs, an island
+//! evolving by tournament writes its population, its soup, and the epoch
+//! its observer has reached to `checkpoint.json.gz`, in its data directory,
+//! in place of its last checkpoint.
+//!
+//! The specimens are kept by their genomes. Their profiles and fitnesses
+//! aren't, and they're evaluated again when they're next chosen for a
+//! tournament. Nor are the observer's window, the island's random number
+//! generators, or the emigrants waiting on the pier kept, so a resumed run
+//! doesn't retrace the steps the interrupted one would have taken. The
+//! epochs between the last checkpoint and the interruption are run again,
+//! and their statistics logged a second time.
+use std::path::{Path, PathBuf};
+
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::{Deserialize, Serialize};
+
+use crate::configure::Config;
+use crate::error::Error;
+use crate::util::dump::{dump, undump};
+
+pub const FILE_NAME: &str = "checkpoint.json.gz";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint<P> {
+    pub island: usize,
+    /// The island's epoch, as its observer had counted it
+    pub epoch: usize,
+    /// The number of tournaments the island had held
+    pub iteration: usize,
+    /// The words the genomes are drawn from, in ROPER runs, as
+    /// `roper::init_soup` gathered them
+    pub soup: Option<Vec<u64>>,
+    pub population: Vec<P>,
+}
+
+/// The path of the checkpoint in an island's data directory.
+pub fn path<D: AsRef<Path>>(data_directory: D) -> PathBuf {
+    data_directory.as_ref().join(FILE_NAME)
+}
+
+impl<P: Serialize> Checkpoint<P> {
+    /// Writes the checkpoint in place of the island's last, by way of a
+    /// temporary file, as `dump` does.
+    pub fn write(&self, config: &Config) -> Result<(), Error> {
+        dump(self, path(config.data_directory()))
+    }
+}
+
+impl<P: DeserializeOwned> Checkpoint<P> {
+    /// Reads the island's checkpoint, if it has one.
+    pub fn read(config: &Config) -> Result<Option<Self>, Error> {
+        read(&path(config.data_directory()))
+    }
+}
+
+fn read<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    undump(path).map(Some)
+}
+
+/// Checks that the checkpoint at `path` can be read, without reading its
+/// specimens as any type in particular, and returns its epoch.
+pub fn check(path: &Path) -> Result<usize, Error> {
+    let checkpoint: Checkpoint<IgnoredAny> = undump(path)?;
+    Ok(checkpoint.epoch)
+}
+
+/// Takes up the island's checkpoint, if the run is being resumed, and the
+/// island has one. Its soup and epoch are set in the config, for the world
+/// and the observer to take up, and the rest is returned, for
+/// `Tournament::resume`.
+pub fn restore<P: DeserializeOwned>(config: &mut Config) -> Option<Checkpoint<P>> {
+    config.observer.resume_directory.as_ref()?;
+    let mut checkpoint = Checkpoint::<P>::read(config)
+        .expect("The checkpoint was checked when the run was resumed")?;
+    if let Some(soup) = checkpoint.soup.take() {
+        config.roper.soup = Some(soup);
+    }
+    config.observer.resumed_epoch = checkpoint.epoch;
+    log::info!(
+        "Resuming island {} from its checkpoint, at epoch {}, with {} specimens",
+        config.island_id,
+        checkpoint.epoch,
+        checkpoint.population.len()
+    );
+    Some(checkpoint)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_and_check() {
+        let dir = std::env::temp_dir().join(format!("berbalang_test_{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = path(&dir);
+        assert!(read::<Checkpoint<u64>>(&path).unwrap().is_none());
+        let checkpoint = Checkpoint {
+            island: 1,
+            epoch: 20,
+            iteration: 5000,
+            soup: Some(vec![0x1000, 0x2000]),
+            population: vec![3_u64, 1, 2],
+        };
+        dump(&checkpoint, &path).unwrap();
+        assert_eq!(check(&path).unwrap(), 20);
+        let restored: Checkpoint<u64> = read(&path).unwrap().unwrap();
+        assert_eq!(restored.population, vec![3, 1, 2]);
+        assert_eq!(restored.soup, Some(vec![0x1000, 0x2000]));
+        std::fs::write(&path, "not a checkpoint").unwrap();
+        assert!(check(&path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
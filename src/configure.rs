@@ -1,17 +1,95 @@
 use std::cmp::Ordering;
 use std::fmt::Debug;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
 use chrono::prelude::*;
 use hashbrown::{HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 
-use crate::emulator::register_pattern::{parse_register_pattern_file, RegisterPattern};
+use crate::emulator::abi::{self, Abi, SyscallAbi};
+use crate::emulator::register_pattern::{
+    parse_register_pattern_file, RegisterPattern, RegisterPatternConfig,
+};
 use crate::error::Error;
+use crate::events::EventKind;
+use crate::fitness::Weighted;
+use crate::layout;
+use crate::util::affinity;
+use crate::util::random::{derive_seed, ISLAND_STREAM};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DataFormat {
+    /// Delimited text, with tabs as the delimiter unless the file ends in
+    /// `.csv`
+    Csv,
+    /// One JSON array of values per line
+    JsonLines,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum ColumnType {
+    Int,
+    Float,
+    Bytes,
+}
+
+fn default_has_headers() -> bool {
+    true
+}
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct DataConfig {
     pub path: String,
+    /// The number of output columns at the end of each row of the data.
+    /// Defaults to one, the class of a classification problem.
+    #[serde(default)]
+    pub num_outputs: usize,
+    /// Inferred from the file extension if not set.
+    #[serde(default)]
+    pub format: Option<DataFormat>,
+    #[serde(default)]
+    pub delimiter: Option<char>,
+    /// Whether the first row of a CSV file is a header, to be skipped.
+    #[serde(default = "default_has_headers")]
+    pub has_headers: bool,
+    /// The type of each column. If this is empty, the narrowest type that
+    /// fits every value in a column is used.
+    #[serde(default)]
+    pub column_types: Vec<ColumnType>,
+    /// The fractions of the rows held out for validation and for testing.
+    /// The split is seeded by the random seed.
+    #[serde(default)]
+    pub validation_fraction: f64,
+    #[serde(default)]
+    pub test_fraction: f64,
+}
+
+impl DataConfig {
+    pub fn num_outputs(&self) -> usize {
+        self.num_outputs.max(1)
+    }
+
+    pub fn format(&self) -> DataFormat {
+        self.format.unwrap_or_else(|| {
+            if self.path.ends_with(".jsonl") || self.path.ends_with(".json") {
+                DataFormat::JsonLines
+            } else {
+                DataFormat::Csv
+            }
+        })
+    }
+
+    pub fn delimiter(&self) -> char {
+        self.delimiter.unwrap_or_else(|| {
+            if self.path.ends_with(".csv") {
+                ','
+            } else {
+                '\t'
+            }
+        })
+    }
 }
 
 #[derive(Clone, Debug, Copy, Serialize, Deserialize)]
@@ -19,6 +97,9 @@ pub enum Job {
     Roper,
     Hello,
     LinearGp,
+    Regression,
+    Boolean,
+    ByteSequence,
 }
 
 impl Default for Job {
@@ -27,10 +108,14 @@ impl Default for Job {
     }
 }
 
+/// A quarter of the cores the process may run on, which, on Linux, are
+/// those its affinity mask allows, but at least one.
 fn default_num_islands() -> usize {
-    num_cpus::get() / 4
+    (num_cpus::get() / 4).max(1)
 }
 
+const DEFAULT_DETERMINISTIC_EMU_STEPS: usize = 0x10_000;
+
 fn default_random_seed() -> u64 {
     rand::random::<u64>()
 }
@@ -46,6 +131,9 @@ fn default_crossover_algorithm() -> String {
 #[derive(Clone, Debug, Deserialize, Serialize, Default)]
 pub struct Config {
     pub job: Job,
+    /// The wall-clock budget of the whole run, in any format that
+    /// `parse_duration` accepts, such as "36h", "90m", or "1 day". When
+    /// it's spent, each island winds down as it would at its epoch limit.
     pub timeout: Option<String>,
     pub selection: Selection,
     #[serde(default = "default_num_islands")]
@@ -69,9 +157,18 @@ pub struct Config {
     #[serde(default = "default_one")]
     pub mutation_rate: f64,
     pub mutation_exponent: f64,
+    /// The temperature of the Metropolis world's acceptance of variations
+    /// less fit than the specimen they vary. This defaults to 1.
+    #[serde(default)]
+    pub temperature: Option<f64>,
     pub observer: ObserverConfig,
     pub pop_size: usize,
     pub problems: Option<Vec<ClassificationProblem>>,
+    /// The problems held out from `problems`, to score champions on
+    #[serde(skip)]
+    pub validation_problems: Option<Vec<ClassificationProblem>>,
+    #[serde(skip)]
+    pub test_problems: Option<Vec<ClassificationProblem>>,
     #[serde(default)]
     pub roulette: RouletteConfig,
     #[serde(default)]
@@ -82,12 +179,245 @@ pub struct Config {
     pub linear_gp: LinearGpConfig,
     #[serde(default)]
     pub hello: HelloConfig,
+    #[serde(default)]
+    pub regression: RegressionConfig,
+    #[serde(default)]
+    pub boolean: BooleanConfig,
+    #[serde(default)]
+    pub provider: ProviderConfig,
+    #[serde(default)]
+    pub arithmetic: ArithmeticConfig,
+    #[serde(default)]
+    pub byte_sequence: ByteSequenceConfig,
     pub num_epochs: usize,
     pub fitness: FitnessConfig,
+    /// The seed from which the islands' seeds are derived, as described at
+    /// `util::random::derive_seed`. This is random by default.
     #[serde(default = "default_random_seed")]
     pub random_seed: u64,
     #[serde(default)]
     pub push_vm: PushVm,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default)]
+    pub affinity: AffinityConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    /// Conditions, besides `num_epochs` and `fitness.target`, on which each
+    /// island stops. See `stopping`.
+    #[serde(default)]
+    pub stopping: Vec<StoppingCriterion>,
+    /// What an island does once it has spent a budget among the `stopping`
+    /// criteria, while the others go on
+    #[serde(default)]
+    pub budget_exhausted: BudgetPolicy,
+}
+
+/// A condition on which an island's run ends early, after its champion is
+/// tested and its population dumped, as at the epoch limit. In the config,
+/// the criteria are listed as, e.g.,
+///
+/// ```text
+/// stopping = [{ Plateau = 50 }, { WallClock = "6h" }, { Predicate = "uniqueness < 0.05" }]
+/// ```
+///
+/// The `WallClock`, `Evaluations`, and `EmulatorTime` criteria are the
+/// island's own budgets: an island that spends one of them stops, or idles,
+/// as `budget_exhausted` has it, and the run goes on until every island has
+/// spent its budget. The others end the whole run.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum StoppingCriterion {
+    /// The best scalar fitness hasn't improved for so many epochs
+    Plateau(usize),
+    /// So much time has passed since the island started, in any format
+    /// that `parse_duration` accepts
+    WallClock(String),
+    /// The island has evaluated so many specimens
+    Evaluations(usize),
+    /// The island's evaluations have spent so much time in the emulator,
+    /// in any format that `parse_duration` accepts
+    EmulatorTime(String),
+    /// A specimen in the window has a `code_coverage` score of at least
+    /// this
+    Coverage(f64),
+    /// A `fasteval` expression over the window's statistics, which stops
+    /// the island when it's nonzero. See `stopping::namespace`.
+    Predicate(String),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum BudgetPolicy {
+    /// The island concludes its run, and its thread ends, freeing its
+    /// emulators
+    Terminate,
+    /// The island concludes its run, but stays, offering its population to
+    /// the other islands as emigrants, until the run ends
+    Idle,
+}
+
+impl Default for BudgetPolicy {
+    fn default() -> Self {
+        Self::Terminate
+    }
+}
+
+/// The cores that each island, and its emulators, are pinned to, for
+/// predictable performance on machines shared with other jobs. Each set of
+/// cores is written as a list of cores and ranges of them, like
+/// "0-7,16-23", and island `i` takes the `i % n`th of the `n` sets given.
+/// By default, nothing is pinned. See `util::affinity`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AffinityConfig {
+    /// The cores of each island's threads, and of those they start
+    #[serde(default)]
+    pub islands: Vec<String>,
+    /// The cores of each island's emulator workers, if they're to be kept
+    /// apart from the island's other threads
+    #[serde(default)]
+    pub emulators: Vec<String>,
+}
+
+impl AffinityConfig {
+    pub fn validate(&self) -> Result<(), Error> {
+        for spec in self.islands.iter().chain(self.emulators.iter()) {
+            affinity::parse_core_set(spec)?;
+        }
+        Ok(())
+    }
+
+    fn cores(sets: &[String], island: usize) -> Option<Vec<usize>> {
+        if sets.is_empty() {
+            return None;
+        }
+        let spec = &sets[island % sets.len()];
+        Some(
+            affinity::parse_core_set(spec)
+                .expect("The core sets were checked when the config was loaded"),
+        )
+    }
+
+    pub fn island_cores(&self, island: usize) -> Option<Vec<usize>> {
+        Self::cores(&self.islands, island)
+    }
+
+    pub fn emulator_cores(&self, island: usize) -> Option<Vec<usize>> {
+        Self::cores(&self.emulators, island)
+    }
+}
+
+/// The levels logged at, by default and by module, and where the records
+/// go. The `BERBALANG_LOG` environment variable, written as env_logger's
+/// filters are, takes precedence over the levels set here. See `logger`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// The level of the modules not named in `modules`: "off", "error",
+    /// "warn", "info", "debug", or "trace". Only errors are logged by
+    /// default.
+    #[serde(default)]
+    pub level: Option<String>,
+    /// Levels by the path of the module within berbalang, as in
+    /// `emulator = "warn"`, or `"roper::push" = "debug"`, each covering the
+    /// modules beneath it
+    #[serde(default)]
+    pub modules: HashMap<String, String>,
+    #[serde(default = "default_log_destinations")]
+    pub destinations: Vec<LogDestination>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LogDestination {
+    Stderr,
+    /// `merged.log`, beside the island directories, and each island's
+    /// `island.log`
+    File,
+    /// `merged.jsonl`, beside the island directories, with one JSON object
+    /// per record
+    Json,
+}
+
+fn default_log_destinations() -> Vec<LogDestination> {
+    vec![LogDestination::Stderr, LogDestination::File]
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: None,
+            modules: HashMap::new(),
+            destinations: default_log_destinations(),
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// The levels set, as env_logger takes them: by full module path, or,
+    /// for the default level, by none.
+    pub fn filters(&self) -> Result<Vec<(Option<String>, log::LevelFilter)>, Error> {
+        let parse = |level: &str| {
+            level.parse::<log::LevelFilter>().map_err(|_| {
+                Error::Parsing(format!(
+                    "Bad log level {:?}: try off, error, warn, info, debug, or trace",
+                    level
+                ))
+            })
+        };
+        let mut filters = Vec::new();
+        if let Some(ref level) = self.level {
+            filters.push((None, parse(level)?));
+        }
+        let mut modules = self.modules.iter().collect::<Vec<_>>();
+        modules.sort();
+        for (module, level) in modules {
+            let module = module.trim_start_matches("berbalib::");
+            filters.push((Some(format!("berbalib::{}", module)), parse(level)?));
+        }
+        Ok(filters)
+    }
+}
+
+/// The commands run, and webhooks posted, on the events of the run. See
+/// `events`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HooksConfig {
+    /// Emit a `Stagnation` event when an island's champion has gone this
+    /// many epochs without improving, and every so many after that. 0, the
+    /// default, never does.
+    #[serde(default)]
+    pub stagnation_epochs: usize,
+    #[serde(default)]
+    pub commands: Vec<CommandHook>,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookHook>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommandHook {
+    pub on: EventKind,
+    /// A shell command, run with the event, as JSON, in the
+    /// `BERBALANG_EVENT` environment variable
+    pub command: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WebhookHook {
+    pub on: EventKind,
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// The event itself, as JSON
+    Json,
+    /// A Slack message, `{"text": ...}`, describing the event
+    Slack,
+}
+
+impl Default for WebhookFormat {
+    fn default() -> Self {
+        Self::Json
+    }
 }
 
 fn default_tournament_size() -> usize {
@@ -105,6 +435,75 @@ pub struct PushVm {
     pub min_len: usize,
     pub max_len: usize,
     pub literal_rate: f64,
+    /// The relative rates of each type of literal, among the literals
+    /// generated.
+    #[serde(default)]
+    pub literal_rates: LiteralRates,
+    /// Keep a trace of the ops executed by each push program, to be dumped
+    /// alongside the champions. This is costly, and meant for debugging.
+    #[serde(default)]
+    pub record_trace: bool,
+    /// The maximum number of stack snapshots kept in each trace. These are
+    /// spread evenly over the `max_steps` budget.
+    #[serde(default = "default_trace_snapshots")]
+    pub trace_snapshots: usize,
+}
+
+fn default_trace_snapshots() -> usize {
+    16
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LiteralRates {
+    /// Words drawn from the soup
+    pub word: f64,
+    /// Functions of the binary, by name
+    pub function: f64,
+    pub int: f64,
+    pub float: f64,
+    pub bool: f64,
+}
+
+impl Default for LiteralRates {
+    // Words and functions were the only literals, once, drawn evenly.
+    fn default() -> Self {
+        Self {
+            word: 1.0,
+            function: 1.0,
+            int: 0.0,
+            float: 0.0,
+            bool: 0.0,
+        }
+    }
+}
+
+impl LiteralRates {
+    /// Checks that the rates are finite and non-negative, and not all zero,
+    /// so that a type of literal can be drawn by them.
+    pub fn check(&self) -> Result<(), Error> {
+        let rates = [
+            ("word", self.word),
+            ("function", self.function),
+            ("int", self.int),
+            ("float", self.float),
+            ("bool", self.bool),
+        ];
+        for (name, rate) in rates.iter() {
+            if !rate.is_finite() || *rate < 0.0 {
+                return Err(Error::Parsing(format!(
+                    "Invalid push_vm.literal_rates.{}: {}. The rates must be finite and non-negative",
+                    name, rate
+                )));
+            }
+        }
+        if rates.iter().all(|(_, rate)| *rate == 0.0) {
+            return Err(Error::Parsing(
+                "The push_vm.literal_rates are all zero, so no literal can be drawn".to_string(),
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
@@ -116,6 +515,57 @@ pub struct FitnessConfig {
     priority: String,
     pub function: String,
     pub weighting: String,
+    /// The objectives the fitness function is expected to score. If any
+    /// are declared, the `weighting` and `priority` may refer to no others,
+    /// and a specimen left without a score for any of them is reported.
+    #[serde(default)]
+    pub objectives: Vec<ObjectiveConfig>,
+    /// How many of each kind of costly objective are kept, under the hash
+    /// of the behavior they're computed from, by each evaluator, so that
+    /// specimens that behave alike aren't scored from scratch. See
+    /// `roper::behavior`. 0 disables the cache.
+    #[serde(default = "default_behavior_cache_size")]
+    pub behavior_cache_size: usize,
+}
+
+fn default_behavior_cache_size() -> usize {
+    4096
+}
+
+/// An objective, as declared in `[[fitness.objectives]]`:
+///
+/// ```text
+/// [[fitness.objectives]]
+/// name = "register_error"
+/// direction = "Minimize"
+/// description = "Distance of the registers from the target pattern"
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ObjectiveConfig {
+    pub name: String,
+    #[serde(default)]
+    pub direction: Direction,
+    #[serde(default)]
+    pub description: String,
+    /// The name, interned as a fitness key, by
+    /// `FitnessConfig::intern_objective_keys`.
+    #[serde(skip)]
+    pub key: &'static str,
+}
+
+/// Which way an objective improves. The scalar fitness is minimized, so an
+/// objective to be maximized should enter the `weighting` negated, or
+/// inverted.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Direction {
+    Minimize,
+    Maximize,
+}
+
+impl Default for Direction {
+    fn default() -> Self {
+        Self::Minimize
+    }
 }
 
 impl FitnessConfig {
@@ -126,6 +576,70 @@ impl FitnessConfig {
             &self.priority
         }
     }
+
+    /// Checks that the objectives are named once each, and that the
+    /// weighting and priority expressions refer to none that aren't
+    /// declared, if any are.
+    pub fn check_objectives(&self) -> Result<(), Error> {
+        if self.objectives.is_empty() {
+            return Ok(());
+        }
+        let mut declared = HashSet::new();
+        for objective in self.objectives.iter() {
+            if objective.name.is_empty() {
+                return Err(Error::Parsing(
+                    "An objective in fitness.objectives has no name".to_string(),
+                ));
+            }
+            if !declared.insert(objective.name.clone()) {
+                return Err(Error::Parsing(format!(
+                    "The objective {:?} is declared twice in fitness.objectives",
+                    objective.name
+                )));
+            }
+        }
+        for (field, expr) in &[("weighting", &self.weighting), ("priority", &self.priority)] {
+            if expr.is_empty() {
+                continue;
+            }
+            let undeclared = crate::fitness::expression_variables(expr)?
+                .into_iter()
+                .filter(|name| !declared.contains(name))
+                .map(|name| match suggest(&name, declared.iter()) {
+                    Some(meant) => format!("{} (did you mean {}?)", name, meant),
+                    None => name,
+                })
+                .collect::<Vec<_>>();
+            if !undeclared.is_empty() {
+                return Err(Error::Parsing(format!(
+                    "fitness.{} refers to objectives not declared in fitness.objectives: {}",
+                    field,
+                    undeclared.join(", ")
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn intern_objective_keys(&mut self) {
+        for objective in self.objectives.iter_mut() {
+            if objective.key.is_empty() {
+                objective.key = Box::leak(objective.name.clone().into_boxed_str());
+            }
+        }
+    }
+
+    /// An empty fitness, weighted by the `weighting`, that expects the
+    /// declared objectives to be scored.
+    pub fn weighted(&self) -> Weighted<'static> {
+        let expected = self
+            .objectives
+            .iter()
+            .map(|o| o.key)
+            .filter(|key| !key.is_empty())
+            .collect::<Vec<_>>();
+        Weighted::expecting(&self.weighting, &expected)
+    }
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
@@ -158,12 +672,253 @@ fn random_population_name() -> String {
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ObserverConfig {
     pub dump_population: f64,
+    /// How the population dumps are compressed
+    #[serde(default)]
+    pub population_compression: DumpCompression,
+    /// Which population dumps are kept, as the run goes on. By default,
+    /// they all are.
+    #[serde(default)]
+    pub population_retention: Option<RetentionPolicy>,
     pub dump_soup: bool,
+    /// The soup dumps record only the changes since the previous epoch,
+    /// with the whole soup stored at least this often. See
+    /// `util::soup_store`.
+    #[serde(default = "default_soup_keyframe_every")]
+    pub soup_keyframe_every: usize,
+    /// How often, in epochs, each island writes a checkpoint of its
+    /// population, soup and epoch, from which the run can be resumed. None
+    /// are written if this is 0. See `checkpoint`.
+    #[serde(default = "default_checkpoint_every")]
+    pub checkpoint_every: usize,
     #[serde(default)]
     pub full_data_directory: String,
     data_directory: String,
+    /// The layout of the data directories beneath the `data_directory`, as
+    /// a template. See `layout`.
+    #[serde(default)]
+    pub data_layout: Option<String>,
     #[serde(default = "random_population_name")]
     pub population_name: String,
+    /// The number of most recent specimens the observation window holds.
+    /// This defaults to the length of an epoch.
+    #[serde(default)]
+    pub window_size: Option<usize>,
+    /// How often, in specimens observed, the window is aggregated and sent
+    /// to the sinks. This defaults to once an epoch.
+    #[serde(default)]
+    pub report_every: Option<usize>,
+    /// How often the window is aggregated and sent to the sinks, in
+    /// evaluations, epochs, or wall-clock time. This takes precedence over
+    /// `report_every`, if both are given.
+    #[serde(default)]
+    pub metrics_cadence: Option<Cadence>,
+    /// How often the soup and the population are dumped. This defaults to
+    /// once an epoch. Whatever the cadence, they're dumped once more when
+    /// the run reaches its epoch limit.
+    #[serde(default)]
+    pub dump_cadence: Option<Cadence>,
+    /// The number of randomly chosen pairs of genomes in the window whose
+    /// edit distances are averaged, for the window's diversity statistics
+    #[serde(default = "default_diversity_sample_pairs")]
+    pub diversity_sample_pairs: usize,
+    /// The number of bins in the histograms of each fitness component that
+    /// are taken of the window, and written to `histogram_statistics.csv`
+    /// by the "csv" sink. 0 disables them.
+    #[serde(default = "default_histogram_bins")]
+    pub histogram_bins: usize,
+    /// How often, in epochs, the shape of the trie of the window's
+    /// execution paths is written to `path_trie_statistics.csv`. See
+    /// `util::diversity::PathTrieStats`. 0 disables it.
+    #[serde(default = "default_path_trie_every")]
+    pub path_trie_every: usize,
+    /// The length of the path prefixes counted among the most common
+    #[serde(default = "default_path_trie_prefix_len")]
+    pub path_trie_prefix_len: usize,
+    /// Further statistics of the window to take, by name: the built-in
+    /// "length_std_dev", "generation", "fitness_std_dev", and
+    /// "scored_fraction", or any registered with `metrics::register`. The
+    /// names are checked when the config is loaded.
+    #[serde(default)]
+    pub window_statistics: Vec<String>,
+    /// The formats in which the window's Pareto front is written out, each
+    /// time the window is sent to the sinks, and once more at the end of
+    /// the run: "Csv" appends the front to `pareto_fronts.csv`, and "Json"
+    /// writes each one to `pareto/front_{counter}.json`. By default, the
+    /// fronts aren't recorded.
+    #[serde(default)]
+    pub pareto_fronts: Vec<FrontFormat>,
+    /// How many specimens may wait to be taken into the window. Once that
+    /// many are waiting, the island blocks as it sends the observer the
+    /// next, until the observer catches up, so that a slow observer slows
+    /// the island down, rather than letting the backlog grow without bound.
+    /// The depth of the queue, and the time spent blocked on it, are
+    /// reported in `resources_statistics.csv`.
+    #[serde(default = "default_queue_capacity")]
+    pub queue_capacity: usize,
+    /// The sinks for the window's statistics and champions: "csv", "log",
+    /// "jsonl", which appends versioned JSON records, along with operator
+    /// statistics, to `observations.jsonl`, "dashboard", which serves a live
+    /// web dashboard, "genealogy", which logs every specimen's parents to
+    /// `genealogy.jsonl`, for the `ancestry` tool, "journal", which keeps a
+    /// checksummed log of every record, and specimen, that can be recovered
+    /// after a crash with the `recover_journal` tool, and "tensorboard", which
+    /// writes TensorBoard scalar summaries. With the features of
+    /// the same names, there are also "parquet", which writes the window
+    /// statistics and every specimen's fitness vector to columnar files, an
+    /// epoch at a time, and "zmq" and "websocket", which stream the JSON
+    /// records as they're produced.
+    #[serde(default = "default_sinks")]
+    pub sinks: Vec<String>,
+    /// Where the dashboard is served, if it's among the sinks. There's one
+    /// dashboard per process, showing every island.
+    #[serde(default = "default_dashboard_address")]
+    pub dashboard_address: String,
+    /// Where the "zmq" sink's PUB socket is bound. See `stream`.
+    #[serde(default = "default_zmq_endpoint")]
+    pub zmq_endpoint: String,
+    /// Where the "websocket" sink accepts connections. See `stream`.
+    #[serde(default = "default_websocket_address")]
+    pub websocket_address: String,
+    /// How many entries the "journal" sink writes between syncing its file
+    /// to disk. It's synced whenever the window statistics are recorded,
+    /// as well.
+    #[serde(default = "default_journal_sync_every")]
+    pub journal_sync_every: usize,
+    /// Where the champion bundles, reports, and manifest are published, as
+    /// they're written, apart from the working data: a local directory, or
+    /// an `s3://` URL. See `artifacts`.
+    #[serde(default)]
+    pub artifact_sink: Option<String>,
+    /// The directory of the run being resumed, if any, in which the islands'
+    /// data directories are laid out, in place of a new one. See `resume`.
+    #[serde(skip)]
+    pub resume_directory: Option<String>,
+    /// The epoch the island's run resumes from, as taken from its
+    /// checkpoint. See `checkpoint::restore`.
+    #[serde(skip)]
+    pub resumed_epoch: usize,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DumpCompression {
+    None,
+    Gzip,
+    /// Requires the "zstd" feature
+    Zstd,
+}
+
+impl Default for DumpCompression {
+    fn default() -> Self {
+        Self::Gzip
+    }
+}
+
+impl DumpCompression {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::None => "json",
+            Self::Gzip => "json.gz",
+            Self::Zstd => "json.zst",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum FrontFormat {
+    Csv,
+    Json,
+}
+
+/// Keeps the population dump of every `keep_every`th epoch, and of the
+/// last `keep_last` epochs, deleting the rest.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    #[serde(default)]
+    pub keep_every: usize,
+    #[serde(default)]
+    pub keep_last: usize,
+}
+
+impl RetentionPolicy {
+    pub fn keeps(&self, epoch: usize, latest_epoch: usize) -> bool {
+        (self.keep_every > 0 && epoch % self.keep_every == 0)
+            || epoch + self.keep_last > latest_epoch
+    }
+}
+
+/// How often one of the observer's periodic tasks is performed. In the
+/// config, this is written as, e.g., `{ Epochs = 5 }`, or
+/// `{ WallClock = "10m" }`, with the duration in any format that
+/// `parse_duration` accepts.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Cadence {
+    /// Every so many specimens evaluated, and observed
+    Evaluations(usize),
+    /// Every so many of the island's epochs
+    Epochs(usize),
+    /// Whenever at least so much time has passed since the task was last
+    /// performed
+    WallClock(String),
+}
+
+impl Cadence {
+    /// The period of a `WallClock` cadence, parsed. The other cadences
+    /// have none, and give a zero duration.
+    pub fn period(&self) -> Result<Duration, Error> {
+        match self {
+            Cadence::WallClock(period) => parse_duration::parse(period)
+                .map_err(|e| Error::Parsing(format!("Invalid cadence {:?}: {}", period, e))),
+            _ => Ok(Duration::default()),
+        }
+    }
+}
+
+fn default_diversity_sample_pairs() -> usize {
+    100
+}
+
+fn default_queue_capacity() -> usize {
+    1024
+}
+
+fn default_histogram_bins() -> usize {
+    20
+}
+
+fn default_path_trie_every() -> usize {
+    1
+}
+
+fn default_path_trie_prefix_len() -> usize {
+    4
+}
+
+fn default_soup_keyframe_every() -> usize {
+    50
+}
+
+fn default_checkpoint_every() -> usize {
+    10
+}
+
+fn default_sinks() -> Vec<String> {
+    vec!["csv".to_string()]
+}
+
+fn default_dashboard_address() -> String {
+    "127.0.0.1:8118".to_string()
+}
+
+fn default_zmq_endpoint() -> String {
+    "tcp://127.0.0.1:5556".to_string()
+}
+
+fn default_journal_sync_every() -> usize {
+    1000
+}
+
+fn default_websocket_address() -> String {
+    "127.0.0.1:8119".to_string()
 }
 
 impl Config {
@@ -176,11 +931,72 @@ impl Config {
         //assert_eq!(self.num_offspring, 2); // all that's supported for now
     }
 
+    /// Reads the config at `path`, in the given format, or, failing that,
+    /// the format its extension implies, applying the `overrides` -- each
+    /// of the form `path.to.field=value` -- to it, in order, before it's
+    /// deserialized. See `apply_override`.
+    ///
+    /// Environment variables are interpolated into the file, and the files
+    /// it includes are merged into it, as described under `load_value`.
+    /// Where they set the same field, the overrides take precedence over
+    /// the file, and the file over its includes.
     pub fn from_path<P: AsRef<Path>>(
         path: P,
+        format: Option<ConfigFormat>,
+        population_name: Option<String>,
+        overrides: &[String],
+    ) -> Result<Self, Error> {
+        let (mut config, value, verbatim) =
+            Self::resolve(path.as_ref(), format, population_name, overrides)?;
+        config.set_data_directory();
+        // copy the config file to the data directory for posterity
+        // bit ugly, here: copying it to the parent of the directory, just above the island subdirs
+        let copy_path = format!("{}/../config.toml", config.data_directory());
+        if verbatim {
+            std::fs::copy(&path, &copy_path)?;
+        } else {
+            // the config as interpolated, included, overridden, and
+            // translated into TOML, so that the run can be repeated from it
+            let toml = toml::to_string(&value).map_err(|e| Error::Misc(e.to_string()))?;
+            std::fs::write(&copy_path, toml)?;
+        }
+        crate::manifest::write_or_verify(&config)?;
+
+        println!("{:#?}", config);
+
+        Ok(config)
+    }
+
+    /// Reads the config as `from_path` does, but without setting up its
+    /// data directory.
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        format: Option<ConfigFormat>,
         population_name: Option<String>,
+        overrides: &[String],
     ) -> Result<Self, Error> {
-        let mut config: Self = toml::from_str(&std::fs::read_to_string(&path)?)?;
+        Self::resolve(path.as_ref(), format, population_name, overrides).map(|(c, _, _)| c)
+    }
+
+    /// Returns the config, the value it was deserialized from, and whether
+    /// that value is just what the file at `path` says.
+    fn resolve(
+        path: &Path,
+        format: Option<ConfigFormat>,
+        population_name: Option<String>,
+        overrides: &[String],
+    ) -> Result<(Self, toml::Value, bool), Error> {
+        let format = format.unwrap_or_else(|| ConfigFormat::of_path(path));
+        let mut value = load_value(path, format, &mut Vec::new())?;
+        for assignment in overrides {
+            apply_override(&mut value, assignment)?;
+        }
+        crate::emulator::preset::apply(&mut value)?;
+        // whether the file says all there is to say about the config
+        let verbatim = format == ConfigFormat::Toml
+            && format.parse(&std::fs::read_to_string(path)?).ok().as_ref() == Some(&value);
+        let mut config: Self = value.clone().try_into()?;
+        check_keys(&value, &config)?;
         if let Some(population_name) = population_name {
             config.observer.population_name = population_name;
         }
@@ -192,18 +1008,97 @@ impl Config {
                 .expect("Failed to get hostname"),
             config.observer.population_name
         );
-        config.assert_invariants();
-        config.set_data_directory();
-        // copy the config file to the data directory for posterity
-        // bit ugly, here: copying it to the parent of the directory, just above the island subdirs
-        std::fs::copy(
-            &path,
-            &format!("{}/../config.toml", config.data_directory()),
-        )?;
+        config.validate()?;
+        Ok((config, value, verbatim))
+    }
 
-        println!("{:#?}", config);
+    /// Checks the fields that can't be checked as they're deserialized, as
+    /// a config read from a file is checked, so that a bad one fails now,
+    /// rather than once the run is set up. A config built in code should be
+    /// checked with this before it's run, as `Berbalang::run` does.
+    pub fn validate(&mut self) -> Result<(), Error> {
+        self.assert_invariants();
+        self.timeout_duration()?;
+        if let Some(ref sink) = self.observer.artifact_sink {
+            crate::artifacts::Sink::parse(sink)?;
+        }
+        crate::observer::check_sinks(&self.observer.sinks)?;
+        self.provider.check(self.job)?;
+        self.check_selection()?;
+        if let Job::Hello = self.job {
+            self.hello.check_weighting(&mut self.fitness)?;
+        }
+        if let Some(ref pattern) = self.roper.register_pattern {
+            pattern.check()?;
+        }
+        self.push_vm.literal_rates.check()?;
+        self.roper.bad_byte_filter()?;
+        self.roper.check_sigreturn_frames()?;
+        crate::roper::jop::check_config(&self.roper)?;
+        self.roper.check_targets(self.num_islands, self.selection)?;
+        self.affinity.validate()?;
+        self.logging.filters()?;
+        if let Some(ref data_layout) = self.observer.data_layout {
+            layout::check(data_layout)?;
+        }
+        for cadence in self
+            .observer
+            .metrics_cadence
+            .iter()
+            .chain(self.observer.dump_cadence.iter())
+        {
+            cadence.period()?;
+        }
+        crate::stopping::check_criteria(&self.stopping)?;
+        crate::metrics::check_statistics(&self.observer.window_statistics)?;
+        self.fitness.check_objectives()?;
+        self.fitness.intern_objective_keys();
+        Ok(())
+    }
 
-        Ok(config)
+    /// Checks that the job supports the selection method. The benchmark
+    /// jobs support tournaments only. See `examples::benchmark`.
+    pub fn check_selection(&self) -> Result<(), Error> {
+        match (self.job, self.selection) {
+            (Job::Regression, Selection::Tournament)
+            | (Job::Boolean, Selection::Tournament)
+            | (Job::ByteSequence, Selection::Tournament) => Ok(()),
+            (job @ Job::Regression, selection)
+            | (job @ Job::Boolean, selection)
+            | (job @ Job::ByteSequence, selection) => Err(Error::Parsing(format!(
+                "The {:?} job supports only Tournament selection, not {:?}",
+                job, selection
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// In deterministic mode, wall-clock timeouts are dropped, since they
+    /// depend on the load on the machine, and migration is disabled, since
+    /// the order in which islands reach the pier depends on the scheduler.
+    pub fn apply_deterministic_mode(&mut self) {
+        if !self.roper.deterministic {
+            return;
+        }
+        if self.roper.max_emu_steps.is_none() {
+            log::warn!(
+                "Deterministic mode needs a step budget. Setting max_emu_steps to 0x{:x}",
+                DEFAULT_DETERMINISTIC_EMU_STEPS
+            );
+            self.roper.max_emu_steps = Some(DEFAULT_DETERMINISTIC_EMU_STEPS);
+        }
+        self.roper.millisecond_timeout = None;
+        let max_emu_steps = self.roper.max_emu_steps;
+        if let Some(escalation) = self.roper.escalation.as_mut() {
+            escalation.millisecond_timeout = None;
+            if escalation.max_emu_steps.is_none() {
+                escalation.max_emu_steps = max_emu_steps;
+            }
+        }
+        if self.num_islands > 1 && self.tournament.migration_rate > 0.0 {
+            log::warn!("Migration between islands is disabled in deterministic mode");
+            self.tournament.migration_rate = 0.0;
+        }
     }
 
     /// Returns the path to the full data directory, creating it if necessary.
@@ -217,15 +1112,35 @@ impl Config {
             data_dir.replace_range(0..1, &home);
         };
 
-        let path = format!(
-            "{data_dir}/berbalang/{job:?}/{selection:?}/{date}/{pop_name}/island_{island}",
-            data_dir = data_dir,
-            job = self.job,
-            selection = self.selection,
-            date = local_date.format("%Y/%m/%d"),
-            pop_name = self.observer.population_name,
-            island = self.island_id,
-        );
+        let path = layout::render(
+            self.observer
+                .data_layout
+                .as_ref()
+                .map(String::as_str)
+                .unwrap_or(layout::DEFAULT_LAYOUT),
+            &layout::Values {
+                data_directory: &data_dir,
+                job: format!("{:?}", self.job),
+                selection: format!("{:?}", self.selection),
+                date: local_date,
+                population: &self.observer.population_name,
+                seed: self.random_seed,
+                island: self.island_id,
+            },
+        )
+        .expect("The data_layout was checked when the config was loaded");
+        // a resumed run keeps to the directory it was laid out in
+        let path = match self.observer.resume_directory {
+            Some(ref run_dir) => format!(
+                "{}/{}",
+                run_dir,
+                Path::new(&path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| format!("island_{}", self.island_id))
+            ),
+            None => path,
+        };
 
         for sub in ["", "soup", "population", "champions"].iter() {
             let d = format!("{}/{}", path, sub);
@@ -240,14 +1155,442 @@ impl Config {
         self.observer.full_data_directory = path;
     }
 
+    /// The `timeout`, parsed.
+    pub fn timeout_duration(&self) -> Result<Option<Duration>, Error> {
+        self.timeout
+            .as_ref()
+            .map(|t| {
+                parse_duration::parse(t)
+                    .map_err(|e| Error::Parsing(format!("Invalid timeout {:?}: {}", t, e)))
+            })
+            .transpose()
+    }
+
+    /// The `random_seed` of each island, as derived from the run's by
+    /// `util::random::derive_seed`.
+    pub fn island_seeds(&self) -> Vec<u64> {
+        (0..self.num_islands.max(1))
+            .map(|i| derive_seed(self.random_seed, i, ISLAND_STREAM))
+            .collect()
+    }
+
     pub fn data_directory(&self) -> &str {
         &self.observer.full_data_directory
     }
 }
 
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
-pub struct HelloConfig {
+/// The formats a config file can be written in. Whatever the format, the
+/// fields are the same as in `config.toml`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl FromStr for ConfigFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "toml" => Ok(Self::Toml),
+            "yaml" | "yml" => Ok(Self::Yaml),
+            "json" => Ok(Self::Json),
+            _ => Err(Error::Misc(format!("No such config format as {}", s))),
+        }
+    }
+}
+
+impl ConfigFormat {
+    /// The format implied by the path's extension, TOML being the default.
+    pub fn of_path(path: &Path) -> Self {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .and_then(|e| e.parse().ok())
+            .unwrap_or(Self::Toml)
+    }
+
+    /// Parses a config into a TOML value, on which overrides can be
+    /// applied before it's deserialized.
+    pub fn parse(self, text: &str) -> Result<toml::Value, Error> {
+        match self {
+            Self::Toml => toml::from_str(text).map_err(Error::from),
+            Self::Yaml => serde_yaml::from_str(text).map_err(|e| Error::Parsing(e.to_string())),
+            Self::Json => serde_json::from_str(text).map_err(|e| Error::Parsing(e.to_string())),
+        }
+    }
+}
+
+/// Replaces each `${VAR}` in the text with the value of the environment
+/// variable `VAR`, or, if it's unset, with the default given as in
+/// `${VAR:-default}`. It's an error for a variable with no default to be
+/// unset. `$${` stands for a literal `${`.
+pub fn interpolate(text: &str) -> Result<String, Error> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            out.push_str(&rest[..start - 1]);
+            out.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| Error::Misc("Unterminated ${ in config".to_string()))?;
+        let mut expr = after[..end].splitn(2, ":-");
+        let name = expr.next().unwrap_or("");
+        let value = match (std::env::var(name), expr.next()) {
+            (Ok(value), _) => value,
+            (Err(_), Some(default)) => default.to_string(),
+            (Err(_), None) => {
+                return Err(Error::Misc(format!(
+                    "Environment variable {} is not set",
+                    name
+                )))
+            }
+        };
+        out.push_str(&value);
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Merges `over` into `base`, table by table, with the values in `over`
+/// taking precedence. Arrays are replaced whole, rather than merged.
+fn merge(base: &mut toml::Value, over: toml::Value) {
+    match (base, over) {
+        (toml::Value::Table(base), toml::Value::Table(over)) => {
+            for (key, value) in over {
+                match base.get_mut(&key) {
+                    Some(b) => merge(b, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+/// Reads the config at `path`, with its includes merged in, as a TOML value,
+/// as `Config::from_path` does before deserializing it.
+pub fn read_value(path: &Path, format: Option<ConfigFormat>) -> Result<toml::Value, Error> {
+    let format = format.unwrap_or_else(|| ConfigFormat::of_path(path));
+    load_value(path, format, &mut Vec::new())
+}
+
+/// Reads a config file into a TOML value, interpolating environment
+/// variables into it, and merging in the files listed in its top-level
+/// `include` field:
+///
+/// ```text
+/// include = ["common.toml", "x86_64.toml"]
+/// ```
+///
+/// The includes' paths are relative to the including file. They may be in
+/// any format, told by their extensions, and may include files in turn.
+/// Later includes take precedence over earlier ones, and the including
+/// file over all of them. `visiting` holds the files being read, so that
+/// cycles can be caught.
+fn load_value(
+    path: &Path,
+    format: ConfigFormat,
+    visiting: &mut Vec<PathBuf>,
+) -> Result<toml::Value, Error> {
+    let canonical = path.canonicalize()?;
+    if visiting.contains(&canonical) {
+        return Err(Error::Misc(format!("{:?} includes itself", path)));
+    }
+    visiting.push(canonical);
+    let mut value = format.parse(&interpolate(&std::fs::read_to_string(path)?)?)?;
+    let includes = match value.as_table_mut().and_then(|t| t.remove("include")) {
+        None => vec![],
+        Some(toml::Value::String(include)) => vec![include],
+        Some(toml::Value::Array(includes)) => includes
+            .into_iter()
+            .map(|i| match i {
+                toml::Value::String(include) => Ok(include),
+                other => Err(Error::Misc(format!("Bad include: {}", other))),
+            })
+            .collect::<Result<Vec<String>, Error>>()?,
+        Some(other) => return Err(Error::Misc(format!("Bad include: {}", other))),
+    };
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut merged = toml::Value::Table(Default::default());
+    for include in includes {
+        let include_path = dir.join(include);
+        let included = load_value(
+            &include_path,
+            ConfigFormat::of_path(&include_path),
+            visiting,
+        )?;
+        merge(&mut merged, included);
+    }
+    merge(&mut merged, value);
+    visiting.pop();
+    Ok(merged)
+}
+
+/// Applies an override, of the form `path.to.field=value`, to a parsed
+/// config, as given by `--set` on the command line:
+///
+/// ```text
+/// berbalang config.toml --set pop_size=500 --set fitness.weighting="place + 2 * ret_count"
+/// ```
+///
+/// The value is read as TOML if it can be, and as a string if it can't, so
+/// strings needn't be quoted twice over. Missing tables along the path are
+/// created, and the elements of an array are picked out by their indices.
+pub fn apply_override(config: &mut toml::Value, assignment: &str) -> Result<(), Error> {
+    let mut parts = assignment.splitn(2, '=');
+    let path = parts.next().unwrap_or("").trim();
+    let raw = parts
+        .next()
+        .ok_or_else(|| Error::Misc(format!("Override {:?} has no value", assignment)))?
+        .trim();
+    if path.is_empty() {
+        return Err(Error::Misc(format!(
+            "Override {:?} has no path",
+            assignment
+        )));
+    }
+    let value = toml::from_str::<toml::Value>(&format!("v = {}", raw))
+        .ok()
+        .and_then(|mut t| t.as_table_mut().and_then(|t| t.remove("v")))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()));
+    let keys = path.split('.').collect::<Vec<&str>>();
+    let (last, parents) = keys.split_last().expect("split always yields a key");
+    let mut node = config;
+    for key in parents {
+        node = match node {
+            toml::Value::Table(table) => table
+                .entry(key.to_string())
+                .or_insert_with(|| toml::Value::Table(Default::default())),
+            toml::Value::Array(array) => key
+                .parse::<usize>()
+                .ok()
+                .and_then(move |i| array.get_mut(i))
+                .ok_or_else(|| Error::Misc(format!("No element {} in {:?}", key, path)))?,
+            _ => return Err(Error::Misc(format!("{:?} is not a table", path))),
+        };
+    }
+    match node {
+        toml::Value::Table(table) => {
+            table.insert(last.to_string(), value);
+        }
+        toml::Value::Array(array) => {
+            let slot = last
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| array.get_mut(i))
+                .ok_or_else(|| Error::Misc(format!("No element {} in {:?}", last, path)))?;
+            *slot = value;
+        }
+        _ => return Err(Error::Misc(format!("{:?} is not a table", path))),
+    }
+    Ok(())
+}
+
+/// Set to accept configs with unknown keys, which are otherwise refused.
+pub const ALLOW_UNKNOWN_KEYS_VAR: &str = "BERBALANG_ALLOW_UNKNOWN_KEYS";
+
+/// The known key nearest the unknown one, if it's near enough to be a
+/// likely misspelling.
+fn suggest<'a, I: Iterator<Item = &'a String>>(key: &str, known: I) -> Option<&'a String> {
+    known
+        .map(|k| (distance::levenshtein(key, k), k))
+        .filter(|(d, _)| *d <= (key.len() / 3).max(2))
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, k)| k)
+}
+
+/// Finds the keys of `given` that weren't taken up into `parsed` -- the
+/// config it was deserialized into, serialized again -- describing each
+/// with a suggestion of what was meant, if one comes to mind.
+fn unknown_keys(
+    given: &toml::Value,
+    parsed: &serde_json::Value,
+    path: &str,
+    out: &mut Vec<String>,
+) {
+    match (given, parsed) {
+        (toml::Value::Table(given), serde_json::Value::Object(parsed)) => {
+            let join = |key: &str| {
+                if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", path, key)
+                }
+            };
+            for (key, value) in given.iter() {
+                match parsed.get(key) {
+                    Some(parsed) => unknown_keys(value, parsed, &join(key), out),
+                    None => out.push(match suggest(key, parsed.keys()) {
+                        Some(known) => format!("{} (did you mean {}?)", join(key), join(known)),
+                        None => join(key),
+                    }),
+                }
+            }
+        }
+        (toml::Value::Array(given), serde_json::Value::Array(parsed)) => {
+            for (i, (value, parsed)) in given.iter().zip(parsed.iter()).enumerate() {
+                unknown_keys(value, parsed, &format!("{}.{}", path, i), out);
+            }
+        }
+        // the field is a map, or something serialized otherwise than it's
+        // written, so its keys can't be checked
+        _ => {}
+    }
+}
+
+/// Refuses a config with keys that don't correspond to any field, and that
+/// would otherwise be silently ignored, leaving the fields they were meant
+/// for at their defaults, unless `ALLOW_UNKNOWN_KEYS_VAR` is set.
+fn check_keys(value: &toml::Value, config: &Config) -> Result<(), Error> {
+    let mut unknown = Vec::new();
+    unknown_keys(value, &serde_json::to_value(config)?, "", &mut unknown);
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    let msg = format!("Unknown keys in the config: {}", unknown.join(", "));
+    if std::env::var(ALLOW_UNKNOWN_KEYS_VAR).is_ok() {
+        log::warn!("{}", msg);
+        return Ok(());
+    }
+    Err(Error::Parsing(format!(
+        "{}. Set {} to ignore them.",
+        msg, ALLOW_UNKNOWN_KEYS_VAR
+    )))
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum StringDistance {
+    /// The number of differing bits, plus eight for each byte by which the
+    /// lengths differ
+    Hamming,
+    Levenshtein,
+    /// As `Levenshtein`, but a transposition of adjacent characters counts
+    /// as a single edit
+    DamerauLevenshtein,
+    /// The number of characters that aren't part of the longest common
+    /// subsequence of the two strings
+    Lcs,
+}
+
+impl StringDistance {
+    /// The key under which the distance is stored in the fitness map
+    pub fn key(&self) -> &'static str {
+        match self {
+            Self::Hamming => "hamming",
+            Self::Levenshtein => "levenshtein",
+            Self::DamerauLevenshtein => "damerau_levenshtein",
+            Self::Lcs => "lcs",
+        }
+    }
+}
+
+/// The distances the Hello job was always scored by, the first of which was
+/// its scalar fitness.
+fn default_string_distances() -> Vec<StringDistance> {
+    vec![StringDistance::DamerauLevenshtein, StringDistance::Hamming]
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HelloConfig {
+    #[serde(default)]
     pub target: String,
+    /// Further targets. A string is scored by its distance to the nearest
+    /// target.
+    #[serde(default)]
+    pub targets: Vec<String>,
+    /// Each distance is a separate entry in the fitness map, keyed by its
+    /// lowercased name, in snake case.
+    #[serde(default = "default_string_distances")]
+    pub distances: Vec<StringDistance>,
+}
+
+impl Default for HelloConfig {
+    fn default() -> Self {
+        Self {
+            target: String::new(),
+            targets: vec![],
+            distances: default_string_distances(),
+        }
+    }
+}
+
+impl HelloConfig {
+    pub fn targets(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(&self.target)
+            .chain(self.targets.iter())
+            .filter(|t| !t.is_empty())
+            .map(String::as_str)
+    }
+
+    /// The keys of the scores in a Hello specimen's fitness map.
+    pub fn fitness_keys(&self) -> Vec<&'static str> {
+        self.distances
+            .iter()
+            .map(StringDistance::key)
+            .chain(std::iter::once("genetic_freq"))
+            .collect()
+    }
+
+    /// Checks that the fitness expressions refer only to the scores the
+    /// Hello job keeps. Before the distances could be chosen, the scalar
+    /// fitness was the first distance, whatever the weighting said, so an
+    /// expression that names none of the scores is taken for one written
+    /// then, and replaced with the first distance's key.
+    pub fn check_weighting(&self, fitness: &mut FitnessConfig) -> Result<(), Error> {
+        if self.distances.is_empty() {
+            return Err(Error::Parsing(
+                "hello.distances must name at least one distance".to_string(),
+            ));
+        }
+        let keys = self.fitness_keys();
+        let first = self.distances[0].key();
+        for (field, expr) in vec![
+            ("weighting", &mut fitness.weighting),
+            ("priority", &mut fitness.priority),
+        ] {
+            if expr.is_empty() {
+                continue;
+            }
+            let names = crate::fitness::expression_variables(expr)?;
+            let unknown = names
+                .iter()
+                .filter(|name| !keys.contains(&name.as_str()))
+                .cloned()
+                .collect::<Vec<String>>();
+            if unknown.is_empty() {
+                continue;
+            }
+            if unknown.len() == names.len() {
+                log::warn!(
+                    "fitness.{} = {:?} names none of the Hello job's scores, {:?}. \
+                     Scoring by {}, as the Hello job used to",
+                    field,
+                    expr,
+                    keys,
+                    first
+                );
+                *expr = first.to_string();
+            } else {
+                return Err(Error::Parsing(format!(
+                    "fitness.{} refers to scores the Hello job doesn't keep: {}. Try {:?}",
+                    field,
+                    unknown.join(", "),
+                    keys
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
@@ -257,6 +1600,303 @@ pub struct LinearGpConfig {
     // file has been supplied, in order to accommodate that data
     pub num_registers: Option<usize>,
     pub return_registers: Option<usize>,
+    /// The size of the float register bank. The float instructions are
+    /// only generated if this is nonzero.
+    #[serde(default)]
+    pub num_float_registers: usize,
+    /// The number of words of addressable data memory. The load and store
+    /// instructions are only generated if this is nonzero.
+    #[serde(default)]
+    pub memory_size: usize,
+    /// The fitness keys for the error on each output of a multi-output
+    /// problem. These are leaked, since the fitness map is keyed by static
+    /// strings.
+    #[serde(skip)]
+    pub output_error_keys: Vec<&'static str>,
+    /// Log only the effective code of the best creature in each report,
+    /// leaving out the introns.
+    #[serde(default)]
+    pub print_effective_code: bool,
+    #[serde(default)]
+    pub instruction_set: InstructionSet,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum InstructionSet {
+    Full,
+    /// Only the bitwise logical ops and `MOV`, for boolean problems
+    Boolean,
+}
+
+impl Default for InstructionSet {
+    fn default() -> Self {
+        Self::Full
+    }
+}
+
+/// The GP system evolved by the benchmark jobs.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum GpBackend {
+    LinearGp,
+    Push,
+}
+
+impl Default for GpBackend {
+    fn default() -> Self {
+        Self::LinearGp
+    }
+}
+
+/// Standard symbolic regression benchmarks, as catalogued by McDermott et
+/// al., "Genetic Programming Needs Better Benchmarks" (GECCO 2012).
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RegressionBenchmark {
+    /// x^4 + x^3 + x^2 + x
+    Koza1,
+    /// x^5 - 2x^3 + x
+    Koza2,
+    /// x^6 - 2x^4 + x^2
+    Koza3,
+    Keijzer1,
+    Keijzer2,
+    Keijzer3,
+    Keijzer4,
+    Keijzer5,
+    Keijzer6,
+    Keijzer7,
+    Keijzer8,
+    Keijzer9,
+    Keijzer10,
+    Keijzer11,
+    Keijzer12,
+    Keijzer13,
+    Keijzer14,
+    Keijzer15,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RegressionConfig {
+    #[serde(default)]
+    pub backend: GpBackend,
+    /// The benchmark to generate the cases from. If this is not set, the
+    /// cases are read from `data.path`, a TSV file whose last column is the
+    /// target.
+    #[serde(default)]
+    pub benchmark: Option<RegressionBenchmark>,
+    #[serde(skip)]
+    pub cases: Vec<RegressionCase>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum BooleanProblem {
+    /// The multiplexer with `size` address bits and `2^size` data bits
+    Multiplexer,
+    /// Whether an even number of the `size` inputs are set
+    EvenParity,
+}
+
+impl Default for BooleanProblem {
+    fn default() -> Self {
+        Self::Multiplexer
+    }
+}
+
+fn default_boolean_size() -> usize {
+    2
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BooleanConfig {
+    #[serde(default)]
+    pub backend: GpBackend,
+    #[serde(default)]
+    pub problem: BooleanProblem,
+    #[serde(default = "default_boolean_size")]
+    pub size: usize,
+    #[serde(skip)]
+    pub cases: Vec<BooleanCase>,
+}
+
+fn default_cases_per_epoch() -> usize {
+    64
+}
+
+fn default_keep_fraction() -> f64 {
+    0.5
+}
+
+fn default_resample_every() -> usize {
+    1
+}
+
+/// Selects the source of the problems each epoch is scored on. See
+/// `provider::get_classification_provider` for the names understood.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    /// "dataset" (the default) scores every epoch on `problems`, "boolean"
+    /// draws fresh rows of the `[boolean]` problem each epoch, and
+    /// "adversarial" does the same, but carries the hardest rows over.
+    #[serde(default)]
+    pub name: String,
+    /// The number of cases generated per epoch
+    #[serde(default = "default_cases_per_epoch")]
+    pub cases_per_epoch: usize,
+    /// The fraction of each epoch's cases that the adversarial provider
+    /// carries over into the next, hardest first.
+    #[serde(default = "default_keep_fraction")]
+    pub keep_fraction: f64,
+    /// If set, the "dataset" provider scores each epoch on a random sample
+    /// of this many problems, instead of all of them. Champions are still
+    /// scored on the full set.
+    #[serde(default)]
+    pub batch_size: Option<usize>,
+    /// The number of epochs between drawing new samples
+    #[serde(default = "default_resample_every")]
+    pub resample_every: usize,
+}
+
+impl Default for ProviderConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            cases_per_epoch: default_cases_per_epoch(),
+            keep_fraction: default_keep_fraction(),
+            batch_size: None,
+            resample_every: default_resample_every(),
+        }
+    }
+}
+
+impl ProviderConfig {
+    /// Checks that the provider exists, and can supply the job's cases.
+    /// Only the linear GP job draws its cases from a provider, and the
+    /// generated providers only generate rows of the `[boolean]` problem.
+    pub fn check(&self, job: Job) -> Result<(), Error> {
+        match (self.name.as_str(), job) {
+            ("", _) | ("dataset", _) => Ok(()),
+            ("boolean", Job::LinearGp) | ("adversarial", Job::LinearGp) => {
+                if self.cases_per_epoch == 0 {
+                    Err(Error::Parsing(
+                        "provider.cases_per_epoch must be positive".to_string(),
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+            ("boolean", _) | ("adversarial", _) => Err(Error::Parsing(format!(
+                "The {} provider generates boolean cases for the LinearGp job, not for {:?}",
+                self.name, job
+            ))),
+            (name, _) => Err(Error::Parsing(format!(
+                "No such problem provider as {:?}: try dataset, boolean, or adversarial",
+                name
+            ))),
+        }
+    }
+}
+
+/// How an arithmetic fault is handled. Either backend may fault by dividing
+/// by zero, by overflowing, or by producing a NaN.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum FaultHandling {
+    /// Each backend's own behaviour: integer overflow wraps, float overflow
+    /// is infinite, NaNs propagate, and division by zero is skipped, except
+    /// in LinearGp's integer `DIV`, which divides by one instead.
+    Native,
+    /// Protected ops, which give a harmless result: division by zero gives
+    /// one, and NaNs give zero. Overflow saturates.
+    Protect,
+    /// Results are clamped to the bounds of their type. Division by zero
+    /// gives the bound in the direction of the dividend, and NaNs give zero.
+    Saturate,
+    /// As `Native`, but each fault adds to the creature's
+    /// `arithmetic_faults` fitness score.
+    Penalize,
+}
+
+impl Default for FaultHandling {
+    fn default() -> Self {
+        Self::Native
+    }
+}
+
+/// Arithmetic semantics, shared by the LinearGp and Push backends.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArithmeticConfig {
+    #[serde(default)]
+    pub division_by_zero: FaultHandling,
+    #[serde(default)]
+    pub overflow: FaultHandling,
+    #[serde(default)]
+    pub nan: FaultHandling,
+    /// The chance that mutating a gene that carries an ephemeral random
+    /// constant perturbs the constant, rather than replacing the gene.
+    #[serde(default)]
+    pub constant_mutation_rate: f64,
+    /// The standard deviation of the gaussian noise added to a constant by
+    /// constant mutation. Integer constants always move by at least one.
+    #[serde(default = "default_one")]
+    pub constant_mutation_scale: f64,
+}
+
+impl Default for ArithmeticConfig {
+    fn default() -> Self {
+        Self {
+            division_by_zero: FaultHandling::default(),
+            overflow: FaultHandling::default(),
+            nan: FaultHandling::default(),
+            constant_mutation_rate: 0.0,
+            constant_mutation_scale: default_one(),
+        }
+    }
+}
+
+impl ArithmeticConfig {
+    /// Whether any kind of fault is penalized.
+    pub fn penalized(&self) -> bool {
+        [self.division_by_zero, self.overflow, self.nan]
+            .iter()
+            .any(|h| *h == FaultHandling::Penalize)
+    }
+}
+
+/// How the byte sequence job hands each buffer to its checker.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ByteInput {
+    /// Piped to the checker's standard input
+    Stdin,
+    /// Written to a temporary file, whose path replaces each `{}` in the
+    /// command's arguments, or is appended to them if there are none.
+    File,
+}
+
+impl Default for ByteInput {
+    fn default() -> Self {
+        Self::Stdin
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ByteSequenceConfig {
+    /// The checker, as a program followed by its arguments
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub input: ByteInput,
+    /// The scores to read from the JSON object that the checker prints to
+    /// its standard output. The checker's exit code is always scored, as
+    /// `exit_code`.
+    #[serde(default)]
+    pub scores: Vec<String>,
+    /// How long the checker may run before it's killed, and the buffer
+    /// given the worst possible scores
+    #[serde(default)]
+    pub timeout: Option<String>,
+    /// The `timeout`, parsed
+    #[serde(skip)]
+    pub timeout_duration: Option<Duration>,
+    /// The scores, leaked to serve as fitness keys
+    #[serde(skip)]
+    pub score_keys: Vec<&'static str>,
 }
 
 fn default_arch() -> unicorn::Arch {
@@ -279,11 +1919,30 @@ pub struct RoperConfig {
     #[serde(default)]
     pub randomize_registers: bool,
     pub register_pattern_file: Option<String>,
+    /// A register pattern given inline, as `[roper.register_pattern]`, in
+    /// the syntax of a register pattern file. It's taken after those in
+    /// `register_pattern_file`, if there is one.
+    #[serde(default)]
+    pub register_pattern: Option<RegisterPatternConfig>,
     #[serde(skip)]
     pub parsed_register_patterns: Vec<RegisterPattern>,
+    /// The cores the island's emulator workers are pinned to, if any, as
+    /// taken from `affinity.emulators`.
+    #[serde(skip)]
+    pub emulator_cores: Option<Vec<usize>>,
+    /// The island's `random_seed`, from which the hatchery's workers are
+    /// seeded in deterministic mode. Set by the evaluators.
+    #[serde(skip)]
+    pub random_seed: u64,
     #[serde(default = "Default::default")]
     pub soup: Option<Vec<u64>>,
     pub soup_size: Option<usize>,
+    /// Cluster the soup by the effects of its gadgets, and draw words from
+    /// the soup cluster by cluster. See `roper::clustering`.
+    #[serde(default)]
+    pub cluster_soup: bool,
+    #[serde(skip)]
+    pub soup_clusters: Option<Vec<Vec<u64>>>,
     // if no gadget file given
     #[serde(default = "default_arch")]
     pub arch: unicorn::Arch,
@@ -303,25 +1962,533 @@ pub struct RoperConfig {
     pub record_memory_writes: bool,
     #[serde(default = "default_stack_size")]
     pub emulator_stack_size: usize,
+    /// A cap, in bytes, on the memory each emulator maps for itself: the
+    /// stack, and any other writeable segments, including scripted ones.
+    /// Loading fails if the memory image would exceed it.
+    #[serde(default)]
+    pub max_emulator_memory: Option<usize>,
     pub binary_path: String,
     #[serde(default)]
     pub ld_paths: Option<Vec<String>>,
+    /// Bytes that can't appear in a payload, which are replaced as it's
+    /// packed, as the target's input handling would mangle them. See
+    /// `BadBytes`.
+    #[serde(default)]
+    pub bad_bytes: Option<BadBytes>,
+    /// What the bytes in a list of `bad_bytes` are replaced with. This
+    /// defaults to 0x41, and mustn't be among them.
     #[serde(default)]
-    pub bad_bytes: Option<HashMap<String, u8>>,
+    pub bad_byte_replacement: Option<u8>,
     pub memory_pattern: Option<Vec<u8>>,
     #[serde(default)]
     pub break_on_calls: bool,
+    /// How calls are handled. Takes precedence over `break_on_calls`, which
+    /// is kept for older configs. See `CallPolicy`.
+    #[serde(default)]
+    pub call_policy: Option<CallPolicy>,
     #[serde(default)]
     pub monitor_stack_writes: bool,
+    /// Whether the memory a chain dirtied is found, and restored before its
+    /// emulator is reused, by hooking writes to note the pages written to,
+    /// rather than by comparing every writeable region with the memory
+    /// image. The hook costs a little on each write, which is repaid unless
+    /// the writeable regions are few and small.
+    #[serde(default = "default_track_dirty_pages")]
+    pub track_dirty_pages: bool,
+    #[serde(default)]
+    pub watchpoints: Vec<Watchpoint>,
+    #[serde(default)]
+    pub chain_mode: ChainMode,
+    pub jop: Option<JopConfig>,
+    /// Register settings for sigreturn frames. Frame genes referring to these
+    /// templates are added to the soup, and are expanded into full frames
+    /// when the payload is packed. See `emulator::srop`.
+    #[serde(default)]
+    pub sigreturn_frames: Vec<HashMap<String, u64>>,
+    #[serde(default)]
+    pub memory_init: Vec<MemoryInit>,
+    /// Snapshot the registers on entry to each gadget, and record what each
+    /// gadget changed. Requires `record_basic_blocks`.
+    #[serde(default)]
+    pub record_register_deltas: bool,
+    /// The name of a preset of `arch`, `mode`, `abi`, `syscall_abi`, and
+    /// `output_registers`, such as "linux-x86_64", "linux-armv7", or
+    /// "win-x64", filling in whichever of them aren't set. See
+    /// `emulator::preset`.
+    #[serde(default)]
+    pub preset: Option<String>,
+    /// The calling convention used to resolve `argN` keys in register
+    /// patterns. Defaults to the usual convention for `arch` and `mode`.
+    #[serde(default)]
+    pub abi: Option<Abi>,
+    /// The operating system's syscall conventions, used to recognize and
+    /// trace syscalls. Defaults to Linux's conventions for `arch` and `mode`.
+    #[serde(default)]
+    pub syscall_abi: Option<SyscallAbi>,
+    /// If set, each payload is first screened with a reduced budget, and
+    /// only re-run with the full budget if it looks promising.
+    pub escalation: Option<EscalationConfig>,
+    /// Bound the emulator by steps alone, and seed the variation operators,
+    /// so that runs with the same seed can be replayed exactly. See
+    /// `Config::apply_deterministic_mode`.
+    #[serde(default)]
+    pub deterministic: bool,
+    /// Start execution at a given address, with a scripted register and
+    /// stack context, rather than by popping the first word of the payload.
+    pub entry: Option<EntryContext>,
+    /// Other builds of the target, each searched by the islands it lists,
+    /// in place of `binary_path`. See `TargetConfig`.
+    #[serde(default)]
+    pub targets: Vec<TargetConfig>,
+    /// The target the island searches: 0 for `binary_path`, and `i + 1` for
+    /// `targets[i]`. Set by `for_target`.
+    #[serde(skip)]
+    pub target: usize,
+}
+
+/// Another build of the target, searched by the islands in `islands` in
+/// place of the binary given in `[roper]`, as `[[roper.targets]]`. The
+/// fields left out are taken from `[roper]`, except `gadget_file`, since a
+/// gadget file's addresses are those of the binary it was harvested from.
+/// The addresses given elsewhere in `[roper]`, in `entry`, `memory_init`
+/// and the register patterns, are taken as they are for every target.
+///
+/// Each target is loaded into a memory image of its own, and its islands
+/// draw their genomes from a soup of its own. Specimens only migrate
+/// between islands searching the same target, since the words of their
+/// genomes are addresses in its memory image.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct TargetConfig {
+    pub islands: Vec<usize>,
+    pub binary_path: String,
+    #[serde(default)]
+    pub ld_paths: Option<Vec<String>>,
+    #[serde(default)]
+    pub gadget_file: Option<String>,
+    #[serde(default)]
+    pub arch: Option<unicorn::Arch>,
+    #[serde(default)]
+    pub mode: Option<unicorn::Mode>,
+    #[serde(default)]
+    pub abi: Option<Abi>,
+    #[serde(default)]
+    pub syscall_abi: Option<SyscallAbi>,
+}
+
+/// A hijack point, from which execution begins. The words in `stack` are
+/// laid out at the stack pointer, followed immediately by the payload, as
+/// though the payload had overflowed the frame they describe. Execution
+/// then starts at `address` -- at a function's epilogue, for instance, or
+/// an indirect callsite -- with `registers` set, and with the registers in
+/// `stack_relative_registers` set to the stack pointer plus the given
+/// offset.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct EntryContext {
+    pub address: u64,
+    #[serde(default)]
+    pub registers: HashMap<String, u64>,
+    #[serde(default)]
+    pub stack_relative_registers: HashMap<String, i64>,
+    #[serde(default)]
+    pub stack: Vec<u64>,
+}
+
+fn default_escalation_predicate() -> String {
+    "ret_count > 1".to_string()
+}
+
+/// A two-tier evaluation budget. Payloads are first run with the screening
+/// budget given here. If `predicate` evaluates to a nonzero value on the
+/// screening profile, the payload is run again with the full budget
+/// (`max_emu_steps` and `millisecond_timeout` in the `roper` section).
+///
+/// The predicate is an expression over the following variables, summed over
+/// the screening runs: `ret_count`, `gadgets_executed`, `blocks_executed`,
+/// `crashed`, and `sigreturn_count`.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct EscalationConfig {
+    pub max_emu_steps: Option<usize>,
+    pub millisecond_timeout: Option<u64>,
+    #[serde(default = "default_escalation_predicate")]
+    pub predicate: String,
+}
+
+/// A scripted memory initialization, applied to the memory image before
+/// evaluation. If `address` falls inside a segment of the binary, that
+/// segment is patched. Otherwise, a new segment is mapped, with `perms`.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct MemoryInit {
+    pub address: u64,
+    /// Hex-encoded bytes to write at `address`.
+    pub bytes: Option<String>,
+    /// A file whose contents are to be written at `address`.
+    pub file: Option<String>,
+    /// Some combination of "r", "w", and "x", for a new segment, which is
+    /// "rw" if left out. A patch keeps the permissions of the segment it
+    /// lands in, so these can't be given for one.
+    #[serde(default)]
+    pub perms: Option<String>,
+}
+
+impl MemoryInit {
+    pub fn data(&self) -> Result<Vec<u8>, Error> {
+        match (&self.bytes, &self.file) {
+            (Some(bytes), None) => hex::decode(bytes).map_err(|e| {
+                Error::Parsing(format!("Bad hex in memory_init at 0x{:x}: {}", self.address, e))
+            }),
+            (None, Some(file)) => Ok(std::fs::read(file)?),
+            _ => Err(Error::Parsing(format!(
+                "memory_init at 0x{:x} needs exactly one of `bytes` or `file`",
+                self.address
+            ))),
+        }
+    }
+}
+
+/// How the payload is laid out and driven in the emulator. In `Rop` mode, the
+/// chromosome is written to the stack and consumed by `ret` instructions. In
+/// `Jop` mode, it is written as a dispatch table, which a dispatcher gadget
+/// walks, and the functional gadgets end in indirect jumps or calls.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum ChainMode {
+    Rop,
+    Jop,
+}
+
+impl Default for ChainMode {
+    fn default() -> Self {
+        Self::Rop
+    }
+}
+
+/// What to do when a chain executes a call instruction. Whatever the policy,
+/// the call targets encountered are recorded in the profile.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum CallPolicy {
+    /// Halt the emulator at the call.
+    Break,
+    /// Skip the callee, as if it had returned `return_value` at once.
+    StepOver { return_value: u64 },
+    /// Follow the call, halting if calls nest deeper than `max_depth`.
+    Follow { max_depth: Option<usize> },
+}
+
+/// The bytes that can't appear in a payload, either as a list,
+///
+/// ```text
+/// bad_bytes = ["nulls", "0x0a", "0x0d"]
+/// bad_byte_replacement = 0x41
+/// ```
+///
+/// each entry a byte, in hex, or the name of a set of them -- "nulls",
+/// "newlines", or "whitespace" -- or, for finer control, as a table of the
+/// bytes, in hex, and what each is replaced with:
+///
+/// ```text
+/// bad_bytes = { "00" = 0x01, "0a" = 0x20 }
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum BadBytes {
+    List(Vec<String>),
+    Replacements(HashMap<String, u8>),
+}
+
+pub const DEFAULT_BAD_BYTE_REPLACEMENT: u8 = 0x41;
+
+/// The named sets of bad bytes.
+pub const BAD_BYTE_SETS: [(&str, &[u8]); 3] = [
+    ("nulls", &[0x00]),
+    ("newlines", &[0x0a, 0x0d]),
+    ("whitespace", &[0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x20]),
+];
+
+fn parse_hex_byte(s: &str) -> Result<u8, Error> {
+    let digits = s.trim_start_matches("0x").trim_start_matches("0X");
+    if digits.is_empty() || digits.len() > 2 {
+        return Err(Error::Parsing(format!(
+            "Bad byte {:?} should be one byte, in hex",
+            s
+        )));
+    }
+    u8::from_str_radix(digits, 16)
+        .map_err(|e| Error::Parsing(format!("Bad byte {:?} isn't hex: {}", s, e)))
+}
+
+impl BadBytes {
+    /// The replacement for each bad byte, as the payload packer takes it.
+    pub fn filter(&self, replacement: Option<u8>) -> Result<HashMap<u8, u8>, Error> {
+        match self {
+            BadBytes::Replacements(table) => table
+                .iter()
+                .map(|(k, v)| Ok((parse_hex_byte(k)?, *v)))
+                .collect(),
+            BadBytes::List(entries) => {
+                let replacement = replacement.unwrap_or(DEFAULT_BAD_BYTE_REPLACEMENT);
+                let mut filter = HashMap::new();
+                for entry in entries {
+                    match BAD_BYTE_SETS.iter().find(|(name, _)| name == entry) {
+                        Some((_, bytes)) => filter.extend(bytes.iter().map(|b| (*b, replacement))),
+                        None => {
+                            filter.insert(parse_hex_byte(entry)?, replacement);
+                        }
+                    }
+                }
+                if filter.contains_key(&replacement) {
+                    return Err(Error::Parsing(format!(
+                        "The bad byte replacement, 0x{:02x}, is itself a bad byte",
+                        replacement
+                    )));
+                }
+                Ok(filter)
+            }
+        }
+    }
+}
+
+fn default_max_jop_gadget_len() -> usize {
+    0x10
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct JopConfig {
+    /// The address of the dispatcher gadget, which advances the table
+    /// register and jumps to the address it points to.
+    pub dispatcher: u64,
+    /// The register the dispatcher uses as its pointer into the dispatch
+    /// table. It is initialized to the address of the first entry.
+    pub table_register: String,
+    /// The register through which the functional gadgets jump back to the
+    /// dispatcher. It is initialized to the dispatcher's address.
+    pub return_register: String,
+    /// The maximum number of bytes preceding an indirect branch that we'll
+    /// consider when harvesting gadgets for the soup.
+    #[serde(default = "default_max_jop_gadget_len")]
+    pub max_gadget_len: usize,
+}
+
+fn default_watchpoint_size() -> usize {
+    8
+}
+
+/// A data watchpoint. Every read or write touching the `size` bytes
+/// beginning at `address` is recorded by the profiler.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Watchpoint {
+    pub name: String,
+    pub address: u64,
+    #[serde(default = "default_watchpoint_size")]
+    pub size: usize,
+    /// The fitness key, `hit_watchpoint_<name>`, set by
+    /// `RoperConfig::intern_watchpoint_keys`.
+    #[serde(skip)]
+    pub key: &'static str,
 }
 
 impl RoperConfig {
-    pub fn parse_register_patterns(&mut self) {
-        if let Some(ref pat_file) = self.register_pattern_file {
-            let ps = parse_register_pattern_file(pat_file)
-                .expect("Failed to parse register pattern file");
-            log::info!("Parsed and reduced register patterns: {:#x?}", ps);
-            self.parsed_register_patterns = ps;
+    /// Parses the register patterns, resolving their argument keys through
+    /// the calling convention. This is done once the binary is loaded, which
+    /// settles the architecture, so it's here, too, that we check that the
+    /// conventions the patterns and the call policy rely on are known for it.
+    pub fn parse_register_patterns(&mut self) -> Result<(), Error> {
+        if let CallPolicy::StepOver { .. } = self.call_policy() {
+            abi::return_register(self.arch, self.mode)?;
+        }
+        let mut ps = match self.register_pattern_file {
+            Some(ref pat_file) => parse_register_pattern_file(pat_file)?,
+            None => vec![],
+        };
+        if let Some(ref pattern) = self.register_pattern {
+            ps.push(pattern.to_pattern()?);
+        }
+        if ps.is_empty() {
+            return Ok(());
+        }
+        let has_args = ps
+            .iter()
+            .any(|p| p.0.keys().any(|k| abi::parse_arg_key(k).is_some()));
+        let ps = if has_args {
+            let abi = self.abi()?;
+            ps.into_iter()
+                .map(|p| p.resolve_arguments(abi))
+                .collect::<Vec<_>>()
+        } else {
+            ps
+        };
+        log::info!("Parsed and reduced register patterns: {:#x?}", ps);
+        self.parsed_register_patterns = ps;
+        // the stack pointer is read along with any stack slots
+        self.registers_to_check()?;
+        Ok(())
+    }
+
+    pub fn bad_byte_filter(&self) -> Result<Option<HashMap<u8, u8>>, Error> {
+        self.bad_bytes
+            .as_ref()
+            .map(|b| b.filter(self.bad_byte_replacement))
+            .transpose()
+    }
+
+    /// Sigreturn frames can only be built where we know the frame's layout,
+    /// and only set the registers found in it.
+    pub fn check_sigreturn_frames(&self) -> Result<(), Error> {
+        if self.sigreturn_frames.is_empty() {
+            return Ok(());
+        }
+        let layout =
+            crate::emulator::srop::frame_layout(self.arch, self.mode).ok_or_else(|| {
+                Error::Parsing(format!(
+                    "sigreturn_frames aren't supported on {:?} in {:?} mode, only on x86_64",
+                    self.arch, self.mode
+                ))
+            })?;
+        for (i, frame) in self.sigreturn_frames.iter().enumerate() {
+            for register in frame.keys() {
+                // the unnamed slots aren't registers
+                if register.is_empty() || !layout.contains(&register.as_str()) {
+                    return Err(Error::Parsing(format!(
+                        "No register {:?} in the sigreturn frame, in sigreturn_frames[{}]. \
+                         Try one of {}",
+                        register,
+                        i,
+                        layout
+                            .iter()
+                            .filter(|r| !r.is_empty())
+                            .cloned()
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that no island is listed by more than one of the `targets`,
+    /// and that each lists some, and checks what depends on the
+    /// architecture for each. The islands are grouped by target, each group
+    /// holding tournaments of its own, so tournament selection is needed.
+    pub fn check_targets(&self, num_islands: usize, selection: Selection) -> Result<(), Error> {
+        if self.targets.is_empty() {
+            return Ok(());
+        }
+        match selection {
+            Selection::Tournament => (),
+            selection => {
+                return Err(Error::Parsing(format!(
+                    "roper.targets needs Tournament selection, not {:?}",
+                    selection
+                )))
+            }
+        }
+        let mut listed = HashSet::new();
+        for (i, target) in self.targets.iter().enumerate() {
+            if target.islands.is_empty() {
+                return Err(Error::Parsing(format!(
+                    "roper.targets[{}] lists no islands",
+                    i
+                )));
+            }
+            for &island in target.islands.iter() {
+                if island >= num_islands {
+                    return Err(Error::Parsing(format!(
+                        "roper.targets[{}] lists island {}, but there are only {} islands",
+                        i, island, num_islands
+                    )));
+                }
+                if !listed.insert(island) {
+                    return Err(Error::Parsing(format!(
+                        "Island {} is listed by more than one of roper.targets",
+                        island
+                    )));
+                }
+            }
+            let config = self.for_target(i + 1);
+            config.check_sigreturn_frames()?;
+            crate::roper::jop::check_config(&config)?;
+        }
+        Ok(())
+    }
+
+    /// The target the island searches: `i + 1`, if `targets[i]` lists it,
+    /// or 0, for `binary_path`.
+    pub fn target_of(&self, island: usize) -> usize {
+        self.targets
+            .iter()
+            .position(|t| t.islands.contains(&island))
+            .map(|i| i + 1)
+            .unwrap_or(0)
+    }
+
+    /// The section as it applies to the islands searching `target`, with
+    /// the fields of `targets[target - 1]` in place of its own.
+    pub fn for_target(&self, target: usize) -> Self {
+        let mut config = self.clone();
+        config.target = target;
+        if target == 0 {
+            return config;
+        }
+        let t = &self.targets[target - 1];
+        config.binary_path = t.binary_path.clone();
+        config.gadget_file = t.gadget_file.clone();
+        if t.ld_paths.is_some() {
+            config.ld_paths = t.ld_paths.clone();
+        }
+        if let Some(arch) = t.arch {
+            config.arch = arch;
+        }
+        if let Some(mode) = t.mode {
+            config.mode = mode;
+        }
+        if t.abi.is_some() {
+            config.abi = t.abi;
+        }
+        if t.syscall_abi.is_some() {
+            config.syscall_abi = t.syscall_abi;
+        }
+        config
+    }
+
+    pub fn call_policy(&self) -> CallPolicy {
+        match self.call_policy {
+            Some(policy) => policy,
+            None if self.break_on_calls => CallPolicy::Break,
+            None => CallPolicy::Follow { max_depth: None },
+        }
+    }
+
+    pub fn abi(&self) -> Result<Abi, Error> {
+        match self.abi {
+            Some(abi) => Ok(abi),
+            None => Abi::default_for(self.arch, self.mode),
+        }
+    }
+
+    pub fn syscall_abi(&self) -> Option<SyscallAbi> {
+        self.syscall_abi
+            .or_else(|| SyscallAbi::default_for(self.arch, self.mode))
+    }
+
+    /// Offsets from the stack pointer of the stack slots named in the
+    /// register patterns, after argument resolution.
+    pub fn stack_slots(&self) -> Vec<u64> {
+        let mut slots = self
+            .parsed_register_patterns
+            .iter()
+            .flat_map(|rp| rp.stack_slots())
+            .collect::<Vec<u64>>();
+        slots.sort();
+        slots.dedup();
+        slots
+    }
+
+    /// The fitness map is keyed by static strings, so we leak the watchpoint
+    /// keys once, here, rather than every time a creature is scored.
+    pub fn intern_watchpoint_keys(&mut self) {
+        for wp in self.watchpoints.iter_mut() {
+            if wp.key.is_empty() {
+                wp.key = Box::leak(format!("hit_watchpoint_{}", wp.name).into_boxed_str());
+            }
         }
     }
 
@@ -329,7 +2496,7 @@ impl RoperConfig {
         &self.parsed_register_patterns
     }
 
-    pub fn registers_to_check(&self) -> Vec<String> {
+    pub fn registers_to_check(&self) -> Result<Vec<String>, Error> {
         let mut set = HashSet::new();
         for r in self
             .output_registers
@@ -340,10 +2507,14 @@ impl RoperConfig {
         }
         for rp in self.parsed_register_patterns.iter() {
             for r in rp.0.keys() {
-                set.insert(r.clone());
+                if abi::parse_stack_slot_key(r).is_some() {
+                    set.insert(abi::stack_pointer(self.arch, self.mode)?.to_string());
+                } else {
+                    set.insert(r.clone());
+                }
             }
         }
-        set.into_iter().collect::<Vec<String>>()
+        Ok(set.into_iter().collect::<Vec<String>>())
     }
 }
 
@@ -363,6 +2534,10 @@ const fn default_stack_size() -> usize {
     0x1000
 }
 
+const fn default_track_dirty_pages() -> bool {
+    true
+}
+
 impl Default for RoperConfig {
     fn default() -> Self {
         Self {
@@ -372,9 +2547,14 @@ impl Default for RoperConfig {
             input_registers: vec![],
             randomize_registers: false,
             register_pattern_file: None,
+            register_pattern: None,
             parsed_register_patterns: vec![],
+            emulator_cores: None,
+            random_seed: 0,
             soup: None,
             soup_size: None,
+            cluster_soup: false,
+            soup_clusters: None,
             arch: unicorn::Arch::X86,
             mode: unicorn::Mode::MODE_64,
             memory_pattern: None,
@@ -386,31 +2566,66 @@ impl Default for RoperConfig {
             record_basic_blocks: false,
             record_memory_writes: false,
             emulator_stack_size: 0x1000,
+            max_emulator_memory: None,
             binary_path: "/bin/sh".to_string(),
             ld_paths: None,
             bad_bytes: None,
+            bad_byte_replacement: None,
             break_on_calls: false,
+            call_policy: None,
             monitor_stack_writes: false,
+            track_dirty_pages: true,
+            watchpoints: vec![],
+            chain_mode: ChainMode::Rop,
+            jop: None,
+            sigreturn_frames: vec![],
+            memory_init: vec![],
+            record_register_deltas: false,
+            preset: None,
+            abi: None,
+            syscall_abi: None,
+            escalation: None,
+            deterministic: false,
+            entry: None,
+            targets: vec![],
+            target: 0,
         }
     }
 }
 
+/// A problem with inputs of type `I` and outputs of type `O`, such as
+/// `i64`, `f64`, or byte strings (`Vec<u8>`). By default, these are the
+/// machine words of the linear GP VM.
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
-pub struct ClassificationProblem {
-    pub input: Vec<i32>,
-    // TODO make this more generic
-    pub output: i32,
+pub struct ClassificationProblem<I = i32, O = I> {
+    pub input: Vec<I>,
+    /// A classification problem has a single output, the class. Problems
+    /// with vector targets have one output per return register.
+    pub output: Vec<O>,
     // Ditto
     pub tag: u64,
 }
 
-impl PartialOrd for ClassificationProblem {
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RegressionCase {
+    pub input: Vec<f64>,
+    pub output: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BooleanCase {
+    pub input: Vec<bool>,
+    pub output: bool,
+}
+
+// Problems are ordered by their tags alone.
+impl<I: PartialEq, O: PartialEq> PartialOrd for ClassificationProblem<I, O> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         self.tag.partial_cmp(&other.tag)
     }
 }
 
-impl Ord for ClassificationProblem {
+impl<I: Eq, O: Eq> Ord for ClassificationProblem<I, O> {
     fn cmp(&self, other: &Self) -> Ordering {
         self.tag.cmp(&other.tag)
     }
@@ -436,3 +2651,395 @@ pub enum Problem {
     RegisterSpecification(RegisterPattern),
     MemoryPattern(Vec<u8>),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_config_formats() {
+        let toml = ConfigFormat::Toml
+            .parse("pop_size = 100\n[fitness]\nweighting = \"place\"\npriority = 1.5\n")
+            .unwrap();
+        let yaml = ConfigFormat::Yaml
+            .parse("pop_size: 100\nfitness:\n  weighting: place\n  priority: 1.5\n")
+            .unwrap();
+        let json = ConfigFormat::Json
+            .parse(r#"{"pop_size": 100, "fitness": {"weighting": "place", "priority": 1.5}}"#)
+            .unwrap();
+        assert_eq!(toml, yaml);
+        assert_eq!(toml, json);
+        assert_eq!(
+            ConfigFormat::of_path(Path::new("experiments/a.yml")),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::of_path(Path::new("config.toml")),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::of_path(Path::new("config")),
+            ConfigFormat::Toml
+        );
+    }
+
+    #[test]
+    fn test_interpolate() {
+        std::env::set_var("BERBALANG_TEST_POP_SIZE", "500");
+        std::env::remove_var("BERBALANG_TEST_UNSET");
+        assert_eq!(
+            interpolate("pop_size = ${BERBALANG_TEST_POP_SIZE}\n").unwrap(),
+            "pop_size = 500\n"
+        );
+        assert_eq!(
+            interpolate("a = \"${BERBALANG_TEST_UNSET:-x86}\" # $${HOME}").unwrap(),
+            "a = \"x86\" # ${HOME}"
+        );
+        assert!(interpolate("a = ${BERBALANG_TEST_UNSET}").is_err());
+        assert!(interpolate("a = ${BERBALANG_TEST_POP_SIZE").is_err());
+    }
+
+    #[test]
+    fn test_includes() {
+        let dir = std::env::temp_dir().join(format!("berbalang_config_{}", rand::random::<u64>()));
+        std::fs::create_dir_all(dir.join("common")).unwrap();
+        std::fs::write(
+            dir.join("common/base.toml"),
+            "pop_size = 100\nnum_islands = 4\n[fitness]\nweighting = \"place\"\npriority = 1\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("common/arch.yaml"),
+            "num_islands: 8\nroper:\n  arch: ARM\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("experiment.toml"),
+            "include = [\"common/base.toml\", \"common/arch.yaml\"]\n\
+             pop_size = 200\n[fitness]\nweighting = \"ret_count\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("loop.toml"), "include = \"loop.toml\"\n").unwrap();
+
+        let value = load_value(
+            &dir.join("experiment.toml"),
+            ConfigFormat::Toml,
+            &mut Vec::new(),
+        );
+        let cycle = load_value(&dir.join("loop.toml"), ConfigFormat::Toml, &mut Vec::new());
+        std::fs::remove_dir_all(&dir).unwrap();
+        let value = value.unwrap();
+        assert!(value.get("include").is_none());
+        assert_eq!(value["pop_size"].as_integer(), Some(200));
+        assert_eq!(value["num_islands"].as_integer(), Some(8));
+        assert_eq!(value["fitness"]["weighting"].as_str(), Some("ret_count"));
+        assert_eq!(value["fitness"]["priority"].as_integer(), Some(1));
+        assert_eq!(value["roper"]["arch"].as_str(), Some("ARM"));
+        assert!(cycle.is_err());
+    }
+
+    #[test]
+    fn test_provider_check() {
+        let provider = |name: &str| ProviderConfig {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        assert!(provider("").check(Job::Hello).is_ok());
+        assert!(provider("adversarial").check(Job::LinearGp).is_ok());
+        assert!(provider("boolean").check(Job::Regression).is_err());
+        assert!(provider("bolean").check(Job::LinearGp).is_err());
+    }
+
+    #[test]
+    fn test_check_selection() {
+        let mut config = Config::default();
+        config.job = Job::Regression;
+        config.selection = Selection::Tournament;
+        assert!(config.check_selection().is_ok());
+        config.selection = Selection::Lexicase;
+        assert!(config.check_selection().is_err());
+        config.job = Job::LinearGp;
+        assert!(config.check_selection().is_ok());
+    }
+
+    #[test]
+    fn test_hello_weighting() {
+        let hello = HelloConfig::default();
+        let fitness = |weighting: &str| FitnessConfig {
+            weighting: weighting.to_string(),
+            ..Default::default()
+        };
+        let mut current = fitness("damerau_levenshtein + genetic_freq / 10");
+        hello.check_weighting(&mut current).unwrap();
+        assert_eq!(current.weighting, "damerau_levenshtein + genetic_freq / 10");
+        // written for the old Hello job, which ignored the weighting
+        let mut legacy = fitness("ret_count + crash_count");
+        hello.check_weighting(&mut legacy).unwrap();
+        assert_eq!(legacy.weighting, "damerau_levenshtein");
+        let mut unknown = fitness("hamming + lcs");
+        assert!(hello.check_weighting(&mut unknown).is_err());
+    }
+
+    #[test]
+    fn test_apply_override() {
+        let mut config: toml::Value = toml::from_str(
+            "pop_size = 100\n[fitness]\nfunction = \"code_coverage\"\n\
+             [[hooks.commands]]\non = \"EpochEnd\"\ncommand = \"true\"\n",
+        )
+        .unwrap();
+        apply_override(&mut config, "pop_size=500").unwrap();
+        apply_override(&mut config, "fitness.weighting=place + 2 * ret_count").unwrap();
+        apply_override(&mut config, "roper.arch=\"ARM\"").unwrap();
+        apply_override(&mut config, "hooks.commands.0.on=Stagnation").unwrap();
+        assert_eq!(config["pop_size"].as_integer(), Some(500));
+        assert_eq!(
+            config["fitness"]["weighting"].as_str(),
+            Some("place + 2 * ret_count")
+        );
+        assert_eq!(
+            config["fitness"]["function"].as_str(),
+            Some("code_coverage")
+        );
+        assert_eq!(config["roper"]["arch"].as_str(), Some("ARM"));
+        assert_eq!(
+            config["hooks"]["commands"][0]["on"].as_str(),
+            Some("Stagnation")
+        );
+        assert!(apply_override(&mut config, "pop_size").is_err());
+        assert!(apply_override(&mut config, "pop_size.x=1").is_err());
+        assert!(apply_override(&mut config, "hooks.commands.3.on=x").is_err());
+    }
+
+    #[test]
+    fn test_timeout_duration() {
+        let mut config = Config::default();
+        assert_eq!(config.timeout_duration().unwrap(), None);
+        config.timeout = Some("36h".to_string());
+        assert_eq!(
+            config.timeout_duration().unwrap(),
+            Some(Duration::from_secs(36 * 3600))
+        );
+        config.timeout = Some("90m".to_string());
+        assert_eq!(
+            config.timeout_duration().unwrap(),
+            Some(Duration::from_secs(90 * 60))
+        );
+        config.timeout = Some("soon".to_string());
+        assert!(config.timeout_duration().is_err());
+    }
+
+    #[test]
+    fn test_literal_rates() {
+        let mut rates = LiteralRates::default();
+        assert!(rates.check().is_ok());
+        rates.word = 0.0;
+        rates.function = 0.0;
+        assert!(rates.check().is_err());
+        rates.int = 2.0;
+        assert!(rates.check().is_ok());
+        rates.float = -1.0;
+        assert!(rates.check().is_err());
+        rates.float = std::f64::NAN;
+        assert!(rates.check().is_err());
+        rates.float = std::f64::INFINITY;
+        assert!(rates.check().is_err());
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let config = Config::default();
+        let given: toml::Value = toml::from_str(
+            "pop_size = 100
+mutation_expnent = 2.0
+frobnicate = true
+             [observer]
+sinks = [\"csv\"]
+sinkz = [\"log\"]
+",
+        )
+        .unwrap();
+        let parsed = serde_json::to_value(&config).unwrap();
+        let mut unknown = Vec::new();
+        unknown_keys(&given, &parsed, "", &mut unknown);
+        unknown.sort();
+        assert_eq!(
+            unknown,
+            vec![
+                "frobnicate",
+                "mutation_expnent (did you mean mutation_exponent?)",
+                "observer.sinkz (did you mean observer.sinks?)",
+            ]
+        );
+        assert!(check_keys(&given, &config).is_err());
+    }
+    #[test]
+    fn test_bad_bytes() {
+        let roper: RoperConfig =
+            toml::from_str("binary_path = \"\"\nbad_bytes = [\"nulls\", \"0x0a\", \"ff\"]\n")
+                .unwrap();
+        let filter = roper.bad_byte_filter().unwrap().unwrap();
+        assert_eq!(filter.len(), 3);
+        assert_eq!(filter[&0x00], DEFAULT_BAD_BYTE_REPLACEMENT);
+        assert_eq!(filter[&0xff], DEFAULT_BAD_BYTE_REPLACEMENT);
+
+        let roper: RoperConfig =
+            toml::from_str("binary_path = \"\"\nbad_bytes = { \"0a\" = 0x20 }\n").unwrap();
+        assert_eq!(roper.bad_byte_filter().unwrap().unwrap()[&0x0a], 0x20);
+
+        let bad = |entries: &[&str], replacement: Option<u8>| {
+            BadBytes::List(entries.iter().map(|e| e.to_string()).collect())
+                .filter(replacement)
+                .is_err()
+        };
+        assert!(bad(&["0x100"], None));
+        assert!(bad(&["tabs"], None));
+        assert!(bad(&["whitespace"], Some(0x20)));
+        assert!(!bad(&["whitespace"], Some(0x2e)));
+    }
+
+    #[test]
+    fn test_logging_filters() {
+        let logging: LoggingConfig = toml::from_str(
+            "level = \"info\"\n[modules]\nemulator = \"warn\"\n\"roper::push\" = \"debug\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            logging.filters().unwrap(),
+            vec![
+                (None, log::LevelFilter::Info),
+                (
+                    Some("berbalib::emulator".to_string()),
+                    log::LevelFilter::Warn
+                ),
+                (
+                    Some("berbalib::roper::push".to_string()),
+                    log::LevelFilter::Debug
+                ),
+            ]
+        );
+        assert_eq!(logging.destinations, default_log_destinations());
+        let logging: LoggingConfig = toml::from_str("level = \"loud\"\n").unwrap();
+        assert!(logging.filters().is_err());
+    }
+
+    #[test]
+    fn test_objectives() {
+        let fitness = |toml: &str| -> FitnessConfig {
+            toml::from_str(&format!(
+                "target = 0\neval_by_case = false\ndynamic = false\nfunction = \"f\"\n{}\n\
+                 [[objectives]]\nname = \"register_error\"\n\
+                 [[objectives]]\nname = \"crash_count\"\ndirection = \"Minimize\"\n",
+                toml
+            ))
+            .unwrap()
+        };
+        assert!(fitness("weighting = \"register_error + crash_count\"")
+            .check_objectives()
+            .is_ok());
+        let err = fitness("weighting = \"register_eror + crash_count\"")
+            .check_objectives()
+            .unwrap_err();
+        assert!(format!("{:?}", err).contains("register_eror (did you mean register_error?)"));
+
+        let mut fitness = fitness("weighting = \"register_error\"");
+        fitness.intern_objective_keys();
+        let mut weighted = fitness.weighted();
+        weighted.insert("register_error", 0.5);
+        assert_eq!(weighted.missing(), vec!["crash_count"]);
+    }
+
+    #[test]
+    fn test_unknown_conventions() {
+        let roper = |arch, mode, key: &str| {
+            let mut pattern = HashMap::new();
+            pattern.insert(key.to_string(), "0x1".to_string());
+            RoperConfig {
+                arch,
+                mode,
+                register_pattern: Some(RegisterPatternConfig(pattern)),
+                ..Default::default()
+            }
+        };
+        let mut config = roper(unicorn::Arch::X86, unicorn::Mode::MODE_64, "arg0");
+        assert!(config.parse_register_patterns().is_ok());
+        assert!(config.parsed_register_patterns[0].0.contains_key("RDI"));
+
+        // there's no default calling convention for MIPS
+        let mut config = roper(unicorn::Arch::MIPS, unicorn::Mode::MODE_32, "arg0");
+        assert!(config.parse_register_patterns().is_err());
+        config.abi = Some(Abi::Aapcs);
+        assert!(config.parse_register_patterns().is_ok());
+
+        // nor a known stack pointer for SPARC
+        let mut config = roper(unicorn::Arch::SPARC, unicorn::Mode::MODE_32, "STACK+0x8");
+        assert!(config.parse_register_patterns().is_err());
+        assert!(config.registers_to_check().is_err());
+    }
+
+    #[test]
+    fn test_check_sigreturn_frames() {
+        let mut frame = HashMap::new();
+        frame.insert("RAX".to_string(), 15);
+        let mut config = RoperConfig {
+            arch: unicorn::Arch::X86,
+            mode: unicorn::Mode::MODE_64,
+            sigreturn_frames: vec![frame],
+            ..Default::default()
+        };
+        assert!(config.check_sigreturn_frames().is_ok());
+        config.sigreturn_frames[0].insert("RXA".to_string(), 1);
+        let err = config.check_sigreturn_frames().unwrap_err();
+        assert!(format!("{:?}", err).contains("RXA"));
+        config.sigreturn_frames[0].remove("RXA");
+        config.sigreturn_frames[0].insert("rip".to_string(), 1);
+        assert!(config.check_sigreturn_frames().is_err());
+        config.sigreturn_frames[0].remove("rip");
+        config.arch = unicorn::Arch::ARM;
+        config.mode = unicorn::Mode::MODE_32;
+        assert!(config.check_sigreturn_frames().is_err());
+    }
+
+    #[test]
+    fn test_targets() {
+        let target = |islands: Vec<usize>, binary_path: &str| TargetConfig {
+            islands,
+            binary_path: binary_path.to_string(),
+            ld_paths: None,
+            gadget_file: None,
+            arch: None,
+            mode: None,
+            abi: None,
+            syscall_abi: None,
+        };
+        let mut config = RoperConfig {
+            binary_path: "./target-O0".to_string(),
+            gadget_file: Some("./target-O0.soup.json".to_string()),
+            mode: unicorn::Mode::MODE_64,
+            targets: vec![
+                target(vec![1, 3], "./target-O2"),
+                target(vec![2], "./target-i386"),
+            ],
+            ..Default::default()
+        };
+        config.targets[1].mode = Some(unicorn::Mode::MODE_32);
+        assert_eq!(config.target_of(0), 0);
+        assert_eq!(config.target_of(3), 1);
+        assert_eq!(config.target_of(2), 2);
+        assert_eq!(config.for_target(0), config);
+        let o2 = config.for_target(1);
+        assert_eq!(o2.target, 1);
+        assert_eq!(o2.binary_path, "./target-O2");
+        assert_eq!(o2.gadget_file, None);
+        assert_eq!(o2.mode, unicorn::Mode::MODE_64);
+        assert_eq!(config.for_target(2).mode, unicorn::Mode::MODE_32);
+
+        assert!(config.check_targets(4, Selection::Tournament).is_ok());
+        assert!(config.check_targets(4, Selection::Roulette).is_err());
+        // island 3 doesn't exist
+        assert!(config.check_targets(3, Selection::Tournament).is_err());
+        config.targets[1].islands.push(1);
+        assert!(config.check_targets(4, Selection::Tournament).is_err());
+        config.targets[1].islands.clear();
+        assert!(config.check_targets(4, Selection::Tournament).is_err());
+    }
+}
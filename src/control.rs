@@ -0,0 +1,232 @@
+//! A few parameters that can be changed while a run is under way, by
+//! editing `control.toml`, beside the island directories, or by posting the
+//! same TOML to the dashboard's `/control` route:
+//!
+//! ```text
+//! mutation_rate = 0.05
+//! migration_rate = 0.01
+//! temperature = 2.0     # of the Metropolis world's acceptance of worse variations
+//! report_every = 500
+//! ```
+//!
+//! Only the fields listed here can be changed, and any of them may be left
+//! out. Changes are staged when they're read, and taken up the next time
+//! any island reaches an epoch boundary, whereupon each world applies them
+//! to its config at the start of its next generation, and each observer
+//! logs them.
+use std::fs;
+use std::mem;
+use std::sync::{Arc, Once, RwLock};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::configure::Config;
+use crate::error::Error;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Controls {
+    pub mutation_rate: Option<f64>,
+    /// The tournament world's migration rate. This is ignored in
+    /// deterministic mode, in which there's no migration.
+    pub migration_rate: Option<f64>,
+    pub temperature: Option<f64>,
+    /// This has no effect if the observer's `metrics_cadence` is set.
+    pub report_every: Option<usize>,
+}
+
+impl Controls {
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let controls: Self = toml::from_str(text)?;
+        controls.validate()?;
+        Ok(controls)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let rate = |name: &str, rate: Option<f64>| match rate {
+            Some(r) if !(0.0..=1.0).contains(&r) => Err(Error::Misc(format!(
+                "{} must be between 0 and 1, not {}",
+                name, r
+            ))),
+            _ => Ok(()),
+        };
+        rate("mutation_rate", self.mutation_rate)?;
+        rate("migration_rate", self.migration_rate)?;
+        match self.temperature {
+            Some(t) if !(t > 0.0 && t.is_finite()) => {
+                return Err(Error::Misc(format!(
+                    "temperature must be positive, not {}",
+                    t
+                )))
+            }
+            _ => {}
+        }
+        if self.report_every == Some(0) {
+            return Err(Error::Misc("report_every must be positive".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Overlays `other` on these controls, its fields taking precedence.
+    fn merge(&mut self, other: Controls) {
+        self.mutation_rate = other.mutation_rate.or(self.mutation_rate);
+        self.migration_rate = other.migration_rate.or(self.migration_rate);
+        self.temperature = other.temperature.or(self.temperature);
+        self.report_every = other.report_every.or(self.report_every);
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether applying the controls would change the config.
+    pub fn changes(&self, config: &Config) -> bool {
+        let differs = |control: Option<f64>, value: f64| control.map_or(false, |c| c != value);
+        differs(self.mutation_rate, config.mutation_rate)
+            || (!config.roper.deterministic
+                && differs(self.migration_rate, config.tournament.migration_rate))
+            || (self.temperature.is_some() && self.temperature != config.temperature)
+            || (self.report_every.is_some() && self.report_every != config.observer.report_every)
+    }
+
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(rate) = self.mutation_rate {
+            config.mutation_rate = rate;
+        }
+        if let Some(rate) = self.migration_rate {
+            if !config.roper.deterministic {
+                config.tournament.migration_rate = rate;
+            }
+        }
+        if self.temperature.is_some() {
+            config.temperature = self.temperature;
+        }
+        if self.report_every.is_some() {
+            config.observer.report_every = self.report_every;
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Changes read, but not yet taken up
+    staged: Controls,
+    /// The controls in force
+    current: Controls,
+    /// When the control file was last read
+    file_modified: Option<SystemTime>,
+}
+
+static INIT_CONTROLS: Once = Once::new();
+static mut CONTROLS: Option<RwLock<State>> = None;
+
+fn state() -> &'static RwLock<State> {
+    INIT_CONTROLS.call_once(|| unsafe { CONTROLS = Some(RwLock::new(State::default())) });
+    unsafe {
+        CONTROLS
+            .as_ref()
+            .expect("The controls have not been initialized")
+    }
+}
+
+pub fn control_path(config: &Config) -> String {
+    format!("{}/../control.toml", config.data_directory())
+}
+
+/// Stages changes to the controls, to be taken up at the next epoch
+/// boundary.
+pub fn submit(controls: Controls) -> Result<(), Error> {
+    controls.validate()?;
+    state().write().expect("poisoned").staged.merge(controls);
+    Ok(())
+}
+
+/// Reads the control file, if it has been modified since it was last read,
+/// and stages its contents.
+fn poll_file(config: &Config) {
+    if config.data_directory().is_empty() {
+        return;
+    }
+    let path = control_path(config);
+    let modified = match fs::metadata(&path).and_then(|m| m.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return,
+    };
+    {
+        let mut state = state().write().expect("poisoned");
+        if state.file_modified == Some(modified) {
+            return;
+        }
+        state.file_modified = Some(modified);
+    }
+    let res = fs::read_to_string(&path)
+        .map_err(Error::from)
+        .and_then(|text| Controls::parse(&text))
+        .and_then(submit);
+    if let Err(e) = res {
+        log::error!("Ignoring the changes in {}: {:?}", path, e);
+    }
+}
+
+/// Polls the control file, and takes up whatever changes have been staged.
+/// Each island's observer calls this at its epoch boundaries.
+pub fn at_epoch_boundary(config: &Config) {
+    poll_file(config);
+    let mut state = state().write().expect("poisoned");
+    if state.staged.is_empty() {
+        return;
+    }
+    let staged = mem::replace(&mut state.staged, Controls::default());
+    state.current.merge(staged);
+}
+
+/// The controls in force.
+pub fn current() -> Controls {
+    state().read().expect("poisoned").current.clone()
+}
+
+/// Applies the controls in force to a world's config.
+pub fn apply(config: &mut Config) {
+    let controls = current();
+    if controls.changes(config) {
+        controls.apply(config);
+    }
+}
+
+/// Applies the controls in force to a world's shared config, cloning it
+/// only if they change it.
+pub fn apply_shared(config: &mut Arc<Config>) {
+    let controls = current();
+    if controls.changes(config) {
+        controls.apply(Arc::make_mut(config));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_controls() {
+        let controls = Controls::parse("mutation_rate = 0.5\nreport_every = 10\n").unwrap();
+        let mut config = Config::default();
+        assert!(controls.changes(&config));
+        controls.apply(&mut config);
+        assert_eq!(config.mutation_rate, 0.5);
+        assert_eq!(config.observer.report_every, Some(10));
+        assert!(!controls.changes(&config));
+
+        let mut merged = controls;
+        merged.merge(Controls::parse("mutation_rate = 0.25\ntemperature = 2.0\n").unwrap());
+        assert_eq!(merged.mutation_rate, Some(0.25));
+        assert_eq!(merged.report_every, Some(10));
+        assert_eq!(merged.temperature, Some(2.0));
+
+        assert!(Controls::parse("mutation_rate = 1.5\n").is_err());
+        assert!(Controls::parse("temperature = 0.0\n").is_err());
+        assert!(Controls::parse("report_every = 0\n").is_err());
+        // only the whitelisted parameters can be changed
+        assert!(Controls::parse("pop_size = 10\n").is_err());
+    }
+}
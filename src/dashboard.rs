@@ -0,0 +1,272 @@
+//! A small, built-in web dashboard, showing the progress of a run as the
+//! observers see it. It's enabled by adding "dashboard" to the observer's
+//! sinks, and served, for every island in the process, at the observer's
+//! `dashboard_address`.
+//!
+//! The run-time controls in force can be read from `/control`, and changed
+//! by posting TOML to it, as described in `control`. There's no
+//! authentication, so the dashboard should only be bound to addresses that
+//! are trusted.
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, Once};
+use std::thread::spawn;
+
+use serde::Serialize;
+
+use crate::configure::Config;
+use crate::control::{self, Controls};
+use crate::error::Error;
+use crate::evolution::{Genome, Phenome};
+use crate::observer::{pareto_front, Sink, SpecimenRecord, WindowStats};
+
+const PAGE: &str = include_str!("dashboard.html");
+
+/// The most window statistics kept per island. When the history fills up,
+/// every other entry is dropped, so the plots cover the whole run at a
+/// coarser and coarser resolution.
+const MAX_HISTORY: usize = 2000;
+
+/// The number of most recently observed specimens from which the Pareto
+/// front is drawn.
+const RECENT_SPECIMENS: usize = 500;
+
+/// The largest request body read.
+const MAX_BODY: usize = 0x10000;
+
+#[derive(Debug, Clone, Serialize)]
+struct ChampionView {
+    record: SpecimenRecord,
+    listing: String,
+}
+
+#[derive(Debug, Default)]
+struct IslandBoard {
+    history: Vec<WindowStats>,
+    champion: Option<ChampionView>,
+    recent: VecDeque<SpecimenRecord>,
+}
+
+#[derive(Debug, Serialize)]
+struct IslandView<'a> {
+    history: &'a [WindowStats],
+    champion: Option<&'a ChampionView>,
+    front: Vec<&'a SpecimenRecord>,
+}
+
+#[derive(Debug, Default)]
+pub struct Board {
+    islands: BTreeMap<usize, IslandBoard>,
+}
+
+impl Board {
+    fn to_json(&self) -> Result<String, Error> {
+        let view = self
+            .islands
+            .iter()
+            .map(|(island, board)| {
+                let view = IslandView {
+                    history: &board.history,
+                    champion: board.champion.as_ref(),
+                    front: pareto_front(board.recent.iter()),
+                };
+                (island, view)
+            })
+            .collect::<BTreeMap<_, _>>();
+        Ok(serde_json::to_string(&view)?)
+    }
+}
+
+static INIT_DASHBOARD: Once = Once::new();
+static mut DASHBOARD: Option<Arc<Mutex<Board>>> = None;
+
+/// Returns the process's dashboard, starting the server the first time
+/// it's called.
+fn dashboard(address: &str) -> Arc<Mutex<Board>> {
+    INIT_DASHBOARD.call_once(|| {
+        let board = Arc::new(Mutex::new(Board::default()));
+        serve(address, board.clone());
+        unsafe { DASHBOARD = Some(board) }
+    });
+    unsafe {
+        DASHBOARD
+            .as_ref()
+            .expect("The dashboard has not been initialized")
+            .clone()
+    }
+}
+
+fn serve(address: &str, board: Arc<Mutex<Board>>) {
+    let listener = match TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Failed to bind the dashboard to {}: {:?}", address, e);
+            return;
+        }
+    };
+    log::info!("Serving the dashboard at http://{}/", address);
+    spawn(move || {
+        for stream in listener.incoming() {
+            let res = stream
+                .map_err(Error::from)
+                .and_then(|stream| respond(stream, &board));
+            if let Err(e) = res {
+                log::warn!("Dashboard request failed: {:?}", e);
+            }
+        }
+    });
+}
+
+/// Reads the method, path and body of a request.
+fn read_request(stream: &TcpStream) -> Result<(String, String, String), Error> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut words = request_line.split_whitespace();
+    let method = words.next().unwrap_or("GET").to_string();
+    let path = words.next().unwrap_or("/").to_string();
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        let mut parts = header.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            content_length = parts
+                .next()
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(0);
+        }
+    }
+    let mut body = vec![0; content_length.min(MAX_BODY)];
+    reader.read_exact(&mut body)?;
+    Ok((method, path, String::from_utf8_lossy(&body).into_owned()))
+}
+
+fn respond(mut stream: TcpStream, board: &Mutex<Board>) -> Result<(), Error> {
+    let (method, path, request_body) = read_request(&stream)?;
+    let (status, content_type, body) = match (method.as_str(), path.as_str()) {
+        (_, "/") => ("200 OK", "text/html", PAGE.to_string()),
+        (_, "/state.json") => {
+            let json = board.lock().expect("poisoned").to_json()?;
+            ("200 OK", "application/json", json)
+        }
+        ("POST", "/control") => match Controls::parse(&request_body).and_then(control::submit) {
+            Ok(()) => (
+                "200 OK",
+                "text/plain",
+                "Staged until the next epoch\n".to_string(),
+            ),
+            Err(e) => ("400 Bad Request", "text/plain", format!("{:?}\n", e)),
+        },
+        (_, "/control") => (
+            "200 OK",
+            "application/json",
+            serde_json::to_string(&control::current())?,
+        ),
+        _ => ("404 Not Found", "text/plain", "Not found".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+/// Posts the window statistics, the champions, and the recent specimens'
+/// fitness vectors to the dashboard.
+pub struct DashboardSink {
+    config: Arc<Config>,
+    board: Arc<Mutex<Board>>,
+}
+
+impl DashboardSink {
+    pub fn new(config: Arc<Config>) -> Self {
+        let board = dashboard(&config.observer.dashboard_address);
+        Self { config, board }
+    }
+
+    fn with_island<F: FnOnce(&mut IslandBoard)>(&self, f: F) {
+        let mut board = self.board.lock().expect("poisoned");
+        f(board.islands.entry(self.config.island_id).or_default())
+    }
+}
+
+impl<O: Phenome + Genome> Sink<O> for DashboardSink {
+    fn record_stats(&mut self, stats: &WindowStats) {
+        self.with_island(|island| {
+            if island.history.len() >= MAX_HISTORY {
+                let thinned = island.history.iter().step_by(2).cloned().collect();
+                island.history = thinned;
+            }
+            island.history.push(stats.clone());
+        })
+    }
+
+    fn record_champion(&mut self, champion: &O, stats: &WindowStats) {
+        let listing = champion
+            .annotated_layout(&self.config)
+            .or_else(|| champion.effective_code())
+            .unwrap_or_else(|| format!("{:#?}", champion));
+        let view = ChampionView {
+            record: SpecimenRecord::of(champion, stats.counter, stats.epoch, &self.config),
+            listing,
+        };
+        self.with_island(|island| island.champion = Some(view))
+    }
+
+    fn record_specimen(&mut self, specimen: &O, counter: usize, epoch: usize) {
+        if specimen.fitness().is_none() {
+            return;
+        }
+        let record = SpecimenRecord::of(specimen, counter, epoch, &self.config);
+        self.with_island(|island| {
+            if island.recent.len() >= RECENT_SPECIMENS {
+                island.recent.pop_front();
+            }
+            island.recent.push_back(record);
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(name: &str, scores: &[(&str, f64)]) -> SpecimenRecord {
+        SpecimenRecord {
+            island: 0,
+            counter: 0,
+            epoch: 0,
+            name: name.to_string(),
+            generation: 0,
+            length: 1,
+            scalar_fitness: None,
+            priority_fitness: None,
+            scores: scores.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_pareto_front() {
+        let specimens = vec![
+            record("a", &[("x", 1.0), ("y", 3.0)]),
+            record("b", &[("x", 2.0), ("y", 2.0)]),
+            record("c", &[("x", 2.0), ("y", 3.0)]),
+            record("d", &[("x", 3.0), ("y", 1.0)]),
+            record("e", &[("x", 1.0), ("y", 3.0)]),
+        ];
+        let front = pareto_front(specimens.iter())
+            .into_iter()
+            .map(|s| s.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(front, vec!["a", "b", "d", "e"]);
+    }
+}
@@ -0,0 +1,360 @@
+//! Datasets of problems, read from delimited text or JSON-lines files, and
+//! split into training, validation, and test sets.
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::configure::{ClassificationProblem, ColumnType, DataConfig, DataFormat};
+use crate::error::Error;
+use crate::util::random::hash_seed_rng;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    /// Floats are converted only if they're whole numbers.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            Value::Float(x) if x.fract() == 0.0 => Some(*x as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(n) => Some(*n as f64),
+            Value::Float(x) => Some(*x),
+            Value::Bytes(_) => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    fn parse(field: &str, column_type: ColumnType) -> Result<Self, Error> {
+        let field = field.trim();
+        match column_type {
+            ColumnType::Int => Ok(Value::Int(field.parse()?)),
+            ColumnType::Float => field
+                .parse()
+                .map(Value::Float)
+                .map_err(|e| Error::Parsing(format!("{:?}: {}", field, e))),
+            ColumnType::Bytes => Ok(Value::Bytes(field.as_bytes().to_vec())),
+        }
+    }
+}
+
+/// The narrowest type that fits every field.
+fn infer_type<'a, I: Iterator<Item = &'a str>>(fields: I) -> ColumnType {
+    let mut column_type = ColumnType::Int;
+    for field in fields {
+        let field = field.trim();
+        if column_type == ColumnType::Int && field.parse::<i64>().is_err() {
+            column_type = ColumnType::Float;
+        }
+        if column_type == ColumnType::Float && field.parse::<f64>().is_err() {
+            return ColumnType::Bytes;
+        }
+    }
+    column_type
+}
+
+/// Splits a row of data into a problem, with the last `num_outputs` values
+/// taken as its outputs.
+pub fn problem_from_row<T>(
+    mut vals: Vec<T>,
+    num_outputs: usize,
+    tag: u64,
+) -> ClassificationProblem<T> {
+    assert!(vals.len() > num_outputs, "Missing output field");
+    let output = vals.split_off(vals.len() - num_outputs);
+    ClassificationProblem {
+        input: vals,
+        output,
+        tag,
+    }
+}
+
+/// Reads the raw fields of each row. JSON values are read as their textual
+/// representations, so that the column types are inferred the same way
+/// whatever the format.
+fn read_rows(config: &DataConfig) -> Result<Vec<Vec<String>>, Error> {
+    match config.format() {
+        DataFormat::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .delimiter(config.delimiter() as u8)
+                .has_headers(config.has_headers)
+                .from_path(&config.path)?;
+            let mut rows = Vec::new();
+            for record in reader.records() {
+                rows.push(record?.iter().map(String::from).collect());
+            }
+            Ok(rows)
+        }
+        DataFormat::JsonLines => {
+            let reader = BufReader::new(File::open(&config.path)?);
+            let mut rows = Vec::new();
+            for line in reader.lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let vals: Vec<serde_json::Value> = serde_json::from_str(&line)?;
+                rows.push(
+                    vals.into_iter()
+                        .map(|v| match v {
+                            serde_json::Value::String(s) => s,
+                            serde_json::Value::Bool(b) => (b as u8).to_string(),
+                            v => v.to_string(),
+                        })
+                        .collect(),
+                );
+            }
+            Ok(rows)
+        }
+    }
+}
+
+/// The training, validation, and test sets
+pub type SplitSets<T> = (
+    Vec<ClassificationProblem<T>>,
+    Vec<ClassificationProblem<T>>,
+    Vec<ClassificationProblem<T>>,
+);
+
+/// Shuffles the problems and deals them out into training, validation, and
+/// test sets. Each set is sorted by tag.
+pub fn split<T>(
+    mut problems: Vec<ClassificationProblem<T>>,
+    validation_fraction: f64,
+    test_fraction: f64,
+    seed: u64,
+) -> Result<SplitSets<T>, Error> {
+    let n = problems.len();
+    let num_test = (n as f64 * test_fraction).round() as usize;
+    let num_validation = (n as f64 * validation_fraction).round() as usize;
+    if num_test + num_validation >= n {
+        return Err(Error::Misc(format!(
+            "Holding out {} of {} problems leaves nothing to train on",
+            num_test + num_validation,
+            n
+        )));
+    }
+    let mut rng = hash_seed_rng(&seed);
+    problems.shuffle(&mut rng);
+    let mut test = problems.split_off(n - num_test);
+    let mut validation = problems.split_off(n - num_test - num_validation);
+    for set in &mut [&mut problems, &mut validation, &mut test] {
+        set.sort_by_key(|p| p.tag);
+    }
+    Ok((problems, validation, test))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Split {
+    Train,
+    Validation,
+    Test,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Dataset<T = Value> {
+    pub column_types: Vec<ColumnType>,
+    pub train: Vec<ClassificationProblem<T>>,
+    pub validation: Vec<ClassificationProblem<T>>,
+    pub test: Vec<ClassificationProblem<T>>,
+}
+
+impl Dataset<Value> {
+    pub fn load(config: &DataConfig, seed: u64) -> Result<Self, Error> {
+        let rows = read_rows(config)?;
+        log::info!("Read {} rows from {}", rows.len(), config.path);
+        Self::from_rows(rows, config, seed)
+    }
+
+    fn from_rows(rows: Vec<Vec<String>>, config: &DataConfig, seed: u64) -> Result<Self, Error> {
+        let width = rows
+            .first()
+            .map(Vec::len)
+            .ok_or_else(|| Error::Misc(format!("No data in {}", config.path)))?;
+        if width <= config.num_outputs() {
+            return Err(Error::Misc(format!(
+                "Rows of {} fields leave no inputs for {} outputs",
+                width,
+                config.num_outputs()
+            )));
+        }
+        if let Some(i) = rows.iter().position(|r| r.len() != width) {
+            return Err(Error::Misc(format!(
+                "Row {} has {} fields, expected {}",
+                i,
+                rows[i].len(),
+                width
+            )));
+        }
+        let column_types = if config.column_types.is_empty() {
+            (0..width)
+                .map(|j| infer_type(rows.iter().map(|r| r[j].as_str())))
+                .collect::<Vec<ColumnType>>()
+        } else if config.column_types.len() == width {
+            config.column_types.clone()
+        } else {
+            return Err(Error::Misc(format!(
+                "{} column types given for {} columns",
+                config.column_types.len(),
+                width
+            )));
+        };
+        let mut problems = Vec::new();
+        for (tag, row) in rows.iter().enumerate() {
+            let vals = row
+                .iter()
+                .zip(column_types.iter())
+                .map(|(field, t)| Value::parse(field, *t))
+                .collect::<Result<Vec<Value>, Error>>()?;
+            problems.push(problem_from_row(vals, config.num_outputs(), tag as u64));
+        }
+        let (train, validation, test) = split(
+            problems,
+            config.validation_fraction,
+            config.test_fraction,
+            seed,
+        )?;
+        Ok(Self {
+            column_types,
+            train,
+            validation,
+            test,
+        })
+    }
+
+    /// Converts every value in the dataset, failing on the first that can't
+    /// be converted.
+    pub fn convert<U, F: Fn(&Value) -> Option<U>>(self, f: F) -> Result<Dataset<U>, Error> {
+        let convert_set = |set: Vec<ClassificationProblem<Value>>| {
+            set.into_iter()
+                .map(|p| {
+                    let convert_vals = |vals: &[Value]| {
+                        vals.iter()
+                            .map(|v| {
+                                f(v).ok_or_else(|| {
+                                    Error::Misc(format!(
+                                        "Can't convert {:?} in problem {}",
+                                        v, p.tag
+                                    ))
+                                })
+                            })
+                            .collect::<Result<Vec<U>, Error>>()
+                    };
+                    Ok(ClassificationProblem {
+                        input: convert_vals(&p.input)?,
+                        output: convert_vals(&p.output)?,
+                        tag: p.tag,
+                    })
+                })
+                .collect::<Result<Vec<ClassificationProblem<U>>, Error>>()
+        };
+        Ok(Dataset {
+            column_types: self.column_types,
+            train: convert_set(self.train)?,
+            validation: convert_set(self.validation)?,
+            test: convert_set(self.test)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rows(data: &[&[&str]]) -> Vec<Vec<String>> {
+        data.iter()
+            .map(|r| r.iter().map(|f| f.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_problem_from_row() {
+        let problem = problem_from_row(vec![1, 2, 3, 4], 1, 0);
+        assert_eq!(problem.input, vec![1, 2, 3]);
+        assert_eq!(problem.output, vec![4]);
+        let problem = problem_from_row(vec![1, 2, 3, 4], 3, 1);
+        assert_eq!(problem.input, vec![1]);
+        assert_eq!(problem.output, vec![2, 3, 4]);
+        let problem = problem_from_row(vec![0.5, 1.5], 1, 2);
+        assert_eq!(problem.output, vec![1.5]);
+    }
+
+    #[test]
+    fn test_problem_value_types() {
+        let problem: ClassificationProblem<Vec<u8>, i64> = ClassificationProblem {
+            input: vec![b"GATTACA".to_vec()],
+            output: vec![-1 << 40],
+            tag: 0,
+        };
+        let json = serde_json::to_string(&problem).unwrap();
+        let parsed: ClassificationProblem<Vec<u8>, i64> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, problem);
+    }
+
+    #[test]
+    fn test_column_types() {
+        let config = DataConfig::default();
+        let data = rows(&[&["1", "2.5", "abc", "0"], &["-3", "4", "7", "1"]]);
+        let dataset = Dataset::from_rows(data, &config, 0).unwrap();
+        assert_eq!(
+            dataset.column_types,
+            vec![
+                ColumnType::Int,
+                ColumnType::Float,
+                ColumnType::Bytes,
+                ColumnType::Int
+            ]
+        );
+        let first = &dataset.train[0];
+        assert_eq!(first.input[1], Value::Float(2.5));
+        assert_eq!(first.input[2].as_bytes(), Some(&b"abc"[..]));
+        assert_eq!(first.output, vec![Value::Int(0)]);
+        // the bytes can't be made into ints
+        assert!(dataset.clone().convert(Value::as_i64).is_err());
+
+        let config = DataConfig {
+            column_types: vec![ColumnType::Float, ColumnType::Int],
+            ..Default::default()
+        };
+        let data = rows(&[&["1", "2"], &["2", "3.5"]]);
+        assert!(Dataset::from_rows(data, &config, 0).is_err());
+    }
+
+    #[test]
+    fn test_split() {
+        let problems = (0..100)
+            .map(|tag| problem_from_row(vec![tag, tag], 1, tag as u64))
+            .collect::<Vec<_>>();
+        let (train, validation, test) = split(problems.clone(), 0.2, 0.1, 7).unwrap();
+        assert_eq!((train.len(), validation.len(), test.len()), (70, 20, 10));
+        assert!(train.windows(2).all(|w| w[0].tag < w[1].tag));
+        let mut tags = train
+            .iter()
+            .chain(validation.iter())
+            .chain(test.iter())
+            .map(|p| p.tag)
+            .collect::<Vec<u64>>();
+        tags.sort();
+        assert_eq!(tags, (0..100).collect::<Vec<u64>>());
+        // the same seed gives the same split
+        assert_eq!(split(problems.clone(), 0.2, 0.1, 7).unwrap().2, test);
+        assert!(split(problems, 0.5, 0.5, 7).is_err());
+    }
+}
@@ -0,0 +1,276 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// Calling conventions, used to translate argument objectives, like
+/// `arg0 = "0xdeadbeef"`, into the register or stack slot where the callee
+/// will look for that argument.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq, Hash)]
+pub enum Abi {
+    /// System V AMD64
+    SysV,
+    /// Microsoft x64
+    Win64,
+    /// 32-bit x86, all arguments on the stack
+    Cdecl,
+    /// 32-bit ARM
+    Aapcs,
+    /// AArch64
+    Aapcs64,
+}
+
+/// Where an argument is to be found on entry to the callee. Stack offsets are
+/// measured from the stack pointer, and account for the return address and
+/// any shadow space the ABI reserves.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ArgLocation {
+    Register(&'static str),
+    Stack(u64),
+}
+
+const STACK_SLOT_PREFIX: &str = "STACK+0x";
+
+impl ArgLocation {
+    /// The key under which this location appears in register patterns and
+    /// register states.
+    pub fn key(&self) -> String {
+        match self {
+            Self::Register(r) => r.to_string(),
+            Self::Stack(offset) => format!("{}{:x}", STACK_SLOT_PREFIX, offset),
+        }
+    }
+}
+
+impl Abi {
+    pub fn default_for(arch: unicorn::Arch, mode: unicorn::Mode) -> Result<Self, Error> {
+        use unicorn::Arch::*;
+        use unicorn::Mode::*;
+        match (arch, mode) {
+            (X86, MODE_64) => Ok(Self::SysV),
+            (X86, _) => Ok(Self::Cdecl),
+            (ARM, _) => Ok(Self::Aapcs),
+            (ARM64, _) => Ok(Self::Aapcs64),
+            (arch, mode) => Err(Error::Misc(format!(
+                "No default ABI for {:?} {:?}; set roper.abi",
+                arch, mode
+            ))),
+        }
+    }
+
+    pub fn argument_registers(self) -> &'static [&'static str] {
+        match self {
+            Self::SysV => &["RDI", "RSI", "RDX", "RCX", "R8", "R9"],
+            Self::Win64 => &["RCX", "RDX", "R8", "R9"],
+            Self::Cdecl => &[],
+            Self::Aapcs => &["R0", "R1", "R2", "R3"],
+            Self::Aapcs64 => &["X0", "X1", "X2", "X3", "X4", "X5", "X6", "X7"],
+        }
+    }
+
+    fn word_size(self) -> u64 {
+        match self {
+            Self::Cdecl | Self::Aapcs => 4,
+            _ => 8,
+        }
+    }
+
+    /// Bytes between the stack pointer and the first stack argument.
+    fn stack_args_offset(self) -> u64 {
+        match self {
+            // return address
+            Self::SysV => 8,
+            Self::Cdecl => 4,
+            // return address and 32 bytes of shadow space
+            Self::Win64 => 8 + 0x20,
+            // the return address is in the link register
+            Self::Aapcs | Self::Aapcs64 => 0,
+        }
+    }
+
+    pub fn arg_location(self, n: usize) -> ArgLocation {
+        let regs = self.argument_registers();
+        if n < regs.len() {
+            ArgLocation::Register(regs[n])
+        } else {
+            let slot = (n - regs.len()) as u64;
+            ArgLocation::Stack(self.stack_args_offset() + slot * self.word_size())
+        }
+    }
+}
+
+/// Operating system conventions for system calls: which instructions trap
+/// into the kernel, and where the kernel looks for the syscall number and
+/// its arguments.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq, Hash)]
+pub enum SyscallAbi {
+    /// `int 0x80` or `sysenter`, number in EAX
+    LinuxX86,
+    /// `syscall`, number in RAX
+    LinuxX64,
+    /// `svc 0` (EABI), number in R7
+    LinuxArm,
+    /// `svc 0`, number in X8
+    LinuxArm64,
+    /// `int 0x2e` or `sysenter`, number in EAX. The arguments are passed on
+    /// the user stack, via EDX, rather than in registers.
+    WindowsX86,
+    /// `syscall`, number in EAX, the first argument moved from RCX to R10
+    WindowsX64,
+}
+
+impl SyscallAbi {
+    /// Assumes Linux, which is what we've mostly been targeting.
+    pub fn default_for(arch: unicorn::Arch, mode: unicorn::Mode) -> Option<Self> {
+        use unicorn::Arch::*;
+        use unicorn::Mode::*;
+        match (arch, mode) {
+            (X86, MODE_64) => Some(Self::LinuxX64),
+            (X86, MODE_32) => Some(Self::LinuxX86),
+            (ARM, _) => Some(Self::LinuxArm),
+            (ARM64, _) => Some(Self::LinuxArm64),
+            _ => None,
+        }
+    }
+
+    /// Whether the instruction, given by its mnemonic and operands, traps
+    /// into the kernel.
+    pub fn is_syscall(self, mnemonic: &str, op_str: &str) -> bool {
+        match (self, mnemonic) {
+            (Self::LinuxX64, "syscall") | (Self::WindowsX64, "syscall") => true,
+            (Self::LinuxX86, "sysenter") | (Self::WindowsX86, "sysenter") => true,
+            (Self::LinuxX86, "int") => op_str == "0x80",
+            (Self::WindowsX86, "int") => op_str == "0x2e",
+            (Self::LinuxArm, "svc") | (Self::LinuxArm, "swi") => true,
+            (Self::LinuxArm64, "svc") => true,
+            _ => false,
+        }
+    }
+
+    pub fn number_register(self) -> &'static str {
+        match self {
+            Self::LinuxX86 | Self::WindowsX86 => "EAX",
+            Self::LinuxX64 | Self::WindowsX64 => "RAX",
+            Self::LinuxArm => "R7",
+            Self::LinuxArm64 => "X8",
+        }
+    }
+
+    pub fn argument_registers(self) -> &'static [&'static str] {
+        match self {
+            Self::LinuxX86 => &["EBX", "ECX", "EDX", "ESI", "EDI", "EBP"],
+            Self::LinuxX64 => &["RDI", "RSI", "RDX", "R10", "R8", "R9"],
+            Self::LinuxArm => &["R0", "R1", "R2", "R3", "R4", "R5", "R6"],
+            Self::LinuxArm64 => &["X0", "X1", "X2", "X3", "X4", "X5"],
+            Self::WindowsX86 => &[],
+            Self::WindowsX64 => &["R10", "RDX", "R8", "R9"],
+        }
+    }
+
+    /// The syscall number that triggers a sigreturn, if there is one.
+    pub fn sigreturn_number(self) -> Option<u64> {
+        match self {
+            Self::LinuxX64 => Some(15),                   // rt_sigreturn
+            Self::LinuxX86 | Self::LinuxArm => Some(173), // rt_sigreturn
+            Self::LinuxArm64 => Some(139),                // rt_sigreturn
+            Self::WindowsX86 | Self::WindowsX64 => None,
+        }
+    }
+}
+
+pub fn stack_pointer(arch: unicorn::Arch, mode: unicorn::Mode) -> Result<&'static str, Error> {
+    use unicorn::Arch::*;
+    use unicorn::Mode::*;
+    match (arch, mode) {
+        (X86, MODE_64) => Ok("RSP"),
+        (X86, MODE_32) => Ok("ESP"),
+        (X86, _) => Ok("SP"),
+        (ARM, _) | (ARM64, _) | (MIPS, _) => Ok("SP"),
+        (arch, mode) => Err(Error::Misc(format!(
+            "No stack pointer known for {:?} {:?}",
+            arch, mode
+        ))),
+    }
+}
+
+/// The register in which a function's return value is passed.
+pub fn return_register(arch: unicorn::Arch, mode: unicorn::Mode) -> Result<&'static str, Error> {
+    use unicorn::Arch::*;
+    use unicorn::Mode::*;
+    match (arch, mode) {
+        (X86, MODE_64) => Ok("RAX"),
+        (X86, MODE_32) => Ok("EAX"),
+        (X86, _) => Ok("AX"),
+        (ARM, _) => Ok("R0"),
+        (ARM64, _) => Ok("X0"),
+        (MIPS, _) => Ok("V0"),
+        (arch, mode) => Err(Error::Misc(format!(
+            "No return register known for {:?} {:?}",
+            arch, mode
+        ))),
+    }
+}
+
+/// The register in which a call leaves the return address, on architectures
+/// that don't push it onto the stack.
+pub fn link_register(arch: unicorn::Arch, _mode: unicorn::Mode) -> Option<&'static str> {
+    use unicorn::Arch::*;
+    match arch {
+        ARM => Some("LR"),
+        ARM64 => Some("X30"),
+        MIPS => Some("RA"),
+        _ => None,
+    }
+}
+
+/// Parses keys of the form `argN`, returning `N`.
+pub fn parse_arg_key(key: &str) -> Option<usize> {
+    let key = key.to_ascii_lowercase();
+    if key.starts_with("arg") {
+        key[3..].parse::<usize>().ok()
+    } else {
+        None
+    }
+}
+
+/// Parses keys of the form produced by `ArgLocation::Stack(..).key()`,
+/// returning the offset from the stack pointer.
+pub fn parse_stack_slot_key(key: &str) -> Option<u64> {
+    if key.starts_with(STACK_SLOT_PREFIX) {
+        u64::from_str_radix(&key[STACK_SLOT_PREFIX.len()..], 16).ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_arg_locations() {
+        assert_eq!(Abi::SysV.arg_location(0), ArgLocation::Register("RDI"));
+        assert_eq!(Abi::SysV.arg_location(5), ArgLocation::Register("R9"));
+        assert_eq!(Abi::SysV.arg_location(6), ArgLocation::Stack(8));
+        assert_eq!(Abi::Win64.arg_location(4), ArgLocation::Stack(0x28));
+        assert_eq!(Abi::Cdecl.arg_location(1), ArgLocation::Stack(8));
+        assert_eq!(Abi::Aapcs.arg_location(5), ArgLocation::Stack(4));
+
+        let key = Abi::Win64.arg_location(5).key();
+        assert_eq!(key, "STACK+0x30");
+        assert_eq!(parse_stack_slot_key(&key), Some(0x30));
+        assert_eq!(parse_arg_key("arg3"), Some(3));
+        assert_eq!(parse_arg_key("RAX"), None);
+    }
+
+    #[test]
+    fn test_syscall_abis() {
+        let linux = SyscallAbi::LinuxX86;
+        assert!(linux.is_syscall("int", "0x80"));
+        assert!(!linux.is_syscall("int", "0x2e"));
+        assert!(SyscallAbi::WindowsX86.is_syscall("int", "0x2e"));
+        assert!(!SyscallAbi::LinuxX64.is_syscall("svc", "0"));
+        assert!(SyscallAbi::LinuxArm64.is_syscall("svc", "#0"));
+        assert_eq!(SyscallAbi::WindowsX64.argument_registers()[0], "R10");
+        assert_eq!(SyscallAbi::WindowsX64.sigreturn_number(), None);
+    }
+}
@@ -1,4 +1,3 @@
-use std::pin::Pin;
 use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
 use std::sync::{Arc, Mutex};
 use std::thread::{spawn, JoinHandle};
@@ -13,14 +12,17 @@ use threadpool::ThreadPool;
 use unicorn::{Context, Cpu, Mode};
 
 pub use crate::configure::RoperConfig;
+use crate::configure::ChainMode;
 use crate::disassembler::Disassembler;
 use crate::emulator::hatchery::hooking::emu_prep_fn;
 use crate::emulator::loader;
-use crate::emulator::loader::Seg;
+use crate::emulator::loader::{Seg, SegType};
 use crate::emulator::pack::Pack;
-use crate::emulator::profiler::{Profile, Profiler};
+use crate::emulator::profiler::{FaultPhase, Profile, Profiler};
 use crate::emulator::register_pattern::Register;
+use crate::emulator::srop;
 use crate::error::Error;
+use crate::util::random::seed_thread_rng;
 
 //use std::sync::atomic::{AtomicUsize, Ordering};
 
@@ -45,20 +47,26 @@ struct EmuPool<C: Cpu<'static>> {
     init_context: Context,
     mode: Mode,
     wait_limit: u64,
-    memory: Arc<Option<Pin<Vec<Seg>>>>,
+    memory: &'static [Seg],
 }
 
 impl<C: Cpu<'static>> EmuPool<C> {
     pub fn new(config: &RoperConfig) -> Self {
-        let static_memory = loader::get_static_memory_image();
-
-        let memory = Some(Pin::new(static_memory.segments().clone()));
+        // The non-writeable segments are mapped straight out of the static memory
+        // image, so that every emulator in the pool shares a single copy of them.
+        let memory: &'static [Seg] = loader::get_static_memory_image().segments();
+        log::info!(
+            "Each emulator maps 0x{:x} bytes of writeable memory, 0x{:x} bytes across {} emulators",
+            loader::emulator_memory_size(memory),
+            loader::emulator_memory_size(memory) * config.num_workers,
+            config.num_workers,
+        );
 
         let pool: Pool<C> = Pool::new(config.num_workers, || {
-            Self::init_emu(&config, &memory).expect("failed to initialize emulator")
+            Self::init_emu(config.mode, memory).expect("failed to initialize emulator")
         });
         let init_context = {
-            let emu = Self::wait_for_emu(&pool, config.wait_limit, config.mode);
+            let emu = Self::wait_for_emu(&pool, config.wait_limit, config.mode, memory);
             let ctx = (*emu).context_save().expect("Failed to save context");
             ctx
         };
@@ -68,56 +76,62 @@ impl<C: Cpu<'static>> EmuPool<C> {
             init_context,
             mode: config.mode,
             wait_limit: config.wait_limit,
-            memory: Arc::new(memory),
+            memory,
         }
     }
 
+    /// Restores the registers of an emulator to their initial state.
+    pub fn reset_context(&self, emu: &mut C) {
+        emu.context_restore(&self.init_context)
+            .expect("Failed to restore context");
+    }
+
     /// Returns a reusable pointer to an emulator, which will be returned to the pool when it's
     /// dropped.
     pub fn pull(&self) -> object_pool::Reusable<'_, C> {
-        let mut emu = Self::wait_for_emu(&self.pool, self.wait_limit, self.mode);
+        let mut emu = Self::wait_for_emu(&self.pool, self.wait_limit, self.mode, self.memory);
         emu.context_restore(&self.init_context)
             .expect("Failed to restore context");
         emu
     }
 
-    fn init_emu(config: &RoperConfig, memory: &Option<Pin<Vec<Seg>>>) -> Result<C, Error> {
-        let mut emu = C::new(config.mode)?;
-        if let Some(segments) = memory {
-            //notice!(emu.mem_map(0x1000, 0x4000, unicorn::Protection::ALL))?;
-            let mut results = Vec::new();
-            // First, map the non-writeable segments to memory. These can be shared.
-            segments.iter().for_each(|s| {
-                log::info!(
-                    "Mapping segment 0x{:x} - 0x{:x} {:?} [{:?}]",
-                    s.aligned_start(),
-                    s.aligned_end(),
-                    s.segtype,
-                    s.perm
-                );
-                if !s.is_writeable() {
-                    // This is a bit risky, but we want our many emulator instances to share common regions
-                    // of non-writeable memory.
-                    unsafe {
-                        let res = emu.mem_map_const_ptr(
-                            s.aligned_start(),
-                            s.aligned_size(),
-                            s.perm.into(),
-                            s.data.as_ptr(),
-                        );
-                        results.push(res);
-                    }
-                } else {
-                    // Next, map the writeable segments
-                    let res = emu.mem_map(s.aligned_start(), s.aligned_size(), s.perm.into());
+    fn init_emu(mode: Mode, segments: &'static [Seg]) -> Result<C, Error> {
+        let mut emu = C::new(mode)?;
+        let mut results = Vec::new();
+        // First, map the non-writeable segments to memory. These can be shared.
+        segments.iter().for_each(|s| {
+            log::info!(
+                "Mapping segment 0x{:x} - 0x{:x} {:?} [{:?}]",
+                s.aligned_start(),
+                s.aligned_end(),
+                s.segtype,
+                s.perm
+            );
+            if !s.is_writeable() {
+                // This is a bit risky, but we want our many emulator instances to share common regions
+                // of non-writeable memory. The backing buffers live in the static memory image, and
+                // so outlive every emulator that points into them.
+                unsafe {
+                    let res = emu.mem_map_const_ptr(
+                        s.aligned_start(),
+                        s.aligned_size(),
+                        s.perm.into(),
+                        s.data.as_ptr(),
+                    );
                     results.push(res);
                 }
-            });
-            // Return an error if there's been an error.
-            let _ = results
-                .into_iter()
-                .collect::<Result<Vec<_>, unicorn::Error>>()?;
-        };
+            } else {
+                // Next, map the writeable segments. Each emulator gets its own copy of these.
+                let res = emu
+                    .mem_map(s.aligned_start(), s.aligned_size(), s.perm.into())
+                    .and_then(|()| emu.mem_write(s.aligned_start(), &s.data));
+                results.push(res);
+            }
+        });
+        // Return an error if there's been an error.
+        let _ = results
+            .into_iter()
+            .collect::<Result<Vec<_>, unicorn::Error>>()?;
         emu.mem_regions()?.iter().for_each(|rgn| {
             log::info!(
                 "Mapped region: 0x{:x} - 0x{:x} [{:?}]",
@@ -129,7 +143,12 @@ impl<C: Cpu<'static>> EmuPool<C> {
         Ok(emu)
     }
 
-    fn wait_for_emu(pool: &Pool<C>, wait_limit: u64, mode: Mode) -> object_pool::Reusable<'_, C> {
+    fn wait_for_emu(
+        pool: &Pool<C>,
+        wait_limit: u64,
+        mode: Mode,
+        memory: &'static [Seg],
+    ) -> object_pool::Reusable<'_, C> {
         let mut wait_time = 0;
         let wait_unit = 1;
         loop {
@@ -143,7 +162,11 @@ impl<C: Cpu<'static>> EmuPool<C> {
                     "Waited {} milliseconds for CPU, creating new one",
                     wait_time
                 );
-                return pool.pull(|| C::new(mode).expect("Failed to spawn replacement CPU"));
+                // The replacement needs the same memory map as the rest of the pool,
+                // so it maps the shared segments too, rather than starting out empty.
+                return pool.pull(|| {
+                    Self::init_emu(mode, memory).expect("Failed to spawn replacement CPU")
+                });
             }
             {
                 std::thread::sleep(Duration::from_millis(wait_unit));
@@ -153,10 +176,18 @@ impl<C: Cpu<'static>> EmuPool<C> {
     }
 }
 
-type InboundTx<T, C> = SyncSender<(T, Option<HashMap<Register<C>, u64>>)>;
-type InboundRx<T, C> = Receiver<(T, Option<HashMap<Register<C>, u64>>)>;
-type OutboundTx = SyncSender<Profile>;
-type OutboundRx = Receiver<Profile>;
+/// A step and time budget for a single run of the emulator. Zero means no
+/// limit, as in `emu_start`.
+#[derive(Clone, Copy, Debug)]
+pub struct Budget {
+    pub max_emu_steps: usize,
+    pub millisecond_timeout: u64,
+}
+
+type InboundTx<T, C> = SyncSender<(T, Option<HashMap<Register<C>, u64>>, Option<Budget>)>;
+type InboundRx<T, C> = Receiver<(T, Option<HashMap<Register<C>, u64>>, Option<Budget>)>;
+type OutboundTx = SyncSender<Vec<Profile>>;
+type OutboundRx = Receiver<Vec<Profile>>;
 type InboundChannel<T, C> = (InboundTx<T, C>, InboundRx<T, C>);
 type OutboundChannel = (OutboundTx, OutboundRx);
 
@@ -164,11 +195,14 @@ pub struct Hatchery<C: Cpu<'static> + Send> {
     emu_pool: Arc<EmuPool<C>>,
     thread_pool: Arc<Mutex<ThreadPool>>,
     config: Arc<RoperConfig>,
-    memory: Arc<Option<Pin<Vec<Seg>>>>,
-    tx: InboundTx<Vec<u64>, C>,
+    memory: &'static [Seg],
+    tx: InboundTx<Vec<Vec<u64>>, C>,
     rx: OutboundRx,
     handle: JoinHandle<()>,
     disassembler: Arc<Disassembler>,
+    /// Whether the shared, unwriteable segments are unmapped when the
+    /// hatchery is dropped. See `keep_shared_mappings`.
+    unmap_on_drop: bool,
 }
 
 impl<C: Cpu<'static> + Send> Drop for Hatchery<C> {
@@ -184,29 +218,31 @@ impl<C: Cpu<'static> + Send> Drop for Hatchery<C> {
             rx: _rx,
             handle: _handle,
             disassembler: _disassembler,
+            unmap_on_drop,
         } = self;
+        if !*unmap_on_drop {
+            return;
+        }
         // handle.join().expect("Failed to join handle in hatchery");
-        if let Some(segments) = memory.as_ref() {
-            // Once a shared, mapped region is unmapped from one emulator, it's unmapped
-            // from them all. Attempting to unmap it again will trigger a NOMEM error.
-            // And I think that attempting to access that unmapped segment *may* trigger a
-            // use-after-free bug.
-            if let Some(mut emu) = emu_pool.pool.try_pull() {
-                segments
-                    .iter()
-                    .filter(|&s| !s.is_writeable())
-                    .for_each(|s| {
-                        log::debug!(
-                            "Unmapping region 0x{:x} - 0x{:x} [{:?}]",
-                            s.aligned_start(),
-                            s.aligned_end(),
-                            s.perm
-                        );
-                        //log::debug!("Unmapping segment at 0x{:x}", s.aligned_start());
-                        emu.mem_unmap(s.aligned_start(), s.aligned_size())
-                            .unwrap_or_else(|e| log::error!("Failed to unmap segment: {:?}", e));
-                    });
-            }
+        // Once a shared, mapped region is unmapped from one emulator, it's unmapped
+        // from them all. Attempting to unmap it again will trigger a NOMEM error.
+        // And I think that attempting to access that unmapped segment *may* trigger a
+        // use-after-free bug.
+        if let Some(mut emu) = emu_pool.pool.try_pull() {
+            memory
+                .iter()
+                .filter(|&s| !s.is_writeable())
+                .for_each(|s| {
+                    log::debug!(
+                        "Unmapping region 0x{:x} - 0x{:x} [{:?}]",
+                        s.aligned_start(),
+                        s.aligned_end(),
+                        s.perm
+                    );
+                    //log::debug!("Unmapping segment at 0x{:x}", s.aligned_start());
+                    emu.mem_unmap(s.aligned_start(), s.aligned_size())
+                        .unwrap_or_else(|e| log::error!("Failed to unmap segment: {:?}", e));
+                });
         }
     }
 }
@@ -220,14 +256,13 @@ impl<C: 'static + Cpu<'static> + Send> Hatchery<C> {
         let disassembler = Arc::new(
             Disassembler::new(config.arch, config.mode).expect("Failed to build disassembler"),
         );
-        let (tx, our_rx): InboundChannel<Vec<u64>, C> = sync_channel(config.num_workers);
+        let (tx, our_rx): InboundChannel<Vec<Vec<u64>>, C> = sync_channel(config.num_workers);
         let (our_tx, rx): OutboundChannel = sync_channel(config.num_workers);
 
         let static_memory = loader::get_static_memory_image();
 
-        let memory = Some(Pin::new(static_memory.segments().clone()));
-
         let emu_pool = Arc::new(EmuPool::new(&config));
+        let memory = emu_pool.memory;
         let thread_pool = Arc::new(Mutex::new(ThreadPool::new(config.num_workers)));
 
         let millisecond_timeout = config.millisecond_timeout.unwrap_or(0);
@@ -238,25 +273,22 @@ impl<C: 'static + Cpu<'static> + Send> Hatchery<C> {
         let e_pool = emu_pool.clone();
         let t_pool = thread_pool.clone();
         let parameters = config.clone();
-        let mem = memory.clone();
         let disas = disassembler.clone();
-        let bad_bytes: Arc<Option<HashMap<u8, u8>>> =
-            Arc::new(config.bad_bytes.as_ref().map(|table| {
-                table
-                    .iter()
-                    // FIXME do this in a less dirty, shotgunny way
-                    .map(|(k, v)| (u8::from_str_radix(k, 16).unwrap(), *v))
-                    .collect::<HashMap<u8, u8>>()
-            }));
+        let bad_bytes: Arc<Option<HashMap<u8, u8>>> = Arc::new(
+            config
+                .bad_byte_filter()
+                .expect("The bad bytes were checked when the config was loaded"),
+        );
+        let stack_slots = Arc::new(config.stack_slots());
         let handle = spawn(move || {
-            for (payload, args) in our_rx.iter() {
+            for (batch, args, budget) in our_rx.iter() {
                 let config = parameters.clone();
                 let bad_bytes = bad_bytes.clone();
                 let our_tx = our_tx.clone();
                 let output_registers = output_registers.clone();
+                let stack_slots = stack_slots.clone();
                 let thread_pool = t_pool.lock().expect("Failed to unlock thread_pool mutex");
                 let emulator_pool = e_pool.clone();
-                let memory = mem.clone();
                 let initial_register_state = if let Some(args) = args {
                     Arc::new(args)
                 } else {
@@ -265,92 +297,151 @@ impl<C: 'static + Cpu<'static> + Send> Hatchery<C> {
                 let disas = disas.clone();
                 // let's get a clean context to use here.
                 thread_pool.execute(move || {
+                    loader::set_target(config.target);
+                    if let Some(ref cores) = config.emulator_cores {
+                        crate::util::affinity::pin_once(cores);
+                    }
                     // Acquire an emulator from the pool.
                     let mut emu: Reusable<'_, C> = emulator_pool.pull();
-                    // Initialize the profiler
-                    let mut profiler = Profiler::new(&output_registers, &initial_register_state);
-                    // load the inputs
-                    for (reg, val) in initial_register_state.iter() {
-                        emu.reg_write(*reg, *val).expect("Failed to load registers");
-                    }
-                    profiler.registers_at_last_ret = Arc::new(Mutex::new((*initial_register_state).clone()));
-
-                    // Pedantically check to make sure the registers are initialized
-                    if true || cfg!(debug_assertions) {
-                        for (r, expected) in initial_register_state.iter() {
-                            // TODO: figure out why the context restore isn't taking care of this
-                            emu.reg_write(*r, *expected).expect("Failed to write regsiter");
-                            // let val = emu.reg_read(*r).expect("Failed to read register!");
-                            // assert_eq!(val, *expected, "register has not been initialized");
+                    let mut profiles = Vec::with_capacity(batch.len());
+                    for (i, payload) in batch.into_iter().enumerate() {
+                        // Seeded by the payload, as well as the island, so that the draws
+                        // don't depend on which worker the payload lands on.
+                        if config.deterministic {
+                            seed_thread_rng(&(config.random_seed, &payload));
+                        }
+                        if i > 0 {
+                            // The previous chain's memory writes have been undone, so
+                            // only the registers need resetting.
+                            emulator_pool.reset_context(&mut (*emu));
+                        }
+                        // Initialize the profiler
+                        let mut profiler = Profiler::new(&output_registers, &initial_register_state);
+                        profiler.stack_slots = stack_slots.to_vec();
+                        // load the inputs
+                        for (reg, val) in initial_register_state.iter() {
+                            emu.reg_write(*reg, *val).expect("Failed to load registers");
+                        }
+                        profiler.registers_at_last_ret = Arc::new(Mutex::new((*initial_register_state).clone()));
+
+                        // Pedantically check to make sure the registers are initialized
+                        if true || cfg!(debug_assertions) {
+                            for (r, expected) in initial_register_state.iter() {
+                                // TODO: figure out why the context restore isn't taking care of this
+                                emu.reg_write(*r, *expected).expect("Failed to write regsiter");
+                                // let val = emu.reg_read(*r).expect("Failed to read register!");
+                                // assert_eq!(val, *expected, "register has not been initialized");
+                            }
                         }
-                    }
 
-                    let code = payload.pack(word_size, endian, (*bad_bytes).as_ref());
-                    let initial_pc = emu_prep_fn(&mut (*emu), &config, &code, &profiler).expect("Failure in the emulator preparation function.");
+                        let payload = srop::expand_frames(payload, &config);
+                        let code = payload.pack(word_size, endian, (*bad_bytes).as_ref());
+                        let initial_pc = emu_prep_fn(&mut (*emu), &config, &code, &profiler).expect("Failure in the emulator preparation function.");
 
-                    if config.record_basic_blocks {
-                        let _hook = hooking::install_code_logging_hook(&mut (*emu), &profiler, &payload.as_code_addrs(word_size, endian), config.break_on_calls).expect("Failed to install code_logging_hook");
-                    }
+                        if config.record_basic_blocks {
+                            let _hook = hooking::install_code_logging_hook(&mut (*emu), &profiler, &payload.as_code_addrs(word_size, endian), &config).expect("Failed to install code_logging_hook");
+                        }
 
-                    // WONTFIX: It turns out that Unicorn never implemented a fetch hook. It's an unused enum in the C code. Balls.
-                    // let _hook = hooking::install_gadget_fetching_hook(&mut (*emu), &profiler).expect("Failed to install gadget_fetching_hook");
+                        // WONTFIX: It turns out that Unicorn never implemented a fetch hook. It's an unused enum in the C code. Balls.
+                        // let _hook = hooking::install_gadget_fetching_hook(&mut (*emu), &profiler).expect("Failed to install gadget_fetching_hook");
 
-                    if cfg!(feature = "disassemble_trace") {
-                        // install the disassembler hook
-                        let _hook = hooking::install_disas_tracer_hook(&mut (*emu), disas.clone(), output_registers.clone()).expect("Failed to install tracer hook");
-                    }
+                        if cfg!(feature = "disassemble_trace") {
+                            // install the disassembler hook
+                            let _hook = hooking::install_disas_tracer_hook(&mut (*emu), disas.clone(), output_registers.clone()).expect("Failed to install tracer hook");
+                        }
 
-                    // let _hook = hooking::install_syscall_hook(&mut (*emu), config.arch, config.mode);
-                    if config.record_memory_writes {
-                        let _hooks = hooking::install_mem_write_hook(&mut (*emu), &profiler, config.monitor_stack_writes).expect("Failed to install mem_write_hook");
-                    }
+                        if !config.watchpoints.is_empty() {
+                            let _hooks = hooking::install_watchpoint_hooks(&mut (*emu), &profiler, &config.watchpoints).expect("Failed to install watchpoint hooks");
+                        }
 
-                    ;
-                    // If the preparation was successful, launch the emulator and execute
-                    // the payload. We want to hang onto the exit code of this task.
-                    let start_time = Instant::now();
-                    /*******************************************************************/
-                    let result = emu.emu_start(
-                        initial_pc,
-                        0,
-                        millisecond_timeout * unicorn::MILLISECOND_SCALE,
-                        max_emu_steps,
-                    );
-                    /*******************************************************************/
-                    profiler.emulation_time = start_time.elapsed();
-                    if let Err(error_code) = result {
-                        profiler.set_error(error_code)
-                    };
+                        // let _hook = hooking::install_syscall_hook(&mut (*emu), config.arch, config.mode);
+                        if config.record_memory_writes {
+                            let _hooks = hooking::install_mem_write_hook(&mut (*emu), &profiler, config.monitor_stack_writes).expect("Failed to install mem_write_hook");
+                        }
 
-                    let written_memory = tools::read_writeable_memory(&(*emu)).expect("Failed to read writeable memory").into_par_iter().filter(|seg| {
-                        let stat = static_memory.try_dereference(seg.addr, None).unwrap();
-                        debug_assert_eq!(stat.len(), seg.data.len());
-                        stat != seg.data.as_slice()
-                    }).collect::<Vec<Seg>>();
-
-                    profiler.written_memory = written_memory;
-
-                    // cleanup
-                    emu.remove_all_hooks().expect("Failed to clean up hooks");
-
-
-                    // clean up writeable memory
-                    // there will never be *too* many segments, so iterating over them is cheap.
-                    if let Some(memory) = memory.as_ref() {
-                        memory.iter().filter(|s| s.is_writeable()).for_each(|seg| {
-                            emu.mem_write(seg.aligned_start(),
-                                          &seg.data,
-                            ).unwrap_or_else(|e| {
-                                log::error!("Failed to refresh writeable memory at 0x{:x} - 0x{:x}: {:?}",
-                                seg.aligned_start(), seg.aligned_end(), e
-                            )
-                            });
+                        if config.track_dirty_pages {
+                            let _hooks = hooking::install_dirty_page_hook(&mut (*emu), &profiler).expect("Failed to install dirty_page_hook");
+                        }
+
+                        ;
+                        // If the preparation was successful, launch the emulator and execute
+                        // the payload. We want to hang onto the exit code of this task.
+                        let start_time = Instant::now();
+                        /*******************************************************************/
+                        let budget = budget.unwrap_or(Budget {
+                            max_emu_steps,
+                            millisecond_timeout,
                         });
+                        let result = emu.emu_start(
+                            initial_pc,
+                            0,
+                            budget.millisecond_timeout * unicorn::MILLISECOND_SCALE,
+                            budget.max_emu_steps,
+                        );
+                        /*******************************************************************/
+                        // Timing is left out of deterministic runs, so that they can be
+                        // compared byte for byte.
+                        if !config.deterministic {
+                            profiler.emulation_time = start_time.elapsed();
+                        }
+                        let pc = emu.reg_read(emu.program_counter()).unwrap_or(0);
+                        profiler.fault_phase = FaultPhase::classify(
+                            result.is_err(),
+                            profiler.halted.load(std::sync::atomic::Ordering::Relaxed),
+                            pc,
+                        );
+                        if let Err(error_code) = result {
+                            profiler.set_error(error_code)
+                        };
+
+                        // Only the regions holding the pages the chain dirtied need be
+                        // compared with the static image, if those pages were tracked.
+                        let dirty_pages = if config.track_dirty_pages {
+                            Some(tools::dirty_pages(&profiler))
+                        } else {
+                            None
+                        };
+                        let written_memory = if let Some(ref pages) = dirty_pages {
+                            tools::read_dirty_memory(&(*emu), pages).expect("Failed to read dirty memory")
+                        } else {
+                            tools::read_writeable_memory(&(*emu)).expect("Failed to read writeable memory")
+                        };
+                        let written_memory = written_memory.into_par_iter().filter(|seg| {
+                            let stat = static_memory.try_dereference(seg.addr, None).unwrap();
+                            debug_assert_eq!(stat.len(), seg.data.len());
+                            stat != seg.data.as_slice()
+                        }).collect::<Vec<Seg>>();
+
+                        profiler.written_memory = written_memory;
+
+                        // cleanup
+                        emu.remove_all_hooks().expect("Failed to clean up hooks");
+
+
+                        // Restore the writeable memory that the chain dirtied: the pages it wrote
+                        // to, if they were tracked, or else every region that differs from the
+                        // static image, above, and so the emulator can be reused without being
+                        // remapped.
+                        if let Some(ref pages) = dirty_pages {
+                            tools::restore_pages(&mut (*emu), static_memory, pages);
+                        } else {
+                            for seg in profiler.written_memory.iter() {
+                                let stat = static_memory.try_dereference(seg.addr, None).unwrap();
+                                emu.mem_write(seg.addr, &stat[0..seg.data.len()]).unwrap_or_else(|e| {
+                                    log::error!("Failed to refresh writeable memory at 0x{:x} - 0x{:x}: {:?}",
+                                    seg.addr, seg.addr + seg.data.len() as u64, e
+                                )
+                                });
+                            }
+                        }
+                        if cfg!(debug_assertions) {
+                            tools::assert_writeable_memory_clean(&(*emu), static_memory);
+                        }
+                        profiles.push(profiler.into());
                     }
-                    let profile = profiler.into();
                     // Now send the code back, along with its profile information.
                     // (The genotype, along with its phenotype.)
-                    our_tx.send(profile).map_err(Error::from).expect("TX Failure in pipeline");
+                    our_tx.send(profiles).map_err(Error::from).expect("TX Failure in pipeline");
                 });
             }
         });
@@ -358,23 +449,102 @@ impl<C: 'static + Cpu<'static> + Send> Hatchery<C> {
             emu_pool,
             thread_pool,
             config,
-            memory: Arc::new(memory),
+            memory,
             tx,
             rx,
             handle,
             disassembler,
+            unmap_on_drop: true,
         }
     }
 
+    /// Leaves the shared, unwriteable segments mapped when the hatchery is
+    /// dropped, for short-lived hatcheries whose emulators share those
+    /// segments with others that are still running.
+    pub fn keep_shared_mappings(mut self) -> Self {
+        self.unmap_on_drop = false;
+        self
+    }
+
+    /// Runs the payload in an emulator from the pool. If `escalation` is
+    /// configured, the payload is first screened with the reduced budget,
+    /// and the screening profile is returned unless it satisfies the
+    /// escalation predicate.
     pub fn execute(
         &self,
         payload: Vec<u64>,
         args: Option<HashMap<Register<C>, u64>>,
     ) -> Result<Profile, Error> {
-        self.tx.send((payload, args))?;
+        if let Some(ref escalation) = self.config.escalation {
+            let budget = Budget {
+                max_emu_steps: escalation.max_emu_steps.unwrap_or(0),
+                millisecond_timeout: escalation.millisecond_timeout.unwrap_or(0),
+            };
+            let screening =
+                self.execute_with_budget(payload.clone(), args.clone(), Some(budget))?;
+            if !is_promising(&screening, &escalation.predicate) {
+                return Ok(screening);
+            }
+            log::debug!("Escalating payload to full evaluation budget");
+        }
+        self.execute_with_budget(payload, args, None)
+    }
+
+    /// Runs the payload with the given budget, or with the budget from the
+    /// config if `budget` is `None`.
+    pub fn execute_with_budget(
+        &self,
+        payload: Vec<u64>,
+        args: Option<HashMap<Register<C>, u64>>,
+        budget: Option<Budget>,
+    ) -> Result<Profile, Error> {
+        self.tx.send((vec![payload], args, budget))?;
+        self.rx
+            .recv()?
+            .pop()
+            .ok_or_else(|| Error::Misc("No profile returned from the hatchery".into()))
+    }
+
+    /// Runs a batch of payloads, one after another, on a single emulator.
+    /// Only the state dirtied by each payload is reset before the next runs,
+    /// which amortizes the cost of acquiring and preparing the emulator.
+    /// Screening with a reduced budget happens per payload, so if
+    /// `escalation` is configured, this falls back to `execute`.
+    pub fn execute_batch(
+        &self,
+        payloads: Vec<Vec<u64>>,
+        args: Option<HashMap<Register<C>, u64>>,
+    ) -> Result<Vec<Profile>, Error> {
+        if self.config.escalation.is_some() {
+            return payloads
+                .into_iter()
+                .map(|payload| self.execute(payload, args.clone()))
+                .collect();
+        }
+        if payloads.is_empty() {
+            return Ok(vec![]);
+        }
+        self.tx.send((payloads, args, None))?;
         self.rx.recv().map_err(Error::from)
     }
 }
+
+/// Evaluates the escalation predicate against a screening profile. A
+/// predicate that fails to evaluate errs on the side of escalation.
+fn is_promising(profile: &Profile, predicate: &str) -> bool {
+    let mut metrics = profile.screening_metrics();
+    match fasteval::ez_eval(predicate, &mut metrics) {
+        Ok(res) => res != 0.0,
+        Err(e) => {
+            log::error!(
+                "Failed to evaluate escalation predicate {:?}: {:?}",
+                predicate,
+                e
+            );
+            true
+        }
+    }
+}
 // TODO: try to reduce the number of mutexes needed in this setup. it seems like a code smell.
 
 pub mod tools {
@@ -397,10 +567,93 @@ pub mod tools {
             .map_err(Error::from)
     }
 
+    /// The addresses of the pages noted as dirty in the profiler, without
+    /// repetition, in order.
+    pub fn dirty_pages<C: Cpu<'static>>(profiler: &Profiler<C>) -> Vec<u64> {
+        let mut pages = Vec::with_capacity(profiler.dirty_pages.len());
+        while let Ok(page) = profiler.dirty_pages.pop() {
+            pages.push(page)
+        }
+        pages.sort_unstable();
+        pages.dedup();
+        pages
+    }
+
+    /// Reads the writeable regions of memory that hold any of the `pages`.
+    /// These are read whole, as `read_writeable_memory` reads them, so that
+    /// the written memory seen by the profile doesn't depend on whether the
+    /// dirty pages were tracked.
+    pub fn read_dirty_memory<C: 'static + Cpu<'static>>(
+        emu: &C,
+        pages: &[u64],
+    ) -> Result<Vec<Seg>, Error> {
+        emu.mem_regions()?
+            .into_iter()
+            .filter(MemRegion::writeable)
+            .filter(|mem_reg| {
+                let end = mem_reg.begin + mem_reg.size() as u64;
+                pages.iter().any(|p| mem_reg.begin <= *p && *p < end)
+            })
+            .map(|mem_reg| {
+                emu.mem_read_as_vec(mem_reg.begin, mem_reg.size())
+                    .map(|data| Seg::from_mem_region_and_data(mem_reg, data))
+            })
+            .collect::<Result<Vec<Seg>, unicorn::Error>>()
+            .map_err(Error::from)
+    }
+
+    /// Copies each of the `pages` back from the static memory image.
+    pub fn restore_pages<C: 'static + Cpu<'static>>(
+        emu: &mut C,
+        static_memory: &loader::MemoryImage,
+        pages: &[u64],
+    ) {
+        for page in pages.iter() {
+            if let Some(stat) = static_memory.try_dereference(*page, None) {
+                let len = stat.len().min(loader::PAGE_SIZE as usize);
+                emu.mem_write(*page, &stat[0..len]).unwrap_or_else(|e| {
+                    log::error!("Failed to restore the page at 0x{:x}: {:?}", page, e)
+                });
+            }
+        }
+    }
+
+    /// Panics if any writeable memory in the emulator differs from the static
+    /// memory image. This is used to check that the memory dirtied by a chain
+    /// has been fully restored before the emulator is reused.
+    pub fn assert_writeable_memory_clean<C: 'static + Cpu<'static>>(
+        emu: &C,
+        static_memory: &loader::MemoryImage,
+    ) {
+        for seg in read_writeable_memory(emu).expect("Failed to read writeable memory") {
+            let stat = static_memory
+                .try_dereference(seg.addr, None)
+                .expect("Writeable region missing from the static memory image");
+            assert!(
+                &stat[0..seg.data.len()] == seg.data.as_slice(),
+                "Dirty memory left in 0x{:x} - 0x{:x} after reset",
+                seg.addr,
+                seg.addr + seg.data.len() as u64
+            );
+        }
+    }
+
     /// Returns the uppermost readable/writeable memory region, in the emulator's
     /// memory map.
     pub fn find_stack<C: 'static + Cpu<'static>>(emu: &C) -> Option<MemRegion> {
         if let Ok(regions) = emu.mem_regions() {
+            // If we know where the loader put the stack, look for that region first,
+            // since scripted memory may have been mapped above it.
+            if let Some(stack_seg) = loader::try_to_get_static_memory_image()
+                .and_then(|m| m.segments().iter().find(|s| s.segtype == SegType::Stack))
+            {
+                if let Some(region) = regions
+                    .iter()
+                    .find(|r| r.begin == stack_seg.aligned_start())
+                {
+                    return Some(region.clone());
+                }
+            }
             let mut bottom = 0;
             let mut stack = None;
             for region in regions.iter() {
@@ -425,24 +678,27 @@ pub mod hooking {
 
     use crate::emulator::hatchery::tools::find_stack;
     use crate::emulator::loader::get_static_memory_image;
-    use crate::emulator::profiler::{read_registers_in_hook, Block, MemLogEntry};
-    use crate::util::architecture::{endian, read_integer, word_size_in_bytes, Perms};
+    use crate::configure::{CallPolicy, EntryContext, Watchpoint};
+    use crate::emulator::abi::{self, SyscallAbi};
+    use crate::emulator::profiler::{
+        mark_dirty_pages, read_registers_in_hook, Block, MemLogEntry, SyscallRecord, WatchpointHit,
+    };
+    use crate::util::architecture::{endian, read_integer, word_size_in_bytes, write_integer, Perms};
 
     use super::*;
 
-    fn is_syscall(arch: unicorn::Arch, mode: unicorn::Mode, inst_bytes: &[u8]) -> bool {
-        use unicorn::Arch::*;
-        use unicorn::Mode::*;
-
+    fn is_syscall(syscall_abi: Option<SyscallAbi>, inst_bytes: &[u8]) -> bool {
+        let syscall_abi = match syscall_abi {
+            Some(syscall_abi) => syscall_abi,
+            None => return false,
+        };
         let memory = get_static_memory_image();
         if let Some(inst) = memory.disassemble_bytes(inst_bytes) {
             if let Some(inst) = inst.iter().next() {
-                match (arch, mode, inst.mnemonic()) {
-                    (X86, MODE_64, Some("syscall")) | (X86, MODE_64, Some("sysenter")) => true,
-                    (X86, _, Some("int")) if inst.op_str() == Some("0x80") => true,
-                    (X86, _, _) => false,
-                    _ => unimplemented!("TODO later"),
-                }
+                syscall_abi.is_syscall(
+                    inst.mnemonic().unwrap_or_default(),
+                    inst.op_str().unwrap_or_default(),
+                )
             } else {
                 false
             }
@@ -461,6 +717,16 @@ pub mod hooking {
         }
     }
 
+    /// The target of a call to an immediate address, like `call 0x401000`.
+    fn direct_call_target(inst: &Insn<'_>) -> Option<u64> {
+        let op = inst.op_str()?;
+        if op.starts_with("0x") {
+            u64::from_str_radix(&op[2..], 16).ok()
+        } else {
+            None
+        }
+    }
+
     fn is_ret(arch: unicorn::Arch, _mode: unicorn::Mode, inst_bytes: &[u8]) -> bool {
         if (inst_bytes[0] == 0xC3 || inst_bytes[0] == 0xC2) && arch == unicorn::Arch::X86 {
             return true;
@@ -468,6 +734,33 @@ pub mod hooking {
         false
     }
 
+    /// If `inst_bytes` begins with a jump or call through a register
+    /// (`jmp rax`, `call r11`, etc.), returns the length of that instruction.
+    /// These are the joints of a JOP chain.
+    pub fn indirect_branch_len(arch: unicorn::Arch, inst_bytes: &[u8]) -> Option<usize> {
+        if arch != unicorn::Arch::X86 || inst_bytes.is_empty() {
+            return None; // TODO: implement other arches later if needed
+        }
+        // skip over a REX prefix, if there is one
+        let i = if (0x40..=0x4f).contains(&inst_bytes[0]) {
+            1
+        } else {
+            0
+        };
+        if inst_bytes.len() < i + 2 || inst_bytes[i] != 0xFF {
+            return None;
+        }
+        // FF /2 with a register operand is `call reg`, FF /4 is `jmp reg`
+        match inst_bytes[i + 1] {
+            0xD0..=0xD7 | 0xE0..=0xE7 => Some(i + 2),
+            _ => None,
+        }
+    }
+
+    fn is_indirect_branch(arch: unicorn::Arch, _mode: unicorn::Mode, inst_bytes: &[u8]) -> bool {
+        indirect_branch_len(arch, inst_bytes).is_some()
+    }
+
     /// We want the emulator to halt on a syscall.
     /// NOTE: this only really works on x86 architectures. TODO: generalize somehow
     // pub fn install_syscall_hook<C: 'static + Cpu<'static>>(
@@ -560,18 +853,45 @@ pub mod hooking {
 
     pub fn emu_prep_fn<C: 'static + Cpu<'static>>(
         emu: &mut C,
-        _config: &RoperConfig,
+        config: &RoperConfig,
         code: &[u8],
-        _profiler: &Profiler<C>,
+        profiler: &Profiler<C>,
     ) -> Result<u64, Error> {
         // now write the payload
         let stack = tools::find_stack(emu).expect("Can't find stack");
         let pad = 0x100;
         let sp = stack.begin + pad;
         let room = (stack.end - (stack.begin + pad)) as usize;
+        if let Some(ref entry) = config.entry {
+            return prep_entry_context(emu, entry, code, sp, room, profiler);
+        }
         let end = room.min(code.len());
         let payload = &code[0..end];
         emu.mem_write(sp, payload)?;
+        profiler.mark_dirty(sp, payload.len());
+        if config.chain_mode == ChainMode::Jop {
+            // The payload is a dispatch table. Point the dispatcher at it, and
+            // give the functional gadgets a way back to the dispatcher. The
+            // stack pointer is left near the top of the stack, out of the way.
+            let jop = config
+                .jop
+                .as_ref()
+                .expect("The JOP config was checked when the config was loaded");
+            let table_register: Register<C> = jop
+                .table_register
+                .parse()
+                .ok()
+                .expect("Failed to parse table register");
+            let return_register: Register<C> = jop
+                .return_register
+                .parse()
+                .ok()
+                .expect("Failed to parse return register");
+            emu.reg_write(table_register, sp)?;
+            emu.reg_write(return_register, jop.dispatcher)?;
+            emu.write_stack_pointer(stack.end - pad)?;
+            return Ok(jop.dispatcher);
+        }
         // set the stack pointer to the middle of the stack
         // now "pop" the stack into the program counter
         let word_size = word_size_in_bytes(emu.arch(), emu.mode());
@@ -586,12 +906,50 @@ pub mod hooking {
         }
     }
 
+    /// Lays out the scripted stack words followed by the payload, beginning
+    /// at `sp`, loads the scripted registers, and returns the entry address.
+    fn prep_entry_context<C: 'static + Cpu<'static>>(
+        emu: &mut C,
+        entry: &EntryContext,
+        code: &[u8],
+        sp: u64,
+        room: usize,
+        profiler: &Profiler<C>,
+    ) -> Result<u64, Error> {
+        let word_size = word_size_in_bytes(emu.arch(), emu.mode());
+        let endian = endian(emu.arch(), emu.mode());
+        let mut frame = vec![0_u8; entry.stack.len() * word_size];
+        for (i, word) in entry.stack.iter().enumerate() {
+            write_integer(endian, word_size, *word, &mut frame[i * word_size..]);
+        }
+        frame.extend_from_slice(code);
+        let end = room.min(frame.len());
+        emu.mem_write(sp, &frame[0..end])?;
+        profiler.mark_dirty(sp, end);
+        emu.write_stack_pointer(sp)?;
+        for (reg, val) in entry.registers.iter() {
+            let reg: Register<C> = reg.parse().ok().expect("Failed to parse register name");
+            emu.reg_write(reg, *val)?;
+        }
+        for (reg, offset) in entry.stack_relative_registers.iter() {
+            let reg: Register<C> = reg.parse().ok().expect("Failed to parse register name");
+            emu.reg_write(reg, (sp as i64).wrapping_add(*offset) as u64)?;
+        }
+        Ok(entry.address)
+    }
+
     pub fn install_code_logging_hook<C: 'static + Cpu<'static>>(
         emu: &mut C,
         profiler: &Profiler<C>,
         gadget_addrs: &[u64],
-        break_on_calls: bool,
+        config: &RoperConfig,
     ) -> Result<unicorn::uc_hook, unicorn::Error> {
+        let call_policy = config.call_policy();
+        let break_on_calls = call_policy == CallPolicy::Break;
+        let jop = config.chain_mode == ChainMode::Jop;
+        let emulate_sigreturn = !config.sigreturn_frames.is_empty();
+        let syscall_abi = config.syscall_abi();
+        let record_register_deltas = config.record_register_deltas;
         let memory = get_static_memory_image();
         // let stack_region: MemRegion = find_stack(emu).expect("Could not find stack");
         // let stack_begin = stack_region.begin;
@@ -605,15 +963,76 @@ pub mod hooking {
         let gadget_log = profiler.gadget_log.clone();
         let ret_count = profiler.ret_count.clone();
         let call_stack_depth = profiler.call_stack_depth.clone();
+        let call_targets = profiler.call_targets.clone();
+        // Set at a call, so that the next block is known to be the callee's entry.
+        let entering_call = atomic::AtomicBool::new(false);
         let register_state = profiler.registers_at_last_ret.clone();
         let registers_to_read = Arc::new(profiler.registers_to_read.clone());
         let committed_write_log = profiler.committed_write_log.clone();
-        let committed_trace_log = profiler.committed_trace_log.clone();
+        let committed_blocks = profiler.committed_blocks.clone();
+        // The number of blocks logged so far, which a commit marks as committed.
+        let blocks_logged = atomic::AtomicUsize::new(0);
         let write_log = profiler.write_log.clone();
         let sp: i32 = emu.stack_pointer().into();
+        let pc: i32 = emu.program_counter().into();
+        // The register to hold the stubbed return value, when stepping over calls.
+        let return_register: i32 = if let CallPolicy::StepOver { .. } = call_policy {
+            let reg: Register<C> = abi::return_register(arch, mode)
+                .expect("The return register was checked when the register patterns were parsed")
+                .parse()
+                .ok()
+                .expect("Failed to parse register name");
+            reg.into()
+        } else {
+            0
+        };
+        // Where the callee finds its return address, if not on the stack.
+        let link_register: Option<i32> = abi::link_register(arch, mode).map(|name| {
+            let reg: Register<C> = name.parse().ok().expect("Failed to parse register name");
+            reg.into()
+        });
+        let sigreturn_count = profiler.sigreturn_count.clone();
+        let register_snapshots = profiler.register_snapshots.clone();
+        let halted = profiler.halted.clone();
+        let syscall_log = profiler.syscall_log.clone();
+        // The register ids of the syscall number and arguments.
+        let syscall_registers: Option<(i32, Vec<i32>)> = syscall_abi.map(|syscall_abi| {
+            let parse = |name: &str| -> i32 {
+                let reg: Register<C> = name.parse().ok().expect("Failed to parse register name");
+                reg.into()
+            };
+            (
+                parse(syscall_abi.number_register()),
+                syscall_abi
+                    .argument_registers()
+                    .iter()
+                    .map(|name| parse(name))
+                    .collect(),
+            )
+        });
+        let sigreturn_number = syscall_abi.and_then(|syscall_abi| syscall_abi.sigreturn_number());
+        // The size of the sigreturn frame, and pairs of (frame offset, register id)
+        // to restore on sigreturn.
+        let sigreturn: Option<(usize, Vec<(usize, i32)>)> = if emulate_sigreturn {
+            let layout = srop::frame_layout(arch, mode)
+                .expect("The sigreturn frames were checked when the config was loaded");
+            let restore = layout
+                .iter()
+                .enumerate()
+                .filter(|(_, name)| !name.is_empty())
+                .map(|(i, name)| {
+                    let reg: Register<C> =
+                        name.parse().ok().expect("Failed to parse register name");
+                    (i * word_size, reg.into())
+                })
+                .collect();
+            Some((layout.len() * word_size, restore))
+        } else {
+            None
+        };
 
         macro_rules! commit_logs {
-            ($engine: expr, $registers_to_read: expr => $register_state: expr, $write_log: expr => $committed_write_log: expr, $trace_log: expr => $committed_trace_log: expr) => {
+            ($engine: expr, $registers_to_read: expr => $register_state: expr, $write_log: expr => $committed_write_log: expr, $blocks_logged: expr => $committed_blocks: expr) => {
                 read_registers_in_hook::<C>(
                     ($register_state).clone(),
                     &($registers_to_read),
@@ -623,11 +1042,10 @@ pub mod hooking {
                     .lock()
                     .unwrap()
                     .absorb_segqueue(&($write_log));
-                if let Ok(mut log) = $committed_trace_log.lock() {
-                    while let Ok(b) = $trace_log.pop() {
-                        log.push(b)
-                    }
-                }
+                $committed_blocks.store(
+                    $blocks_logged.load(atomic::Ordering::Relaxed),
+                    atomic::Ordering::Relaxed,
+                );
             };
         }
 
@@ -638,10 +1056,52 @@ pub mod hooking {
             //     .unwrap_or_default();
             let memory = get_static_memory_image();
 
+            if entering_call.swap(false, atomic::Ordering::Relaxed) {
+                call_targets.push(entry);
+                if let CallPolicy::StepOver { return_value } = call_policy {
+                    // Play the part of the callee: take the return address from the link
+                    // register, or pop it, and go back to the caller at once, with the
+                    // stubbed return value.
+                    let return_address = match link_register {
+                        Some(lr) => engine.reg_read(lr).ok(),
+                        None => {
+                            let stack_pointer =
+                                engine.reg_read(sp).expect("Failed to read stack pointer");
+                            let popped = engine
+                                .mem_read_as_vec(stack_pointer, word_size)
+                                .ok()
+                                .and_then(|v| read_integer(&v, endian, word_size));
+                            if popped.is_some() {
+                                engine
+                                    .reg_write(sp, stack_pointer + word_size as u64)
+                                    .expect("Failed to write stack pointer");
+                            }
+                            popped
+                        }
+                    };
+                    if let Some(return_address) = return_address {
+                        engine.reg_write(return_register, return_value).expect("Failed to write register");
+                        engine.reg_write(pc, return_address).expect("Failed to write program counter");
+                        return;
+                    }
+                }
+            }
+
             let block = Block { entry, size };
             block_log.push(block);
+            blocks_logged.fetch_add(1, atomic::Ordering::Relaxed);
             if gadget_addrs.contains(&entry) {
                 gadget_log.push(entry);
+                if record_register_deltas {
+                    let snapshot = registers_to_read
+                        .iter()
+                        .map(|r| {
+                            let reg: i32 = (*r).into();
+                            (*r, engine.reg_read(reg).expect("Failed to read register"))
+                        })
+                        .collect::<HashMap<Register<C>, u64>>();
+                    register_snapshots.push((entry, snapshot));
+                }
             }
 
             if let Ok(inst) = engine.mem_read_as_vec(entry, size) {
@@ -682,32 +1142,86 @@ pub mod hooking {
                                 call_stack_depth.fetch_sub(1, atomic::Ordering::Relaxed);
                             } else {
                                 ret_count.fetch_add(1, atomic::Ordering::Relaxed);
-                                commit_logs!(engine, registers_to_read => register_state, write_log => committed_write_log, block_log => committed_trace_log);
+                                commit_logs!(engine, registers_to_read => register_state, write_log => committed_write_log, blocks_logged => committed_blocks);
                             }
                             // Quietly stop the emulator if there's an attempt to return to 0
                             if addr == 0 {
+                                halted.store(true, atomic::Ordering::Relaxed);
                                 engine.emu_stop().expect("Failed to stop emulator");
                             }
                         }
                     }
                 // it would be cool if we could save the context at each ret, so that we can rewind
                 // bad gadgets.
-                } else if is_syscall(arch, mode, &inst) {
+                } else if jop && is_indirect_branch(arch, mode, &inst) {
+                    // In a JOP chain, the indirect branches play the part that rets play in a ROP
+                    // chain: each one hands control back to the dispatcher, or on to the next gadget.
+                    let registers_to_read = registers_to_read.clone();
+                    ret_count.fetch_add(1, atomic::Ordering::Relaxed);
+                    commit_logs!(engine, registers_to_read => register_state, write_log => committed_write_log, blocks_logged => committed_blocks);
+                } else if is_syscall(syscall_abi, &inst) {
                     // Committing the logs at a syscall is one way to get trapped in a non-composable local optima.
-                    // commit_logs!(engine, registers_to_read => register_state, write_log => committed_write_log, block_log => committed_trace_log);
+                    // commit_logs!(engine, registers_to_read => register_state, write_log => committed_write_log, blocks_logged => committed_blocks);
+                    let mut number = None;
+                    if let Some((number_register, ref arg_registers)) = syscall_registers {
+                        number = engine.reg_read(number_register).ok();
+                        syscall_log.push(SyscallRecord {
+                            program_counter: entry,
+                            number: number.unwrap_or_default(),
+                            args: arg_registers
+                                .iter()
+                                .map(|r| engine.reg_read(*r).unwrap_or_default())
+                                .collect(),
+                        });
+                    }
+                    if let Some((frame_size, ref restore)) = sigreturn {
+                        if number.is_some() && number == sigreturn_number {
+                            // Play the part of the kernel: load the registers from the frame
+                            // on the stack, and carry on from wherever the frame's PC says.
+                            let stack_pointer = engine.reg_read(sp).expect("Failed to read stack pointer");
+                            if let Ok(frame) = engine.mem_read_as_vec(stack_pointer, frame_size) {
+                                sigreturn_count.fetch_add(1, atomic::Ordering::Relaxed);
+                                for (offset, reg) in restore.iter() {
+                                    if let Some(val) = read_integer(&frame[*offset..], endian, word_size) {
+                                        engine.reg_write(*reg, val).expect("Failed to write register");
+                                    }
+                                }
+                                return;
+                            }
+                        }
+                    }
+                    halted.store(true, atomic::Ordering::Relaxed);
                     engine.emu_stop().expect("Failed to stop emulator");
                 } else {
                     // if not a RETURN
-                    // EXPERIMENTAL: halt execution at calls. see what happens.
-                    if let Some(insts) = memory.disassemble(entry, size, Some(1)) {
-                        for inst in insts.iter() {
+                    // A call ends its basic block, so it's the last instruction we need to check.
+                    if let Some(insts) = memory.disassemble(entry, size, None) {
+                        if let Some(inst) = insts.iter().last() {
                             if is_call(arch, mode, &inst) {
-                                call_stack_depth.fetch_add(1, atomic::Ordering::Relaxed);
-                                if break_on_calls {
-                                    engine.emu_stop().expect("Failed to stop emulator");
+                                match call_policy {
+                                    CallPolicy::Break => {
+                                        // we won't see the callee's entry, so this is only
+                                        // known for direct calls
+                                        if let Some(target) = direct_call_target(&inst) {
+                                            call_targets.push(target);
+                                        }
+                                        call_stack_depth.fetch_add(1, atomic::Ordering::Relaxed);
+                                        halted.store(true, atomic::Ordering::Relaxed);
+                                        engine.emu_stop().expect("Failed to stop emulator");
+                                    }
+                                    CallPolicy::StepOver { .. } => {
+                                        entering_call.store(true, atomic::Ordering::Relaxed);
+                                    }
+                                    CallPolicy::Follow { max_depth } => {
+                                        entering_call.store(true, atomic::Ordering::Relaxed);
+                                        let depth = call_stack_depth.fetch_add(1, atomic::Ordering::Relaxed) + 1;
+                                        if max_depth.map(|max| depth > max).unwrap_or(false) {
+                                            halted.store(true, atomic::Ordering::Relaxed);
+                                            engine.emu_stop().expect("Failed to stop emulator");
+                                        }
+                                    }
                                 }
                             }
-                            break;
                         }
                     }
                 }
@@ -754,6 +1268,93 @@ pub mod hooking {
         Ok(hooks)
     }
 
+    /// Notes the pages of writeable memory that the chain writes to, the
+    /// stack's included, so that only they need be restored once it's run.
+    pub fn install_dirty_page_hook<C: 'static + Cpu<'static>>(
+        emu: &mut C,
+        profiler: &Profiler<C>,
+    ) -> Result<Vec<unicorn::uc_hook>, unicorn::Error> {
+        let dirty_pages = profiler.dirty_pages.clone();
+        // Writes tend to come in runs to the same page, which needn't be
+        // noted more than once.
+        let mut last_write = None;
+        let dirty_page_callback = move |_engine: &unicorn::Unicorn<'_>,
+                                        _mem_type,
+                                        address: u64,
+                                        num_bytes_written: usize,
+                                        _value| {
+            let pages = (
+                address >> loader::PAGE_BITS,
+                (address + num_bytes_written as u64) >> loader::PAGE_BITS,
+            );
+            if last_write != Some(pages) {
+                mark_dirty_pages(&dirty_pages, address, num_bytes_written);
+                last_write = Some(pages);
+            }
+            false
+        };
+
+        mem_hook_by_prot(
+            emu,
+            MemHookType::MEM_WRITE,
+            Protection::WRITE,
+            dirty_page_callback,
+            true,
+        )
+    }
+
+    /// Record every read or write that touches one of the configured watchpoints.
+    pub fn install_watchpoint_hooks<C: 'static + Cpu<'static>>(
+        emu: &mut C,
+        profiler: &Profiler<C>,
+        watchpoints: &[Watchpoint],
+    ) -> Result<Vec<unicorn::uc_hook>, unicorn::Error> {
+        let pc: i32 = emu.program_counter().into();
+        let endian = endian(emu.arch(), emu.mode());
+        let mut hooks = Vec::new();
+        for (index, watchpoint) in watchpoints.iter().enumerate() {
+            let watchpoint_log = profiler.watchpoint_log.clone();
+            let callback =
+                move |engine: &unicorn::Unicorn<'_>, mem_type, address, size: usize, value: i64| {
+                    let program_counter = engine.reg_read(pc).expect("Failed to read PC register");
+                    let write = if let MemType::WRITE = mem_type {
+                        true
+                    } else {
+                        false
+                    };
+                    // Unicorn doesn't pass along the value for reads, so we fetch it ourselves.
+                    let value = if write {
+                        value as u64
+                    } else {
+                        engine
+                            .mem_read_as_vec(address, size)
+                            .ok()
+                            .and_then(|bytes| match size {
+                                1 => bytes.first().map(|b| *b as u64),
+                                2 | 4 | 8 => read_integer(&bytes, endian, size),
+                                _ => None,
+                            })
+                            .unwrap_or(0)
+                    };
+                    watchpoint_log.push(WatchpointHit {
+                        watchpoint: index,
+                        program_counter,
+                        address,
+                        size,
+                        value,
+                        write,
+                    });
+                    false
+                };
+            // unicorn's hook ranges are inclusive
+            let begin = watchpoint.address;
+            let end = watchpoint.address + watchpoint.size.max(1) as u64 - 1;
+            hooks.push(emu.add_mem_hook(MemHookType::MEM_READ, begin, end, callback.clone())?);
+            hooks.push(emu.add_mem_hook(MemHookType::MEM_WRITE, begin, end, callback)?);
+        }
+        Ok(hooks)
+    }
+
     /// Add a memory hook wherever the specified protections are satisfied.
     ///
     /// The callback takes four arguments:
@@ -835,12 +1436,13 @@ mod test {
             emu: &mut C,
             _config: &RoperConfig,
             code: &[u8],
-            _profiler: &Profiler<C>,
+            profiler: &Profiler<C>,
         ) -> Result<Address, Error> {
             // now write the payload
             let stack = tools::find_stack(emu).expect("no stack");
             let sp = stack.begin + (stack.end - stack.begin) / 2;
             emu.mem_write(sp, code)?;
+            profiler.mark_dirty(sp, code.len());
             // set the stack pointer to the middle of the stack
             // now "pop" the stack into the program counter
             let word_size = word_size_in_bytes(emu.arch(), emu.mode());
@@ -1,6 +1,7 @@
+use std::cell::Cell;
 use std::fmt;
 use std::hash::Hash;
-use std::sync::Once;
+use std::sync::{Once, RwLock};
 
 use capstone::Instructions;
 use falcon::il;
@@ -12,7 +13,7 @@ use rand::distributions::{Distribution, WeightedIndex};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-use crate::configure::RoperConfig;
+use crate::configure::{MemoryInit, RoperConfig};
 use crate::disassembler::Disassembler;
 use crate::error::Error;
 use crate::util::architecture::{endian, read_integer, word_size_in_bytes, Endian, Perms};
@@ -21,17 +22,50 @@ use crate::util::random::hash_seed_rng;
 pub const PAGE_BITS: u64 = 12;
 pub const PAGE_SIZE: u64 = 1 << PAGE_BITS;
 
-// placeholders
-pub static mut MEM_IMAGE: MemoryImage = MemoryImage {
-    segs: Vec::new(),
-    arch: unicorn::Arch::X86,
-    mode: unicorn::Mode::MODE_64,
-    endian: Endian::Little,
-    word_size: 8,
-    disasm: None,
-    il_program: None,
-};
-static INIT_MEM_IMAGE: Once = Once::new();
+/// The memory images of the targets, by index: 0 for `roper.binary_path`,
+/// and `i + 1` for `roper.targets[i]`. Each is leaked as it's loaded, so
+/// that it can be borrowed for the rest of the run.
+static INIT_MEM_IMAGES: Once = Once::new();
+static mut MEM_IMAGES: Option<RwLock<Vec<Option<&'static MemoryImage>>>> = None;
+
+thread_local! {
+    /// The target the current thread is working on, and its memory image,
+    /// once it's been looked up.
+    static TARGET: Cell<(usize, Option<&'static MemoryImage>)> = Cell::new((0, None));
+}
+
+fn mem_images() -> &'static RwLock<Vec<Option<&'static MemoryImage>>> {
+    INIT_MEM_IMAGES.call_once(|| unsafe { MEM_IMAGES = Some(RwLock::new(Vec::new())) });
+    unsafe {
+        MEM_IMAGES
+            .as_ref()
+            .expect("The memory images have not been initialized")
+    }
+}
+
+/// Sets the target whose memory image `get_static_memory_image` returns on
+/// the current thread. A thread working for an island, including a rayon
+/// worker lent to it, sets the island's target first, as it's 0 otherwise.
+pub fn set_target(target: usize) {
+    TARGET.with(|t| {
+        if t.get().0 != target {
+            t.set((target, None))
+        }
+    });
+}
+
+pub fn current_target() -> usize {
+    TARGET.with(|t| t.get().0)
+}
+
+/// The memory image of `target`, if it has been loaded.
+pub fn memory_image(target: usize) -> Option<&'static MemoryImage> {
+    mem_images()
+        .read()
+        .expect("poisoned")
+        .get(target)
+        .and_then(|image| *image)
+}
 
 #[derive(Debug)]
 pub struct MemoryImage {
@@ -417,6 +451,7 @@ pub enum SegType {
     GnuStack,
     GnuRelRo,
     Stack,
+    Scripted,
     Other,
     /* KLUDGE: a temporary catchall */
 }
@@ -513,40 +548,153 @@ fn load_elf(elf: Elf<'_>, code_buffer: &[u8], stack_size: usize) -> Vec<Seg> {
     segs
 }
 
+fn parse_perms(s: &str) -> Result<Perms, Error> {
+    let mut perm = Perms::NONE;
+    for c in s.to_lowercase().chars() {
+        match c {
+            'r' => perm |= Perms::READ,
+            'w' => perm |= Perms::WRITE,
+            'x' => perm |= Perms::EXEC,
+            _ => {
+                return Err(Error::Parsing(format!(
+                    "Unknown permission {:?} in {:?}, expected some of \"rwx\"",
+                    c, s
+                )))
+            }
+        }
+    }
+    Ok(perm)
+}
+
+/// Applies the scripted memory initializations to the segments of the memory
+/// image. Writes that land inside an existing segment patch that segment's
+/// data, and keep its permissions, and writes that land outside of any
+/// segment get a segment of their own. Writes that straddle a segment
+/// boundary, or that give permissions for a patch, are errors.
+pub fn apply_memory_inits(segs: &mut Vec<Seg>, inits: &[MemoryInit]) -> Result<(), Error> {
+    for init in inits {
+        let data = init.data()?;
+        let start = init.address;
+        let end = start + data.len() as u64;
+        let containing = segs
+            .iter()
+            .position(|s| s.aligned_start() <= start && start < s.aligned_end());
+        if let Some(i) = containing {
+            let seg = &mut segs[i];
+            if let Some(ref perms) = init.perms {
+                return Err(Error::Misc(format!(
+                    "memory_init at 0x{:x} patches segment {}, whose permissions it can't \
+                     change to {:?}",
+                    start, seg, perms
+                )));
+            }
+            if end > seg.aligned_end() {
+                return Err(Error::Misc(format!(
+                    "memory_init at 0x{:x} runs past the end of segment {}",
+                    start, seg
+                )));
+            }
+            seg.ensure_data_alignment();
+            let offset = (start - seg.aligned_start()) as usize;
+            seg.data[offset..offset + data.len()].copy_from_slice(&data);
+            log::info!("Patched 0x{:x} bytes at 0x{:x} in {}", data.len(), start, seg);
+        } else {
+            if segs
+                .iter()
+                .any(|s| start < s.aligned_start() && s.aligned_start() < end)
+            {
+                return Err(Error::Misc(format!(
+                    "memory_init at 0x{:x} overlaps the start of an existing segment",
+                    start
+                )));
+            }
+            let base = start & !(PAGE_SIZE - 1);
+            let offset = (start - base) as usize;
+            let mut seg = Seg {
+                addr: base,
+                memsz: offset + data.len(),
+                perm: parse_perms(init.perms.as_ref().map_or("rw", |p| p.as_str()))?,
+                segtype: SegType::Scripted,
+                data: vec![],
+            };
+            seg.ensure_data_alignment();
+            seg.data[offset..offset + data.len()].copy_from_slice(&data);
+            log::info!("Mapped scripted segment {}", seg);
+            segs.push(seg);
+        }
+    }
+    segs.sort_by_key(|s| s.aligned_start());
+    Ok(())
+}
+
+/// The number of bytes each emulator maps for its own use. The
+/// non-writeable segments are shared between emulators, and don't count.
+pub fn emulator_memory_size(segs: &[Seg]) -> usize {
+    segs.iter()
+        .filter(|s| s.is_writeable())
+        .map(Seg::aligned_size)
+        .sum()
+}
+
+/// Returns an error if the memory each emulator would map exceeds `cap`.
+pub fn check_emulator_memory(segs: &[Seg], cap: Option<usize>) -> Result<(), Error> {
+    let size = emulator_memory_size(segs);
+    match cap {
+        Some(cap) if size > cap => Err(Error::Misc(format!(
+            "Each emulator would map 0x{:x} bytes of writeable memory, exceeding max_emulator_memory (0x{:x}). Consider reducing emulator_stack_size or the scripted memory_init regions.",
+            size, cap
+        ))),
+        _ => Ok(()),
+    }
+}
+
 fn initialize_memory_image(
+    target: usize,
     segments: &[Seg],
     arch: unicorn::Arch,
     mode: unicorn::Mode,
     il_program: Option<il::Program>,
 ) {
+    let mut images = mem_images().write().expect("poisoned");
+    if images.len() <= target {
+        images.resize(target + 1, None);
+    }
+    if images[target].is_some() {
+        return;
+    }
     let endian = endian(arch, mode);
     let word_size = word_size_in_bytes(arch, mode);
-    unsafe {
-        MEM_IMAGE = MemoryImage {
-            segs: segments.to_owned(),
-            arch,
-            mode,
-            endian,
-            word_size,
-            disasm: Some(Disassembler::new(arch, mode).expect("Failed to initialize disassembler")),
-            il_program,
-        }
-    }
+    let image = Box::new(MemoryImage {
+        segs: segments.to_owned(),
+        arch,
+        mode,
+        endian,
+        word_size,
+        disasm: Some(Disassembler::new(arch, mode).expect("Failed to initialize disassembler")),
+        il_program,
+    });
+    images[target] = Some(Box::leak(image));
 }
 
+/// The memory image of the current thread's target. See `set_target`.
 pub fn try_to_get_static_memory_image() -> Option<&'static MemoryImage> {
-    if INIT_MEM_IMAGE.is_completed() {
-        unsafe { Some(&MEM_IMAGE) }
-    } else {
-        None
-    }
+    TARGET.with(|t| match t.get() {
+        (_, Some(image)) => Some(image),
+        (target, None) => {
+            let image = memory_image(target);
+            t.set((target, image));
+            image
+        }
+    })
 }
 
 pub fn get_static_memory_image() -> &'static MemoryImage {
-    if INIT_MEM_IMAGE.is_completed() {
-        unsafe { &MEM_IMAGE }
-    } else {
-        panic!("MEM_IMAGE has not been initialized")
+    match try_to_get_static_memory_image() {
+        Some(image) => image,
+        None => panic!(
+            "The memory image of target {} has not been loaded",
+            current_target()
+        ),
     }
 }
 
@@ -555,10 +703,12 @@ pub fn load(
     stack_size: usize,
     arch: unicorn::Arch,
     mode: unicorn::Mode,
+    memory_inits: &[MemoryInit],
+    target: usize,
     init: bool,
 ) -> Result<Vec<Seg>, Error> {
-    if INIT_MEM_IMAGE.is_completed() {
-        unsafe { Ok(MEM_IMAGE.segments().clone()) }
+    if let Some(image) = memory_image(target) {
+        Ok(image.segments().clone())
     } else {
         let obj = Object::parse(code_buffer)?;
         let mut segs = match obj {
@@ -566,13 +716,14 @@ pub fn load(
             _ => unimplemented!("Only ELF binaries are supported at this time."),
         };
         segs.sort_by_key(|s| s.aligned_start());
+        apply_memory_inits(&mut segs, memory_inits)?;
         for seg in &segs {
             log::info!("{}, data len: {:x}", seg, seg.data.len());
         }
 
         // Cache the memory image as a globally accessible static
         if init {
-            INIT_MEM_IMAGE.call_once(|| initialize_memory_image(&segs, arch, mode, None));
+            initialize_memory_image(target, &segs, arch, mode, None);
         }
 
         Ok(segs)
@@ -584,7 +735,17 @@ pub fn load_from_path(config: &RoperConfig, init: bool) -> Result<Vec<Seg>, Erro
     let stack_size = config.emulator_stack_size;
     let arch = config.arch;
     let mode = config.mode;
-    load(&std::fs::read(path)?, stack_size, arch, mode, init)
+    let segs = load(
+        &std::fs::read(path)?,
+        stack_size,
+        arch,
+        mode,
+        &config.memory_init,
+        config.target,
+        init,
+    )?;
+    check_emulator_memory(&segs, config.max_emulator_memory)?;
+    Ok(segs)
 }
 
 pub mod falcon_loader {
@@ -622,8 +783,8 @@ pub mod falcon_loader {
     }
 
     pub fn load_from_path(config: &mut Config, init: bool) -> Result<Vec<Seg>, Error> {
-        if INIT_MEM_IMAGE.is_completed() {
-            unsafe { Ok(MEM_IMAGE.segments().clone()) }
+        if let Some(image) = memory_image(config.roper.target) {
+            Ok(image.segments().clone())
         } else {
             log::info!("Using falcon loader");
             let path = &config.roper.binary_path;
@@ -682,6 +843,8 @@ pub mod falcon_loader {
             for seg in segs.iter_mut() {
                 seg.ensure_data_alignment()
             }
+            apply_memory_inits(&mut segs, &config.roper.memory_init)?;
+            check_emulator_memory(&segs, config.roper.max_emulator_memory)?;
 
             let (arch, mode) = arch_mode_from_linker(&linker);
             config.roper.arch = arch;
@@ -725,7 +888,7 @@ pub mod falcon_loader {
 
             if init {
                 // TODO: let lift_program be optional, and only activated when using Push
-                INIT_MEM_IMAGE.call_once(|| initialize_memory_image(&segs, arch, mode, program));
+                initialize_memory_image(config.roper.target, &segs, arch, mode, program);
             }
             Ok(segs)
         }
@@ -740,6 +903,97 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn test_emulator_memory_cap() {
+        let seg = |addr, perm| {
+            let mut seg = Seg {
+                addr,
+                memsz: 0x1800,
+                perm,
+                segtype: SegType::Load,
+                data: vec![],
+            };
+            seg.ensure_data_alignment();
+            seg
+        };
+        let segs = vec![
+            seg(0x1000, Perms::READ | Perms::EXEC),
+            seg(0x4000, Perms::READ | Perms::WRITE),
+        ];
+        assert_eq!(emulator_memory_size(&segs), 0x2000);
+        assert!(check_emulator_memory(&segs, None).is_ok());
+        assert!(check_emulator_memory(&segs, Some(0x2000)).is_ok());
+        assert!(check_emulator_memory(&segs, Some(0x1fff)).is_err());
+    }
+
+    #[test]
+    fn test_apply_memory_inits() {
+        let init = |address, bytes: &str, perms: Option<&str>| MemoryInit {
+            address,
+            bytes: Some(bytes.to_string()),
+            file: None,
+            perms: perms.map(String::from),
+        };
+        let mut segs = vec![Seg {
+            addr: 0x1000,
+            memsz: 0x1000,
+            perm: Perms::READ | Perms::EXEC,
+            segtype: SegType::Load,
+            data: vec![0; 0x1000],
+        }];
+        let inits = vec![
+            init(0x1010, "deadbeef", None),
+            init(0x8004, "cafe", Some("rx")),
+            init(0x4000, "00", None),
+        ];
+        apply_memory_inits(&mut segs, &inits).unwrap();
+        assert_eq!(segs.len(), 3);
+        assert_eq!(&segs[0].data[0x10..0x14], &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(segs[0].perm, Perms::READ | Perms::EXEC);
+        assert_eq!(segs[1].aligned_start(), 0x4000);
+        assert_eq!(segs[1].perm, Perms::READ | Perms::WRITE);
+        assert_eq!(segs[2].aligned_start(), 0x8000);
+        assert_eq!(segs[2].segtype, SegType::Scripted);
+        assert_eq!(segs[2].perm, Perms::READ | Perms::EXEC);
+        assert_eq!(&segs[2].data[4..6], &[0xca, 0xfe]);
+
+        // a patch keeps its segment's permissions
+        let mut patched = segs.clone();
+        assert!(apply_memory_inits(&mut patched, &[init(0x1000, "00", Some("rw"))]).is_err());
+        // there's no such permission as "q"
+        assert!(apply_memory_inits(&mut patched, &[init(0x10000, "00", Some("rq"))]).is_err());
+        // nor may a write run past the end of the segment it patches
+        assert!(apply_memory_inits(&mut patched, &[init(0x1fff, "0000", None)]).is_err());
+    }
+
+    #[test]
+    fn test_memory_images() {
+        let seg = |addr| Seg {
+            addr,
+            memsz: 0x1000,
+            perm: Perms::READ | Perms::EXEC,
+            segtype: SegType::Load,
+            data: vec![0; 0x1000],
+        };
+        // targets well beyond those the other tests load
+        initialize_memory_image(100, &[seg(0x1000)], Arch::X86, Mode::MODE_64, None);
+        initialize_memory_image(101, &[seg(0x8000)], Arch::X86, Mode::MODE_32, None);
+        // the first image loaded is kept
+        initialize_memory_image(100, &[seg(0x4000)], Arch::X86, Mode::MODE_64, None);
+        assert!(memory_image(102).is_none());
+        set_target(100);
+        assert_eq!(get_static_memory_image().first_address(), 0x1000);
+        set_target(101);
+        assert_eq!(get_static_memory_image().first_address(), 0x8000);
+        assert_eq!(get_static_memory_image().word_size, 4);
+        set_target(102);
+        assert!(try_to_get_static_memory_image().is_none());
+        // each thread has a target of its own
+        std::thread::spawn(|| assert_eq!(current_target(), 0))
+            .join()
+            .unwrap();
+    }
+
     #[test]
     fn test_loader() {
         //pretty_env_logger::init();
@@ -1,5 +1,9 @@
+pub mod abi;
 pub mod hatchery;
 pub mod loader;
 pub mod pack;
+pub mod path;
+pub mod preset;
 pub mod profiler;
 pub mod register_pattern;
+pub mod srop;
@@ -0,0 +1,202 @@
+//! Execution paths, kept compactly. A path of basic blocks is stored as the
+//! variable-length encoding of each block's size, and of the distance of
+//! its entry from the end of the block before it, zigzagged, so that jumps
+//! backward are as short as jumps forward. Most blocks are near the last,
+//! and a few dozen bytes long, so a block takes a few bytes, rather than
+//! the 16 of a `Block`, and a short path fits in the buffer inline, without
+//! an allocation of its own.
+//!
+//! A path is serialized as the sequence of its blocks, as it was when paths
+//! were kept as vectors of `Block`s, so that old dumps can still be read.
+use std::fmt;
+use std::iter::FromIterator;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use smallvec::SmallVec;
+
+use crate::emulator::profiler::Block;
+
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct BlockPath {
+    bytes: SmallVec<[u8; 32]>,
+    len: usize,
+    /// The end of the last block pushed, from which the next's entry is
+    /// measured
+    last_end: u64,
+}
+
+impl BlockPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, block: Block) {
+        let delta = block.entry.wrapping_sub(self.last_end) as i64;
+        push_varint(&mut self.bytes, zigzag(delta));
+        push_varint(&mut self.bytes, block.size as u64);
+        self.last_end = block.entry.wrapping_add(block.size as u64);
+        self.len += 1;
+    }
+
+    /// The number of blocks in the path.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of bytes the path is encoded in.
+    pub fn encoded_len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Decodes the blocks, in the order in which they were executed.
+    pub fn iter(&self) -> Blocks<'_> {
+        Blocks {
+            bytes: &self.bytes,
+            remaining: self.len,
+            last_end: 0,
+        }
+    }
+}
+
+pub struct Blocks<'a> {
+    bytes: &'a [u8],
+    remaining: usize,
+    last_end: u64,
+}
+
+impl<'a> Iterator for Blocks<'a> {
+    type Item = Block;
+
+    fn next(&mut self) -> Option<Block> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let delta = unzigzag(read_varint(&mut self.bytes));
+        let size = read_varint(&mut self.bytes);
+        let entry = self.last_end.wrapping_add(delta as u64);
+        self.last_end = entry.wrapping_add(size);
+        self.remaining -= 1;
+        Some(Block {
+            entry,
+            size: size as usize,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Blocks<'a> {}
+
+impl<'a> IntoIterator for &'a BlockPath {
+    type Item = Block;
+    type IntoIter = Blocks<'a>;
+
+    fn into_iter(self) -> Blocks<'a> {
+        self.iter()
+    }
+}
+
+impl FromIterator<Block> for BlockPath {
+    fn from_iter<I: IntoIterator<Item = Block>>(iter: I) -> Self {
+        let mut path = Self::new();
+        for block in iter {
+            path.push(block)
+        }
+        path
+    }
+}
+
+impl fmt::Debug for BlockPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl Serialize for BlockPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for BlockPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<Block>::deserialize(deserializer).map(|blocks| blocks.into_iter().collect())
+    }
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    ((n >> 1) as i64) ^ -((n & 1) as i64)
+}
+
+/// Pushes `n`, seven bits at a time, least significant first, the high bit
+/// of each byte but the last set.
+fn push_varint(bytes: &mut SmallVec<[u8; 32]>, mut n: u64) {
+    while n >= 0x80 {
+        bytes.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    bytes.push(n as u8)
+}
+
+/// Reads a number pushed by `push_varint` off the front of `bytes`.
+fn read_varint(bytes: &mut &[u8]) -> u64 {
+    let mut n = 0;
+    let mut shift = 0;
+    while let Some((byte, rest)) = bytes.split_first() {
+        *bytes = rest;
+        n |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    n
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn block(entry: u64, size: usize) -> Block {
+        Block { entry, size }
+    }
+
+    #[test]
+    fn test_block_path() {
+        let blocks = vec![
+            block(0x40_1000, 12),
+            block(0x40_100c, 3),
+            // a jump backward
+            block(0x40_0ff0, 7),
+            block(0x7fff_ffff_f000, 0x20),
+            block(0, 1),
+            block(std::u64::MAX - 4, 4),
+        ];
+        let path = blocks.iter().cloned().collect::<BlockPath>();
+        assert_eq!(path.len(), blocks.len());
+        assert_eq!(path.iter().collect::<Vec<Block>>(), blocks);
+        assert!(path.encoded_len() < blocks.len() * std::mem::size_of::<Block>());
+
+        let short = blocks[..3].iter().cloned().collect::<BlockPath>();
+        // the first block's entry is far from 0, but each after it is near
+        // the last, and takes 2 bytes
+        assert_eq!(short.encoded_len(), 5 + 2 + 2);
+        assert!(!short.bytes.spilled());
+
+        // serialized as the blocks themselves
+        let json = serde_json::to_string(&path).unwrap();
+        assert_eq!(json, serde_json::to_string(&blocks).unwrap());
+        assert_eq!(serde_json::from_str::<BlockPath>(&json).unwrap(), path);
+        assert!(BlockPath::new().iter().next().is_none());
+    }
+}
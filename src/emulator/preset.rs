@@ -0,0 +1,132 @@
+//! Named presets of the architecture-specific fields of `[roper]`, so that
+//! a config can say
+//!
+//! ```text
+//! [roper]
+//! preset = "linux-x86_64"
+//! ```
+//!
+//! in place of setting `arch`, `mode`, `abi`, `syscall_abi`, and
+//! `output_registers` by hand. Whatever the section sets itself takes
+//! precedence over the preset.
+use serde::Serialize;
+
+use crate::emulator::abi::{Abi, SyscallAbi};
+use crate::error::Error;
+
+pub struct Preset {
+    pub name: &'static str,
+    pub arch: unicorn::Arch,
+    pub mode: unicorn::Mode,
+    pub abi: Abi,
+    pub syscall_abi: SyscallAbi,
+    /// The return and argument registers, of calls and syscalls, and the
+    /// stack and instruction pointers
+    pub output_registers: &'static [&'static str],
+}
+
+pub const PRESETS: [Preset; 3] = [
+    Preset {
+        name: "linux-x86_64",
+        arch: unicorn::Arch::X86,
+        mode: unicorn::Mode::MODE_64,
+        abi: Abi::SysV,
+        syscall_abi: SyscallAbi::LinuxX64,
+        output_registers: &["RAX", "RDI", "RSI", "RDX", "R10", "R8", "R9", "RSP", "RIP"],
+    },
+    Preset {
+        name: "linux-armv7",
+        arch: unicorn::Arch::ARM,
+        // which is also unicorn's ARM mode
+        mode: unicorn::Mode::LITTLE_ENDIAN,
+        abi: Abi::Aapcs,
+        syscall_abi: SyscallAbi::LinuxArm,
+        output_registers: &["R0", "R1", "R2", "R3", "R7", "SP", "PC"],
+    },
+    Preset {
+        name: "win-x64",
+        arch: unicorn::Arch::X86,
+        mode: unicorn::Mode::MODE_64,
+        abi: Abi::Win64,
+        syscall_abi: SyscallAbi::WindowsX64,
+        output_registers: &["RAX", "RCX", "RDX", "R8", "R9", "R10", "RSP", "RIP"],
+    },
+];
+
+pub fn get(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|p| p.name == name)
+}
+
+fn to_value<T: Serialize>(t: T) -> Result<toml::Value, Error> {
+    toml::Value::try_from(t).map_err(|e| Error::Misc(e.to_string()))
+}
+
+impl Preset {
+    /// The fields of `[roper]` the preset fills in.
+    fn fields(&self) -> Result<Vec<(&'static str, toml::Value)>, Error> {
+        Ok(vec![
+            ("arch", to_value(self.arch)?),
+            ("mode", to_value(self.mode)?),
+            ("abi", to_value(self.abi)?),
+            ("syscall_abi", to_value(self.syscall_abi)?),
+            ("output_registers", to_value(self.output_registers)?),
+        ])
+    }
+}
+
+/// Fills in the fields of the config's `[roper]` section from the preset
+/// it names, if it names one, leaving those it sets alone.
+pub fn apply(config: &mut toml::Value) -> Result<(), Error> {
+    let roper = match config.get_mut("roper").and_then(toml::Value::as_table_mut) {
+        Some(roper) => roper,
+        None => return Ok(()),
+    };
+    let name = match roper.get("preset") {
+        Some(toml::Value::String(name)) => name.clone(),
+        Some(other) => {
+            return Err(Error::Parsing(format!(
+                "roper.preset should be a string, not {}",
+                other
+            )))
+        }
+        None => return Ok(()),
+    };
+    let preset = get(&name).ok_or_else(|| {
+        Error::Parsing(format!(
+            "No such preset as {:?}. Try one of {}",
+            name,
+            PRESETS
+                .iter()
+                .map(|p| p.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    })?;
+    for (key, value) in preset.fields()? {
+        roper.entry(key.to_string()).or_insert(value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let mut config: toml::Value =
+            toml::from_str("[roper]\npreset = \"linux-x86_64\"\noutput_registers = [\"RAX\"]\n")
+                .unwrap();
+        apply(&mut config).unwrap();
+        let roper = &config["roper"];
+        assert_eq!(roper["arch"], to_value(unicorn::Arch::X86).unwrap());
+        assert_eq!(roper["mode"], to_value(unicorn::Mode::MODE_64).unwrap());
+        assert_eq!(roper["abi"].as_str(), Some("SysV"));
+        assert_eq!(roper["syscall_abi"].as_str(), Some("LinuxX64"));
+        // set in the config, so left alone
+        assert_eq!(roper["output_registers"].as_array().unwrap().len(), 1);
+
+        let mut config: toml::Value = toml::from_str("[roper]\npreset = \"beos\"\n").unwrap();
+        assert!(apply(&mut config).is_err());
+    }
+}
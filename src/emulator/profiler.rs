@@ -1,7 +1,7 @@
 use std::cmp::{Ord, PartialOrd};
 use std::collections::BTreeMap;
 use std::fmt;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -16,8 +16,9 @@ use unicorn::Cpu;
 
 use crate::emulator::loader;
 use crate::emulator::loader::{get_static_memory_image, try_to_get_static_memory_image, Seg};
-use crate::emulator::register_pattern::{Register, RegisterState};
-use crate::util::architecture::{write_integer, Endian};
+use crate::emulator::path::BlockPath;
+use crate::emulator::register_pattern::{register_name, Register, RegisterState};
+use crate::util::architecture::{write_integer, Endian, Perms};
 
 #[derive(Clone, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize, Hash)]
 pub struct Block {
@@ -57,21 +58,41 @@ impl fmt::Debug for Block {
 pub struct Profiler<C: Cpu<'static>> {
     /// The Arc<RwLock<_>> fields need to be writeable for the unicorn callbacks.
     pub trace_log: Arc<SegQueue<Block>>,
-    pub committed_trace_log: Arc<Mutex<Vec<Block>>>,
+    /// The number of blocks at the head of the `trace_log` that had been
+    /// logged by the last commit, at a composable `ret`. The log is drained
+    /// once the emulation is over, and the blocks past these dropped, so
+    /// the hook never takes a lock to commit it.
+    pub committed_blocks: Arc<AtomicUsize>,
 
     pub ret_count: Arc<AtomicUsize>,
+    pub sigreturn_count: Arc<AtomicUsize>,
     pub call_stack_depth: Arc<AtomicUsize>,
+    /// The addresses called into, whether or not the calls were followed.
+    pub call_targets: Arc<SegQueue<u64>>,
     pub gadget_log: Arc<SegQueue<u64>>,
     //Arc<RwLock<Vec<u64>>>,
+    /// Register values on entry to each gadget, if we're recording register deltas
+    pub register_snapshots: Arc<SegQueue<(u64, HashMap<Register<C>, u64>)>>,
+    /// The pages of writeable memory written to, by the chain or by the
+    /// host, as it lays the payload out, noted if `track_dirty_pages` is
+    /// set. These may repeat.
+    pub dirty_pages: Arc<SegQueue<u64>>,
     /// These fields are written to after the emulation has finished.
     pub written_memory: Vec<Seg>,
     pub write_log: Arc<SegQueue<MemLogEntry>>,
     pub committed_write_log: Arc<Mutex<SparseDataHelper>>,
     //Arc<RwLock<Vec<MemLogEntry>>>,
+    pub watchpoint_log: Arc<SegQueue<WatchpointHit>>,
+    pub syscall_log: Arc<SegQueue<SyscallRecord>>,
     pub cpu_error: Option<unicorn::Error>,
+    /// Set by the hooks when they stop the emulator deliberately
+    pub halted: Arc<AtomicBool>,
+    pub fault_phase: Option<FaultPhase>,
     pub emulation_time: Duration,
     pub registers_at_last_ret: Arc<Mutex<HashMap<Register<C>, u64>>>,
     pub registers_to_read: Vec<Register<C>>,
+    /// Offsets from the stack pointer of words to be read along with the registers
+    pub stack_slots: Vec<u64>,
     pub input: HashMap<Register<C>, u64>,
 }
 
@@ -79,18 +100,27 @@ impl<C: Cpu<'static>> Default for Profiler<C> {
     fn default() -> Self {
         Self {
             ret_count: Arc::new(AtomicUsize::new(0)),
+            sigreturn_count: Arc::new(AtomicUsize::new(0)),
             call_stack_depth: Arc::new(AtomicUsize::new(0)),
+            call_targets: Arc::new(SegQueue::new()),
             write_log: Arc::new(SegQueue::new()), //Arc::new(RwLock::new(Vec::default())),
             input: HashMap::default(),
             registers_at_last_ret: Arc::new(Mutex::new(HashMap::default())),
             cpu_error: None,
+            halted: Arc::new(AtomicBool::new(false)),
+            fault_phase: None,
             registers_to_read: Vec::new(),
+            stack_slots: Vec::new(),
             emulation_time: Duration::default(),
             trace_log: Arc::new(SegQueue::new()),
             gadget_log: Arc::new(SegQueue::new()), //Arc::new(RwLock::new(Vec::new())),
+            register_snapshots: Arc::new(SegQueue::new()),
+            dirty_pages: Arc::new(SegQueue::new()),
             written_memory: vec![],
             committed_write_log: Default::default(),
-            committed_trace_log: Default::default(),
+            committed_blocks: Arc::new(AtomicUsize::new(0)),
+            watchpoint_log: Arc::new(SegQueue::new()),
+            syscall_log: Arc::new(SegQueue::new()),
         }
     }
 }
@@ -104,6 +134,12 @@ impl<C: Cpu<'static>> Profiler<C> {
         }
     }
 
+    /// Notes the pages spanned by a write of `len` bytes at `address` made
+    /// by the host, which the dirty-page hook doesn't see.
+    pub fn mark_dirty(&self, address: u64, len: usize) {
+        mark_dirty_pages(&self.dirty_pages, address, len)
+    }
+
     pub fn read_registers(&mut self, emu: &mut C) {
         let mut registers = self.registers_at_last_ret.lock().unwrap();
         for r in &self.registers_to_read {
@@ -119,7 +155,7 @@ impl<C: Cpu<'static>> Profiler<C> {
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Profile {
-    pub paths: Vec<Vec<Block>>,
+    pub paths: Vec<BlockPath>,
     pub code_executed: Vec<Vec<u8>>,
     //PrefixSet<Block>,
     // TODO: cpu_errors should be a vector of Option<usize>
@@ -132,16 +168,20 @@ pub struct Profile {
     pub memory_writes: Vec<SparseData>,
     pub executable: bool,
     pub ret_counts: Vec<usize>,
+    pub watchpoint_hits: Vec<Vec<WatchpointHit>>,
+    pub sigreturn_counts: Vec<usize>,
+    pub register_deltas: Vec<Vec<GadgetDelta>>,
+    pub fault_phases: Vec<Option<FaultPhase>>,
+    pub call_targets: Vec<Vec<u64>>,
+    pub syscalls: Vec<Vec<SyscallRecord>>,
 }
 
-fn fetch_code_executed(path: &Vec<Block>, extra_segs: Option<&[Seg]>) -> Vec<u8> {
+fn fetch_code_executed(path: &BlockPath, extra_segs: Option<&[Seg]>) -> Vec<u8> {
     let memory = get_static_memory_image();
-    let mut code = vec![];
+    let mut code = Vec::with_capacity(path.iter().map(|block| block.size).sum());
     for block in path.iter() {
         if let Some(bytes) = memory.try_dereference(block.entry, extra_segs) {
-            for b in bytes[0..block.size].iter() {
-                code.push(*b);
-            }
+            code.extend_from_slice(&bytes[0..block.size]);
         }
     }
     //crate::util::dump::zip(&code).expect("Failed to compress code executed")
@@ -162,27 +202,46 @@ impl<C: 'static + Cpu<'static>> From<Profiler<C>> for Profile {
         let mut memory_writes = Vec::new();
         let mut ret_counts = Vec::new();
         let mut code_paths_executed = Vec::new();
+        let mut watchpoint_hits = Vec::new();
+        let mut sigreturn_counts = Vec::new();
+        let mut register_deltas = Vec::new();
+        let mut fault_phases = Vec::new();
+        let mut call_targets = Vec::new();
+        let mut syscalls = Vec::new();
 
         let Profiler {
             trace_log,
             call_stack_depth,
+            call_targets: call_target_log,
             write_log,
             cpu_error,
+            halted,
+            fault_phase,
             emulation_time,
             registers_at_last_ret: registers,
             gadget_log,
+            register_snapshots,
+            dirty_pages: _dirty_pages,
             written_memory,
             ret_count,
+            sigreturn_count,
             committed_write_log,
-            committed_trace_log,
+            committed_blocks,
             registers_to_read,
+            stack_slots,
             input,
+            watchpoint_log,
+            syscall_log,
         } = p;
-        let path = Arc::try_unwrap(committed_trace_log)
-            .ok()
-            .unwrap()
-            .into_inner()
-            .unwrap();
+        // The logs are drained, their entries moved into the profile, rather
+        // than copied out of them.
+        let committed = committed_blocks.load(std::sync::atomic::Ordering::Relaxed);
+        let mut path = BlockPath::new();
+        while let Ok(b) = trace_log.pop() {
+            if path.len() < committed {
+                path.push(b)
+            }
+        }
         let code_executed = fetch_code_executed(&path, Some(&written_memory));
         paths.push(path);
         code_paths_executed.push(code_executed);
@@ -193,11 +252,22 @@ impl<C: 'static + Cpu<'static>> From<Profiler<C>> for Profile {
         }
         gadgets_executed.push(executed);
         cpu_errors.push(cpu_error);
+        fault_phases.push(fault_phase);
         computation_times.push(emulation_time);
-        register_maps.push(RegisterState::new::<C>(
-            &registers.lock().unwrap(),
-            Some(&written_memory),
+        // The hooks that shared the registers have been removed, so they can
+        // be moved out, rather than read under the lock.
+        let registers = Arc::try_unwrap(registers)
+            .map(|registers| registers.into_inner().unwrap())
+            .unwrap_or_else(|registers| registers.lock().unwrap().clone());
+        register_deltas.push(GadgetDelta::from_snapshots::<C>(
+            segqueue_to_vec(register_snapshots),
+            &registers,
         ));
+        let mut register_state = RegisterState::new::<C>(&registers, Some(&written_memory));
+        if !stack_slots.is_empty() {
+            register_state.add_stack_slots(&stack_slots, Some(&written_memory));
+        }
+        register_maps.push(register_state);
 
         let log = Arc::try_unwrap(committed_write_log)
             .ok()
@@ -208,12 +278,12 @@ impl<C: 'static + Cpu<'static>> From<Profiler<C>> for Profile {
 
         ret_counts.push(ret_count.load(std::sync::atomic::Ordering::Relaxed));
 
+        watchpoint_hits.push(segqueue_to_vec(watchpoint_log));
+        sigreturn_counts.push(sigreturn_count.load(std::sync::atomic::Ordering::Relaxed));
+        call_targets.push(segqueue_to_vec(call_target_log));
+        syscalls.push(segqueue_to_vec(syscall_log));
+
         if cfg!(debug_assertions) {
-            log::debug!(
-                "registers: {} strong, {} weak",
-                Arc::strong_count(&registers),
-                Arc::weak_count(&registers)
-            );
             log::debug!(
                 "gadget_log: {} strong, {} weak",
                 Arc::strong_count(&gadget_log),
@@ -241,12 +311,28 @@ impl<C: 'static + Cpu<'static>> From<Profiler<C>> for Profile {
             memory_writes,
             executable: true,
             ret_counts,
+            watchpoint_hits,
+            sigreturn_counts,
+            register_deltas,
+            fault_phases,
+            call_targets,
+            syscalls,
         }
     }
 }
 
+/// Pushes the address of each page spanned by a write of `len` bytes at
+/// `address`.
+pub fn mark_dirty_pages(dirty_pages: &SegQueue<u64>, address: u64, len: usize) {
+    let first = address >> loader::PAGE_BITS;
+    let last = (address + (len.max(1) as u64) - 1) >> loader::PAGE_BITS;
+    for page in first..=last {
+        dirty_pages.push(page << loader::PAGE_BITS)
+    }
+}
+
 fn segqueue_to_vec<T>(sq: Arc<SegQueue<T>>) -> Vec<T> {
-    let mut v = vec![];
+    let mut v = Vec::with_capacity(sq.len());
     while let Ok(x) = sq.pop() {
         v.push(x)
     }
@@ -268,6 +354,12 @@ impl Profile {
             memory_writes,
             executable,
             ret_counts,
+            watchpoint_hits,
+            sigreturn_counts,
+            register_deltas,
+            fault_phases,
+            call_targets,
+            syscalls,
         } = other;
 
         self.paths.extend(paths.into_iter());
@@ -278,22 +370,145 @@ impl Profile {
         self.gadgets_executed.extend(gadgets_executed.into_iter());
         self.memory_writes.extend(memory_writes.into_iter());
         self.ret_counts.extend(ret_counts.into_iter());
+        self.watchpoint_hits.extend(watchpoint_hits.into_iter());
+        self.sigreturn_counts.extend(sigreturn_counts.into_iter());
+        self.register_deltas.extend(register_deltas.into_iter());
+        self.fault_phases.extend(fault_phases.into_iter());
+        self.call_targets.extend(call_targets.into_iter());
+        self.syscalls.extend(syscalls.into_iter());
         self.executable &= executable;
     }
 
+    /// A handful of cheap summary statistics, by which a profile can be
+    /// screened before a creature is given a full evaluation. See
+    /// `configure::EscalationConfig`.
+    pub fn screening_metrics(&self) -> BTreeMap<&'static str, f64> {
+        let mut metrics = BTreeMap::new();
+        metrics.insert("ret_count", self.ret_counts.iter().sum::<usize>() as f64);
+        metrics.insert(
+            "gadgets_executed",
+            self.gadgets_executed.iter().map(|g| g.len()).sum::<usize>() as f64,
+        );
+        metrics.insert(
+            "blocks_executed",
+            self.paths.iter().map(|p| p.len()).sum::<usize>() as f64,
+        );
+        metrics.insert(
+            "crashed",
+            self.cpu_errors.iter().filter(|e| e.is_some()).count() as f64,
+        );
+        metrics.insert(
+            "sigreturn_count",
+            self.sigreturn_counts.iter().sum::<usize>() as f64,
+        );
+        metrics
+    }
+
+    /// Summarizes each execution in the profile -- the blocks executed, the
+    /// memory written and the watchpoints hit, and the final register state
+    /// -- in a form that symbolic execution tools can pick up from. The
+    /// `payloads` are matched to the runs by index.
+    pub fn path_export(&self, payloads: &[Vec<u64>]) -> PathExport {
+        let memory = get_static_memory_image();
+        let runs = self
+            .paths
+            .iter()
+            .enumerate()
+            .map(|(i, path)| RunExport {
+                payload: payloads.get(i).cloned().unwrap_or_default(),
+                blocks: path.iter().collect(),
+                memory_writes: self
+                    .memory_writes
+                    .get(i)
+                    .map(|w| {
+                        w.regions()
+                            .map(|(address, bytes)| WriteExport {
+                                address,
+                                bytes: hex::encode(bytes),
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                watchpoint_hits: self.watchpoint_hits.get(i).cloned().unwrap_or_default(),
+                final_registers: self
+                    .registers
+                    .get(i)
+                    .map(|r| {
+                        r.0.iter()
+                            .map(|(reg, chain)| (reg.clone(), chain[0]))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                cpu_error: self
+                    .cpu_errors
+                    .get(i)
+                    .and_then(|e| e.as_ref())
+                    .map(|e| format!("{:?}", e)),
+            })
+            .collect();
+        PathExport {
+            arch: format!("{:?}", memory.arch),
+            mode: format!("{:?}", memory.mode),
+            word_size: memory.word_size,
+            runs,
+        }
+    }
+
+    /// The number of runs that ended with a fault in the given phase.
+    pub fn fault_count(&self, phase: FaultPhase) -> usize {
+        self.fault_phases
+            .iter()
+            .filter(|p| **p == Some(phase))
+            .count()
+    }
+
     pub fn avg_emulation_micros(&self) -> f64 {
         self.emulation_times.iter().sum::<Duration>().as_micros() as f64
             / self.emulation_times.len() as f64
     }
 
-    pub fn execution_trace_iter(&self) -> impl Iterator<Item = &Vec<Block>> + '_ {
+    pub fn total_emulation_time(&self) -> Duration {
+        self.emulation_times.iter().sum()
+    }
+
+    /// The entry addresses of the blocks executed, run by run.
+    pub fn block_paths(&self) -> Vec<Vec<u64>> {
+        self.paths
+            .iter()
+            .map(|path| path.iter().map(|block| block.entry).collect())
+            .collect()
+    }
+
+    /// The screening metrics, along with the number of runs profiled and
+    /// their mean emulation time, for the champion bundles.
+    pub fn summary(&self) -> BTreeMap<String, f64> {
+        let mut summary = self
+            .screening_metrics()
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect::<BTreeMap<String, f64>>();
+        summary.insert("runs".to_string(), self.paths.len() as f64);
+        summary.insert(
+            "avg_emulation_micros".to_string(),
+            self.avg_emulation_micros(),
+        );
+        summary
+    }
+
+    pub fn execution_trace_iter(&self) -> impl Iterator<Item = &BlockPath> + '_ {
         self.paths.iter()
     }
 
     pub fn disas_paths(&self) -> impl Iterator<Item = String> + '_ {
+        // the blocks are disassembled on rayon's workers, out of the memory
+        // image of the caller's target
+        let target = loader::current_target();
         self.paths.iter().map(move |path| {
-            path.par_iter()
+            path.iter()
+                .collect::<Vec<Block>>()
+                .par_iter()
                 .map(|b| {
+                    loader::set_target(target);
                     let prefix = if self.times_executed(b.entry) > 0 {
                         "----\n"
                     } else {
@@ -333,6 +548,14 @@ impl Profile {
         }
     }
 
+    /// Counts the recorded hits on the `index`th configured watchpoint, over all runs.
+    pub fn watchpoint_hit_count(&self, index: usize) -> usize {
+        self.watchpoint_hits
+            .iter()
+            .map(|hits| hits.iter().filter(|h| h.watchpoint == index).count())
+            .sum()
+    }
+
     pub fn addresses_visited(&self) -> HashSet<u64> {
         let mut set = HashSet::new();
         for path in self.paths.iter() {
@@ -385,6 +608,134 @@ pub struct MemLogEntry {
     pub value: u64,
 }
 
+/// The registers changed by a gadget, mapped to their values before and
+/// after it ran.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct GadgetDelta {
+    pub gadget: u64,
+    pub changes: BTreeMap<String, (u64, u64)>,
+}
+
+impl GadgetDelta {
+    /// Takes the register snapshots made on entry to each gadget, and attributes
+    /// the difference between each snapshot and the next to the earlier gadget.
+    /// The last gadget is compared against the final register state.
+    pub fn from_snapshots<C: 'static + Cpu<'static>>(
+        snapshots: Vec<(u64, HashMap<Register<C>, u64>)>,
+        final_registers: &HashMap<Register<C>, u64>,
+    ) -> Vec<Self> {
+        (0..snapshots.len())
+            .map(|i| {
+                let (gadget, ref before) = snapshots[i];
+                let after = snapshots
+                    .get(i + 1)
+                    .map(|(_, s)| s)
+                    .unwrap_or(final_registers);
+                let changes = before
+                    .iter()
+                    .filter_map(|(reg, old)| {
+                        after
+                            .get(reg)
+                            .filter(|new| *new != old)
+                            .map(|new| (register_name::<C>(*reg).to_string(), (*old, *new)))
+                    })
+                    .collect::<BTreeMap<String, (u64, u64)>>();
+                Self { gadget, changes }
+            })
+            .collect()
+    }
+
+    pub fn changed(&self, register: &str) -> bool {
+        self.changes.contains_key(register)
+    }
+}
+
+/// A read or write that touched one of the configured watchpoints.
+/// `watchpoint` is the index of the watchpoint in `RoperConfig::watchpoints`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct WatchpointHit {
+    pub watchpoint: usize,
+    pub program_counter: u64,
+    pub address: u64,
+    pub size: usize,
+    pub value: u64,
+    pub write: bool,
+}
+
+/// A syscall made by a chain, with its number and arguments read from the
+/// registers named by the configured `SyscallAbi`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct SyscallRecord {
+    pub program_counter: u64,
+    pub number: u64,
+    pub args: Vec<u64>,
+}
+
+/// Where in the execution of a chain things went wrong.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub enum FaultPhase {
+    /// The chain handed control to an address that isn't executable, such
+    /// as a literal popped into the program counter by a `ret`.
+    Dispatch,
+    /// A fault occurred inside the body of a gadget.
+    Body,
+    /// The emulator ran out of steps or time without being stopped.
+    Timeout,
+}
+
+impl FaultPhase {
+    /// Classifies the end of an emulation, given whether `emu_start` returned
+    /// an error, whether the hooks stopped the emulator themselves, and the
+    /// final value of the program counter. Returning to 0 is how a healthy
+    /// chain ends, and isn't counted as a fault.
+    pub fn classify(errored: bool, halted: bool, pc: u64) -> Option<Self> {
+        if errored {
+            if pc == 0 {
+                None
+            } else if get_static_memory_image()
+                .perm_of_addr(pc)
+                .map(|p| p.intersects(Perms::EXEC))
+                .unwrap_or(false)
+            {
+                Some(Self::Body)
+            } else {
+                Some(Self::Dispatch)
+            }
+        } else if halted {
+            None
+        } else {
+            Some(Self::Timeout)
+        }
+    }
+}
+
+/// An executed path, exported for concolic follow-up. See
+/// `Profile::path_export`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PathExport {
+    pub arch: String,
+    pub mode: String,
+    pub word_size: usize,
+    pub runs: Vec<RunExport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RunExport {
+    pub payload: Vec<u64>,
+    pub blocks: Vec<Block>,
+    pub memory_writes: Vec<WriteExport>,
+    pub watchpoint_hits: Vec<WatchpointHit>,
+    pub final_registers: BTreeMap<String, u64>,
+    pub cpu_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WriteExport {
+    pub address: u64,
+    /// hex-encoded
+    pub bytes: String,
+}
+
 #[derive(Clone, Hash, Default)]
 pub struct SparseDataHelper(BTreeMap<u64, u8>);
 
@@ -513,6 +864,11 @@ impl SparseData {
     pub fn len(&self) -> usize {
         self.0.values().map(|buf| buf.len()).sum()
     }
+
+    /// Iterates over the contiguous regions written, by start address.
+    pub fn regions(&self) -> impl Iterator<Item = (u64, &[u8])> + '_ {
+        self.0.iter().map(|(addr, buf)| (*addr, buf.as_slice()))
+    }
 }
 
 impl fmt::Debug for SparseData {
@@ -576,4 +932,14 @@ mod test {
 
         println!("res = {:#x?}", res);
     }
+
+    #[test]
+    fn test_mark_dirty_pages() {
+        let dirty_pages = SegQueue::new();
+        mark_dirty_pages(&dirty_pages, 0x1ffe, 4);
+        mark_dirty_pages(&dirty_pages, 0x3000, 8);
+        mark_dirty_pages(&dirty_pages, 0x4fff, 1);
+        let pages = segqueue_to_vec(Arc::new(dirty_pages));
+        assert_eq!(pages, vec![0x1000, 0x2000, 0x3000, 0x4000]);
+    }
 }
@@ -1,12 +1,15 @@
+use std::any::TypeId;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::str::FromStr;
+use std::sync::{Once, RwLock};
 
 use hashbrown::HashMap;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use unicorn::Cpu;
 
+use crate::emulator::abi::{self, Abi};
 use crate::emulator::loader;
 use crate::emulator::loader::{get_static_memory_image, Seg};
 use crate::error::Error;
@@ -14,6 +17,7 @@ use crate::util;
 use crate::util::architecture::{write_integer, Endian};
 use crate::util::bitwise;
 use crate::util::bitwise::nybble;
+use crate::util::distance::Signature;
 use std::ops::Sub;
 
 /// Parse the register pattern config file
@@ -38,6 +42,36 @@ pub fn parse_register_patterns(data: &str) -> Result<Vec<RegisterPattern>, Error
 
 pub type Register<C> = <C as Cpu<'static>>::Reg;
 
+type RegisterNames = HashMap<(TypeId, i32), &'static str>;
+
+static INIT_REGISTER_NAMES: Once = Once::new();
+static mut REGISTER_NAMES: Option<RwLock<RegisterNames>> = None;
+
+fn register_names() -> &'static RwLock<RegisterNames> {
+    INIT_REGISTER_NAMES.call_once(|| unsafe { REGISTER_NAMES = Some(RwLock::new(HashMap::new())) });
+    unsafe {
+        REGISTER_NAMES
+            .as_ref()
+            .expect("The register names have not been initialized")
+    }
+}
+
+/// The name of a register, as its `Debug` form spells it, e.g. `EAX`, under
+/// which register states and patterns are keyed. Each name is formatted
+/// the first time it's asked for, and kept in a table, by the register's
+/// type and number, for the rest of the process's life, so that the
+/// registers read in each evaluation are named without being formatted.
+pub fn register_name<C: 'static + Cpu<'static>>(register: Register<C>) -> &'static str {
+    let key: (TypeId, i32) = (TypeId::of::<Register<C>>(), register.into());
+    if let Some(name) = register_names().read().expect("poisoned").get(&key) {
+        return *name;
+    }
+    let mut names = register_names().write().expect("poisoned");
+    *names
+        .entry(key)
+        .or_insert_with(|| Box::leak(format!("{:?}", register).into_boxed_str()))
+}
+
 // TODO:
 // A dereferenced value should only count as "close" to the target if:
 // - it contains the head or tail of the target (so that sliding it along may find the target)
@@ -137,11 +171,11 @@ impl FromStr for RegisterValue {
 // do a numerically thick match along the trunk, and a sparse search of the branches
 // register states are trees when spidered
 
-impl<C: Cpu<'static>> From<UnicornRegisterState<C>> for RegisterPattern {
+impl<C: 'static + Cpu<'static>> From<UnicornRegisterState<C>> for RegisterPattern {
     fn from(registers: UnicornRegisterState<C>) -> Self {
         let mut map = HashMap::new();
         for (k, v) in registers.0.into_iter() {
-            map.insert(format!("{:?}", k), v.into()); // FIXME use stable conversion method
+            map.insert(register_name::<C>(k).to_string(), v.into());
         }
         RegisterPattern(map)
     }
@@ -164,22 +198,44 @@ impl PartialEq for RegisterPattern {
 
 impl Eq for RegisterPattern {}
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+/// A register pattern as written, in a register pattern file, or inline in
+/// the config, as `[roper.register_pattern]`: each register named with its
+/// value, as a string in the grammar of `RegisterValue`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RegisterPatternConfig(pub HashMap<String, String>);
 
-impl From<&RegisterPatternConfig> for RegisterPattern {
-    fn from(rp: &RegisterPatternConfig) -> Self {
-        let mut map = HashMap::new();
-        for (k, v) in rp.0.iter() {
-            let mut value = v
-                .parse::<RegisterValue>()
-                .expect("Failed to parse RegisterValue");
+impl RegisterPatternConfig {
+    fn parse_values(&self) -> Result<HashMap<String, RegisterValue>, Error> {
+        self.0
+            .iter()
+            .map(|(k, v)| {
+                v.parse::<RegisterValue>()
+                    .map(|value| (k.to_string(), value))
+                    .map_err(|e| Error::Parsing(format!("Bad value {:?} for {}: {:?}", v, k, e)))
+            })
+            .collect()
+    }
+
+    /// Checks that the values can be parsed, without reducing their
+    /// references, which needs the binary to be loaded.
+    pub fn check(&self) -> Result<(), Error> {
+        self.parse_values().map(|_| ())
+    }
+
+    pub fn to_pattern(&self) -> Result<RegisterPattern, Error> {
+        let mut map = self.parse_values()?;
+        for value in map.values_mut() {
             log::debug!("Before reduction: {:#x?}", value);
             value.reduce_references();
             log::debug!("After reduction: {:#x?}", value);
-            map.insert(k.to_string(), value);
         }
-        Self(map)
+        Ok(RegisterPattern(map))
+    }
+}
+
+impl From<&RegisterPatternConfig> for RegisterPattern {
+    fn from(rp: &RegisterPatternConfig) -> Self {
+        rp.to_pattern().expect("Failed to parse RegisterValue")
     }
 }
 
@@ -337,6 +393,80 @@ impl RegisterPattern {
     pub fn features(&self) -> Vec<RegisterFeature> {
         RegisterFeature::decompose_reg_pattern(self)
     }
+
+    /// Replaces argument keys, like `arg0`, with the register or stack slot
+    /// where `abi` passes that argument. Other keys are left alone.
+    pub fn resolve_arguments(self, abi: Abi) -> Self {
+        Self(
+            self.0
+                .into_iter()
+                .map(|(k, v)| match abi::parse_arg_key(&k) {
+                    Some(n) => (abi.arg_location(n).key(), v),
+                    None => (k, v),
+                })
+                .collect(),
+        )
+    }
+
+    /// The stack pointer offsets of any stack slots named in the pattern.
+    pub fn stack_slots(&self) -> Vec<u64> {
+        self.0
+            .keys()
+            .filter_map(|k| abi::parse_stack_slot_key(k))
+            .collect()
+    }
+
+    /// The MinHash signature of the pattern's target words, taken once, and
+    /// compared with each register state by `similarity_to_register_state`.
+    pub fn signature(&self) -> Signature {
+        let words = self
+            .0
+            .iter()
+            .sorted_by_key(|p| p.0)
+            .map(|(_, r_val)| r_val.vals[0])
+            .collect::<Vec<u64>>();
+        word_signature(&words)
+    }
+
+    /// The estimated Jaccard similarity of the pattern, whose `signature` is
+    /// given, to the register state, comparing each register's target with
+    /// the word at the depth of its dereference chain where it's wanted.
+    pub fn similarity_to_register_state(
+        &self,
+        signature: &Signature,
+        register_state: &RegisterState,
+    ) -> f64 {
+        if self.0.is_empty() {
+            return 1.0;
+        }
+        let words = self
+            .0
+            .iter()
+            .sorted_by_key(|p| p.0)
+            .map(|(reg, r_val)| {
+                register_state
+                    .0
+                    .get(reg)
+                    .and_then(|vals| vals.get(r_val.deref))
+                    .cloned()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<u64>>();
+        signature.similarity(&word_signature(&words))
+    }
+}
+
+/// The number of hash functions in the signatures of register words
+const SIGNATURE_HASHES: u64 = 64;
+
+/// A byte's position is taken to be the word it's in, so that bytes agree
+/// only when they're found in the same register.
+fn word_signature(words: &[u64]) -> Signature {
+    let bytes = words
+        .iter()
+        .flat_map(|w| w.to_le_bytes().to_vec())
+        .collect::<Vec<u8>>();
+    Signature::of(&bytes, words.len(), SIGNATURE_HASHES)
 }
 
 // impl From<&RegisterPattern> for Vec<u8> {
@@ -434,6 +564,38 @@ impl RegisterState {
         Self(Self::spider::<C>(registers, extra_segs))
     }
 
+    /// Adds the words found at each of the given offsets from the stack
+    /// pointer, and their dereference chains, keyed as stack slots. The
+    /// stack pointer must be among the registers read. A slot that can't be
+    /// read is taken to hold 0.
+    pub fn add_stack_slots(&mut self, offsets: &[u64], extra_segs: Option<&[Seg]>) {
+        const MAX_SPIDER_STEPS: usize = 10;
+        let memory = match loader::try_to_get_static_memory_image() {
+            Some(m) => m,
+            None => return,
+        };
+        let sp = match abi::stack_pointer(memory.arch, memory.mode)
+            .ok()
+            .and_then(|sp| self.0.get(sp))
+        {
+            Some(chain) => chain[0],
+            None => {
+                log::error!("Stack pointer not read, so stack slots can't be checked");
+                return;
+            }
+        };
+        for offset in offsets {
+            let addr = sp.wrapping_add(*offset);
+            let mut path = memory.deref_chain(addr, MAX_SPIDER_STEPS + 1, extra_segs);
+            // the first link is the address of the slot itself
+            path.remove(0);
+            if path.is_empty() {
+                path.push(0);
+            }
+            self.0.insert(abi::ArgLocation::Stack(*offset).key(), path);
+        }
+    }
+
     fn spider<C: 'static + Cpu<'static>>(
         registers: &HashMap<Register<C>, u64>,
         extra_segs: Option<&[Seg]>,
@@ -443,13 +605,11 @@ impl RegisterState {
         if let Some(memory) = loader::try_to_get_static_memory_image() {
             for (k, v) in registers.iter() {
                 let path = memory.deref_chain(*v, MAX_SPIDER_STEPS, extra_segs);
-                let reg_name = format!("{:?}", k);
-                map.insert(reg_name, path);
+                map.insert(register_name::<C>(*k).to_string(), path);
             }
         } else {
             for (k, v) in registers.iter() {
-                let reg_name = format!("{:?}", k);
-                map.insert(reg_name, vec![*v]);
+                map.insert(register_name::<C>(*k).to_string(), vec![*v]);
             }
         }
         map
@@ -689,6 +849,37 @@ mod test {
         assert!(res < std::f64::EPSILON, "nonzero score on match");
     }
 
+    #[test]
+    fn test_register_pattern_similarity() {
+        let register_pattern = RegisterPattern(hashmap! {
+            "RAX".to_string() => RegisterValue {
+                vals: vec![0xbeef],
+                deref: 1,
+            },
+
+            "RBX".to_string() => RegisterValue {
+                vals: vec![3],
+                deref: 0,
+            },
+        });
+        let signature = register_pattern.signature();
+
+        let matching = RegisterState(hashmap! {
+            "RAX".to_string() => vec![0xdead, 0xbeef, 0],
+            "RBX".to_string() => vec![3],
+        });
+        let similarity = register_pattern.similarity_to_register_state(&signature, &matching);
+        assert_eq!(similarity, 1.0);
+
+        // the right words, but in the wrong registers
+        let swapped = RegisterState(hashmap! {
+            "RAX".to_string() => vec![0xdead, 3, 0],
+            "RBX".to_string() => vec![0xbeef],
+        });
+        let similarity = register_pattern.similarity_to_register_state(&signature, &swapped);
+        assert!(similarity < 1.0);
+    }
+
     #[test]
     fn test_register_features() {
         initialize_mem_image();
@@ -800,4 +991,36 @@ mod test {
         println!("res = {}", res);
         assert!(res - (1.0 + 3.0) < std::f64::EPSILON);
     }
+    #[test]
+    fn test_inline_register_pattern() {
+        let config: RoperConfig = toml::from_str(
+            "binary_path = \"/bin/sh\"\n\
+             [register_pattern]\nRAX = \"0x3b\"\nRDI = \"0\"\n",
+        )
+        .unwrap();
+        let pattern = config.register_pattern.as_ref().unwrap();
+        pattern.check().unwrap();
+        let pattern = pattern.to_pattern().unwrap();
+        assert_eq!(pattern.0["RAX"], RegisterValue::from(0x3b));
+        assert_eq!(pattern.0["RDI"], RegisterValue::from(0));
+
+        let bad = RegisterPatternConfig(hashmap! {
+            "RAX".to_string() => "0xzz".to_string(),
+        });
+        assert!(bad.check().is_err());
+    }
+
+    #[test]
+    fn test_register_name() {
+        type X86 = unicorn::CpuX86<'static>;
+        let eax: Register<X86> = "EAX".parse().ok().expect("no such register");
+        let rip: Register<X86> = "RIP".parse().ok().expect("no such register");
+        assert_eq!(register_name::<X86>(eax), "EAX");
+        assert_eq!(register_name::<X86>(rip), "RIP");
+        // the name is formatted once, and kept
+        assert!(std::ptr::eq(
+            register_name::<X86>(eax),
+            register_name::<X86>(eax)
+        ));
+    }
 }
@@ -0,0 +1,134 @@
+use hashbrown::HashMap;
+
+use crate::configure::RoperConfig;
+
+/// Genes carrying this tag in their upper 16 bits don't stand for addresses
+/// or literals. They expand, when the payload is packed, into a sigreturn
+/// frame built from the `sigreturn_frames` template whose index is held in
+/// the low bits.
+pub const FRAME_GENE_TAG: u64 = 0x5160_0000_0000_0000;
+const FRAME_GENE_MASK: u64 = 0xFFFF_0000_0000_0000;
+
+/// The layout of the x86_64 `rt_sigframe`, as seen from the stack pointer
+/// at the moment of the `rt_sigreturn` syscall. Empty names mark words that
+/// we don't treat as registers.
+static X86_64_FRAME_LAYOUT: [&str; 31] = [
+    "", // uc_flags
+    "", // uc_link
+    "", // uc_stack.ss_sp
+    "", // uc_stack.ss_flags
+    "", // uc_stack.ss_size
+    "R8", "R9", "R10", "R11", "R12", "R13", "R14", "R15", "RDI", "RSI", "RBP", "RBX", "RDX",
+    "RAX", "RCX", "RSP", "RIP", "EFLAGS",
+    "", // cs, gs, fs, __pad0
+    "", // err
+    "", // trapno
+    "", // oldmask
+    "", // cr2
+    "", // fpstate
+    "", // __reserved
+    "", // uc_sigmask
+];
+
+/// The index of the cs/gs/fs word in the x86_64 frame. The kernel refuses to
+/// return to a frame with a null code segment, so we fill in the usual
+/// user-mode selector.
+const X86_64_CSGSFS_INDEX: usize = 23;
+const X86_64_USER_CS: u64 = 0x33;
+
+pub fn frame_gene(index: usize) -> u64 {
+    FRAME_GENE_TAG | (index as u64 & !FRAME_GENE_MASK)
+}
+
+pub fn frame_index(word: u64) -> Option<usize> {
+    if word & FRAME_GENE_MASK == FRAME_GENE_TAG {
+        Some((word & !FRAME_GENE_MASK) as usize)
+    } else {
+        None
+    }
+}
+
+/// The layout of the sigreturn frame on the given architecture, or `None`
+/// if we don't know it. Only x86_64 frames are laid out, so far.
+pub fn frame_layout(arch: unicorn::Arch, mode: unicorn::Mode) -> Option<&'static [&'static str]> {
+    use unicorn::Arch::*;
+    use unicorn::Mode::*;
+    match (arch, mode) {
+        (X86, MODE_64) => Some(&X86_64_FRAME_LAYOUT),
+        _ => None,
+    }
+}
+
+/// Lays out a sigreturn frame, as a sequence of words, with the register
+/// values given in `registers`. Unspecified registers are zeroed. Returns
+/// `None` if there's no frame layout for the architecture.
+pub fn build_frame(
+    arch: unicorn::Arch,
+    mode: unicorn::Mode,
+    registers: &HashMap<String, u64>,
+) -> Option<Vec<u64>> {
+    let mut frame = frame_layout(arch, mode)?
+        .iter()
+        .map(|name| registers.get(*name).cloned().unwrap_or(0))
+        .collect::<Vec<u64>>();
+    if let (unicorn::Arch::X86, unicorn::Mode::MODE_64) = (arch, mode) {
+        frame[X86_64_CSGSFS_INDEX] = X86_64_USER_CS;
+    }
+    Some(frame)
+}
+
+/// Replaces each frame gene in the payload with the sigreturn frame it
+/// stands for. Frame genes that don't index any template are left as they
+/// are, and will be treated like any other word, as will every frame gene
+/// on an architecture whose frames we can't lay out.
+pub fn expand_frames(payload: Vec<u64>, config: &RoperConfig) -> Vec<u64> {
+    if config.sigreturn_frames.is_empty() || frame_layout(config.arch, config.mode).is_none() {
+        return payload;
+    }
+    let mut expanded = Vec::with_capacity(payload.len());
+    for word in payload.into_iter() {
+        match frame_index(word).and_then(|i| config.sigreturn_frames.get(i)) {
+            Some(template) => expanded.extend(
+                build_frame(config.arch, config.mode, template)
+                    .expect("The frame layout was checked above"),
+            ),
+            None => expanded.push(word),
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_expand_frames() {
+        let mut config = RoperConfig::default();
+        let mut template = HashMap::new();
+        template.insert("RIP".to_string(), 0xdead_beef);
+        template.insert("RAX".to_string(), 59);
+        config.sigreturn_frames.push(template);
+
+        let payload = vec![0x1000, frame_gene(0), 0x2000];
+        let expanded = expand_frames(payload, &config);
+        assert_eq!(expanded.len(), 2 + X86_64_FRAME_LAYOUT.len());
+        assert_eq!(expanded[0], 0x1000);
+        assert_eq!(expanded[1 + 18], 59);
+        assert_eq!(expanded[1 + 21], 0xdead_beef);
+        assert_eq!(expanded[1 + X86_64_CSGSFS_INDEX], X86_64_USER_CS);
+        assert_eq!(*expanded.last().unwrap(), 0x2000);
+
+        // an out of range frame gene is just a word
+        assert_eq!(expand_frames(vec![frame_gene(7)], &config), vec![frame_gene(7)]);
+
+        // as is every frame gene, where there's no frame layout
+        config.arch = unicorn::Arch::ARM;
+        config.mode = unicorn::Mode::LITTLE_ENDIAN;
+        assert!(frame_layout(config.arch, config.mode).is_none());
+        assert_eq!(
+            expand_frames(vec![frame_gene(0)], &config),
+            vec![frame_gene(0)]
+        );
+    }
+}
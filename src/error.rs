@@ -56,6 +56,19 @@ impl From<unicorn::Error> for Error {
     }
 }
 
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Self {
+        Self::Parsing(e.to_string())
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<parquet::errors::ParquetError> for Error {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        Self::Misc(e.to_string())
+    }
+}
+
 impl From<toml::de::Error> for Error {
     fn from(e: toml::de::Error) -> Self {
         Self::Parsing(e.to_string())
@@ -0,0 +1,351 @@
+//! Typed events in the course of a run, and the hooks that respond to them.
+//! When berbalang is used as a library, callbacks can be registered for
+//! each kind of event with `register`. When it's run as a binary, shell
+//! commands can be attached to them in the `[hooks]` section of the config,
+//!
+//! ```text
+//! [[hooks.commands]]
+//! on = "NewChampion"
+//! command = "notify-send berbalang \"$BERBALANG_EVENT\""
+//! ```
+//!
+//! which are run with the event, as JSON, in the `BERBALANG_EVENT`
+//! environment variable. Webhooks can be attached to them, too, to be sent
+//! notifications of long, unattended runs,
+//!
+//! ```text
+//! [[hooks.webhooks]]
+//! on = "RunFinished"
+//! url = "https://hooks.slack.com/services/..."
+//! format = "Slack"
+//! ```
+//!
+//! which are posted the event, as JSON, by `curl`, or, in the "Slack"
+//! format, a message describing it.
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Once, RwLock};
+use std::thread::spawn;
+
+use serde::{Deserialize, Serialize};
+
+use crate::configure::{Config, WebhookFormat};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    EpochEnd,
+    NewChampion,
+    Migration,
+    Stagnation,
+    TargetReached,
+    RunFinished,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+#[serde(tag = "event")]
+pub enum Event {
+    /// An island's epoch has ended
+    EpochEnd {
+        island: usize,
+        epoch: usize,
+        counter: usize,
+        best_fitness: Option<f64>,
+    },
+    /// An island has a new champion
+    NewChampion {
+        island: usize,
+        epoch: usize,
+        name: String,
+        fitness: Option<f64>,
+    },
+    /// A specimen has left the island, or arrived at it, by way of the pier
+    Migration {
+        island: usize,
+        name: String,
+        arrived: bool,
+    },
+    /// An island's champion hasn't improved for `stagnant_epochs` epochs.
+    /// This is emitted every `hooks.stagnation_epochs` epochs, for as long
+    /// as it lasts.
+    Stagnation {
+        island: usize,
+        epoch: usize,
+        stagnant_epochs: usize,
+    },
+    /// An island's champion has reached the fitness target
+    TargetReached {
+        island: usize,
+        epoch: usize,
+        name: String,
+        fitness: Option<f64>,
+    },
+    /// An island has finished its run, for the given reason
+    RunFinished {
+        island: usize,
+        epoch: usize,
+        counter: usize,
+        outcome: String,
+        best_fitness: Option<f64>,
+    },
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::EpochEnd { .. } => EventKind::EpochEnd,
+            Event::NewChampion { .. } => EventKind::NewChampion,
+            Event::Migration { .. } => EventKind::Migration,
+            Event::Stagnation { .. } => EventKind::Stagnation,
+            Event::TargetReached { .. } => EventKind::TargetReached,
+            Event::RunFinished { .. } => EventKind::RunFinished,
+        }
+    }
+
+    /// A one-line description of the event, for chat notifications.
+    pub fn describe(&self, population_name: &str) -> String {
+        let fmt = |f: &Option<f64>| f.map(|f| f.to_string()).unwrap_or_else(|| "-".to_string());
+        let what = match self {
+            Event::EpochEnd {
+                epoch,
+                best_fitness,
+                ..
+            } => format!(
+                "finished epoch {}, best fitness {}",
+                epoch,
+                fmt(best_fitness)
+            ),
+            Event::NewChampion {
+                epoch,
+                name,
+                fitness,
+                ..
+            } => format!(
+                "has a new champion, {}, with fitness {}, in epoch {}",
+                name,
+                fmt(fitness),
+                epoch
+            ),
+            Event::Migration { name, arrived, .. } => {
+                if *arrived {
+                    format!("welcomed {}", name)
+                } else {
+                    format!("sent {} abroad", name)
+                }
+            }
+            Event::Stagnation {
+                stagnant_epochs, ..
+            } => format!("has stagnated for {} epochs", stagnant_epochs),
+            Event::TargetReached {
+                epoch,
+                name,
+                fitness,
+                ..
+            } => format!(
+                "reached the target in epoch {}, with {}, of fitness {}",
+                epoch,
+                name,
+                fmt(fitness)
+            ),
+            Event::RunFinished {
+                epoch,
+                outcome,
+                best_fitness,
+                ..
+            } => format!(
+                "finished in epoch {} ({}), with best fitness {}",
+                epoch,
+                outcome,
+                fmt(best_fitness)
+            ),
+        };
+        format!("{}, island {}: {}", population_name, self.island(), what)
+    }
+
+    pub fn island(&self) -> usize {
+        match self {
+            Event::EpochEnd { island, .. }
+            | Event::NewChampion { island, .. }
+            | Event::Migration { island, .. }
+            | Event::Stagnation { island, .. }
+            | Event::TargetReached { island, .. }
+            | Event::RunFinished { island, .. } => *island,
+        }
+    }
+}
+
+pub type Callback = Arc<dyn Fn(&Event) + Send + Sync + 'static>;
+
+static INIT_CALLBACKS: Once = Once::new();
+static mut CALLBACKS: Option<RwLock<Vec<(EventKind, Callback)>>> = None;
+
+fn callbacks() -> &'static RwLock<Vec<(EventKind, Callback)>> {
+    INIT_CALLBACKS.call_once(|| unsafe { CALLBACKS = Some(RwLock::new(Vec::new())) });
+    unsafe {
+        CALLBACKS
+            .as_ref()
+            .expect("The event callbacks have not been initialized")
+    }
+}
+
+/// Registers a callback for every event of the given kind, on any island,
+/// for the rest of the process's life. Callbacks are called on the thread
+/// that emits the event -- usually an observer's -- so they should be quick.
+pub fn register<F>(kind: EventKind, callback: F)
+where
+    F: Fn(&Event) + Send + Sync + 'static,
+{
+    callbacks()
+        .write()
+        .expect("poisoned")
+        .push((kind, Arc::new(callback)));
+}
+
+/// Posts the payload, as JSON, to the URL, with `curl`.
+fn post(url: &str, payload: &str) {
+    let child = Command::new("curl")
+        .args(&["--silent", "--show-error", "--max-time", "30"])
+        .args(&["--header", "Content-Type: application/json"])
+        .args(&["--data-binary", "@-", url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn();
+    let res = child.and_then(|mut child| {
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(payload.as_bytes())?;
+        }
+        child.wait()
+    });
+    match res {
+        Ok(status) if !status.success() => {
+            log::warn!("Webhook {} failed: curl exited with {}", url, status)
+        }
+        Err(e) => log::error!("Failed to post to webhook {}: {:?}", url, e),
+        _ => {}
+    }
+}
+
+/// Calls the callbacks registered for the event, and starts any commands
+/// and webhooks configured for it, without waiting for them to finish.
+pub fn emit(event: Event, config: &Config) {
+    let kind = event.kind();
+    log::debug!("Event: {:?}", event);
+    for (k, callback) in callbacks().read().expect("poisoned").iter() {
+        if *k == kind {
+            callback(&event)
+        }
+    }
+    let commands = config
+        .hooks
+        .commands
+        .iter()
+        .filter(|c| c.on == kind)
+        .collect::<Vec<_>>();
+    let webhooks = config
+        .hooks
+        .webhooks
+        .iter()
+        .filter(|w| w.on == kind)
+        .collect::<Vec<_>>();
+    if commands.is_empty() && webhooks.is_empty() {
+        return;
+    }
+    let json = match serde_json::to_string(&event) {
+        Ok(json) => json,
+        Err(e) => {
+            log::error!("Failed to serialize {:?}: {:?}", event, e);
+            return;
+        }
+    };
+    for hook in commands {
+        let command = hook.command.clone();
+        let json = json.clone();
+        spawn(move || {
+            let res = Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .env("BERBALANG_EVENT", json)
+                .status();
+            match res {
+                Ok(status) if !status.success() => {
+                    log::warn!("Hook `{}` exited with {}", command, status)
+                }
+                Err(e) => log::error!("Failed to run hook `{}`: {:?}", command, e),
+                _ => {}
+            }
+        });
+    }
+    for hook in webhooks {
+        let payload = match hook.format {
+            WebhookFormat::Json => json.clone(),
+            WebhookFormat::Slack => serde_json::json!({
+                "text": event.describe(&config.observer.population_name)
+            })
+            .to_string(),
+        };
+        let url = hook.url.clone();
+        spawn(move || post(&url, &payload));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_callbacks() {
+        static STAGNATIONS: AtomicUsize = AtomicUsize::new(0);
+        register(EventKind::Stagnation, |event| {
+            if let Event::Stagnation {
+                stagnant_epochs, ..
+            } = event
+            {
+                STAGNATIONS.fetch_add(*stagnant_epochs, Ordering::SeqCst);
+            }
+        });
+        let config = Config::default();
+        emit(
+            Event::Stagnation {
+                island: 0,
+                epoch: 30,
+                stagnant_epochs: 20,
+            },
+            &config,
+        );
+        emit(
+            Event::EpochEnd {
+                island: 0,
+                epoch: 30,
+                counter: 3000,
+                best_fitness: None,
+            },
+            &config,
+        );
+        assert_eq!(STAGNATIONS.load(Ordering::SeqCst), 20);
+
+        let json = serde_json::to_string(&Event::Migration {
+            island: 1,
+            name: "mo".to_string(),
+            arrived: true,
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            r#"{"event":"Migration","island":1,"name":"mo","arrived":true}"#
+        );
+
+        let finished = Event::RunFinished {
+            island: 2,
+            epoch: 100,
+            counter: 10000,
+            outcome: "epoch limit reached".to_string(),
+            best_fitness: Some(0.5),
+        };
+        assert_eq!(finished.kind(), EventKind::RunFinished);
+        assert_eq!(
+            finished.describe("pop"),
+            "pop, island 2: finished in epoch 100 (epoch limit reached), with best fitness 0.5"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Storage for a chromosome's genes, shared between its copies. A specimen
+//! is cloned whenever it's sent to the observer, dumped, migrates, or is
+//! bred asexually, and most of those copies are never changed, so cloning
+//! `Genes` copies a pointer to the genes, rather than the genes themselves,
+//! which are copied only when a copy that shares them is written to, as by
+//! mutation.
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Genes<A>(Arc<Vec<A>>);
+
+impl<A> Genes<A> {
+    /// Whether the genes are stored in the same place as the other's, as
+    /// they are when one is a clone of the other, and neither has been
+    /// written to since.
+    pub fn shares_storage_with(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<A> Default for Genes<A> {
+    fn default() -> Self {
+        Genes(Arc::new(Vec::new()))
+    }
+}
+
+impl<A> From<Vec<A>> for Genes<A> {
+    fn from(genes: Vec<A>) -> Self {
+        Genes(Arc::new(genes))
+    }
+}
+
+impl<A> FromIterator<A> for Genes<A> {
+    fn from_iter<I: IntoIterator<Item = A>>(iter: I) -> Self {
+        Genes(Arc::new(iter.into_iter().collect()))
+    }
+}
+
+impl<A> Deref for Genes<A> {
+    type Target = [A];
+
+    fn deref(&self) -> &[A] {
+        &self.0
+    }
+}
+
+impl<A: Clone> DerefMut for Genes<A> {
+    /// Copies the genes first, if they're shared.
+    fn deref_mut(&mut self) -> &mut [A] {
+        Arc::make_mut(&mut self.0)
+    }
+}
+
+/// Hashed as the slice of genes is, so that seeds taken from a chromosome's
+/// hash are as they were when the genes were kept in a `Vec`.
+impl<A: Hash> Hash for Genes<A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_slice().hash(state)
+    }
+}
+
+impl<A: fmt::Debug> fmt::Debug for Genes<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.as_slice().fmt(f)
+    }
+}
+
+impl<A: Serialize> Serialize for Genes<A> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.as_slice().serialize(serializer)
+    }
+}
+
+impl<'de, A: Deserialize<'de>> Deserialize<'de> for Genes<A> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Genes::from)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::hash_map::DefaultHasher;
+
+    use super::*;
+
+    fn hash_of<T: Hash>(x: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        x.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let genes: Genes<u64> = vec![1, 2, 3].into();
+        let mut copy = genes.clone();
+        assert!(copy.shares_storage_with(&genes));
+        copy[1] = 20;
+        assert!(!copy.shares_storage_with(&genes));
+        assert_eq!(&genes[..], &[1, 2, 3]);
+        assert_eq!(&copy[..], &[1, 20, 3]);
+
+        assert_eq!(hash_of(&genes), hash_of(&vec![1_u64, 2, 3]));
+        assert_eq!(
+            serde_json::to_string(&copy).unwrap(),
+            serde_json::to_string(&vec![1, 20, 3]).unwrap()
+        );
+        let read: Genes<u64> = serde_json::from_str("[1,20,3]").unwrap();
+        assert_eq!(&read[..], &copy[..]);
+    }
+}
@@ -12,6 +12,7 @@ use std::sync::Arc;
 use rand::Rng;
 
 use crate::configure::Config;
+use crate::control;
 use crate::evolution::population::pier::Pier;
 use crate::evolution::population::shuffling_heap::ShufflingHeap;
 use crate::evolution::{Genome, Phenome};
@@ -76,13 +77,14 @@ impl<Q: Hash + Debug, E: Develop<P>, P: Phenome<Problem = Q> + Genome + 'static>
         let Self {
             mut population,
             mut problems,
-            config,
+            mut config,
             best,
             iteration,
             mut observer,
             womb,
             pier,
         } = self;
+        control::apply_shared(&mut config);
 
         let mut rng = hash_seed_rng(&(iteration as u64 ^ config.random_seed));
 
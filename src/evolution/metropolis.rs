@@ -3,6 +3,7 @@ use std::sync::atomic::Ordering;
 use rand::Rng;
 
 use crate::configure::Config;
+use crate::control;
 use crate::evolution::{Genome, Phenome};
 use crate::observer::Observer;
 use crate::ontogenesis::Develop;
@@ -35,13 +36,14 @@ impl<E: Develop<P>, P: Phenome + Genome + 'static> Metropolis<E, P> {
     pub fn evolve(self) -> Self {
         let Self {
             specimen,
-            config,
+            mut config,
             iteration,
             observer,
             evaluator,
             best,
         } = self;
 
+        control::apply(&mut config);
         EPOCH_COUNTER.fetch_add(1, Ordering::Relaxed);
 
         let mut specimen = if specimen.fitness().is_none() {
@@ -64,7 +66,8 @@ impl<E: Develop<P>, P: Phenome + Genome + 'static> Metropolis<E, P> {
         // if the variation is fitter, replace.
         // otherwise, let there be a chance of replacement inversely proportionate to the
         // difference in fitness.
-        if delta < 0.0 || ((-delta).exp()) < rng.gen_range(0.0, 1.0) {
+        let temperature = config.temperature.unwrap_or(1.0);
+        if delta < 0.0 || ((-delta / temperature).exp()) < rng.gen_range(0.0, 1.0) {
             //if delta < 0.0 { // pure hillclimbing
             specimen = variation;
             log::info!(
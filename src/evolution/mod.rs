@@ -1,18 +1,22 @@
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fmt::Debug;
 use std::hash::Hash;
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::configure::Config;
 use crate::fitness::FitnessScore;
 use crate::util;
 use crate::util::count_min_sketch::Sketch;
 use crate::util::levy_flight::levy_decision;
-use crate::util::random::{hash_seed_rng, Prng};
+use crate::util::random::{hash_seed_rng, thread_rng, Prng};
 
+pub use genes::Genes;
+
+pub mod genes;
 //pub mod lexicase;
 pub mod metropolis;
 pub mod pareto_roulette;
@@ -28,7 +32,7 @@ pub trait Mutation {
     where
         Self: Sized,
     {
-        let mut rng = rand::thread_rng();
+        let mut rng = thread_rng();
         let len = chromosome.len();
         (0..len)
             .map(|i| {
@@ -43,15 +47,17 @@ pub trait Mutation {
 }
 
 //@formatter:off
-#[derive(Clone, Hash, Serialize)]
+#[derive(Clone, Hash, Serialize, Deserialize)]
+// the alleles and mutations are already bound to be `DeserializeOwned`
+#[serde(bound(deserialize = ""))]
 //@formatter:on
 pub struct LinearChromosome<
     A: Debug + Clone + Hash + Serialize + DeserializeOwned + Sized,
     M: Debug + Clone + Hash + Serialize + DeserializeOwned + Mutation<Allele = A> + Sized,
 > {
-    pub chromosome: Vec<A>,
-    pub mutations: Vec<Option<M>>,
-    pub parentage: Vec<usize>,
+    pub chromosome: Genes<A>,
+    pub mutations: Genes<Option<M>>,
+    pub parentage: Genes<usize>,
     pub parent_names: Vec<String>,
     pub name: String,
     pub generation: usize,
@@ -68,6 +74,25 @@ impl<
         self.chromosome.len()
     }
 
+    /// The names of the operators that produced this chromosome: how it
+    /// was bred, followed by each point mutation it has undergone.
+    pub fn operators(&self) -> Vec<String> {
+        let breeding = match self.parent_names.len() {
+            0 => None,
+            1 => Some("clone"),
+            _ => Some("crossover"),
+        };
+        breeding
+            .into_iter()
+            .map(str::to_string)
+            .chain(
+                self.mutations
+                    .iter()
+                    .filter_map(|m| m.as_ref().map(|m| format!("{:?}", m))),
+            )
+            .collect()
+    }
+
     pub fn crossover(parents: &[&Self], config: &Config) -> Self {
         let min_mate_len = parents.iter().map(|p| p.len()).min().unwrap();
         let lambda = min_mate_len as f64 / config.crossover_period;
@@ -90,7 +115,7 @@ impl<
     pub fn cloned_offspring(&self) -> Self {
         let mut offspring = self.clone();
         offspring.generation += 1;
-        offspring.parentage = vec![0; offspring.chromosome.len()];
+        offspring.parentage = vec![0; offspring.chromosome.len()].into();
         offspring.parent_names = vec![self.name.clone()];
         offspring.name = util::name::random(4, &self);
         offspring
@@ -136,9 +161,9 @@ impl<
         let len = chromosome.len();
         let generation = father.generation.max(mother.generation) + 1;
         Self {
-            chromosome,
-            mutations: vec![None; len],
-            parentage,
+            chromosome: chromosome.into(),
+            mutations: vec![None; len].into(),
+            parentage: parentage.into(),
             parent_names: parents
                 .iter()
                 .map(|p| p.name.clone())
@@ -196,9 +221,9 @@ impl<
         let name = util::name::random(4, &chromosome);
 
         Self {
-            chromosome,
-            parentage,
-            mutations: vec![None; len],
+            chromosome: chromosome.into(),
+            parentage: parentage.into(),
+            mutations: vec![None; len].into(),
             parent_names: parents
                 .iter()
                 .map(|p| p.name.clone())
@@ -211,7 +236,7 @@ impl<
     pub fn mutate(&mut self, config: &Config) {
         // maybe check a uniform mutation rate to see if any pointwise mutations happen at all.
         let mutations = M::mutate(&mut self.chromosome, config);
-        self.mutations = mutations;
+        self.mutations = mutations.into();
     }
 }
 
@@ -420,4 +445,55 @@ pub trait Phenome: Clone + Debug + Send + Serialize + Hash {
     fn mature(&self) -> bool {
         unimplemented!("implement as needed")
     }
+
+    /// An annotated, human-readable rendering of the phenotype's memory
+    /// layout, if that makes sense for the phenotype in question. This is
+    /// dumped alongside each new champion.
+    fn annotated_layout(&self, _config: &Config) -> Option<String> {
+        None
+    }
+
+    /// The executed path, as JSON, for concolic follow-up with tools like
+    /// angr, if the phenotype was executed in the emulator. This is dumped
+    /// alongside each new champion.
+    fn path_export(&self) -> Option<String> {
+        None
+    }
+
+    /// The program with its introns stripped out, if the genotype is a
+    /// program that can be analysed that way. This is dumped alongside
+    /// each new champion.
+    fn effective_code(&self) -> Option<String> {
+        None
+    }
+
+    /// The program lowered to a Rust closure, if the genotype is a program
+    /// that can be lowered. This is dumped alongside each new champion.
+    fn transpiled_code(&self, _config: &Config) -> Option<String> {
+        None
+    }
+
+    /// Summary statistics of the phenotype's execution profile, if it has
+    /// one. These are included in the champion bundles.
+    fn profile_summary(&self) -> Option<BTreeMap<String, f64>> {
+        None
+    }
+
+    /// The entry addresses of the blocks executed on each of the
+    /// phenotype's runs, if it was executed in the emulator, for measuring
+    /// the phenotypic diversity of the population.
+    fn execution_paths(&self) -> Vec<Vec<u64>> {
+        Vec::new()
+    }
+
+    /// The names of the phenotype's parents, if it was bred from others.
+    fn parents(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The names of the variation operators that produced the phenotype,
+    /// one entry per application, for the operator statistics.
+    fn operators(&self) -> Vec<String> {
+        Vec::new()
+    }
 }
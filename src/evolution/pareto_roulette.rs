@@ -7,6 +7,7 @@ use rand_distr::Distribution;
 use non_dominated_sort::{non_dominated_sort, DominanceOrd};
 
 use crate::configure::Config;
+use crate::control;
 use crate::evolution::{Genome, Phenome};
 use crate::increment_epoch_counter;
 use crate::observer::Observer;
@@ -43,10 +44,11 @@ impl<E: Develop<P>, P: Phenome + Genome + 'static, D: DominanceOrd<P>> Roulette<
             population,
             observer,
             mut evaluator,
-            config,
+            mut config,
             iteration,
             dominance_order,
         } = self;
+        control::apply_shared(&mut config);
 
         let mut rng = hash_seed_rng(&population);
         // measure and assign fitness scores to entire population
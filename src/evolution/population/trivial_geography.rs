@@ -45,6 +45,11 @@ impl<P: Hash> TrivialGeography<P> {
         self.deme.len() - self.vacancies.len()
     }
 
+    /// The specimens of the population, in their places in the deme.
+    pub fn iter(&self) -> impl Iterator<Item = &P> {
+        self.deme.iter().filter_map(Option::as_ref)
+    }
+
     pub fn extract(&mut self, index: usize) -> Option<P> {
         // let's try to handle empty cells gracefully
         let len = self.deme.len();
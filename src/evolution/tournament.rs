@@ -1,17 +1,25 @@
 use std::cmp::{Ordering, PartialOrd};
 use std::iter;
 use std::sync::Arc;
+use std::time::Duration;
 
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
+use serde::de::DeserializeOwned;
 
+use crate::checkpoint::Checkpoint;
 use crate::configure::Config;
+use crate::control;
+use crate::events::{self, Event};
 use crate::evolution::population::pier::Pier;
 use crate::evolution::population::trivial_geography::TrivialGeography;
 use crate::evolution::{Genome, Phenome};
 use crate::observer::Observer;
 use crate::ontogenesis::Develop;
-use crate::util::random::hash_seed_rng;
+use crate::util::random::{derive_seed, hash_seed_rng, Prng, MIGRATION_STREAM};
+
+/// How long an idle island waits between offers of emigrants.
+const IDLE_PAUSE: Duration = Duration::from_millis(500);
 
 pub struct Tournament<E: Develop<P>, P: Phenome + 'static> {
     pub population: TrivialGeography<P>,
@@ -20,6 +28,11 @@ pub struct Tournament<E: Develop<P>, P: Phenome + 'static> {
     pub observer: Observer<P>,
     pub evaluator: E,
     pub pier: Arc<Pier<P>>,
+    /// Decides when to migrate, apart from the generator used in selection,
+    /// so that migration doesn't shift the island's other random draws
+    pub migration_rng: Prng,
+    /// The epoch of the island's last checkpoint. See `checkpoint`.
+    pub last_checkpoint: usize,
 }
 
 impl<E: Develop<P>, P: Phenome + Genome + 'static> Tournament<E, P> {
@@ -27,26 +40,92 @@ impl<E: Develop<P>, P: Phenome + Genome + 'static> Tournament<E, P> {
     where
         Self: Sized,
     {
-        let config = config.clone();
         log::debug!("Initializing population");
-        let mut population: TrivialGeography<P> = (0..config.pop_size)
+        let population: TrivialGeography<P> = (0..config.pop_size)
             .into_par_iter()
             .map(|i| {
                 log::debug!("creating phenome {}/{}", i, config.pop_size);
-                P::random(&config, i)
+                P::random(config, i)
             })
             .collect();
-        population.set_radius(config.tournament.geographic_radius);
         log::debug!("population initialized");
+        Self::with_population(config, observer, evaluator, pier, population)
+    }
+
+    fn with_population(
+        config: &Config,
+        observer: Observer<P>,
+        evaluator: E,
+        pier: Arc<Pier<P>>,
+        mut population: TrivialGeography<P>,
+    ) -> Self {
+        let config = config.clone();
+        population.set_radius(config.tournament.geographic_radius);
+        let migration_rng = Prng::seed_from_u64(derive_seed(
+            config.random_seed,
+            config.island_id,
+            MIGRATION_STREAM,
+        ));
 
         Self {
             population,
-            config,
             iteration: 0,
             observer,
             evaluator,
             pier,
+            migration_rng,
+            last_checkpoint: config.observer.resumed_epoch,
+            config,
+        }
+    }
+
+    /// Writes a checkpoint of the island, once `observer.checkpoint_every`
+    /// epochs have passed since its last. See `checkpoint`.
+    pub fn checkpoint(&mut self) {
+        let every = self.config.observer.checkpoint_every;
+        let epoch = self.observer.epoch();
+        if every == 0 || epoch < self.last_checkpoint + every {
+            return;
+        }
+        self.last_checkpoint = epoch;
+        let checkpoint = Checkpoint {
+            island: self.config.island_id,
+            epoch,
+            iteration: self.iteration,
+            soup: self.config.roper.soup.clone(),
+            population: self.population.iter().collect::<Vec<&P>>(),
+        };
+        match checkpoint.write(&self.config) {
+            Ok(()) => log::info!(
+                "Island {} checkpointed at epoch {}",
+                self.config.island_id,
+                epoch
+            ),
+            Err(e) => log::error!("Failed to write a checkpoint: {:?}", e),
+        }
+    }
+
+    /// Offers a specimen of the population to the other islands, as an
+    /// emigrant, if the pier has room, in place of evolving, once the
+    /// island has spent its budget. See `BudgetPolicy::Idle`.
+    pub fn idle(&mut self) {
+        let specimen = self
+            .population
+            .choose_combatants(1, &mut self.migration_rng)
+            .pop();
+        if let Some(specimen) = specimen {
+            if self.pier.embark(specimen.clone()).is_ok() {
+                log::debug!(
+                    "Idle island {} offered {} to the pier",
+                    self.config.island_id,
+                    specimen.name()
+                );
+            }
+            self.population
+                .insert(specimen)
+                .expect("Failed to return the specimen to the population");
         }
+        std::thread::sleep(IDLE_PAUSE);
     }
 
     pub fn evolve(self) -> Self {
@@ -55,10 +134,13 @@ impl<E: Develop<P>, P: Phenome + Genome + 'static> Tournament<E, P> {
             mut population,
             observer,
             mut evaluator,
-            config,
+            mut config,
             iteration,
             pier,
+            mut migration_rng,
+            last_checkpoint,
         } = self;
+        control::apply(&mut config);
         log::debug!(
             "population size in island {}: {}",
             config.island_id,
@@ -97,14 +179,23 @@ impl<E: Develop<P>, P: Phenome + Genome + 'static> Tournament<E, P> {
         // the number of parents plus the number of children
         if survivors.len() > config.tournament.num_parents {
             let mut migrated = false;
-            if rng.gen_range(0.0, 1.0) < config.tournament.migration_rate {
+            if migration_rng.gen_range(0.0, 1.0) < config.tournament.migration_rate {
                 log::debug!("Attempting migration...");
                 let emigrant = survivors.pop().unwrap();
+                let name = emigrant.name().to_string();
                 if let Err(emigrant) = pier.embark(emigrant) {
                     log::debug!("Pier full, returning emigrant to population");
                     survivors.push(emigrant);
                 } else {
                     migrated = true;
+                    events::emit(
+                        Event::Migration {
+                            island: config.island_id,
+                            name,
+                            arrived: false,
+                        },
+                        &config,
+                    );
                 }
             }
             if !migrated {
@@ -114,6 +205,14 @@ impl<E: Develop<P>, P: Phenome + Genome + 'static> Tournament<E, P> {
                         immigrant.name(),
                         config.island_id
                     );
+                    events::emit(
+                        Event::Migration {
+                            island: config.island_id,
+                            name: immigrant.name().to_string(),
+                            arrived: true,
+                        },
+                        &config,
+                    );
                     survivors.push(immigrant);
                 }
             }
@@ -150,6 +249,25 @@ impl<E: Develop<P>, P: Phenome + Genome + 'static> Tournament<E, P> {
             observer,
             evaluator,
             pier,
+            migration_rng,
+            last_checkpoint,
         }
     }
 }
+
+impl<E: Develop<P>, P: Phenome + Genome + DeserializeOwned + 'static> Tournament<E, P> {
+    /// The world of an island resumed from its checkpoint, whose soup and
+    /// epoch `checkpoint::restore` has already set in the config.
+    pub fn resume(
+        config: &Config,
+        observer: Observer<P>,
+        evaluator: E,
+        pier: Arc<Pier<P>>,
+        checkpoint: Checkpoint<P>,
+    ) -> Self {
+        let population = checkpoint.population.into_iter().collect();
+        let mut world = Self::with_population(config, observer, evaluator, pier, population);
+        world.iteration = checkpoint.iteration;
+        world
+    }
+}
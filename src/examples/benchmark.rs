@@ -0,0 +1,386 @@
+//! The scaffolding shared by the benchmark jobs -- regression, boolean and
+//! byte sequence -- which differ only in the problem they pose: what their
+//! genes are, what's made of a chromosome when it's run on the problem's
+//! cases, and how that's scored. Each poses its problem by implementing
+//! `Problem`, and is run, by `run`, with tournament selection, the only kind
+//! these jobs support. See `Config::check_selection`.
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use rand::Rng;
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::configure::{Config, Selection};
+use crate::error::Error;
+use crate::evolution::population::pier::Pier;
+use crate::evolution::{tournament::Tournament, Genome, Phenome};
+use crate::fitness::Weighted;
+use crate::observer::{Observer, ReportFn, Window};
+use crate::ontogenesis::{Develop, FitnessFn};
+use crate::util;
+use crate::util::count_min_sketch::CountMinSketch;
+use crate::util::levy_flight::levy_decision;
+use crate::util::random::{hash_seed_rng, Prng};
+
+pub type Fitness<'a> = Weighted<'a>;
+
+/// A problem posed to a benchmark job.
+pub trait Problem: 'static + Sized + Send + Sync {
+    type Allele: 'static + Clone + Debug + PartialEq + Eq + Hash + Serialize + Send + Sync;
+    /// What's made of a chromosome when it's run on the problem's cases
+    type Outcome: 'static + Clone + Debug + Default + Serialize + Send + Sync;
+
+    /// Whether the creatures are developed in parallel, which is worth it
+    /// only if their development spends its time waiting
+    const PARALLEL: bool = false;
+
+    /// Sets up the problem's cases, and whatever else in the config the
+    /// problem needs, before the run.
+    fn prepare(config: &mut Config) -> Result<(), Error>;
+
+    fn random_allele(config: &Config, rng: &mut Prng) -> Self::Allele;
+
+    fn mutate_allele(config: &Config, allele: &mut Self::Allele, rng: &mut Prng);
+
+    /// Runs the creature's chromosome on the problem's cases.
+    fn develop(config: &Config, creature: &Creature<Self>) -> Self::Outcome;
+
+    /// Scores the outcome. The creature's genetic frequency is scored
+    /// apart from it, as `genetic_freq`.
+    fn score(config: &Config, outcome: &Self::Outcome) -> Fitness<'static>;
+
+    /// Whether the outcome fails the case with the given index.
+    fn fails(outcome: &Self::Outcome, case: usize) -> bool;
+
+    /// Writes the chromosome, and what was made of it, into the creature's
+    /// description.
+    fn describe(
+        chromosome: &[Self::Allele],
+        outcome: &Self::Outcome,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result;
+
+    /// The most alleles a chromosome may have, if there's a limit.
+    fn max_length(_config: &Config) -> Option<usize> {
+        None
+    }
+
+    /// Logs whatever the problem has to report of the frame, besides its
+    /// average length and fitness, and its best.
+    fn report(_frame: &[Creature<Self>], _counter: usize, _config: &Config) {}
+
+    fn transpiled_code(_config: &Config, _creature: &Creature<Self>) -> Option<String> {
+        None
+    }
+}
+
+#[derive(Serialize)]
+#[serde(bound = "")]
+pub struct Creature<P: Problem> {
+    chromosome: Vec<P::Allele>,
+    pub outcome: P::Outcome,
+    pub fitness: Option<Fitness<'static>>,
+    /// The indices of the cases answered, if they've been stored
+    answers: Option<Vec<usize>>,
+    tag: u64,
+    name: String,
+    generation: usize,
+    native_island: usize,
+    num_offspring: usize,
+}
+
+impl<P: Problem> Default for Creature<P> {
+    fn default() -> Self {
+        Self {
+            chromosome: vec![],
+            outcome: Default::default(),
+            fitness: None,
+            answers: None,
+            tag: 0,
+            name: String::new(),
+            generation: 0,
+            native_island: 0,
+            num_offspring: 0,
+        }
+    }
+}
+
+impl<P: Problem> Clone for Creature<P> {
+    fn clone(&self) -> Self {
+        Self {
+            chromosome: self.chromosome.clone(),
+            outcome: self.outcome.clone(),
+            fitness: self.fitness.clone(),
+            answers: self.answers.clone(),
+            tag: self.tag,
+            name: self.name.clone(),
+            generation: self.generation,
+            native_island: self.native_island,
+            num_offspring: self.num_offspring,
+        }
+    }
+}
+
+impl<P: Problem> Hash for Creature<P> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.tag.hash(state)
+    }
+}
+
+impl<P: Problem> Debug for Creature<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Name: {}, generation: {}, from island {}",
+            self.name, self.generation, self.native_island
+        )?;
+        P::describe(&self.chromosome, &self.outcome, f)?;
+        writeln!(f, "Fitness: {:#?}", self.fitness)
+    }
+}
+
+impl<P: Problem> Phenome for Creature<P> {
+    type Fitness = Fitness<'static>;
+    /// The index of a case of the problem
+    type Problem = usize;
+
+    fn fitness(&self) -> Option<&Self::Fitness> {
+        self.fitness.as_ref()
+    }
+
+    fn scalar_fitness(&self, weighting: &str) -> Option<f64> {
+        self.fitness
+            .as_ref()
+            .map(|f| f.scalar_with_expression(weighting))
+    }
+
+    fn set_fitness(&mut self, f: Self::Fitness) {
+        self.fitness = Some(f)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn tag(&self) -> u64 {
+        self.tag
+    }
+
+    fn set_tag(&mut self, tag: u64) {
+        self.tag = tag
+    }
+
+    fn answers(&self) -> Option<&Vec<Self::Problem>> {
+        self.answers.as_ref()
+    }
+
+    fn store_answers(&mut self, answers: Vec<Self::Problem>) {
+        self.answers = Some(answers)
+    }
+
+    fn is_goal_reached(&self, config: &Config) -> bool {
+        if let Some(fitness) = self.scalar_fitness(&config.fitness.priority()) {
+            return fitness <= config.fitness.target;
+        }
+        false
+    }
+
+    fn transpiled_code(&self, config: &Config) -> Option<String> {
+        P::transpiled_code(config, self)
+    }
+
+    fn fails(&self, case: &Self::Problem) -> bool {
+        P::fails(&self.outcome, *case)
+    }
+}
+
+impl<P: Problem> Genome for Creature<P> {
+    type Allele = P::Allele;
+
+    fn generation(&self) -> usize {
+        self.generation
+    }
+
+    fn num_offspring(&self) -> usize {
+        self.num_offspring
+    }
+
+    fn incr_num_offspring(&mut self, n: usize) {
+        self.num_offspring += n
+    }
+
+    fn native_island(&self) -> usize {
+        self.native_island
+    }
+
+    fn chromosome(&self) -> &[Self::Allele] {
+        &self.chromosome
+    }
+
+    fn chromosome_mut(&mut self) -> &mut [Self::Allele] {
+        &mut self.chromosome
+    }
+
+    fn random<H: Hash>(config: &Config, salt: H) -> Self {
+        let mut hasher = fnv::FnvHasher::default();
+        salt.hash(&mut hasher);
+        config.random_seed.hash(&mut hasher);
+        let seed = hasher.finish();
+        let mut rng = hash_seed_rng(&seed);
+        let len = rng.gen_range(config.min_init_len, config.max_init_len) + 1;
+        let chromosome = (0..len)
+            .map(|_| P::random_allele(config, &mut rng))
+            .collect::<Vec<P::Allele>>();
+        Self {
+            chromosome,
+            tag: rng.gen::<u64>(),
+            name: util::name::random(4, &seed),
+            native_island: config.island_id,
+            ..Default::default()
+        }
+    }
+
+    fn crossover(mates: &[&Self], config: &Config) -> Self {
+        let distribution = rand_distr::Exp::new(config.crossover_period)
+            .expect("Failed to create random distribution");
+        let mut rng = hash_seed_rng(&mates[0]);
+        let mut chromosome = if rng.gen_range(0.0, 1.0) < config.crossover_rate {
+            let parental_chromosomes = mates.iter().map(|m| m.chromosome()).collect::<Vec<_>>();
+            Self::crossover_by_distribution(&distribution, &parental_chromosomes).0
+        } else {
+            mates[rng.gen_range(0, mates.len())].chromosome.clone()
+        };
+        if let Some(max_length) = P::max_length(config) {
+            chromosome.truncate(max_length);
+        }
+        Self {
+            name: util::name::random(4, &chromosome),
+            chromosome,
+            tag: rng.gen::<u64>(),
+            generation: mates.iter().map(|p| p.generation).max().unwrap() + 1,
+            native_island: config.island_id,
+            ..Default::default()
+        }
+    }
+
+    fn mutate(&mut self, config: &Config) {
+        let mut rng = hash_seed_rng(&self);
+        let len = self.len();
+        for i in 0..len {
+            if levy_decision(&mut rng, len, config.mutation_exponent) {
+                P::mutate_allele(config, &mut self.chromosome[i], &mut rng);
+            }
+        }
+    }
+}
+
+fn report<P: Problem>(window: &Window<Creature<P>>, counter: usize, config: &Config) {
+    let frame = &window.frame;
+    let avg_len = frame.iter().map(|c| c.len()).sum::<usize>() as f64 / frame.len() as f64;
+    let avg_fit = frame
+        .iter()
+        .filter_map(|g| g.scalar_fitness(&config.fitness.weighting))
+        .sum::<f64>()
+        / frame.len() as f64;
+    log::info!(
+        "[{}] Average length: {}, average fitness: {}",
+        counter,
+        avg_len,
+        avg_fit,
+    );
+    P::report(frame, counter, config);
+    if let Some(ref best) = window.best {
+        log::info!("Best: {:?}", best);
+    }
+}
+
+pub fn fitness_function<P: Problem>(
+    mut creature: Creature<P>,
+    sketch: &mut CountMinSketch,
+    config: Arc<Config>,
+) -> Creature<P> {
+    let mut fitness = P::score(&config, &creature.outcome);
+    creature.record_genetic_frequency(sketch);
+    fitness.insert("genetic_freq", creature.query_genetic_frequency(sketch));
+    creature.set_fitness(fitness);
+    creature
+}
+
+fn develop<P: Problem>(config: &Config, mut creature: Creature<P>) -> Creature<P> {
+    if creature.fitness.is_none() {
+        creature.outcome = P::develop(config, &creature);
+    }
+    creature
+}
+
+pub struct Evaluator<P: Problem> {
+    sketch: CountMinSketch,
+    fitness_fn: FitnessFn<Creature<P>, CountMinSketch, Config>,
+    config: Arc<Config>,
+}
+
+impl<P: Problem> Evaluator<P> {
+    pub fn spawn(
+        config: &Config,
+        fitness_fn: FitnessFn<Creature<P>, CountMinSketch, Config>,
+    ) -> Self {
+        Self {
+            sketch: CountMinSketch::new(config),
+            fitness_fn,
+            config: Arc::new(config.clone()),
+        }
+    }
+}
+
+impl<P: Problem> Develop<Creature<P>> for Evaluator<P> {
+    fn develop(&self, creature: Creature<P>) -> Creature<P> {
+        develop(&self.config, creature)
+    }
+
+    fn apply_fitness_function(&mut self, creature: Creature<P>) -> Creature<P> {
+        (self.fitness_fn)(creature, &mut self.sketch, self.config.clone())
+    }
+
+    fn development_pipeline<I: 'static + Iterator<Item = Creature<P>> + Send>(
+        &self,
+        inbound: I,
+    ) -> Vec<Creature<P>> {
+        let config = &self.config;
+        if P::PARALLEL {
+            inbound
+                .collect::<Vec<Creature<P>>>()
+                .into_par_iter()
+                .map(|c| develop(config, c))
+                .collect()
+        } else {
+            inbound.map(|c| develop(config, c)).collect()
+        }
+    }
+}
+
+/// Prepares the problem, and evolves solutions to it, until the run is
+/// stopped.
+pub fn run<P: Problem>(mut config: Config) -> Result<(), Error> {
+    P::prepare(&mut config)?;
+    let report_fn: ReportFn<_> = Box::new(report::<P>);
+    let fitness_fn: FitnessFn<Creature<P>, _, _> = Box::new(fitness_function::<P>);
+    let observer = Observer::spawn(&config, report_fn);
+    let evaluator = Evaluator::spawn(&config, fitness_fn);
+    match config.selection {
+        Selection::Tournament => {
+            let pier = Arc::new(Pier::new(config.num_islands));
+            let mut world =
+                Tournament::<Evaluator<P>, Creature<P>>::new(&config, observer, evaluator, pier);
+            while crate::keep_going() {
+                world = world.evolve();
+            }
+        }
+        sel => unreachable!(
+            "{:?} selection for {:?} was refused when the config was loaded",
+            sel, config.job
+        ),
+    }
+    Ok(())
+}
@@ -0,0 +1,292 @@
+//! The boolean benchmarks, the multiplexer and even parity, by either
+//! backend. See `benchmark` for the scaffolding of the job.
+use std::fmt;
+
+use rand::Rng;
+
+use crate::configure::{BooleanCase, BooleanProblem, Config, GpBackend, InstructionSet};
+use crate::error::Error;
+use crate::evolution::{Genome, Phenome};
+use crate::examples::benchmark::{self, Creature, Fitness, Problem};
+use crate::examples::linear_gp::machine::{Inst, Machine};
+use crate::examples::linear_gp::MachineWord;
+use crate::examples::regression::Gene;
+use crate::roper::push;
+use crate::transpile::{self, Interface};
+use crate::util::random::{hash_seed, Prng};
+
+/// Every assignment of `n` bits, with the first bit the most significant.
+fn truth_table(n: usize) -> impl Iterator<Item = Vec<bool>> {
+    (0..(1_usize << n)).map(move |i| (0..n).map(|j| (i >> (n - 1 - j)) & 1 == 1).collect())
+}
+
+/// The answer to a boolean problem of the given size. The multiplexer takes
+/// `size` address bits followed by `2^size` data bits, and answers with the
+/// data bit selected by the address.
+pub fn target(problem: BooleanProblem, size: usize, input: &[bool]) -> bool {
+    match problem {
+        BooleanProblem::Multiplexer => {
+            let address = input[..size]
+                .iter()
+                .fold(0, |acc, bit| (acc << 1) | *bit as usize);
+            input[size + address]
+        }
+        BooleanProblem::EvenParity => input.iter().filter(|b| **b).count() % 2 == 0,
+    }
+}
+
+/// The number of input bits of a problem of the given size.
+pub fn num_inputs(problem: BooleanProblem, size: usize) -> usize {
+    match problem {
+        BooleanProblem::Multiplexer => size + (1 << size),
+        BooleanProblem::EvenParity => size,
+    }
+}
+
+/// Every case of the problem, in the order of its truth table.
+pub fn problem_cases(problem: BooleanProblem, size: usize) -> Vec<BooleanCase> {
+    truth_table(num_inputs(problem, size))
+        .map(|input| BooleanCase {
+            output: target(problem, size, &input),
+            input,
+        })
+        .collect()
+}
+
+/// The multiplexer with `k` address bits, followed by `2^k` data bits.
+pub fn multiplexer(k: usize) -> Vec<BooleanCase> {
+    problem_cases(BooleanProblem::Multiplexer, k)
+}
+
+/// Whether an even number of the `n` inputs are set.
+pub fn even_parity(n: usize) -> Vec<BooleanCase> {
+    problem_cases(BooleanProblem::EvenParity, n)
+}
+
+fn random_gene<R: Rng>(config: &Config, rng: &mut R) -> Gene {
+    match config.boolean.backend {
+        GpBackend::LinearGp => Gene::Linear(Inst::random(&config.linear_gp)),
+        GpBackend::Push => {
+            let num_inputs = config.boolean.cases.first().map_or(0, |c| c.input.len());
+            Gene::Push(push::random_boolean_op(
+                rng,
+                num_inputs,
+                config.push_vm.literal_rate,
+            ))
+        }
+    }
+}
+
+/// Runs the program on a single case. The linear backend answers with the
+/// low bit of its return register. A push program that leaves no bool
+/// behind has no answer.
+pub fn execute(config: &Config, chromosome: &[Gene], input: &[bool]) -> Option<bool> {
+    match config.boolean.backend {
+        GpBackend::LinearGp => {
+            let code = Gene::linear_code(chromosome);
+            let input = input
+                .iter()
+                .map(|b| *b as MachineWord)
+                .collect::<Vec<MachineWord>>();
+            Machine::new(&config.linear_gp)
+                .exec(&code, &input)
+                .first()
+                .map(|w| w & 1 == 1)
+        }
+        GpBackend::Push => {
+            let code = Gene::push_code(chromosome);
+            push::MachineState::default().exec_boolean(&code, input, config.push_vm.max_steps)
+        }
+    }
+}
+
+/// The number of cases the program got right
+pub fn num_hits(hits: &[bool]) -> usize {
+    hits.iter().filter(|h| **h).count()
+}
+
+/// The number of creatures in the window that get each case right. The
+/// hardest cases are the ones lexicase selection would lean on.
+pub fn case_hit_counts(frame: &[Creature<Boolean>], num_cases: usize) -> Vec<usize> {
+    (0..num_cases)
+        .map(|i| frame.iter().filter(|c| !c.fails(&i)).count())
+        .collect()
+}
+
+pub struct Boolean;
+
+impl Problem for Boolean {
+    type Allele = Gene;
+    /// Whether the program got each case right
+    type Outcome = Vec<bool>;
+
+    fn prepare(config: &mut Config) -> Result<(), Error> {
+        config.boolean.cases = problem_cases(config.boolean.problem, config.boolean.size);
+        let num_inputs = num_inputs(config.boolean.problem, config.boolean.size);
+        // The linear backend reads its inputs from the second register on, and
+        // answers in the first.
+        let linear_gp = &mut config.linear_gp;
+        linear_gp.instruction_set = InstructionSet::Boolean;
+        linear_gp.num_registers = Some(linear_gp.num_registers.unwrap_or(0).max(num_inputs + 2));
+        linear_gp.return_registers = Some(1);
+        log::info!(
+            "{} cases of the {:?} problem, with {} inputs",
+            config.boolean.cases.len(),
+            config.boolean.problem,
+            num_inputs
+        );
+        Ok(())
+    }
+
+    fn random_allele(config: &Config, rng: &mut Prng) -> Gene {
+        random_gene(config, rng)
+    }
+
+    fn mutate_allele(config: &Config, gene: &mut Gene, rng: &mut Prng) {
+        match gene {
+            Gene::Linear(inst) => {
+                let seed = hash_seed(&rng.gen::<u64>());
+                inst.mutate(&config.linear_gp, &seed);
+            }
+            gene => *gene = random_gene(config, rng),
+        }
+    }
+
+    fn develop(config: &Config, creature: &Creature<Self>) -> Vec<bool> {
+        config
+            .boolean
+            .cases
+            .iter()
+            .map(|case| execute(config, creature.chromosome(), &case.input) == Some(case.output))
+            .collect()
+    }
+
+    /// Scores the hits by the number of cases missed, `errors`.
+    fn score(config: &Config, hits: &Vec<bool>) -> Fitness<'static> {
+        let mut fitness = config.fitness.weighted();
+        fitness.insert("errors", (hits.len() - num_hits(hits)) as f64);
+        fitness
+    }
+
+    fn fails(hits: &Vec<bool>, case: usize) -> bool {
+        !hits.get(case).cloned().unwrap_or(false)
+    }
+
+    fn describe(genes: &[Gene], hits: &Vec<bool>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Gene::describe(genes, f)?;
+        writeln!(f, "Hits: {} of {}", num_hits(hits), hits.len())
+    }
+
+    fn report(frame: &[Creature<Self>], counter: usize, config: &Config) {
+        let hit_counts = case_hit_counts(frame, config.boolean.cases.len());
+        log::info!(
+            "[{}] Hits per case: {:?}, unsolved cases: {}",
+            counter,
+            hit_counts,
+            hit_counts.iter().filter(|n| **n == 0).count()
+        );
+    }
+
+    fn transpiled_code(config: &Config, creature: &Creature<Self>) -> Option<String> {
+        match config.boolean.backend {
+            GpBackend::LinearGp => {
+                let code = Gene::linear_code(creature.chromosome());
+                Some(transpile::linear_gp(
+                    &code,
+                    &config.linear_gp,
+                    Interface::Boolean,
+                ))
+            }
+            GpBackend::Push => {
+                let code = Gene::push_code(creature.chromosome());
+                let num_inputs = num_inputs(config.boolean.problem, config.boolean.size);
+                transpile::push(
+                    &code,
+                    num_inputs,
+                    config.push_vm.max_steps,
+                    Interface::Boolean,
+                )
+                .map_err(|e| log::debug!("Can't lower {}: {:?}", creature.name(), e))
+                .ok()
+            }
+        }
+    }
+}
+
+pub fn run(config: Config) -> Result<(), Error> {
+    benchmark::run::<Boolean>(config)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::configure::LinearGpConfig;
+    use crate::examples::linear_gp::machine::Op;
+
+    use super::*;
+
+    #[test]
+    fn test_problem_cases() {
+        let cases = multiplexer(2);
+        assert_eq!(cases.len(), 64);
+        // address 0b10 selects the third data bit
+        let case = cases
+            .iter()
+            .find(|c| c.input == vec![true, false, false, false, true, false])
+            .unwrap();
+        assert!(case.output);
+        assert_eq!(cases.iter().filter(|c| c.output).count(), 32);
+
+        let cases = even_parity(3);
+        assert_eq!(cases.len(), 8);
+        assert!(cases[0].output);
+        assert!(!cases[1].output);
+        assert!(cases[3].output);
+    }
+
+    #[test]
+    fn test_backends() {
+        let mut config = Config::default();
+        config.boolean.cases = even_parity(2);
+        config.linear_gp = LinearGpConfig {
+            max_steps: 100,
+            num_registers: Some(4),
+            return_registers: Some(1),
+            instruction_set: InstructionSet::Boolean,
+            ..Default::default()
+        };
+        config.push_vm.max_steps = 100;
+        let inst = |op, a, b| Gene::Linear(Inst { op, a, b });
+        // !(x ^ y)
+        let linear = vec![
+            inst(Op::Mov, 0, 1),
+            inst(Op::Xor, 0, 2),
+            inst(Op::Not, 0, 0),
+            inst(Op::End, 0, 0),
+        ];
+        config.boolean.backend = GpBackend::LinearGp;
+        for case in config.boolean.cases.iter() {
+            assert_eq!(execute(&config, &linear, &case.input), Some(case.output));
+        }
+
+        // the exec stack runs from the end of the program
+        let push = vec![
+            Gene::Push(push::Op::Eq(push::Type::Bool)),
+            Gene::Push(push::Op::Input(1)),
+            Gene::Push(push::Op::Input(0)),
+        ];
+        config.boolean.backend = GpBackend::Push;
+        for case in config.boolean.cases.iter() {
+            assert_eq!(execute(&config, &push, &case.input), Some(case.output));
+        }
+        assert_eq!(execute(&config, &[], &[true, true]), None);
+
+        let creature = Creature::<Boolean> {
+            outcome: vec![true, false],
+            ..Default::default()
+        };
+        assert_eq!(
+            case_hit_counts(&[creature.clone(), creature], 2),
+            vec![2, 0]
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! Evolves raw byte buffers, scored by an external checker, which reads
+//! each buffer and reports on it through its exit code, and, optionally, a
+//! JSON object of scores printed to its standard output. This makes
+//! berbalang usable as a black-box search harness, with no Rust to write.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::configure::{ByteInput, ByteSequenceConfig, Config};
+use crate::error::Error;
+use crate::evolution::{Genome, Phenome};
+use crate::examples::benchmark::{self, Creature, Fitness, Problem};
+use crate::util::random::Prng;
+
+/// What the checker made of a buffer
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Verdict {
+    /// This is `None` if the checker was killed, by a signal or by the
+    /// timeout.
+    pub exit_code: Option<i32>,
+    pub stdout: Vec<u8>,
+}
+
+/// Runs the checker on the buffer. The tag keeps the temporary files of
+/// concurrent checks apart.
+pub fn check(config: &ByteSequenceConfig, buffer: &[u8], tag: u64) -> Result<Verdict, Error> {
+    let (program, args) = config
+        .command
+        .split_first()
+        .ok_or_else(|| Error::Misc("No checker command given".to_string()))?;
+    let timeout = config.timeout_duration;
+    let mut command = Command::new(program);
+    match config.input {
+        ByteInput::Stdin => {
+            command.args(args);
+            run_checker(command, Some(buffer), timeout)
+        }
+        ByteInput::File => {
+            let path = std::env::temp_dir().join(format!(
+                "berbalang_{}_{:016x}.bin",
+                std::process::id(),
+                tag
+            ));
+            fs::write(&path, buffer)?;
+            let path_str = path.to_string_lossy();
+            if args.iter().any(|a| a.contains("{}")) {
+                command.args(args.iter().map(|a| a.replace("{}", &path_str)));
+            } else {
+                command.args(args).arg(path_str.as_ref());
+            }
+            let verdict = run_checker(command, None, timeout);
+            let _ = fs::remove_file(&path);
+            verdict
+        }
+    }
+}
+
+/// Kills the checker's process group, and with it whatever the checker
+/// spawned, as `sh -c` does, which may be holding its stdout open.
+fn kill_process_group(child: &Child) {
+    // The group may be gone already
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+fn run_checker(
+    mut command: Command,
+    stdin: Option<&[u8]>,
+    timeout: Option<Duration>,
+) -> Result<Verdict, Error> {
+    // The checker leads a process group of its own, so that it can be
+    // killed along with its children.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setpgid(0, 0) == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        });
+    }
+    let mut child = command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // The pipes are fed and drained from their own threads, so that a
+    // checker that writes before it's done reading can't deadlock with us.
+    let writer = match (stdin, child.stdin.take()) {
+        (Some(buffer), Some(mut pipe)) => {
+            let buffer = buffer.to_vec();
+            Some(thread::spawn(move || {
+                // The checker is free to stop reading early
+                let _ = pipe.write_all(&buffer);
+            }))
+        }
+        _ => None,
+    };
+    let mut pipe = child.stdout.take().expect("stdout should be piped");
+    let reader = thread::spawn(move || {
+        let mut stdout = Vec::new();
+        let _ = pipe.read_to_end(&mut stdout);
+        stdout
+    });
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if timeout.map_or(false, |t| started.elapsed() >= t) {
+            // It may have exited in the meantime
+            kill_process_group(&child);
+            child.wait()?;
+            break None;
+        }
+        thread::sleep(Duration::from_millis(1));
+    };
+    // Whatever the checker left running would keep its stdout open, and
+    // the reader waiting on it.
+    kill_process_group(&child);
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+    let stdout = reader
+        .join()
+        .map_err(|_| Error::Misc("Failed to read the checker's output".to_string()))?;
+    Ok(Verdict {
+        exit_code: status.and_then(|s| s.code()),
+        stdout,
+    })
+}
+
+/// Scores the verdict by its `exit_code`, and by each of the configured
+/// scores in the checker's JSON output. Whatever is missing, including the
+/// whole verdict, if the checker couldn't be run, gets the worst possible
+/// score.
+pub fn score_verdict(config: &Config, verdict: Option<&Verdict>) -> Fitness<'static> {
+    let mut fitness = config.fitness.weighted();
+    let exit_code = verdict
+        .and_then(|v| v.exit_code)
+        .map_or(std::f64::MAX, |c| c as f64);
+    fitness.insert("exit_code", exit_code);
+    let scores: Option<HashMap<String, serde_json::Value>> =
+        verdict.and_then(|v| serde_json::from_slice(&v.stdout).ok());
+    for key in config.byte_sequence.score_keys.iter() {
+        let score = scores
+            .as_ref()
+            .and_then(|s| s.get(*key))
+            .and_then(serde_json::Value::as_f64)
+            .unwrap_or(std::f64::MAX);
+        fitness.insert(*key, score);
+    }
+    fitness
+}
+
+pub struct ByteSequence;
+
+impl Problem for ByteSequence {
+    type Allele = u8;
+    /// This is `None` if the checker couldn't be run.
+    type Outcome = Option<Verdict>;
+
+    /// The checkers are run in parallel, since they'll spend most of their
+    /// time waiting on the external process.
+    const PARALLEL: bool = true;
+
+    fn prepare(config: &mut Config) -> Result<(), Error> {
+        let byte_sequence = &mut config.byte_sequence;
+        if byte_sequence.command.is_empty() {
+            return Err(Error::Parsing(
+                "The byte sequence job needs a checker command, in byte_sequence.command"
+                    .to_string(),
+            ));
+        }
+        byte_sequence.timeout_duration = byte_sequence
+            .timeout
+            .as_ref()
+            .map(|t| {
+                parse_duration::parse(t).map_err(|e| {
+                    Error::Parsing(format!("Invalid byte_sequence.timeout {:?}: {}", t, e))
+                })
+            })
+            .transpose()?;
+        byte_sequence.score_keys = byte_sequence
+            .scores
+            .iter()
+            .map(|s| &*Box::leak(s.clone().into_boxed_str()))
+            .collect();
+        log::info!(
+            "Checking buffers with {:?}, scored by exit code and {:?}",
+            byte_sequence.command,
+            byte_sequence.scores
+        );
+        Ok(())
+    }
+
+    fn random_allele(_config: &Config, rng: &mut Prng) -> u8 {
+        rng.gen::<u8>()
+    }
+
+    /// Each byte chosen is either replaced, or has one of its bits flipped.
+    fn mutate_allele(_config: &Config, byte: &mut u8, rng: &mut Prng) {
+        if rng.gen() {
+            *byte = rng.gen::<u8>();
+        } else {
+            *byte ^= 1 << rng.gen_range(0, 8);
+        }
+    }
+
+    fn develop(config: &Config, creature: &Creature<Self>) -> Option<Verdict> {
+        check(&config.byte_sequence, creature.chromosome(), creature.tag())
+            .map_err(|e| log::warn!("Failed to check {}: {:?}", creature.name(), e))
+            .ok()
+    }
+
+    fn score(config: &Config, verdict: &Option<Verdict>) -> Fitness<'static> {
+        score_verdict(config, verdict.as_ref())
+    }
+
+    /// The buffer's only case is passed if the checker exits with 0.
+    fn fails(verdict: &Option<Verdict>, case: usize) -> bool {
+        case != 0 || verdict.as_ref().and_then(|v| v.exit_code) != Some(0)
+    }
+
+    fn describe(
+        buffer: &[u8],
+        verdict: &Option<Verdict>,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        writeln!(f, "Buffer: {}", hex::encode(buffer))?;
+        writeln!(f, "As text: {:?}", String::from_utf8_lossy(buffer))?;
+        if let Some(ref verdict) = verdict {
+            writeln!(
+                f,
+                "Exit code: {:?}, output: {:?}",
+                verdict.exit_code,
+                String::from_utf8_lossy(&verdict.stdout)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn max_length(config: &Config) -> Option<usize> {
+        Some(config.max_length)
+    }
+
+    fn report(frame: &[Creature<Self>], counter: usize, _config: &Config) {
+        let killed = frame
+            .iter()
+            .filter(|c| c.outcome.as_ref().map_or(true, |v| v.exit_code.is_none()))
+            .count();
+        log::info!("[{}] Checks killed or failed: {}", counter, killed);
+    }
+}
+
+pub fn run(config: Config) -> Result<(), Error> {
+    benchmark::run::<ByteSequence>(config)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn checker(command: &[&str], input: ByteInput) -> ByteSequenceConfig {
+        ByteSequenceConfig {
+            command: command.iter().map(|s| s.to_string()).collect(),
+            input,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let config = checker(&["sh", "-c", "exit 3"], ByteInput::Stdin);
+        assert_eq!(check(&config, b"", 0).unwrap().exit_code, Some(3));
+
+        let config = checker(&["cat"], ByteInput::Stdin);
+        let verdict = check(&config, b"\x00\xffabc", 1).unwrap();
+        assert_eq!(verdict.exit_code, Some(0));
+        assert_eq!(verdict.stdout, b"\x00\xffabc".to_vec());
+
+        let config = checker(&["cat", "{}"], ByteInput::File);
+        assert_eq!(check(&config, b"abc", 2).unwrap().stdout, b"abc".to_vec());
+
+        let mut config = checker(&["sleep", "5"], ByteInput::Stdin);
+        config.timeout_duration = Some(Duration::from_millis(50));
+        assert_eq!(check(&config, b"", 3).unwrap().exit_code, None);
+
+        // a grandchild left holding stdout is killed with the checker
+        let started = Instant::now();
+        let config = checker(&["sh", "-c", "sleep 5 & exit 0"], ByteInput::Stdin);
+        assert_eq!(check(&config, b"", 5).unwrap().exit_code, Some(0));
+        let mut config = checker(&["sh", "-c", "sleep 5; sleep 5"], ByteInput::Stdin);
+        config.timeout_duration = Some(Duration::from_millis(50));
+        assert_eq!(check(&config, b"", 6).unwrap().exit_code, None);
+        assert!(started.elapsed() < Duration::from_secs(4));
+
+        assert!(check(&checker(&[], ByteInput::Stdin), b"", 4).is_err());
+    }
+
+    #[test]
+    fn test_score_verdict() {
+        let mut config = Config::default();
+        config.fitness.weighting = "exit_code + distance".to_string();
+        config.byte_sequence.score_keys = vec!["distance", "missing"];
+        let verdict = Verdict {
+            exit_code: Some(1),
+            stdout: br#"{"distance": 2.5}"#.to_vec(),
+        };
+        let fitness = score_verdict(&config, Some(&verdict));
+        assert_eq!(fitness.scores["exit_code"], 1.0);
+        assert_eq!(fitness.scores["distance"], 2.5);
+        assert_eq!(fitness.scores["missing"], std::f64::MAX);
+        let fitness = score_verdict(&config, None);
+        assert_eq!(fitness.scores["exit_code"], std::f64::MAX);
+    }
+}
@@ -9,21 +9,23 @@ use rand::distributions::Alphanumeric;
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::configure::Config;
+use crate::configure::{Config, StringDistance};
 use crate::evolution::population::pier::Pier;
 use crate::evolution::{Genome, Phenome};
+use crate::fitness::{average_weighted, Weighted};
 use crate::observer::Window;
 use crate::util::count_min_sketch::CountMinSketch;
 use crate::util::levy_flight::levy_decision;
 use crate::util::random::hash_seed_rng;
 use crate::{evolution::tournament::*, observer::Observer, ontogenesis::Develop};
 
-pub type Fitness = Vec<f64>;
+pub type Fitness<'a> = Weighted<'a>;
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Genotype {
     pub genes: String,
-    fitness: Option<Fitness>,
+    #[serde(borrow)]
+    fitness: Option<Fitness<'static>>,
     tag: u64,
     // used for sorting in heap
     generation: usize,
@@ -59,18 +61,20 @@ impl Ord for Genotype {
 
 // because this is a GA we identify genome and phenome
 impl Phenome for Genotype {
-    type Fitness = Fitness;
+    type Fitness = Fitness<'static>;
     type Problem = ();
 
-    fn fitness(&self) -> Option<&Fitness> {
+    fn fitness(&self) -> Option<&Self::Fitness> {
         self.fitness.as_ref()
     }
 
-    fn scalar_fitness(&self, _weighting: &str) -> Option<f64> {
-        self.fitness.as_ref().map(|v| v[0])
+    fn scalar_fitness(&self, weighting: &str) -> Option<f64> {
+        self.fitness
+            .as_ref()
+            .map(|f| f.scalar_with_expression(weighting))
     }
 
-    fn set_fitness(&mut self, f: Fitness) {
+    fn set_fitness(&mut self, f: Self::Fitness) {
         self.fitness = Some(f);
     }
 
@@ -92,7 +96,7 @@ impl Phenome for Genotype {
 
     fn is_goal_reached(&self, config: &Config) -> bool {
         (self
-            .scalar_fitness(&config.fitness.weighting)
+            .scalar_fitness(config.fitness.priority())
             .unwrap_or(std::f64::MAX)
             - config.fitness.target)
             <= std::f64::EPSILON
@@ -211,19 +215,11 @@ impl Genome for Genotype {
 
 fn report(window: &Window<Genotype>, counter: usize, _config: &Config) {
     let frame = &window.frame;
-    let fitnesses: Vec<Fitness> = frame.iter().filter_map(|g| g.fitness.clone()).collect();
-    let len = fitnesses.len();
-    let avg_fit = fitnesses
-        .iter()
-        .fold(vec![0.0; len], |a: Fitness, b: &Fitness| {
-            a.iter()
-                .zip(b.iter())
-                .map(|(a, b)| a + b)
-                .collect::<Vec<f64>>()
-        })
-        .iter()
-        .map(|v| v / len as f64)
-        .collect::<Vec<f64>>();
+    let fitnesses: Vec<Fitness<'static>> = frame.iter().filter_map(|g| g.fitness.clone()).collect();
+    if fitnesses.is_empty() {
+        return;
+    }
+    let avg_fit = average_weighted(&fitnesses).scores;
     let avg_gen = frame.iter().map(|g| g.generation).sum::<usize>() as f64 / frame.len() as f64;
 
     log::info!(
@@ -234,36 +230,74 @@ fn report(window: &Window<Genotype>, counter: usize, _config: &Config) {
     );
 }
 
+fn hamming_distance(a: &str, b: &str) -> usize {
+    let (short, long) = if a.len() < b.len() { (a, b) } else { (b, a) };
+    let dif = long.len() - short.len();
+    let short = short.as_bytes();
+    let long = &long.as_bytes()[0..short.len()];
+    hamming::distance(short, long) as usize + dif * 8
+}
+
+fn lcs_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<char>>();
+    let b = b.chars().collect::<Vec<char>>();
+    // a single row of the dynamic programming table
+    let mut row = vec![0; b.len() + 1];
+    for x in a.iter() {
+        let mut diagonal = 0;
+        for (j, y) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if x == y {
+                diagonal + 1
+            } else {
+                above.max(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    a.len() + b.len() - 2 * row[b.len()]
+}
+
+pub fn string_distance(metric: StringDistance, a: &str, b: &str) -> f64 {
+    (match metric {
+        StringDistance::Hamming => hamming_distance(a, b),
+        StringDistance::Levenshtein => distance::levenshtein(a, b),
+        StringDistance::DamerauLevenshtein => distance::damerau_levenshtein(a, b),
+        StringDistance::Lcs => lcs_distance(a, b),
+    }) as f64
+}
+
 cached_key! {
-    FF_CACHE: TimedCache<String, Vec<f64>> = TimedCache::with_lifespan(2);
-
-    Key = { format!("{}\x00\x00{}", phenome, target ) };
-
-    fn ff_helper(phenome: &str, target: &str) -> Vec<f64> = {
-        let l_fitness = distance::damerau_levenshtein(phenome, target);
-        let (short, long) = if phenome.len() < target.len() {
-            (phenome, target)
-        } else {
-            (target, phenome)
-        };
-        let dif = long.len() - short.len();
-        let short = short.as_bytes();
-        let long = &long.as_bytes()[0..short.len()];
-        let h_fitness = hamming::distance(short, long) + (dif * 8) as u64;
-        vec![l_fitness as f64, h_fitness as f64]
+    FF_CACHE: TimedCache<String, f64> = TimedCache::with_lifespan(2);
+
+    Key = { format!("{:?}\x00\x00{}\x00\x00{}", metric, phenome, target ) };
+
+    fn ff_helper(metric: StringDistance, phenome: &str, target: &str) -> f64 = {
+        string_distance(metric, phenome, target)
     }
 }
 
+/// Scores the string by each of the configured distances to the nearest
+/// target, and by the frequency of the string in the population so far,
+/// `genetic_freq`.
 fn fitness_function(
     mut phenome: Genotype,
     sketch: &mut CountMinSketch,
     config: Arc<Config>,
 ) -> Genotype {
     if phenome.fitness.is_none() {
-        phenome.set_fitness(ff_helper(&phenome.genes, &config.hello.target));
+        let mut fitness = config.fitness.weighted();
+        for metric in config.hello.distances.iter() {
+            let dist = config
+                .hello
+                .targets()
+                .map(|target| ff_helper(*metric, &phenome.genes, target))
+                .fold(std::f64::MAX, f64::min);
+            fitness.insert(metric.key(), dist);
+        }
         sketch.insert(&phenome.genes);
-        let freq = sketch.query(&phenome.genes);
-        phenome.fitness.as_mut().map(|f| f.push(freq));
+        fitness.insert("genetic_freq", sketch.query(&phenome.genes));
+        phenome.set_fitness(fitness);
     };
     phenome
 }
@@ -350,3 +384,31 @@ mod evaluation {
 }
 
 //build_observation_mod!(observation, Genotype, Config);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_string_distances() {
+        assert_eq!(string_distance(StringDistance::Hamming, "abc", "abc"), 0.0);
+        // 'a' and 'c' differ in one bit, and there's a byte missing
+        assert_eq!(string_distance(StringDistance::Hamming, "ab", "cbd"), 9.0);
+        assert_eq!(
+            string_distance(StringDistance::Levenshtein, "kitten", "sitting"),
+            3.0
+        );
+        // a transposition is two edits, or one
+        assert_eq!(
+            string_distance(StringDistance::Levenshtein, "ab", "ba"),
+            2.0
+        );
+        assert_eq!(
+            string_distance(StringDistance::DamerauLevenshtein, "ab", "ba"),
+            1.0
+        );
+        // the longest common subsequence of these is "ace"
+        assert_eq!(string_distance(StringDistance::Lcs, "abcde", "aXcYe"), 4.0);
+        assert_eq!(string_distance(StringDistance::Lcs, "", "abc"), 3.0);
+    }
+}
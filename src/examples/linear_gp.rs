@@ -1,4 +1,5 @@
 use std::cmp::Ordering;
+use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
@@ -8,13 +9,16 @@ use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 use crate::configure::{ClassificationProblem, Config, Selection};
+use crate::dataset::Dataset;
 use crate::evolution::metropolis::Metropolis;
 use crate::evolution::pareto_roulette::Roulette;
 use crate::evolution::population::pier::Pier;
 use crate::evolution::{tournament::Tournament, Genome, Phenome};
 use crate::fitness::Weighted;
-use crate::observer::{Observer, ReportFn, Window};
+use crate::observer::{HoldoutFn, Observer, ReportFn, Window};
 use crate::ontogenesis::FitnessFn;
+use crate::provider::get_classification_provider;
+use crate::transpile::{self, Interface};
 use crate::util;
 use crate::util::count_min_sketch::CountMinSketch;
 use crate::util::levy_flight::levy_decision;
@@ -28,12 +32,15 @@ pub mod machine {
     use std::fmt::{self, Display};
     use std::hash::Hash;
 
+    use hashbrown::HashSet;
+
     use rand::distributions::{Distribution, Standard};
     use rand::Rng;
     use serde::{Deserialize, Serialize};
 
-    use crate::configure::LinearGpConfig;
+    use crate::configure::{ArithmeticConfig, InstructionSet, LinearGpConfig};
     use crate::examples::linear_gp::MachineWord;
+    use crate::util::arithmetic::{self, Guard};
     use crate::util::random::hash_seed_rng;
 
     use super::Mutation;
@@ -51,6 +58,66 @@ pub mod machine {
         And,
         Jle,
         End,
+        SkipLt,
+        SkipEq,
+        Load,
+        Store,
+        Or,
+        Not,
+        // These operate on the float register bank
+        FAdd,
+        FSub,
+        FMul,
+        FDiv,
+        FMov,
+        ItoF,
+        FtoI,
+    }
+
+    impl Op {
+        pub fn is_float(&self) -> bool {
+            use Op::*;
+            match self {
+                FAdd | FSub | FMul | FDiv | FMov | ItoF | FtoI => true,
+                _ => false,
+            }
+        }
+
+        pub fn is_memory(&self) -> bool {
+            match self {
+                Op::Load | Op::Store => true,
+                _ => false,
+            }
+        }
+
+        /// The ops that keep the low bit of a register a function of the
+        /// low bits of their operands.
+        pub fn is_boolean(&self) -> bool {
+            use Op::*;
+            match self {
+                And | Or | Xor | Not | Mov => true,
+                _ => false,
+            }
+        }
+
+        /// Draws a random op, leaving out the float ops if there's no float
+        /// register bank, and the memory ops if there's no data memory. The
+        /// boolean instruction set is restricted to the boolean ops.
+        pub fn random(config: &LinearGpConfig) -> Self {
+            loop {
+                let op = rand::random::<Op>();
+                let available = match config.instruction_set {
+                    InstructionSet::Full => {
+                        (config.num_float_registers > 0 || !op.is_float())
+                            && (config.memory_size > 0 || !op.is_memory())
+                    }
+                    InstructionSet::Boolean => op.is_boolean(),
+                };
+                if available {
+                    return op;
+                }
+            }
+        }
     }
 
     impl Display for Op {
@@ -68,11 +135,24 @@ pub mod machine {
                 And => write!(f, "AND"),
                 Jle => write!(f, "JLE"), // Jump if less than or equal to 0
                 End => write!(f, "END"),
+                SkipLt => write!(f, "SKLT"), // Skip the next instruction if less than
+                SkipEq => write!(f, "SKEQ"), // Skip the next instruction if equal
+                Load => write!(f, "LOAD"),
+                Store => write!(f, "STOR"),
+                Or => write!(f, "OR"),
+                Not => write!(f, "NOT"),
+                FAdd => write!(f, "FADD"),
+                FSub => write!(f, "FSUB"),
+                FMul => write!(f, "FMUL"),
+                FDiv => write!(f, "FDIV"),
+                FMov => write!(f, "FMOV"),
+                ItoF => write!(f, "ITOF"), // Convert integer register to float
+                FtoI => write!(f, "FTOI"), // Convert float register to integer, rounding
             }
         }
     }
 
-    pub const NUM_OPS: usize = 11;
+    pub const NUM_OPS: usize = 24;
 
     impl Distribution<Op> for Standard {
         fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Op {
@@ -89,6 +169,19 @@ pub mod machine {
                 8 => And,
                 9 => Jle,
                 10 => End,
+                11 => FAdd,
+                12 => FSub,
+                13 => FMul,
+                14 => FDiv,
+                15 => FMov,
+                16 => ItoF,
+                17 => FtoI,
+                18 => SkipLt,
+                19 => SkipEq,
+                20 => Load,
+                21 => Store,
+                22 => Or,
+                23 => Not,
                 _ => unreachable!("out of range"),
             }
         }
@@ -96,6 +189,15 @@ pub mod machine {
 
     pub type Register = usize;
 
+    /// A register in either bank, as seen by the intron analysis. The data
+    /// memory is treated as a single register, which stores only add to.
+    #[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
+    enum Reg {
+        Int(Register),
+        Float(Register),
+        Memory,
+    }
+
     // TODO make this configurable
     //const FIRST_INPUT_REGISTER: usize = 1;
     //const RETURN_REGISTER: usize = 0;
@@ -112,6 +214,11 @@ pub mod machine {
             match self.op {
                 Op::Set(n) => write!(f, "SET  R{}  0x{:X}", self.a, n),
                 Op::End => write!(f, "END"),
+                Op::ItoF => write!(f, "{}  F{}, R{}", self.op, self.a, self.b),
+                Op::FtoI => write!(f, "{}  R{}, F{}", self.op, self.a, self.b),
+                Op::Load => write!(f, "{}  R{}, [R{}]", self.op, self.a, self.b),
+                Op::Store => write!(f, "{}  [R{}], R{}", self.op, self.b, self.a),
+                op if op.is_float() => write!(f, "{}  F{}, F{}", self.op, self.a, self.b),
                 _ => write!(f, "{}  R{}, R{}", self.op, self.a, self.b),
             }
         }
@@ -121,19 +228,64 @@ pub mod machine {
         pub fn random(config: &LinearGpConfig) -> Self {
             let num_registers = config.num_registers.unwrap();
             Self {
-                op: rand::random::<Op>(),
+                op: Op::random(config),
                 a: rand::random::<usize>() % num_registers,
                 b: rand::random::<usize>() % num_registers,
             }
         }
 
+        fn is_skip(&self) -> bool {
+            match self.op {
+                Op::SkipLt | Op::SkipEq => true,
+                _ => false,
+            }
+        }
+
+        /// The register the instruction writes to, if any, mirroring
+        /// `Machine::eval`.
+        fn writes(&self, config: &LinearGpConfig) -> Option<Reg> {
+            use Op::*;
+            let n = config.num_registers.unwrap();
+            let nf = config.num_float_registers;
+            match self.op {
+                Add | Div | Mov | Mult | Sub | Xor | Set(_) | Lsl | And | Or | Not | FtoI
+                | Load => {
+                    Some(Reg::Int(self.a % n))
+                }
+                Store => Some(Reg::Memory),
+                FAdd | FSub | FMul | FDiv | FMov | ItoF if nf > 0 => Some(Reg::Float(self.a % nf)),
+                _ => None,
+            }
+        }
+
+        /// The registers the instruction reads from, mirroring `Machine::eval`.
+        fn reads(&self, config: &LinearGpConfig) -> Vec<Reg> {
+            use Op::*;
+            let n = config.num_registers.unwrap();
+            let nf = config.num_float_registers.max(1);
+            let (a, b) = (Reg::Int(self.a % n), Reg::Int(self.b % n));
+            let (fa, fb) = (Reg::Float(self.a % nf), Reg::Float(self.b % nf));
+            match self.op {
+                Add | Div | Mult | Sub | Xor | Lsl | And | Or | Jle | SkipLt | SkipEq => {
+                    vec![a, b]
+                }
+                Mov | Not => vec![b],
+                Set(_) | End => vec![],
+                FAdd | FSub | FMul | FDiv => vec![fa, fb],
+                FMov | FtoI => vec![fb],
+                ItoF => vec![b],
+                Load => vec![b, Reg::Memory],
+                Store => vec![a, b],
+            }
+        }
+
         pub fn mutate<H: Hash>(&mut self, config: &LinearGpConfig, seed: H) -> Mutation {
             let num_registers = config.num_registers.unwrap();
             let mut rng = hash_seed_rng(&seed);
             let mutation = rng.gen::<u8>() % 4;
 
             match mutation {
-                0 => self.op = rand::random(),
+                0 => self.op = Op::random(config),
                 1 => self.a = (self.a + 1) % num_registers,
                 2 => self.b = (self.b + 1) % num_registers,
                 3 => std::mem::swap(&mut self.a, &mut self.b),
@@ -141,13 +293,92 @@ pub mod machine {
             }
             mutation
         }
+
+        /// Perturbs the constant of a `SET`, at the configured rate,
+        /// returning whether it did.
+        pub fn mutate_constant<R: Rng>(&mut self, config: &ArithmeticConfig, rng: &mut R) -> bool {
+            match self.op {
+                Op::Set(n) if arithmetic::mutates_constant(config, rng) => {
+                    let n = arithmetic::perturb_int(config, rng, n as i64);
+                    self.op = Op::Set(n as MachineWord);
+                    true
+                }
+                _ => false,
+            }
+        }
+    }
+
+    /// Structural intron detection: marks the instructions that can
+    /// influence the return registers, working backwards from the end of the
+    /// program. An instruction guarded by a skip is effective if it would
+    /// be, unguarded, and the skip is effective along with it. If the
+    /// program branches, or has no `END`, execution may wrap around, so the
+    /// analysis is repeated until it settles. Jumps are always taken to be
+    /// effective, but their targets aren't followed, so with `JLE` in the
+    /// program this is an approximation.
+    pub fn effective_instructions(code: &[Inst], config: &LinearGpConfig) -> Vec<bool> {
+        let end = code.iter().position(|i| i.op == Op::End);
+        let branching = code.iter().any(|i| i.op == Op::Jle || i.is_skip());
+        let (len, cyclic) = match end {
+            Some(e) if !branching => (e + 1, false),
+            _ => (code.len(), true),
+        };
+        let returns = (0..config.return_registers.unwrap())
+            .map(Reg::Int)
+            .collect::<HashSet<Reg>>();
+        let mut effective = vec![false; code.len()];
+        let mut live_at_end = returns.clone();
+        loop {
+            let mut live = live_at_end.clone();
+            let mut changed = false;
+            for i in (0..len).rev() {
+                let inst = code[i];
+                let is_effective = match inst.op {
+                    Op::End | Op::Jle => true,
+                    Op::SkipLt | Op::SkipEq => effective[(i + 1) % len],
+                    _ => inst
+                        .writes(config)
+                        .map(|r| live.contains(&r))
+                        .unwrap_or(false),
+                };
+                if !is_effective {
+                    continue;
+                }
+                // A write that might be skipped doesn't kill the register,
+                // and nor does a store, which only writes one word
+                let guarded = i > 0 && code[i - 1].is_skip();
+                match inst.writes(config) {
+                    Some(Reg::Memory) | None => {}
+                    Some(r) if !guarded => {
+                        live.remove(&r);
+                    }
+                    Some(_) => {}
+                }
+                live.extend(inst.reads(config));
+                changed |= !effective[i];
+                effective[i] = true;
+            }
+            if !cyclic {
+                break;
+            }
+            live.extend(returns.iter().cloned());
+            if !changed && live == live_at_end {
+                break;
+            }
+            live_at_end = live;
+        }
+        effective
     }
 
     pub struct Machine {
         return_registers: usize,
         registers: Vec<MachineWord>,
+        float_registers: Vec<f64>,
+        memory: Vec<MachineWord>,
         pc: usize,
         max_steps: usize,
+        exhausted: bool,
+        guard: Guard,
     }
 
     impl Machine {
@@ -155,17 +386,53 @@ pub mod machine {
             Self {
                 return_registers: config.return_registers.unwrap(),
                 registers: vec![0; config.num_registers.unwrap()],
+                float_registers: vec![0.0; config.num_float_registers],
+                memory: vec![0; config.memory_size],
                 pc: 0,
                 max_steps: config.max_steps,
+                exhausted: false,
+                guard: Guard::default(),
             }
         }
 
+        /// Sets the handling of arithmetic faults.
+        pub fn with_arithmetic(mut self, config: &ArithmeticConfig) -> Self {
+            self.guard = Guard::new(config);
+            self
+        }
+
+        /// The number of arithmetic faults in the most recent call to
+        /// `exec`.
+        pub fn faults(&self) -> usize {
+            self.guard.faults()
+        }
+
         #[inline]
         fn set(&mut self, reg: usize, val: MachineWord) {
             let n = self.registers.len();
             self.registers[reg % n] = val
         }
 
+        // The float instructions share their register fields with the
+        // integer instructions, so they index the float bank modulo its size.
+        #[inline]
+        fn fget(&self, reg: usize) -> f64 {
+            let n = self.float_registers.len();
+            if n == 0 {
+                0.0
+            } else {
+                self.float_registers[reg % n]
+            }
+        }
+
+        #[inline]
+        fn fset(&mut self, reg: usize, val: f64) {
+            let n = self.float_registers.len();
+            if n > 0 {
+                self.float_registers[reg % n] = val
+            }
+        }
+
         fn eval(&mut self, inst: Inst) {
             use Op::*;
 
@@ -175,34 +442,90 @@ pub mod machine {
                 };
             }
 
+            let (a, b) = (self.registers[inst.a], self.registers[inst.b]);
             match inst.op {
-                Add => set!(self.registers[inst.a].wrapping_add(self.registers[inst.b])),
-                Div => {
-                    let b = if self.registers[inst.b] == 0 {
-                        1
-                    } else {
-                        self.registers[inst.b]
-                    };
-                    set!(self.registers[inst.a].wrapping_div(b))
-                }
+                Add => set!(self.guard.add(a, b)),
+                // Natively, division by zero divides by one
+                Div => set!(self.guard.div(a, b).unwrap_or(a)),
                 Mov => set!(self.registers[inst.b]),
-                Mult => set!(self.registers[inst.a].wrapping_mul(self.registers[inst.b])),
-                Sub => set!(self.registers[inst.a].wrapping_sub(self.registers[inst.b])),
+                Mult => set!(self.guard.mul(a, b)),
+                Sub => set!(self.guard.sub(a, b)),
                 Xor => set!(self.registers[inst.a] ^ self.registers[inst.b]),
                 Set(n) => set!(n),
                 Lsl => set!(self.registers[inst.a].wrapping_shl(self.registers[inst.b] as u32)),
                 And => set!(self.registers[inst.a] & self.registers[inst.b]),
+                Or => set!(self.registers[inst.a] | self.registers[inst.b]),
+                Not => set!(!self.registers[inst.b]),
                 Jle => {
                     if self.registers[inst.a] <= 0 {
                         self.pc = self.registers[inst.b] as usize
                     }
                 }
                 End => {}
+                // Skips only ever jump forward, but the program counter wraps
+                // around, so loops are still possible, and are cut off by the
+                // step limit.
+                SkipLt => {
+                    if self.registers[inst.a] < self.registers[inst.b] {
+                        self.pc += 1
+                    }
+                }
+                SkipEq => {
+                    if self.registers[inst.a] == self.registers[inst.b] {
+                        self.pc += 1
+                    }
+                }
+                FAdd => {
+                    let x = self.guard.float(self.fget(inst.a) + self.fget(inst.b));
+                    self.fset(inst.a, x)
+                }
+                FSub => {
+                    let x = self.guard.float(self.fget(inst.a) - self.fget(inst.b));
+                    self.fset(inst.a, x)
+                }
+                FMul => {
+                    let x = self.guard.float(self.fget(inst.a) * self.fget(inst.b));
+                    self.fset(inst.a, x)
+                }
+                FDiv => {
+                    if let Some(x) = self.guard.fdiv(self.fget(inst.a), self.fget(inst.b)) {
+                        self.fset(inst.a, x)
+                    }
+                }
+                FMov => self.fset(inst.a, self.fget(inst.b)),
+                ItoF => self.fset(inst.a, self.registers[inst.b] as f64),
+                // Out of bounds loads and stores do nothing
+                Load => {
+                    if let Some(w) = self.address(inst.b).map(|i| self.memory[i]) {
+                        set!(w)
+                    }
+                }
+                Store => {
+                    if let Some(i) = self.address(inst.b) {
+                        self.memory[i] = self.registers[inst.a]
+                    }
+                }
+                FtoI => set!(self.fget(inst.b).round() as MachineWord),
+            }
+        }
+
+        /// The index into data memory held in register `reg`, if it's in
+        /// bounds.
+        #[inline]
+        fn address(&self, reg: usize) -> Option<usize> {
+            let i = self.registers[reg];
+            if i >= 0 && (i as usize) < self.memory.len() {
+                Some(i as usize)
+            } else {
+                None
             }
         }
 
         fn flush_registers(&mut self) {
+            self.guard.reset();
             self.registers.iter_mut().for_each(|i| *i = 0);
+            self.float_registers.iter_mut().for_each(|f| *f = 0.0);
+            self.memory.iter_mut().for_each(|w| *w = 0);
         }
 
         fn load_input(&mut self, inputs: &[MachineWord]) {
@@ -212,6 +535,11 @@ pub mod machine {
             (self.return_registers..(self.registers.len()))
                 .zip(inputs.iter())
                 .for_each(|(r, i)| self.set(r, *i));
+            // The float bank gets a copy of the inputs, from its first register
+            self.float_registers
+                .iter_mut()
+                .zip(inputs.iter())
+                .for_each(|(r, i)| *r = *i as f64);
         }
 
         fn exec_insts(&mut self, code: &[Inst]) {
@@ -239,12 +567,31 @@ pub mod machine {
                 );
                 step += 1;
             }
+            self.exhausted = step >= max_steps;
+        }
+
+        /// True if the most recent call to `exec` was cut off by the step
+        /// limit, rather than reaching an `END` instruction.
+        pub fn exhausted(&self) -> bool {
+            self.exhausted
         }
 
         fn return_value(&self) -> &[MachineWord] {
             &self.registers[0..self.return_registers]
         }
 
+        /// Runs the code on float inputs, which are loaded into the float
+        /// bank from its second register, and returns the first.
+        pub fn exec_float(&mut self, code: &[Inst], input: &[f64]) -> f64 {
+            self.flush_registers();
+            let n = self.float_registers.len();
+            (1..n)
+                .zip(input.iter())
+                .for_each(|(r, x)| self.float_registers[r] = *x);
+            self.exec_insts(code);
+            self.fget(0)
+        }
+
         pub fn exec<'a>(&'a mut self, code: &[Inst], input: &[MachineWord]) -> &'a [MachineWord] {
             self.flush_registers();
             self.load_input(input);
@@ -278,6 +625,18 @@ pub struct Creature {
     chromosome_parentage: Vec<usize>,
     chromosome_mutation: Vec<Option<Mutation>>,
     answers: Option<Answer>,
+    /// The number of problems on which execution was cut off by the step
+    /// limit.
+    step_limit_hits: usize,
+    /// The number of arithmetic faults, over all problems
+    arithmetic_faults: usize,
+    /// Marks the instructions that can influence the return registers.
+    /// Those that can't are introns.
+    effective: Vec<bool>,
+    /// The cases the answers were given to, as handed out by the problem
+    /// provider.
+    #[serde(skip)]
+    cases: Option<Arc<Vec<ClassificationProblem>>>,
     #[serde(borrow)]
     pub fitness: Option<Fitness<'static>>,
     tag: u64,
@@ -325,6 +684,23 @@ impl Debug for Creature {
     }
 }
 
+impl Creature {
+    /// The number of instructions that can influence the return registers.
+    pub fn effective_length(&self) -> usize {
+        self.effective.iter().filter(|e| **e).count()
+    }
+
+    fn effective_listing(&self) -> String {
+        self.chromosome
+            .iter()
+            .zip(self.effective.iter())
+            .enumerate()
+            .filter(|(_, (_, e))| **e)
+            .map(|(i, (inst, _))| format!("[{}]  {}\n", i, inst))
+            .collect()
+    }
+}
+
 impl Phenome for Creature {
     type Fitness = Fitness<'static>;
     type Problem = ClassificationProblem;
@@ -359,6 +735,40 @@ impl Phenome for Creature {
         self.answers = Some(answers);
     }
 
+    fn effective_code(&self) -> Option<String> {
+        if self.effective.is_empty() {
+            None
+        } else {
+            Some(self.effective_listing())
+        }
+    }
+
+    fn transpiled_code(&self, config: &Config) -> Option<String> {
+        Some(transpile::linear_gp(&self.chromosome, &config.linear_gp, Interface::Classification))
+    }
+
+    fn parents(&self) -> Vec<String> {
+        self.parents.clone()
+    }
+
+    fn operators(&self) -> Vec<String> {
+        let breeding = match self.parents.len() {
+            0 => None,
+            1 => Some("clone"),
+            _ => Some("crossover"),
+        };
+        breeding
+            .into_iter()
+            .map(str::to_string)
+            .chain(
+                self.chromosome_mutation
+                    .iter()
+                    .filter_map(|m| m.map(mutation_name))
+                    .map(str::to_string),
+            )
+            .collect()
+    }
+
     fn is_goal_reached<'a>(&'a self, config: &'a Config) -> bool {
         if let Some(fitness) = self.scalar_fitness(&config.fitness.priority()) {
             return fitness <= config.fitness.target;
@@ -465,6 +875,10 @@ impl Genome for Creature {
             chromosome_parentage,
             chromosome_mutation: vec![None; length],
             answers: None,
+            step_limit_hits: 0,
+            arithmetic_faults: 0,
+            effective: vec![],
+            cases: None,
             fitness: None,
             tag: rand::random::<u64>(),
             //crossover_mask: 0,
@@ -484,6 +898,10 @@ impl Genome for Creature {
             if !levy_decision(&mut rng, self.len(), config.mutation_exponent) {
                 continue;
             }
+            if self.chromosome_mut()[i].mutate_constant(&config.arithmetic, &mut rng) {
+                self.chromosome_mutation[i] = Some(CONSTANT_MUTATION);
+                continue;
+            }
             let seed = hash_seed(&rng.gen::<u64>());
             let m = self.chromosome_mut()[i].mutate(&config.linear_gp, &seed);
             self.chromosome_mutation[i] = Some(m);
@@ -493,6 +911,20 @@ impl Genome for Creature {
 
 type Mutation = u8;
 
+/// Follows the mutations numbered by `Inst::mutate`
+const CONSTANT_MUTATION: Mutation = 4;
+
+fn mutation_name(mutation: Mutation) -> &'static str {
+    match mutation {
+        0 => "random_op",
+        1 => "increment_a",
+        2 => "increment_b",
+        3 => "swap_registers",
+        CONSTANT_MUTATION => "perturb_constant",
+        _ => "unknown",
+    }
+}
+
 fn report(window: &Window<Creature>, counter: usize, config: &Config) {
     let frame = &window.frame;
     let avg_len = frame.iter().map(|c| c.len()).sum::<usize>() as f64 / frame.len() as f64;
@@ -519,29 +951,25 @@ fn report(window: &Window<Creature>, counter: usize, config: &Config) {
     );
     let soup = window.soup();
     log::info!("soup size: {}", soup.len());
+    if let Some(score) = window.champion_training {
+        log::info!("champion's full training set error rate: {}", score);
+    }
+    if let Some(score) = window.champion_validation {
+        log::info!("champion's validation error rate: {}", score);
+    }
     // TODO export tsv stats here too. generalize a bit.
     if let Some(ref best) = window.best {
-        log::info!("Best: {:?}", best);
-    }
-}
-
-fn parse_data(path: &str) -> Option<Vec<ClassificationProblem>> {
-    if let Ok(mut reader) = csv::ReaderBuilder::new().delimiter(b'\t').from_path(path) {
-        let mut problems = Vec::new();
-        let mut tag = 0;
-        for row in reader.records() {
-            if let Ok(row) = row {
-                let mut vals: Vec<MachineWord> =
-                    row.deserialize(None).expect("Error parsing row in data");
-                let output = vals.pop().expect("Missing output field");
-                let input = vals;
-                problems.push(ClassificationProblem { input, output, tag });
-                tag += 1;
-            }
-        }
-        Some(problems)
-    } else {
-        None
+        if config.linear_gp.print_effective_code {
+            log::info!(
+                "Best: {} (effective code, {} of {} instructions):\n{}",
+                best.name,
+                best.effective_length(),
+                best.len(),
+                best.effective_listing()
+            );
+        } else {
+            log::info!("Best: {:?}", best);
+        }
     }
 }
 
@@ -553,6 +981,7 @@ mod evaluation {
     //#[cfg(not(debug_assertions))]
     use rayon::prelude::*;
 
+    use crate::dataset::Split;
     use crate::examples::linear_gp::machine::Machine;
     use crate::ontogenesis::{Develop, FitnessFn};
     use crate::util::count_min_sketch::CountMinSketch;
@@ -579,12 +1008,27 @@ mod evaluation {
             let (our_tx, rx): (Sender<Creature>, Receiver<Creature>) = channel();
             let config = Arc::new(config.clone());
             let conf = config.clone();
+            let mut provider = get_classification_provider(&config);
             let handle = spawn(move || {
                 // TODO: parameterize sketch construction
 
                 for mut phenome in our_rx.iter() {
-                    if phenome.fitness().is_none() {
-                        phenome = execute(conf.clone(), phenome);
+                    let cases = provider.cases(crate::get_epoch_counter());
+                    // Answers to an earlier epoch's cases are stale
+                    let stale = phenome
+                        .cases
+                        .as_ref()
+                        .map_or(true, |c| !Arc::ptr_eq(c, &cases));
+                    if phenome.fitness().is_none() || stale {
+                        phenome = execute(&conf, phenome, cases.clone());
+                        let failures = phenome
+                            .answers()
+                            .expect("Missing phenotype!")
+                            .iter()
+                            .zip(cases.iter())
+                            .map(|(answer, case)| answer.output != case.output)
+                            .collect::<Vec<bool>>();
+                        provider.record_failures(&failures);
                     }
 
                     our_tx.send(phenome).expect("Channel failure");
@@ -603,10 +1047,14 @@ mod evaluation {
         }
     }
 
-    // It's important that the problems in the pheno are returned sorted
-    pub fn execute(config: Arc<Config>, mut creature: Creature) -> Creature {
-        let problems = config.problems.as_ref().expect("No problems!");
-
+    /// Runs the code on each problem, returning its answers, sorted by tag,
+    /// along with whether execution was cut off by the step limit, and the
+    /// number of arithmetic faults.
+    fn run_problems(
+        config: &Config,
+        code: &[machine::Inst],
+        problems: &[ClassificationProblem],
+    ) -> Vec<(ClassificationProblem, bool, usize)> {
         //#[cfg(debug_assertions)]
         //let iterator = problems.iter();
         // #[cfg(not(debug_assertions))]
@@ -616,30 +1064,54 @@ mod evaluation {
             .map(
                 |ClassificationProblem {
                      input,
-                     output: _expected,
+                     output: expected,
                      tag,
                  }| {
                     // TODO: note that we have the expected value here. maybe this
                     // would be a good place to call the fitness function, too.
                     // TODO: is it worth creating a new machine per-thread?
                     // Probably not when it comes to unicorn, but for this, yeah.
-                    let mut machine = Machine::new(&config.linear_gp);
-                    let return_regs = machine.exec(creature.chromosome(), &input);
-                    let output = (0..return_regs.len())
-                        .map(|i| return_regs[i])
-                        .fold(0, i32::max);
-
-                    ClassificationProblem {
-                        input: input.clone(),
-                        output,
-                        tag: *tag,
-                    }
+                    let mut machine =
+                        Machine::new(&config.linear_gp).with_arithmetic(&config.arithmetic);
+                    let return_regs = machine.exec(code, &input);
+                    // A single output is read as the greatest value in the
+                    // return registers, and a vector as the registers themselves.
+                    let output = if expected.len() == 1 {
+                        vec![return_regs.iter().cloned().fold(0, i32::max)]
+                    } else {
+                        return_regs[..expected.len()].to_vec()
+                    };
+
+                    (
+                        ClassificationProblem {
+                            input: input.clone(),
+                            output,
+                            tag: *tag,
+                        },
+                        machine.exhausted(),
+                        machine.faults(),
+                    )
                 },
             )
-            .collect::<Vec<ClassificationProblem>>();
+            .collect::<Vec<(ClassificationProblem, bool, usize)>>();
         // Sort by tag to avoid any non-seeded randomness
-        results.sort_by_key(|p| p.tag);
-        creature.store_answers(results);
+        results.sort_by_key(|(p, _, _)| p.tag);
+        results
+    }
+
+    // It's important that the problems in the pheno are returned sorted
+    pub fn execute(
+        config: &Config,
+        mut creature: Creature,
+        cases: Arc<Vec<ClassificationProblem>>,
+    ) -> Creature {
+        let results = run_problems(config, creature.chromosome(), &cases);
+        creature.step_limit_hits = results.iter().filter(|(_, exhausted, _)| *exhausted).count();
+        creature.arithmetic_faults = results.iter().map(|(_, _, faults)| faults).sum();
+        creature.effective =
+            machine::effective_instructions(creature.chromosome(), &config.linear_gp);
+        creature.store_answers(results.into_iter().map(|(p, _, _)| p).collect());
+        creature.cases = Some(cases);
         creature
     }
 
@@ -648,32 +1120,77 @@ mod evaluation {
         _sketch: &mut CountMinSketch,
         config: Arc<Config>,
     ) -> Creature {
-        #[allow(clippy::unnecessary_fold)]
-        let score = creature
-            .answers()
-            .as_ref()
-            .expect("Missing phenotype!")
-            .iter()
-            .zip(config.problems.as_ref().expect("no problems!").iter())
-            .filter_map(|(result, expected)| {
-                assert_eq!(result.tag, expected.tag);
-                // Simply counting errors.
-                // TODO: consider trying distance metrics
-                if result.output == expected.output {
-                    None
-                } else {
-                    Some(1)
-                }
-            })
-            .fold(0, |a, b| a + b);
-        let mut fitness = Weighted::new(&config.fitness.weighting);
+        let answers = creature.answers().as_ref().expect("Missing phenotype!");
+        let expected: &[ClassificationProblem] = match creature.cases.as_ref() {
+            Some(cases) => &cases[..],
+            None => &config.problems.as_ref().expect("no problems!")[..],
+        };
+        let (score, output_errors) = count_errors(answers, expected, config.data.num_outputs());
+        let mut fitness = config.fitness.weighted();
         fitness.insert("error_rate", score as f64);
+        for (key, errors) in config
+            .linear_gp
+            .output_error_keys
+            .iter()
+            .zip(output_errors.iter())
+        {
+            fitness.insert(*key, *errors as f64);
+        }
+        fitness.insert("step_limit_hits", creature.step_limit_hits as f64);
+        if config.arithmetic.penalized() {
+            fitness.insert("arithmetic_faults", creature.arithmetic_faults as f64);
+        }
+        fitness.insert("effective_length", creature.effective_length() as f64);
         // TODO: refactor types
         //creature.set_fitness((fitness, 0.0, 0.0, len));
         creature.set_fitness(fitness);
         creature
     }
 
+    /// The number of problems answered wrongly, and the number of errors on
+    /// each output.
+    fn count_errors(
+        answers: &[ClassificationProblem],
+        expected: &[ClassificationProblem],
+        num_outputs: usize,
+    ) -> (usize, Vec<usize>) {
+        // Simply counting errors.
+        // TODO: consider trying distance metrics
+        let mut output_errors = vec![0; num_outputs];
+        let mut score = 0;
+        for (result, expected) in answers.iter().zip(expected.iter()) {
+            assert_eq!(result.tag, expected.tag);
+            if result.output != expected.output {
+                score += 1;
+            }
+            for (i, (r, e)) in result.output.iter().zip(expected.output.iter()).enumerate() {
+                if r != e {
+                    output_errors[i] += 1;
+                }
+            }
+        }
+        (score, output_errors)
+    }
+
+    /// The fraction of the problems in a held-out split that the creature
+    /// answers wrongly.
+    pub fn holdout_error_rate(creature: &Creature, split: Split, config: &Config) -> Option<f64> {
+        let problems = match split {
+            Split::Train => config.problems.as_ref(),
+            Split::Validation => config.validation_problems.as_ref(),
+            Split::Test => config.test_problems.as_ref(),
+        }?;
+        if problems.is_empty() {
+            return None;
+        }
+        let answers = run_problems(config, creature.chromosome(), problems)
+            .into_iter()
+            .map(|(p, _, _)| p)
+            .collect::<Vec<ClassificationProblem>>();
+        let (errors, _) = count_errors(&answers, problems, config.data.num_outputs());
+        Some(errors as f64 / problems.len() as f64)
+    }
+
     impl Develop<Creature> for Evaluator {
         fn develop(&self, genome: Creature) -> Creature {
             self.tx.send(genome).expect("tx failure");
@@ -706,22 +1223,51 @@ mod evaluation {
 }
 
 fn prepare(mut config: Config) -> (Config, Observer<Creature>, evaluation::Evaluator) {
-    let problems = parse_data(&config.data.path);
-    assert!(problems.is_some());
+    let num_outputs = config.data.num_outputs();
+    let from_dataset = config.provider.name.is_empty() || config.provider.name == "dataset";
+    let problems = if from_dataset {
+        let dataset = Dataset::load(&config.data, config.random_seed)
+            // values that don't fit in a machine word are reported, not truncated
+            .and_then(|d| d.convert(|v| v.as_i64().and_then(|n| MachineWord::try_from(n).ok())))
+            .expect("Failed to load the dataset");
+        log::info!(
+            "{} training, {} validation, and {} test problems",
+            dataset.train.len(),
+            dataset.validation.len(),
+            dataset.test.len()
+        );
+        config.validation_problems = Some(dataset.validation);
+        config.test_problems = Some(dataset.test);
+        Some(dataset.train)
+    } else {
+        // The registers are sized to fit the first epoch's cases
+        Some(get_classification_provider(&config).cases(0).to_vec())
+    };
     // figure out the number of return registers needed
     // FIXME: refactor duplicated code out of this constructor
 
-    let mut return_registers = problems
-        .as_ref()
-        .unwrap()
-        .iter()
-        .map(|p| p.output)
-        .collect::<std::collections::HashSet<i32>>()
-        .len();
+    let mut return_registers = if num_outputs == 1 {
+        problems
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|p| p.output[0])
+            .collect::<std::collections::HashSet<i32>>()
+            .len()
+    } else {
+        num_outputs
+    };
+    if num_outputs > 1 {
+        config.linear_gp.output_error_keys = (0..num_outputs)
+            .map(|i| &*Box::leak(format!("output_{}_error", i).into_boxed_str()))
+            .collect();
+    }
     // and how many input registers
     let input_registers = problems.as_ref().unwrap()[0].input.len();
 
-    config.problems = problems;
+    if from_dataset {
+        config.problems = problems;
+    }
     if let Some(r) = config.linear_gp.return_registers {
         return_registers = std::cmp::max(return_registers, r);
     }
@@ -736,7 +1282,8 @@ fn prepare(mut config: Config) -> (Config, Observer<Creature>, evaluation::Evalu
     log::info!("Config: {:#?}", config);
     let report_fn: ReportFn<_> = Box::new(report);
     let fitness_fn: FitnessFn<Creature, _, _> = Box::new(evaluation::fitness_function);
-    let observer = Observer::spawn(&config, report_fn);
+    let holdout_fn: HoldoutFn<_> = Box::new(evaluation::holdout_error_rate);
+    let observer = Observer::spawn_with_holdout(&config, report_fn, holdout_fn);
     let evaluator = evaluation::Evaluator::spawn(&config, fitness_fn);
     (config, observer, evaluator)
 }
@@ -782,3 +1329,158 @@ pub fn run(config: Config) {
         sel => unimplemented!("{:?} not implemented for {:?}", sel, config.job),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::configure::{ArithmeticConfig, FaultHandling, LinearGpConfig};
+
+    use super::machine::{effective_instructions, Inst, Machine, Op};
+
+    fn inst(op: Op, a: usize, b: usize) -> Inst {
+        Inst { op, a, b }
+    }
+
+    #[test]
+    fn test_float_registers() {
+        let config = LinearGpConfig {
+            max_steps: 100,
+            num_registers: Some(4),
+            return_registers: Some(1),
+            num_float_registers: 3,
+            ..Default::default()
+        };
+        let mut machine = Machine::new(&config);
+        // R0 = round(F0 / 2 * 2 + F0 / 2), with F0 = 7
+        let code = vec![
+            inst(Op::ItoF, 2, 2),
+            inst(Op::FDiv, 0, 2),
+            inst(Op::FMov, 1, 0),
+            inst(Op::FMul, 0, 2),
+            inst(Op::FAdd, 0, 1),
+            inst(Op::FtoI, 0, 0),
+            inst(Op::End, 0, 0),
+        ];
+        assert_eq!(machine.exec(&code, &[7, 2]), &[11]);
+    }
+
+    #[test]
+    fn test_arithmetic_faults() {
+        let config = LinearGpConfig {
+            max_steps: 100,
+            num_registers: Some(3),
+            return_registers: Some(1),
+            ..Default::default()
+        };
+        // R0 = R1 / R2
+        let code = vec![inst(Op::Mov, 0, 1), inst(Op::Div, 0, 2), inst(Op::End, 0, 0)];
+        let mut machine = Machine::new(&config);
+        // natively, division by zero divides by one, and isn't counted
+        assert_eq!(machine.exec(&code, &[7, 0]), &[7]);
+        assert_eq!(machine.faults(), 0);
+        let penalized = ArithmeticConfig {
+            division_by_zero: FaultHandling::Penalize,
+            ..Default::default()
+        };
+        let mut machine = Machine::new(&config).with_arithmetic(&penalized);
+        assert_eq!(machine.exec(&code, &[7, 0]), &[7]);
+        assert_eq!(machine.faults(), 1);
+        let protected = ArithmeticConfig {
+            division_by_zero: FaultHandling::Protect,
+            ..Default::default()
+        };
+        let mut machine = Machine::new(&config).with_arithmetic(&protected);
+        assert_eq!(machine.exec(&code, &[7, 0]), &[1]);
+        assert_eq!(machine.exec(&code, &[7, 2]), &[3]);
+        assert_eq!(machine.faults(), 0);
+    }
+
+    #[test]
+    fn test_skips() {
+        let config = LinearGpConfig {
+            max_steps: 100,
+            num_registers: Some(4),
+            return_registers: Some(1),
+            ..Default::default()
+        };
+        let mut machine = Machine::new(&config);
+        // R0 = max(R1, R2)
+        let code = vec![
+            inst(Op::Mov, 0, 1),
+            inst(Op::SkipLt, 2, 1),
+            inst(Op::Mov, 0, 2),
+            inst(Op::End, 0, 0),
+        ];
+        assert_eq!(machine.exec(&code, &[3, 5]), &[5]);
+        assert_eq!(machine.exec(&code, &[5, 3]), &[5]);
+        assert!(!machine.exhausted());
+
+        // Skipping the END, the program loops until the step limit
+        let code = vec![
+            inst(Op::Add, 0, 1),
+            inst(Op::SkipEq, 0, 0),
+            inst(Op::End, 0, 0),
+        ];
+        assert_eq!(machine.exec(&code, &[1]), &[50]);
+        assert!(machine.exhausted());
+    }
+
+    #[test]
+    fn test_effective_instructions() {
+        let config = LinearGpConfig {
+            max_steps: 100,
+            num_registers: Some(4),
+            return_registers: Some(1),
+            ..Default::default()
+        };
+        let code = vec![
+            inst(Op::Set(3), 2, 0),
+            inst(Op::Add, 3, 1), // intron: R3 is never read
+            inst(Op::Mov, 0, 2),
+            inst(Op::Set(1), 2, 0), // intron: R2 is dead after the MOV
+            inst(Op::End, 0, 0),
+            inst(Op::Mov, 0, 1), // unreachable
+        ];
+        assert_eq!(
+            effective_instructions(&code, &config),
+            vec![true, false, true, false, true, false]
+        );
+
+        // A guarded write doesn't kill R0, so the first MOV stays effective
+        let code = vec![
+            inst(Op::Mov, 0, 1),
+            inst(Op::SkipLt, 1, 2),
+            inst(Op::Mov, 0, 2),
+            inst(Op::End, 0, 0),
+        ];
+        assert_eq!(
+            effective_instructions(&code, &config),
+            vec![true, true, true, true]
+        );
+    }
+
+    #[test]
+    fn test_memory() {
+        let config = LinearGpConfig {
+            max_steps: 100,
+            num_registers: Some(4),
+            return_registers: Some(1),
+            memory_size: 4,
+            ..Default::default()
+        };
+        let mut machine = Machine::new(&config);
+        // mem[R2] = R1; R0 = mem[R2] + mem[R3], where R3 is out of bounds
+        let code = vec![
+            inst(Op::Store, 1, 2),
+            inst(Op::Load, 0, 2),
+            inst(Op::Load, 1, 3),
+            inst(Op::Add, 0, 1),
+            inst(Op::End, 0, 0),
+        ];
+        assert_eq!(machine.exec(&code, &[7, 3, 9]), &[14]);
+        assert_eq!(machine.exec(&code, &[7, 3, -1]), &[14]);
+        assert_eq!(
+            effective_instructions(&code, &config),
+            vec![true, true, true, true, true]
+        );
+    }
+}
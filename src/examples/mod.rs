@@ -1,2 +1,6 @@
+pub mod benchmark;
+pub mod boolean;
+pub mod byte_sequence;
 pub mod hello_world;
 pub mod linear_gp;
+pub mod regression;
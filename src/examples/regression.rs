@@ -0,0 +1,443 @@
+//! Symbolic regression, by either backend, on the cases of a benchmark, or
+//! of a TSV file. See `benchmark` for the scaffolding of the job.
+use std::f64::consts::PI;
+use std::fmt;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::configure::{ArithmeticConfig, Config, GpBackend, RegressionBenchmark, RegressionCase};
+use crate::error::Error;
+use crate::evolution::{Genome, Phenome};
+use crate::examples::benchmark::{self, Creature, Fitness, Problem};
+use crate::examples::linear_gp::machine::{Inst, Machine};
+use crate::roper::push;
+use crate::transpile::{self, Interface};
+use crate::util::random::{hash_seed, hash_seed_rng, Prng};
+
+/// `n` points, with each variable drawn uniformly from its range.
+fn uniform(rng: &mut Prng, n: usize, ranges: &[(f64, f64)]) -> Vec<Vec<f64>> {
+    (0..n)
+        .map(|_| {
+            ranges
+                .iter()
+                .map(|(lo, hi)| rng.gen_range(*lo, *hi))
+                .collect()
+        })
+        .collect()
+}
+
+/// Evenly spaced points of a single variable, from `lo` to `hi` inclusive.
+fn grid(lo: f64, hi: f64, step: f64) -> Vec<Vec<f64>> {
+    let n = ((hi - lo) / step).round() as usize + 1;
+    (0..n).map(|i| vec![lo + i as f64 * step]).collect()
+}
+
+fn target(benchmark: RegressionBenchmark, v: &[f64]) -> f64 {
+    use RegressionBenchmark::*;
+    let x = v[0];
+    match benchmark {
+        Koza1 => x.powi(4) + x.powi(3) + x.powi(2) + x,
+        Koza2 => x.powi(5) - 2.0 * x.powi(3) + x,
+        Koza3 => x.powi(6) - 2.0 * x.powi(4) + x.powi(2),
+        Keijzer1 | Keijzer2 | Keijzer3 => 0.3 * x * (2.0 * PI * x).sin(),
+        Keijzer4 => x.powi(3) * (-x).exp() * x.cos() * x.sin() * (x.sin().powi(2) * x.cos() - 1.0),
+        Keijzer5 => 30.0 * x * v[2] / ((x - 10.0) * v[1].powi(2)),
+        Keijzer6 => (1..=(x as usize)).map(|i| 1.0 / i as f64).sum(),
+        Keijzer7 => x.ln(),
+        Keijzer8 => x.sqrt(),
+        Keijzer9 => x.asinh(),
+        Keijzer10 => x.powf(v[1]),
+        Keijzer11 => x * v[1] + ((x - 1.0) * (v[1] - 1.0)).sin(),
+        Keijzer12 => x.powi(4) - x.powi(3) + v[1].powi(2) / 2.0 - v[1],
+        Keijzer13 => 6.0 * x.sin() * v[1].cos(),
+        Keijzer14 => 8.0 / (2.0 + x.powi(2) + v[1].powi(2)),
+        Keijzer15 => x.powi(3) / 5.0 + v[1].powi(3) / 2.0 - v[1] - x,
+    }
+}
+
+/// Generates the training cases for a benchmark, sampled as prescribed in
+/// the benchmark's definition.
+pub fn benchmark_cases(benchmark: RegressionBenchmark, seed: u64) -> Vec<RegressionCase> {
+    use RegressionBenchmark::*;
+    let mut rng = hash_seed_rng(&seed);
+    let points = match benchmark {
+        Koza1 | Koza2 | Koza3 => uniform(&mut rng, 20, &[(-1.0, 1.0)]),
+        Keijzer1 => grid(-1.0, 1.0, 0.1),
+        Keijzer2 => grid(-2.0, 2.0, 0.1),
+        Keijzer3 => grid(-3.0, 3.0, 0.1),
+        Keijzer4 => grid(0.0, 10.0, 0.05),
+        Keijzer5 => uniform(&mut rng, 1000, &[(-1.0, 1.0), (1.0, 2.0), (-1.0, 1.0)]),
+        Keijzer6 => grid(1.0, 50.0, 1.0),
+        Keijzer7 => grid(1.0, 100.0, 1.0),
+        Keijzer8 | Keijzer9 => grid(0.0, 100.0, 1.0),
+        Keijzer10 => uniform(&mut rng, 100, &[(0.0, 1.0), (0.0, 1.0)]),
+        Keijzer11 | Keijzer12 | Keijzer13 | Keijzer14 | Keijzer15 => {
+            uniform(&mut rng, 20, &[(-3.0, 3.0), (-3.0, 3.0)])
+        }
+    };
+    points
+        .into_iter()
+        .map(|input| RegressionCase {
+            output: target(benchmark, &input),
+            input,
+        })
+        .collect()
+}
+
+/// Reads the cases from a TSV file, whose last column is the target.
+fn parse_data(path: &str) -> Result<Vec<RegressionCase>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .from_path(path)
+        .map_err(|e| {
+            Error::Parsing(format!(
+                "Failed to read regression data from {}: {}",
+                path, e
+            ))
+        })?;
+    let mut cases = Vec::new();
+    for row in reader.records() {
+        let mut input: Vec<f64> = row?.deserialize(None)?;
+        let output = input
+            .pop()
+            .ok_or_else(|| Error::Parsing(format!("Empty row in regression data {}", path)))?;
+        cases.push(RegressionCase { input, output });
+    }
+    Ok(cases)
+}
+
+/// A gene for either backend. The genes of a creature all belong to the
+/// same backend.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Gene {
+    Linear(Inst),
+    Push(push::Op),
+}
+
+impl Gene {
+    fn random<R: Rng>(config: &Config, rng: &mut R) -> Self {
+        match config.regression.backend {
+            GpBackend::LinearGp => Gene::Linear(Inst::random(&config.linear_gp)),
+            GpBackend::Push => {
+                Gene::Push(push::random_numeric_op(rng, config.push_vm.literal_rate))
+            }
+        }
+    }
+
+    /// Perturbs the gene's constant, if it has one, at the configured rate,
+    /// returning whether it did.
+    pub fn mutate_constant<R: Rng>(&mut self, config: &ArithmeticConfig, rng: &mut R) -> bool {
+        match self {
+            Gene::Linear(inst) => inst.mutate_constant(config, rng),
+            Gene::Push(op) => push::mutate_constant(op, config, rng),
+        }
+    }
+
+    /// The linear instructions among the genes
+    pub fn linear_code(genes: &[Gene]) -> Vec<Inst> {
+        genes
+            .iter()
+            .filter_map(|g| match g {
+                Gene::Linear(inst) => Some(*inst),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The push ops among the genes
+    pub fn push_code(genes: &[Gene]) -> Vec<push::Op> {
+        genes
+            .iter()
+            .filter_map(|g| match g {
+                Gene::Push(op) => Some(op.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Writes the genes, one to a line.
+    pub fn describe(genes: &[Gene], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, gene) in genes.iter().enumerate() {
+            match gene {
+                Gene::Linear(inst) => writeln!(f, "[{}]  {}", i, inst)?,
+                Gene::Push(op) => writeln!(f, "[{}]  {:?}", i, op)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs the program on a single case. A program that leaves no float
+/// behind answers with NaN.
+pub fn execute(config: &Config, chromosome: &[Gene], input: &[f64]) -> f64 {
+    execute_with_faults(config, chromosome, input).0
+}
+
+/// As `execute`, but also returns the number of arithmetic faults.
+fn execute_with_faults(config: &Config, chromosome: &[Gene], input: &[f64]) -> (f64, usize) {
+    match config.regression.backend {
+        GpBackend::LinearGp => {
+            let code = Gene::linear_code(chromosome);
+            let mut machine = Machine::new(&config.linear_gp).with_arithmetic(&config.arithmetic);
+            let output = machine.exec_float(&code, input);
+            (output, machine.faults())
+        }
+        GpBackend::Push => {
+            let code = Gene::push_code(chromosome);
+            let mut machine = push::MachineState::default().with_arithmetic(&config.arithmetic);
+            let output = machine
+                .exec_numeric(&code, input, config.push_vm.max_steps)
+                .unwrap_or(std::f64::NAN);
+            (output, machine.faults())
+        }
+    }
+}
+
+/// A case is hit, as Koza counts hits, if the answer is within this of the
+/// target.
+const HIT_TOLERANCE: f64 = 0.01;
+
+/// What a program made of the cases
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Answers {
+    /// The difference of the program's answer to each case from its target
+    errors: Vec<f64>,
+    /// The number of arithmetic faults, over all cases
+    arithmetic_faults: usize,
+}
+
+pub struct Regression;
+
+impl Problem for Regression {
+    type Allele = Gene;
+    type Outcome = Answers;
+
+    fn prepare(config: &mut Config) -> Result<(), Error> {
+        config.regression.cases = match config.regression.benchmark {
+            Some(benchmark) => benchmark_cases(benchmark, config.random_seed),
+            None => parse_data(&config.data.path)?,
+        };
+        let num_inputs = match config.regression.cases.first() {
+            Some(case) => case.input.len(),
+            None => {
+                return Err(Error::Parsing(format!(
+                    "There are no regression cases in {}",
+                    config.data.path
+                )))
+            }
+        };
+        // The linear backend reads its inputs from the float bank, starting at
+        // the second register, and answers in the first. Operands are drawn
+        // modulo the number of integer registers, so there must be as many of
+        // those, for every float register to be addressed.
+        let linear_gp = &mut config.linear_gp;
+        let num_float_registers = linear_gp.num_float_registers.max(num_inputs + 2);
+        linear_gp.num_float_registers = num_float_registers;
+        linear_gp.num_registers = Some(
+            linear_gp
+                .num_registers
+                .unwrap_or(0)
+                .max(4)
+                .max(num_float_registers),
+        );
+        linear_gp.return_registers = Some(linear_gp.return_registers.unwrap_or(1));
+        log::info!(
+            "{} regression cases, with {} inputs",
+            config.regression.cases.len(),
+            num_inputs
+        );
+        Ok(())
+    }
+
+    fn random_allele(config: &Config, rng: &mut Prng) -> Gene {
+        Gene::random(config, rng)
+    }
+
+    fn mutate_allele(config: &Config, gene: &mut Gene, rng: &mut Prng) {
+        if gene.mutate_constant(&config.arithmetic, rng) {
+            return;
+        }
+        match gene {
+            Gene::Linear(inst) => {
+                let seed = hash_seed(&rng.gen::<u64>());
+                inst.mutate(&config.linear_gp, &seed);
+            }
+            gene => *gene = Gene::random(config, rng),
+        }
+    }
+
+    fn develop(config: &Config, creature: &Creature<Self>) -> Answers {
+        let results = config
+            .regression
+            .cases
+            .iter()
+            .map(|case| {
+                let (output, faults) =
+                    execute_with_faults(config, creature.chromosome(), &case.input);
+                (output - case.output, faults)
+            })
+            .collect::<Vec<(f64, usize)>>();
+        Answers {
+            arithmetic_faults: results.iter().map(|(_, faults)| faults).sum(),
+            errors: results.into_iter().map(|(error, _)| error).collect(),
+        }
+    }
+
+    /// Scores the answers by their root mean squared error, `rmse`, and mean
+    /// absolute error, `mae`. Answers that aren't finite numbers get the
+    /// worst possible score.
+    fn score(config: &Config, answers: &Answers) -> Fitness<'static> {
+        let errors = &answers.errors;
+        let n = errors.len().max(1) as f64;
+        let (rmse, mae) = if errors.iter().all(|e| e.is_finite()) {
+            (
+                (errors.iter().map(|e| e * e).sum::<f64>() / n).sqrt(),
+                errors.iter().map(|e| e.abs()).sum::<f64>() / n,
+            )
+        } else {
+            (std::f64::MAX, std::f64::MAX)
+        };
+        let mut fitness = config.fitness.weighted();
+        fitness.insert("rmse", rmse);
+        fitness.insert("mae", mae);
+        if config.arithmetic.penalized() {
+            fitness.insert("arithmetic_faults", answers.arithmetic_faults as f64);
+        }
+        fitness
+    }
+
+    fn fails(answers: &Answers, case: usize) -> bool {
+        answers
+            .errors
+            .get(case)
+            .map_or(true, |e| e.is_nan() || e.abs() >= HIT_TOLERANCE)
+    }
+
+    fn describe(genes: &[Gene], _answers: &Answers, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Gene::describe(genes, f)
+    }
+
+    fn transpiled_code(config: &Config, creature: &Creature<Self>) -> Option<String> {
+        match config.regression.backend {
+            GpBackend::LinearGp => {
+                let code = Gene::linear_code(creature.chromosome());
+                Some(transpile::linear_gp(
+                    &code,
+                    &config.linear_gp,
+                    Interface::Numeric,
+                ))
+            }
+            GpBackend::Push => {
+                let code = Gene::push_code(creature.chromosome());
+                let num_inputs = config.regression.cases.first()?.input.len();
+                transpile::push(
+                    &code,
+                    num_inputs,
+                    config.push_vm.max_steps,
+                    Interface::Numeric,
+                )
+                .map_err(|e| log::debug!("Can't lower {}: {:?}", creature.name(), e))
+                .ok()
+            }
+        }
+    }
+}
+
+pub fn run(config: Config) -> Result<(), Error> {
+    benchmark::run::<Regression>(config)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::configure::LinearGpConfig;
+    use crate::examples::linear_gp::machine::Op;
+
+    use super::*;
+
+    #[test]
+    fn test_benchmark_cases() {
+        let cases = benchmark_cases(RegressionBenchmark::Koza1, 1);
+        assert_eq!(cases.len(), 20);
+        assert!(cases.iter().all(|c| c.input[0] >= -1.0 && c.input[0] < 1.0));
+        let cases = benchmark_cases(RegressionBenchmark::Keijzer6, 1);
+        assert_eq!(cases.len(), 50);
+        assert!((cases[2].output - (1.0 + 1.0 / 2.0 + 1.0 / 3.0)).abs() < 1e-9);
+        let cases = benchmark_cases(RegressionBenchmark::Keijzer1, 1);
+        assert_eq!(cases.len(), 21);
+    }
+
+    #[test]
+    fn test_prepare_without_cases() {
+        let path =
+            std::env::temp_dir().join(format!("berbalang_test_{}.tsv", rand::random::<u64>()));
+        std::fs::write(&path, "x\ty\n").unwrap();
+        let mut config = Config::default();
+        config.data.path = path.to_string_lossy().into_owned();
+        assert!(Regression::prepare(&mut config).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_prepare_registers() {
+        let path =
+            std::env::temp_dir().join(format!("berbalang_test_{}.tsv", rand::random::<u64>()));
+        std::fs::write(&path, "a\tb\tc\td\te\ty\n1\t2\t3\t4\t5\t15\n").unwrap();
+        let mut config = Config::default();
+        config.data.path = path.to_string_lossy().into_owned();
+        Regression::prepare(&mut config).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // the last of the five inputs is in float register 5
+        assert_eq!(config.linear_gp.num_float_registers, 7);
+        assert_eq!(config.linear_gp.num_registers, Some(7));
+    }
+
+    #[test]
+    fn test_backends() {
+        let mut config = Config::default();
+        config.linear_gp = LinearGpConfig {
+            max_steps: 100,
+            num_registers: Some(4),
+            return_registers: Some(1),
+            num_float_registers: 3,
+            ..Default::default()
+        };
+        config.push_vm.max_steps = 100;
+        // x * x + x
+        let linear = vec![
+            Gene::Linear(Inst {
+                op: Op::FMov,
+                a: 0,
+                b: 1,
+            }),
+            Gene::Linear(Inst {
+                op: Op::FMul,
+                a: 0,
+                b: 1,
+            }),
+            Gene::Linear(Inst {
+                op: Op::FAdd,
+                a: 0,
+                b: 1,
+            }),
+            Gene::Linear(Inst {
+                op: Op::End,
+                a: 0,
+                b: 0,
+            }),
+        ];
+        config.regression.backend = GpBackend::LinearGp;
+        assert_eq!(execute(&config, &linear, &[3.0]), 12.0);
+
+        // the exec stack runs from the end of the program
+        let push = vec![
+            Gene::Push(push::Op::FloatAdd),
+            Gene::Push(push::Op::FloatMul),
+            Gene::Push(push::Op::Dup(push::Type::Float)),
+            Gene::Push(push::Op::Dup(push::Type::Float)),
+        ];
+        config.regression.backend = GpBackend::Push;
+        assert_eq!(execute(&config, &push, &[3.0]), 12.0);
+        assert!(execute(&config, &[], &[]).is_nan());
+    }
+}
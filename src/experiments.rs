@@ -0,0 +1,297 @@
+//! Compares the results of many runs. The runs are found by scanning the
+//! directory tree laid out by `Config::set_data_directory`, by default as
+//!
+//! ```text
+//! {data_directory}/berbalang/{job}/{selection}/{yyyy}/{mm}/{dd}/{population}/island_{n}
+//! ```
+//!
+//! and grouped into configurations by job, selection, and the settings in
+//! the `config.toml` copied into each population's directory, other than
+//! the random seed. Each run's
+//! result is the lowest value a column of one of the islands' statistics
+//! files reached, on any island -- by default, the `min_fitness` column of
+//! `window_statistics.csv`. The configurations are then tabulated, and
+//! compared pairwise with Mann-Whitney U tests.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::util::statistics::{mann_whitney_u, median};
+
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub configuration: String,
+    pub population: String,
+    pub path: PathBuf,
+    pub result: f64,
+}
+
+/// Finds the population directories -- those with `island_*`
+/// subdirectories -- under `root`.
+fn find_populations(root: &Path, found: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let mut has_islands = false;
+    let mut subdirs = Vec::new();
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let is_island = path
+            .file_name()
+            .map(|n| n.to_string_lossy().starts_with("island_"))
+            .unwrap_or(false);
+        if is_island {
+            has_islands = true;
+        } else {
+            subdirs.push(path);
+        }
+    }
+    if has_islands {
+        found.push(root.to_path_buf());
+    } else {
+        for dir in subdirs {
+            find_populations(&dir, found)?;
+        }
+    }
+    Ok(())
+}
+
+/// Hashes a `config.toml`, leaving out its `random_seed`, and the
+/// observer's `population_name` and data directories, so that runs
+/// differing only in their seeds and names, as the replicas of a sweep do,
+/// count as runs of the same configuration.
+fn config_hash(bytes: &[u8]) -> u32 {
+    let parsed = std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|text| toml::from_str::<toml::Value>(text).ok());
+    match parsed {
+        Some(mut value) => {
+            if let Some(table) = value.as_table_mut() {
+                table.remove("random_seed");
+                if let Some(observer) = table.get_mut("observer").and_then(|o| o.as_table_mut()) {
+                    observer.remove("population_name");
+                    observer.remove("data_directory");
+                    observer.remove("full_data_directory");
+                }
+            }
+            seahash::hash(value.to_string().as_bytes()) as u32
+        }
+        None => seahash::hash(bytes) as u32,
+    }
+}
+
+/// Labels the population's configuration with its job and selection, taken
+/// from its position in the tree, and a hash of its `config.toml`, if it has
+/// one.
+fn configuration_label(population: &Path) -> String {
+    let components = population
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<String>>();
+    // .../{job}/{selection}/{yyyy}/{mm}/{dd}/{population}
+    let n = components.len();
+    let (job, selection) = if n >= 6 {
+        (components[n - 6].as_str(), components[n - 5].as_str())
+    } else {
+        ("?", "?")
+    };
+    let config_hash = fs::read(population.join("config.toml"))
+        .map(|bytes| format!("{:08x}", config_hash(&bytes)))
+        .unwrap_or_else(|_| "no-config".to_string());
+    format!("{}/{}/{}", job, selection, config_hash)
+}
+
+/// The lowest value the column reached in the file.
+fn column_min(path: &Path, column: &str) -> Result<Option<f64>, Error> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let index = match reader.headers()?.iter().position(|h| h == column) {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+    let mut min: Option<f64> = None;
+    for record in reader.records() {
+        if let Some(x) = record?.get(index).and_then(|x| x.parse::<f64>().ok()) {
+            if !x.is_nan() {
+                min = Some(min.map_or(x, |m| m.min(x)));
+            }
+        }
+    }
+    Ok(min)
+}
+
+/// Collects the results of every run under `root`, skipping those with no
+/// result in the given statistics file and column.
+pub fn collect_runs<P: AsRef<Path>>(root: P, file: &str, column: &str) -> Result<Vec<Run>, Error> {
+    let mut populations = Vec::new();
+    find_populations(root.as_ref(), &mut populations)?;
+    let mut runs = Vec::new();
+    for population in populations {
+        let mut result: Option<f64> = None;
+        for entry in fs::read_dir(&population)? {
+            let path = entry?.path().join(format!("{}_statistics.csv", file));
+            if path.exists() {
+                if let Some(x) = column_min(&path, column)? {
+                    result = Some(result.map_or(x, |r| r.min(x)));
+                }
+            }
+        }
+        match result {
+            Some(result) => runs.push(Run {
+                configuration: configuration_label(&population),
+                population: population
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                path: population,
+                result,
+            }),
+            None => log::warn!("No {} in {} for {:?}", column, file, population),
+        }
+    }
+    Ok(runs)
+}
+
+pub fn group_runs(runs: &[Run]) -> BTreeMap<&str, Vec<f64>> {
+    let mut groups: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+    for run in runs {
+        groups
+            .entry(run.configuration.as_str())
+            .or_default()
+            .push(run.result);
+    }
+    groups
+}
+
+/// Renders the summary of each configuration, and the pairwise comparisons
+/// between them, as tab-separated tables.
+pub fn comparison_tables(groups: &BTreeMap<&str, Vec<f64>>) -> String {
+    let mut out = String::from("configuration\truns\tmin\tmedian\tmean\tmax\n");
+    for (label, results) in groups.iter() {
+        let mean = results.iter().sum::<f64>() / results.len() as f64;
+        out.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\n",
+            label,
+            results.len(),
+            results.iter().cloned().fold(std::f64::INFINITY, f64::min),
+            median(results),
+            mean,
+            results
+                .iter()
+                .cloned()
+                .fold(std::f64::NEG_INFINITY, f64::max),
+        ));
+    }
+    out.push_str("\nconfiguration_a\tconfiguration_b\tU\tz\tp_value\n");
+    let labels = groups.keys().collect::<Vec<_>>();
+    for i in 0..labels.len() {
+        for j in (i + 1)..labels.len() {
+            if let Some(test) = mann_whitney_u(&groups[labels[i]], &groups[labels[j]]) {
+                out.push_str(&format!(
+                    "{}\t{}\t{}\t{:.4}\t{:.6}\n",
+                    labels[i], labels[j], test.u, test.z, test.p_value
+                ));
+            }
+        }
+    }
+    out
+}
+
+/// The `aggregate` subcommand:
+///
+/// ```text
+/// berbalang aggregate <directory> [<statistics file> <column>]
+/// ```
+pub fn run_cli(args: &[String]) -> Result<(), Error> {
+    let root = args.get(0).ok_or_else(|| {
+        Error::Misc("Usage: berbalang aggregate <directory> [<file> <column>]".to_string())
+    })?;
+    let file = args.get(1).map(String::as_str).unwrap_or("window");
+    let column = args.get(2).map(String::as_str).unwrap_or("min_fitness");
+    let runs = collect_runs(root, file, column)?;
+    log::info!("Found {} runs under {}", runs.len(), root);
+    for run in runs.iter() {
+        log::info!(
+            "{}: {} = {} ({:?})",
+            run.configuration,
+            column,
+            run.result,
+            run.path
+        );
+    }
+    print!("{}", comparison_tables(&group_runs(&runs)));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_config_hash() {
+        assert_eq!(
+            config_hash(b"a = 1\nrandom_seed = 5\n"),
+            config_hash(b"random_seed = 6\na = 1\n")
+        );
+        assert_ne!(config_hash(b"a = 1\n"), config_hash(b"a = 2\n"));
+        // replicas differ in their names, and so in their data directories
+        let replica = |seed: u64, name: &str| {
+            format!(
+                "random_seed = {}\n\n[observer]\ndump_soup = true\npopulation_name = \"{}\"\n\
+                 data_directory = \"~/data/{}\"\nfull_data_directory = \"~/data/{}/run\"\n",
+                seed, name, name, name
+            )
+        };
+        assert_eq!(
+            config_hash(replica(1, "ann").as_bytes()),
+            config_hash(replica(2, "bob").as_bytes())
+        );
+        assert_ne!(
+            config_hash(replica(1, "ann").as_bytes()),
+            config_hash(replica(1, "ann").replace("true", "false").as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_collect_runs() {
+        let root = std::env::temp_dir().join(format!("berbalang_runs_{}", rand::random::<u64>()));
+        let populations = [
+            ("Roper/Tournament/2020/06/01/a", "a = 1", &[3.0, 2.0][..]),
+            ("Roper/Tournament/2020/06/02/b", "a = 1", &[1.5][..]),
+            ("Roper/Tournament/2020/06/02/c", "a = 2", &[4.0][..]),
+        ];
+        for (path, config, results) in populations.iter() {
+            let dir = root.join("berbalang").join(path);
+            for (i, result) in results.iter().enumerate() {
+                let island = dir.join(format!("island_{}", i));
+                fs::create_dir_all(&island).unwrap();
+                fs::write(
+                    island.join("window_statistics.csv"),
+                    format!("counter,min_fitness\n100,{}\n200,NaN\n", result),
+                )
+                .unwrap();
+            }
+            fs::write(dir.join("config.toml"), config).unwrap();
+        }
+
+        let runs = collect_runs(&root, "window", "min_fitness").unwrap();
+        assert_eq!(runs.len(), 3);
+        let groups = group_runs(&runs);
+        assert_eq!(groups.len(), 2);
+        let mut results = groups
+            .values()
+            .map(|r| {
+                let mut r = r.clone();
+                r.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                r
+            })
+            .collect::<Vec<_>>();
+        results.sort_by(|a, b| a.len().cmp(&b.len()));
+        assert_eq!(results, vec![vec![4.0], vec![1.5, 2.0]]);
+        assert!(groups.keys().all(|k| k.starts_with("Roper/Tournament/")));
+
+        let tables = comparison_tables(&groups);
+        assert_eq!(tables.lines().count(), 1 + 2 + 1 + 1 + 1);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -4,12 +4,14 @@ use std::fmt;
 use std::fmt::Debug;
 use std::hash::{Hash, Hasher};
 use std::ops::Index;
+use std::sync::atomic::{self, AtomicBool};
 use std::sync::Mutex;
 
-use itertools::Itertools;
 use serde::export::Formatter;
 use serde::{Deserialize, Serialize};
 
+use crate::error::Error;
+
 pub type FitnessMap<'a> = BTreeMap<&'a str, f64>;
 
 pub trait HasScalar {
@@ -25,6 +27,10 @@ impl HasScalar for Vec<f64> {
 pub trait FitnessScore:
     Sized + PartialEq + Debug + Send + Clone + PartialOrd + Serialize + PartialOrd + HasScalar
 {
+    /// The named components of the score, if it has any.
+    fn components(&self) -> Vec<(&str, f64)> {
+        Vec::new()
+    }
 }
 
 impl FitnessScore for Vec<f64> {}
@@ -37,8 +43,10 @@ impl Pareto<'static> {
         Pareto(BTreeMap::new())
     }
 
+    /// The scores, in the order of their objectives' names, which is the
+    /// order the map keeps them in.
     pub fn values(&self) -> impl Iterator<Item = &f64> {
-        self.inner().iter().sorted_by_key(|p| p.0).map(|(_k, v)| v)
+        self.0.values()
     }
 }
 
@@ -97,7 +105,11 @@ pub trait MapFit {
     }
 }
 
-impl FitnessScore for Pareto<'static> {}
+impl FitnessScore for Pareto<'static> {
+    fn components(&self) -> Vec<(&str, f64)> {
+        self.inner().iter().map(|(k, v)| (*k, *v)).collect()
+    }
+}
 
 impl PartialOrd for Pareto<'static> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
@@ -193,7 +205,6 @@ impl fmt::Debug for Pareto<'_> {
         writeln!(f, "Pareto [")?;
         self.0
             .iter()
-            .sorted_by_key(|p| p.0)
             .map(|(obj, score)| writeln!(f, "\t{} => {},", obj, score))
             .collect::<Result<Vec<()>, _>>()?;
         writeln!(f, "]")
@@ -210,8 +221,10 @@ impl ShuffleFit {
         Self(FitnessMap::new())
     }
 
+    /// The scores, in the order of their objectives' names, which is the
+    /// order the map keeps them in.
     pub fn values(&self) -> impl Iterator<Item = &f64> {
-        self.inner().iter().sorted_by_key(|p| p.0).map(|(_k, v)| v)
+        self.0.values()
     }
 
     pub fn epoch_key(&self) -> &'static str {
@@ -278,13 +291,21 @@ impl From<BTreeMap<&'static str, f64>> for ShuffleFit {
     }
 }
 
-impl FitnessScore for ShuffleFit {}
+impl FitnessScore for ShuffleFit {
+    fn components(&self) -> Vec<(&str, f64)> {
+        self.inner().iter().map(|(k, v)| (*k, *v)).collect()
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct Weighted<'a> {
     weighting: String,
     #[serde(borrow)]
     pub scores: BTreeMap<&'a str, f64>,
+    /// The objectives declared in `fitness.objectives`, which should all
+    /// be scored
+    #[serde(skip)]
+    expected: Vec<&'static str>,
     cached_scalar: Mutex<Option<f64>>,
 }
 
@@ -329,19 +350,36 @@ impl Clone for Weighted<'_> {
             cached_scalar: Mutex::new(None),
             weighting: self.weighting.clone(),
             scores: self.scores.clone(),
+            expected: self.expected.clone(),
         }
     }
 }
 
 impl Weighted<'static> {
     pub fn new(weighting: &str) -> Self {
+        Self::expecting(weighting, &[])
+    }
+
+    /// A fitness that expects the objectives given to be scored, so that
+    /// any left unscored can be found with `missing`.
+    pub fn expecting(weighting: &str, objectives: &[&'static str]) -> Self {
         Self {
             weighting: weighting.to_string(),
             scores: FitnessMap::new(),
+            expected: objectives.to_vec(),
             cached_scalar: Mutex::new(None),
         }
     }
 
+    /// The expected objectives that haven't been scored.
+    pub fn missing(&self) -> Vec<&'static str> {
+        self.expected
+            .iter()
+            .filter(|key| !self.scores.contains_key(*key))
+            .cloned()
+            .collect()
+    }
+
     fn powf(&self, n: f64) -> Self {
         let mut res = self.clone();
         for v in res.scores.values_mut() {
@@ -350,8 +388,9 @@ impl Weighted<'static> {
         res
     }
 
+    /// The scores, in the order of their objectives' names.
     pub fn values(&self) -> impl Iterator<Item = &f64> {
-        self.scores.iter().sorted_by_key(|p| p.0).map(|(_k, v)| v)
+        self.scores.values()
     }
 
     pub fn scale_by(&mut self, factor: f64) {
@@ -387,11 +426,18 @@ impl Weighted<'static> {
         if self.scores.is_empty() {
             return f64::MAX;
         }
+        let missing = self.missing();
+        if !missing.is_empty() && !WARNED_OF_MISSING.swap(true, atomic::Ordering::Relaxed) {
+            log::warn!(
+                "The fitness function left the declared objectives {} unscored",
+                missing.join(", ")
+            );
+        }
         let mut ns = self.scores.clone();
         match fasteval::ez_eval(expr, &mut ns) {
             Err(e) => panic!(
-                "Failed to evaluate expression {:?} with scores {:?}: {:?}",
-                expr, self.scores, e
+                "Failed to evaluate expression {:?} with scores {:?}, missing {:?}: {:?}",
+                expr, self.scores, missing, e
             ),
             Ok(res) => res,
         }
@@ -400,6 +446,35 @@ impl Weighted<'static> {
     pub fn declare_failure(&mut self) {
         *self.cached_scalar.get_mut().unwrap() = Some(f64::MAX)
     }
+
+    /// Whether this fitness marks a failed evaluation, by scoring nothing or
+    /// by `declare_failure`. Nothing should be added to such a fitness.
+    pub fn is_failure(&self) -> bool {
+        self.scores.is_empty() || *self.cached_scalar.lock().expect("poisoned") == Some(f64::MAX)
+    }
+}
+
+/// Whether the objectives left unscored have been warned of, which is done
+/// only once, lest every specimen repeat it.
+static WARNED_OF_MISSING: AtomicBool = AtomicBool::new(false);
+
+/// The names of the variables in a `fasteval` expression, and of any
+/// functions it calls that `fasteval` doesn't provide, in order.
+pub fn expression_variables(expr: &str) -> Result<Vec<String>, Error> {
+    let mut names = Vec::new();
+    // evaluated with every variable at 1, and then at 0, so that the names
+    // on both sides of short-circuiting operators are seen
+    for value in &[1.0, 0.0] {
+        let mut ns = |name: &str, _args: Vec<f64>| -> Option<f64> {
+            names.push(name.to_string());
+            Some(*value)
+        };
+        fasteval::ez_eval(expr, &mut ns)
+            .map_err(|e| Error::Parsing(format!("Bad expression {:?}: {:?}", expr, e)))?;
+    }
+    names.sort();
+    names.dedup();
+    Ok(names)
 }
 
 pub fn average_weighted(ws: &[Weighted<'static>]) -> Weighted<'static> {
@@ -443,7 +518,11 @@ impl PartialOrd for Weighted<'static> {
     }
 }
 
-impl FitnessScore for Weighted<'static> {}
+impl FitnessScore for Weighted<'static> {
+    fn components(&self) -> Vec<(&str, f64)> {
+        self.inner().iter().map(|(k, v)| (*k, *v)).collect()
+    }
+}
 
 impl MapFit for Weighted<'static> {
     fn inner_mut(&mut self) -> &mut BTreeMap<&'static str, f64> {
@@ -473,9 +552,13 @@ impl Index<&str> for Weighted<'static> {
 impl fmt::Debug for Weighted<'static> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Scores:")?;
-        for (attr, score) in self.scores.iter().sorted_by_key(|p| p.0) {
+        for (attr, score) in self.scores.iter() {
             writeln!(f, "    {}: {}", attr, score)?;
         }
+        let missing = self.missing();
+        if !missing.is_empty() {
+            writeln!(f, "Missing: {}", missing.join(", "))?;
+        }
         writeln!(f, "Weighting expression: {}", self.weighting)?;
         writeln!(f, "Scalar: {}", self.scalar())
     }
@@ -494,6 +577,12 @@ mod test {
         let mut ps = vec![&p1, &p2];
         ps.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
         assert_eq!(ps[0], &p2);
+        // the values are in the order of the objectives' names
+        assert_eq!(
+            p1.values().cloned().collect::<Vec<f64>>(),
+            vec![3.1, 0.1, 2.0]
+        );
+        assert_eq!(p1[1], 0.1);
     }
 
     #[test]
@@ -519,6 +608,20 @@ mod test {
         assert_eq!(w_sum.scalar(), 2.6);
     }
 
+    #[test]
+    fn test_expected_objectives() {
+        let mut w = Weighted::expecting("foo + bar", &["foo", "bar", "baz"]);
+        w.insert("foo", 1.0);
+        w.insert("bar", 2.0);
+        assert_eq!(w.missing(), vec!["baz"]);
+        assert_eq!(w.scalar(), 3.0);
+        assert_eq!(
+            expression_variables("foo + max(bar, 2 * baz) || qux").unwrap(),
+            vec!["bar", "baz", "foo", "qux"]
+        );
+        assert!(expression_variables("foo +").is_err());
+    }
+
     #[test]
     fn test_average_and_stddev_weighted() {
         let mut w1 = Weighted::new("foo + bar");
@@ -0,0 +1,207 @@
+//! A log of every specimen's parents, and of the operators that produced
+//! it, from which a champion's ancestry can be exported as a GraphViz DOT
+//! graph. The log is kept by the "genealogy" sink, and read by the
+//! `ancestry` tool.
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use hashbrown::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+
+use crate::configure::Config;
+use crate::error::Error;
+use crate::evolution::{Genome, Phenome};
+use crate::observer::{append_json_line, Sink, WindowStats};
+
+/// A line of `genealogy.jsonl`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Birth {
+    pub name: String,
+    pub parents: Vec<String>,
+    pub generation: usize,
+    /// The epoch in which the specimen was first observed, which, since
+    /// offspring are observed as soon as they've been evaluated, is the
+    /// epoch in which it was born.
+    pub epoch: usize,
+    pub island: usize,
+    pub operators: Vec<String>,
+}
+
+/// Appends a `Birth` to `genealogy.jsonl` for each specimen, the first time
+/// it's observed.
+pub struct GenealogySink {
+    config: Arc<Config>,
+    seen: HashSet<String>,
+}
+
+impl GenealogySink {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self {
+            config,
+            seen: HashSet::new(),
+        }
+    }
+}
+
+impl<O: Phenome + Genome> Sink<O> for GenealogySink {
+    fn record_stats(&mut self, _stats: &WindowStats) {}
+
+    fn record_specimen(&mut self, specimen: &O, _counter: usize, epoch: usize) {
+        if !self.seen.insert(specimen.name().to_string()) {
+            return;
+        }
+        let birth = Birth {
+            name: specimen.name().to_string(),
+            parents: specimen.parents(),
+            generation: specimen.generation(),
+            epoch,
+            island: self.config.island_id,
+            operators: specimen.operators(),
+        };
+        let filename = format!("{}/genealogy.jsonl", self.config.data_directory());
+        append_json_line(&filename, &birth)
+    }
+}
+
+/// Loads a genealogy log, indexed by name. Names are not guaranteed to be
+/// unique, so where one recurs, the first birth is kept.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<HashMap<String, Birth>, Error> {
+    let mut births = HashMap::new();
+    for line in fs::read_to_string(path)?.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let birth: Birth = serde_json::from_str(line)?;
+        births.entry(birth.name.clone()).or_insert(birth);
+    }
+    Ok(births)
+}
+
+/// The births of the named specimen and its ancestors, breadth first, going
+/// back at most `max_depth` generations, if given.
+pub fn ancestry<'a>(
+    births: &'a HashMap<String, Birth>,
+    name: &str,
+    max_depth: Option<usize>,
+) -> Vec<&'a Birth> {
+    let mut lineage = Vec::new();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    queue.push_back((name, 0));
+    while let Some((name, depth)) = queue.pop_front() {
+        if !visited.insert(name) {
+            continue;
+        }
+        if let Some(birth) = births.get(name) {
+            lineage.push(birth);
+            if max_depth.map(|max| depth < max).unwrap_or(true) {
+                for parent in birth.parents.iter() {
+                    queue.push_back((parent.as_str(), depth + 1));
+                }
+            }
+        }
+    }
+    lineage
+}
+
+fn summarize_operators(operators: &[String]) -> String {
+    let mut counts = BTreeMap::new();
+    for op in operators {
+        *counts.entry(op.as_str()).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(op, n)| {
+            if n > 1 {
+                format!("{} x{}", op, n)
+            } else {
+                op.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Renders the lineage as a GraphViz DOT graph, with edges running from
+/// parent to offspring, and the root specimen highlighted. Parents that
+/// don't appear in the lineage are drawn as bare names.
+pub fn to_dot(lineage: &[&Birth], root: &str) -> String {
+    let mut dot = String::from("digraph ancestry {\n    node [shape=box];\n");
+    for birth in lineage {
+        let style = if birth.name == root {
+            ", style=filled, fillcolor=gold"
+        } else {
+            ""
+        };
+        dot.push_str(&format!(
+            "    \"{}\" [label=\"{}\\ngeneration {}, epoch {}, island {}\\n{}\"{}];\n",
+            birth.name,
+            birth.name,
+            birth.generation,
+            birth.epoch,
+            birth.island,
+            summarize_operators(&birth.operators),
+            style
+        ));
+    }
+    for birth in lineage {
+        for parent in birth.parents.iter() {
+            dot.push_str(&format!("    \"{}\" -> \"{}\";\n", parent, birth.name));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn birth(name: &str, parents: &[&str], generation: usize) -> Birth {
+        Birth {
+            name: name.to_string(),
+            parents: parents.iter().map(|p| p.to_string()).collect(),
+            generation,
+            epoch: generation,
+            island: 0,
+            operators: vec!["crossover".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_ancestry() {
+        let births = vec![
+            birth("adam", &[], 0),
+            birth("eve", &[], 0),
+            birth("cain", &["adam", "eve"], 1),
+            birth("abel", &["adam", "eve"], 1),
+            birth("enoch", &["cain"], 2),
+        ]
+        .into_iter()
+        .map(|b| (b.name.clone(), b))
+        .collect::<HashMap<String, Birth>>();
+
+        let names = |lineage: Vec<&Birth>| {
+            lineage
+                .into_iter()
+                .map(|b| b.name.as_str())
+                .collect::<Vec<&str>>()
+        };
+        assert_eq!(
+            names(ancestry(&births, "enoch", None)),
+            vec!["enoch", "cain", "adam", "eve"]
+        );
+        assert_eq!(
+            names(ancestry(&births, "enoch", Some(1))),
+            vec!["enoch", "cain"]
+        );
+
+        let lineage = ancestry(&births, "enoch", Some(1));
+        let dot = to_dot(&lineage, "enoch");
+        assert!(dot.contains("\"cain\" -> \"enoch\";"));
+        assert!(dot.contains("\"adam\" -> \"cain\";"));
+        assert!(dot.contains("fillcolor=gold"));
+    }
+}
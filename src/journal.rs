@@ -0,0 +1,194 @@
+//! The "journal" sink keeps an append-only log of everything the observer
+//! records -- the window statistics, champions, operator statistics, and
+//! every specimen's fitness vector -- in `journal.log`, which can be
+//! recovered after a crash, or the run's being killed for running out of
+//! memory, when the other sinks' buffered output would be lost.
+//!
+//! Each entry is one line, holding its sequence number, the CRC-32C of the
+//! record, in hex, and the record, as the versioned JSON object the "jsonl"
+//! sink writes, separated by tabs:
+//!
+//! ```text
+//! 17    8f3e02a1    {"schema_version":1,"record":"window","island":0,...}
+//! ```
+//!
+//! Each entry is written with a single call, unbuffered, and the file is
+//! synced to disk every `observer.journal_sync_every` entries, and whenever
+//! the window statistics are recorded. A crash can leave a torn entry at
+//! the end, which `recover` -- and the `recover_journal` tool -- detects and
+//! drops, along with any other entry whose checksum doesn't match.
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::configure::Config;
+use crate::error::Error;
+use crate::evolution::{Genome, Phenome};
+use crate::observer::{JsonlRecord, OperatorStats, Sink, SpecimenRecord, WindowStats};
+use crate::util::checksum::crc32c;
+
+pub fn journal_path(config: &Config) -> String {
+    format!("{}/journal.log", config.data_directory())
+}
+
+fn entry(sequence: u64, json: &str) -> String {
+    format!("{}\t{:08x}\t{}\n", sequence, crc32c(json.as_bytes()), json)
+}
+
+pub struct JournalSink {
+    config: Arc<Config>,
+    file: Option<File>,
+    sequence: u64,
+    unsynced: usize,
+}
+
+impl JournalSink {
+    pub fn new(config: Arc<Config>) -> Self {
+        let path = journal_path(&config);
+        // continue the numbering of an existing journal
+        let sequence = recover(&path)
+            .ok()
+            .and_then(|r| r.entries.last().map(|(seq, _)| seq + 1))
+            .unwrap_or(0);
+        let file = match OpenOptions::new().append(true).create(true).open(&path) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                log::error!("Failed to open journal {}: {:?}", path, e);
+                None
+            }
+        };
+        Self {
+            config,
+            file,
+            sequence,
+            unsynced: 0,
+        }
+    }
+
+    fn append<T: Serialize>(&mut self, record: &'static str, body: &T, sync: bool) {
+        let file = match self.file {
+            Some(ref mut file) => file,
+            None => return,
+        };
+        let json = match serde_json::to_string(&JsonlRecord::new(record, body)) {
+            Ok(json) => json,
+            Err(e) => {
+                log::error!("Failed to serialize {} record: {:?}", record, e);
+                return;
+            }
+        };
+        if let Err(e) = file.write_all(entry(self.sequence, &json).as_bytes()) {
+            log::error!("Failed to append to journal: {:?}", e);
+            return;
+        }
+        self.sequence += 1;
+        self.unsynced += 1;
+        if sync || self.unsynced >= self.config.observer.journal_sync_every {
+            if let Err(e) = file.sync_data() {
+                log::error!("Failed to sync journal: {:?}", e);
+            }
+            self.unsynced = 0;
+        }
+    }
+}
+
+impl<O: Phenome + Genome> Sink<O> for JournalSink {
+    fn record_stats(&mut self, stats: &WindowStats) {
+        self.append("window", stats, true)
+    }
+
+    fn record_champion(&mut self, champion: &O, stats: &WindowStats) {
+        let record = SpecimenRecord::of(champion, stats.counter, stats.epoch, &self.config);
+        self.append("champion", &record, false)
+    }
+
+    fn record_operators(&mut self, stats: &OperatorStats) {
+        self.append("operators", stats, false)
+    }
+
+    fn record_specimen(&mut self, specimen: &O, counter: usize, epoch: usize) {
+        let record = SpecimenRecord::of(specimen, counter, epoch, &self.config);
+        self.append("specimen", &record, false)
+    }
+}
+
+impl Drop for JournalSink {
+    fn drop(&mut self) {
+        if let Some(ref file) = self.file {
+            let _ = file.sync_all();
+        }
+    }
+}
+
+/// What could be read back from a journal.
+#[derive(Debug, Default)]
+pub struct Recovery {
+    /// The intact entries, by sequence number
+    pub entries: Vec<(u64, serde_json::Value)>,
+    /// The line numbers of the entries that were damaged, and dropped
+    pub corrupt: Vec<usize>,
+    /// Whether the last entry was cut short, as by a crash mid-write
+    pub torn_tail: bool,
+}
+
+fn parse_entry(line: &str) -> Option<(u64, serde_json::Value)> {
+    let mut fields = line.splitn(3, '\t');
+    let sequence = fields.next()?.parse::<u64>().ok()?;
+    let checksum = u32::from_str_radix(fields.next()?, 16).ok()?;
+    let json = fields.next()?;
+    if crc32c(json.as_bytes()) != checksum {
+        return None;
+    }
+    serde_json::from_str(json)
+        .ok()
+        .map(|value| (sequence, value))
+}
+
+/// Reads back the intact entries of the journal at `path`.
+pub fn recover(path: &str) -> Result<Recovery, Error> {
+    let bytes = fs::read(path)?;
+    let text = String::from_utf8_lossy(&bytes);
+    let mut recovery = Recovery {
+        torn_tail: !bytes.is_empty() && !bytes.ends_with(b"\n"),
+        ..Default::default()
+    };
+    for (i, line) in text.lines().enumerate() {
+        match parse_entry(line) {
+            Some(entry) => recovery.entries.push(entry),
+            None => recovery.corrupt.push(i + 1),
+        }
+    }
+    Ok(recovery)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_recover() {
+        let path = std::env::temp_dir().join(format!("journal_test_{}.log", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut text = String::new();
+        text.push_str(&entry(0, r#"{"record":"window","counter":100}"#));
+        // an entry damaged after it was written
+        let damaged = entry(1, r#"{"record":"window","counter":200}"#);
+        text.push_str(&damaged.replace(":200", ":201"));
+        text.push_str(&entry(2, r#"{"record":"window","counter":300}"#));
+        let torn = entry(3, r#"{"record":"window","counter":400}"#);
+        text.push_str(&torn[..torn.len() / 2]);
+        fs::write(path, text).unwrap();
+
+        let recovery = recover(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(
+            recovery.entries.iter().map(|(s, _)| *s).collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+        assert_eq!(recovery.entries[1].1["counter"], 300);
+        assert_eq!(recovery.corrupt, vec![2, 4]);
+        assert!(recovery.torn_tail);
+    }
+}
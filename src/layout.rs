@@ -0,0 +1,202 @@
+//! The layout of the data directories, as a template set in
+//! `observer.data_layout`, by default
+//!
+//! ```text
+//! {data_directory}/berbalang/{job}/{selection}/{date}/{population}/island_{island}
+//! ```
+//!
+//! The placeholders are
+//!
+//! - `{data_directory}`: the `observer.data_directory`
+//! - `{job}` and `{selection}`: as named in the config, e.g. `Roper`
+//! - `{date}`: the date the run started, as `yyyy/mm/dd`, or, as
+//!   `{date:%Y-%m-%d}`, in any `strftime` format
+//! - `{population}`: the population's name
+//! - `{seed}`: the run's `random_seed`
+//! - `{island}`: the island's number
+//!
+//! The islands of a run share its directory, in which its manifest, config,
+//! and merged log are kept, so `{island}` may appear only in the last
+//! component of the path, which must begin with `island_`. A template that
+//! doesn't mention `{island}` is taken to be the run's directory, with the
+//! islands in `island_{island}` beneath it.
+use chrono::format::{Item, StrftimeItems};
+use chrono::prelude::*;
+
+use crate::error::Error;
+
+pub const DEFAULT_LAYOUT: &str =
+    "{data_directory}/berbalang/{job}/{selection}/{date}/{population}/island_{island}";
+
+const DEFAULT_DATE_FORMAT: &str = "%Y/%m/%d";
+
+const PLACEHOLDERS: [&str; 7] = [
+    "data_directory",
+    "job",
+    "selection",
+    "date",
+    "population",
+    "seed",
+    "island",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment<'a> {
+    Literal(&'a str),
+    /// A placeholder's name, and its format, if it has one
+    Placeholder(&'a str, Option<&'a str>),
+}
+
+fn bad(template: &str, msg: String) -> Error {
+    Error::Parsing(format!("Bad data_layout {:?}: {}", template, msg))
+}
+
+fn parse(template: &str) -> Result<Vec<Segment<'_>>, Error> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+    while !rest.is_empty() {
+        let open = rest.find('{').unwrap_or(rest.len());
+        if rest[..open].contains('}') {
+            return Err(bad(template, "a '}' is unopened".to_string()));
+        }
+        if open > 0 {
+            segments.push(Segment::Literal(&rest[..open]));
+        }
+        if open == rest.len() {
+            break;
+        }
+        let close = rest[open..]
+            .find('}')
+            .map(|i| open + i)
+            .ok_or_else(|| bad(template, "a '{' is unclosed".to_string()))?;
+        let inner = &rest[open + 1..close];
+        let mut parts = inner.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let format = parts.next();
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(bad(
+                template,
+                format!(
+                    "no such placeholder as {{{}}}. Try one of {}",
+                    name,
+                    PLACEHOLDERS.join(", ")
+                ),
+            ));
+        }
+        if let Some(format) = format {
+            if name != "date" {
+                return Err(bad(template, "only {date} takes a format".to_string()));
+            }
+            if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                return Err(bad(template, format!("bad date format {:?}", format)));
+            }
+        }
+        segments.push(Segment::Placeholder(name, format));
+        rest = &rest[close + 1..];
+    }
+    Ok(segments)
+}
+
+/// Checks the template, returning it, with `/island_{island}` appended if
+/// it doesn't mention `{island}`.
+pub fn check(template: &str) -> Result<String, Error> {
+    let segments = parse(template)?;
+    let mentions_island = segments
+        .iter()
+        .any(|s| *s == Segment::Placeholder("island", None));
+    if !mentions_island {
+        return Ok(format!(
+            "{}/island_{{island}}",
+            template.trim_end_matches('/')
+        ));
+    }
+    let last = template.rsplit('/').next().unwrap_or("");
+    if !last.starts_with("island_") || template.matches("{island}").count() > 1 {
+        return Err(bad(
+            template,
+            "{island} may appear only in the last component, which must begin with island_"
+                .to_string(),
+        ));
+    }
+    Ok(template.to_string())
+}
+
+/// The values the placeholders stand for.
+pub struct Values<'a> {
+    pub data_directory: &'a str,
+    pub job: String,
+    pub selection: String,
+    pub date: DateTime<Local>,
+    pub population: &'a str,
+    pub seed: u64,
+    pub island: usize,
+}
+
+/// Fills in the template, as `check` has it, with the values given.
+pub fn render(template: &str, values: &Values<'_>) -> Result<String, Error> {
+    let template = check(template)?;
+    let mut path = String::new();
+    for segment in parse(&template)? {
+        match segment {
+            Segment::Literal(s) => path.push_str(s),
+            Segment::Placeholder(name, format) => {
+                let value = match name {
+                    "data_directory" => values.data_directory.to_string(),
+                    "job" => values.job.clone(),
+                    "selection" => values.selection.clone(),
+                    "date" => values
+                        .date
+                        .format(format.unwrap_or(DEFAULT_DATE_FORMAT))
+                        .to_string(),
+                    "population" => values.population.to_string(),
+                    "seed" => values.seed.to_string(),
+                    "island" => values.island.to_string(),
+                    _ => unreachable!("the placeholders were checked when parsed"),
+                };
+                path.push_str(&value);
+            }
+        }
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let values = Values {
+            data_directory: "/data",
+            job: "Roper".to_string(),
+            selection: "Tournament".to_string(),
+            date: Local.ymd(2020, 6, 1).and_hms(12, 0, 0),
+            population: "happy-otter",
+            seed: 42,
+            island: 3,
+        };
+        assert_eq!(
+            render(DEFAULT_LAYOUT, &values).unwrap(),
+            "/data/berbalang/Roper/Tournament/2020/06/01/happy-otter/island_3"
+        );
+        assert_eq!(
+            render(
+                "{data_directory}/{date:%Y-%m-%d}/{population}-{seed}/",
+                &values
+            )
+            .unwrap(),
+            "/data/2020-06-01/happy-otter-42/island_3"
+        );
+        assert_eq!(
+            render("/lab/{job}/{population}/island_{island}_of_{seed}", &values).unwrap(),
+            "/lab/Roper/happy-otter/island_3_of_42"
+        );
+        assert!(check("/lab/{island}/{population}").is_err());
+        assert!(check("/lab/{population}/run_{island}").is_err());
+        assert!(check("/lab/{pop}").is_err());
+        assert!(check("/lab/{seed:%Y}").is_err());
+        assert!(check("/lab/{date:%Q}").is_err());
+        assert!(check("/lab/{population").is_err());
+        assert!(check("/lab/population}").is_err());
+    }
+}
@@ -7,29 +7,61 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use configure::Config;
 
+pub mod aggregator;
+pub mod artifacts;
+pub mod bests;
+pub mod checkpoint;
 pub mod configure;
+pub mod control;
+pub mod dashboard;
+pub mod dataset;
 #[allow(dead_code)] // FIXME
 mod disassembler;
 #[allow(dead_code)] // FIXME
 pub mod emulator;
 pub mod error;
+pub mod events;
 pub mod evolution;
 pub mod examples;
+pub mod experiments;
 #[allow(dead_code)] // FIXME
 pub mod fitness;
+pub mod genealogy;
+pub mod journal;
+pub mod layout;
 pub mod logger;
 pub mod macros;
+pub mod manifest;
+pub mod metrics;
 pub mod observer;
 pub mod ontogenesis;
+pub mod provider;
+pub mod resources;
+pub mod resume;
 pub mod roper;
+pub mod runner;
+pub mod schema;
+pub mod shutdown;
+pub mod stopping;
+pub mod stream;
+pub mod summary;
+pub mod sweep;
+pub mod tensorboard;
+pub mod transpile;
 #[allow(dead_code)] // FIXME
 pub mod util;
 
+pub use runner::{Berbalang, RunOutcome};
+
 pub static EPOCH_COUNTER: AtomicUsize = AtomicUsize::new(0);
 pub static KEEP_GOING: AtomicBool = AtomicBool::new(true);
 pub static WINNING_ISLAND: AtomicUsize = AtomicUsize::new(0xbaad_f00d);
 pub static STARTING_TIMESTAMP: AtomicUsize = AtomicUsize::new(0);
 pub static TIMEOUT: AtomicUsize = AtomicUsize::new(0);
+/// The number of islands evolving at once, and the number of them that have
+/// spent their budgets. The run ends once they all have. See `stopping`.
+pub static NUM_ISLANDS: AtomicUsize = AtomicUsize::new(1);
+pub static ISLANDS_SPENT: AtomicUsize = AtomicUsize::new(0);
 
 pub fn set_starting_timestamp() {
     let now: usize = SystemTime::now()
@@ -39,9 +71,14 @@ pub fn set_starting_timestamp() {
     STARTING_TIMESTAMP.store(now, atomic::Ordering::Relaxed);
 }
 
-pub fn set_timeout(timeout: &str) {
-    let timeout = parse_duration::parse(timeout).expect("Failed to parse timeout string");
-    let seconds = timeout.as_secs() as usize;
+/// How long past the timeout the islands are given to wind down, before
+/// `keep_going` stops them regardless.
+const TIMEOUT_GRACE: Duration = Duration::from_secs(60);
+
+pub fn set_timeout(timeout: Duration) {
+    // a timeout of less than a second is taken as a second, since zero
+    // means there's none
+    let seconds = timeout.as_secs().max(1) as usize;
     TIMEOUT.store(seconds, atomic::Ordering::Relaxed);
 }
 
@@ -58,24 +95,32 @@ pub fn uptime() -> Duration {
     }
 }
 
-/// If no `timeout` parameter is set, then this check always fails, and the process never times out.
-pub fn timeout_expired() -> bool {
+fn timeout_expired_by(grace: Duration) -> bool {
     let timeout = TIMEOUT.load(atomic::Ordering::Relaxed) as u64;
     if timeout == 0 {
         return false;
     }
-    let timeout = Duration::from_secs(timeout);
+    let timeout = Duration::from_secs(timeout) + grace;
     let up_for = uptime();
     log::debug!("Uptime: {:?}", up_for);
-    let expired = up_for > timeout;
-    if expired {
-        log::error!("Berbalang has timed out, at {:?}", up_for);
-    }
-    expired
+    up_for > timeout
+}
+
+/// If no `timeout` parameter is set, then this check always fails, and the process never times out.
+/// Once it holds, each island's observer winds its island down, and stops the run.
+pub fn timeout_expired() -> bool {
+    timeout_expired_by(Duration::from_secs(0))
 }
 
 pub fn keep_going() -> bool {
-    !timeout_expired() && KEEP_GOING.load(atomic::Ordering::Relaxed)
+    if timeout_expired_by(TIMEOUT_GRACE) {
+        log::error!("Berbalang has timed out, at {:?}", uptime());
+        return false;
+    }
+    if shutdown::requested() {
+        return false;
+    }
+    KEEP_GOING.load(atomic::Ordering::Relaxed)
 }
 
 pub fn stop_everything(island: usize, champion: bool) {
@@ -98,6 +143,26 @@ pub fn stop_everything(island: usize, champion: bool) {
     };
 }
 
+pub fn set_num_islands(n: usize) {
+    NUM_ISLANDS.store(n.max(1), atomic::Ordering::Relaxed);
+}
+
+/// Records that the island has spent its budget, stopping the run if it's
+/// the last to have done so.
+pub fn spend_island_budget(island: usize) {
+    let spent = ISLANDS_SPENT.fetch_add(1, atomic::Ordering::SeqCst) + 1;
+    let num_islands = NUM_ISLANDS.load(atomic::Ordering::Relaxed);
+    log::info!(
+        "Island {} has spent its budget, as have {} of {} islands",
+        island,
+        spent,
+        num_islands
+    );
+    if spent >= num_islands {
+        stop_everything(island, false);
+    }
+}
+
 pub fn get_epoch_counter() -> usize {
     EPOCH_COUNTER.load(atomic::Ordering::Relaxed)
 }
@@ -1,61 +1,304 @@
+use std::cell::Cell;
+use std::fs::{File, OpenOptions};
 use std::io::Write;
 use std::sync::atomic::Ordering;
+use std::sync::{Mutex, Once};
 
 use chrono::prelude::*;
+use hashbrown::HashMap;
+use log::{Log, Metadata, Record};
 
+use crate::configure::{Config, LogDestination, LoggingConfig};
 use crate::EPOCH_COUNTER;
 
-/// This function initializes the Berbalang logger.
+thread_local! {
+    /// The island on whose behalf the current thread is working, if any.
+    static ISLAND: Cell<Option<usize>> = Cell::new(None);
+}
+
+/// Besides going to stderr, each record is written to `merged.log`, beside
+/// the island directories, and, if it was logged by a thread working for
+/// an island, to `island.log` in that island's directory, and, as JSON, to
+/// `merged.jsonl`, as `logging.destinations` has them.
+#[derive(Default)]
+struct LogFiles {
+    text: bool,
+    json: bool,
+    merged: Option<File>,
+    merged_json: Option<File>,
+    islands: HashMap<usize, File>,
+}
+
+static INIT_LOG_FILES: Once = Once::new();
+static mut LOG_FILES: Option<Mutex<LogFiles>> = None;
+
+fn log_files() -> &'static Mutex<LogFiles> {
+    INIT_LOG_FILES.call_once(|| unsafe { LOG_FILES = Some(Mutex::new(LogFiles::default())) });
+    unsafe {
+        LOG_FILES
+            .as_ref()
+            .expect("The log files have not been initialized")
+    }
+}
+
+fn open(path: &str) -> Option<File> {
+    // this can't be logged, since we're holding the lock on the log files
+    OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .map_err(|e| eprintln!("Failed to open log file {}: {:?}", path, e))
+        .ok()
+}
+
+/// Marks the current thread as working for the config's island, so that
+/// what it logs is tagged with the island, and written to the island's own
+/// log file. Call this at the top of each thread an island spawns.
+pub fn set_island(config: &Config) {
+    ISLAND.with(|island| island.set(Some(config.island_id)));
+    let dir = config.data_directory();
+    if dir.is_empty() {
+        return;
+    }
+    let mut files = log_files().lock().expect("poisoned");
+    if files.text && files.merged.is_none() {
+        files.merged = open(&format!("{}/../merged.log", dir));
+    }
+    if files.json && files.merged_json.is_none() {
+        files.merged_json = open(&format!("{}/../merged.jsonl", dir));
+    }
+    if files.text && !files.islands.contains_key(&config.island_id) {
+        if let Some(file) = open(&format!("{}/island.log", dir)) {
+            files.islands.insert(config.island_id, file);
+        }
+    }
+}
+
+fn current_island() -> Option<usize> {
+    ISLAND.with(Cell::get)
+}
+
+/// Formats a record for the log files, as tab-separated fields -- time,
+/// level, island, epoch, thread, target, and message -- with any
+/// continuation lines of the message indented:
 ///
+/// ```text
+/// 2020-06-01 12:00:00  INFO  island=3  epoch=12  thread=ThreadId(7)  berbalib::observer  ...
+/// ```
+fn line(record: &Record<'_>, island: Option<usize>, epoch: usize) -> String {
+    format!(
+        "{}\t{}\tisland={}\tepoch={}\tthread={}\t{}\t{}\n",
+        Local::now().format("%F %T"),
+        record.level(),
+        island
+            .map(|i| i.to_string())
+            .unwrap_or_else(|| "-".to_string()),
+        epoch,
+        thread_name(),
+        record.target(),
+        record.args().to_string().replace('\n', "\n\t"),
+    )
+}
+
+/// Formats a record as a line of JSON, with the same fields as `line`.
+fn json_line(record: &Record<'_>, island: Option<usize>, epoch: usize) -> String {
+    let mut line = serde_json::json!({
+        "time": Local::now().to_rfc3339(),
+        "level": record.level().to_string(),
+        "island": island,
+        "epoch": epoch,
+        "thread": thread_name(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    })
+    .to_string();
+    line.push('\n');
+    line
+}
+
+fn thread_name() -> String {
+    let thread = std::thread::current();
+    thread
+        .name()
+        .map(String::from)
+        .unwrap_or_else(|| format!("{:?}", thread.id()))
+}
+
+struct Logger {
+    /// Filters the records, and writes them to stderr, if `to_stderr`
+    stderr: env_logger::Logger,
+    to_stderr: bool,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.stderr.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.stderr.matches(record) {
+            return;
+        }
+        if self.to_stderr {
+            self.stderr.log(record);
+        }
+        // no file has been opened yet
+        if !INIT_LOG_FILES.is_completed() {
+            return;
+        }
+        let island = current_island();
+        let epoch = EPOCH_COUNTER.load(Ordering::Relaxed);
+        if let Ok(mut files) = log_files().lock() {
+            if files.text {
+                let line = line(record, island, epoch);
+                if let Some(ref mut merged) = files.merged {
+                    let _ = merged.write_all(line.as_bytes());
+                }
+                if let Some(file) = island.and_then(|i| files.islands.get_mut(&i)) {
+                    let _ = file.write_all(line.as_bytes());
+                }
+            }
+            if let Some(ref mut merged_json) = files.merged_json {
+                let _ = merged_json.write_all(json_line(record, island, epoch).as_bytes());
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush()
+    }
+}
+
+/// This function initializes the Berbalang logger, at the levels set by
+/// the `BERBALANG_LOG` environment variable, if any, and otherwise only
+/// for errors.
 pub fn init(population_name: &str) {
+    init_with(population_name, &LoggingConfig::default())
+}
+
+/// Initializes the logger as the config's `[logging]` section has it, any
+/// levels set by `BERBALANG_LOG` taking precedence over the section's.
+pub fn init_with(population_name: &str, logging: &LoggingConfig) {
+    {
+        let mut files = log_files().lock().expect("poisoned");
+        files.text = logging.destinations.contains(&LogDestination::File);
+        files.json = logging.destinations.contains(&LogDestination::Json);
+    }
     let population_name = population_name.to_owned();
-    env_logger::from_env(env_logger::Env::from("BERBALANG_LOG"))
-        .format(move |f, record| {
-            use env_logger::fmt::Color;
-            use log::Level::*;
-
-            let epoch = EPOCH_COUNTER.load(Ordering::Relaxed);
-
-            let path = match record.level() {
-                Error | Debug | Warn | Trace => format!(
-                    ":{}:{}",
-                    record.file().unwrap_or(""),
-                    record.line().unwrap_or(0),
-                ),
-                Info => "".to_string(),
-            };
-
-            let mut time_style = f.style();
-            time_style
-                .set_color(Color::Rgb(100, 100, 100))
-                .set_bold(false);
-            let time = time_style.value(format!("[{}]", Local::now().format("%F %T")));
-
-            let mut level_style = f.style();
-            let color = match record.level() {
-                Error => Color::Red,
-                Warn => Color::Yellow,
-                Info => Color::Green,
-                Trace => Color::Magenta,
-                Debug => Color::Cyan,
-            };
-            level_style.set_color(color).set_bold(true);
-            let level = level_style.value(record.level());
-
-            let mut pop_style = f.style();
-            pop_style.set_color(Color::White).set_bold(true);
-            let population_name = pop_style.value(&population_name);
-
-            writeln!(
-                f,
-                "[{level}{path}]{time} {pop_name} at epoch {epoch} => {record:#x?}",
-                time = time,
-                path = path,
-                level = level,
-                pop_name = population_name,
-                record = record.args(),
-                epoch = epoch,
-            )
-        })
-        .init();
+    let mut builder = env_logger::Builder::new();
+    for (module, level) in logging
+        .filters()
+        .expect("The log levels were checked when the config was loaded")
+    {
+        builder.filter(module.as_ref().map(String::as_str), level);
+    }
+    if let Ok(filters) = std::env::var("BERBALANG_LOG") {
+        builder.parse_filters(&filters);
+    }
+    if let Ok(style) = std::env::var("BERBALANG_LOG_STYLE") {
+        builder.parse_write_style(&style);
+    }
+    builder.format(move |f, record| {
+        use env_logger::fmt::Color;
+        use log::Level::*;
+
+        let epoch = EPOCH_COUNTER.load(Ordering::Relaxed);
+
+        let path = match record.level() {
+            Error | Debug | Warn | Trace => format!(
+                ":{}:{}",
+                record.file().unwrap_or(""),
+                record.line().unwrap_or(0),
+            ),
+            Info => "".to_string(),
+        };
+
+        let mut time_style = f.style();
+        time_style
+            .set_color(Color::Rgb(100, 100, 100))
+            .set_bold(false);
+        let time = time_style.value(format!("[{}]", Local::now().format("%F %T")));
+
+        let mut level_style = f.style();
+        let color = match record.level() {
+            Error => Color::Red,
+            Warn => Color::Yellow,
+            Info => Color::Green,
+            Trace => Color::Magenta,
+            Debug => Color::Cyan,
+        };
+        level_style.set_color(color).set_bold(true);
+        let level = level_style.value(record.level());
+
+        let mut pop_style = f.style();
+        pop_style.set_color(Color::White).set_bold(true);
+        let population_name = match current_island() {
+            Some(island) => pop_style.value(format!("{}/island_{}", population_name, island)),
+            None => pop_style.value(population_name.clone()),
+        };
+
+        writeln!(
+            f,
+            "[{level}{path}]{time} {pop_name} at epoch {epoch} => {record:#x?}",
+            time = time,
+            path = path,
+            level = level,
+            pop_name = population_name,
+            record = record.args(),
+            epoch = epoch,
+        )
+    });
+    let stderr = builder.build();
+    let max_level = stderr.filter();
+    let to_stderr = logging.destinations.contains(&LogDestination::Stderr);
+    log::set_boxed_logger(Box::new(Logger { stderr, to_stderr }))
+        .map(|()| log::set_max_level(max_level))
+        .expect("Failed to initialize the logger");
+}
+
+#[cfg(test)]
+mod test {
+    use log::Level;
+
+    use super::*;
+
+    #[test]
+    fn test_line() {
+        let line = line(
+            &Record::builder()
+                .args(format_args!("first\nsecond"))
+                .level(Level::Warn)
+                .target("berbalib::observer")
+                .build(),
+            Some(3),
+            12,
+        );
+        let fields = line.trim_end().split('\t').collect::<Vec<_>>();
+        assert_eq!(fields[1], "WARN");
+        assert_eq!(fields[2], "island=3");
+        assert_eq!(fields[3], "epoch=12");
+        assert!(fields[4].starts_with("thread="));
+        assert_eq!(fields[5], "berbalib::observer");
+        assert_eq!(&fields[6..], &["first\n", "second"]);
+    }
+
+    #[test]
+    fn test_json_line() {
+        let line = json_line(
+            &Record::builder()
+                .args(format_args!("first\nsecond"))
+                .level(Level::Info)
+                .target("berbalib::emulator")
+                .build(),
+            None,
+            4,
+        );
+        assert!(line.ends_with('\n'));
+        let record: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(record["level"], "INFO");
+        assert_eq!(record["island"], serde_json::Value::Null);
+        assert_eq!(record["epoch"], 4);
+        assert_eq!(record["target"], "berbalib::emulator");
+        assert_eq!(record["message"], "first\nsecond");
+    }
 }
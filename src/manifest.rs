@@ -0,0 +1,279 @@
+//! The run manifest, written to `manifest.json` in the population's data
+//! directory at startup, records what's needed to reproduce the run: the
+//! config as resolved, with its defaults filled in, the random seed, and
+//! the seeds derived from it for each island, the commit berbalang was
+//! built from, the host, the version of unicorn, and
+//! the SHA-256 digest of the target binary.
+//!
+//! If the data directory already holds a manifest -- because a run with the
+//! same population name is being restarted, on the same day -- the new run
+//! is refused if it would diverge from the old one, unless the
+//! `BERBALANG_ALLOW_DIVERGENT_RESUME` environment variable is set, in which
+//! case its manifest is written alongside the old one.
+//!
+//! When the run ends, the manifest is updated with when it ended, how long
+//! it ran, and whether it ran out of time.
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::configure::Config;
+use crate::error::Error;
+use crate::util::random::{derive_seed, MIGRATION_STREAM};
+
+pub const ALLOW_DIVERGENCE_VAR: &str = "BERBALANG_ALLOW_DIVERGENT_RESUME";
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Manifest {
+    pub berbalang_version: String,
+    /// The commit berbalang was built from, suffixed with `-dirty` if the
+    /// tree had uncommitted changes, if it was built from a git checkout
+    pub git_commit: Option<String>,
+    pub hostname: String,
+    pub unicorn_version: String,
+    pub started: String,
+    pub random_seed: u64,
+    /// The seeds of each island, derived from `random_seed`
+    #[serde(default)]
+    pub island_seeds: Vec<IslandSeeds>,
+    pub binary_path: String,
+    /// The SHA-256 digest of the target binary, if there is one
+    pub binary_sha256: Option<String>,
+    pub config: serde_json::Value,
+    #[serde(default)]
+    pub finished: Option<String>,
+    #[serde(default)]
+    pub runtime_secs: Option<u64>,
+    /// Whether the run was stopped by its `timeout`
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Whether the run was stopped by SIGINT or SIGTERM. See `shutdown`.
+    #[serde(default)]
+    pub interrupted: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct IslandSeeds {
+    pub island: usize,
+    /// The island's `random_seed`
+    pub seed: u64,
+    /// The seed of the island's migration decisions, derived from its own
+    pub migration_seed: u64,
+}
+
+fn island_seeds(config: &Config) -> Vec<IslandSeeds> {
+    config
+        .island_seeds()
+        .into_iter()
+        .enumerate()
+        .map(|(island, seed)| IslandSeeds {
+            island,
+            seed,
+            migration_seed: derive_seed(seed, island, MIGRATION_STREAM),
+        })
+        .collect()
+}
+
+/// The fields of the config that vary between the islands of a run, or
+/// between invocations of the same run, and so aren't compared.
+const VOLATILE_FIELDS: [(&str, &str); 2] = [("observer", "full_data_directory"), ("", "island_id")];
+
+pub(crate) fn resolved_config(config: &Config) -> Result<serde_json::Value, Error> {
+    let mut value = serde_json::to_value(config)?;
+    for (section, field) in VOLATILE_FIELDS.iter() {
+        let table = if section.is_empty() {
+            Some(&mut value)
+        } else {
+            value.get_mut(*section)
+        };
+        if let Some(serde_json::Value::Object(table)) = table {
+            table.remove(*field);
+        }
+    }
+    Ok(value)
+}
+
+fn sha256_of_file(path: &str) -> Option<String> {
+    if path.is_empty() {
+        return None;
+    }
+    match fs::read(path) {
+        Ok(bytes) => Some(hex::encode(Sha256::digest(&bytes))),
+        Err(e) => {
+            log::warn!("Failed to read {} to take its digest: {:?}", path, e);
+            None
+        }
+    }
+}
+
+impl Manifest {
+    pub fn of(config: &Config) -> Result<Self, Error> {
+        let (major, minor) = unicorn::unicorn_version();
+        Ok(Self {
+            berbalang_version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: option_env!("BERBALANG_GIT_COMMIT").map(String::from),
+            hostname: gethostname::gethostname().to_string_lossy().to_string(),
+            unicorn_version: format!("{}.{}", major, minor),
+            started: Local::now().to_rfc3339(),
+            random_seed: config.random_seed,
+            island_seeds: island_seeds(config),
+            binary_path: config.roper.binary_path.clone(),
+            binary_sha256: sha256_of_file(&config.roper.binary_path),
+            config: resolved_config(config)?,
+            finished: None,
+            runtime_secs: None,
+            timed_out: false,
+            interrupted: false,
+        })
+    }
+
+    /// Describes each way in which a run with this manifest would diverge
+    /// from one with the other.
+    pub fn divergences(&self, other: &Self) -> Vec<String> {
+        let mut divergences = Vec::new();
+        if self.git_commit != other.git_commit {
+            divergences.push(format!(
+                "built from commit {:?}, not {:?}",
+                self.git_commit, other.git_commit
+            ));
+        }
+        if self.unicorn_version != other.unicorn_version {
+            divergences.push(format!(
+                "unicorn version {}, not {}",
+                self.unicorn_version, other.unicorn_version
+            ));
+        }
+        if self.random_seed != other.random_seed {
+            divergences.push(format!(
+                "random seed {}, not {}",
+                self.random_seed, other.random_seed
+            ));
+        }
+        // a manifest written before the seeds were recorded has none
+        if !other.island_seeds.is_empty() && self.island_seeds != other.island_seeds {
+            divergences.push("differently derived island seeds".to_string());
+        }
+        if self.binary_sha256 != other.binary_sha256 {
+            divergences.push(format!(
+                "binary digest {:?}, not {:?}",
+                self.binary_sha256, other.binary_sha256
+            ));
+        }
+        if self.config != other.config {
+            divergences.push("a different config".to_string());
+        }
+        divergences
+    }
+}
+
+/// Writes the run's manifest to the population's data directory, or, if
+/// one is already there, checks that the run doesn't diverge from it.
+pub fn write_or_verify(config: &Config) -> Result<Manifest, Error> {
+    write_or_verify_allowing(config, std::env::var(ALLOW_DIVERGENCE_VAR).is_ok())
+}
+
+/// As `write_or_verify`, but allowing divergence, with a warning, if
+/// `allow_divergence`, rather than as `ALLOW_DIVERGENCE_VAR` says.
+pub fn write_or_verify_allowing(
+    config: &Config,
+    allow_divergence: bool,
+) -> Result<Manifest, Error> {
+    let manifest = Manifest::of(config)?;
+    let dir = format!("{}/..", config.data_directory());
+    let path = format!("{}/manifest.json", dir);
+    if Path::new(&path).exists() {
+        let prior: Manifest = serde_json::from_str(&fs::read_to_string(&path)?)?;
+        let divergences = manifest.divergences(&prior);
+        if divergences.is_empty() {
+            log::info!("Resuming the run recorded in {}", path);
+            return Ok(manifest);
+        }
+        let msg = format!(
+            "This run would diverge from the one recorded in {}: it has {}",
+            path,
+            divergences.join(", ")
+        );
+        if !allow_divergence {
+            return Err(Error::Misc(format!(
+                "{}. Set {} to run it anyway.",
+                msg, ALLOW_DIVERGENCE_VAR
+            )));
+        }
+        log::warn!("{}", msg);
+        let path = format!("{}/manifest_{}.json", dir, Local::now().format("%H%M%S"));
+        fs::write(&path, serde_json::to_string_pretty(&manifest)?)?;
+        return Ok(manifest);
+    }
+    fs::write(&path, serde_json::to_string_pretty(&manifest)?)?;
+    log::info!("Wrote run manifest to {}", path);
+    Ok(manifest)
+}
+
+/// Reads the manifest of the run in the given directory, the parent of its
+/// island directories.
+pub fn read(run_directory: &Path) -> Result<Manifest, Error> {
+    let path = run_directory.join("manifest.json");
+    Ok(serde_json::from_str(&fs::read_to_string(&path)?)?)
+}
+
+/// Records the end of the run in its manifest, given the data directory of
+/// any of its islands.
+pub fn record_runtime(
+    data_directory: &str,
+    runtime: Duration,
+    timed_out: bool,
+    interrupted: bool,
+) -> Result<(), Error> {
+    let path = format!("{}/../manifest.json", data_directory);
+    let mut manifest: Manifest = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    manifest.finished = Some(Local::now().to_rfc3339());
+    manifest.runtime_secs = Some(runtime.as_secs());
+    manifest.timed_out = timed_out;
+    manifest.interrupted = interrupted;
+    fs::write(&path, serde_json::to_string_pretty(&manifest)?)?;
+    log::info!("Recorded the run's runtime of {:?} in {}", runtime, path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_divergences() {
+        let mut config = Config::default();
+        config.random_seed = 42;
+        config.observer.full_data_directory = "/tmp/a/island_0".to_string();
+        let manifest = Manifest::of(&config).unwrap();
+
+        let mut other = config.clone();
+        other.island_id = 3;
+        other.observer.full_data_directory = "/tmp/a/island_3".to_string();
+        let mut same = Manifest::of(&other).unwrap();
+        same.started = "later".to_string();
+        assert!(manifest.divergences(&same).is_empty());
+
+        other.random_seed = 43;
+        other.pop_size = 100;
+        let divergent = Manifest::of(&other).unwrap();
+        assert_eq!(manifest.divergences(&divergent).len(), 2);
+    }
+
+    #[test]
+    fn test_island_seeds() {
+        let mut config = Config::default();
+        config.random_seed = 42;
+        config.num_islands = 3;
+        let seeds = Manifest::of(&config).unwrap().island_seeds;
+        assert_eq!(seeds.len(), 3);
+        assert_eq!(seeds[2].seed, config.island_seeds()[2]);
+        assert_eq!(
+            seeds[2].migration_seed,
+            derive_seed(seeds[2].seed, 2, MIGRATION_STREAM)
+        );
+    }
+}
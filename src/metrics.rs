@@ -0,0 +1,212 @@
+//! Statistics of the observation window beyond those that `WindowStats`
+//! always takes. Each is a `WindowStatistic`, which is fed the window and
+//! returns named values, and those listed in `observer.window_statistics`
+//! are taken whenever the window is aggregated, and reported alongside the
+//! rest, under the `custom` field of the window statistics, and as columns
+//! of their own in `window_statistics.csv`. Their values can be referred to
+//! by name in stopping predicates, too.
+//!
+//! A few statistics that make sense for any kind of specimen are built in.
+//! Others, which may be particular to a kind of specimen, can be registered
+//! under a name with `register`, before the run starts.
+use std::any::{Any, TypeId};
+use std::sync::{Once, RwLock};
+
+use hashbrown::HashMap;
+
+use crate::configure::Config;
+use crate::error::Error;
+use crate::evolution::{Genome, Phenome};
+
+pub trait WindowStatistic<O>: Send {
+    /// Returns the statistic's values, each with a name unique among the
+    /// statistics in use.
+    fn compute(&self, window: &[O], config: &Config) -> Vec<(String, f64)>;
+}
+
+impl<O, F> WindowStatistic<O> for F
+where
+    F: Fn(&[O], &Config) -> Vec<(String, f64)> + Send,
+{
+    fn compute(&self, window: &[O], config: &Config) -> Vec<(String, f64)> {
+        self(window, config)
+    }
+}
+
+type Factory<O> = fn() -> Box<dyn WindowStatistic<O>>;
+
+static INIT_REGISTRY: Once = Once::new();
+static mut REGISTRY: Option<RwLock<HashMap<(TypeId, String), Box<dyn Any + Send + Sync>>>> = None;
+
+fn registry() -> &'static RwLock<HashMap<(TypeId, String), Box<dyn Any + Send + Sync>>> {
+    INIT_REGISTRY.call_once(|| unsafe { REGISTRY = Some(RwLock::new(HashMap::new())) });
+    unsafe {
+        REGISTRY
+            .as_ref()
+            .expect("The window statistics registry has not been initialized")
+    }
+}
+
+/// Registers a statistic of windows of specimens of type `O`, so that it
+/// can be enabled by listing its name in `observer.window_statistics`. A
+/// registered statistic takes precedence over a built-in one of the same
+/// name.
+pub fn register<O: 'static>(name: &str, factory: Factory<O>) {
+    registry()
+        .write()
+        .expect("poisoned")
+        .insert((TypeId::of::<O>(), name.to_string()), Box::new(factory));
+}
+
+fn mean_and_std_dev<I: Iterator<Item = f64>>(xs: I) -> (f64, f64) {
+    let xs = xs.collect::<Vec<f64>>();
+    let n = xs.len() as f64;
+    let mean = xs.iter().sum::<f64>() / n;
+    let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+    (mean, var.sqrt())
+}
+
+/// The names of the built-in statistics
+pub const BUILTIN_STATISTICS: [&str; 4] = [
+    "length_std_dev",
+    "generation",
+    "fitness_std_dev",
+    "scored_fraction",
+];
+
+fn builtin<O: Phenome + Genome>(name: &str) -> Option<Box<dyn WindowStatistic<O>>> {
+    let statistic: Box<dyn WindowStatistic<O>> = match name {
+        // the spread of the genome lengths, which tells bloat apart from
+        // a few outsized specimens
+        "length_std_dev" => Box::new(|window: &[O], _: &Config| {
+            let (_, std_dev) = mean_and_std_dev(window.iter().map(|g| g.len() as f64));
+            vec![("length_std_dev".to_string(), std_dev)]
+        }),
+        "generation" => Box::new(|window: &[O], _: &Config| {
+            let (mean, _) = mean_and_std_dev(window.iter().map(|g| g.generation() as f64));
+            let max = window.iter().map(|g| g.generation()).max().unwrap_or(0);
+            vec![
+                ("mean_generation".to_string(), mean),
+                ("max_generation".to_string(), max as f64),
+            ]
+        }),
+        "fitness_std_dev" => Box::new(|window: &[O], config: &Config| {
+            let weighting = &config.fitness.weighting;
+            let fitnesses = window.iter().filter_map(|g| g.scalar_fitness(weighting));
+            let (_, std_dev) = mean_and_std_dev(fitnesses);
+            vec![("fitness_std_dev".to_string(), std_dev)]
+        }),
+        // the fraction of the window that has been scored
+        "scored_fraction" => Box::new(|window: &[O], _: &Config| {
+            let scored = window.iter().filter(|g| g.fitness().is_some()).count();
+            vec![(
+                "scored_fraction".to_string(),
+                scored as f64 / window.len() as f64,
+            )]
+        }),
+        _ => return None,
+    };
+    Some(statistic)
+}
+
+/// Looks up the named statistic, among those registered for `O`, and then
+/// among the built-in ones.
+pub fn get<O: Phenome + Genome + 'static>(name: &str) -> Option<Box<dyn WindowStatistic<O>>> {
+    let registered = registry()
+        .read()
+        .expect("poisoned")
+        .get(&(TypeId::of::<O>(), name.to_string()))
+        .and_then(|factory| factory.downcast_ref::<Factory<O>>())
+        .map(|factory| factory());
+    registered.or_else(|| builtin(name))
+}
+
+/// Checks that each of the statistics named is built in, or has been
+/// registered, for some kind of specimen. Statistics are to be registered
+/// before the config is loaded, for this to find them.
+pub fn check_statistics(names: &[String]) -> Result<(), Error> {
+    let registry = registry().read().expect("poisoned");
+    for name in names {
+        if !BUILTIN_STATISTICS.contains(&name.as_str())
+            && !registry.keys().any(|(_, registered)| registered == name)
+        {
+            return Err(Error::Parsing(format!(
+                "No such statistic as {:?}, in observer.window_statistics. Try one of {}, \
+                 or register it with metrics::register",
+                name,
+                BUILTIN_STATISTICS.join(", ")
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The statistics enabled in the config. Those that aren't found for `O`,
+/// having been registered for another kind of specimen, are skipped.
+pub fn get_statistics<O: Phenome + Genome + 'static>(
+    config: &Config,
+) -> Vec<Box<dyn WindowStatistic<O>>> {
+    config
+        .observer
+        .window_statistics
+        .iter()
+        .filter_map(|name| {
+            let statistic = get(name);
+            if statistic.is_none() {
+                log::error!("No such statistic as {} for this kind of specimen", name);
+            }
+            statistic
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mean_and_std_dev() {
+        let (mean, std_dev) =
+            mean_and_std_dev(vec![2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0].into_iter());
+        assert_eq!(mean, 5.0);
+        assert_eq!(std_dev, 2.0);
+    }
+
+    #[test]
+    fn test_check_statistics() {
+        assert!(check_statistics(&["generation".to_string()]).is_ok());
+        assert!(check_statistics(&["generaton".to_string()]).is_err());
+        struct Checked;
+        fn nothing() -> Box<dyn WindowStatistic<Checked>> {
+            Box::new(|_: &[Checked], _: &Config| vec![])
+        }
+        register::<Checked>("nothing_much", nothing);
+        assert!(check_statistics(&["nothing_much".to_string()]).is_ok());
+    }
+
+    #[test]
+    fn test_register() {
+        struct Marker;
+        fn count() -> Box<dyn WindowStatistic<Marker>> {
+            Box::new(|window: &[Marker], _: &Config| {
+                vec![("count".to_string(), window.len() as f64)]
+            })
+        }
+        register::<Marker>("count", count);
+        let factory = registry()
+            .read()
+            .unwrap()
+            .get(&(TypeId::of::<Marker>(), "count".to_string()))
+            .and_then(|f| f.downcast_ref::<Factory<Marker>>())
+            .cloned()
+            .unwrap();
+        let values = factory().compute(&[Marker, Marker], &Config::default());
+        assert_eq!(values, vec![("count".to_string(), 2.0)]);
+        // registered for one type of specimen, it isn't for another
+        assert!(registry()
+            .read()
+            .unwrap()
+            .get(&(TypeId::of::<u8>(), "count".to_string()))
+            .is_none());
+    }
+}
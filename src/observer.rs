@@ -1,24 +1,52 @@
 // A Logger needs to asynchronously gather and periodically
 // record information on the evolutionary process.
 
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::fs;
 use std::fs::OpenOptions;
+use std::hash::{Hash, Hasher};
 use std::io::BufWriter;
 use std::io::Write;
 use std::path::Path;
-use std::sync::atomic::{self, AtomicUsize};
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::Arc;
+use std::sync::atomic::{self, AtomicBool, AtomicUsize};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
 use rand::{thread_rng, seq::IteratorRandom};
 use std::thread::{spawn, JoinHandle};
+use std::time::{Duration, Instant};
 
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
+use serde::Serialize;
 
-use crate::configure::Config;
+use crate::aggregator::{self, IslandSummary};
+use crate::artifacts;
+use crate::bests::{self, Bests};
+use crate::configure::{Cadence, Config, FrontFormat, RetentionPolicy};
+use crate::control::{self, Controls};
+use crate::dashboard::DashboardSink;
+use crate::dataset::Split;
+use crate::emulator::loader;
+use crate::error::Error;
+use crate::events::{self, Event};
 use crate::evolution::{Genome, Phenome};
+use crate::fitness::FitnessScore;
+use crate::genealogy::GenealogySink;
+use crate::journal::JournalSink;
+use crate::logger;
+use crate::metrics::{self, WindowStatistic};
+use crate::resources::{self, ResourceRecord};
+use crate::shutdown::ObserverGuard;
+use crate::stopping::Stopper;
+#[cfg(any(feature = "zmq", feature = "websocket"))]
+use crate::stream::StreamSink;
+use crate::summary::{self, ChampionSummary, HistoryPoint, RunSummary};
+use crate::tensorboard::TensorBoardSink;
 use crate::util::count_min_sketch::CountMinSketch;
-use crate::util::dump::dump;
+use crate::util::diversity::{entropy_of_counts, mean_pairwise_distance, PathTrie, PathTrieStats};
+use crate::util::dump::{dump, dump_compressed};
+use crate::util::soup_store::SoupStore;
+use crate::util::statistics::{percentile, Histogram};
 
 // TODO: fix the stat writer so that it uses the header() and row() functions.
 
@@ -47,11 +75,22 @@ fn get_log_filename(name: &str, config: &Config) -> String {
 
 pub struct Observer<O: Send> {
     pub handle: JoinHandle<()>,
-    tx: Sender<O>,
+    tx: SyncSender<O>,
+    /// The number of specimens sent, but not yet taken into the window
+    pending: Arc<AtomicUsize>,
+    island: usize,
+    /// Set once the island has spent its budget. See `stopping`.
+    spent: Arc<AtomicBool>,
+    /// The island's epoch, as the window counts it
+    epoch: Arc<AtomicUsize>,
 }
 
 pub type ReportFn<T> = Box<dyn Fn(&Window<T>, usize, &Config) -> () + Sync + Send + 'static>;
 
+/// Scores a specimen on a held-out split of the data. These scores are kept
+/// apart from the specimen's fitness, and have no bearing on selection.
+pub type HoldoutFn<T> = Box<dyn Fn(&T, Split, &Config) -> Option<f64> + Sync + Send + 'static>;
+
 #[allow(dead_code)]
 pub fn default_report_fn<P: Phenome + Genome>(window: &Window<P>, counter: usize, config: &Config) {
     let frame = &window.frame;
@@ -87,35 +126,895 @@ fn epoch_length(config: &Config) -> usize {
     config.pop_size / config.tournament.num_offspring
 }
 
+/// Receives the aggregated statistics of the observation window, and the
+/// champions, as they're crowned.
+pub trait Sink<O>: Send {
+    fn record_stats(&mut self, stats: &WindowStats);
+
+    fn record_champion(&mut self, _champion: &O, _stats: &WindowStats) {}
+
+    fn record_operators(&mut self, _stats: &OperatorStats) {}
+
+    fn record_specimen(&mut self, _specimen: &O, _counter: usize, _epoch: usize) {}
+}
+
+/// Appends the statistics to `window_statistics.csv`.
+pub struct CsvSink {
+    config: Arc<Config>,
+}
+
+impl<O> Sink<O> for CsvSink {
+    fn record_stats(&mut self, stats: &WindowStats) {
+        append_record(stats, "window", &self.config);
+        for (objective, histogram) in stats.histograms.iter() {
+            for (bin, count) in histogram.counts.iter().enumerate() {
+                let (lower, upper) = histogram.bounds(bin);
+                let row = HistogramRow {
+                    counter: stats.counter,
+                    epoch: stats.epoch,
+                    objective,
+                    bin,
+                    lower,
+                    upper,
+                    count: *count,
+                };
+                append_record(&row, "histogram", &self.config);
+            }
+        }
+    }
+}
+
+/// A bin of one of the window's fitness histograms, as a row of
+/// `histogram_statistics.csv`.
+#[derive(Debug)]
+struct HistogramRow<'a> {
+    counter: usize,
+    epoch: usize,
+    objective: &'a str,
+    bin: usize,
+    lower: f64,
+    upper: f64,
+    count: usize,
+}
+
+impl LogRecord for HistogramRow<'_> {
+    fn header(&self) -> String {
+        "counter,epoch,objective,bin,lower,upper,count".to_string()
+    }
+
+    fn row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{}",
+            self.counter, self.epoch, self.objective, self.bin, self.lower, self.upper, self.count
+        )
+    }
+}
+
+/// Logs the statistics.
+pub struct LogSink;
+
+impl<O: Debug> Sink<O> for LogSink {
+    fn record_stats(&mut self, stats: &WindowStats) {
+        log::info!("Island {} window: {:?}", stats.island, stats);
+    }
+
+    fn record_champion(&mut self, champion: &O, stats: &WindowStats) {
+        log::info!(
+            "Island {}: champion after {} specimens: {:?}",
+            stats.island,
+            stats.counter,
+            champion
+        );
+    }
+}
+
+/// The version of the schema of the records written by the `JsonlSink`.
+/// Bump it whenever a field is renamed, removed, or changes its meaning.
+pub const JSONL_SCHEMA_VERSION: u32 = 1;
+
+/// A line of `observations.jsonl`. The `record` field names the kind of
+/// record -- "window", "champion", or "operators" -- and the fields of
+/// the body sit alongside it.
+#[derive(Serialize)]
+pub(crate) struct JsonlRecord<'a, T: Serialize> {
+    schema_version: u32,
+    record: &'static str,
+    #[serde(flatten)]
+    body: &'a T,
+}
+
+impl<'a, T: Serialize> JsonlRecord<'a, T> {
+    pub(crate) fn new(record: &'static str, body: &'a T) -> Self {
+        Self {
+            schema_version: JSONL_SCHEMA_VERSION,
+            record,
+            body,
+        }
+    }
+}
+
+/// A specimen's fitness vector, with enough about it to place it in the
+/// run. The `JsonlSink` records champions this way.
+#[derive(Clone, Debug, Serialize)]
+pub struct SpecimenRecord {
+    pub island: usize,
+    pub counter: usize,
+    pub epoch: usize,
+    pub name: String,
+    pub generation: usize,
+    pub length: usize,
+    pub scalar_fitness: Option<f64>,
+    pub priority_fitness: Option<f64>,
+    pub scores: BTreeMap<String, f64>,
+}
+
+impl SpecimenRecord {
+    pub fn of<O: Phenome + Genome>(
+        specimen: &O,
+        counter: usize,
+        epoch: usize,
+        config: &Config,
+    ) -> Self {
+        Self {
+            island: config.island_id,
+            counter,
+            epoch,
+            name: specimen.name().to_string(),
+            generation: specimen.generation(),
+            length: specimen.len(),
+            scalar_fitness: specimen.scalar_fitness(&config.fitness.weighting),
+            priority_fitness: specimen.scalar_fitness(&config.fitness.priority()),
+            scores: specimen
+                .fitness()
+                .map(|f| {
+                    f.components()
+                        .into_iter()
+                        .map(|(key, score)| (key.to_string(), score))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl LogRecord for SpecimenRecord {
+    fn header(&self) -> String {
+        let mut header =
+            "island,counter,epoch,name,generation,length,scalar_fitness,priority_fitness"
+                .to_string();
+        for key in self.scores.keys() {
+            header.push_str(&format!(",{}", key));
+        }
+        header
+    }
+
+    fn row(&self) -> String {
+        let mut row = format!(
+            "{},{},{},{},{},{},{},{}",
+            self.island,
+            self.counter,
+            self.epoch,
+            self.name,
+            self.generation,
+            self.length,
+            self.scalar_fitness.unwrap_or(std::f64::NAN),
+            self.priority_fitness.unwrap_or(std::f64::NAN),
+        );
+        for score in self.scores.values() {
+            row.push_str(&format!(",{}", score));
+        }
+        row
+    }
+}
+
+/// Whether `a` dominates `b`, taking lower scores to be better.
+pub fn dominates(a: &SpecimenRecord, b: &SpecimenRecord) -> bool {
+    let mut strictly = false;
+    for (key, score) in a.scores.iter() {
+        match b.scores.get(key) {
+            Some(other) if score > other => return false,
+            Some(other) if score < other => strictly = true,
+            _ => {}
+        }
+    }
+    strictly
+}
+
+/// The specimens that no other specimen dominates.
+pub fn pareto_front<'a, I: Iterator<Item = &'a SpecimenRecord> + Clone>(
+    specimens: I,
+) -> Vec<&'a SpecimenRecord> {
+    specimens
+        .clone()
+        .filter(|s| !specimens.clone().any(|t| dominates(t, s)))
+        .collect()
+}
+
+/// A snapshot of the window's Pareto front, as written to
+/// `pareto/front_{counter}.json`.
+#[derive(Debug, Serialize)]
+struct FrontSnapshot<'a> {
+    island: usize,
+    counter: usize,
+    epoch: usize,
+    front: &'a [&'a SpecimenRecord],
+}
+
+/// How often each variation operator was applied in producing the
+/// specimens in the observation window.
+#[derive(Clone, Debug, Serialize)]
+pub struct OperatorStats {
+    pub island: usize,
+    pub counter: usize,
+    pub epoch: usize,
+    pub counts: BTreeMap<String, usize>,
+}
+
+impl OperatorStats {
+    pub fn of<O: Phenome>(frame: &[O], stats: &WindowStats) -> Self {
+        let mut counts = BTreeMap::new();
+        for operator in frame.iter().flat_map(|g| g.operators()) {
+            *counts.entry(operator).or_insert(0) += 1;
+        }
+        Self {
+            island: stats.island,
+            counter: stats.counter,
+            epoch: stats.epoch,
+            counts,
+        }
+    }
+}
+
+/// Appends the statistics, champions, and operator statistics, one JSON
+/// object per line, to `observations.jsonl`.
+pub struct JsonlSink {
+    config: Arc<Config>,
+}
+
+impl JsonlSink {
+    fn append<T: Serialize>(&self, record: &'static str, body: &T) {
+        let filename = format!("{}/observations.jsonl", self.config.data_directory());
+        append_json_line(&filename, &JsonlRecord::new(record, body))
+    }
+}
+
+/// Appends the value to the file as a line of JSON, logging, rather than
+/// returning, any errors, since these records are a side-channel.
+pub fn append_json_line<T: Serialize>(filename: &str, value: &T) {
+    let line = match serde_json::to_string(value) {
+        Ok(line) => line,
+        Err(e) => {
+            log::error!("Failed to serialize record for {}: {:?}", filename, e);
+            return;
+        }
+    };
+    let res = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(filename)
+        .and_then(|fd| writeln!(BufWriter::new(fd), "{}", line));
+    if let Err(e) = res {
+        log::error!("Failed to append to {}: {:?}", filename, e);
+    }
+}
+
+impl<O: Phenome + Genome> Sink<O> for JsonlSink {
+    fn record_stats(&mut self, stats: &WindowStats) {
+        self.append("window", stats)
+    }
+
+    fn record_champion(&mut self, champion: &O, stats: &WindowStats) {
+        self.append(
+            "champion",
+            &SpecimenRecord::of(champion, stats.counter, stats.epoch, &self.config),
+        )
+    }
+
+    fn record_operators(&mut self, stats: &OperatorStats) {
+        self.append("operators", stats)
+    }
+}
+
+/// Buffers the window statistics, and the fitness vectors of the specimens
+/// observed, writing each epoch's worth to `parquet/window_{epoch}.parquet`
+/// and `parquet/specimens_{epoch}.parquet`, respectively. Missing scores are
+/// written as NaN.
+#[cfg(feature = "parquet")]
+pub struct ParquetSink {
+    config: Arc<Config>,
+    epoch: usize,
+    windows: Vec<WindowStats>,
+    specimens: Vec<SpecimenRecord>,
+}
+
+#[cfg(feature = "parquet")]
+impl ParquetSink {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self {
+            config,
+            epoch: 0,
+            windows: Vec::new(),
+            specimens: Vec::new(),
+        }
+    }
+
+    fn maybe_flush(&mut self, epoch: usize) {
+        if epoch != self.epoch {
+            self.flush();
+            self.epoch = epoch;
+        }
+    }
+
+    fn flush(&mut self) {
+        use crate::util::parquet::{write_table, Column};
+        use std::collections::BTreeSet;
+        use std::f64::NAN;
+
+        fn int<T, F: Fn(&T) -> usize>(name: &str, rows: &[T], f: F) -> (String, Column) {
+            let column = Column::Int(rows.iter().map(|r| f(r) as i64).collect());
+            (name.to_string(), column)
+        }
+        fn float<T, F: Fn(&T) -> Option<f64>>(name: &str, rows: &[T], f: F) -> (String, Column) {
+            let column = Column::Float(rows.iter().map(|r| f(r).unwrap_or(NAN)).collect());
+            (name.to_string(), column)
+        }
+
+        let dir = format!("{}/parquet", self.config.data_directory());
+        if let Err(e) = fs::create_dir_all(&dir) {
+            log::error!("Failed to create {}: {:?}", dir, e);
+            return;
+        }
+
+        if !self.windows.is_empty() {
+            let rows = &self.windows;
+            let mut columns = vec![
+                int("island", rows, |r| r.island),
+                int("counter", rows, |r| r.counter),
+                int("epoch", rows, |r| r.epoch),
+                int("size", rows, |r| r.size),
+                float("mean_length", rows, |r| Some(r.mean_length)),
+                float("min_fitness", rows, |r| Some(r.min_fitness)),
+                float("mean_fitness", rows, |r| Some(r.mean_fitness)),
+                float("max_fitness", rows, |r| Some(r.max_fitness)),
+                float("uniqueness", rows, |r| Some(r.uniqueness)),
+                float("genotypic_entropy", rows, |r| Some(r.genotypic_entropy)),
+                float("mean_genome_distance", rows, |r| {
+                    Some(r.mean_genome_distance)
+                }),
+                float("path_diversity", rows, |r| Some(r.path_diversity)),
+            ];
+            let keys = rows
+                .iter()
+                .flat_map(|r| r.mean_scores.keys().cloned())
+                .collect::<BTreeSet<String>>();
+            for key in keys {
+                let name = format!("mean_{}", key);
+                columns.push(float(&name, rows, |r| r.mean_scores.get(&key).cloned()));
+                let name = format!("best_{}", key);
+                columns.push(float(&name, rows, |r| r.best_scores.get(&key).cloned()));
+                let p = |r: &WindowStats| r.score_percentiles.get(&key).cloned();
+                let name = format!("p10_{}", key);
+                columns.push(float(&name, rows, |r| p(r).map(|p| p.p10)));
+                let name = format!("p50_{}", key);
+                columns.push(float(&name, rows, |r| p(r).map(|p| p.p50)));
+                let name = format!("p90_{}", key);
+                columns.push(float(&name, rows, |r| p(r).map(|p| p.p90)));
+            }
+            let path = format!("{}/window_{}.parquet", dir, self.epoch);
+            if let Err(e) = write_table(&path, &columns) {
+                log::error!("Failed to write {}: {:?}", path, e);
+            }
+        }
+
+        if !self.specimens.is_empty() {
+            let rows = &self.specimens;
+            let mut columns = vec![
+                int("island", rows, |r| r.island),
+                int("counter", rows, |r| r.counter),
+                int("epoch", rows, |r| r.epoch),
+                (
+                    "name".to_string(),
+                    Column::Text(rows.iter().map(|r| r.name.clone()).collect()),
+                ),
+                int("generation", rows, |r| r.generation),
+                int("length", rows, |r| r.length),
+                float("scalar_fitness", rows, |r| r.scalar_fitness),
+                float("priority_fitness", rows, |r| r.priority_fitness),
+            ];
+            let keys = rows
+                .iter()
+                .flat_map(|r| r.scores.keys().cloned())
+                .collect::<BTreeSet<String>>();
+            for key in keys {
+                columns.push(float(&key, rows, |r| r.scores.get(&key).cloned()));
+            }
+            let path = format!("{}/specimens_{}.parquet", dir, self.epoch);
+            if let Err(e) = write_table(&path, &columns) {
+                log::error!("Failed to write {}: {:?}", path, e);
+            }
+        }
+
+        self.windows.clear();
+        self.specimens.clear();
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl<O: Phenome + Genome> Sink<O> for ParquetSink {
+    fn record_stats(&mut self, stats: &WindowStats) {
+        self.maybe_flush(stats.epoch);
+        self.windows.push(stats.clone());
+    }
+
+    fn record_specimen(&mut self, specimen: &O, counter: usize, epoch: usize) {
+        self.maybe_flush(epoch);
+        self.specimens
+            .push(SpecimenRecord::of(specimen, counter, epoch, &self.config));
+    }
+}
+
+/// Writes out whatever is left of the last epoch when the observer shuts
+/// down.
+#[cfg(feature = "parquet")]
+impl Drop for ParquetSink {
+    fn drop(&mut self) {
+        self.flush()
+    }
+}
+
+/// Everything needed to re-evaluate or replay a champion long after the
+/// run: the specimen itself, its scores, the configuration it evolved
+/// under, and where and when it was found.
+#[derive(Serialize)]
+pub struct ChampionBundle<'a, O: Serialize> {
+    pub bundle_version: u32,
+    pub berbalang_version: &'static str,
+    pub hostname: String,
+    pub timestamp: String,
+    pub island: usize,
+    pub random_seed: u64,
+    pub counter: usize,
+    pub epoch: usize,
+    pub global_epoch: usize,
+    pub record: SpecimenRecord,
+    pub profile_summary: Option<BTreeMap<String, f64>>,
+    pub config: &'a Config,
+    pub champion: &'a O,
+}
+
+impl<'a, O: Phenome + Genome> ChampionBundle<'a, O> {
+    pub fn new(champion: &'a O, config: &'a Config, counter: usize, epoch: usize) -> Self {
+        Self {
+            bundle_version: 1,
+            berbalang_version: env!("CARGO_PKG_VERSION"),
+            hostname: gethostname::gethostname().to_string_lossy().into_owned(),
+            timestamp: chrono::Local::now().to_rfc3339(),
+            island: config.island_id,
+            random_seed: config.random_seed,
+            counter,
+            epoch,
+            global_epoch: crate::get_epoch_counter(),
+            record: SpecimenRecord::of(champion, counter, epoch, config),
+            profile_summary: champion.profile_summary(),
+            config,
+            champion,
+        }
+    }
+}
+
+/// The sinks, and the features they need, if any.
+const SINKS: [(&str, Option<&str>); 10] = [
+    ("csv", None),
+    ("log", None),
+    ("jsonl", None),
+    ("dashboard", None),
+    ("genealogy", None),
+    ("journal", None),
+    ("tensorboard", None),
+    ("parquet", Some("parquet")),
+    ("zmq", Some("zmq")),
+    ("websocket", Some("websocket")),
+];
+
+fn feature_enabled(feature: &str) -> bool {
+    match feature {
+        "parquet" => cfg!(feature = "parquet"),
+        "zmq" => cfg!(feature = "zmq"),
+        "websocket" => cfg!(feature = "websocket"),
+        _ => false,
+    }
+}
+
+/// Checks that each of the sinks named exists, and was built.
+pub fn check_sinks(sinks: &[String]) -> Result<(), Error> {
+    for name in sinks {
+        match SINKS.iter().find(|(sink, _)| *sink == name.as_str()) {
+            None => {
+                return Err(Error::Parsing(format!(
+                    "No such sink as {:?}: try one of {:?}",
+                    name,
+                    SINKS.iter().map(|(sink, _)| *sink).collect::<Vec<&str>>()
+                )))
+            }
+            Some((_, Some(feature))) if !feature_enabled(feature) => {
+                return Err(Error::Parsing(format!(
+                    "The {} sink requires the {:?} feature",
+                    name, feature
+                )))
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
+
+pub fn get_sinks<O: Phenome + Genome>(config: &Arc<Config>) -> Vec<Box<dyn Sink<O>>> {
+    config
+        .observer
+        .sinks
+        .iter()
+        .map(|name| match name.as_str() {
+            "csv" => Box::new(CsvSink {
+                config: config.clone(),
+            }) as Box<dyn Sink<O>>,
+            "log" => Box::new(LogSink) as Box<dyn Sink<O>>,
+            "jsonl" => Box::new(JsonlSink {
+                config: config.clone(),
+            }) as Box<dyn Sink<O>>,
+            "dashboard" => Box::new(DashboardSink::new(config.clone())) as Box<dyn Sink<O>>,
+            "genealogy" => Box::new(GenealogySink::new(config.clone())) as Box<dyn Sink<O>>,
+            "journal" => Box::new(JournalSink::new(config.clone())) as Box<dyn Sink<O>>,
+            "tensorboard" => Box::new(TensorBoardSink::new(config.clone())) as Box<dyn Sink<O>>,
+            #[cfg(feature = "parquet")]
+            "parquet" => Box::new(ParquetSink::new(config.clone())) as Box<dyn Sink<O>>,
+            #[cfg(feature = "zmq")]
+            "zmq" => Box::new(StreamSink::zmq(config.clone())) as Box<dyn Sink<O>>,
+            #[cfg(feature = "websocket")]
+            "websocket" => Box::new(StreamSink::websocket(config.clone())) as Box<dyn Sink<O>>,
+            s => unreachable!("The sink {} was checked when the config was loaded", s),
+        })
+        .collect()
+}
+
+/// Statistics over the specimens in the observation window. The fitness
+/// statistics are taken over the specimens that have been scored.
+#[derive(Clone, Debug, Serialize)]
+pub struct WindowStats {
+    pub island: usize,
+    pub counter: usize,
+    pub epoch: usize,
+    pub size: usize,
+    pub mean_length: f64,
+    pub min_fitness: f64,
+    pub mean_fitness: f64,
+    pub max_fitness: f64,
+    /// The fraction of the specimens whose genomes are distinct
+    pub uniqueness: f64,
+    /// The Shannon entropy, in bits, of the frequencies of the alleles
+    pub genotypic_entropy: f64,
+    /// The mean normalized edit distance between a sample of pairs of
+    /// genomes
+    pub mean_genome_distance: f64,
+    /// The diversity of the execution paths, as measured by
+    /// `util::diversity::path_trie_diversity`, or NaN if the specimens
+    /// have none. This is taken from the trie of the window's paths, which
+    /// the window keeps up to date as specimens come and go.
+    pub path_diversity: f64,
+    /// The mean of each component of the fitness scores
+    pub mean_scores: BTreeMap<String, f64>,
+    /// The lowest, and so best, value of each component of the fitness
+    /// scores
+    pub best_scores: BTreeMap<String, f64>,
+    /// The 10th, 50th, and 90th percentiles of each component of the
+    /// fitness scores, which tend to be too skewed for the mean to say much
+    pub score_percentiles: BTreeMap<String, Percentiles>,
+    /// A histogram of each component of the fitness scores, with
+    /// `observer.histogram_bins` bins
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub histograms: BTreeMap<String, Histogram>,
+    /// The values of the statistics enabled in
+    /// `observer.window_statistics`. See `metrics`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom: BTreeMap<String, f64>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+pub struct Percentiles {
+    pub p10: f64,
+    pub p50: f64,
+    pub p90: f64,
+}
+
+impl Percentiles {
+    pub fn of(sorted: &[f64]) -> Self {
+        Self {
+            p10: percentile(sorted, 10.0),
+            p50: percentile(sorted, 50.0),
+            p90: percentile(sorted, 90.0),
+        }
+    }
+}
+
+impl WindowStats {
+    pub fn of<O: Phenome + Genome>(
+        frame: &[O],
+        paths: &PathTrie,
+        config: &Config,
+        counter: usize,
+        epoch: usize,
+    ) -> Self {
+        let fitnesses = frame
+            .iter()
+            .filter_map(|g| g.scalar_fitness(&config.fitness.weighting))
+            .collect::<Vec<f64>>();
+        let mut scores: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        for fitness in frame.iter().filter_map(|g| g.fitness()) {
+            for (key, score) in fitness.components() {
+                scores.entry(key.to_string()).or_default().push(score);
+            }
+        }
+        for values in scores.values_mut() {
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        }
+        let genomes = frame
+            .iter()
+            .map(|g| {
+                let mut h = fnv::FnvHasher::default();
+                g.chromosome().hash(&mut h);
+                h.finish()
+            })
+            .collect::<HashSet<u64>>();
+        let mut allele_counts: HashMap<&O::Allele, usize> = HashMap::new();
+        for allele in frame.iter().flat_map(|g| g.chromosome()) {
+            *allele_counts.entry(allele).or_insert(0) += 1;
+        }
+        let chromosomes = frame.iter().map(|g| g.chromosome()).collect::<Vec<_>>();
+        let mean = |xs: &[f64]| xs.iter().sum::<f64>() / xs.len() as f64;
+        Self {
+            island: config.island_id,
+            counter,
+            epoch,
+            size: frame.len(),
+            mean_length: mean(&frame.iter().map(|g| g.len() as f64).collect::<Vec<f64>>()),
+            min_fitness: fitnesses.iter().cloned().fold(std::f64::NAN, f64::min),
+            mean_fitness: mean(&fitnesses),
+            max_fitness: fitnesses.iter().cloned().fold(std::f64::NAN, f64::max),
+            uniqueness: genomes.len() as f64 / frame.len() as f64,
+            genotypic_entropy: entropy_of_counts(allele_counts.values().cloned()),
+            mean_genome_distance: mean_pairwise_distance(
+                &chromosomes,
+                config.observer.diversity_sample_pairs,
+                &mut thread_rng(),
+            ),
+            path_diversity: paths.diversity(),
+            mean_scores: scores
+                .iter()
+                .map(|(key, values)| (key.clone(), mean(values)))
+                .collect(),
+            best_scores: scores
+                .iter()
+                .map(|(key, values)| (key.clone(), values[0]))
+                .collect(),
+            score_percentiles: scores
+                .iter()
+                .map(|(key, values)| (key.clone(), Percentiles::of(values)))
+                .collect(),
+            histograms: scores
+                .iter()
+                .filter_map(|(key, values)| {
+                    Histogram::of(values, config.observer.histogram_bins).map(|h| (key.clone(), h))
+                })
+                .collect(),
+            custom: BTreeMap::new(),
+        }
+    }
+}
+
+impl LogRecord for WindowStats {
+    fn header(&self) -> String {
+        let mut header = "island,counter,epoch,size,mean_length,min_fitness,mean_fitness,\
+                          max_fitness,uniqueness,genotypic_entropy,mean_genome_distance,\
+                          path_diversity"
+            .to_string();
+        for key in self.mean_scores.keys() {
+            header.push_str(&format!(",mean_{}", key));
+        }
+        for key in self.best_scores.keys() {
+            header.push_str(&format!(",best_{}", key));
+        }
+        for key in self.score_percentiles.keys() {
+            header.push_str(&format!(",p10_{},p50_{},p90_{}", key, key, key));
+        }
+        for key in self.custom.keys() {
+            header.push_str(&format!(",{}", key));
+        }
+        header
+    }
+
+    fn row(&self) -> String {
+        let mut row = format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.island,
+            self.counter,
+            self.epoch,
+            self.size,
+            self.mean_length,
+            self.min_fitness,
+            self.mean_fitness,
+            self.max_fitness,
+            self.uniqueness,
+            self.genotypic_entropy,
+            self.mean_genome_distance,
+            self.path_diversity
+        );
+        for score in self.mean_scores.values().chain(self.best_scores.values()) {
+            row.push_str(&format!(",{}", score));
+        }
+        for p in self.score_percentiles.values() {
+            row.push_str(&format!(",{},{},{}", p.p10, p.p50, p.p90));
+        }
+        for value in self.custom.values() {
+            row.push_str(&format!(",{}", value));
+        }
+        row
+    }
+}
+
+/// Tells when a periodic task, performed with the given cadence, is due.
+/// The period of a `WallClock` cadence is passed in, parsed.
+struct Ticker {
+    cadence: Cadence,
+    period: Duration,
+    last_epoch: usize,
+    last_time: Instant,
+}
+
+impl Ticker {
+    fn new(cadence: Cadence, period: Duration) -> Self {
+        Self {
+            cadence,
+            period,
+            last_epoch: 0,
+            last_time: Instant::now(),
+        }
+    }
+
+    fn is_due(&mut self, counter: usize, epoch: usize) -> bool {
+        let due = match self.cadence {
+            Cadence::Evaluations(n) => counter % n.max(1) == 0,
+            Cadence::Epochs(n) => epoch >= self.last_epoch + n.max(1),
+            Cadence::WallClock(_) => self.last_time.elapsed() >= self.period,
+        };
+        if due {
+            self.last_epoch = epoch;
+            self.last_time = Instant::now();
+        }
+        due
+    }
+}
+
 pub struct Window<O: Phenome + 'static> {
     pub frame: Vec<O>,
+    /// The trie of the execution paths of the specimens in the frame
+    path_trie: PathTrie,
     window_size: usize,
+    epoch_length: usize,
+    metrics_ticker: Ticker,
+    dump_ticker: Ticker,
+    /// The run-time controls last taken up
+    controls: Controls,
+    /// The epoch of the last soup and population dumps, which are named by
+    /// epoch, and so made at most once an epoch
+    last_dump_epoch: Option<usize>,
+    sinks: Vec<Box<dyn Sink<O>>>,
+    statistics: Vec<Box<dyn WindowStatistic<O>>>,
     pub config: Arc<Config>,
     counter: usize,
     i: usize,
     report_fn: ReportFn<O>,
+    holdout_fn: Option<HoldoutFn<O>>,
+    /// The best specimen seen, by scalar fitness, which is kept even after
+    /// it leaves the window, or dies out of the population
     pub best: Option<O>,
+    /// The local epoch in which the best scalar fitness last improved
+    best_epoch: usize,
+    /// The best scores seen on each objective, in the run and in the epoch
+    pub bests: Bests,
     pub champion: Option<O>,
+    /// The champion's score on the validation set, if there is one
+    pub champion_validation: Option<f64>,
+    /// The champion's score on the full training set, if the epochs are
+    /// scored on mini-batches of it
+    pub champion_training: Option<f64>,
+    /// The local epoch in which the champion last improved
+    champion_epoch: usize,
+    /// When the current epoch began, and the counter at the time
+    epoch_started: (Instant, usize),
+    stopper: Stopper,
+    /// The statistics of each aggregation of the window, for the summary
+    history: Vec<HistoryPoint>,
+    /// Whether the island's run has finished, and its summary been written
+    finished: AtomicBool,
+    /// Whether the island has spent its budget, shared with the `Observer`
+    spent: Arc<AtomicBool>,
     // priority fitness best
     pub archive: Vec<O>,
-    pub local_epoch: AtomicUsize,
+    /// The island's epoch, shared with the `Observer`
+    pub local_epoch: Arc<AtomicUsize>,
+    soup_store: Mutex<SoupStore>,
     // stat_writers: HashMap<&'static str, Arc<Mutex<csv::Writer<fs::File>>>>,
 }
 
 impl<O: Genome + Phenome + 'static> Window<O> {
-    fn new(report_fn: ReportFn<O>, config: Arc<Config>) -> Self {
-        let window_size = epoch_length(&config);
+    fn new(
+        report_fn: ReportFn<O>,
+        holdout_fn: Option<HoldoutFn<O>>,
+        sinks: Vec<Box<dyn Sink<O>>>,
+        config: Arc<Config>,
+        spent: Arc<AtomicBool>,
+        local_epoch: Arc<AtomicUsize>,
+    ) -> Self {
+        let epoch_length = epoch_length(&config);
+        let window_size = config.observer.window_size.unwrap_or(epoch_length).max(1);
+        let metrics_cadence = config.observer.metrics_cadence.clone().unwrap_or_else(|| {
+            Cadence::Evaluations(config.observer.report_every.unwrap_or(epoch_length))
+        });
+        let dump_cadence = config
+            .observer
+            .dump_cadence
+            .clone()
+            .unwrap_or(Cadence::Epochs(1));
+        let period = |cadence: &Cadence| {
+            cadence
+                .period()
+                .expect("The cadences were checked when the config was loaded")
+        };
+        let metrics_period = period(&metrics_cadence);
+        let dump_period = period(&dump_cadence);
+        // a resumed island takes up the epoch of its checkpoint, and island
+        // 0, which keeps the global epoch, takes up that too
+        let epoch = local_epoch.load(atomic::Ordering::Relaxed);
+        if epoch > 0 && config.island_id == 0 {
+            crate::EPOCH_COUNTER.store(epoch, atomic::Ordering::Relaxed);
+        }
+        let mut metrics_ticker = Ticker::new(metrics_cadence, metrics_period);
+        metrics_ticker.last_epoch = epoch;
+        let mut dump_ticker = Ticker::new(dump_cadence, dump_period);
+        dump_ticker.last_epoch = epoch;
         Self {
             frame: Vec::with_capacity(window_size),
+            path_trie: PathTrie::new(config.observer.path_trie_prefix_len),
             window_size,
-            config,
+            epoch_length,
+            metrics_ticker,
+            dump_ticker,
+            controls: Controls::default(),
+            last_dump_epoch: None,
+            sinks,
+            statistics: metrics::get_statistics(&config),
             counter: 0,
             i: 0,
             report_fn,
+            holdout_fn,
             best: None,
+            best_epoch: epoch,
+            bests: Bests::default(),
             champion: None,
+            champion_validation: None,
+            champion_training: None,
+            champion_epoch: epoch,
+            epoch_started: (Instant::now(), 0),
+            stopper: Stopper::new(&config.stopping),
+            history: vec![],
+            finished: AtomicBool::new(false),
+            spent,
             archive: vec![],
-            local_epoch: AtomicUsize::new(0),
+            local_epoch,
+            soup_store: Mutex::new(SoupStore::new(
+                format!("{}/soup", config.data_directory()),
+                config.observer.soup_keyframe_every,
+            )),
+            config,
         }
     }
 
@@ -130,10 +1029,16 @@ impl<O: Genome + Phenome + 'static> Window<O> {
             self.config.num_epochs != 0 && self.config.num_epochs <= crate::get_epoch_counter();
         if epoch_limit_reached {
             log::debug!("epoch limit reached");
-            self.report();
-            self.dump_soup();
-            self.dump_population();
-            crate::stop_everything(self.config.island_id, false);
+            self.finish("epoch limit reached");
+        } else if let Some(reason) = self.spent_budget() {
+            log::info!(
+                "Island {} has spent its budget: {}",
+                self.config.island_id,
+                reason
+            );
+            self.spend_budget(&reason);
+        } else {
+            self.wind_down_if_stopped();
         }
 
         if let Some(ref champion) = self.champion {
@@ -141,12 +1046,170 @@ impl<O: Genome + Phenome + 'static> Window<O> {
                 let path = format!("{}/winning_champion.json.gz", self.config.data_directory());
                 log::info!("dumping winning champion to {}", path);
                 dump(champion, &path).expect("failed to dump champion");
+                self.test_champion();
                 self.report();
+                events::emit(
+                    Event::TargetReached {
+                        island: self.config.island_id,
+                        epoch: self.get_local_epoch(),
+                        name: champion.name().to_string(),
+                        fitness: champion.scalar_fitness(&self.config.fitness.priority()),
+                    },
+                    &self.config,
+                );
+                self.conclude("goal reached");
                 crate::stop_everything(self.config.island_id, true);
             }
         }
     }
 
+    /// Finishes the island's run, if the run's `timeout` has expired, or
+    /// it's been interrupted by a signal, and it hasn't finished already.
+    fn wind_down_if_stopped(&self) {
+        if self.finished.load(atomic::Ordering::Relaxed) {
+            return;
+        }
+        if crate::shutdown::requested() {
+            log::info!(
+                "Island {} is winding down, the run having been interrupted",
+                self.config.island_id
+            );
+            self.finish("interrupted");
+        } else if crate::timeout_expired() {
+            log::info!(
+                "Island {} is winding down, the run having timed out after {:?}",
+                self.config.island_id,
+                crate::uptime()
+            );
+            self.finish("timeout reached");
+        }
+    }
+
+    /// Describes the budget the island has spent, if it has spent one and
+    /// is still running.
+    fn spent_budget(&self) -> Option<String> {
+        if self.finished.load(atomic::Ordering::Relaxed) || !crate::keep_going() {
+            return None;
+        }
+        let emulation_time = if self.stopper.has_emulator_budget() {
+            resources::emulation_time(self.config.island_id)
+        } else {
+            Duration::from_secs(0)
+        };
+        self.stopper.spent_budget(self.counter, emulation_time)
+    }
+
+    /// Concludes the island's run, as `finish` does, but leaves the other
+    /// islands to go on, unless this was the last of them.
+    fn spend_budget(&self, outcome: &str) {
+        self.wrap_up(outcome);
+        self.spent.store(true, atomic::Ordering::Relaxed);
+        crate::spend_island_budget(self.config.island_id);
+    }
+
+    /// Tests the champion and dumps the population one last time, and
+    /// stops the run.
+    fn finish(&self, outcome: &str) {
+        self.wrap_up(outcome);
+        crate::stop_everything(self.config.island_id, false);
+    }
+
+    /// Tests the champion, dumps the population one last time, and writes
+    /// the summary.
+    fn wrap_up(&self, outcome: &str) {
+        if let Some(ref best) = self.best {
+            let path = format!("{}/best.json.gz", self.config.data_directory());
+            log::info!("Dumping the best specimen of the run to {}", path);
+            dump(best, &path).expect("Failed to dump best specimen");
+        }
+        self.test_champion();
+        self.report();
+        self.dump_soup();
+        self.dump_population();
+        self.snapshot_pareto_front();
+        self.conclude(outcome);
+    }
+
+    /// Writes the summary, and announces the end of the island's run, the
+    /// first time it's called.
+    fn conclude(&self, outcome: &str) {
+        if self.finished.swap(true, atomic::Ordering::Relaxed) {
+            return;
+        }
+        self.write_summary(outcome);
+        let dir = self.config.data_directory();
+        artifacts::publish(
+            &self.config,
+            &["report.md", "report.html", "fitness.svg", "best.json.gz"]
+                .iter()
+                .map(|file| format!("{}/{}", dir, file))
+                .collect::<Vec<_>>(),
+        );
+        events::emit(
+            Event::RunFinished {
+                island: self.config.island_id,
+                epoch: self.get_local_epoch(),
+                counter: self.counter,
+                outcome: outcome.to_string(),
+                best_fitness: self
+                    .best
+                    .as_ref()
+                    .and_then(|b| b.scalar_fitness(&self.config.fitness.weighting)),
+            },
+            &self.config,
+        );
+    }
+
+    /// Writes the run's summary report to the data directory. See
+    /// `summary`.
+    fn write_summary(&self, outcome: &str) {
+        let champion = self.champion.as_ref().map(|champion| ChampionSummary {
+            name: champion.name().to_string(),
+            scalar_fitness: champion.scalar_fitness(&self.config.fitness.weighting),
+            scores: champion
+                .fitness()
+                .map(|f| {
+                    f.components()
+                        .into_iter()
+                        .map(|(key, score)| (key.to_string(), score))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            listing: champion
+                .annotated_layout(&self.config)
+                .or_else(|| champion.effective_code())
+                .unwrap_or_else(|| format!("{:#?}", champion)),
+        });
+        let mut statistics = vec![];
+        if let Some(score) = self.champion_validation {
+            statistics.push(("Champion's validation score".to_string(), score.to_string()));
+        }
+        if let Some(score) = self.champion_training {
+            statistics.push(("Champion's training score".to_string(), score.to_string()));
+        }
+        let summary = RunSummary {
+            population_name: self.config.observer.population_name.clone(),
+            island: self.config.island_id,
+            outcome: outcome.to_string(),
+            counter: self.counter,
+            epoch: self.get_local_epoch(),
+            elapsed: crate::uptime(),
+            statistics,
+            champion,
+            history: self.history.clone(),
+            config: summary::config_table(&self.config),
+        };
+        match summary.write(self.config.data_directory()) {
+            Ok(()) => log::info!(
+                "Wrote the summary of island {} to {}/report.html",
+                self.config.island_id,
+                self.config.data_directory()
+            ),
+            Err(e) => log::error!("Failed to write summary: {:?}", e),
+        }
+        summary::record(summary);
+    }
+
     pub fn get_local_epoch(&self) -> usize {
         self.local_epoch.load(atomic::Ordering::Relaxed)
     }
@@ -156,7 +1219,7 @@ impl<O: Genome + Phenome + 'static> Window<O> {
         // `pop_size` offspring have been spawned.
         // For now, only Island 0 can increment the epoch. We can weigh the
         // pros and cons of letting each island have its own epoch, later.
-        if self.counter > 0 && self.counter % self.window_size == 0 {
+        if self.counter > 0 && self.counter % self.epoch_length == 0 {
             let epoch = self.local_epoch.fetch_add(1, atomic::Ordering::Relaxed) + 1;
             log::info!(
                 "Epoch {} on island {} ({} specimens seen)",
@@ -176,31 +1239,260 @@ impl<O: Genome + Phenome + 'static> Window<O> {
     }
 
     fn insert(&mut self, thing: O) {
+        self.observe_bests(&thing);
         self.update_best(&thing);
         self.update_champion(&thing);
 
-        // insert the incoming thing into the observation window
+        // insert the incoming thing into the observation window, and its
+        // paths into the trie, in place of those of the specimen it replaces
+        for path in thing.execution_paths() {
+            self.path_trie.insert(&path);
+        }
         self.i = (self.i + 1) % self.window_size;
-        if self.frame.len() < self.window_size {
-            self.frame.push(thing)
+        let index = if self.frame.len() < self.window_size {
+            self.frame.push(thing);
+            self.frame.len() - 1
         } else {
+            for path in self.frame[self.i].execution_paths() {
+                self.path_trie.remove(&path);
+            }
             self.frame[self.i] = thing;
-        }
+            self.i
+        };
 
         // Perform various periodic tasks
         self.counter += 1;
 
         let epoch_has_incremented = self.maybe_increment_epoch();
 
-        if epoch_has_incremented {
+        let epoch = self.get_local_epoch();
+        for sink in self.sinks.iter_mut() {
+            sink.record_specimen(&self.frame[index], self.counter, epoch);
+        }
+
+        if self.metrics_ticker.is_due(self.counter, epoch) {
+            self.aggregate();
+        }
+
+        if self.last_dump_epoch != Some(epoch) && self.dump_ticker.is_due(self.counter, epoch) {
+            self.last_dump_epoch = Some(epoch);
             self.dump_soup();
             self.dump_population();
+        }
+
+        if epoch_has_incremented {
             self.report();
+            self.account_resources(epoch);
+            self.report_bests(epoch);
+            self.report_path_trie(epoch);
+            self.emit_epoch_events(epoch);
+            self.take_up_controls();
         }
 
         self.is_halting_condition_reached();
     }
 
+    fn observe_bests(&mut self, specimen: &O) {
+        let mut scores = specimen
+            .fitness()
+            .map(|f| f.components())
+            .unwrap_or_default();
+        if let Some(scalar) = specimen.scalar_fitness(&self.config.fitness.weighting) {
+            scores.push((bests::SCALAR, scalar));
+        }
+        let epoch = self.get_local_epoch();
+        self.bests
+            .observe(scores, specimen.name(), epoch, self.counter);
+    }
+
+    /// Writes out the bests of the epoch that just ended, and of the run so
+    /// far.
+    fn report_bests(&mut self, epoch: usize) {
+        for record in self.bests.end_epoch(self.config.island_id, epoch - 1) {
+            append_record(&record, "bests", &self.config);
+        }
+    }
+
+    /// Takes up any changes to the run-time controls, at an epoch boundary.
+    /// The worlds apply them to their own configs; the observer need only
+    /// heed a change in `report_every`.
+    fn take_up_controls(&mut self) {
+        control::at_epoch_boundary(&self.config);
+        let controls = control::current();
+        if controls == self.controls {
+            return;
+        }
+        log::info!("Taking up the run-time controls: {:?}", controls);
+        if controls.report_every != self.controls.report_every
+            && self.config.observer.metrics_cadence.is_none()
+        {
+            if let Some(n) = controls.report_every {
+                self.metrics_ticker = Ticker::new(Cadence::Evaluations(n), Duration::default());
+            }
+        }
+        self.controls = controls;
+    }
+
+    /// Writes out the shape of the trie of the window's execution paths,
+    /// every `observer.path_trie_every` epochs.
+    fn report_path_trie(&mut self, epoch: usize) {
+        let every = self.config.observer.path_trie_every;
+        if every == 0 || (epoch - 1) % every != 0 {
+            return;
+        }
+        if self.path_trie.paths() == 0 {
+            return;
+        }
+        let record = PathTrieRecord {
+            island: self.config.island_id,
+            epoch: epoch - 1,
+            counter: self.counter,
+            stats: self.path_trie.report(COMMON_PREFIXES),
+        };
+        append_record(&record, "path_trie", &self.config);
+    }
+
+    /// Logs the resources used over the epoch that just ended.
+    fn account_resources(&mut self, epoch: usize) {
+        let (started, start_counter) = self.epoch_started;
+        let record = ResourceRecord::new(
+            self.config.island_id,
+            epoch - 1,
+            self.counter,
+            started.elapsed(),
+            self.counter - start_counter,
+            resources::take_usage(self.config.island_id),
+        );
+        log::info!("Island {} resources: {:?}", self.config.island_id, record);
+        append_record(&record, "resources", &self.config);
+        self.epoch_started = (Instant::now(), self.counter);
+    }
+
+    fn emit_epoch_events(&self, epoch: usize) {
+        events::emit(
+            Event::EpochEnd {
+                island: self.config.island_id,
+                epoch: epoch - 1,
+                counter: self.counter,
+                best_fitness: self
+                    .best
+                    .as_ref()
+                    .and_then(|b| b.scalar_fitness(&self.config.fitness.weighting)),
+            },
+            &self.config,
+        );
+        let every = self.config.hooks.stagnation_epochs;
+        let stagnant_epochs = epoch - self.champion_epoch;
+        if every > 0 && stagnant_epochs > 0 && stagnant_epochs % every == 0 {
+            events::emit(
+                Event::Stagnation {
+                    island: self.config.island_id,
+                    epoch,
+                    stagnant_epochs,
+                },
+                &self.config,
+            );
+        }
+    }
+
+    fn stats(&self) -> WindowStats {
+        let mut stats = WindowStats::of(
+            &self.frame,
+            &self.path_trie,
+            &self.config,
+            self.counter,
+            self.get_local_epoch(),
+        );
+        for statistic in self.statistics.iter() {
+            stats
+                .custom
+                .extend(statistic.compute(&self.frame, &self.config));
+        }
+        stats
+    }
+
+    /// Sends the window's statistics to the sinks.
+    fn aggregate(&mut self) {
+        let stats = self.stats();
+        self.history.push(HistoryPoint::of(&stats));
+        let operators = OperatorStats::of(&self.frame, &stats);
+        for sink in self.sinks.iter_mut() {
+            sink.record_stats(&stats);
+            sink.record_operators(&operators);
+        }
+        self.snapshot_pareto_front();
+        if !crate::keep_going() {
+            return;
+        }
+        let stagnant_epochs = stats.epoch - self.best_epoch;
+        let coverage = self.max_coverage();
+        if let Some(reason) = self.stopper.check(&stats, stagnant_epochs, coverage) {
+            log::info!("Island {} is stopping: {}", self.config.island_id, reason);
+            self.finish(&reason);
+        }
+    }
+
+    /// The highest `code_coverage` score in the window.
+    fn max_coverage(&self) -> Option<f64> {
+        self.frame
+            .iter()
+            .filter_map(|s| s.fitness())
+            .filter_map(|f| {
+                f.components()
+                    .into_iter()
+                    .find(|(key, _)| *key == "code_coverage")
+                    .map(|(_, score)| score)
+            })
+            .fold(None, |max, x| Some(max.map_or(x, |m: f64| m.max(x))))
+    }
+
+    /// Writes out the non-dominated specimens in the window, in each of the
+    /// formats listed in `pareto_fronts`. The snapshots are taken by
+    /// counter, which the records' `counter` fields give.
+    fn snapshot_pareto_front(&self) {
+        let formats = &self.config.observer.pareto_fronts;
+        if formats.is_empty() {
+            return;
+        }
+        let epoch = self.get_local_epoch();
+        let mut seen = HashSet::new();
+        let records = self
+            .frame
+            .iter()
+            .filter(|s| seen.insert(s.name()))
+            .map(|s| SpecimenRecord::of(s, self.counter, epoch, &self.config))
+            .filter(|r| !r.scores.is_empty())
+            .collect::<Vec<_>>();
+        let front = pareto_front(records.iter());
+        let dir = self.config.data_directory();
+        for format in formats {
+            match format {
+                FrontFormat::Csv => {
+                    let filename = format!("{}/pareto_fronts.csv", dir);
+                    for record in front.iter() {
+                        append_record_to(*record, &filename);
+                    }
+                }
+                FrontFormat::Json => {
+                    let snapshot = FrontSnapshot {
+                        island: self.config.island_id,
+                        counter: self.counter,
+                        epoch,
+                        front: &front,
+                    };
+                    let path = format!("{}/pareto/front_{}.json", dir, self.counter);
+                    let res = fs::create_dir_all(format!("{}/pareto", dir))
+                        .map_err(Error::from)
+                        .and_then(|_| Ok(serde_json::to_vec(&snapshot)?))
+                        .and_then(|bytes| Ok(fs::write(&path, bytes)?));
+                    if let Err(e) = res {
+                        log::error!("Failed to write Pareto front to {}: {:?}", path, e);
+                    }
+                }
+            }
+        }
+    }
+
     fn update_best(&mut self, specimen: &O) {
         let mut updated = false;
         if let Some(specimen_fitness) = specimen.scalar_fitness(&self.config.fitness.weighting) {
@@ -223,6 +1515,7 @@ impl<O: Genome + Phenome + 'static> Window<O> {
         }
 
         if updated {
+            self.best_epoch = self.get_local_epoch();
             log::info!(
                 "Island {}: new best:\n{:#?}",
                 self.config.island_id,
@@ -253,6 +1546,7 @@ impl<O: Genome + Phenome + 'static> Window<O> {
         }
 
         if updated {
+            let epoch = self.get_local_epoch();
             if let Some(ref mut champion) = self.champion {
                 champion.generate_description();
                 log::info!(
@@ -268,6 +1562,49 @@ impl<O: Genome + Phenome + 'static> Window<O> {
                 );
                 log::info!("Dumping new champion to {}", path);
                 dump(champion, &path).expect("Failed to dump champion");
+                if let Some(layout) = champion.annotated_layout(&self.config) {
+                    let layout_path = format!(
+                        "{}/champions/champion_{}.layout.txt",
+                        self.config.data_directory(),
+                        self.counter,
+                    );
+                    fs::write(&layout_path, layout).expect("Failed to dump champion layout");
+                }
+                if let Some(path_export) = champion.path_export() {
+                    let export_path = format!(
+                        "{}/champions/champion_{}.path.json",
+                        self.config.data_directory(),
+                        self.counter,
+                    );
+                    fs::write(&export_path, path_export).expect("Failed to export champion path");
+                }
+                if let Some(code) = champion.effective_code() {
+                    let code_path = format!(
+                        "{}/champions/champion_{}.effective.txt",
+                        self.config.data_directory(),
+                        self.counter,
+                    );
+                    fs::write(&code_path, code).expect("Failed to dump champion effective code");
+                }
+                if let Some(code) = champion.transpiled_code(&self.config) {
+                    let code_path = format!(
+                        "{}/champions/champion_{}.rs",
+                        self.config.data_directory(),
+                        self.counter,
+                    );
+                    fs::write(&code_path, code).expect("Failed to dump champion transpiled code");
+                }
+                let bundle_path = format!(
+                    "{}/champions/champion_{}.bundle.json.gz",
+                    self.config.data_directory(),
+                    self.counter,
+                );
+                let bundle = ChampionBundle::new(champion, &self.config, self.counter, epoch);
+                dump(&bundle, &bundle_path).expect("Failed to dump champion bundle");
+                if self.config.observer.artifact_sink.is_some() {
+                    let config = self.config.clone();
+                    spawn(move || artifacts::publish(&config, &[bundle_path]));
+                }
                 let latest = format!(
                     "{}/champions/latest_champion.json.gz",
                     self.config.data_directory()
@@ -277,36 +1614,98 @@ impl<O: Genome + Phenome + 'static> Window<O> {
                     fs::remove_file(latest).expect("failed to remove old symlink");
                 }
                 std::os::unix::fs::symlink(path, latest).expect("Failed to make symlink");
+                self.champion_epoch = epoch;
+                events::emit(
+                    Event::NewChampion {
+                        island: self.config.island_id,
+                        epoch,
+                        name: champion.name().to_string(),
+                        fitness: champion.scalar_fitness(&self.config.fitness.priority()),
+                    },
+                    &self.config,
+                );
+            }
+            let stats = self.stats();
+            if let Some(ref champion) = self.champion {
+                for sink in self.sinks.iter_mut() {
+                    sink.record_champion(champion, &stats);
+                }
             }
+            self.validate_champion();
         }
     }
 
-    fn report(&self) {
-        (self.report_fn)(&self, self.counter, &self.config);
+    fn holdout_score(&self, split: Split) -> Option<f64> {
+        match (self.holdout_fn.as_ref(), self.champion.as_ref()) {
+            (Some(holdout_fn), Some(champion)) => holdout_fn(champion, split, &self.config),
+            _ => None,
+        }
     }
 
-    pub fn log_record<S: LogRecord + Debug>(&self, record: S, name: &str) {
-        log::debug!(
-            "Island {}, logging to {}: {:#?}",
+    fn log_holdout_score(&self, split: Split, score: f64) {
+        let training = self
+            .champion
+            .as_ref()
+            .and_then(|c| c.scalar_fitness(self.config.fitness.priority()))
+            .unwrap_or(std::f64::MAX);
+        log::info!(
+            "Island {}: champion's {:?} score: {} (training fitness: {})",
             self.config.island_id,
-            name,
-            record
+            split,
+            score,
+            training
         );
-        let filename = get_log_filename(name, &self.config);
-        // check to see if file exists yet
-        let msg = if !Path::exists((&filename).as_ref()) {
-            log::debug!("Creating header for {}", filename);
-            format!("{}\n{}\n", record.header(), record.row())
-        } else {
-            format!("{}\n", record.row())
+        let record = HoldoutRecord {
+            counter: self.counter,
+            epoch: self.get_local_epoch(),
+            training,
+            score,
         };
-        let fd = OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(&filename)
-            .expect("Failed to open log file");
-        let mut w = BufWriter::new(fd);
-        write!(w, "{}", msg).expect("Failed to log row");
+        match split {
+            Split::Train => self.log_record(record, "training"),
+            Split::Validation => self.log_record(record, "validation"),
+            Split::Test => self.log_record(record, "test"),
+        }
+    }
+
+    /// Scores each new champion on the validation set, and on the full
+    /// training set when its fitness comes from a mini-batch.
+    fn validate_champion(&mut self) {
+        if self.config.provider.batch_size.is_some() {
+            self.champion_training = self.holdout_score(Split::Train);
+            if let Some(score) = self.champion_training {
+                self.log_holdout_score(Split::Train, score);
+            }
+        }
+        self.champion_validation = self.holdout_score(Split::Validation);
+        if let Some(score) = self.champion_validation {
+            self.log_holdout_score(Split::Validation, score);
+        }
+    }
+
+    /// Scores the champion on the test set, once evolution is over.
+    fn test_champion(&self) {
+        if let Some(score) = self.holdout_score(Split::Test) {
+            self.log_holdout_score(Split::Test, score);
+        }
+    }
+
+    fn report(&self) {
+        (self.report_fn)(&self, self.counter, &self.config);
+        if self.config.num_islands > 1 {
+            let summary = IslandSummary::of(
+                &self.frame,
+                self.best.as_ref(),
+                &self.config,
+                self.counter,
+                self.get_local_epoch(),
+            );
+            aggregator::post(summary, &self.config);
+        }
+    }
+
+    pub fn log_record<S: LogRecord + Debug>(&self, record: S, name: &str) {
+        append_record(&record, name, &self.config);
 
         // self.stat_writers[name]
         //     .lock()
@@ -326,18 +1725,54 @@ impl<O: Genome + Phenome + 'static> Window<O> {
             log::debug!("Not dumping population");
             return;
         }
+        let compression = self.config.observer.population_compression;
         let path = format!(
-            "{}/population/population_{}.json.gz",
+            "{}/population/population_{}.{}",
             self.config.data_directory(),
             self.get_local_epoch(),
+            compression.extension(),
         );
         if self.config.observer.dump_population == 1.0 {
-            dump(&self.frame, &path).expect("Failed to dump population");
+            dump_compressed(&self.frame, &path, compression).expect("Failed to dump population");
         } else {
             let mut rng = thread_rng();
             let n = (self.frame.len() as f64 * self.config.observer.dump_population).ceil() as usize; 
             let sample = self.frame.iter().choose_multiple(&mut rng, n);
-            dump(&sample, &path).expect("Failed to dump population");
+            dump_compressed(&sample, &path, compression).expect("Failed to dump population");
+        }
+        if let Some(policy) = self.config.observer.population_retention {
+            self.prune_population_dumps(policy);
+        }
+    }
+
+    /// Deletes the population dumps that the retention policy doesn't keep.
+    fn prune_population_dumps(&self, policy: RetentionPolicy) {
+        let dir = format!("{}/population", self.config.data_directory());
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::error!("Failed to read {}: {:?}", dir, e);
+                return;
+            }
+        };
+        let latest_epoch = self.get_local_epoch();
+        for entry in entries.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with("population_") {
+                continue;
+            }
+            let epoch = name["population_".len()..]
+                .split('.')
+                .next()
+                .and_then(|n| n.parse::<usize>().ok());
+            if let Some(epoch) = epoch {
+                if !policy.keeps(epoch, latest_epoch) {
+                    log::debug!("Removing population dump {}", name);
+                    if let Err(e) = fs::remove_file(entry.path()) {
+                        log::error!("Failed to remove {}: {:?}", name, e);
+                    }
+                }
+            }
         }
     }
 
@@ -359,23 +1794,22 @@ impl<O: Genome + Phenome + 'static> Window<O> {
             log::debug!("Not dumping soup");
             return;
         }
-        let mut soup = self.soup();
+        let soup = self.soup();
         log::debug!(
             "Island {} soup size: {} alleles",
             self.config.island_id,
             soup.len()
         );
-        let path = format!(
-            "{}/soup/soup_at_epoch_{}.json",
-            self.config.data_directory(),
-            self.get_local_epoch(),
-        );
-        if let Ok(mut soup_file) =
-            fs::File::create(&path).map_err(|e| log::error!("Failed to create soup file: {:?}", e))
-        {
-            let soup_vec = soup.drain().collect::<Vec<_>>();
-            serde_json::to_writer(&mut soup_file, &soup_vec).expect("Failed to dump soup!");
-            log::debug!("Soup dumped to {}", path);
+        let epoch = self.get_local_epoch();
+        let mut store = self.soup_store.lock().expect("poisoned");
+        match store.record(epoch, &soup) {
+            Ok(record) => log::debug!(
+                "Soup dumped for epoch {}: {} changed, {} removed",
+                epoch,
+                record.changed.len(),
+                record.removed.len()
+            ),
+            Err(e) => log::error!("Failed to dump soup: {:?}", e),
         }
     }
 }
@@ -383,22 +1817,101 @@ impl<O: Genome + Phenome + 'static> Window<O> {
 impl<O: 'static + Phenome + Genome> Observer<O> {
     /// The observe method should take a clone of the observable
     /// and store in something like a sliding observation window.
+    ///
+    /// If `observer.queue_capacity` specimens are already waiting to be
+    /// taken into the window, this blocks until there's room for another.
     pub fn observe(&self, ob: O) {
-        self.tx.send(ob).expect("tx failure");
+        let depth = self.pending.fetch_add(1, atomic::Ordering::Relaxed) + 1;
+        let blocked = match self.tx.try_send(ob) {
+            Ok(()) => Duration::default(),
+            Err(TrySendError::Full(ob)) => {
+                let started = Instant::now();
+                self.tx.send(ob).expect("tx failure");
+                started.elapsed()
+            }
+            Err(TrySendError::Disconnected(_)) => panic!("tx failure"),
+        };
+        resources::record_observation(self.island, depth, blocked);
     }
 
     pub fn spawn(config: &Config, report_fn: ReportFn<O>) -> Observer<O> {
-        let (tx, rx): (Sender<O>, Receiver<O>) = channel();
+        Self::spawn_window(config, report_fn, None)
+    }
+
+    /// Spawns an observer that scores each new champion on the validation
+    /// set, and the final champion on the test set.
+    pub fn spawn_with_holdout(
+        config: &Config,
+        report_fn: ReportFn<O>,
+        holdout_fn: HoldoutFn<O>,
+    ) -> Observer<O> {
+        Self::spawn_window(config, report_fn, Some(holdout_fn))
+    }
+
+    fn spawn_window(
+        config: &Config,
+        report_fn: ReportFn<O>,
+        holdout_fn: Option<HoldoutFn<O>>,
+    ) -> Observer<O> {
+        let (tx, rx): (SyncSender<O>, Receiver<O>) = sync_channel(config.observer.queue_capacity);
+        let pending = Arc::new(AtomicUsize::new(0));
+        let window_pending = pending.clone();
+        let island = config.island_id;
 
         let config = Arc::new(config.clone());
+        let guard = ObserverGuard::acquire();
+        let spent = Arc::new(AtomicBool::new(false));
+        let window_spent = spent.clone();
+        let epoch = Arc::new(AtomicUsize::new(config.observer.resumed_epoch));
+        let window_epoch = epoch.clone();
         let handle: JoinHandle<()> = spawn(move || {
-            let mut window: Window<O> = Window::new(report_fn, config.clone());
+            let _guard = guard;
+            logger::set_island(&config);
+            // the report function and the sinks may look into the island's
+            // memory image, in ROPER runs
+            loader::set_target(config.roper.target);
+            let sinks = get_sinks(&config);
+            let mut window: Window<O> = Window::new(
+                report_fn,
+                holdout_fn,
+                sinks,
+                config.clone(),
+                window_spent,
+                window_epoch,
+            );
             for observable in rx {
+                window_pending.fetch_sub(1, atomic::Ordering::Relaxed);
                 window.insert(observable);
             }
+            // the world may have stopped before the window saw the timeout,
+            // or the signal
+            window.wind_down_if_stopped();
+            // so that the other islands' global statistics don't wait on it
+            if config.num_islands > 1 {
+                aggregator::retire(&config);
+            }
         });
 
-        Observer { handle, tx }
+        Observer {
+            handle,
+            tx,
+            pending,
+            island,
+            spent,
+            epoch,
+        }
+    }
+
+    /// Whether the island has spent its budget, and should stop evolving.
+    /// See `BudgetPolicy`.
+    pub fn has_spent_budget(&self) -> bool {
+        self.spent.load(atomic::Ordering::Relaxed)
+    }
+
+    /// The island's epoch, as the window has counted it. This lags behind
+    /// the world by the specimens sent, but not yet observed.
+    pub fn epoch(&self) -> usize {
+        self.epoch.load(atomic::Ordering::Relaxed)
     }
 
     // pub fn stop_evolution(&mut self) {
@@ -406,7 +1919,209 @@ impl<O: 'static + Phenome + Genome> Observer<O> {
     // }
 }
 
+/// Appends the record to its statistics file, starting the file with a
+/// header if it's new.
+fn append_record<S: LogRecord + Debug>(record: &S, name: &str, config: &Config) {
+    log::debug!(
+        "Island {}, logging to {}: {:#?}",
+        config.island_id,
+        name,
+        record
+    );
+    append_record_to(record, &get_log_filename(name, config))
+}
+
+/// Appends the record to the named file, starting the file with a header if
+/// it's new.
+pub fn append_record_to<S: LogRecord>(record: &S, filename: &str) {
+    // check to see if file exists yet
+    let msg = if !Path::exists((&filename).as_ref()) {
+        log::debug!("Creating header for {}", filename);
+        format!("{}\n{}\n", record.header(), record.row())
+    } else {
+        format!("{}\n", record.row())
+    };
+    let fd = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&filename)
+        .expect("Failed to open log file");
+    let mut w = BufWriter::new(fd);
+    write!(w, "{}", msg).expect("Failed to log row");
+}
+
 pub trait LogRecord {
     fn header(&self) -> String;
     fn row(&self) -> String;
 }
+
+#[derive(Debug)]
+struct HoldoutRecord {
+    counter: usize,
+    epoch: usize,
+    training: f64,
+    score: f64,
+}
+
+impl LogRecord for HoldoutRecord {
+    fn header(&self) -> String {
+        "counter,epoch,training,score".to_string()
+    }
+
+    fn row(&self) -> String {
+        format!(
+            "{},{},{},{}",
+            self.counter, self.epoch, self.training, self.score
+        )
+    }
+}
+
+/// The number of the commonest path prefixes reported.
+const COMMON_PREFIXES: usize = 5;
+
+#[derive(Debug)]
+struct PathTrieRecord {
+    island: usize,
+    epoch: usize,
+    counter: usize,
+    stats: PathTrieStats,
+}
+
+impl LogRecord for PathTrieRecord {
+    fn header(&self) -> String {
+        "island,epoch,counter,paths,node_count,novel_prefixes,mean_depth,branching_factor,\
+         common_prefixes"
+            .to_string()
+    }
+
+    /// The common prefixes are written as their steps, in hex, separated by
+    /// colons, each followed by its count, and separated by spaces.
+    fn row(&self) -> String {
+        let prefixes = self
+            .stats
+            .common_prefixes
+            .iter()
+            .map(|(prefix, n)| {
+                let steps = prefix
+                    .iter()
+                    .map(|s| format!("{:x}", s))
+                    .collect::<Vec<_>>()
+                    .join(":");
+                format!("{}={}", steps, n)
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!(
+            "{},{},{},{},{},{},{},{},{}",
+            self.island,
+            self.epoch,
+            self.counter,
+            self.stats.paths,
+            self.stats.node_count,
+            self.stats.novel_prefixes,
+            self.stats.mean_depth,
+            self.stats.branching_factor,
+            prefixes
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_window_stats_record() {
+        let mut mean_scores = BTreeMap::new();
+        mean_scores.insert("error_rate".to_string(), 0.25);
+        mean_scores.insert("genetic_freq".to_string(), 2.0);
+        let mut best_scores = BTreeMap::new();
+        best_scores.insert("error_rate".to_string(), 0.125);
+        best_scores.insert("genetic_freq".to_string(), 1.0);
+        let mut score_percentiles = BTreeMap::new();
+        score_percentiles.insert(
+            "error_rate".to_string(),
+            Percentiles::of(&[0.125, 0.25, 0.375]),
+        );
+        let stats = WindowStats {
+            island: 1,
+            counter: 200,
+            epoch: 2,
+            size: 100,
+            mean_length: 12.5,
+            min_fitness: 0.0,
+            mean_fitness: 0.5,
+            max_fitness: 1.0,
+            uniqueness: 0.75,
+            genotypic_entropy: 3.5,
+            mean_genome_distance: 0.5,
+            path_diversity: std::f64::NAN,
+            mean_scores,
+            best_scores,
+            score_percentiles,
+            histograms: BTreeMap::new(),
+            custom: BTreeMap::new(),
+        };
+        assert_eq!(
+            stats.header(),
+            "island,counter,epoch,size,mean_length,min_fitness,mean_fitness,max_fitness,\
+             uniqueness,genotypic_entropy,mean_genome_distance,path_diversity,\
+             mean_error_rate,mean_genetic_freq,best_error_rate,best_genetic_freq,\
+             p10_error_rate,p50_error_rate,p90_error_rate"
+        );
+        assert_eq!(
+            stats.row(),
+            "1,200,2,100,12.5,0,0.5,1,0.75,3.5,0.5,NaN,0.25,2,0.125,1,0.15,0.25,0.35"
+        );
+    }
+
+    #[test]
+    fn test_ticker() {
+        let mut ticker = Ticker::new(Cadence::Evaluations(3), Duration::default());
+        let due = (1..=9).filter(|&i| ticker.is_due(i, 0)).collect::<Vec<_>>();
+        assert_eq!(due, vec![3, 6, 9]);
+
+        let mut ticker = Ticker::new(Cadence::Epochs(2), Duration::default());
+        let due = (0..=6).filter(|&e| ticker.is_due(0, e)).collect::<Vec<_>>();
+        assert_eq!(due, vec![2, 4, 6]);
+
+        let hourly = Cadence::WallClock("1h".to_string());
+        let mut ticker = Ticker::new(hourly.clone(), hourly.period().unwrap());
+        assert!(!ticker.is_due(1, 1));
+        let mut ticker = Ticker::new(Cadence::WallClock("0s".to_string()), Duration::default());
+        assert!(ticker.is_due(1, 1));
+        assert!(Cadence::WallClock("every so often".to_string())
+            .period()
+            .is_err());
+    }
+
+    #[test]
+    fn test_check_sinks() {
+        let sinks = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(check_sinks(&sinks(&["csv", "log", "journal"])).is_ok());
+        assert!(check_sinks(&sinks(&["csv", "cvs"])).is_err());
+        assert_eq!(
+            check_sinks(&sinks(&["parquet"])).is_ok(),
+            cfg!(feature = "parquet")
+        );
+    }
+
+    #[test]
+    fn test_jsonl_record() {
+        let mut counts = BTreeMap::new();
+        counts.insert("clone".to_string(), 3);
+        counts.insert("crossover".to_string(), 7);
+        let stats = OperatorStats {
+            island: 0,
+            counter: 100,
+            epoch: 1,
+            counts,
+        };
+        let line = serde_json::to_string(&JsonlRecord::new("operators", &stats)).unwrap();
+        assert_eq!(
+            line,
+            "{\"schema_version\":1,\"record\":\"operators\",\"island\":0,\
+             \"counter\":100,\"epoch\":1,\"counts\":{\"clone\":3,\"crossover\":7}}"
+        );
+    }
+}
@@ -0,0 +1,296 @@
+//! Sources of the cases that a population is scored on. A provider may hand
+//! out the same cases every epoch, as with a static dataset, or generate new
+//! ones as the run goes on.
+use std::cmp::Reverse;
+use std::sync::Arc;
+
+use rand::seq::index;
+use rand::Rng;
+
+use crate::configure::{ClassificationProblem, Config};
+use crate::examples::boolean;
+use crate::util::random::{hash_seed_rng, Prng};
+
+pub trait ProblemProvider<P>: Send {
+    /// The cases to score creatures on in the given epoch. Repeated calls
+    /// with the same epoch return the same cases.
+    fn cases(&mut self, epoch: usize) -> Arc<Vec<P>>;
+
+    /// Reports which of the current cases a creature failed, in the order
+    /// the cases were handed out.
+    fn record_failures(&mut self, _failures: &[bool]) {}
+}
+
+/// The same cases, every epoch.
+pub struct StaticProvider<P> {
+    cases: Arc<Vec<P>>,
+}
+
+impl<P> StaticProvider<P> {
+    pub fn new(cases: Vec<P>) -> Self {
+        Self {
+            cases: Arc::new(cases),
+        }
+    }
+}
+
+impl<P: Send + Sync> ProblemProvider<P> for StaticProvider<P> {
+    fn cases(&mut self, _epoch: usize) -> Arc<Vec<P>> {
+        self.cases.clone()
+    }
+}
+
+/// A random sample of a fixed set of cases, drawn anew every
+/// `resample_every` epochs. The sampled cases keep their order in the set.
+pub struct MiniBatchProvider<P> {
+    all: Vec<P>,
+    batch_size: usize,
+    resample_every: usize,
+    seed: u64,
+    batch: Option<usize>,
+    cases: Arc<Vec<P>>,
+}
+
+impl<P> MiniBatchProvider<P> {
+    pub fn new(all: Vec<P>, batch_size: usize, resample_every: usize, seed: u64) -> Self {
+        Self {
+            batch_size: batch_size.min(all.len()),
+            all,
+            resample_every: resample_every.max(1),
+            seed,
+            batch: None,
+            cases: Arc::new(Vec::new()),
+        }
+    }
+}
+
+impl<P: Clone + Send + Sync> ProblemProvider<P> for MiniBatchProvider<P> {
+    fn cases(&mut self, epoch: usize) -> Arc<Vec<P>> {
+        let batch = epoch / self.resample_every;
+        if self.batch != Some(batch) {
+            let mut rng = hash_seed_rng(&(self.seed, batch));
+            let mut indices = index::sample(&mut rng, self.all.len(), self.batch_size).into_vec();
+            indices.sort();
+            self.cases = Arc::new(indices.into_iter().map(|i| self.all[i].clone()).collect());
+            self.batch = Some(batch);
+        }
+        self.cases.clone()
+    }
+}
+
+/// Takes an rng and a serial number, unique across epochs, and makes a case.
+pub type Generator<P> = Box<dyn FnMut(&mut Prng, u64) -> P + Send>;
+
+/// Fresh cases every epoch. The cases of each epoch are seeded by the
+/// epoch and the random seed, so a run can be replayed.
+pub struct GeneratedProvider<P> {
+    generator: Generator<P>,
+    cases_per_epoch: usize,
+    seed: u64,
+    epoch: Option<usize>,
+    cases: Arc<Vec<P>>,
+}
+
+impl<P> GeneratedProvider<P> {
+    pub fn new(generator: Generator<P>, cases_per_epoch: usize, seed: u64) -> Self {
+        Self {
+            generator,
+            cases_per_epoch,
+            seed,
+            epoch: None,
+            cases: Arc::new(Vec::new()),
+        }
+    }
+}
+
+impl<P: Send + Sync> ProblemProvider<P> for GeneratedProvider<P> {
+    fn cases(&mut self, epoch: usize) -> Arc<Vec<P>> {
+        if self.epoch != Some(epoch) {
+            let mut rng = hash_seed_rng(&(self.seed, epoch));
+            let first = (epoch * self.cases_per_epoch) as u64;
+            let generator = &mut self.generator;
+            self.cases = Arc::new(
+                (0..self.cases_per_epoch as u64)
+                    .map(|i| generator(&mut rng, first + i))
+                    .collect(),
+            );
+            self.epoch = Some(epoch);
+        }
+        self.cases.clone()
+    }
+}
+
+/// Carries the cases that were failed most often over into the next epoch,
+/// and makes up the rest with freshly generated ones, so that the cases
+/// track the weaknesses of the population.
+pub struct AdversarialProvider<P> {
+    generated: GeneratedProvider<P>,
+    keep_fraction: f64,
+    epoch: Option<usize>,
+    cases: Arc<Vec<P>>,
+    failures: Vec<usize>,
+}
+
+impl<P> AdversarialProvider<P> {
+    pub fn new(generated: GeneratedProvider<P>, keep_fraction: f64) -> Self {
+        Self {
+            generated,
+            keep_fraction,
+            epoch: None,
+            cases: Arc::new(Vec::new()),
+            failures: Vec::new(),
+        }
+    }
+}
+
+impl<P: Clone + Send + Sync> ProblemProvider<P> for AdversarialProvider<P> {
+    fn cases(&mut self, epoch: usize) -> Arc<Vec<P>> {
+        if self.epoch != Some(epoch) {
+            let n = self.generated.cases_per_epoch;
+            let num_kept = (n as f64 * self.keep_fraction).round() as usize;
+            let failures = &self.failures;
+            let mut ranked = (0..self.cases.len())
+                .filter(|i| failures[*i] > 0)
+                .collect::<Vec<usize>>();
+            ranked.sort_by_key(|i| Reverse(failures[*i]));
+            ranked.truncate(num_kept);
+            // Keep the survivors in their old order, ahead of the new cases,
+            // so that the serial numbers stay in ascending order.
+            ranked.sort();
+            let mut cases = ranked
+                .into_iter()
+                .map(|i| self.cases[i].clone())
+                .collect::<Vec<P>>();
+            let fresh = self.generated.cases(epoch);
+            let num_fresh = n - cases.len();
+            cases.extend(fresh.iter().take(num_fresh).cloned());
+            self.failures = vec![0; cases.len()];
+            self.cases = Arc::new(cases);
+            self.epoch = Some(epoch);
+        }
+        self.cases.clone()
+    }
+
+    fn record_failures(&mut self, failures: &[bool]) {
+        for (count, failed) in self.failures.iter_mut().zip(failures.iter()) {
+            if *failed {
+                *count += 1;
+            }
+        }
+    }
+}
+
+/// Draws random rows of the truth table of the configured boolean problem,
+/// as 0s and 1s, tagged by their serial numbers.
+fn boolean_generator(config: &Config) -> Generator<ClassificationProblem> {
+    let problem = config.boolean.problem;
+    let size = config.boolean.size;
+    let num_inputs = boolean::num_inputs(problem, size);
+    Box::new(move |rng: &mut Prng, tag: u64| {
+        let input = (0..num_inputs)
+            .map(|_| rng.gen::<bool>())
+            .collect::<Vec<bool>>();
+        let output = boolean::target(problem, size, &input);
+        ClassificationProblem {
+            input: input.into_iter().map(|b| b as i32).collect(),
+            output: vec![output as i32],
+            tag,
+        }
+    })
+}
+
+pub fn get_classification_provider(
+    config: &Config,
+) -> Box<dyn ProblemProvider<ClassificationProblem>> {
+    let provider = &config.provider;
+    match provider.name.as_str() {
+        "" | "dataset" => {
+            let problems = config.problems.clone().expect("No problems!");
+            match provider.batch_size {
+                Some(batch_size) => Box::new(MiniBatchProvider::new(
+                    problems,
+                    batch_size,
+                    provider.resample_every,
+                    config.random_seed,
+                )),
+                None => Box::new(StaticProvider::new(problems)),
+            }
+        }
+        "boolean" => Box::new(GeneratedProvider::new(
+            boolean_generator(config),
+            provider.cases_per_epoch,
+            config.random_seed,
+        )),
+        "adversarial" => Box::new(AdversarialProvider::new(
+            GeneratedProvider::new(
+                boolean_generator(config),
+                provider.cases_per_epoch,
+                config.random_seed,
+            ),
+            provider.keep_fraction,
+        )),
+        s => unreachable!("The provider {} was checked when the config was loaded", s),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn counter() -> GeneratedProvider<u64> {
+        GeneratedProvider::new(
+            Box::new(|rng: &mut Prng, serial| serial * 1000 + rng.gen_range(0, 1000)),
+            4,
+            7,
+        )
+    }
+
+    #[test]
+    fn test_generated_provider() {
+        let mut provider = counter();
+        let first = provider.cases(0);
+        assert_eq!(first.len(), 4);
+        assert_eq!(provider.cases(0), first);
+        let second = provider.cases(1);
+        assert_ne!(second, first);
+        assert!(second.iter().all(|c| c / 1000 >= 4));
+        // the same seed and epoch give the same cases
+        assert_eq!(counter().cases(1), second);
+
+        let mut provider = StaticProvider::new(vec![1, 2, 3]);
+        assert_eq!(provider.cases(0), provider.cases(9));
+    }
+
+    #[test]
+    fn test_mini_batch_provider() {
+        let mut provider = MiniBatchProvider::new((0..100).collect::<Vec<u32>>(), 10, 2, 7);
+        let first = provider.cases(0);
+        assert_eq!(first.len(), 10);
+        assert!(first.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(provider.cases(1), first);
+        assert_ne!(provider.cases(2), first);
+        // a batch larger than the set is the whole set
+        let mut provider = MiniBatchProvider::new(vec![3, 1, 2], 10, 1, 7);
+        assert_eq!(*provider.cases(5), vec![3, 1, 2]);
+    }
+
+    #[test]
+    fn test_adversarial_provider() {
+        let mut provider = AdversarialProvider::new(counter(), 0.5);
+        let first = provider.cases(0);
+        provider.record_failures(&[false, true, true, false]);
+        provider.record_failures(&[false, false, true, true]);
+        provider.record_failures(&[false, false, true, false]);
+        let second = provider.cases(1);
+        assert_eq!(second.len(), 4);
+        // the two hardest cases survive, in their old order
+        assert_eq!(&second[..2], &[first[1], first[2]]);
+        assert!(second.windows(2).all(|w| w[0] < w[1]));
+
+        // cases that nobody failed aren't carried over
+        provider.record_failures(&[true, false, false, false]);
+        let third = provider.cases(2);
+        assert_eq!(third[0], second[0]);
+        assert!(third[1..].iter().all(|c| c / 1000 >= 8));
+    }
+}
@@ -0,0 +1,217 @@
+//! Accounting of the resources each island uses. The evaluators record
+//! the time spent in the emulator, and how often a specimen's profile is
+//! reused rather than recomputed, the island records how deep its
+//! observer's queue gets, and how long it's held up waiting on it, and each
+//! island's observer reports these, along with its throughput and the
+//! process's peak resident set size, to `resources_statistics.csv`, once an
+//! epoch.
+use std::fs;
+use std::sync::{Mutex, Once};
+use std::time::Duration;
+
+use hashbrown::HashMap;
+use serde::Serialize;
+
+use crate::observer::LogRecord;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub emulation_time: Duration,
+    /// Evaluations that reused a specimen's existing profile
+    pub cache_hits: usize,
+    /// Evaluations that had to run the specimen in the emulator
+    pub cache_misses: usize,
+    /// The most specimens waiting at once to be taken into the window
+    pub observer_queue_peak: usize,
+    /// The time spent waiting for room in the observer's queue
+    pub observer_blocked: Duration,
+}
+
+/// An island's usage since it was last taken, and its time in the emulator
+/// over the whole run, against which its `EmulatorTime` budget is checked.
+#[derive(Debug, Clone, Copy, Default)]
+struct Account {
+    usage: Usage,
+    emulation_time: Duration,
+}
+
+static INIT_USAGE: Once = Once::new();
+static mut USAGE: Option<Mutex<HashMap<usize, Account>>> = None;
+
+fn usage() -> &'static Mutex<HashMap<usize, Account>> {
+    INIT_USAGE.call_once(|| unsafe { USAGE = Some(Mutex::new(HashMap::new())) });
+    unsafe {
+        USAGE
+            .as_ref()
+            .expect("The resource accounts have not been initialized")
+    }
+}
+
+/// Records an evaluation on the island that reused the specimen's profile.
+pub fn record_cache_hit(island: usize) {
+    usage()
+        .lock()
+        .expect("poisoned")
+        .entry(island)
+        .or_default()
+        .usage
+        .cache_hits += 1;
+}
+
+/// Records an evaluation on the island that ran the specimen in the
+/// emulator, for the given time.
+pub fn record_emulation(island: usize, time: Duration) {
+    let mut usage = usage().lock().expect("poisoned");
+    let account = usage.entry(island).or_default();
+    account.usage.cache_misses += 1;
+    account.usage.emulation_time += time;
+    account.emulation_time += time;
+}
+
+/// Records a specimen sent to the island's observer, with the number then
+/// waiting to be taken into the window, itself included, and the time spent
+/// waiting for room in the queue.
+pub fn record_observation(island: usize, queue_depth: usize, blocked: Duration) {
+    let mut usage = usage().lock().expect("poisoned");
+    let account = usage.entry(island).or_default();
+    account.usage.observer_queue_peak = account.usage.observer_queue_peak.max(queue_depth);
+    account.usage.observer_blocked += blocked;
+}
+
+/// Returns the island's usage since it was last taken, resetting it.
+pub fn take_usage(island: usize) -> Usage {
+    usage()
+        .lock()
+        .expect("poisoned")
+        .get_mut(&island)
+        .map(|account| std::mem::replace(&mut account.usage, Usage::default()))
+        .unwrap_or_default()
+}
+
+/// The island's time in the emulator, over the whole run.
+pub fn emulation_time(island: usize) -> Duration {
+    usage()
+        .lock()
+        .expect("poisoned")
+        .get(&island)
+        .map(|account| account.emulation_time)
+        .unwrap_or_default()
+}
+
+/// The peak resident set size of the process, in bytes, as reported by
+/// `/proc/self/status`.
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kilobytes = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// An island's resource usage over an epoch.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceRecord {
+    pub island: usize,
+    pub epoch: usize,
+    pub counter: usize,
+    pub wall_seconds: f64,
+    pub evaluations: usize,
+    pub evaluations_per_second: f64,
+    pub emulation_seconds: f64,
+    pub cache_hits: usize,
+    pub cache_misses: usize,
+    /// The fraction of evaluations that reused an existing profile, or NaN
+    /// if none were recorded
+    pub cache_hit_rate: f64,
+    pub observer_queue_peak: usize,
+    pub observer_blocked_seconds: f64,
+    pub peak_rss_bytes: Option<u64>,
+}
+
+impl ResourceRecord {
+    pub fn new(
+        island: usize,
+        epoch: usize,
+        counter: usize,
+        wall_time: Duration,
+        evaluations: usize,
+        usage: Usage,
+    ) -> Self {
+        let wall_seconds = wall_time.as_secs_f64();
+        let lookups = usage.cache_hits + usage.cache_misses;
+        Self {
+            island,
+            epoch,
+            counter,
+            wall_seconds,
+            evaluations,
+            evaluations_per_second: evaluations as f64 / wall_seconds,
+            emulation_seconds: usage.emulation_time.as_secs_f64(),
+            cache_hits: usage.cache_hits,
+            cache_misses: usage.cache_misses,
+            cache_hit_rate: usage.cache_hits as f64 / lookups as f64,
+            observer_queue_peak: usage.observer_queue_peak,
+            observer_blocked_seconds: usage.observer_blocked.as_secs_f64(),
+            peak_rss_bytes: peak_rss_bytes(),
+        }
+    }
+}
+
+impl LogRecord for ResourceRecord {
+    fn header(&self) -> String {
+        "island,epoch,counter,wall_seconds,evaluations,evaluations_per_second,\
+         emulation_seconds,cache_hits,cache_misses,cache_hit_rate,observer_queue_peak,\
+         observer_blocked_seconds,peak_rss_bytes"
+            .to_string()
+    }
+
+    fn row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.island,
+            self.epoch,
+            self.counter,
+            self.wall_seconds,
+            self.evaluations,
+            self.evaluations_per_second,
+            self.emulation_seconds,
+            self.cache_hits,
+            self.cache_misses,
+            self.cache_hit_rate,
+            self.observer_queue_peak,
+            self.observer_blocked_seconds,
+            self.peak_rss_bytes
+                .map(|b| b.to_string())
+                .unwrap_or_default()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_usage() {
+        // an island number no other test will use
+        let island = 0xdead;
+        record_cache_hit(island);
+        record_emulation(island, Duration::from_millis(250));
+        record_emulation(island, Duration::from_millis(750));
+        record_observation(island, 3, Duration::default());
+        record_observation(island, 1, Duration::from_millis(500));
+        let usage = take_usage(island);
+        assert_eq!(usage.cache_hits, 1);
+        assert_eq!(usage.cache_misses, 2);
+        assert_eq!(usage.emulation_time, Duration::from_secs(1));
+        assert_eq!(usage.observer_queue_peak, 3);
+        assert_eq!(usage.observer_blocked, Duration::from_millis(500));
+        assert_eq!(take_usage(island), Usage::default());
+        // the run's emulation time isn't reset by taking the usage
+        assert_eq!(emulation_time(island), Duration::from_secs(1));
+
+        let record = ResourceRecord::new(island, 3, 300, Duration::from_secs(2), 100, usage);
+        assert_eq!(record.evaluations_per_second, 50.0);
+        assert!((record.cache_hit_rate - 1.0 / 3.0).abs() < 1e-12);
+        assert!(record.peak_rss_bytes.map(|b| b > 0).unwrap_or(true));
+    }
+}
@@ -0,0 +1,267 @@
+//! Resuming a run, with `berbalang <config> --resume <run directory>`, in
+//! the directory it was laid out in, the parent of its island directories,
+//! under the population name and random seed recorded in its manifest.
+//!
+//! The config is checked against the one the manifest recorded, and, if it
+//! has drifted from it, the differences are printed, field by field,
+//!
+//! ```text
+//! mutation_rate: 0.1 -> 0.2
+//! observer.sinks: ["csv"] -> ["csv","jsonl"]
+//! roper.max_emu_steps: (unset) -> 4096
+//! ```
+//!
+//! and the run is refused, unless `--allow-config-drift` is given, in which
+//! case the new manifest is written beside the old one.
+//!
+//! Each island that has written a checkpoint is restored from it, with its
+//! population, soup and epoch, and carries on writing to its directory. See
+//! `checkpoint`. An island that hasn't written anything yet -- as when the
+//! run was set up, but stopped before it started evolving -- begins again,
+//! from its seed. A run with an island that has written data, but no
+//! checkpoint, as islands that don't evolve by tournament don't, is refused,
+//! rather than have that data written over.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::checkpoint;
+use crate::configure::{Config, ConfigFormat};
+use crate::error::Error;
+use crate::manifest;
+
+/// A field whose value differs between the stored config and the current
+/// one, `None` standing for a field that's absent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Drift {
+    pub path: String,
+    pub stored: Option<Value>,
+    pub current: Option<Value>,
+}
+
+impl Drift {
+    pub fn describe(&self) -> String {
+        let show = |v: &Option<Value>| match v {
+            None | Some(Value::Null) => "(unset)".to_string(),
+            Some(v) => v.to_string(),
+        };
+        format!(
+            "{}: {} -> {}",
+            self.path,
+            show(&self.stored),
+            show(&self.current)
+        )
+    }
+}
+
+fn diff(stored: Option<&Value>, current: Option<&Value>, path: &str, out: &mut Vec<Drift>) {
+    // an unset field is recorded as null, or, if it's newer than the
+    // manifest, not at all
+    let stored = stored.filter(|v| !v.is_null());
+    let current = current.filter(|v| !v.is_null());
+    match (stored, current) {
+        (Some(Value::Object(s)), Some(Value::Object(c))) => {
+            let mut keys = s.keys().chain(c.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                diff(s.get(key), c.get(key), &path, out);
+            }
+        }
+        (s, c) if s != c => out.push(Drift {
+            path: path.to_string(),
+            stored: s.cloned(),
+            current: c.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+/// The fields of the resolved configs, as recorded in manifests, that
+/// differ, in order of their paths. Arrays are compared whole.
+pub fn config_drift(stored: &Value, current: &Value) -> Vec<Drift> {
+    let mut drift = Vec::new();
+    diff(Some(stored), Some(current), "", &mut drift);
+    drift
+}
+
+/// The island directories under `run_directory` that hold data, each with
+/// the first file found in it. The files at the top of the run directory,
+/// like the manifest and the config, are written before the islands start.
+fn island_data(run_directory: &Path) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    fn first_file(dir: &Path) -> Result<Option<PathBuf>, Error> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if !path.is_dir() {
+                return Ok(Some(path));
+            }
+            if let Some(file) = first_file(&path)? {
+                return Ok(Some(file));
+            }
+        }
+        Ok(None)
+    }
+
+    let mut islands = Vec::new();
+    for entry in fs::read_dir(run_directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if let Some(file) = first_file(&path)? {
+                islands.push((path, file));
+            }
+        }
+    }
+    islands.sort();
+    Ok(islands)
+}
+
+/// Checks that each island under `run_directory` that has written data has
+/// a checkpoint to resume from, and that the checkpoint can be read.
+fn check_islands(run_directory: &Path) -> Result<(), Error> {
+    for (island, file) in island_data(run_directory)? {
+        let path = checkpoint::path(&island);
+        if !path.exists() {
+            return Err(Error::Misc(format!(
+                "The run in {} has already written {}, but has no checkpoint in {} to \
+                 resume from, and would write over it. Start a new run instead.",
+                run_directory.display(),
+                file.display(),
+                island.display()
+            )));
+        }
+        let epoch = checkpoint::check(&path).map_err(|e| {
+            Error::Misc(format!(
+                "Failed to read the checkpoint {}: {:?}",
+                path.display(),
+                e
+            ))
+        })?;
+        log::info!("{} will resume from epoch {}", island.display(), epoch);
+    }
+    Ok(())
+}
+
+/// Reads the config, as `Config::from_path` does, to resume the run in
+/// `run_directory`, refusing it if it has drifted from the run's recorded
+/// config, unless `allow_drift`, or if any of its islands has written data,
+/// but no checkpoint to resume from.
+pub fn load(
+    path: &Path,
+    format: Option<ConfigFormat>,
+    overrides: &[String],
+    run_directory: &Path,
+    allow_drift: bool,
+) -> Result<Config, Error> {
+    let prior = manifest::read(run_directory)?;
+    check_islands(run_directory)?;
+    let mut config = Config::load(path, format, None, overrides)?;
+    // the run is identified by these, so they're taken from the manifest
+    if let Some(name) = prior
+        .config
+        .pointer("/observer/population_name")
+        .and_then(Value::as_str)
+    {
+        config.observer.population_name = name.to_string();
+    }
+    config.random_seed = prior.random_seed;
+
+    let drift = config_drift(&prior.config, &manifest::resolved_config(&config)?);
+    if !drift.is_empty() {
+        let described = drift
+            .iter()
+            .map(|d| format!("  {}", d.describe()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let msg = format!(
+            "The config has drifted from the one recorded in {}:\n{}",
+            run_directory.display(),
+            described
+        );
+        if !allow_drift {
+            return Err(Error::Misc(format!(
+                "{}\nPass --allow-config-drift to resume the run anyway.",
+                msg
+            )));
+        }
+        log::warn!("{}", msg);
+    }
+
+    config.observer.resume_directory =
+        Some(run_directory.canonicalize()?.to_string_lossy().into_owned());
+    config.set_data_directory();
+    manifest::write_or_verify_allowing(&config, allow_drift)?;
+    log::info!(
+        "Resuming {} in {}",
+        config.observer.population_name,
+        run_directory.display()
+    );
+    Ok(config)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_config_drift() {
+        let stored = json!({
+            "mutation_rate": 0.1,
+            "observer": { "sinks": ["csv"], "report_every": null },
+            "roper": { "arch": "X86" },
+        });
+        let current = json!({
+            "mutation_rate": 0.2,
+            "observer": { "sinks": ["csv"], "report_every": 100 },
+            "roper": { "arch": "X86", "preset": "linux-x86_64" },
+        });
+        let drift = config_drift(&stored, &current)
+            .iter()
+            .map(Drift::describe)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            drift,
+            vec![
+                "mutation_rate: 0.1 -> 0.2",
+                "observer.report_every: (unset) -> 100",
+                "roper.preset: (unset) -> \"linux-x86_64\"",
+            ]
+        );
+        assert!(config_drift(&stored, &stored).is_empty());
+    }
+
+    #[test]
+    fn test_island_data() {
+        let run = std::env::temp_dir().join(format!("berbalang_resume_{}", rand::random::<u64>()));
+        let island = run.join("island_0");
+        fs::create_dir_all(island.join("population")).unwrap();
+        fs::write(run.join("manifest.json"), "{}").unwrap();
+        // a run that was set up, but never started, can be resumed
+        assert_eq!(island_data(&run).unwrap(), vec![]);
+        assert!(check_islands(&run).is_ok());
+        let dump = island.join("population").join("population_0.json.gz");
+        fs::write(&dump, "").unwrap();
+        assert_eq!(island_data(&run).unwrap(), vec![(island.clone(), dump)]);
+        // but not one that has written data without a checkpoint
+        assert!(check_islands(&run).is_err());
+        let checkpoint = checkpoint::Checkpoint {
+            island: 0,
+            epoch: 10,
+            iteration: 2500,
+            soup: None,
+            population: vec![1_u64, 2, 3],
+        };
+        crate::util::dump::dump(&checkpoint, checkpoint::path(&island)).unwrap();
+        assert!(check_islands(&run).is_ok());
+        fs::write(checkpoint::path(&island), "").unwrap();
+        assert!(check_islands(&run).is_err());
+        fs::remove_dir_all(&run).unwrap();
+    }
+}
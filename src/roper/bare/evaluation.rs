@@ -5,6 +5,7 @@ use unicorn::Cpu;
 use crate::configure::ClassificationProblem;
 use crate::emulator::register_pattern::Register;
 use crate::ontogenesis::FitnessFn;
+use crate::resources;
 use crate::roper::Sketches;
 use crate::{configure::Config, emulator::hatchery::Hatchery, ontogenesis::Develop, util};
 
@@ -20,11 +21,14 @@ pub struct Evaluator<C: 'static + Cpu<'static>> {
 impl<C: 'static + Cpu<'static>> Evaluator<C> {
     pub fn spawn(config: &Config, fitness_fn: FitnessFn<Creature, Sketches, Config>) -> Self {
         let config = config.clone();
-        let hatch_config = Arc::new(config.roper.clone());
+        let mut hatch_config = config.roper.clone();
+        hatch_config.random_seed = config.random_seed;
+        let hatch_config = Arc::new(hatch_config);
         let output_registers: Vec<Register<C>> = {
             config
                 .roper
                 .registers_to_check()
+                .expect("The registers were checked when the register patterns were parsed")
                 .into_iter()
                 // running error through ok() because it can't be formatted with Debug
                 .map(|r| r.parse().ok().expect("Failed to parse register name"))
@@ -73,6 +77,7 @@ fn classification_problem_to_register_map<C: 'static + Cpu<'static>>(
 impl<'a, C: 'static + Cpu<'static>> Develop<Creature> for Evaluator<C> {
     fn develop(&self, mut creature: Creature) -> Creature {
         if creature.profile.is_some() {
+            resources::record_cache_hit(self.config.island_id);
             return creature;
         }
         // TODO: implement classification task here.
@@ -86,6 +91,7 @@ impl<'a, C: 'static + Cpu<'static>> Develop<Creature> for Evaluator<C> {
                     .hatchery
                     .execute(creature.chromosome().to_vec(), Some(reg_map))
                     .expect("Failed to evaluate creature");
+                resources::record_emulation(self.config.island_id, profile.total_emulation_time());
                 creature.add_profile(profile);
             }
             return creature;
@@ -102,6 +108,7 @@ impl<'a, C: 'static + Cpu<'static>> Develop<Creature> for Evaluator<C> {
             .hatchery
             .execute(creature.chromosome().to_vec(), None)
             .expect("Failed to evaluate creature");
+        resources::record_emulation(self.config.island_id, profile.total_emulation_time());
         creature.add_profile(profile);
         creature
     }
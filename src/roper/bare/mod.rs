@@ -1,22 +1,24 @@
+use std::collections::BTreeMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 
 use byteorder::{BigEndian, ByteOrder, LittleEndian};
 use hashbrown::HashMap;
 use rand::seq::IteratorRandom;
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use rand_distr::{Distribution, Standard};
 use serde::{Deserialize, Serialize};
 
-use crate::configure::Config;
+use crate::configure::{ChainMode, Config, RoperConfig};
 use crate::emulator::loader;
 use crate::emulator::loader::get_static_memory_image;
 use crate::emulator::pack::Pack;
 use crate::emulator::profiler::{HasProfile, Profile};
-use crate::evolution::{Genome, LinearChromosome, Mutation, Phenome};
+use crate::emulator::srop;
+use crate::evolution::{Genes, Genome, LinearChromosome, Mutation, Phenome};
 use crate::roper::Fitness;
 use crate::util::architecture::{read_integer, write_integer, Perms};
-use crate::util::random::hash_seed_rng;
+use crate::util::random::{hash_seed_rng, thread_rng};
 use crate::util::{self, architecture::Endian};
 
 /// The `evaluation` module contains the various fitness functions, and the construction
@@ -24,16 +26,20 @@ use crate::util::{self, architecture::Endian};
 /// scores to each member of the population.
 pub mod evaluation;
 
-#[derive(Clone, Serialize)]
+/// A creature read back from a dump, or a checkpoint, has its genome, but
+/// not its profile or fitness, and is evaluated again.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Creature {
     // pub chromosome: Vec<T>,
     // pub chromosome_parentage: Vec<usize>,
     // pub chromosome_mutation: Vec<Option<WordMutation>>,
     pub chromosome: LinearChromosome<u64, WordMutation>,
     pub tag: u64,
+    #[serde(skip_deserializing)]
     pub profile: Option<Profile>,
-    #[serde(borrow)]
+    #[serde(skip_deserializing)]
     pub fitness: Option<Fitness<'static>>,
+    #[serde(skip_deserializing)]
     pub front: Option<usize>,
     pub num_offspring: usize,
     pub native_island: usize,
@@ -255,6 +261,8 @@ impl Genome for Creature {
     }
 
     fn random<H: Hash>(config: &Config, salt: H) -> Self {
+        // drawn on rayon's workers, by Tournament::new
+        loader::set_target(config.roper.target);
         let mut hasher = fnv::FnvHasher::default();
         salt.hash(&mut hasher);
         config.random_seed.hash(&mut hasher);
@@ -279,9 +287,9 @@ impl Genome for Creature {
         Self {
             //crossover_mask,
             chromosome: LinearChromosome {
-                chromosome,
-                mutations: vec![None; len],
-                parentage: vec![],
+                chromosome: chromosome.into(),
+                mutations: vec![None; len].into(),
+                parentage: Genes::default(),
                 parent_names: vec![],
                 name,
                 generation: 0,
@@ -330,18 +338,25 @@ pub enum WordMutation {
     AddressAdd,
     AddressSub,
     BitFlip,
+    Resample,
 }
 
 impl Mutation for WordMutation {
     type Allele = u64;
 
-    fn mutate_point(allele: &mut Self::Allele, _config: &Config) -> Self {
+    fn mutate_point(allele: &mut Self::Allele, config: &Config) -> Self {
         let mut rng = thread_rng();
-        let mutation = rand::random::<WordMutation>();
+        // Resampling from the soup is only worth a share of the mutations
+        // once the soup's been clustered; otherwise the distribution is left
+        // as it was.
+        let mutation = if config.roper.cluster_soup && rng.gen_range(0, 6) == 0 {
+            WordMutation::Resample
+        } else {
+            rng.gen::<WordMutation>()
+        };
         let memory = get_static_memory_image();
         let endian = memory.endian;
         let word_size = memory.word_size;
-        // TODO: add a mutation that picks a random address from the soup
         match mutation {
             WordMutation::Dereference => {
                 if let Some(bytes) = memory.try_dereference(*allele, None) {
@@ -372,6 +387,11 @@ impl Mutation for WordMutation {
                 let word = *allele ^ (1 << rng.gen_range(0, word_size as u64 * 8));
                 *allele = word;
             }
+            WordMutation::Resample => {
+                if let Some(word) = crate::roper::soup::sample_word(&config.roper, &mut rng) {
+                    *allele = word;
+                }
+            }
         }
         mutation
     }
@@ -413,6 +433,25 @@ impl Phenome for Creature {
         self.chromosome.name.as_str()
     }
 
+    fn profile_summary(&self) -> Option<BTreeMap<String, f64>> {
+        self.profile.as_ref().map(Profile::summary)
+    }
+
+    fn execution_paths(&self) -> Vec<Vec<u64>> {
+        self.profile
+            .as_ref()
+            .map(Profile::block_paths)
+            .unwrap_or_default()
+    }
+
+    fn parents(&self) -> Vec<String> {
+        self.chromosome.parent_names.clone()
+    }
+
+    fn operators(&self) -> Vec<String> {
+        self.chromosome.operators()
+    }
+
     fn set_fitness(&mut self, f: Self::Fitness) {
         self.fitness = Some(f)
     }
@@ -455,4 +494,185 @@ impl Phenome for Creature {
     fn mature(&self) -> bool {
         self.profile.is_some()
     }
+
+    fn annotated_layout(&self, config: &Config) -> Option<String> {
+        Some(self.stack_layout(&config.roper))
+    }
+
+    fn path_export(&self) -> Option<String> {
+        self.profile.as_ref().and_then(|p| {
+            let payloads = vec![self.chromosome().to_vec(); p.paths.len()];
+            serde_json::to_string_pretty(&p.path_export(&payloads)).ok()
+        })
+    }
+}
+
+/// The most bytes we'll disassemble when looking for the end of a gadget.
+const MAX_GADGET_BYTES: usize = 0x40;
+
+/// Disassembles the gadget at `addr`, up to and including the first
+/// instruction that transfers control (a `ret`, `jmp`, `call`, or syscall).
+fn gadget_disassembly(addr: u64) -> Vec<String> {
+    let memory = loader::get_static_memory_image();
+    let available = memory
+        .try_dereference(addr, None)
+        .map(|b| b.len())
+        .unwrap_or(0);
+    let size = available.min(MAX_GADGET_BYTES);
+    let mut lines = Vec::new();
+    if size == 0 {
+        return lines;
+    }
+    if let Some(insts) = memory.disassemble(addr, size, None) {
+        for inst in insts.iter() {
+            lines.push(format!(
+                "0x{:x}: {} {}",
+                inst.address(),
+                inst.mnemonic().unwrap_or("??"),
+                inst.op_str().unwrap_or("")
+            ));
+            match inst.mnemonic() {
+                Some("ret") | Some("jmp") | Some("call") | Some("syscall") | Some("sysenter")
+                | Some("int") => break,
+                _ => {}
+            }
+        }
+    }
+    lines
+}
+
+/// The words each gene of the payload is packed as, along with the offset,
+/// in words, of the first of them from the start of the payload. Most genes
+/// are packed as a single word, but a sigreturn frame gene is packed as the
+/// whole frame it stands for.
+fn packed_genes(genes: &[u64], config: &RoperConfig) -> Vec<(usize, Vec<u64>)> {
+    let mut offset = 0;
+    genes
+        .iter()
+        .map(|gene| {
+            let words = srop::expand_frames(vec![*gene], config);
+            let packed = (offset, words);
+            offset += packed.1.len();
+            packed
+        })
+        .collect()
+}
+
+impl Creature {
+    /// Renders the payload as it's laid out in memory, once packed: the
+    /// offset and value of each word, what we take that word to be, and, for
+    /// gadgets, the disassembly of the code it points to. The payload is
+    /// preceded by the entry context's stack words, if there are any, and is
+    /// a dispatch table, rather than a stack, in JOP mode.
+    pub fn stack_layout(&self, config: &RoperConfig) -> String {
+        let memory = loader::get_static_memory_image();
+        let word_size = memory.word_size;
+        let render = |word: u64| {
+            if word_size == 8 {
+                format!("{:0>16x}", word)
+            } else {
+                format!("{:0>8x}", word)
+            }
+        };
+        let what = if config.entry.is_none() && config.chain_mode == ChainMode::Jop {
+            "Dispatch table"
+        } else {
+            "Stack layout"
+        };
+        let mut s = format!(
+            "{} of {}, from island {}\n",
+            what, self.chromosome.name, self.native_island
+        );
+        let entry_stack = config
+            .entry
+            .as_ref()
+            .map(|e| e.stack.as_slice())
+            .unwrap_or_default();
+        for (i, word) in entry_stack.iter().enumerate() {
+            s.push_str(&format!(
+                "+0x{:04x}  0x{}  entry stack\n",
+                i * word_size,
+                render(*word)
+            ));
+        }
+        let frame_layout = srop::frame_layout(config.arch, config.mode).unwrap_or_default();
+        for (gene, (offset, words)) in self
+            .chromosome()
+            .iter()
+            .zip(packed_genes(self.chromosome(), config))
+        {
+            let offset = (entry_stack.len() + offset) * word_size;
+            if let Some(frame) = srop::frame_index(*gene).filter(|_| words.len() > 1) {
+                for (j, word) in words.iter().enumerate() {
+                    let register = frame_layout.get(j).cloned().unwrap_or("");
+                    s.push_str(&format!(
+                        "+0x{:04x}  0x{}  sigreturn frame #{} {}\n",
+                        offset + j * word_size,
+                        render(*word),
+                        frame,
+                        register
+                    ));
+                }
+                continue;
+            }
+            let kind = if let Some(frame) = srop::frame_index(*gene) {
+                format!("sigreturn frame #{} (not expanded)", frame)
+            } else {
+                match memory.perm_of_addr(*gene) {
+                    Some(perm) if perm.intersects(Perms::EXEC) => {
+                        let executed = self
+                            .profile
+                            .as_ref()
+                            .map(|p| p.times_executed(*gene))
+                            .unwrap_or(0);
+                        format!("gadget (executed {} times)", executed)
+                    }
+                    Some(perm) => format!("pointer ({:?})", perm),
+                    None => "literal".to_string(),
+                }
+            };
+            s.push_str(&format!(
+                "+0x{:04x}  0x{}  {}\n",
+                offset,
+                render(*gene),
+                kind
+            ));
+            if memory
+                .perm_of_addr(*gene)
+                .map(|p| p.intersects(Perms::EXEC))
+                .unwrap_or(false)
+            {
+                for line in gadget_disassembly(*gene) {
+                    s.push_str(&format!("            {}\n", line));
+                }
+            }
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_packed_genes() {
+        let mut config = RoperConfig::default();
+        let mut template = HashMap::new();
+        template.insert("RAX".to_string(), 15);
+        config.sigreturn_frames.push(template);
+        let frame_len = srop::frame_layout(config.arch, config.mode)
+            .expect("x86_64 frames are laid out")
+            .len();
+
+        // the frame gene in the middle pushes the last gene a whole frame on
+        let genes = vec![0x1000, srop::frame_gene(0), 0x2000];
+        let packed = packed_genes(&genes, &config);
+        let offsets = packed.iter().map(|(offset, _)| *offset).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0, 1, 1 + frame_len]);
+        assert_eq!(packed[1].1.len(), frame_len);
+        assert_eq!(packed[2].1, vec![0x2000]);
+        let words = packed.into_iter().flat_map(|(_, w)| w).collect::<Vec<_>>();
+        assert_eq!(words, srop::expand_frames(genes, &config));
+    }
 }
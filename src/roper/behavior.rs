@@ -0,0 +1,137 @@
+//! Memoization of the objectives that are costly to compute, but depend on
+//! nothing but what a specimen did: the distance of its register states
+//! from the target patterns, the addresses its execution paths visited, and
+//! the occurrences of the memory pattern among its writes. Each is kept
+//! under a hash of the part of the profile it's computed from, so that
+//! specimens that behave alike, as most do once a population converges,
+//! aren't scored from scratch each time, even under a dynamic fitness,
+//! whose frequency-based objectives are still computed afresh.
+//!
+//! Each evaluator keeps its own cache, in its `Sketches`, of at most
+//! `fitness.behavior_cache_size` entries of each kind, the oldest of which
+//! are dropped first. A size of 0 disables it.
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use hashbrown::HashMap;
+
+use crate::configure::Config;
+
+/// The hash under which a behavior's objectives are kept. Two behaviors
+/// that differ are taken to be the same only if their 64-bit hashes
+/// collide.
+pub fn behavior_hash<T: Hash + ?Sized>(behavior: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    behavior.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Values computed from behaviors, kept under their hashes, up to a
+/// capacity, past which the oldest are dropped.
+pub struct Memo<V> {
+    values: HashMap<u64, V>,
+    order: VecDeque<u64>,
+    capacity: usize,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl<V: Clone> Memo<V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            values: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// The value kept for the behavior, or, if there's none, the one
+    /// `compute` returns, which is kept for next time.
+    pub fn get_or_compute<B, F>(&mut self, behavior: &B, compute: F) -> V
+    where
+        B: Hash + ?Sized,
+        F: FnOnce() -> V,
+    {
+        if self.capacity == 0 {
+            return compute();
+        }
+        let key = behavior_hash(behavior);
+        if let Some(value) = self.values.get(&key) {
+            self.hits += 1;
+            return value.clone();
+        }
+        self.misses += 1;
+        let value = compute();
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.values.remove(&oldest);
+            }
+        }
+        self.order.push_back(key);
+        self.values.insert(key, value.clone());
+        value
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+pub struct BehaviorCache {
+    /// Each register state's distance from the pattern of its case
+    pub register_errors: Memo<f64>,
+    /// The addresses visited by each set of execution paths, in no order
+    pub addresses_visited: Memo<Arc<Vec<u64>>>,
+    /// The occurrences of each prefix of the memory pattern among each set
+    /// of memory writes
+    pub memory_pattern_occurrences: Memo<Arc<Vec<f64>>>,
+}
+
+impl BehaviorCache {
+    pub fn new(config: &Config) -> Self {
+        let capacity = config.fitness.behavior_cache_size;
+        Self {
+            register_errors: Memo::new(capacity),
+            addresses_visited: Memo::new(capacity),
+            memory_pattern_occurrences: Memo::new(capacity),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_memo() {
+        let mut memo = Memo::new(2);
+        let mut computed = 0;
+        let mut square = |memo: &mut Memo<u64>, x: u64| {
+            memo.get_or_compute(&x, || {
+                computed += 1;
+                x * x
+            })
+        };
+        assert_eq!(square(&mut memo, 3), 9);
+        assert_eq!(square(&mut memo, 3), 9);
+        assert_eq!(square(&mut memo, 4), 16);
+        // 3, the oldest, is dropped to make room for 5
+        assert_eq!(square(&mut memo, 5), 25);
+        assert_eq!(square(&mut memo, 3), 9);
+        assert_eq!(computed, 4);
+        assert_eq!((memo.hits, memo.misses), (1, 4));
+        assert_eq!(memo.len(), 2);
+
+        let mut disabled = Memo::new(0);
+        assert_eq!(disabled.get_or_compute(&3, || 9), 9);
+        assert!(disabled.is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use hashbrown::HashMap;
+use unicorn::Cpu;
+
+use crate::configure::{ChainMode, Config};
+use crate::emulator::hatchery::Hatchery;
+use crate::emulator::register_pattern::Register;
+use crate::error::Error;
+use crate::util::architecture::{constant_register_state, word_size_in_bytes};
+
+/// The number of canonical register states each gadget is run on.
+const NUM_CANONICAL_STATES: usize = 3;
+
+/// What a gadget does to a register, as seen across the canonical states.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RegisterEffect {
+    /// The register holds the value it started with.
+    Preserved,
+    /// The register holds the same value, whatever state it started in.
+    Constant,
+    /// The register holds the starting value of the register with this index.
+    Copied(usize),
+    Other,
+}
+
+/// The effect signature of a gadget. Gadgets with the same signature are
+/// put in the same cluster.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Signature {
+    pub registers: Vec<RegisterEffect>,
+    pub writes_memory: bool,
+    pub crashes: bool,
+}
+
+/// Distinct for every register and every state, and unlikely to be mistaken
+/// for an address.
+fn canonical_value(register: usize, state: usize, word_size: usize) -> u64 {
+    ((state as u64 + 1) << (word_size * 8 - 8)) | ((register as u64 + 1) << 4)
+}
+
+/// `inputs[k][i]` and `outputs[k][i]` are the values of the `i`th register
+/// before and after the gadget ran on the `k`th canonical state.
+pub fn signature(
+    inputs: &[Vec<u64>],
+    outputs: &[Vec<u64>],
+    writes_memory: bool,
+    crashes: bool,
+) -> Signature {
+    let num_registers = inputs.first().map(Vec::len).unwrap_or(0);
+    let registers = (0..num_registers)
+        .map(|i| {
+            let out = |k: usize| outputs[k][i];
+            let states = 0..inputs.len();
+            if states.clone().all(|k| out(k) == inputs[k][i]) {
+                RegisterEffect::Preserved
+            } else if states.clone().all(|k| out(k) == out(0)) {
+                RegisterEffect::Constant
+            } else if let Some(j) =
+                (0..num_registers).find(|j| states.clone().all(|k| out(k) == inputs[k][*j]))
+            {
+                RegisterEffect::Copied(j)
+            } else {
+                RegisterEffect::Other
+            }
+        })
+        .collect();
+    Signature {
+        registers,
+        writes_memory,
+        crashes,
+    }
+}
+
+/// Runs each gadget in the soup in isolation, on a handful of canonical
+/// register states, and groups the gadgets by their effect signatures.
+pub fn cluster_soup<C: 'static + Cpu<'static>>(config: &Config) -> Result<Vec<Vec<u64>>, Error> {
+    let soup = config
+        .roper
+        .soup
+        .as_ref()
+        .ok_or_else(|| Error::Misc("The soup must be initialized before clustering".into()))?;
+    if config.roper.chain_mode != ChainMode::Rop {
+        return Err(Error::Misc(
+            "Soup clustering is only supported for ROP chains".into(),
+        ));
+    }
+    let names = &config.roper.output_registers;
+    let registers: Vec<Register<C>> = names
+        .iter()
+        .map(|r| r.parse().ok().expect("Failed to parse register name"))
+        .collect();
+    let word_size = word_size_in_bytes(config.roper.arch, config.roper.mode);
+    // The gadgets' segments are shared with the islands' emulators, so they
+    // mustn't be unmapped when this hatchery is done with.
+    let hatchery: Hatchery<C> = Hatchery::new(
+        Arc::new(config.roper.clone()),
+        Arc::new(constant_register_state::<C>(&registers, 0)),
+        Arc::new(registers.clone()),
+    )
+    .keep_shared_mappings();
+
+    // Each gadget returns to the zeroed stack beyond its payload, which
+    // halts the emulator cleanly.
+    let payloads = soup.iter().map(|w| vec![*w]).collect::<Vec<Vec<u64>>>();
+    let mut inputs = Vec::new();
+    let mut runs = Vec::new();
+    for k in 0..NUM_CANONICAL_STATES {
+        let state = (0..registers.len())
+            .map(|i| canonical_value(i, k, word_size))
+            .collect::<Vec<u64>>();
+        let args = registers
+            .iter()
+            .cloned()
+            .zip(state.iter().cloned())
+            .collect::<HashMap<Register<C>, u64>>();
+        runs.push(hatchery.execute_batch(payloads.clone(), Some(args))?);
+        inputs.push(state);
+    }
+
+    let mut clusters: BTreeMap<Signature, Vec<u64>> = BTreeMap::new();
+    for (g, word) in soup.iter().enumerate() {
+        let outputs = runs
+            .iter()
+            .map(|profiles| {
+                let registers = profiles[g].registers.first();
+                names
+                    .iter()
+                    .map(|n| {
+                        registers
+                            .and_then(|r| r.0.get(n))
+                            .map(|chain| chain[0])
+                            .unwrap_or(0)
+                    })
+                    .collect::<Vec<u64>>()
+            })
+            .collect::<Vec<Vec<u64>>>();
+        let crashes = runs
+            .iter()
+            .any(|profiles| profiles[g].cpu_errors.iter().any(Option::is_some));
+        let writes_memory = runs.iter().any(|profiles| {
+            profiles[g]
+                .memory_writes
+                .iter()
+                .any(|w| w.regions().next().is_some())
+        });
+        clusters
+            .entry(signature(&inputs, &outputs, writes_memory, crashes))
+            .or_insert_with(Vec::new)
+            .push(*word);
+    }
+    log::info!(
+        "Clustered {} gadgets into {} clusters by effect",
+        soup.len(),
+        clusters.len()
+    );
+    Ok(clusters.into_iter().map(|(_, words)| words).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        let inputs = (0..NUM_CANONICAL_STATES)
+            .map(|k| (0..3).map(|i| canonical_value(i, k, 8)).collect())
+            .collect::<Vec<Vec<u64>>>();
+        // r0 untouched, r1 = 7, r2 = r0
+        let outputs = inputs
+            .iter()
+            .map(|state| vec![state[0], 7, state[0]])
+            .collect::<Vec<Vec<u64>>>();
+        let sig = signature(&inputs, &outputs, false, false);
+        assert_eq!(
+            sig.registers,
+            vec![
+                RegisterEffect::Preserved,
+                RegisterEffect::Constant,
+                RegisterEffect::Copied(0)
+            ]
+        );
+        let outputs = inputs
+            .iter()
+            .map(|state| vec![state[0] + 1, state[1], state[2]])
+            .collect::<Vec<Vec<u64>>>();
+        let sig = signature(&inputs, &outputs, true, false);
+        assert_eq!(sig.registers[0], RegisterEffect::Other);
+        assert!(sig.writes_memory);
+    }
+}
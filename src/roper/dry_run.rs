@@ -0,0 +1,181 @@
+//! Dry runs, as `berbalang --dry-run` makes them, which check, in a few
+//! seconds, that a config will get as far as evolving anything: that the
+//! binary can be mapped, the register patterns parsed, and the registers
+//! named found on the architecture, and that an initial population can be
+//! built, and one of its specimens evaluated, for each of the targets. A
+//! report of each step is returned, or the first problem found.
+use std::fmt::Write;
+use std::time::Instant;
+
+use unicorn::Cpu;
+
+use crate::configure::Config;
+use crate::emulator::loader;
+use crate::emulator::register_pattern::{parse_register_pattern_file, Register};
+use crate::error::Error;
+use crate::evolution::{Genome, Phenome};
+use crate::fitness::FitnessScore;
+use crate::ontogenesis::Develop;
+use crate::roper::fitness_functions::{get_fitness_function, FITNESS_FUNCTIONS};
+use crate::roper::{bare, init_soup, push};
+
+/// The names, among those given, of registers the architecture lacks.
+fn unknown_registers<C: 'static + Cpu<'static>>(names: &[String]) -> Vec<String> {
+    let mut unknown = names
+        .iter()
+        .filter(|name| name.parse::<Register<C>>().is_err())
+        .cloned()
+        .collect::<Vec<_>>();
+    unknown.sort();
+    unknown
+}
+
+/// Builds the initial population, without evolving it, and evaluates its
+/// first specimen.
+fn build_and_evaluate<P, E>(
+    config: &Config,
+    mut evaluator: E,
+    report: &mut String,
+) -> Result<(), Error>
+where
+    P: Genome + Phenome,
+    E: Develop<P>,
+{
+    let population = (0..config.pop_size)
+        .map(|i| P::random(config, i))
+        .collect::<Vec<P>>();
+    let lengths = population.iter().map(Genome::len).collect::<Vec<usize>>();
+    let _ = writeln!(
+        report,
+        "Built a population of {}, of lengths {} to {}",
+        population.len(),
+        lengths.iter().min().unwrap_or(&0),
+        lengths.iter().max().unwrap_or(&0)
+    );
+    let specimen = population
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::Misc("The population is empty".to_string()))?;
+    let started = Instant::now();
+    let specimen = evaluator.develop(specimen);
+    let specimen = evaluator.apply_fitness_function(specimen);
+    let _ = writeln!(
+        report,
+        "Evaluated {} in {:?}: scores {:?}, scalar fitness {:?}",
+        specimen.name(),
+        started.elapsed(),
+        specimen.fitness().map(|f| f.components()),
+        specimen.scalar_fitness(&config.fitness.weighting)
+    );
+    Ok(())
+}
+
+fn check<C: 'static + Cpu<'static>>(
+    mut config: Config,
+    mut report: String,
+) -> Result<String, Error> {
+    let registers = config.roper.registers_to_check()?;
+    let unknown = unknown_registers::<C>(&registers);
+    if !unknown.is_empty() {
+        return Err(Error::Misc(format!(
+            "No such registers on {:?}: {}",
+            config.roper.arch,
+            unknown.join(", ")
+        )));
+    }
+    let _ = writeln!(
+        report,
+        "Found the {} registers named on {:?}",
+        registers.len(),
+        config.roper.arch
+    );
+    let soup = init_soup(&mut config)?;
+    let _ = writeln!(report, "Initialized a soup of {} words", soup.len());
+
+    // a single worker is all one evaluation needs
+    config.roper.num_workers = 1;
+    config.roper.num_emulators = 1;
+    if config.roper.use_push {
+        let fitness_function = get_fitness_function(&config.fitness.function);
+        let evaluator = push::evaluation::Evaluator::<C>::spawn(&config, fitness_function);
+        build_and_evaluate::<push::Creature, _>(&config, evaluator, &mut report)?;
+    } else {
+        let fitness_function = get_fitness_function(&config.fitness.function);
+        let evaluator = bare::evaluation::Evaluator::<C>::spawn(&config, fitness_function);
+        build_and_evaluate::<bare::Creature, _>(&config, evaluator, &mut report)?;
+    }
+    Ok(report)
+}
+
+/// Checks the config, as described above, for each target some island
+/// searches, returning a report of the checks.
+pub fn dry_run(config: Config) -> Result<String, Error> {
+    let mut report = String::new();
+    for target in 0..=config.roper.targets.len() {
+        if (0..config.num_islands).all(|i| config.roper.target_of(i) != target) {
+            continue;
+        }
+        let mut config = config.clone();
+        config.roper = config.roper.for_target(target);
+        loader::set_target(target);
+        report = dry_run_target(config, report)?;
+    }
+    Ok(report)
+}
+
+fn dry_run_target(mut config: Config, mut report: String) -> Result<String, Error> {
+    let segments = loader::falcon_loader::load_from_path(&mut config, true)?;
+    let _ = writeln!(
+        report,
+        "Mapped {} in {} segments",
+        config.roper.binary_path,
+        segments.len()
+    );
+    // parsed here first, so that a bad pattern is reported, not panicked on
+    if let Some(ref path) = config.roper.register_pattern_file {
+        let patterns = parse_register_pattern_file(path)?;
+        let _ = writeln!(report, "Parsed {} register patterns", patterns.len());
+    }
+    if let Some(ref pattern) = config.roper.register_pattern {
+        pattern.to_pattern()?;
+        let _ = writeln!(report, "Parsed the inline register pattern");
+    }
+    config.roper.parse_register_patterns()?;
+    config.roper.intern_watchpoint_keys();
+    config.apply_deterministic_mode();
+    if !FITNESS_FUNCTIONS.contains(&config.fitness.function.as_str()) {
+        return Err(Error::Misc(format!(
+            "No such fitness function as {:?}. Try one of {}",
+            config.fitness.function,
+            FITNESS_FUNCTIONS.join(", ")
+        )));
+    }
+
+    use unicorn::Arch::*;
+    match config.roper.arch {
+        X86 => check::<unicorn::CpuX86<'_>>(config, report),
+        ARM => check::<unicorn::CpuARM<'_>>(config, report),
+        ARM64 => check::<unicorn::CpuARM64<'_>>(config, report),
+        MIPS => check::<unicorn::CpuMIPS<'_>>(config, report),
+        SPARC => check::<unicorn::CpuSPARC<'_>>(config, report),
+        M68K => check::<unicorn::CpuM68K<'_>>(config, report),
+        arch => Err(Error::Misc(format!(
+            "The {:?} architecture is unimplemented",
+            arch
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unknown_registers() {
+        let names = vec!["RAX".to_string(), "XMM99".to_string(), "RIP".to_string()];
+        assert_eq!(
+            unknown_registers::<unicorn::CpuX86<'_>>(&names),
+            vec!["XMM99".to_string()]
+        );
+    }
+}
@@ -4,7 +4,7 @@ use hashbrown::HashSet;
 
 use crate::configure::Config;
 use crate::emulator::loader::get_static_memory_image;
-use crate::emulator::profiler::HasProfile;
+use crate::emulator::profiler::{FaultPhase, HasProfile, Profile};
 use crate::evolution::{Genome, Phenome};
 use crate::fitness::Weighted;
 use crate::ontogenesis::FitnessFn;
@@ -24,7 +24,7 @@ where
             }
         }
         let register_freq = stats::mean(scores.into_iter());
-        let mut fitness = Weighted::new(&config.fitness.weighting);
+        let mut fitness = config.fitness.weighted();
         fitness.insert("register_freq", register_freq);
         let gadgets_executed = profile.gadgets_executed.len();
         fitness.insert("gadgets_executed", gadgets_executed as f64);
@@ -46,7 +46,7 @@ where
     // for now, let's just handle the register pattern task
     if let Some(ref profile) = creature.profile() {
         let number_of_cases = profile.registers.len();
-        let mut fitness = Weighted::new(&config.fitness.weighting);
+        let mut fitness = config.fitness.weighted();
         // If the specimen doesn't report the right number of register states, then
         // something must have gone wrong in execution. Mark that specimen as a total
         // failure, and exit the function.
@@ -60,10 +60,20 @@ where
             return creature;
         }
         for (idx, pattern) in config.roper.register_patterns().iter().enumerate() {
-            let register_error = pattern.distance_from_register_state(&profile.registers[idx]);
-            let mut weighted_fitness = Weighted::new(&config.fitness.weighting);
+            let register_state = &profile.registers[idx];
+            let register_error = sketch
+                .behaviors
+                .register_errors
+                .get_or_compute(&(idx, register_state), || {
+                    pattern.distance_from_register_state(register_state)
+                });
+            let mut weighted_fitness = config.fitness.weighted();
             weighted_fitness.insert_or_add("register_error", register_error);
 
+            let similarity = pattern
+                .similarity_to_register_state(&sketch.register_signatures[idx], register_state);
+            weighted_fitness.insert_or_add("register_jaccard", 1.0 - similarity);
+
             // Calculate the novelty of register state errors
             let register_freq = stats::mean(
                 pattern
@@ -112,7 +122,7 @@ where
         if let Some(registers) = profile.registers.last() {
             let just_regs = registers.0.values().map(|v| v[0]).collect::<Vec<u64>>();
             let entropy = just_regs.entropy();
-            let mut weighted_fitness = Weighted::new(&config.fitness.weighting);
+            let mut weighted_fitness = config.fitness.weighted();
             weighted_fitness.insert("register_entropy", entropy);
             log::debug!("registers = {:x?}\n1/entropy = {}", just_regs, entropy);
 
@@ -148,7 +158,7 @@ where
             let score = conj.count_zeros() as f64;
             // ignore bits outside of the register's word size
             debug_assert!(score <= word_size as f64);
-            let mut weighted_fitness = Weighted::new(&config.fitness.weighting);
+            let mut weighted_fitness = config.fitness.weighted();
             weighted_fitness.insert("zeroes", score);
             weighted_fitness.insert("gadgets_executed", profile.gadgets_executed.len() as f64);
 
@@ -162,6 +172,22 @@ where
     creature
 }
 
+/// The occurrences among the profile's memory writes of each prefix of the
+/// pattern, from the shortest.
+fn memory_pattern_occurrences(profile: &Profile, pattern: &[u8]) -> Arc<Vec<f64>> {
+    let occurrences = (1..pattern.len() + 1)
+        .map(|i| {
+            let sub_pattern = &pattern[0..i];
+            profile
+                .memory_writes
+                .iter()
+                .map(|data| data.find_seq(sub_pattern).len())
+                .sum::<usize>() as f64
+        })
+        .collect::<Vec<f64>>();
+    Arc::new(occurrences)
+}
+
 pub fn memory_pattern_ff<C>(mut creature: C, sketch: &mut Sketches, config: Arc<Config>) -> C
 where
     C: HasProfile + Genome + Phenome<Fitness = Weighted<'static>> + Sized,
@@ -175,7 +201,7 @@ where
     ];
     // TODO: iterate through the different cases here, as above
     if let Some(profile) = creature.profile() {
-        let mut fitness = Weighted::new(&config.fitness.weighting);
+        let mut fitness = config.fitness.weighted();
 
         let pattern = config
             .roper
@@ -183,14 +209,14 @@ where
             .as_ref()
             .expect("No memory pattern provided");
 
-        for i in 1..pattern.len() + 1 {
-            let sub_pattern = &pattern[0..i];
-            let occurrences = profile
-                .memory_writes
-                .iter()
-                .map(|data| data.find_seq(sub_pattern).len())
-                .sum::<usize>() as f64;
-            fitness.insert_or_add(SUBPATTERN_LABELS[i - 1], occurrences);
+        let occurrences = sketch
+            .behaviors
+            .memory_pattern_occurrences
+            .get_or_compute(&profile.memory_writes, || {
+                memory_pattern_occurrences(profile, pattern)
+            });
+        for (i, occurrences) in occurrences.iter().enumerate() {
+            fitness.insert_or_add(SUBPATTERN_LABELS[i], *occurrences);
         }
 
         sketch.memory_writes.insert(&profile.memory_writes);
@@ -218,6 +244,19 @@ where
     creature
 }
 
+/// The addresses of the instructions executed along the profile's paths.
+fn addresses_visited(profile: &Profile) -> Arc<Vec<u64>> {
+    let mut addresses_visited = HashSet::new();
+    profile.execution_trace_iter().for_each(|path| {
+        for block in path {
+            for addr in block.entry..(block.entry + block.size as u64) {
+                addresses_visited.insert(addr);
+            }
+        }
+    });
+    Arc::new(addresses_visited.into_iter().collect())
+}
+
 pub fn code_coverage_ff<C>(mut creature: C, sketch: &mut Sketches, config: Arc<Config>) -> C
 where
     C: HasProfile + Genome + Phenome<Fitness = Weighted<'static>> + Sized,
@@ -227,15 +266,10 @@ where
     // }
 
     if let Some(ref profile) = creature.profile() {
-        let mut addresses_visited = HashSet::new();
-        // TODO: optimize this, maybe parallelize
-        profile.execution_trace_iter().for_each(|path| {
-            for block in path {
-                for addr in block.entry..(block.entry + block.size as u64) {
-                    addresses_visited.insert(addr);
-                }
-            }
-        });
+        let addresses_visited = sketch
+            .behaviors
+            .addresses_visited
+            .get_or_compute(&profile.paths, || addresses_visited(profile));
         let mut freq_score = 0.0;
         for addr in addresses_visited.iter() {
             sketch.addresses_visited.insert(*addr);
@@ -251,7 +285,7 @@ where
         let code_size = get_static_memory_image().size_of_executable_memory();
         let code_coverage = num_addr_visit / code_size as f64;
 
-        let mut fitness = Weighted::new(&config.fitness.weighting);
+        let mut fitness = config.fitness.weighted();
         fitness.insert("code_coverage", code_coverage);
         fitness.insert("code_freq", avg_freq);
 
@@ -264,11 +298,68 @@ where
     creature
 }
 
+/// Adds a `hit_watchpoint_<name>` entry to the creature's fitness for each
+/// configured watchpoint, counting the reads and writes that touched it. A
+/// failed evaluation is left as it is.
+pub fn watchpoint_ff<C>(mut creature: C, config: &Config) -> C
+where
+    C: HasProfile + Genome + Phenome<Fitness = Weighted<'static>> + Sized,
+{
+    if config.roper.watchpoints.is_empty() {
+        return creature;
+    }
+    if let (Some(profile), Some(fitness)) = (creature.profile(), creature.fitness()) {
+        if fitness.is_failure() {
+            return creature;
+        }
+        let mut fitness = fitness.clone();
+        for (index, watchpoint) in config.roper.watchpoints.iter().enumerate() {
+            let hits = profile.watchpoint_hit_count(index);
+            fitness.insert(watchpoint.key, hits as f64);
+        }
+        creature.set_fitness(fitness);
+    }
+    creature
+}
+
+/// Adds separate counts of the runs that faulted while dispatching to a
+/// gadget, faulted inside a gadget, or timed out, as `dispatch_faults`,
+/// `body_faults`, and `timeouts`. A failed evaluation is left as it is.
+pub fn fault_phase_ff<C>(mut creature: C) -> C
+where
+    C: HasProfile + Genome + Phenome<Fitness = Weighted<'static>> + Sized,
+{
+    if let (Some(profile), Some(fitness)) = (creature.profile(), creature.fitness()) {
+        if fitness.is_failure() {
+            return creature;
+        }
+        let mut fitness = fitness.clone();
+        fitness.insert(
+            "dispatch_faults",
+            profile.fault_count(FaultPhase::Dispatch) as f64,
+        );
+        fitness.insert("body_faults", profile.fault_count(FaultPhase::Body) as f64);
+        fitness.insert("timeouts", profile.fault_count(FaultPhase::Timeout) as f64);
+        creature.set_fitness(fitness);
+    }
+    creature
+}
+
+/// The names of the fitness functions `get_fitness_function` knows.
+pub const FITNESS_FUNCTIONS: [&str; 6] = [
+    "register_pattern",
+    "register_conjunction",
+    "register_entropy",
+    "code_coverage",
+    "memory_pattern",
+    "just_novelty",
+];
+
 pub fn get_fitness_function<C>(name: &str) -> FitnessFn<C, Sketches, Config>
 where
     C: HasProfile + Genome + Phenome<Fitness = Weighted<'static>> + Sized + 'static,
 {
-    match name {
+    let ff: FitnessFn<C, Sketches, Config> = match name {
         "register_pattern" => Box::new(register_pattern_ff),
         "register_conjunction" => Box::new(register_conjunction_ff),
         "register_entropy" => Box::new(register_entropy_ff),
@@ -276,5 +367,62 @@ where
         "memory_pattern" => Box::new(memory_pattern_ff),
         "just_novelty" => Box::new(just_novelty_ff),
         s => unimplemented!("No such fitness function as {}", s),
+    };
+    Box::new(move |creature, sketch, config| {
+        let creature = ff(creature, sketch, config.clone());
+        let creature = fault_phase_ff(creature);
+        watchpoint_ff(creature, &config)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::configure::Watchpoint;
+    use crate::emulator::profiler::Profile;
+    use crate::emulator::register_pattern::{RegisterPattern, RegisterValue};
+    use crate::evolution::{Genes, LinearChromosome};
+    use crate::roper::bare;
+
+    use super::*;
+
+    #[test]
+    fn test_failed_register_pattern_evaluation() {
+        let mut config = Config::default();
+        config.pop_size = 100;
+        config.fitness.weighting = "register_error + dispatch_faults".to_string();
+        let mut pattern = RegisterPattern(Default::default());
+        pattern.0.insert("RAX".to_string(), RegisterValue::from(1));
+        config.roper.parsed_register_patterns = vec![pattern];
+        config.roper.watchpoints = vec![Watchpoint {
+            name: "flag".to_string(),
+            address: 0x1000,
+            size: 8,
+            key: "hit_watchpoint_flag",
+        }];
+        let mut sketches = Sketches::new(&config);
+        // no register states, where one was expected
+        let creature = bare::Creature {
+            chromosome: LinearChromosome {
+                chromosome: vec![0x1000_u64].into(),
+                mutations: vec![None].into(),
+                parentage: Genes::default(),
+                parent_names: vec![],
+                name: "test".to_string(),
+                generation: 0,
+            },
+            tag: 0,
+            profile: Some(Profile::default()),
+            fitness: None,
+            front: None,
+            num_offspring: 0,
+            native_island: 0,
+            description: None,
+        };
+        let ff = get_fitness_function::<bare::Creature>("register_pattern");
+        let creature = ff(creature, &mut sketches, Arc::new(config));
+        let fitness = creature.fitness().expect("the failure should be scored");
+        assert!(fitness.is_failure());
+        assert!(fitness.scores.is_empty());
+        assert_eq!(fitness.scalar(), std::f64::MAX);
     }
 }
@@ -0,0 +1,116 @@
+use rand::seq::index;
+use unicorn::Cpu;
+
+use crate::configure::{ChainMode, JopConfig, RoperConfig};
+use crate::emulator::hatchery::hooking::indirect_branch_len;
+use crate::emulator::loader;
+use crate::emulator::register_pattern::Register;
+use crate::error::Error;
+use crate::util::random::hash_seed_rng;
+
+/// Scans the executable segments of the static memory image for gadgets that
+/// terminate in a jump or call through a register. An address is taken to be
+/// the start of a gadget if the bytes from that address up to and including the
+/// indirect branch disassemble cleanly, with the branch as the final instruction.
+///
+/// If `limit` is 0, every gadget found is returned. Otherwise, a random
+/// sample of `limit` of them is, drawn with the given seed, so that the
+/// soup isn't crowded into the lowest addresses of the binary.
+pub fn harvest_gadgets(jop: &JopConfig, limit: usize, seed: u64) -> Vec<u64> {
+    let memory = loader::get_static_memory_image();
+    let mut gadgets = Vec::new();
+    for seg in memory.segments().iter().filter(|s| s.is_executable()) {
+        let start = seg.aligned_start();
+        for offset in 0..seg.data.len() {
+            let branch_len = match indirect_branch_len(memory.arch, &seg.data[offset..]) {
+                Some(n) => n,
+                None => continue,
+            };
+            let branch_addr = start + offset as u64;
+            for back in 0..=jop.max_gadget_len.min(offset) {
+                let gadget_addr = branch_addr - back as u64;
+                let size = back + branch_len;
+                if let Some(insts) = memory.disassemble(gadget_addr, size, None) {
+                    let decoded = insts.iter().map(|i| i.bytes().len()).sum::<usize>();
+                    let ends_at_branch = insts
+                        .iter()
+                        .last()
+                        .map(|i| i.address() == branch_addr)
+                        .unwrap_or(false);
+                    if decoded == size && ends_at_branch {
+                        gadgets.push(gadget_addr);
+                    }
+                }
+            }
+        }
+    }
+    log::info!("Harvested {} JOP gadgets", gadgets.len());
+    if limit == 0 || gadgets.len() <= limit {
+        return gadgets;
+    }
+    let mut rng = hash_seed_rng(&seed);
+    let mut sample = index::sample(&mut rng, gadgets.len(), limit).into_vec();
+    sample.sort_unstable();
+    sample.into_iter().map(|i| gadgets[i]).collect()
+}
+
+fn is_register<C: 'static + Cpu<'static>>(name: &str) -> bool {
+    name.parse::<Register<C>>().is_ok()
+}
+
+/// Checks that JOP mode has a `[roper.jop]` section, that it targets x86,
+/// the only architecture whose indirect branches `indirect_branch_len`
+/// recognises, and that the registers it names are found on it.
+pub fn check_config(config: &RoperConfig) -> Result<(), Error> {
+    if config.chain_mode != ChainMode::Jop {
+        return Ok(());
+    }
+    let jop = config.jop.as_ref().ok_or_else(|| {
+        Error::Parsing("JOP mode requires a [roper.jop] section in the config".to_string())
+    })?;
+    // Elsewhere, no gadgets would be harvested, leaving the soup empty
+    if config.arch != unicorn::Arch::X86 {
+        return Err(Error::Parsing(format!(
+            "JOP mode is only supported on X86, not on {:?}",
+            config.arch
+        )));
+    }
+    for name in &[&jop.table_register, &jop.return_register] {
+        if !is_register::<unicorn::CpuX86<'static>>(name) {
+            return Err(Error::Parsing(format!(
+                "No such register as {} on {:?}, in [roper.jop]",
+                name, config.arch
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_config() {
+        let mut config = RoperConfig {
+            chain_mode: ChainMode::Jop,
+            arch: unicorn::Arch::X86,
+            mode: unicorn::Mode::MODE_64,
+            ..Default::default()
+        };
+        assert!(check_config(&config).is_err());
+        config.jop = Some(JopConfig {
+            dispatcher: 0x1000,
+            table_register: "RBX".to_string(),
+            return_register: "RDX".to_string(),
+            max_gadget_len: 8,
+        });
+        assert!(check_config(&config).is_ok());
+        config.jop.as_mut().unwrap().return_register = "XMM99".to_string();
+        assert!(check_config(&config).is_err());
+        config.jop.as_mut().unwrap().return_register = "RDX".to_string();
+        config.arch = unicorn::Arch::ARM;
+        config.mode = unicorn::Mode::MODE_32;
+        assert!(check_config(&config).is_err());
+    }
+}
@@ -1,25 +1,28 @@
-use std::fs::File;
 use std::hash::Hash;
 use std::hash::Hasher;
-use std::io::{BufRead, BufReader};
 use std::sync::Arc;
 use std::thread::spawn;
 
 use non_dominated_sort::DominanceOrd;
-use rand::Rng;
 use unicorn::Cpu;
 
-use crate::configure::{Config, Selection};
+use crate::checkpoint;
+use crate::configure::{BudgetPolicy, ChainMode, Config, Selection};
 use crate::error::Error;
 use crate::evolution::metropolis::Metropolis;
 use crate::evolution::pareto_roulette::Roulette;
 use crate::evolution::population::pier::Pier;
 use crate::fitness::Weighted;
+use crate::logger;
 use crate::observer::Observer;
 use crate::ontogenesis::FitnessFn;
+use crate::roper::behavior::BehaviorCache;
+use crate::roper::soup::{Provenance, Soup};
+use crate::util::affinity;
 use crate::util::architecture::Perms;
 use crate::util::count_min_sketch::CountMinSketch;
-use crate::util::random::hash_seed_rng;
+use crate::util::distance::Signature;
+use crate::util::random::seed_thread_rng;
 use crate::{
     emulator::loader,
     evolution::{tournament::Tournament, Phenome},
@@ -40,30 +43,56 @@ pub mod bare;
 /// A ROPER-specific implementation of Spector's PUSH VM.
 pub mod push;
 
+/// Support for jump-oriented programming chains.
+pub mod jop;
+
+/// Gadget pools annotated with provenance, which can be saved and merged
+/// across runs.
+pub mod soup;
+
+/// Clustering of the soup by the effects of its gadgets.
+pub mod clustering;
+
+/// Replaying dumped champions, with their execution traced.
+pub mod replay;
+
+/// Checking a config, without running the experiment it describes.
+pub mod dry_run;
+
+/// Memoization of the objectives computed from a specimen's behavior.
+pub mod behavior;
+
 /// load binary before calling this function
-pub fn init_soup(config: &mut Config) -> Result<(), Error> {
-    let mut soup = Vec::new();
+///
+/// Returns the soup with the provenance of each word recorded. The bare
+/// words are stored in `config.roper.soup`.
+pub fn init_soup(config: &mut Config) -> Result<Soup, Error> {
+    let mut soup = Soup::new();
     //might as well take the constants from the register pattern
     for pattern in config.roper.register_patterns() {
-        pattern
-            .0
-            .values()
-            .for_each(|w| w.vals.iter().for_each(|word| soup.push(*word)))
+        pattern.0.values().for_each(|w| {
+            soup.extend(
+                w.vals.iter().cloned(),
+                Provenance::Seeded,
+                "register_pattern",
+            )
+        })
     }
     if let Some(gadget_file) = config.roper.gadget_file.as_ref() {
-        // parse the gadget file
-        let reader = File::open(gadget_file).map(BufReader::new)?;
-
-        if gadget_file.ends_with(".json") {
-            log::info!("Deserializing soup from {}", gadget_file);
-            soup = serde_json::from_reader(reader)?;
-        } else {
-            log::info!("Parsing soup from {}", gadget_file);
-            for line in reader.lines() {
-                let word = line?.parse::<u64>()?;
-                soup.push(word)
-            }
-        }
+        log::info!("Loading soup from {}", gadget_file);
+        soup.merge(Soup::load(gadget_file)?);
+    } else if config.roper.chain_mode == ChainMode::Jop {
+        let jop = config
+            .roper
+            .jop
+            .as_ref()
+            .expect("The JOP config was checked when the config was loaded");
+        let limit = config.roper.soup_size.unwrap_or(0);
+        soup.extend(
+            jop::harvest_gadgets(jop, limit, config.random_seed),
+            Provenance::Harvested,
+            &config.roper.binary_path,
+        );
     } else if let Some(soup_size) = config.roper.soup_size.as_ref() {
         let memory = loader::get_static_memory_image();
         for addr in (0..(*soup_size)).map(|i| {
@@ -73,11 +102,19 @@ pub fn init_soup(config: &mut Config) -> Result<(), Error> {
             let seed = hasher.finish();
             memory.random_address(Some(Perms::EXEC), seed)
         }) {
-            soup.push(addr)
+            soup.insert(addr, Provenance::Harvested, &config.roper.binary_path)
         }
     }
-    config.roper.soup = Some(soup);
-    Ok(())
+    // Make the sigreturn frame templates available to the genome
+    for i in 0..config.roper.sigreturn_frames.len() {
+        soup.insert(
+            crate::emulator::srop::frame_gene(i),
+            Provenance::Seeded,
+            "sigreturn_frames",
+        )
+    }
+    config.roper.soup = Some(soup.words());
+    Ok(soup)
 }
 
 pub struct Sketches {
@@ -85,6 +122,10 @@ pub struct Sketches {
     pub memory_writes: CountMinSketch,
     pub genetic: CountMinSketch,
     pub addresses_visited: CountMinSketch,
+    pub push_trace: CountMinSketch,
+    pub behaviors: BehaviorCache,
+    /// The signatures of the register patterns, taken once
+    pub register_signatures: Vec<Signature>,
 }
 
 impl Sketches {
@@ -94,6 +135,14 @@ impl Sketches {
             memory_writes: CountMinSketch::new(config),
             addresses_visited: CountMinSketch::new(config),
             genetic: CountMinSketch::new(config),
+            push_trace: CountMinSketch::new(config),
+            behaviors: BehaviorCache::new(config),
+            register_signatures: config
+                .roper
+                .register_patterns()
+                .iter()
+                .map(|p| p.signature())
+                .collect(),
         }
     }
 }
@@ -143,47 +192,129 @@ impl DominanceOrd<&bare::Creature> for CreatureDominanceOrd {}
 
 impl DominanceOrd<&push::Creature> for CreatureDominanceOrd {}
 
-pub fn run(mut config: Config) {
-    let _ = loader::falcon_loader::load_from_path(&mut config, true)
-        .expect("Failed to load binary image");
-    config.roper.parse_register_patterns();
-    init_soup(&mut config).expect("Failed to initialize the soup");
+/// Loads each target, and gathers its soup, then launches the islands
+/// searching it, for its architecture. See `configure::TargetConfig`.
+pub fn run(config: Config) -> Result<(), Error> {
+    let mut handles = Vec::new();
+    for target in 0..=config.roper.targets.len() {
+        let islands = (0..config.num_islands)
+            .filter(|&i| config.roper.target_of(i) == target)
+            .collect::<Vec<usize>>();
+        if islands.is_empty() {
+            continue;
+        }
+        let mut config = config.clone();
+        config.roper = config.roper.for_target(target);
+        loader::set_target(target);
+        let _ = loader::falcon_loader::load_from_path(&mut config, true)?;
+        config.roper.parse_register_patterns()?;
+        config.roper.intern_watchpoint_keys();
+        config.apply_deterministic_mode();
+        let soup = init_soup(&mut config)?;
+        if !config.data_directory().is_empty() {
+            // alongside the config, above the island directories
+            let path = if target == 0 {
+                format!("{}/../initial.soup.json", config.data_directory())
+            } else {
+                format!(
+                    "{}/../initial.soup.target_{}.json",
+                    config.data_directory(),
+                    target
+                )
+            };
+            soup.save(&path)
+                .unwrap_or_else(|e| log::error!("Failed to save soup to {}: {:?}", path, e));
+        }
+
+        handles.push(spawn(move || {
+            loader::set_target(target);
+            use unicorn::Arch::*;
+            match config.roper.arch {
+                X86 => launch::<unicorn::CpuX86<'_>>(config, islands),
+                ARM => launch::<unicorn::CpuARM<'_>>(config, islands),
+                ARM64 => launch::<unicorn::CpuARM64<'_>>(config, islands),
+                MIPS => launch::<unicorn::CpuMIPS<'_>>(config, islands),
+                SPARC => launch::<unicorn::CpuSPARC<'_>>(config, islands),
+                M68K => launch::<unicorn::CpuM68K<'_>>(config, islands),
+                _ => unimplemented!("architecture unimplemented"),
+            }
+        }));
+    }
+    for h in handles.into_iter() {
+        h.join().expect("Failed to join thread");
+    }
+    Ok(())
+}
 
-    use unicorn::Arch::*;
-    match config.roper.arch {
-        X86 => launch::<unicorn::CpuX86<'_>>(config),
-        ARM => launch::<unicorn::CpuARM<'_>>(config),
-        ARM64 => launch::<unicorn::CpuARM64<'_>>(config),
-        MIPS => launch::<unicorn::CpuMIPS<'_>>(config),
-        SPARC => launch::<unicorn::CpuSPARC<'_>>(config),
-        M68K => launch::<unicorn::CpuM68K<'_>>(config),
-        _ => unimplemented!("architecture unimplemented"),
+/// Pins the calling thread to the island's cores, and notes the cores its
+/// emulators are to be pinned to, as `[affinity]` configures them.
+fn pin_island(config: &mut Config) {
+    if let Some(cores) = config.affinity.island_cores(config.island_id) {
+        match affinity::pin_current_thread(&cores) {
+            Ok(()) => log::info!("Pinned island {} to cores {:?}", config.island_id, cores),
+            Err(e) => log::warn!("{:?}", e),
+        }
     }
+    config.roper.emulator_cores = config.affinity.emulator_cores(config.island_id);
 }
 
-pub fn launch<C: 'static + Cpu<'static>>(config: Config) {
+/// Launches the islands given, which all search the target the config has
+/// been set up for, and so share a pier.
+pub fn launch<C: 'static + Cpu<'static>>(mut config: Config, islands: Vec<usize>) {
+    if config.roper.deterministic {
+        seed_thread_rng(&config.random_seed);
+    }
+    if config.roper.cluster_soup {
+        config.roper.soup_clusters =
+            Some(clustering::cluster_soup::<C>(&config).expect("Failed to cluster the soup"));
+    }
     match config.selection {
         Selection::Tournament => {
             // TODO: Refactor this!!
             let num_islands = config.num_islands;
+            crate::set_num_islands(num_islands);
             if config.roper.use_push {
                 let pier: Arc<Pier<push::Creature>> = Arc::new(Pier::new(config.num_islands));
                 let mut handles = Vec::new();
-                let mut rng = hash_seed_rng(&config.random_seed);
-                for i in 0..num_islands {
+                let seeds = config.island_seeds();
+                for &i in islands.iter() {
                     let mut config = config.clone();
                     config.island_id = i;
                     config.set_data_directory();
-                    config.random_seed = rng.gen::<u64>();
-                    let (observer, evaluator) = prepare_push(&config);
+                    config.random_seed = seeds[i];
                     let pier = pier.clone();
                     let h = spawn(move || {
-                        let mut world =
-                            Tournament::<push::evaluation::Evaluator<C>, push::Creature>::new(
-                                &config, observer, evaluator, pier,
-                            );
+                        logger::set_island(&config);
+                        loader::set_target(config.roper.target);
+                        // pinned before the island's evaluator and observer
+                        // are started, so that their threads inherit its cores
+                        pin_island(&mut config);
+                        let checkpoint = checkpoint::restore::<push::Creature>(&mut config);
+                        let (observer, evaluator) = prepare_push(&config);
+                        if config.roper.deterministic {
+                            seed_thread_rng(&config.random_seed);
+                        }
+                        let mut world = match checkpoint {
+                            Some(checkpoint) => {
+                                Tournament::<push::evaluation::Evaluator<C>, push::Creature>::resume(
+                                    &config, observer, evaluator, pier, checkpoint,
+                                )
+                            }
+                            None => {
+                                Tournament::<push::evaluation::Evaluator<C>, push::Creature>::new(
+                                    &config, observer, evaluator, pier,
+                                )
+                            }
+                        };
                         while crate::keep_going() {
-                            world = world.evolve();
+                            if !world.observer.has_spent_budget() {
+                                world = world.evolve();
+                                world.checkpoint();
+                            } else if config.budget_exhausted == BudgetPolicy::Idle {
+                                world.idle();
+                            } else {
+                                break;
+                            }
                         }
                     });
                     handles.push(h);
@@ -194,21 +325,45 @@ pub fn launch<C: 'static + Cpu<'static>>(config: Config) {
             } else {
                 let pier: Arc<Pier<bare::Creature>> = Arc::new(Pier::new(config.num_islands));
                 let mut handles = Vec::new();
-                let mut rng = hash_seed_rng(&config.random_seed);
-                for i in 0..num_islands {
+                let seeds = config.island_seeds();
+                for &i in islands.iter() {
                     let mut config = config.clone();
                     config.island_id = i;
                     config.set_data_directory();
-                    config.random_seed = rng.gen::<u64>();
-                    let (observer, evaluator) = prepare_bare(&config);
+                    config.random_seed = seeds[i];
                     let pier = pier.clone();
                     let h = spawn(move || {
-                        let mut world =
-                            Tournament::<bare::evaluation::Evaluator<C>, bare::Creature>::new(
-                                &config, observer, evaluator, pier,
-                            );
+                        logger::set_island(&config);
+                        loader::set_target(config.roper.target);
+                        // pinned before the island's evaluator and observer
+                        // are started, so that their threads inherit its cores
+                        pin_island(&mut config);
+                        let checkpoint = checkpoint::restore::<bare::Creature>(&mut config);
+                        let (observer, evaluator) = prepare_bare(&config);
+                        if config.roper.deterministic {
+                            seed_thread_rng(&config.random_seed);
+                        }
+                        let mut world = match checkpoint {
+                            Some(checkpoint) => {
+                                Tournament::<bare::evaluation::Evaluator<C>, bare::Creature>::resume(
+                                    &config, observer, evaluator, pier, checkpoint,
+                                )
+                            }
+                            None => {
+                                Tournament::<bare::evaluation::Evaluator<C>, bare::Creature>::new(
+                                    &config, observer, evaluator, pier,
+                                )
+                            }
+                        };
                         while crate::keep_going() {
-                            world = world.evolve();
+                            if !world.observer.has_spent_budget() {
+                                world = world.evolve();
+                                world.checkpoint();
+                            } else if config.budget_exhausted == BudgetPolicy::Idle {
+                                world.idle();
+                            } else {
+                                break;
+                            }
                         }
                     });
                     handles.push(h);
@@ -219,6 +374,7 @@ pub fn launch<C: 'static + Cpu<'static>>(config: Config) {
             }
         }
         Selection::Roulette => {
+            pin_island(&mut config);
             let (observer, evaluator) = prepare_bare(&config);
             let mut world = Roulette::<
                 bare::evaluation::Evaluator<C>,
@@ -230,6 +386,7 @@ pub fn launch<C: 'static + Cpu<'static>>(config: Config) {
             }
         }
         Selection::Metropolis => {
+            pin_island(&mut config);
             let (observer, evaluator) = prepare_bare(&config);
             let mut world = Metropolis::<bare::evaluation::Evaluator<C>, bare::Creature>::new(
                 &config, observer, evaluator,
@@ -2,17 +2,20 @@ use std::sync::Arc;
 
 use unicorn::Cpu;
 
-use crate::configure::Config;
+use crate::configure::{Config, PushVm};
 use crate::emulator::hatchery::Hatchery;
 use crate::emulator::profiler::{HasProfile, Profile};
 use crate::emulator::register_pattern::{Register, RegisterPattern};
 use crate::evolution::{Genome, Phenome};
-use crate::fitness::Weighted;
 use crate::ontogenesis::{Develop, FitnessFn};
+use crate::resources;
 use crate::roper::push;
-use crate::roper::push::{register_pattern_to_push_args, Creature, MachineState};
+use crate::roper::push::{
+    register_pattern_to_push_args, Creature, MachineState, PushStats, PushTrace,
+};
 use crate::roper::Sketches;
 use crate::util;
+use crate::util::count_min_sketch::CountMinSketch;
 
 pub struct Evaluator<C: Cpu<'static> + 'static> {
     config: Arc<Config>,
@@ -24,10 +27,13 @@ pub struct Evaluator<C: Cpu<'static> + 'static> {
 impl<C: 'static + Cpu<'static>> Evaluator<C> {
     pub fn spawn(config: &Config, fitness_fn: FitnessFn<Creature, Sketches, Config>) -> Self {
         let config = config.clone();
-        let hatch_config = Arc::new(config.roper.clone());
+        let mut hatch_config = config.roper.clone();
+        hatch_config.random_seed = config.random_seed;
+        let hatch_config = Arc::new(hatch_config);
         let output_registers: Vec<Register<C>> = config
             .roper
             .registers_to_check()
+            .expect("The registers were checked when the register patterns were parsed")
             .iter()
             .map(|r| r.parse().ok().expect("Failed to parse register"))
             .collect::<Vec<_>>();
@@ -55,14 +61,69 @@ impl<C: 'static + Cpu<'static>> Evaluator<C> {
     }
 }
 
+/// Runs the creature's push program on the problem, returning the payload
+/// it builds, along with the statistics and trace of the run.
 pub fn problem_to_payload(
     creature: &push::Creature,
     problem: &RegisterPattern,
-    steps: usize,
-) -> Vec<u64> {
+    params: &PushVm,
+) -> (Vec<u64>, PushStats, PushTrace) {
     let args = register_pattern_to_push_args(&problem);
-    let mut machine = MachineState::default();
-    machine.exec(creature.chromosome(), &args, steps)
+    let mut machine = if params.record_trace {
+        MachineState::with_trace(params.trace_snapshots)
+    } else {
+        MachineState::default()
+    };
+    let payload = machine.exec(creature.chromosome(), &args, params.max_steps);
+    let stats = machine.stats().clone();
+    (payload, stats, machine.take_trace())
+}
+
+/// Adds the chain-construction properties, which are computed from the
+/// payloads before emulation, to the creature's fitness, averaged over the
+/// payloads: `chain_length` and `gadget_diversity`.
+fn add_chain_properties(creature: &mut push::Creature) {
+    let n = creature.push_stats.len();
+    if n == 0 {
+        return;
+    }
+    // cloning the fitness also clears its cached scalar
+    if let Some(mut fitness) = creature.fitness.clone() {
+        let chain_length =
+            creature.push_stats.iter().map(|s| s.chain_len).sum::<usize>() as f64 / n as f64;
+        let gadget_diversity = creature
+            .push_stats
+            .iter()
+            .map(PushStats::gadget_diversity)
+            .sum::<f64>()
+            / n as f64;
+        fitness.insert("chain_length", chain_length);
+        fitness.insert("gadget_diversity", gadget_diversity);
+        creature.set_fitness(fitness);
+    }
+}
+
+/// Adds the frequency of the creature's push trace descriptors to its
+/// fitness, as `trace_freq`. Lower is more novel. This does nothing unless
+/// `push_vm.record_trace` is set.
+fn add_trace_novelty(creature: &mut push::Creature, sketch: &mut CountMinSketch) {
+    let descriptors = creature
+        .push_traces
+        .iter()
+        .filter(|t| !t.is_empty())
+        .map(PushTrace::descriptor)
+        .collect::<Vec<_>>();
+    if descriptors.is_empty() {
+        return;
+    }
+    if let Some(mut fitness) = creature.fitness.clone() {
+        let trace_freq = stats::mean(descriptors.iter().map(|d| {
+            sketch.insert(d);
+            sketch.query(d)
+        }));
+        fitness.insert("trace_freq", trace_freq);
+        creature.set_fitness(fitness);
+    }
 }
 
 impl<C: 'static + Cpu<'static>> Develop<push::Creature> for Evaluator<C> {
@@ -71,39 +132,49 @@ impl<C: 'static + Cpu<'static>> Develop<push::Creature> for Evaluator<C> {
         // for now, this doesn't matter -- we haven't defined any other kinds of tasks
         if creature.fitness.is_none() {
             let mut payloads = Vec::new();
+            creature.push_stats.clear();
+            creature.push_traces.clear();
             // TODO: Refactor and generalize to other problem types.
             for register_pattern in self.config.roper.register_patterns() {
-                let payload =
-                    problem_to_payload(&creature, register_pattern, self.config.push_vm.max_steps);
+                let (payload, stats, trace) =
+                    problem_to_payload(&creature, register_pattern, &self.config.push_vm);
                 payloads.push(payload);
+                creature.push_stats.push(stats);
+                creature.push_traces.push(trace);
             }
 
             // TODO refactor bare roper in a similar fashion. just send the payload,
             // not the whole creature.
-            let mut used_payloads = Vec::new();
-            for payload in payloads.into_iter() {
-                if !payload.is_empty() {
-                    let profile = self
-                        .hatchery
-                        .execute(payload.clone(), None)
-                        .expect("Failed to evaluate creature");
-                    creature.add_profile(profile);
-                    used_payloads.push(payload);
-                } else {
-                    // this will mark the profile as non-executable
-                    let profile = Profile::default();
-                    debug_assert!(!profile.executable);
-                    used_payloads.push(payload);
-                    creature.payloads = used_payloads;
-                    creature.add_profile(profile);
-                    return creature;
-                }
+            // An empty payload marks the creature as non-executable, so there's
+            // no point running anything after it.
+            let first_empty = payloads.iter().position(|p| p.is_empty());
+            let runnable = first_empty.unwrap_or(payloads.len());
+            let mut used_payloads = payloads;
+            used_payloads.truncate(runnable + 1);
+            let profiles = self
+                .hatchery
+                .execute_batch(used_payloads[0..runnable].to_vec(), None)
+                .expect("Failed to evaluate creature");
+            let emulation_time = profiles.iter().map(Profile::total_emulation_time).sum();
+            resources::record_emulation(self.config.island_id, emulation_time);
+            for profile in profiles.into_iter() {
+                creature.add_profile(profile);
+            }
+            if first_empty.is_some() {
+                // this will mark the profile as non-executable
+                let profile = Profile::default();
+                debug_assert!(!profile.executable);
+                creature.payloads = used_payloads;
+                creature.add_profile(profile);
+                return creature;
             }
             creature.payloads = used_payloads;
             log::debug!(
                 "Finished developing creature. profile: {:#x?}",
                 creature.profile
             );
+        } else {
+            resources::record_cache_hit(self.config.island_id);
         }
         creature
     }
@@ -113,12 +184,16 @@ impl<C: 'static + Cpu<'static>> Develop<push::Creature> for Evaluator<C> {
             .profile()
             .expect("Attempted to apply fitness function to undeveloped creature");
         if !profile.executable {
-            let mut fitness = Weighted::new(&self.config.fitness.weighting);
+            let mut fitness = self.config.fitness.weighted();
             fitness.declare_failure();
             creature.set_fitness(fitness);
             creature
         } else {
-            (self.fitness_fn)(creature, &mut self.sketches, self.config.clone())
+            let mut creature =
+                (self.fitness_fn)(creature, &mut self.sketches, self.config.clone());
+            add_chain_properties(&mut creature);
+            add_trace_novelty(&mut creature, &mut self.sketches.push_trace);
+            creature
         }
     }
 
@@ -1,19 +1,22 @@
+use std::collections::BTreeMap;
 use std::fmt;
 
 use falcon::il;
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use itertools::Itertools;
+use rand::distributions::{Distribution, WeightedIndex};
 use rand::prelude::SliceRandom;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 pub use creature::Creature;
 
-use crate::configure::Config;
+use crate::configure::{ArithmeticConfig, Config};
 use crate::emulator::loader;
 use crate::emulator::loader::get_static_memory_image;
 use crate::emulator::register_pattern::RegisterPattern;
 use crate::util::architecture::{read_integer, write_integer, Perms};
+use crate::util::arithmetic::{self, Guard};
 
 pub mod evaluation;
 
@@ -103,9 +106,31 @@ pub enum Op {
     FloatTanh,
     FloatToWord,
     FloatLess,
+    FloatAdd,
+    FloatSub,
+    FloatMul,
+    FloatDiv,
+
+    // Signed integers, as distinct from words, which are mostly addresses
+    IntConst(i64),
+    IntAdd,
+    IntSub,
+    IntMul,
+    IntDiv,
+    IntMod,
+    IntNeg,
+    IntLess,
+    IntToWord,
+    WordToInt,
+    IntToFloat,
+    FloatToInt,
+    IntToBool,
+    BoolToInt,
 
     CodeQuote,
     CodeDoAll,
+    CodeDo,
+    CodeIf,
 
     // Falcon Instructions
     InstIsBranch,
@@ -133,8 +158,14 @@ pub enum Op {
     ExecK,
     ExecS,
     ExecY,
+    ExecIfElse,
+    ExecWhile,
+    ExecDoTimes,
+    ExecDoRange,
 
     Nop,
+    /// Pushes a copy of an input, indexed modulo the number of inputs
+    Input(usize),
 
     Eq(Type),
     Rot(Type),
@@ -145,7 +176,7 @@ pub enum Op {
     List(Vec<Op>),
 }
 
-static NON_CONSTANT_OPS: [Op; 109] = [
+static NON_CONSTANT_OPS: [Op; 149] = [
     Op::BoolAnd,
     Op::BoolOr,
     Op::BoolNot,
@@ -186,15 +217,34 @@ static NON_CONSTANT_OPS: [Op; 109] = [
     // Op::BufTail,
     // Op::BufSearch,
     // floats need to be encoded as u64 to preserve Op::Hash
-    // Op::FloatLog,
-    // Op::FloatSin,
-    // Op::FloatCos,
-    // Op::FloatTan,
-    // Op::FloatTanh,
-    // Op::FloatToWord,
-    // Op::FloatLess,
+    Op::FloatLog,
+    Op::FloatSin,
+    Op::FloatCos,
+    Op::FloatTan,
+    Op::FloatTanh,
+    Op::FloatToWord,
+    Op::FloatLess,
+    Op::FloatAdd,
+    Op::FloatSub,
+    Op::FloatMul,
+    Op::FloatDiv,
+    Op::IntAdd,
+    Op::IntSub,
+    Op::IntMul,
+    Op::IntDiv,
+    Op::IntMod,
+    Op::IntNeg,
+    Op::IntLess,
+    Op::IntToWord,
+    Op::WordToInt,
+    Op::IntToFloat,
+    Op::FloatToInt,
+    Op::IntToBool,
+    Op::BoolToInt,
     Op::CodeQuote,
     Op::CodeDoAll,
+    Op::CodeDo,
+    Op::CodeIf,
     Op::InstIsBranch,
     Op::InstIsStore,
     Op::InstIsLoad,
@@ -223,6 +273,10 @@ static NON_CONSTANT_OPS: [Op; 109] = [
     Op::ExecK,
     Op::ExecS,
     Op::ExecY,
+    Op::ExecIfElse,
+    Op::ExecWhile,
+    Op::ExecDoTimes,
+    Op::ExecDoRange,
     Op::Nop,
     // Generics
     Op::Eq(Type::Word),
@@ -245,11 +299,16 @@ static NON_CONSTANT_OPS: [Op; 109] = [
     Op::Swap(Type::Scalar),
     Op::Drop(Type::Scalar),
     Op::Dup(Type::Scalar),
-    // Op::Eq(Type::Float),
-    // Op::Rot(Type::Float),
-    // Op::Swap(Type::Float),
-    // Op::Drop(Type::Float),
-    // Op::Dup(Type::Float),
+    Op::Eq(Type::Float),
+    Op::Rot(Type::Float),
+    Op::Swap(Type::Float),
+    Op::Drop(Type::Float),
+    Op::Dup(Type::Float),
+    Op::Eq(Type::Int),
+    Op::Rot(Type::Int),
+    Op::Swap(Type::Int),
+    Op::Drop(Type::Int),
+    Op::Dup(Type::Int),
     Op::Eq(Type::Expression),
     Op::Rot(Type::Expression),
     Op::Swap(Type::Expression),
@@ -287,6 +346,137 @@ static NON_CONSTANT_OPS: [Op; 109] = [
     Op::Dup(Type::Code),
 ];
 
+/// The ops that touch neither the emulator nor the memory image, for push
+/// programs that work on problems of their own, like symbolic regression.
+pub static NUMERIC_OPS: [Op; 60] = [
+    Op::BoolAnd,
+    Op::BoolOr,
+    Op::BoolNot,
+    Op::FloatLog,
+    Op::FloatSin,
+    Op::FloatCos,
+    Op::FloatTan,
+    Op::FloatTanh,
+    Op::FloatLess,
+    Op::FloatAdd,
+    Op::FloatSub,
+    Op::FloatMul,
+    Op::FloatDiv,
+    Op::IntAdd,
+    Op::IntSub,
+    Op::IntMul,
+    Op::IntDiv,
+    Op::IntMod,
+    Op::IntNeg,
+    Op::IntLess,
+    Op::IntToFloat,
+    Op::FloatToInt,
+    Op::IntToBool,
+    Op::BoolToInt,
+    Op::CodeQuote,
+    Op::CodeDo,
+    Op::CodeIf,
+    Op::ExecIf,
+    Op::ExecIfElse,
+    Op::ExecWhile,
+    Op::ExecDoTimes,
+    Op::ExecDoRange,
+    Op::ExecK,
+    Op::ExecS,
+    Op::ExecY,
+    Op::Eq(Type::Float),
+    Op::Rot(Type::Float),
+    Op::Swap(Type::Float),
+    Op::Drop(Type::Float),
+    Op::Dup(Type::Float),
+    Op::Eq(Type::Int),
+    Op::Rot(Type::Int),
+    Op::Swap(Type::Int),
+    Op::Drop(Type::Int),
+    Op::Dup(Type::Int),
+    Op::Eq(Type::Bool),
+    Op::Rot(Type::Bool),
+    Op::Swap(Type::Bool),
+    Op::Drop(Type::Bool),
+    Op::Dup(Type::Bool),
+    Op::Eq(Type::Exec),
+    Op::Rot(Type::Exec),
+    Op::Swap(Type::Exec),
+    Op::Drop(Type::Exec),
+    Op::Eq(Type::Code),
+    Op::Rot(Type::Code),
+    Op::Swap(Type::Code),
+    Op::Drop(Type::Code),
+    Op::Dup(Type::Code),
+    Op::Nop,
+];
+
+/// The ops for boolean problems, whose inputs are read with `Op::Input`.
+pub static BOOLEAN_OPS: [Op; 13] = [
+    Op::BoolAnd,
+    Op::BoolOr,
+    Op::BoolNot,
+    Op::ExecIf,
+    Op::ExecIfElse,
+    Op::ExecK,
+    Op::ExecS,
+    Op::Eq(Type::Bool),
+    Op::Rot(Type::Bool),
+    Op::Swap(Type::Bool),
+    Op::Drop(Type::Bool),
+    Op::Dup(Type::Bool),
+    Op::Nop,
+];
+
+/// Draws an op from `BOOLEAN_OPS`, or, at `literal_rate`, an input.
+pub fn random_boolean_op<R: Rng + ?Sized>(
+    rng: &mut R,
+    num_inputs: usize,
+    literal_rate: f64,
+) -> Op {
+    if rng.gen_range(0.0, 1.0) < literal_rate {
+        Op::Input(rng.gen_range(0, num_inputs.max(1)))
+    } else {
+        BOOLEAN_OPS
+            .choose(rng)
+            .expect("Failed to choose random op")
+            .clone()
+    }
+}
+
+/// Draws an op from `NUMERIC_OPS`, or, at `literal_rate`, a small float or
+/// int literal.
+pub fn random_numeric_op<R: Rng + ?Sized>(rng: &mut R, literal_rate: f64) -> Op {
+    if rng.gen_range(0.0, 1.0) < literal_rate {
+        if rng.gen() {
+            Op::FloatConst(rng.gen_range(-1.0_f64, 1.0).to_bits())
+        } else {
+            Op::IntConst(rng.gen_range(-10, 10))
+        }
+    } else {
+        NUMERIC_OPS
+            .choose(rng)
+            .expect("Failed to choose random op")
+            .clone()
+    }
+}
+
+/// Perturbs an int or float literal, at the configured rate, returning
+/// whether it did.
+pub fn mutate_constant<R: Rng>(op: &mut Op, config: &ArithmeticConfig, rng: &mut R) -> bool {
+    match op {
+        Op::IntConst(n) if arithmetic::mutates_constant(config, rng) => {
+            *n = arithmetic::perturb_int(config, rng, *n);
+            true
+        }
+        Op::FloatConst(bits) if arithmetic::mutates_constant(config, rng) => {
+            *bits = arithmetic::perturb_float(config, rng, f64::from_bits(*bits)).to_bits();
+            true
+        }
+        _ => false,
+    }
+}
+
 fn random_ops<R: Rng>(rng: &mut R, config: &Config) -> Vec<Op> {
     let mut ops = Vec::new();
 
@@ -300,9 +490,19 @@ fn random_ops<R: Rng>(rng: &mut R, config: &Config) -> Vec<Op> {
         .map(il::Function::name)
         .collect();
 
+    let rates = &config.push_vm.literal_rates;
+    let literal_types = WeightedIndex::new(&[
+        rates.function,
+        rates.word,
+        rates.int,
+        rates.float,
+        rates.bool,
+    ])
+    .expect("The literal_rates were checked when the config was loaded");
+
     for _ in 0..count {
         if rng.gen_range(0.0, 1.0) < config.push_vm.literal_rate {
-            match rng.gen_range(0, 2) {
+            match literal_types.sample(rng) {
                 0 => {
                     // functions
                     let f = function_names
@@ -313,19 +513,13 @@ fn random_ops<R: Rng>(rng: &mut R, config: &Config) -> Vec<Op> {
                 }
                 1 => {
                     // addresses
-                    let addr = config
-                        .roper
-                        .soup
-                        .as_ref()
-                        .expect("No soup?!")
-                        .choose(rng)
+                    let addr = crate::roper::soup::sample_word(&config.roper, rng)
                         .expect("Failed to choose word from soup.");
-                    ops.push(Op::WordConst(*addr))
+                    ops.push(Op::WordConst(addr))
                 }
-                // 3 => {
-                //     // float
-                //     ops.push(Op::FloatConst(rng.gen::<f64>().to_bits()))
-                // }
+                2 => ops.push(Op::IntConst(rng.gen_range(-0x100, 0x100))),
+                3 => ops.push(Op::FloatConst(rng.gen::<f64>().to_bits())),
+                4 => ops.push(Op::BoolConst(rng.gen())),
                 _ => unreachable!("nope"),
             };
         } else {
@@ -347,6 +541,12 @@ impl Op {
         use Val::*;
         match self {
             Nop => {}
+            Input(i) => {
+                if !mach.inputs.is_empty() {
+                    let val = mach.inputs[i % mach.inputs.len()].clone();
+                    mach.push(val)
+                }
+            }
             // Code manipulation
             List(ref ops) => {
                 for op in ops {
@@ -393,6 +593,22 @@ impl Op {
                     mach.push(Exec(op))
                 }
             }
+            CodeDo => {
+                if let Code(op) = mach.pop(&Type::Code) {
+                    mach.push(Exec(op))
+                }
+            }
+            CodeIf => {
+                // Execute the first of the top two Code items if the top of the
+                // Bool stack is `true`, and the second if it's `false`.
+                if let (Bool(b), Code(a), Code(c)) = (
+                    mach.pop(&Type::Bool),
+                    mach.pop(&Type::Code),
+                    mach.pop(&Type::Code),
+                ) {
+                    mach.push(Exec(if b { a } else { c }))
+                }
+            }
             ExecIf => {
                 // If the top of the Bool stack is `false`, then skip the next
                 // instruction in the Exec stack.
@@ -400,6 +616,64 @@ impl Op {
                     let _ = mach.pop(&Type::Exec);
                 }
             }
+            ExecIfElse => {
+                // Keep the first of the next two instructions in the Exec
+                // stack if the top of the Bool stack is `true`, and the second
+                // if it's `false`.
+                if let (Bool(b), Exec(a), Exec(c)) = (
+                    mach.pop(&Type::Bool),
+                    mach.pop(&Type::Exec),
+                    mach.pop(&Type::Exec),
+                ) {
+                    mach.push(Exec(if b { a } else { c }))
+                }
+            }
+            ExecWhile => {
+                // Run the next instruction for as long as it leaves `true`
+                // on top of the Bool stack.
+                // On `false`, the body is dropped.
+                if let (Bool(true), Exec(body)) = (mach.pop(&Type::Bool), mach.pop(&Type::Exec)) {
+                    mach.push(Exec(List(vec![body.clone(), ExecWhile])));
+                    mach.push(Exec(body));
+                }
+            }
+            // Loops are unrolled one iteration at a time, so each iteration
+            // is charged against the step limit as it runs.
+            ExecDoTimes => {
+                if let (Int(n), Exec(body)) = (mach.pop(&Type::Int), mach.pop(&Type::Exec)) {
+                    if n > 1 {
+                        mach.push(Exec(List(vec![
+                            body.clone(),
+                            ExecDoTimes,
+                            IntConst(n - 1),
+                        ])));
+                    }
+                    if n > 0 {
+                        mach.push(Exec(body));
+                    }
+                }
+            }
+            ExecDoRange => {
+                // Counts from the second Int to the top Int, inclusive,
+                // pushing the index to the Int stack before each iteration.
+                if let (Int(dest), Int(start), Exec(body)) = (
+                    mach.pop(&Type::Int),
+                    mach.pop(&Type::Int),
+                    mach.pop(&Type::Exec),
+                ) {
+                    if start != dest {
+                        let next = if start < dest { start + 1 } else { start - 1 };
+                        mach.push(Exec(List(vec![
+                            body.clone(),
+                            ExecDoRange,
+                            IntConst(dest),
+                            IntConst(next),
+                        ])));
+                    }
+                    mach.push(Int(start));
+                    mach.push(Exec(body));
+                }
+            }
 
             // Generic Operations
             Eq(t) => {
@@ -706,12 +980,14 @@ impl Op {
             }
             WordAdd => {
                 if let (Word(a), Word(b)) = (mach.pop(&Type::Word), mach.pop(&Type::Word)) {
-                    mach.push(Word(a.wrapping_add(b)))
+                    let n = mach.guard.add(a, b);
+                    mach.push(Word(n))
                 }
             }
             WordSub => {
                 if let (Word(a), Word(b)) = (mach.pop(&Type::Word), mach.pop(&Type::Word)) {
-                    mach.push(Word(a.wrapping_sub(b)))
+                    let n = mach.guard.sub(a, b);
+                    mach.push(Word(n))
                 }
             }
             WordAnd => {
@@ -731,20 +1007,21 @@ impl Op {
             }
             WordMul => {
                 if let (Word(a), Word(b)) = (mach.pop(&Type::Word), mach.pop(&Type::Word)) {
-                    mach.push(Word(a.wrapping_mul(b)))
+                    let n = mach.guard.mul(a, b);
+                    mach.push(Word(n))
                 }
             }
             WordDiv => {
                 if let (Word(a), Word(b)) = (mach.pop(&Type::Word), mach.pop(&Type::Word)) {
-                    if b != 0 {
-                        mach.push(Word(a / b))
+                    if let Some(n) = mach.guard.div(a, b) {
+                        mach.push(Word(n))
                     }
                 }
             }
             WordMod => {
                 if let (Word(a), Word(b)) = (mach.pop(&Type::Word), mach.pop(&Type::Word)) {
-                    if b != 0 {
-                        mach.push(Word(a % b))
+                    if let Some(n) = mach.guard.rem(a, b) {
+                        mach.push(Word(n))
                     }
                 }
             }
@@ -806,27 +1083,32 @@ impl Op {
             }
             FloatLog => {
                 if let Float(a) = mach.pop(&Type::Float) {
-                    mach.push(Float(f64::from_bits(a).ln().to_bits()))
+                    let x = mach.guard.float(f64::from_bits(a).ln());
+                    mach.push(Float(x.to_bits()))
                 }
             }
             FloatCos => {
                 if let Float(a) = mach.pop(&Type::Float) {
-                    mach.push(Float(f64::from_bits(a).cos().to_bits()))
+                    let x = mach.guard.float(f64::from_bits(a).cos());
+                    mach.push(Float(x.to_bits()))
                 }
             }
             FloatSin => {
                 if let Float(a) = mach.pop(&Type::Float) {
-                    mach.push(Float(f64::from_bits(a).sin().to_bits()))
+                    let x = mach.guard.float(f64::from_bits(a).sin());
+                    mach.push(Float(x.to_bits()))
                 }
             }
             FloatTanh => {
                 if let Float(a) = mach.pop(&Type::Float) {
-                    mach.push(Float(f64::from_bits(a).tanh().to_bits()))
+                    let x = mach.guard.float(f64::from_bits(a).tanh());
+                    mach.push(Float(x.to_bits()))
                 }
             }
             FloatTan => {
                 if let Float(a) = mach.pop(&Type::Float) {
-                    mach.push(Float(f64::from_bits(a).tan().to_bits()))
+                    let x = mach.guard.float(f64::from_bits(a).tan());
+                    mach.push(Float(x.to_bits()))
                 }
             }
             FloatConst(n) => mach.push(Float(*n)),
@@ -843,6 +1125,106 @@ impl Op {
                     mach.push(Word(f64::from_bits(a) as u64))
                 }
             }
+            FloatAdd => {
+                if let (Float(a), Float(b)) = (mach.pop(&Type::Float), mach.pop(&Type::Float)) {
+                    let x = mach.guard.float(f64::from_bits(a) + f64::from_bits(b));
+                    mach.push(Float(x.to_bits()))
+                }
+            }
+            FloatSub => {
+                if let (Float(a), Float(b)) = (mach.pop(&Type::Float), mach.pop(&Type::Float)) {
+                    let x = mach.guard.float(f64::from_bits(a) - f64::from_bits(b));
+                    mach.push(Float(x.to_bits()))
+                }
+            }
+            FloatMul => {
+                if let (Float(a), Float(b)) = (mach.pop(&Type::Float), mach.pop(&Type::Float)) {
+                    let x = mach.guard.float(f64::from_bits(a) * f64::from_bits(b));
+                    mach.push(Float(x.to_bits()))
+                }
+            }
+            FloatDiv => {
+                if let (Float(a), Float(b)) = (mach.pop(&Type::Float), mach.pop(&Type::Float)) {
+                    if let Some(x) = mach.guard.fdiv(f64::from_bits(a), f64::from_bits(b)) {
+                        mach.push(Float(x.to_bits()))
+                    }
+                }
+            }
+
+            // Integer Operations
+            IntConst(n) => mach.push(Int(*n)),
+            IntAdd => {
+                if let (Int(a), Int(b)) = (mach.pop(&Type::Int), mach.pop(&Type::Int)) {
+                    let n = mach.guard.add(a, b);
+                    mach.push(Int(n))
+                }
+            }
+            IntSub => {
+                if let (Int(a), Int(b)) = (mach.pop(&Type::Int), mach.pop(&Type::Int)) {
+                    let n = mach.guard.sub(a, b);
+                    mach.push(Int(n))
+                }
+            }
+            IntMul => {
+                if let (Int(a), Int(b)) = (mach.pop(&Type::Int), mach.pop(&Type::Int)) {
+                    let n = mach.guard.mul(a, b);
+                    mach.push(Int(n))
+                }
+            }
+            IntDiv => {
+                if let (Int(a), Int(b)) = (mach.pop(&Type::Int), mach.pop(&Type::Int)) {
+                    if let Some(n) = mach.guard.div(a, b) {
+                        mach.push(Int(n))
+                    }
+                }
+            }
+            IntMod => {
+                if let (Int(a), Int(b)) = (mach.pop(&Type::Int), mach.pop(&Type::Int)) {
+                    if let Some(n) = mach.guard.rem(a, b) {
+                        mach.push(Int(n))
+                    }
+                }
+            }
+            IntNeg => {
+                if let Int(a) = mach.pop(&Type::Int) {
+                    mach.push(Int(a.wrapping_neg()))
+                }
+            }
+            IntLess => {
+                if let (Int(a), Int(b)) = (mach.pop(&Type::Int), mach.pop(&Type::Int)) {
+                    mach.push(Bool(a < b))
+                }
+            }
+            IntToWord => {
+                if let Int(a) = mach.pop(&Type::Int) {
+                    mach.push(Word(a as u64))
+                }
+            }
+            WordToInt => {
+                if let Word(a) = mach.pop(&Type::Word) {
+                    mach.push(Int(a as i64))
+                }
+            }
+            IntToFloat => {
+                if let Int(a) = mach.pop(&Type::Int) {
+                    mach.push(Float((a as f64).to_bits()))
+                }
+            }
+            FloatToInt => {
+                if let Float(a) = mach.pop(&Type::Float) {
+                    mach.push(Int(f64::from_bits(a) as i64))
+                }
+            }
+            IntToBool => {
+                if let Int(a) = mach.pop(&Type::Int) {
+                    mach.push(Bool(a != 0))
+                }
+            }
+            BoolToInt => {
+                if let Bool(a) = mach.pop(&Type::Bool) {
+                    mach.push(Int(a as i64))
+                }
+            }
 
             WordToFloat => {
                 if let Word(a) = mach.pop(&Type::Word) {
@@ -1071,6 +1453,7 @@ impl Op {
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Type {
     Word,
+    Int,
     Buf,
     // Not using byte slices yet. but we could try later.
     Bool,
@@ -1093,6 +1476,7 @@ impl From<&Val> for Type {
     fn from(v: &Val) -> Self {
         match v {
             Val::Word(_) => Type::Word,
+            Val::Int(_) => Type::Int,
             Val::Gadget(_) => Type::Gadget,
             Val::Buf(_) => Type::Buf,
             Val::Bool(_) => Type::Bool,
@@ -1114,6 +1498,7 @@ impl From<&Val> for Type {
 pub enum Val {
     Null,
     Word(u64),
+    Int(i64),
     Buf(Buffer),
     Bool(bool),
     Exec(Op),
@@ -1135,6 +1520,7 @@ impl fmt::Debug for Val {
         match self {
             Null => write!(f, "Null"),
             Word(w) => write!(f, "Word(0x{:x})", w),
+            Int(n) => write!(f, "Int({})", n),
             Buf(b) => {
                 write!(f, "Buf({:x?})", b)
                 // let i = 16.min(b.len());
@@ -1167,15 +1553,133 @@ impl Val {
     }
 }
 
+/// Statistics describing a single run of a push program, and the chain it
+/// produced. These are available before the chain is emulated.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PushStats {
+    pub program_len: usize,
+    pub steps: usize,
+    /// True if the program was cut off by the step limit.
+    pub exhausted: bool,
+    /// The depth of each stack when the program halted.
+    pub stack_depths: BTreeMap<String, usize>,
+    pub chain_len: usize,
+    /// The number of distinct executable addresses in the chain.
+    pub distinct_gadgets: usize,
+}
+
+impl PushStats {
+    pub fn gadget_diversity(&self) -> f64 {
+        if self.chain_len == 0 {
+            0.0
+        } else {
+            self.distinct_gadgets as f64 / self.chain_len as f64
+        }
+    }
+}
+
+/// The state of the stacks at some step of a push run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Hash, PartialEq, Eq)]
+pub struct StackSnapshot {
+    pub step: usize,
+    /// The depth of each non-empty stack.
+    pub depths: BTreeMap<String, usize>,
+    /// The top item of each non-empty stack.
+    pub tops: BTreeMap<String, String>,
+}
+
+/// The ops executed in a push run, in order, along with a bounded number of
+/// snapshots of the stacks taken along the way.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PushTrace {
+    pub ops: Vec<Op>,
+    pub snapshots: Vec<StackSnapshot>,
+}
+
+impl PushTrace {
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// A behaviour descriptor for novelty measures: how often each kind of
+    /// op was executed, rounded up to a power of two, so that programs
+    /// differing only in their literals or in a few steps look alike.
+    pub fn descriptor(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for op in &self.ops {
+            let name = format!("{:?}", op);
+            let kind = name.split('(').next().unwrap_or("").to_string();
+            *counts.entry(kind).or_insert(0) += 1;
+        }
+        counts
+            .into_iter()
+            .map(|(k, n): (String, usize)| (k, n.next_power_of_two()))
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct MachineState {
     stacks: HashMap<Type, Stack<Val>>,
     counter: usize,
+    record_trace: bool,
+    max_snapshots: usize,
+    trace: PushTrace,
+    stats: PushStats,
+    inputs: Vec<Val>,
+    guard: Guard,
 }
 
 // TODO try optimizing by getting rid of the hashmap in favour of just
 // using struct fields
 impl MachineState {
+    /// Builds a machine that records the ops it executes, along with up to
+    /// `max_snapshots` snapshots of its stacks.
+    pub fn with_trace(max_snapshots: usize) -> Self {
+        Self {
+            record_trace: true,
+            max_snapshots,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the handling of arithmetic faults.
+    pub fn with_arithmetic(mut self, config: &ArithmeticConfig) -> Self {
+        self.guard = Guard::new(config);
+        self
+    }
+
+    /// The number of arithmetic faults in the most recent run.
+    pub fn faults(&self) -> usize {
+        self.guard.faults()
+    }
+
+    /// Statistics for the most recent call to `exec`.
+    pub fn stats(&self) -> &PushStats {
+        &self.stats
+    }
+
+    /// Takes the trace of ops executed in the most recent call to `exec`.
+    /// This will be empty unless the machine was built `with_trace`.
+    pub fn take_trace(&mut self) -> PushTrace {
+        std::mem::replace(&mut self.trace, PushTrace::default())
+    }
+
+    fn snapshot(&self) -> StackSnapshot {
+        let mut snapshot = StackSnapshot {
+            step: self.counter,
+            ..Default::default()
+        };
+        for (t, s) in self.stacks.iter() {
+            if let Some(top) = s.last() {
+                let t = format!("{:?}", t);
+                snapshot.depths.insert(t.clone(), s.len());
+                snapshot.tops.insert(t, format!("{:x?}", top));
+            }
+        }
+        snapshot
+    }
+
     pub fn load_args(&mut self, args: &[Val]) {
         for arg in args {
             self.push(arg.clone())
@@ -1194,9 +1698,12 @@ impl MachineState {
         self.stacks.insert(Type::Function, vec![]);
         self.stacks.insert(Type::Gadget, vec![]);
         self.stacks.insert(Type::Instruction, vec![]);
+        self.stacks.insert(Type::Int, vec![]);
         self.stacks.insert(Type::Scalar, vec![]);
         self.stacks.insert(Type::Word, vec![]);
         self.counter = 0;
+        self.trace = PushTrace::default();
+        self.guard.reset();
     }
 
     // the only reason for using Val::Null is to make the code
@@ -1220,25 +1727,93 @@ impl MachineState {
             .push(val)
     }
 
-    pub fn exec(&mut self, code: &[Op], args: &[Val], max_steps: usize) -> Vec<u64> {
-        self.flush();
-        self.load_args(args);
-        // Load the exec stack
-        for op in code {
-            self.push(Val::Exec(op.clone()))
-        }
-
+    /// Runs the ops on the exec stack until it's empty, or until `max_steps`
+    /// ops have been popped from it. Every op popped counts as a step,
+    /// including the lists and loop bodies unfolded along the way, so loops
+    /// are paid for by the iteration. Returns true if the step limit was hit.
+    pub fn run(&mut self, max_steps: usize) -> bool {
+        let snapshot_interval = if self.max_snapshots == 0 {
+            0
+        } else {
+            (max_steps / self.max_snapshots).max(1)
+        };
+        let mut exhausted = false;
         while let Some(Val::Exec(op)) = self.pop_opt(&Type::Exec) {
             log::trace!("[{}] {:x?}", self.counter, op);
+            if self.record_trace {
+                if snapshot_interval > 0
+                    && self.counter % snapshot_interval == 0
+                    && self.trace.snapshots.len() < self.max_snapshots
+                {
+                    let snapshot = self.snapshot();
+                    self.trace.snapshots.push(snapshot);
+                }
+                self.trace.ops.push(op.clone());
+            }
             self.counter += 1;
             if self.counter >= max_steps {
+                exhausted = true;
                 break;
             }
 
             op.eval(self)
         }
+        exhausted
+    }
+
+    /// Runs the code with the inputs loaded onto the float stack, and
+    /// returns the top of the float stack, if there's anything there.
+    pub fn exec_numeric(&mut self, code: &[Op], inputs: &[f64], max_steps: usize) -> Option<f64> {
+        self.flush();
+        for x in inputs {
+            self.push(Val::Float(x.to_bits()))
+        }
+        for op in code {
+            self.push(Val::Exec(op.clone()))
+        }
+        self.run(max_steps);
+        match self.pop_opt(&Type::Float) {
+            Some(Val::Float(bits)) => Some(f64::from_bits(bits)),
+            _ => None,
+        }
+    }
+
+    /// Runs the code with the inputs available to `Op::Input`, and returns
+    /// the top of the bool stack, if there's anything there.
+    pub fn exec_boolean(
+        &mut self,
+        code: &[Op],
+        inputs: &[bool],
+        max_steps: usize,
+    ) -> Option<bool> {
+        self.flush();
+        self.inputs = inputs.iter().map(|b| Val::Bool(*b)).collect();
+        for op in code {
+            self.push(Val::Exec(op.clone()))
+        }
+        self.run(max_steps);
+        match self.pop_opt(&Type::Bool) {
+            Some(Val::Bool(b)) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn exec(&mut self, code: &[Op], args: &[Val], max_steps: usize) -> Vec<u64> {
+        self.flush();
+        self.load_args(args);
+        // Load the exec stack
+        for op in code {
+            self.push(Val::Exec(op.clone()))
+        }
+        let exhausted = self.run(max_steps);
 
         log::trace!("Completed execution. Machine state: {:#?}", self);
+        let stack_depths = self
+            .stacks
+            .iter()
+            .filter(|(_, s)| !s.is_empty())
+            .map(|(t, s)| (format!("{:?}", t), s.len()))
+            .collect::<BTreeMap<String, usize>>();
         // first, take the explicitly-marked gadgets.
         // ensure that this list begins with an executable.
 
@@ -1271,6 +1846,25 @@ impl MachineState {
         }
         payload.reverse();
 
+        let distinct_gadgets = payload
+            .iter()
+            .filter(|w| {
+                memory
+                    .perm_of_addr(**w)
+                    .map(|p| p.intersects(Perms::EXEC))
+                    .unwrap_or(false)
+            })
+            .collect::<HashSet<_>>()
+            .len();
+        self.stats = PushStats {
+            program_len: code.len(),
+            steps: self.counter,
+            exhausted,
+            stack_depths,
+            chain_len: payload.len(),
+            distinct_gadgets,
+        };
+
         log::trace!("Payload: {:#x?}", payload);
         payload
     }
@@ -1301,13 +1895,11 @@ pub mod creature {
     use std::fmt;
     use std::hash::{Hash, Hasher};
 
-    use rand::thread_rng;
-
     use crate::emulator::profiler::{HasProfile, Profile};
-    use crate::evolution::{Genome, LinearChromosome, Mutation, Phenome};
+    use crate::evolution::{Genes, Genome, LinearChromosome, Mutation, Phenome};
     use crate::roper::Fitness;
     use crate::util;
-    use crate::util::random::hash_seed_rng;
+    use crate::util::random::{hash_seed_rng, thread_rng};
 
     use super::*;
 
@@ -1329,21 +1921,33 @@ pub mod creature {
         }
     }
 
-    #[derive(Clone, Serialize)]
+    /// A creature read back from a dump, or a checkpoint, has its genome,
+    /// but not its payloads, profile or fitness, and is evaluated again.
+    #[derive(Clone, Serialize, Deserialize)]
     pub struct Creature {
         pub chromosome: LinearChromosome<Op, OpMutation>,
         pub tag: u64,
         // TODO: this should become a hashmap associating problems with payloads
+        #[serde(skip_deserializing)]
         pub payloads: Vec<Vec<u64>>,
+        /// Statistics for the push runs that produced each payload
+        #[serde(skip_deserializing)]
+        pub push_stats: Vec<PushStats>,
+        /// Traces of the push runs that produced each payload, if
+        /// `push_vm.record_trace` is set
+        #[serde(skip_deserializing)]
+        pub push_traces: Vec<PushTrace>,
         // But then we need some way to map the problems to the profiles. not just
         // flat vecs. I think we may need to refactor the profile struct, which could
         // get a bit messy. For the best, though.
         // We don't need to hold the problems themselves, here. An index into a problem
         // table held in Config would be just fine. We can always get a pointer to Config
         // in scope.
+        #[serde(skip_deserializing)]
         pub profile: Option<Profile>,
-        #[serde(borrow)]
+        #[serde(skip_deserializing)]
         pub fitness: Option<Fitness<'static>>,
+        #[serde(skip_deserializing)]
         pub front: Option<usize>,
         pub num_offspring: usize,
         pub native_island: usize,
@@ -1374,7 +1978,61 @@ pub mod creature {
         }
     }
 
-    impl Creature {}
+    impl Creature {
+        /// Describes each payload alongside the push run that built it: the
+        /// run's statistics, the chain itself, and the trace, if recorded.
+        pub fn push_report(&self) -> String {
+            let mut report = String::new();
+            let memory = get_static_memory_image();
+            for (p_num, payload) in self.payloads.iter().enumerate() {
+                report.push_str(&format!("==== payload {} ====\n", p_num));
+                if let Some(stats) = self.push_stats.get(p_num) {
+                    report.push_str(&format!("program length: {}\n", stats.program_len));
+                    report.push_str(&format!(
+                        "steps: {}{}\n",
+                        stats.steps,
+                        if stats.exhausted {
+                            " (step limit reached)"
+                        } else {
+                            ""
+                        }
+                    ));
+                    report.push_str(&format!("chain length: {}\n", stats.chain_len));
+                    report.push_str(&format!(
+                        "distinct gadgets: {} (diversity {:.3})\n",
+                        stats.distinct_gadgets,
+                        stats.gadget_diversity()
+                    ));
+                    report.push_str(&format!("final stack depths: {:?}\n", stats.stack_depths));
+                }
+                report.push_str("-- chain --\n");
+                for (i, w) in payload.iter().enumerate() {
+                    let perms = memory
+                        .perm_of_addr(*w)
+                        .map(|p| format!(" ({:?})", p))
+                        .unwrap_or_else(String::new);
+                    report.push_str(&format!("[{}] 0x{:010x}{}\n", i, w, perms));
+                }
+                if let Some(trace) = self.push_traces.get(p_num) {
+                    if !trace.is_empty() {
+                        report.push_str("-- push trace --\n");
+                        let mut snapshots = trace.snapshots.iter().peekable();
+                        for (step, op) in trace.ops.iter().enumerate() {
+                            while snapshots.peek().map(|s| s.step <= step).unwrap_or(false) {
+                                let snapshot = snapshots.next().unwrap();
+                                report.push_str(&format!(
+                                    "    stacks: {:?}\n    tops: {:?}\n",
+                                    snapshot.depths, snapshot.tops
+                                ));
+                            }
+                            report.push_str(&format!("[{}] {:x?}\n", step, op));
+                        }
+                    }
+                }
+            }
+            report
+        }
+    }
 
     impl Genome for Creature {
         type Allele = Op;
@@ -1403,6 +2061,8 @@ pub mod creature {
         where
             Self: Sized,
         {
+            // drawn on rayon's workers, by Tournament::new
+            loader::set_target(config.roper.target);
             // First, let's get some information from the lifted program
             let mut rng = hash_seed_rng(&salt);
             let length = rng.gen_range(config.push_vm.min_len, config.push_vm.max_len);
@@ -1410,15 +2070,17 @@ pub mod creature {
 
             Self {
                 chromosome: LinearChromosome {
-                    chromosome: ops,
-                    mutations: vec![None; length],
-                    parentage: vec![],
+                    chromosome: ops.into(),
+                    mutations: vec![None; length].into(),
+                    parentage: Genes::default(),
                     parent_names: vec![],
                     name: util::name::random(4, rng.gen::<u64>()),
                     generation: 0,
                 },
                 tag: rng.gen::<u64>(),
                 payloads: vec![],
+                push_stats: vec![],
+                push_traces: vec![],
                 profile: None,
                 fitness: None,
                 front: None,
@@ -1441,6 +2103,8 @@ pub mod creature {
                 chromosome,
                 tag: thread_rng().gen::<u64>(),
                 payloads: vec![],
+                push_stats: vec![],
+                push_traces: vec![],
                 profile: None,
                 fitness: None,
                 front: None,
@@ -1497,11 +2161,40 @@ pub mod creature {
             unimplemented!()
         }
 
+        fn profile_summary(&self) -> Option<BTreeMap<String, f64>> {
+            self.profile.as_ref().map(Profile::summary)
+        }
+
+        fn execution_paths(&self) -> Vec<Vec<u64>> {
+            self.profile
+                .as_ref()
+                .map(Profile::block_paths)
+                .unwrap_or_default()
+        }
+
+        fn parents(&self) -> Vec<String> {
+            self.chromosome.parent_names.clone()
+        }
+
+        fn operators(&self) -> Vec<String> {
+            self.chromosome.operators()
+        }
+
         fn is_goal_reached(&self, config: &Config) -> bool {
             self.scalar_fitness(&config.fitness.priority())
                 .map(|p| p - config.fitness.target <= std::f64::EPSILON)
                 .unwrap_or(false)
         }
+
+        fn annotated_layout(&self, _config: &Config) -> Option<String> {
+            Some(self.push_report())
+        }
+
+        fn path_export(&self) -> Option<String> {
+            self.profile
+                .as_ref()
+                .and_then(|p| serde_json::to_string_pretty(&p.path_export(&self.payloads)).ok())
+        }
     }
 
     impl fmt::Debug for Creature {
@@ -1512,6 +2205,9 @@ pub mod creature {
 
             for (p_num, payload) in self.payloads.iter().enumerate() {
                 writeln!(f, "payload {}, of length {}", p_num, payload.len())?;
+                if let Some(stats) = self.push_stats.get(p_num) {
+                    writeln!(f, "push stats: {:?}", stats)?;
+                }
                 let memory = get_static_memory_image();
                 for (i, w) in payload.iter().enumerate() {
                     let perms = memory
@@ -1593,6 +2289,9 @@ mod test {
             min_len: 10,
             max_len: 100,
             literal_rate: 0.3,
+            literal_rates: Default::default(),
+            record_trace: false,
+            trace_snapshots: 0,
         };
         loader::falcon_loader::load_from_path(&mut config, true).expect("failed to load");
         crate::roper::init_soup(&mut config).expect("Failed to init soup");
@@ -1606,4 +2305,81 @@ mod test {
         println!("Machine state: {:#?}", machine);
         println!("Result: {:#x?}", res);
     }
+
+    #[test]
+    fn test_typed_stacks() {
+        use Val::*;
+        let mut machine = MachineState::default();
+        machine.flush();
+        machine.push(Int(-7));
+        machine.push(Int(3));
+        Op::IntAdd.eval(&mut machine);
+        Op::Dup(Type::Int).eval(&mut machine);
+        Op::IntToFloat.eval(&mut machine);
+        machine.push(Float(0.5_f64.to_bits()));
+        Op::FloatMul.eval(&mut machine);
+        assert_eq!(machine.pop(&Type::Float), Float((-2.0_f64).to_bits()));
+        Op::IntToBool.eval(&mut machine);
+        assert_eq!(machine.pop(&Type::Bool), Bool(true));
+
+        // CodeIf picks between the top two code items
+        machine.push(Code(Op::IntConst(2)));
+        machine.push(Code(Op::IntConst(1)));
+        machine.push(Bool(false));
+        Op::CodeIf.eval(&mut machine);
+        assert_eq!(machine.pop(&Type::Exec), Exec(Op::IntConst(2)));
+        assert_eq!(machine.pop_opt(&Type::Code), None);
+    }
+
+    #[test]
+    fn test_loops() {
+        let mut machine = MachineState::default();
+        machine.flush();
+        // The exec stack is a stack: the last op pushed runs first.
+        for op in vec![
+            Op::IntAdd,
+            Op::ExecDoRange,
+            Op::IntConst(4),
+            Op::IntConst(1),
+            Op::IntConst(0),
+        ] {
+            machine.push(Val::Exec(op));
+        }
+        assert!(!machine.run(100));
+        // 0 + 1 + 2 + 3 + 4
+        assert_eq!(machine.stacks[&Type::Int], vec![Val::Int(10)]);
+
+        // Loops are charged by the iteration, and cut off at the step limit.
+        machine.flush();
+        for op in vec![Op::Nop, Op::ExecDoTimes, Op::IntConst(1_000_000)] {
+            machine.push(Val::Exec(op));
+        }
+        assert!(machine.run(100));
+        assert_eq!(machine.counter, 100);
+
+        machine.flush();
+        for op in vec![Op::IntConst(2), Op::IntConst(1), Op::ExecIfElse] {
+            machine.push(Val::Exec(op));
+        }
+        machine.push(Val::Bool(false));
+        assert!(!machine.run(100));
+        assert_eq!(machine.stacks[&Type::Int], vec![Val::Int(2)]);
+    }
+
+    #[test]
+    fn test_trace_descriptor() {
+        let mut trace = PushTrace::default();
+        for n in 0..3 {
+            trace.ops.push(Op::IntConst(n));
+        }
+        trace.ops.push(Op::IntAdd);
+        let descriptor = trace.descriptor();
+        assert_eq!(descriptor["IntConst"], 4);
+        assert_eq!(descriptor["IntAdd"], 1);
+
+        // literals don't matter
+        let mut other = trace.clone();
+        other.ops[0] = Op::IntConst(99);
+        assert_eq!(other.descriptor(), descriptor);
+    }
 }
@@ -0,0 +1,323 @@
+//! The `replay` subcommand, which loads a champion bundle, as dumped by the
+//! observer to `champions/champion_{counter}.bundle.json.gz`, runs its
+//! payloads through the emulator again, with basic blocks and memory writes
+//! recorded, and prints a report of the execution:
+//!
+//! ```text
+//! berbalang replay <bundle> [<binary>]
+//! ```
+//!
+//! The binary is the one named in the bundled config, unless another path
+//! is given -- as when the bundle has been moved to another machine. The
+//! screening metrics of the replay are set beside those recorded when the
+//! champion was found, so that a champion that doesn't behave the same way
+//! twice is easy to spot.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::sync::Arc;
+
+use itertools::Itertools;
+use serde_json::Value;
+use unicorn::Cpu;
+
+use crate::configure::Config;
+use crate::emulator::hatchery::Hatchery;
+use crate::emulator::loader;
+use crate::emulator::profiler::Profile;
+use crate::emulator::register_pattern::Register;
+use crate::error::Error;
+use crate::util;
+
+/// What's needed of a champion bundle to replay it.
+#[derive(Debug)]
+pub struct Bundle {
+    pub config: Config,
+    pub name: String,
+    pub island: usize,
+    pub counter: usize,
+    pub epoch: usize,
+    /// The payloads the champion sent to the emulator: its chromosome, for
+    /// a bare creature, or those its push programs built, for a push one
+    pub payloads: Vec<Vec<u64>>,
+    pub scores: BTreeMap<String, f64>,
+    pub profile_summary: BTreeMap<String, f64>,
+}
+
+fn field<T: serde::de::DeserializeOwned>(value: &Value, pointer: &str) -> Result<T, Error> {
+    let v = value
+        .pointer(pointer)
+        .ok_or_else(|| Error::Misc(format!("Bundle has no {} field", pointer)))?;
+    serde_json::from_value(v.clone()).map_err(Error::from)
+}
+
+impl Bundle {
+    pub fn from_value(bundle: &Value) -> Result<Self, Error> {
+        let payloads = if bundle.pointer("/champion/payloads").is_some() {
+            field(bundle, "/champion/payloads")?
+        } else {
+            vec![field(bundle, "/champion/chromosome/chromosome")?]
+        };
+        Ok(Self {
+            config: field(bundle, "/config")?,
+            name: field(bundle, "/record/name")?,
+            island: field(bundle, "/island")?,
+            counter: field(bundle, "/counter")?,
+            epoch: field(bundle, "/epoch")?,
+            payloads,
+            scores: field(bundle, "/record/scores")?,
+            profile_summary: field::<Option<_>>(bundle, "/profile_summary")?.unwrap_or_default(),
+        })
+    }
+
+    /// Loads a bundle, compressed or not.
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let value: Value = if path.ends_with(".gz") {
+            util::dump::undump(path)?
+        } else {
+            serde_json::from_slice(&fs::read(path)?)?
+        };
+        Self::from_value(&value)
+    }
+}
+
+fn emulate<C: 'static + Cpu<'static>>(
+    config: &Config,
+    payloads: &[Vec<u64>],
+) -> Result<Vec<Profile>, Error> {
+    let output_registers: Vec<Register<C>> = config
+        .roper
+        .registers_to_check()?
+        .into_iter()
+        // running error through ok() because it can't be formatted with Debug
+        .map(|r| r.parse().ok().expect("Failed to parse register name"))
+        .collect::<Vec<_>>();
+    // the same initial state as the evaluators use, so that the replay
+    // starts where the original run did
+    let initial_register_state = if config.roper.randomize_registers {
+        util::architecture::random_register_state::<u64, C>(&output_registers, config.random_seed)
+    } else {
+        util::architecture::constant_register_state::<C>(&output_registers, 0_u64)
+    };
+    let hatchery: Hatchery<C> = Hatchery::new(
+        Arc::new(config.roper.clone()),
+        Arc::new(initial_register_state),
+        Arc::new(output_registers),
+    );
+    payloads
+        .iter()
+        .map(|payload| hatchery.execute(payload.clone(), None))
+        .collect()
+}
+
+/// Replays the bundle's payloads, returning a profile for each.
+pub fn replay(bundle: &Bundle, binary: Option<&str>) -> Result<Vec<Profile>, Error> {
+    let mut config = bundle.config.clone();
+    // the target the champion's island searched
+    let target = config.roper.target_of(config.island_id);
+    config.roper = config.roper.for_target(target);
+    loader::set_target(target);
+    if let Some(binary) = binary {
+        config.roper.binary_path = binary.to_string();
+    }
+    config.roper.num_emulators = 1;
+    config.roper.num_workers = 1;
+    config.roper.record_basic_blocks = true;
+    config.roper.record_memory_writes = true;
+    loader::falcon_loader::load_from_path(&mut config, true)?;
+    config.roper.parse_register_patterns()?;
+
+    use unicorn::Arch::*;
+    match config.roper.arch {
+        X86 => emulate::<unicorn::CpuX86<'_>>(&config, &bundle.payloads),
+        ARM => emulate::<unicorn::CpuARM<'_>>(&config, &bundle.payloads),
+        ARM64 => emulate::<unicorn::CpuARM64<'_>>(&config, &bundle.payloads),
+        MIPS => emulate::<unicorn::CpuMIPS<'_>>(&config, &bundle.payloads),
+        SPARC => emulate::<unicorn::CpuSPARC<'_>>(&config, &bundle.payloads),
+        M68K => emulate::<unicorn::CpuM68K<'_>>(&config, &bundle.payloads),
+        _ => unimplemented!("architecture unimplemented"),
+    }
+}
+
+/// Sets the replayed summary beside the recorded one, flagging the metrics
+/// that differ.
+fn compare_summaries(
+    recorded: &BTreeMap<String, f64>,
+    replayed: &BTreeMap<String, f64>,
+) -> Vec<String> {
+    // emulation times are expected to vary
+    recorded
+        .keys()
+        .chain(replayed.keys())
+        .unique()
+        .sorted()
+        .filter(|k| !k.contains("emulation"))
+        .map(|k| {
+            let recorded = recorded.get(k);
+            let replayed = replayed.get(k);
+            let flag = if recorded == replayed {
+                ""
+            } else {
+                "  <-- differs"
+            };
+            let show = |v: Option<&f64>| v.map(|v| v.to_string()).unwrap_or_else(|| "-".into());
+            format!(
+                "{:<32} {:>16} {:>16}{}",
+                k,
+                show(recorded),
+                show(replayed),
+                flag
+            )
+        })
+        .collect()
+}
+
+/// A report of the replay, in the order in which things happened: each
+/// payload, the blocks it executed, with their disassembly, and the state
+/// it left the machine in.
+pub fn report(bundle: &Bundle, profiles: &[Profile]) -> String {
+    let mut s = String::new();
+    let _ = writeln!(
+        s,
+        "Replay of {} (island {}, epoch {}, counter {})",
+        bundle.name, bundle.island, bundle.epoch, bundle.counter
+    );
+    let _ = writeln!(s, "Binary: {}", bundle.config.roper.binary_path);
+    for (i, (payload, profile)) in bundle.payloads.iter().zip(profiles.iter()).enumerate() {
+        let _ = writeln!(s, "\n==== Payload {} ({} words) ====", i, payload.len());
+        for (j, w) in payload.iter().enumerate() {
+            let _ = writeln!(
+                s,
+                "[{:3}] {:#018x}  executed {} times",
+                j,
+                w,
+                profile.times_executed(*w)
+            );
+        }
+        for (n, path) in profile.paths.iter().enumerate() {
+            let _ = writeln!(s, "\n---- Run {}: {} blocks ----", n, path.len());
+            for block in path.iter() {
+                let _ = writeln!(s, "{:#x} ({} bytes)", block.entry, block.size);
+                let _ = write!(s, "{}", block.disassemble());
+            }
+            let _ = writeln!(s, "\nReturns: {}", profile.ret_counts[n]);
+            let _ = writeln!(s, "Sigreturns: {}", profile.sigreturn_counts[n]);
+            let _ = writeln!(s, "CPU error: {:?}", profile.cpu_errors[n]);
+            let _ = writeln!(s, "Fault phase: {:?}", profile.fault_phases[n]);
+            let _ = writeln!(
+                s,
+                "Emulation time: {} μs",
+                profile.emulation_times[n].as_micros()
+            );
+            let _ = writeln!(s, "Registers:");
+            for (reg, vals) in profile.registers[n].0.iter().sorted_by_key(|p| p.0) {
+                let _ = writeln!(s, "  {:<8} {:#x?}", reg, vals);
+            }
+            if !profile.call_targets[n].is_empty() {
+                let _ = writeln!(s, "Call targets: {:#x?}", profile.call_targets[n]);
+            }
+            for syscall in profile.syscalls[n].iter() {
+                let _ = writeln!(s, "Syscall: {:x?}", syscall);
+            }
+            for hit in profile.watchpoint_hits[n].iter() {
+                let _ = writeln!(s, "Watchpoint hit: {:x?}", hit);
+            }
+            if let Some(writes) = profile.memory_writes.get(n) {
+                let _ = writeln!(s, "Memory written:");
+                for (addr, bytes) in writes.regions() {
+                    let _ = writeln!(s, "  {:#x}: {}", addr, hex::encode(bytes));
+                }
+            }
+        }
+    }
+    let _ = writeln!(s, "\n==== Recorded scores ====");
+    for (k, v) in bundle.scores.iter() {
+        let _ = writeln!(s, "{:<32} {:>16}", k, v);
+    }
+    let mut replayed = Profile::default();
+    for profile in profiles.iter() {
+        replayed.absorb(profile.clone());
+    }
+    let _ = writeln!(
+        s,
+        "\n==== Screening metrics ====\n{:<32} {:>16} {:>16}",
+        "", "recorded", "replayed"
+    );
+    for line in compare_summaries(&bundle.profile_summary, &replayed.summary()) {
+        let _ = writeln!(s, "{}", line);
+    }
+    s
+}
+
+/// The `replay` subcommand:
+///
+/// ```text
+/// berbalang replay <bundle> [<binary>]
+/// ```
+pub fn run_cli(args: &[String]) -> Result<(), Error> {
+    let path = args
+        .get(0)
+        .ok_or_else(|| Error::Misc("Usage: berbalang replay <bundle> [<binary>]".to_string()))?;
+    let bundle = Bundle::load(path)?;
+    log::info!(
+        "Replaying {} payload(s) of {} from {}",
+        bundle.payloads.len(),
+        bundle.name,
+        path
+    );
+    let profiles = replay(&bundle, args.get(1).map(String::as_str))?;
+    print!("{}", report(&bundle, &profiles));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn bundle(champion: Value) -> Value {
+        json!({
+            "bundle_version": 1,
+            "island": 2,
+            "counter": 300,
+            "epoch": 3,
+            "record": {"name": "alice", "scores": {"place": 1.0}},
+            "profile_summary": {"ret_count": 4.0, "avg_emulation_micros": 10.0},
+            "config": serde_json::to_value(Config::default()).unwrap(),
+            "champion": champion,
+        })
+    }
+
+    #[test]
+    fn test_bundle_payloads() {
+        let bare = bundle(json!({"chromosome": {"chromosome": [1, 2, 3], "name": "alice"}}));
+        let bundle_ = Bundle::from_value(&bare).unwrap();
+        assert_eq!(bundle_.payloads, vec![vec![1, 2, 3]]);
+        assert_eq!(bundle_.counter, 300);
+        assert_eq!(bundle_.scores["place"], 1.0);
+
+        let push = bundle(json!({"chromosome": {"chromosome": []}, "payloads": [[4], [5, 6]]}));
+        let bundle_ = Bundle::from_value(&push).unwrap();
+        assert_eq!(bundle_.payloads, vec![vec![4], vec![5, 6]]);
+
+        assert!(Bundle::from_value(&bundle(json!({}))).is_err());
+    }
+
+    #[test]
+    fn test_compare_summaries() {
+        let recorded = vec![
+            ("ret_count".to_string(), 4.0),
+            ("avg_emulation_micros".into(), 10.0),
+        ]
+        .into_iter()
+        .collect();
+        let replayed = vec![("ret_count".to_string(), 5.0), ("runs".into(), 1.0)]
+            .into_iter()
+            .collect();
+        let lines = compare_summaries(&recorded, &replayed);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("ret_count") && lines[0].ends_with("differs"));
+        assert!(lines[1].starts_with("runs"));
+    }
+}
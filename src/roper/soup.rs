@@ -0,0 +1,191 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::configure::RoperConfig;
+use crate::error::Error;
+
+/// Where a word in the soup came from.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Provenance {
+    /// Found by scanning the binary, e.g. random executable addresses or
+    /// harvested JOP gadgets.
+    Harvested,
+    /// Supplied by the experimenter: a gadget file, the constants in the
+    /// register patterns, sigreturn frame genes.
+    Seeded,
+    /// Found in an evolved population.
+    Evolved,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SoupEntry {
+    pub provenance: BTreeSet<Provenance>,
+    /// The number of times the word has been seen. For evolved words, this
+    /// is the number of occurrences in the population.
+    #[serde(default)]
+    pub count: usize,
+    /// The runs or files the word was collected from.
+    #[serde(default)]
+    pub sources: BTreeSet<String>,
+}
+
+/// A gadget pool annotated with provenance, so that soups can be saved,
+/// curated, and merged across runs. Serialized as JSON, with files ending
+/// in `.soup.json` by convention.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Soup {
+    pub entries: BTreeMap<u64, SoupEntry>,
+}
+
+impl Soup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, word: u64, provenance: Provenance, source: &str) {
+        self.insert_n(word, provenance, source, 1)
+    }
+
+    fn insert_n(&mut self, word: u64, provenance: Provenance, source: &str, count: usize) {
+        let entry = self.entries.entry(word).or_insert_with(|| SoupEntry {
+            provenance: BTreeSet::new(),
+            count: 0,
+            sources: BTreeSet::new(),
+        });
+        entry.provenance.insert(provenance);
+        entry.count += count;
+        entry.sources.insert(source.to_string());
+    }
+
+    pub fn extend<I: IntoIterator<Item = u64>>(
+        &mut self,
+        words: I,
+        provenance: Provenance,
+        source: &str,
+    ) {
+        for word in words {
+            self.insert(word, provenance, source)
+        }
+    }
+
+    /// Absorbs another soup, taking the union of the provenances and sources
+    /// of each word, and summing the counts.
+    pub fn merge(&mut self, other: Soup) {
+        for (word, theirs) in other.entries.into_iter() {
+            match self.entries.get_mut(&word) {
+                Some(ours) => {
+                    ours.provenance.extend(theirs.provenance);
+                    ours.sources.extend(theirs.sources);
+                    ours.count += theirs.count;
+                }
+                None => {
+                    self.entries.insert(word, theirs);
+                }
+            }
+        }
+    }
+
+    pub fn words(&self) -> Vec<u64> {
+        self.entries.keys().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Loads a soup from any of the formats we've used over time:
+    /// - a structured `.soup.json` file, as written by `save`
+    /// - a soup dumped by the observer, a JSON list of `[word, count]` pairs,
+    ///   whose words are taken to have evolved
+    /// - a JSON list of words, or a text file with one decimal word per line,
+    ///   which are taken to have been seeded
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let source = path.as_ref().to_string_lossy().to_string();
+        if source.ends_with(".soup.json") {
+            let reader = File::open(&path).map(BufReader::new)?;
+            return Ok(serde_json::from_reader(reader)?);
+        }
+        let mut soup = Soup::new();
+        if source.ends_with(".json") {
+            let data = std::fs::read_to_string(&path)?;
+            if let Ok(pairs) = serde_json::from_str::<Vec<(u64, usize)>>(&data) {
+                for (word, count) in pairs {
+                    soup.insert_n(word, Provenance::Evolved, &source, count);
+                }
+            } else {
+                let words = serde_json::from_str::<Vec<u64>>(&data)?;
+                soup.extend(words, Provenance::Seeded, &source);
+            }
+        } else {
+            let reader = File::open(&path).map(BufReader::new)?;
+            for line in reader.lines() {
+                let word = line?.parse::<u64>()?;
+                soup.insert(word, Provenance::Seeded, &source);
+            }
+        }
+        Ok(soup)
+    }
+}
+
+/// Draws a word from the soup. If the soup has been clustered, a cluster is
+/// chosen uniformly first, so that gadgets with uncommon effects are drawn
+/// as often as those with common ones.
+pub fn sample_word<R: Rng + ?Sized>(config: &RoperConfig, rng: &mut R) -> Option<u64> {
+    if let Some(cluster) = config.soup_clusters.as_ref().and_then(|c| c.choose(rng)) {
+        return cluster.choose(rng).copied();
+    }
+    config.soup.as_ref().and_then(|s| s.choose(rng)).copied()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_merge_soups() {
+        let mut a = Soup::new();
+        a.extend(vec![1, 2, 3], Provenance::Harvested, "a");
+        let mut b = Soup::new();
+        b.extend(vec![3, 4], Provenance::Evolved, "b");
+        b.insert(3, Provenance::Evolved, "b");
+        a.merge(b);
+
+        assert_eq!(a.words(), vec![1, 2, 3, 4]);
+        let three = &a.entries[&3];
+        assert_eq!(three.count, 3);
+        assert!(three.provenance.contains(&Provenance::Harvested));
+        assert!(three.provenance.contains(&Provenance::Evolved));
+        assert_eq!(three.sources.len(), 2);
+        assert_eq!(a.entries[&4].provenance.len(), 1);
+    }
+
+    #[test]
+    fn test_sample_clustered_soup() {
+        let mut config = RoperConfig::default();
+        config.soup = Some((0..100).collect());
+        config.soup_clusters = Some(vec![(0..99).collect(), vec![99]]);
+        let mut rng = rand::thread_rng();
+        let rare = (0..1000)
+            .filter(|_| sample_word(&config, &mut rng) == Some(99))
+            .count();
+        // about half the draws should come from the singleton cluster
+        assert!(rare > 300, "rare = {}", rare);
+    }
+}
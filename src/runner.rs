@@ -0,0 +1,295 @@
+//! Running an experiment from code, as the `berbalang` binary runs one from
+//! a config file. The config may be read from a file, built in code, or
+//! both,
+//!
+//! ```text
+//! let mut config = Config::load("config.toml", None, None, &[])?;
+//! config.pop_size = 500;
+//! let outcome = Berbalang::new(config).with_logger(true).run()?;
+//! if let Some(champion) = outcome.champion() {
+//!     println!("{} scored {:?}", champion.name, champion.scalar_fitness);
+//! }
+//! ```
+//!
+//! and is checked, as a config read from a file is, before the run is set
+//! up. The outcome holds the summary of each island's run, as written to
+//! its report, and its champion.
+//!
+//! The state of a run -- its epoch counter, timeout, and whether it should
+//! keep going -- is kept in statics, so a process can make only one run.
+//! Several runs are made in processes of their own, as `sweep` makes them.
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{self, AtomicBool};
+use std::time::Duration;
+
+use crate::configure::{Config, ConfigFormat, Job};
+use crate::error::Error;
+use crate::examples::{boolean, byte_sequence, hello_world, linear_gp, regression};
+use crate::summary::{ChampionSummary, RunSummary};
+use crate::{artifacts, logger, manifest, resume, roper, shutdown};
+
+/// How long the observers are given to finish, once the run has stopped.
+const OBSERVER_WAIT: Duration = Duration::from_secs(120);
+
+static RAN: AtomicBool = AtomicBool::new(false);
+
+/// A run of an experiment, as configured, which is made by `run`.
+pub struct Berbalang {
+    config: Config,
+    /// Whether the config has been checked, and its data directory set up,
+    /// as `Config::from_path` and `resume::load` do
+    prepared: bool,
+    logger: bool,
+    signal_handlers: bool,
+}
+
+impl Berbalang {
+    /// A run of the config, which is checked, and its data directory set
+    /// up, when it's run.
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            prepared: false,
+            logger: false,
+            signal_handlers: false,
+        }
+    }
+
+    /// A run of the config at `path`, read as `Config::from_path` reads it.
+    pub fn from_path<P: AsRef<Path>>(
+        path: P,
+        format: Option<ConfigFormat>,
+        population_name: Option<String>,
+        overrides: &[String],
+    ) -> Result<Self, Error> {
+        let config = Config::from_path(path, format, population_name, overrides)?;
+        Ok(Self {
+            prepared: true,
+            ..Self::new(config)
+        })
+    }
+
+    /// The resumption of the run in `run_directory`. See `resume::load`.
+    pub fn resume(
+        path: &Path,
+        format: Option<ConfigFormat>,
+        overrides: &[String],
+        run_directory: &Path,
+        allow_drift: bool,
+    ) -> Result<Self, Error> {
+        let config = resume::load(path, format, overrides, run_directory, allow_drift)?;
+        Ok(Self {
+            prepared: true,
+            ..Self::new(config)
+        })
+    }
+
+    /// Whether to initialize the logger, as `[logging]` configures it, when
+    /// the run starts. Off by default, for programs that log as they please.
+    pub fn with_logger(mut self, logger: bool) -> Self {
+        self.logger = logger;
+        self
+    }
+
+    /// Whether to install the handlers of SIGINT and SIGTERM that wind the
+    /// run down. See `shutdown`. Off by default, so that a program's
+    /// signals are left to it.
+    pub fn with_signal_handlers(mut self, signal_handlers: bool) -> Self {
+        self.signal_handlers = signal_handlers;
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Checks the config, without running the experiment, returning a
+    /// report of the checks. See `roper::dry_run`.
+    pub fn dry_run(mut self) -> Result<String, Error> {
+        if !self.prepared {
+            self.config.validate()?;
+        }
+        if self.logger {
+            logger::init_with(&self.config.observer.population_name, &self.config.logging);
+        }
+        match self.config.job {
+            Job::Roper => roper::dry_run::dry_run(self.config),
+            job => Ok(format!("The config of the {:?} job is sound\n", job)),
+        }
+    }
+
+    /// Runs the experiment, returning once every island has stopped, and
+    /// its observer has written its report.
+    pub fn run(self) -> Result<RunOutcome, Error> {
+        if RAN.swap(true, atomic::Ordering::SeqCst) {
+            return Err(Error::Misc(
+                "A process can make only one run. See the runner module".to_string(),
+            ));
+        }
+        let Self {
+            mut config,
+            prepared,
+            logger,
+            signal_handlers,
+        } = self;
+        if !prepared {
+            prepare(&mut config)?;
+        }
+        if logger {
+            logger::init_with(&config.observer.population_name, &config.logging);
+        }
+        if signal_handlers {
+            shutdown::install();
+        }
+        crate::set_starting_timestamp();
+        if let Some(timeout) = config.timeout_duration()? {
+            crate::set_timeout(timeout);
+        }
+        let population_name = config.observer.population_name.clone();
+        let data_directory = config.data_directory().to_string();
+        // to publish the manifest, once the run is over
+        let artifact_config = config.clone();
+
+        if let Ok(n) = std::env::var("BERBALANG_LIMIT_THREADS") {
+            let n = n.parse().map_err(|e| {
+                Error::Parsing(format!(
+                    "Invalid value for BERBALANG_LIMIT_THREADS: {:?}",
+                    e
+                ))
+            })?;
+            crate::limit_threads(n, &mut config);
+        } else if cfg!(feature = "disassemble_trace") {
+            crate::limit_threads(1, &mut config);
+        }
+
+        match config.job {
+            Job::LinearGp => linear_gp::run(config),
+            Job::Hello => hello_world::run(config),
+            Job::Regression => regression::run(config)?,
+            Job::Boolean => boolean::run(config)?,
+            Job::ByteSequence => byte_sequence::run(config)?,
+            Job::Roper => roper::run(config)?,
+        }
+
+        // the observers write their reports and final dumps once their
+        // islands have stopped
+        shutdown::wait_for_observers(OBSERVER_WAIT);
+        let runtime = crate::uptime();
+        let timed_out = crate::timeout_expired();
+        let interrupted = shutdown::requested();
+        if let Err(e) = manifest::record_runtime(&data_directory, runtime, timed_out, interrupted) {
+            log::error!("Failed to record the runtime in the manifest: {:?}", e);
+        }
+        let run_directory = Path::new(&data_directory)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from(&data_directory));
+        artifacts::publish(
+            &artifact_config,
+            &[
+                run_directory.join("manifest.json"),
+                run_directory.join("config.toml"),
+            ],
+        );
+
+        Ok(RunOutcome {
+            population_name,
+            run_directory,
+            islands: crate::summary::take_recorded(),
+            runtime,
+            timed_out,
+            interrupted,
+        })
+    }
+}
+
+/// Checks the config, and sets up its data directory, as `Config::from_path`
+/// does, writing the config there as TOML, since it has no file to copy.
+fn prepare(config: &mut Config) -> Result<(), Error> {
+    config.validate()?;
+    config.set_data_directory();
+    let copy_path = format!("{}/../config.toml", config.data_directory());
+    let value = toml::Value::try_from(&*config).map_err(|e| Error::Misc(e.to_string()))?;
+    let toml = toml::to_string(&value).map_err(|e| Error::Misc(e.to_string()))?;
+    std::fs::write(&copy_path, toml)?;
+    manifest::write_or_verify(config)
+}
+
+/// What came of a run.
+#[derive(Clone, Debug)]
+pub struct RunOutcome {
+    pub population_name: String,
+    /// The directory of the run, the parent of its islands'
+    pub run_directory: PathBuf,
+    /// The summary of each island's run, in order of the islands
+    pub islands: Vec<RunSummary>,
+    pub runtime: Duration,
+    pub timed_out: bool,
+    pub interrupted: bool,
+}
+
+impl RunOutcome {
+    /// The fittest of the islands' champions, by scalar fitness, the least
+    /// being the fittest.
+    pub fn champion(&self) -> Option<&ChampionSummary> {
+        let fitness = |c: &ChampionSummary| match c.scalar_fitness {
+            Some(f) if !f.is_nan() => f,
+            _ => std::f64::INFINITY,
+        };
+        self.islands
+            .iter()
+            .filter_map(|island| island.champion.as_ref())
+            .min_by(|a, b| {
+                fitness(a)
+                    .partial_cmp(&fitness(b))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn island(island: usize, scalar_fitness: Option<f64>) -> RunSummary {
+        RunSummary {
+            population_name: "happy-otter".to_string(),
+            island,
+            outcome: "timeout reached".to_string(),
+            counter: 100,
+            epoch: 1,
+            elapsed: Duration::from_secs(1),
+            statistics: vec![],
+            champion: Some(ChampionSummary {
+                name: format!("champion-{}", island),
+                scalar_fitness,
+                scores: vec![],
+                listing: String::new(),
+            }),
+            history: vec![],
+            config: vec![],
+        }
+    }
+
+    #[test]
+    fn test_champion() {
+        let mut outcome = RunOutcome {
+            population_name: "happy-otter".to_string(),
+            run_directory: PathBuf::from("/data/happy-otter"),
+            islands: vec![
+                island(0, Some(0.5)),
+                island(1, None),
+                island(2, Some(0.25)),
+                island(3, Some(std::f64::NAN)),
+            ],
+            runtime: Duration::from_secs(1),
+            timed_out: true,
+            interrupted: false,
+        };
+        assert_eq!(outcome.champion().unwrap().name, "champion-2");
+        outcome.islands.retain(|i| i.island % 2 == 1);
+        assert!(outcome.champion().is_some());
+        outcome.islands.clear();
+        assert!(outcome.champion().is_none());
+    }
+}
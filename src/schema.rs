@@ -0,0 +1,500 @@
+//! The `default-config` subcommand, which prints a config for a job with
+//! every field that has a default filled in, and each field commented with
+//! its documentation, or, with `--schema`, a JSON schema of the same:
+//!
+//! ```text
+//! berbalang default-config [<job>] [--schema]
+//! ```
+//!
+//! The job is named as in the config, e.g. "Roper" (the default) or
+//! "LinearGp". The defaults are those serde fills in, found by leaving out
+//! each field of the config in turn, and seeing whether it still parses,
+//! and the documentation is read from the declarations in `configure.rs`,
+//! so neither needs to be kept up to date by hand. Fields that have no
+//! default are marked as required, and given placeholder values, and those
+//! that are unset by default are listed, commented out, with their types.
+use std::fmt::Write;
+use std::mem;
+
+use hashbrown::{HashMap, HashSet};
+use serde_json::json;
+
+use crate::configure::{Config, Job};
+use crate::error::Error;
+
+const SOURCE: &str = include_str!("configure.rs");
+
+/// Fields used internally, which aren't to be set in a config.
+const HIDDEN: [&str; 2] = ["island_id", "observer.full_data_directory"];
+
+/// The sections of the config that concern only one job, and the job each
+/// concerns.
+const JOB_SECTIONS: [(&str, &str); 6] = [
+    ("Roper", "roper"),
+    ("Hello", "hello"),
+    ("LinearGp", "linear_gp"),
+    ("Regression", "regression"),
+    ("Boolean", "boolean"),
+    ("ByteSequence", "byte_sequence"),
+];
+
+#[derive(Clone, Debug)]
+struct Field {
+    name: String,
+    ty: String,
+    doc: Vec<String>,
+    skipped: bool,
+}
+
+/// The fields of each struct declared in the source, in order, with their
+/// types and comments.
+fn struct_fields(source: &str) -> HashMap<String, Vec<Field>> {
+    let mut structs = HashMap::new();
+    let mut current: Option<(String, Vec<Field>)> = None;
+    let mut doc = Vec::new();
+    let mut skipped = false;
+    for line in source.lines() {
+        let trimmed = line.trim();
+        if current.is_none() {
+            if line.starts_with("pub struct ") && trimmed.ends_with('{') {
+                let name = line["pub struct ".len()..]
+                    .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .next()
+                    .unwrap_or("")
+                    .to_string();
+                current = Some((name, Vec::new()));
+                doc.clear();
+                skipped = false;
+            }
+            continue;
+        }
+        if line.starts_with('}') {
+            let (name, fields) = current.take().expect("Not in a struct");
+            structs.insert(name, fields);
+        } else if trimmed.starts_with("///") {
+            doc.push(trimmed[3..].trim().to_string());
+        } else if trimmed.starts_with("//") {
+            // leaving out commented-out code
+            let comment = trimmed[2..].trim();
+            if !comment.ends_with(|c: char| c == ',' || c == ';' || c == '{' || c == '}') {
+                doc.push(comment.to_string());
+            }
+        } else if trimmed.starts_with("#[") {
+            skipped |= trimmed.contains("skip");
+        } else if trimmed.starts_with("pub ") && trimmed.contains(':') {
+            let mut parts = trimmed["pub ".len()..].splitn(2, ':');
+            let name = parts.next().unwrap_or("").trim().to_string();
+            let ty = parts
+                .next()
+                .unwrap_or("")
+                .trim()
+                .trim_end_matches(',')
+                .to_string();
+            if let Some((_, ref mut fields)) = current {
+                fields.push(Field {
+                    name,
+                    ty,
+                    doc: mem::replace(&mut doc, Vec::new()),
+                    skipped,
+                });
+            }
+            skipped = false;
+        } else {
+            doc.clear();
+            skipped = false;
+        }
+    }
+    structs
+}
+
+/// The struct declared in the source that a field of the given type holds,
+/// if any.
+fn nested_struct(ty: &str, structs: &HashMap<String, Vec<Field>>) -> Option<String> {
+    ty.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| structs.contains_key(*word))
+        .last()
+        .map(String::from)
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+/// The path of every table entry in the value, tables before their entries.
+fn paths(value: &toml::Value, prefix: &[String], paths_: &mut Vec<Vec<String>>) {
+    if let toml::Value::Table(table) = value {
+        for (key, v) in table.iter() {
+            let mut path = prefix.to_vec();
+            path.push(key.clone());
+            paths_.push(path.clone());
+            paths(v, &path, paths_);
+        }
+    }
+}
+
+fn remove_at(value: &mut toml::Value, path: &[String]) -> Option<toml::Value> {
+    let (last, init) = path.split_last()?;
+    let mut table = value;
+    for key in init {
+        table = table.get_mut(key)?;
+    }
+    table.as_table_mut()?.remove(last)
+}
+
+fn parses(value: &toml::Value) -> bool {
+    value.clone().try_into::<Config>().is_ok()
+}
+
+/// What's left of the value when every field that can be left out of it,
+/// because it has a default, has been.
+fn minimal(mut value: toml::Value) -> toml::Value {
+    let mut all = Vec::new();
+    paths(&value, &[], &mut all);
+    for path in all {
+        let mut trial = value.clone();
+        // if the path's table has been left out already, there's no need
+        if remove_at(&mut trial, &path).is_some() && parses(&trial) {
+            value = trial;
+        }
+    }
+    value
+}
+
+/// The job's config with its defaults filled in, and the paths of the
+/// fields that have no defaults.
+fn defaults(job: Job) -> Result<(toml::Value, HashSet<String>), Error> {
+    let mut config = Config::default();
+    config.job = job;
+    let base = toml::Value::try_from(&config).map_err(|e| Error::Misc(e.to_string()))?;
+    if !parses(&base) {
+        return Err(Error::Misc(
+            "The default config can't be parsed back in".to_string(),
+        ));
+    }
+    let required = minimal(base);
+    let mut required_paths = Vec::new();
+    paths(&required, &[], &mut required_paths);
+
+    let mut config: Config = required.try_into()?;
+    // the seed is random by default, and may not fit in a TOML integer
+    config.random_seed = 0;
+    let mut value = toml::Value::try_from(&config).map_err(|e| Error::Misc(e.to_string()))?;
+    remove_at(&mut value, &["random_seed".to_string()]);
+    let job_name = format!("{:?}", job);
+    for (other, section) in JOB_SECTIONS.iter() {
+        if *other != job_name {
+            remove_at(&mut value, &[section.to_string()]);
+        }
+    }
+    for path in HIDDEN.iter() {
+        let path = path.split('.').map(String::from).collect::<Vec<_>>();
+        remove_at(&mut value, &path);
+    }
+    Ok((value, required_paths.iter().map(|p| p.join(".")).collect()))
+}
+
+fn key(name: &str) -> String {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        name.to_string()
+    } else {
+        toml::Value::String(name.to_string()).to_string()
+    }
+}
+
+/// The value as it's written inline, tables and all.
+fn inline(value: &toml::Value) -> String {
+    match value {
+        toml::Value::Table(table) => {
+            let entries = table
+                .iter()
+                .map(|(k, v)| format!("{} = {}", key(k), inline(v)))
+                .collect::<Vec<_>>();
+            if entries.is_empty() {
+                "{}".to_string()
+            } else {
+                format!("{{ {} }}", entries.join(", "))
+            }
+        }
+        toml::Value::Array(array) => format!(
+            "[{}]",
+            array.iter().map(inline).collect::<Vec<_>>().join(", ")
+        ),
+        v => v.to_string(),
+    }
+}
+
+/// The fields of the table, documented ones first, in the order of their
+/// declaration, and then the rest.
+fn ordered_fields(
+    table: Option<&toml::value::Table>,
+    fields: &[Field],
+    prefix: &str,
+) -> Vec<Field> {
+    let mut ordered = fields
+        .iter()
+        .filter(|f| !f.skipped && !HIDDEN.contains(&join(prefix, &f.name).as_str()))
+        .cloned()
+        .collect::<Vec<_>>();
+    if let Some(table) = table {
+        for name in table.keys() {
+            if !ordered.iter().any(|f| &f.name == name) {
+                ordered.push(Field {
+                    name: name.clone(),
+                    ty: String::new(),
+                    doc: vec![],
+                    skipped: false,
+                });
+            }
+        }
+    }
+    ordered
+}
+
+fn comment(out: &mut String, doc: &[String]) {
+    for line in doc {
+        let _ = writeln!(out, "# {}", line);
+    }
+}
+
+struct Renderer<'a> {
+    structs: &'a HashMap<String, Vec<Field>>,
+    required: &'a HashSet<String>,
+}
+
+impl<'a> Renderer<'a> {
+    fn render(
+        &self,
+        out: &mut String,
+        table: &toml::value::Table,
+        struct_name: &str,
+        prefix: &str,
+    ) {
+        let fields = ordered_fields(
+            Some(table),
+            self.structs
+                .get(struct_name)
+                .map(Vec::as_slice)
+                .unwrap_or(&[]),
+            prefix,
+        );
+        let mut tables = Vec::new();
+        for field in fields.iter() {
+            let path = join(prefix, &field.name);
+            match table.get(&field.name) {
+                Some(toml::Value::Table(t)) => tables.push((field, t)),
+                Some(v) => {
+                    comment(out, &field.doc);
+                    if self.required.contains(&path) {
+                        let _ = writeln!(out, "# (required)");
+                    }
+                    let _ = writeln!(out, "{} = {}", key(&field.name), inline(v));
+                }
+                None => {
+                    comment(out, &field.doc);
+                    let _ = writeln!(out, "#{} = ...    # {}", key(&field.name), field.ty);
+                }
+            }
+        }
+        for (field, t) in tables {
+            let path = join(prefix, &field.name);
+            let _ = writeln!(out);
+            comment(out, &field.doc);
+            let _ = writeln!(out, "[{}]", path);
+            let nested = nested_struct(&field.ty, self.structs).unwrap_or_default();
+            self.render(out, t, &nested, &path);
+        }
+    }
+
+    fn schema(
+        &self,
+        table: Option<&toml::value::Table>,
+        struct_name: &str,
+        prefix: &str,
+    ) -> serde_json::Value {
+        let fields = ordered_fields(
+            table,
+            self.structs
+                .get(struct_name)
+                .map(Vec::as_slice)
+                .unwrap_or(&[]),
+            prefix,
+        );
+        let mut properties = serde_json::Map::new();
+        let mut required = Vec::new();
+        for field in fields.iter() {
+            let path = join(prefix, &field.name);
+            let value = table.and_then(|t| t.get(&field.name));
+            let nested = nested_struct(&field.ty, self.structs);
+            let mut property = match (value, nested) {
+                (Some(toml::Value::Table(t)), nested) => {
+                    self.schema(Some(t), &nested.unwrap_or_default(), &path)
+                }
+                (None, Some(ref nested)) if !field.ty.contains("Vec<") => {
+                    self.schema(None, nested, &path)
+                }
+                (value, _) => {
+                    let mut property = json!({
+                        "default": value
+                            .and_then(|v| serde_json::to_value(v).ok())
+                            .unwrap_or(serde_json::Value::Null),
+                    });
+                    if let Some(ty) = json_type(value, &field.ty) {
+                        property["type"] = json!(ty);
+                    }
+                    property
+                }
+            };
+            if !field.ty.is_empty() {
+                property["rust_type"] = json!(field.ty);
+            }
+            if !field.doc.is_empty() {
+                property["description"] = json!(field.doc.join(" "));
+            }
+            if self.required.contains(&path) {
+                required.push(json!(field.name));
+            }
+            properties.insert(field.name.clone(), property);
+        }
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        })
+    }
+}
+
+/// The JSON type of the field, judged by its default value, or, failing
+/// that, its declared type.
+fn json_type(value: Option<&toml::Value>, ty: &str) -> Option<&'static str> {
+    use toml::Value::*;
+    match value {
+        Some(String(_)) | Some(Datetime(_)) => Some("string"),
+        Some(Integer(_)) => Some("integer"),
+        Some(Float(_)) => Some("number"),
+        Some(Boolean(_)) => Some("boolean"),
+        Some(Array(_)) => Some("array"),
+        Some(Table(_)) => Some("object"),
+        None => {
+            let ty = ty.trim_start_matches("Option<").trim_end_matches('>');
+            match ty {
+                "bool" => Some("boolean"),
+                "f32" | "f64" => Some("number"),
+                "String" => Some("string"),
+                t if t.starts_with("Vec<") => Some("array"),
+                t if t.starts_with('u') || t.starts_with('i') => {
+                    if t[1..].chars().all(|c| c.is_ascii_digit()) || t.ends_with("size") {
+                        Some("integer")
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            }
+        }
+    }
+}
+
+/// A config for the job, with its defaults filled in, and its fields
+/// commented.
+pub fn default_config(job: Job) -> Result<String, Error> {
+    let (value, required) = defaults(job)?;
+    let structs = struct_fields(SOURCE);
+    let mut out = format!(
+        "# The default config for the {:?} job, as printed by `berbalang default-config`\n\n",
+        job
+    );
+    let table = value
+        .as_table()
+        .ok_or_else(|| Error::Misc("The config isn't a table".to_string()))?;
+    Renderer {
+        structs: &structs,
+        required: &required,
+    }
+    .render(&mut out, table, "Config", "");
+    Ok(out)
+}
+
+/// A JSON schema of the job's config, with the defaults and documentation
+/// of its fields.
+pub fn schema(job: Job) -> Result<serde_json::Value, Error> {
+    let (value, required) = defaults(job)?;
+    let structs = struct_fields(SOURCE);
+    let mut schema = Renderer {
+        structs: &structs,
+        required: &required,
+    }
+    .schema(value.as_table(), "Config", "");
+    schema["$schema"] = json!("http://json-schema.org/draft-07/schema#");
+    schema["title"] = json!(format!("berbalang config for the {:?} job", job));
+    Ok(schema)
+}
+
+/// The `default-config` subcommand:
+///
+/// ```text
+/// berbalang default-config [<job>] [--schema]
+/// ```
+pub fn run_cli(args: &[String]) -> Result<(), Error> {
+    let as_schema = args.iter().any(|a| a == "--schema");
+    let job = match args.iter().find(|a| !a.starts_with("--")) {
+        Some(name) => toml::Value::String(name.clone()).try_into::<Job>()?,
+        None => Job::default(),
+    };
+    if as_schema {
+        println!("{}", serde_json::to_string_pretty(&schema(job)?)?);
+    } else {
+        print!("{}", default_config(job)?);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_struct_fields() {
+        let structs = struct_fields(SOURCE);
+        let config = &structs["Config"];
+        let pop_size = config.iter().find(|f| f.name == "pop_size").unwrap();
+        assert_eq!(pop_size.ty, "usize");
+        let timeout = config.iter().find(|f| f.name == "timeout").unwrap();
+        assert!(timeout.doc[0].starts_with("The wall-clock budget"));
+        assert!(config
+            .iter()
+            .any(|f| f.name == "test_problems" && f.skipped));
+        let observer = config.iter().find(|f| f.name == "observer").unwrap();
+        assert_eq!(
+            nested_struct(&observer.ty, &structs),
+            Some("ObserverConfig".to_string())
+        );
+    }
+
+    #[test]
+    fn test_default_config() {
+        let text = default_config(Job::Roper).unwrap();
+        assert!(text.contains("\n[roper]\n"));
+        assert!(!text.contains("\n[hello]\n"));
+        assert!(text.contains("\npath_trie_every = 1\n"));
+        // it's a config that can be run as it is
+        assert!(toml::from_str::<Config>(&text).is_ok());
+
+        let schema = schema(Job::Roper).unwrap();
+        assert_eq!(
+            schema["properties"]["observer"]["properties"]["path_trie_every"]["default"],
+            json!(1)
+        );
+        assert!(schema["required"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("pop_size")));
+    }
+}
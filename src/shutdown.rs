@@ -0,0 +1,87 @@
+//! Stopping a run cleanly when it's sent SIGINT or SIGTERM. Once either
+//! arrives, `keep_going` fails, so that each island finishes the
+//! tournament under way -- its evaluations in flight included -- and starts
+//! no more, and each observer, once its island has stopped, dumps the
+//! island's best specimen, population, and soup, and writes its report, as
+//! it would on a timeout. The process waits for the observers before it
+//! exits. A second signal exits at once, for a run that won't wind down.
+use std::sync::atomic::{self, AtomicBool, AtomicUsize};
+use std::time::{Duration, Instant};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static LIVE_OBSERVERS: AtomicUsize = AtomicUsize::new(0);
+
+/// The status a second signal exits with, as a shell reports a process
+/// killed by SIGINT.
+#[cfg(unix)]
+const FORCED_EXIT_STATUS: i32 = 130;
+
+#[cfg(unix)]
+extern "C" fn handle_signal(_signal: libc::c_int) {
+    // only async-signal-safe calls are made here
+    if REQUESTED.swap(true, atomic::Ordering::SeqCst) {
+        unsafe { libc::_exit(FORCED_EXIT_STATUS) }
+    }
+}
+
+/// Installs the handlers of SIGINT and SIGTERM.
+#[cfg(unix)]
+pub fn install() {
+    let handler = handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    for signal in &[libc::SIGINT, libc::SIGTERM] {
+        if unsafe { libc::signal(*signal, handler) } == libc::SIG_ERR {
+            log::warn!("Failed to install the handler of signal {}", signal);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install() {
+    log::warn!("Graceful shutdown on signals is only supported on Unix");
+}
+
+/// Whether the run has been asked to stop, by a signal.
+pub fn requested() -> bool {
+    REQUESTED.load(atomic::Ordering::Relaxed)
+}
+
+/// Held by each observer's thread for as long as it runs, so that the
+/// process can wait for the observers to finish writing before it exits.
+pub struct ObserverGuard(());
+
+impl ObserverGuard {
+    /// This should be called before the observer's thread is spawned, so
+    /// that it's counted even if the run ends before the thread starts.
+    pub fn acquire() -> Self {
+        LIVE_OBSERVERS.fetch_add(1, atomic::Ordering::SeqCst);
+        ObserverGuard(())
+    }
+}
+
+impl Drop for ObserverGuard {
+    fn drop(&mut self) {
+        LIVE_OBSERVERS.fetch_sub(1, atomic::Ordering::SeqCst);
+    }
+}
+
+/// Waits until every observer has finished, or `limit` has passed,
+/// returning whether they all finished.
+pub fn wait_for_observers(limit: Duration) -> bool {
+    let started = Instant::now();
+    loop {
+        let live = LIVE_OBSERVERS.load(atomic::Ordering::SeqCst);
+        if live == 0 {
+            return true;
+        }
+        if started.elapsed() > limit {
+            log::error!(
+                "Gave up waiting for {} observers to finish, after {:?}",
+                live,
+                limit
+            );
+            return false;
+        }
+        log::debug!("Waiting for {} observers to finish", live);
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
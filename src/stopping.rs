@@ -0,0 +1,256 @@
+//! The criteria, configured in `stopping`, on which an island ends its run
+//! early. The island's budgets -- of wall-clock time, evaluations, and time
+//! in the emulator -- are checked on every specimen observed, and the rest
+//! whenever the window's statistics are taken.
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::configure::StoppingCriterion;
+use crate::error::Error;
+use crate::observer::WindowStats;
+
+pub struct Stopper {
+    criteria: Vec<StoppingCriterion>,
+    started: Instant,
+    budget: Option<Duration>,
+    evaluations: Option<usize>,
+    emulator_time: Option<Duration>,
+}
+
+fn parse(duration: &str) -> Result<Duration, Error> {
+    parse_duration::parse(duration)
+        .map_err(|e| Error::Parsing(format!("Invalid budget {:?}: {}", duration, e)))
+}
+
+/// Checks that the budgets' durations can be parsed.
+pub fn check_criteria(criteria: &[StoppingCriterion]) -> Result<(), Error> {
+    for criterion in criteria {
+        match criterion {
+            StoppingCriterion::WallClock(d) | StoppingCriterion::EmulatorTime(d) => {
+                parse(d)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+impl Stopper {
+    pub fn new(criteria: &[StoppingCriterion]) -> Self {
+        let durations = |wanted: fn(&StoppingCriterion) -> Option<&String>| {
+            criteria
+                .iter()
+                .filter_map(wanted)
+                .map(|d| parse(d).expect("The budgets were checked when the config was loaded"))
+                .min()
+        };
+        let budget = durations(|c| match c {
+            StoppingCriterion::WallClock(d) => Some(d),
+            _ => None,
+        });
+        let emulator_time = durations(|c| match c {
+            StoppingCriterion::EmulatorTime(d) => Some(d),
+            _ => None,
+        });
+        let evaluations = criteria
+            .iter()
+            .filter_map(|c| match c {
+                StoppingCriterion::Evaluations(n) => Some(*n),
+                _ => None,
+            })
+            .min();
+        Self {
+            criteria: criteria.to_vec(),
+            started: Instant::now(),
+            budget,
+            evaluations,
+            emulator_time,
+        }
+    }
+
+    /// Whether the wall-clock budget, if there is one, has been spent.
+    pub fn is_out_of_time(&self) -> bool {
+        self.budget
+            .map(|budget| self.started.elapsed() >= budget)
+            .unwrap_or(false)
+    }
+
+    /// Whether the island has a budget of time in the emulator, which
+    /// needs the island's emulation time to check.
+    pub fn has_emulator_budget(&self) -> bool {
+        self.emulator_time.is_some()
+    }
+
+    /// Describes the budget the island has spent, if it's spent any, given
+    /// the specimens it has evaluated, and the time it has spent in the
+    /// emulator.
+    pub fn spent_budget(&self, evaluations: usize, emulator_time: Duration) -> Option<String> {
+        if self.is_out_of_time() {
+            return Some("wall-clock budget spent".to_string());
+        }
+        if let Some(budget) = self.evaluations {
+            if evaluations >= budget {
+                return Some(format!("budget of {} evaluations spent", budget));
+            }
+        }
+        if let Some(budget) = self.emulator_time {
+            if emulator_time >= budget {
+                return Some(format!("budget of {:?} in the emulator spent", budget));
+            }
+        }
+        None
+    }
+
+    /// Returns the reason to stop, if any of the criteria that bear on the
+    /// window's statistics are met. `stagnant_epochs` is the number of
+    /// epochs since the best scalar fitness improved, and `coverage` the
+    /// highest `code_coverage` score in the window, if any were scored.
+    pub fn check(
+        &self,
+        stats: &WindowStats,
+        stagnant_epochs: usize,
+        coverage: Option<f64>,
+    ) -> Option<String> {
+        self.criteria.iter().find_map(|criterion| match criterion {
+            StoppingCriterion::Plateau(epochs) if stagnant_epochs >= *epochs => Some(format!(
+                "best fitness has plateaued for {} epochs",
+                stagnant_epochs
+            )),
+            StoppingCriterion::Coverage(target) => match coverage {
+                Some(coverage) if coverage >= *target => {
+                    Some(format!("code coverage of {} reached", coverage))
+                }
+                _ => None,
+            },
+            StoppingCriterion::Predicate(expr) => {
+                let mut ns = namespace(stats, stagnant_epochs);
+                match fasteval::ez_eval(expr, &mut ns) {
+                    Ok(res) if res != 0.0 => Some(format!("stopping predicate {:?} holds", expr)),
+                    Ok(_) => None,
+                    Err(e) => {
+                        log::error!("Failed to evaluate stopping predicate {:?}: {:?}", expr, e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        })
+    }
+}
+
+/// The variables a stopping predicate can refer to: the scalar fields of
+/// the window's statistics, by name; `mean_`, `best_`, `p10_`, `p50_`, and
+/// `p90_` followed by the name of each fitness component; the values of
+/// the statistics enabled in `observer.window_statistics`, by name; and
+/// `stagnant_epochs`.
+pub fn namespace(stats: &WindowStats, stagnant_epochs: usize) -> BTreeMap<String, f64> {
+    let mut ns = BTreeMap::new();
+    let fields = [
+        ("counter", stats.counter as f64),
+        ("epoch", stats.epoch as f64),
+        ("size", stats.size as f64),
+        ("mean_length", stats.mean_length),
+        ("min_fitness", stats.min_fitness),
+        ("mean_fitness", stats.mean_fitness),
+        ("max_fitness", stats.max_fitness),
+        ("uniqueness", stats.uniqueness),
+        ("genotypic_entropy", stats.genotypic_entropy),
+        ("mean_genome_distance", stats.mean_genome_distance),
+        ("path_diversity", stats.path_diversity),
+        ("stagnant_epochs", stagnant_epochs as f64),
+    ];
+    for (key, value) in fields.iter() {
+        ns.insert(key.to_string(), *value);
+    }
+    for (key, value) in stats.custom.iter() {
+        ns.insert(key.clone(), *value);
+    }
+    for (key, value) in stats.mean_scores.iter() {
+        ns.insert(format!("mean_{}", key), *value);
+    }
+    for (key, value) in stats.best_scores.iter() {
+        ns.insert(format!("best_{}", key), *value);
+    }
+    for (key, p) in stats.score_percentiles.iter() {
+        ns.insert(format!("p10_{}", key), p.p10);
+        ns.insert(format!("p50_{}", key), p.p50);
+        ns.insert(format!("p90_{}", key), p.p90);
+    }
+    ns
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stats() -> WindowStats {
+        WindowStats {
+            island: 0,
+            counter: 1000,
+            epoch: 10,
+            size: 100,
+            mean_length: 20.0,
+            min_fitness: 0.5,
+            mean_fitness: 0.75,
+            max_fitness: 1.0,
+            uniqueness: 0.04,
+            genotypic_entropy: 3.0,
+            mean_genome_distance: 0.2,
+            path_diversity: std::f64::NAN,
+            mean_scores: vec![("ret_count".to_string(), 4.0)].into_iter().collect(),
+            best_scores: vec![("ret_count".to_string(), 1.0)].into_iter().collect(),
+            score_percentiles: BTreeMap::new(),
+            histograms: BTreeMap::new(),
+            custom: vec![("scored_fraction".to_string(), 0.5)]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let stats = stats();
+        let stopper = Stopper::new(&[
+            StoppingCriterion::Plateau(20),
+            StoppingCriterion::Coverage(0.5),
+        ]);
+        assert!(stopper.check(&stats, 19, Some(0.49)).is_none());
+        assert!(stopper.check(&stats, 19, None).is_none());
+        assert!(stopper.check(&stats, 20, None).is_some());
+        assert!(stopper.check(&stats, 0, Some(0.5)).is_some());
+        assert!(!stopper.is_out_of_time());
+
+        let stopper = Stopper::new(&[StoppingCriterion::Predicate(
+            "uniqueness < 0.05 && mean_ret_count > 3".to_string(),
+        )]);
+        assert!(stopper.check(&stats, 0, None).is_some());
+        let stopper = Stopper::new(&[StoppingCriterion::Predicate(
+            "best_ret_count < 1".to_string(),
+        )]);
+        assert!(stopper.check(&stats, 0, None).is_none());
+        let stopper = Stopper::new(&[StoppingCriterion::Predicate(
+            "scored_fraction >= 0.5".to_string(),
+        )]);
+        assert!(stopper.check(&stats, 0, None).is_some());
+
+        let stopper = Stopper::new(&[StoppingCriterion::WallClock("0s".to_string())]);
+        assert!(stopper.is_out_of_time());
+    }
+
+    #[test]
+    fn test_spent_budget() {
+        let stopper = Stopper::new(&[
+            StoppingCriterion::Evaluations(1000),
+            StoppingCriterion::EmulatorTime("10s".to_string()),
+            StoppingCriterion::Evaluations(2000),
+        ]);
+        assert!(stopper.has_emulator_budget());
+        assert!(stopper.spent_budget(999, Duration::from_secs(9)).is_none());
+        assert_eq!(
+            stopper.spent_budget(1000, Duration::from_secs(0)),
+            Some("budget of 1000 evaluations spent".to_string())
+        );
+        assert!(stopper.spent_budget(0, Duration::from_secs(10)).is_some());
+        assert!(check_criteria(&[StoppingCriterion::EmulatorTime("soon".to_string())]).is_err());
+    }
+}
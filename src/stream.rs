@@ -0,0 +1,268 @@
+//! Streams the observation records as they're produced, so that external
+//! tools can consume them live, without polling the data directory. The
+//! records are the versioned JSON objects that the "jsonl" sink writes to
+//! `observations.jsonl`.
+//!
+//! With the "zmq" feature, the "zmq" sink publishes them on a ZeroMQ PUB
+//! socket bound to `observer.zmq_endpoint`, as two-part messages: the kind
+//! of record, which subscribers can filter on, and the record itself. With
+//! the "websocket" feature, the "websocket" sink sends them, as text
+//! messages, to every client connected to `observer.websocket_address`.
+//! Either way, the socket is shared by all the islands in the process, and
+//! records are dropped when no one is listening, or, for a WebSocket
+//! client, when it falls behind.
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::configure::Config;
+use crate::evolution::{Genome, Phenome};
+use crate::observer::{JsonlRecord, OperatorStats, Sink, SpecimenRecord, WindowStats};
+
+pub trait Publish: Send + Sync {
+    fn publish(&self, record: &str, json: &str);
+}
+
+pub struct StreamSink {
+    config: Arc<Config>,
+    publisher: &'static dyn Publish,
+}
+
+impl StreamSink {
+    fn send<T: Serialize>(&self, record: &'static str, body: &T) {
+        match serde_json::to_string(&JsonlRecord::new(record, body)) {
+            Ok(json) => self.publisher.publish(record, &json),
+            Err(e) => log::error!("Failed to serialize {} record: {:?}", record, e),
+        }
+    }
+}
+
+impl<O: Phenome + Genome> Sink<O> for StreamSink {
+    fn record_stats(&mut self, stats: &WindowStats) {
+        self.send("window", stats)
+    }
+
+    fn record_champion(&mut self, champion: &O, stats: &WindowStats) {
+        self.send(
+            "champion",
+            &SpecimenRecord::of(champion, stats.counter, stats.epoch, &self.config),
+        )
+    }
+
+    fn record_operators(&mut self, stats: &OperatorStats) {
+        self.send("operators", stats)
+    }
+}
+
+#[cfg(feature = "zmq")]
+mod zmq_publisher {
+    use std::sync::{Arc, Mutex, Once};
+
+    use super::{Publish, StreamSink};
+    use crate::configure::Config;
+
+    struct ZmqPublisher {
+        socket: Mutex<zmq::Socket>,
+    }
+
+    impl Publish for ZmqPublisher {
+        fn publish(&self, record: &str, json: &str) {
+            let socket = self.socket.lock().expect("poisoned");
+            let parts = [record.as_bytes(), json.as_bytes()];
+            if let Err(e) = socket.send_multipart(&parts, zmq::DONTWAIT) {
+                log::debug!("Failed to publish {} record: {:?}", record, e);
+            }
+        }
+    }
+
+    static INIT_PUBLISHER: Once = Once::new();
+    static mut PUBLISHER: Option<ZmqPublisher> = None;
+
+    impl StreamSink {
+        /// Binds the process's PUB socket the first time it's called.
+        pub fn zmq(config: Arc<Config>) -> Self {
+            INIT_PUBLISHER.call_once(|| {
+                let endpoint = &config.observer.zmq_endpoint;
+                let socket = zmq::Context::new()
+                    .socket(zmq::PUB)
+                    .expect("Failed to create ZeroMQ socket");
+                socket
+                    .bind(endpoint)
+                    .unwrap_or_else(|e| panic!("Failed to bind {}: {:?}", endpoint, e));
+                log::info!("Publishing observations on {}", endpoint);
+                unsafe {
+                    PUBLISHER = Some(ZmqPublisher {
+                        socket: Mutex::new(socket),
+                    })
+                }
+            });
+            let publisher = unsafe {
+                PUBLISHER
+                    .as_ref()
+                    .expect("The ZeroMQ publisher has not been initialized")
+            };
+            Self { config, publisher }
+        }
+    }
+}
+
+#[cfg(feature = "websocket")]
+mod websocket_publisher {
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+    use std::sync::{Arc, Mutex, Once};
+    use std::thread::spawn;
+    use std::time::Duration;
+
+    use tungstenite::Message;
+
+    use super::{Publish, StreamSink};
+    use crate::configure::Config;
+
+    /// The records queued for each client, beyond which its records are
+    /// dropped until it catches up.
+    const CLIENT_QUEUE_CAPACITY: usize = 1024;
+    /// How long a write to a client may block before the client is taken to
+    /// have stopped reading, and is disconnected.
+    const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Each client is written to from its own thread, through a bounded
+    /// queue, so that a client that stops reading holds up no one else.
+    fn connect(stream: TcpStream) -> Result<SyncSender<String>, String> {
+        stream
+            .set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))
+            .map_err(|e| format!("{:?}", e))?;
+        let mut ws = tungstenite::accept(stream).map_err(|e| e.to_string())?;
+        let (tx, rx) = sync_channel::<String>(CLIENT_QUEUE_CAPACITY);
+        spawn(move || {
+            for json in rx {
+                if let Err(e) = ws.write_message(Message::Text(json)) {
+                    log::debug!("Dropping WebSocket client: {:?}", e);
+                    return;
+                }
+            }
+            // the publisher is gone
+            let _ = ws.close(None);
+        });
+        Ok(tx)
+    }
+
+    struct WebSocketPublisher {
+        clients: Arc<Mutex<Vec<SyncSender<String>>>>,
+    }
+
+    impl WebSocketPublisher {
+        fn start(address: &str) -> Self {
+            let listener = TcpListener::bind(address)
+                .unwrap_or_else(|e| panic!("Failed to bind {}: {:?}", address, e));
+            log::info!(
+                "Streaming observations to WebSocket clients at ws://{}/",
+                address
+            );
+            let clients = Arc::new(Mutex::new(Vec::new()));
+            let accepted = clients.clone();
+            spawn(move || {
+                for stream in listener.incoming() {
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            log::warn!("WebSocket connection failed: {:?}", e);
+                            continue;
+                        }
+                    };
+                    match connect(stream) {
+                        Ok(client) => accepted.lock().expect("poisoned").push(client),
+                        Err(e) => log::warn!("WebSocket handshake failed: {}", e),
+                    }
+                }
+            });
+            Self { clients }
+        }
+    }
+
+    impl Publish for WebSocketPublisher {
+        /// Queues the record for each client, without waiting on any of
+        /// them. A client whose queue is full misses the record, and one
+        /// whose writer has given up on it is dropped.
+        fn publish(&self, record: &str, json: &str) {
+            let mut clients = self.clients.lock().expect("poisoned");
+            let mut i = 0;
+            while i < clients.len() {
+                match clients[i].try_send(json.to_string()) {
+                    Err(TrySendError::Disconnected(_)) => {
+                        clients.swap_remove(i);
+                        continue;
+                    }
+                    Err(TrySendError::Full(_)) => {
+                        log::debug!("WebSocket client lagging, dropped a {} record", record)
+                    }
+                    Ok(()) => {}
+                }
+                i += 1;
+            }
+        }
+    }
+
+    static INIT_PUBLISHER: Once = Once::new();
+    static mut PUBLISHER: Option<WebSocketPublisher> = None;
+
+    impl StreamSink {
+        /// Starts the process's WebSocket server the first time it's called.
+        pub fn websocket(config: Arc<Config>) -> Self {
+            INIT_PUBLISHER.call_once(|| {
+                let publisher = WebSocketPublisher::start(&config.observer.websocket_address);
+                unsafe { PUBLISHER = Some(publisher) }
+            });
+            let publisher = unsafe {
+                PUBLISHER
+                    .as_ref()
+                    .expect("The WebSocket publisher has not been initialized")
+            };
+            Self { config, publisher }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        sent: Mutex<Vec<(String, String)>>,
+    }
+
+    impl Publish for Recorder {
+        fn publish(&self, record: &str, json: &str) {
+            self.sent
+                .lock()
+                .unwrap()
+                .push((record.to_string(), json.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_stream_sink() {
+        let recorder: &'static Recorder = Box::leak(Box::new(Recorder::default()));
+        let sink = StreamSink {
+            config: Arc::new(Config::default()),
+            publisher: recorder,
+        };
+        let stats = OperatorStats {
+            island: 2,
+            counter: 100,
+            epoch: 1,
+            counts: vec![("clone".to_string(), 3)].into_iter().collect(),
+        };
+        sink.send("operators", &stats);
+        let sent = recorder.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, "operators");
+        let value: serde_json::Value = serde_json::from_str(&sent[0].1).unwrap();
+        assert_eq!(value["record"], "operators");
+        assert_eq!(value["island"], 2);
+        assert_eq!(value["counts"]["clone"], 3);
+    }
+}
@@ -0,0 +1,421 @@
+//! The summary of an island's run, written when the run ends to
+//! `report.md` and `report.html` in the island's data directory, so that
+//! the results can be shared as they are. It gives the outcome and the key
+//! statistics of the run, a plot of the fitness over the epochs, which the
+//! HTML report inlines and the Markdown report refers to, as
+//! `fitness.svg`, a listing of the champion, and a table of the config.
+use std::fmt::Write;
+use std::fs;
+use std::sync::{Mutex, Once};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::configure::Config;
+use crate::error::Error;
+use crate::observer::WindowStats;
+
+/// Config values longer than this are elided in the table.
+const MAX_CONFIG_VALUE_LEN: usize = 80;
+
+/// The window statistics kept for each epoch, for the summary's plot.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct HistoryPoint {
+    pub epoch: usize,
+    pub counter: usize,
+    pub min_fitness: f64,
+    pub mean_fitness: f64,
+    pub uniqueness: f64,
+}
+
+impl HistoryPoint {
+    pub fn of(stats: &WindowStats) -> Self {
+        Self {
+            epoch: stats.epoch,
+            counter: stats.counter,
+            min_fitness: stats.min_fitness,
+            mean_fitness: stats.mean_fitness,
+            uniqueness: stats.uniqueness,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChampionSummary {
+    pub name: String,
+    pub scalar_fitness: Option<f64>,
+    pub scores: Vec<(String, f64)>,
+    /// The champion's annotated layout, effective code, or description
+    pub listing: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct RunSummary {
+    pub population_name: String,
+    pub island: usize,
+    pub outcome: String,
+    pub counter: usize,
+    pub epoch: usize,
+    pub elapsed: Duration,
+    /// Further statistics, by name, such as the champion's scores on the
+    /// held-out data
+    pub statistics: Vec<(String, String)>,
+    pub champion: Option<ChampionSummary>,
+    pub history: Vec<HistoryPoint>,
+    pub config: Vec<(String, String)>,
+}
+
+static INIT_RECORDED: Once = Once::new();
+static mut RECORDED: Option<Mutex<Vec<RunSummary>>> = None;
+
+fn recorded() -> &'static Mutex<Vec<RunSummary>> {
+    INIT_RECORDED.call_once(|| unsafe { RECORDED = Some(Mutex::new(Vec::new())) });
+    unsafe {
+        RECORDED
+            .as_ref()
+            .expect("The recorded summaries have not been initialized")
+    }
+}
+
+/// Keeps the summary of an island's run, for the outcome that
+/// `Berbalang::run` returns.
+pub fn record(summary: RunSummary) {
+    recorded().lock().expect("poisoned").push(summary)
+}
+
+/// Takes the summaries recorded so far, in order of their islands.
+pub fn take_recorded() -> Vec<RunSummary> {
+    let mut summaries = std::mem::replace(&mut *recorded().lock().expect("poisoned"), Vec::new());
+    summaries.sort_by_key(|s| s.island);
+    summaries
+}
+
+/// Flattens the config into a table of dotted keys and their values,
+/// leaving out the empty ones, and eliding the long ones.
+pub fn config_table(config: &Config) -> Vec<(String, String)> {
+    fn flatten(prefix: &str, value: &serde_json::Value, table: &mut Vec<(String, String)>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map.iter() {
+                    let key = if prefix.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", prefix, key)
+                    };
+                    flatten(&key, value, table)
+                }
+            }
+            serde_json::Value::Null => {}
+            value => {
+                let mut s = value.to_string();
+                if s.len() > MAX_CONFIG_VALUE_LEN {
+                    let mut end = MAX_CONFIG_VALUE_LEN;
+                    while !s.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    s.truncate(end);
+                    s.push_str("...");
+                }
+                table.push((prefix.to_string(), s))
+            }
+        }
+    }
+    let mut table = Vec::new();
+    match serde_json::to_value(config) {
+        Ok(value) => flatten("", &value, &mut table),
+        Err(e) => log::error!("Failed to serialize config for the summary: {:?}", e),
+    }
+    table
+}
+
+/// Plots the best and mean fitness, and the uniqueness, over the epochs,
+/// as an SVG line chart. The fitness is scaled to its range, which is
+/// labelled, and the uniqueness, which lies between 0 and 1, to the height
+/// of the chart.
+pub fn fitness_plot(history: &[HistoryPoint]) -> String {
+    const WIDTH: f64 = 640.0;
+    const HEIGHT: f64 = 320.0;
+    const MARGIN: f64 = 40.0;
+    let finite = |x: f64| if x.is_finite() { Some(x) } else { None };
+    let fitnesses = history
+        .iter()
+        .flat_map(|p| vec![p.min_fitness, p.mean_fitness])
+        .filter_map(finite)
+        .collect::<Vec<f64>>();
+    let lo = fitnesses.iter().cloned().fold(std::f64::INFINITY, f64::min);
+    let hi = fitnesses
+        .iter()
+        .cloned()
+        .fold(std::f64::NEG_INFINITY, f64::max);
+    let (lo, hi) = if lo < hi {
+        (lo, hi)
+    } else if lo.is_finite() {
+        (lo - 0.5, lo + 0.5)
+    } else {
+        (0.0, 1.0)
+    };
+    let first = history.first().map(|p| p.epoch).unwrap_or(0) as f64;
+    let last = history.last().map(|p| p.epoch).unwrap_or(0) as f64;
+    let span = if last > first { last - first } else { 1.0 };
+    let x = |epoch: usize| MARGIN + (epoch as f64 - first) / span * (WIDTH - 2.0 * MARGIN);
+    let y =
+        |v: f64, lo: f64, hi: f64| HEIGHT - MARGIN - (v - lo) / (hi - lo) * (HEIGHT - 2.0 * MARGIN);
+    let line = |f: &dyn Fn(&HistoryPoint) -> f64, lo: f64, hi: f64| {
+        history
+            .iter()
+            .filter_map(|p| finite(f(p)).map(|v| format!("{:.1},{:.1}", x(p.epoch), y(v, lo, hi))))
+            .collect::<Vec<String>>()
+            .join(" ")
+    };
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" "#,
+            r#"font-family="sans-serif" font-size="11">"#
+        ),
+        w = WIDTH,
+        h = HEIGHT
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect x="{m}" y="{m}" width="{w}" height="{h}" fill="none" stroke="#999"/>"##,
+        m = MARGIN,
+        w = WIDTH - 2.0 * MARGIN,
+        h = HEIGHT - 2.0 * MARGIN
+    );
+    for (label, colour, points) in [
+        ("best", "#1f77b4", line(&|p| p.min_fitness, lo, hi)),
+        ("mean", "#ff7f0e", line(&|p| p.mean_fitness, lo, hi)),
+        ("uniqueness", "#2ca02c", line(&|p| p.uniqueness, 0.0, 1.0)),
+    ]
+    .iter()
+    {
+        let _ = writeln!(
+            svg,
+            r#"<polyline points="{}" fill="none" stroke="{}"><title>{}</title></polyline>"#,
+            points, colour, label
+        );
+    }
+    let _ = writeln!(
+        svg,
+        r#"<text x="{m}" y="{t}">{hi:.4}</text><text x="{m}" y="{b}">{lo:.4}</text>"#,
+        m = 2.0,
+        t = MARGIN,
+        b = HEIGHT - MARGIN,
+        hi = hi,
+        lo = lo
+    );
+    let _ = writeln!(
+        svg,
+        concat!(
+            r#"<text x="{l}" y="{y}">epoch {first}</text>"#,
+            r#"<text x="{r}" y="{y}" text-anchor="end">epoch {last}</text>"#
+        ),
+        l = MARGIN,
+        r = WIDTH - MARGIN,
+        y = HEIGHT - MARGIN / 2.0,
+        first = first,
+        last = last
+    );
+    let _ = writeln!(
+        svg,
+        concat!(
+            r##"<text x="{m}" y="{y}"><tspan fill="#1f77b4">best</tspan> "##,
+            r##"<tspan fill="#ff7f0e">mean</tspan> fitness, "##,
+            r##"<tspan fill="#2ca02c">uniqueness</tspan></text>"##
+        ),
+        m = MARGIN,
+        y = MARGIN - 8.0
+    );
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn fmt_option(x: Option<f64>) -> String {
+    x.map(|x| x.to_string()).unwrap_or_else(|| "-".to_string())
+}
+
+impl RunSummary {
+    fn key_statistics(&self) -> Vec<(String, String)> {
+        let mut stats = vec![
+            ("Outcome".to_string(), self.outcome.clone()),
+            ("Specimens observed".to_string(), self.counter.to_string()),
+            ("Epochs".to_string(), self.epoch.to_string()),
+            ("Elapsed".to_string(), format!("{:?}", self.elapsed)),
+        ];
+        if let Some(last) = self.history.last() {
+            stats.push((
+                "Final best fitness".to_string(),
+                last.min_fitness.to_string(),
+            ));
+            stats.push((
+                "Final mean fitness".to_string(),
+                last.mean_fitness.to_string(),
+            ));
+            stats.push(("Final uniqueness".to_string(), last.uniqueness.to_string()));
+        }
+        stats.extend(self.statistics.iter().cloned());
+        stats
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
+        let _ = writeln!(md, "# {}, island {}\n", self.population_name, self.island);
+        md.push_str("## Key statistics\n\n| | |\n|---|---|\n");
+        for (key, value) in self.key_statistics() {
+            let _ = writeln!(md, "| {} | {} |", key, value);
+        }
+        md.push_str("\n## Fitness\n\n![Fitness over the epochs](fitness.svg)\n\n");
+        md.push_str("## Champion\n\n");
+        match self.champion {
+            Some(ref champion) => {
+                let _ = writeln!(
+                    md,
+                    "`{}`, with scalar fitness {}\n",
+                    champion.name,
+                    fmt_option(champion.scalar_fitness)
+                );
+                if !champion.scores.is_empty() {
+                    md.push_str("| Objective | Score |\n|---|---|\n");
+                    for (key, score) in champion.scores.iter() {
+                        let _ = writeln!(md, "| {} | {} |", key, score);
+                    }
+                    md.push('\n');
+                }
+                let _ = writeln!(md, "```text\n{}\n```\n", champion.listing.trim_end());
+            }
+            None => md.push_str("No champion was found.\n\n"),
+        }
+        md.push_str("## Config\n\n| Key | Value |\n|---|---|\n");
+        for (key, value) in self.config.iter() {
+            let _ = writeln!(md, "| `{}` | `{}` |", key, value.replace('|', "\\|"));
+        }
+        md
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        let title = escape_html(&format!("{}, island {}", self.population_name, self.island));
+        let _ = writeln!(
+            html,
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{t}</title>\
+             <style>body{{font-family:sans-serif;max-width:60em;margin:auto}}\
+             td,th{{border:1px solid #ccc;padding:2px 6px;text-align:left}}\
+             table{{border-collapse:collapse}}pre{{background:#f6f6f6;padding:1em;\
+             overflow-x:auto}}</style></head><body>\n<h1>{t}</h1>",
+            t = title
+        );
+        html.push_str("<h2>Key statistics</h2>\n<table>\n");
+        for (key, value) in self.key_statistics() {
+            let _ = writeln!(
+                html,
+                "<tr><th>{}</th><td>{}</td></tr>",
+                escape_html(&key),
+                escape_html(&value)
+            );
+        }
+        html.push_str("</table>\n<h2>Fitness</h2>\n");
+        html.push_str(&fitness_plot(&self.history));
+        html.push_str("<h2>Champion</h2>\n");
+        match self.champion {
+            Some(ref champion) => {
+                let _ = writeln!(
+                    html,
+                    "<p><code>{}</code>, with scalar fitness {}</p>",
+                    escape_html(&champion.name),
+                    fmt_option(champion.scalar_fitness)
+                );
+                if !champion.scores.is_empty() {
+                    html.push_str("<table>\n<tr><th>Objective</th><th>Score</th></tr>\n");
+                    for (key, score) in champion.scores.iter() {
+                        let _ = writeln!(
+                            html,
+                            "<tr><td>{}</td><td>{}</td></tr>",
+                            escape_html(key),
+                            score
+                        );
+                    }
+                    html.push_str("</table>\n");
+                }
+                let _ = writeln!(html, "<pre>{}</pre>", escape_html(&champion.listing));
+            }
+            None => html.push_str("<p>No champion was found.</p>\n"),
+        }
+        html.push_str("<h2>Config</h2>\n<table>\n");
+        for (key, value) in self.config.iter() {
+            let _ = writeln!(
+                html,
+                "<tr><td><code>{}</code></td><td><code>{}</code></td></tr>",
+                escape_html(key),
+                escape_html(value)
+            );
+        }
+        html.push_str("</table>\n</body></html>\n");
+        html
+    }
+
+    /// Writes `report.md`, `report.html`, and `fitness.svg` to the
+    /// directory.
+    pub fn write(&self, dir: &str) -> Result<(), Error> {
+        fs::write(format!("{}/report.md", dir), self.to_markdown())?;
+        fs::write(format!("{}/report.html", dir), self.to_html())?;
+        fs::write(format!("{}/fitness.svg", dir), fitness_plot(&self.history))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let history = (0..5)
+            .map(|epoch| HistoryPoint {
+                epoch,
+                counter: epoch * 100,
+                min_fitness: 1.0 / (epoch + 1) as f64,
+                mean_fitness: 2.0 / (epoch + 1) as f64,
+                uniqueness: if epoch == 0 { std::f64::NAN } else { 0.5 },
+            })
+            .collect::<Vec<_>>();
+        let mut config = Config::default();
+        config.observer.population_name = "test-pop".to_string();
+        let summary = RunSummary {
+            population_name: config.observer.population_name.clone(),
+            island: 1,
+            outcome: "epoch limit reached".to_string(),
+            counter: 500,
+            epoch: 5,
+            elapsed: Duration::from_secs(90),
+            statistics: vec![],
+            champion: Some(ChampionSummary {
+                name: "alice".to_string(),
+                scalar_fitness: Some(0.2),
+                scores: vec![("ret_count".to_string(), 3.0)],
+                listing: "mov <rax>, 1".to_string(),
+            }),
+            history,
+            config: config_table(&config),
+        };
+        let md = summary.to_markdown();
+        assert!(md.starts_with("# test-pop, island 1"));
+        assert!(md.contains("| Outcome | epoch limit reached |"));
+        assert!(md.contains("| ret_count | 3 |"));
+        assert!(md.contains("| `observer.population_name` | `\"test-pop\"` |"));
+        let html = summary.to_html();
+        assert!(html.contains("<svg"));
+        assert!(html.contains("mov &lt;rax&gt;, 1"));
+        // the NaN uniqueness of the first epoch is left out of the plot
+        let svg = fitness_plot(&summary.history);
+        assert_eq!(svg.matches("<polyline").count(), 3);
+        assert!(!svg.contains("NaN"));
+    }
+}
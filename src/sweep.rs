@@ -0,0 +1,378 @@
+//! Parameter sweeps. A sweep spec names a base config, and lists values for
+//! some of its fields, by their dotted paths, as taken by `--set`:
+//!
+//! ```text
+//! name = "pop_vs_weighting"
+//! base = "config.toml"
+//! mode = "Grid"             # or { Random = 20 }
+//! seeds = 5
+//! parallel = 2
+//! data_directory = "~/logs/sweeps"
+//!
+//! [parameters]
+//! pop_size = [100, 500, 1000]
+//! "fitness.weighting" = ["place", "place + ret_count"]
+//! ```
+//!
+//! Each configuration the sweep resolves to -- every combination of the
+//! values, in a grid sweep, or so many random picks among them, in a random
+//! one -- is run `seeds` times, each with its own random seed, as a child
+//! process, with up to `parallel` running at once. The runs are all laid
+//! out under `{data_directory}/{name}`, where `sweep.tsv` records which run
+//! was which, and `summary.tsv`, written once they're done, compares the
+//! configurations, as `berbalang aggregate` would.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::channel;
+
+use rand::Rng;
+use serde::Deserialize;
+use threadpool::ThreadPool;
+
+use crate::configure::{self, interpolate, ConfigFormat};
+use crate::error::Error;
+use crate::experiments;
+use crate::util::random::hash_seed_rng;
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub enum SweepMode {
+    /// Every combination of the listed values
+    Grid,
+    /// So many configurations, each with its values picked at random from
+    /// those listed
+    Random(usize),
+}
+
+impl Default for SweepMode {
+    fn default() -> Self {
+        Self::Grid
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SweepSpec {
+    /// Prefixes the population names of the runs, and names the directory
+    /// they're laid out in
+    pub name: String,
+    /// The base config, relative to the spec
+    pub base: String,
+    #[serde(default)]
+    pub mode: SweepMode,
+    /// The number of runs of each configuration
+    #[serde(default = "default_one")]
+    pub seeds: usize,
+    /// The number of runs at once
+    #[serde(default = "default_one")]
+    pub parallel: usize,
+    #[serde(default = "default_data_directory")]
+    pub data_directory: String,
+    /// The values of each field swept, by dotted path
+    #[serde(default)]
+    pub parameters: BTreeMap<String, Vec<toml::Value>>,
+    /// Overrides applied to every run, before the swept values
+    #[serde(default)]
+    pub overrides: Vec<String>,
+}
+
+fn default_one() -> usize {
+    1
+}
+
+fn default_data_directory() -> String {
+    "~/logs/sweeps".to_string()
+}
+
+impl SweepSpec {
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let text = interpolate(&fs::read_to_string(path)?)?;
+        let spec = ConfigFormat::of_path(path).parse(&text)?.try_into()?;
+        Ok(spec)
+    }
+
+    /// Where the runs are laid out.
+    pub fn root(&self) -> PathBuf {
+        let mut dir = self.data_directory.clone();
+        if dir.starts_with('~') {
+            let home = std::env::var("HOME")
+                .expect("No HOME environment variable found. Please set this.");
+            dir.replace_range(0..1, &home);
+        }
+        Path::new(&dir).join(&self.name)
+    }
+
+    /// The configurations the sweep resolves to, as the values of each
+    /// parameter, in the order of `parameters`.
+    fn points(&self) -> Vec<Vec<&toml::Value>> {
+        let values = self.parameters.values().collect::<Vec<_>>();
+        match self.mode {
+            SweepMode::Grid => values.iter().fold(vec![vec![]], |points, vs| {
+                points
+                    .iter()
+                    .flat_map(|p| {
+                        vs.iter().map(move |v| {
+                            let mut p = p.clone();
+                            p.push(v);
+                            p
+                        })
+                    })
+                    .collect()
+            }),
+            SweepMode::Random(n) => {
+                let mut rng = hash_seed_rng(&self.name);
+                (0..n)
+                    .map(|_| {
+                        values
+                            .iter()
+                            .map(|vs| &vs[rng.gen_range(0, vs.len())])
+                            .collect()
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Every run of the sweep.
+    pub fn plan(&self) -> Result<Vec<SweepRun>, Error> {
+        if let Some((key, _)) = self.parameters.iter().find(|(_, vs)| vs.is_empty()) {
+            return Err(Error::Misc(format!("No values are given for {}", key)));
+        }
+        let mut runs = Vec::new();
+        for (point, values) in self.points().into_iter().enumerate() {
+            let mut overrides = self.overrides.clone();
+            overrides.extend(
+                self.parameters
+                    .keys()
+                    .zip(values)
+                    .map(|(key, value)| format!("{}={}", key, value)),
+            );
+            overrides.push(format!(
+                "observer.data_directory={}",
+                toml::Value::String(self.root().to_string_lossy().into_owned())
+            ));
+            for replica in 0..self.seeds {
+                // TOML's integers are signed
+                let seed = hash_seed_rng(&(&self.name, point, replica)).gen::<u64>() >> 1;
+                let mut overrides = overrides.clone();
+                overrides.push(format!("random_seed={}", seed));
+                runs.push(SweepRun {
+                    point,
+                    replica,
+                    population_name: format!("{}-p{:03}-r{}", self.name, point, replica),
+                    overrides,
+                });
+            }
+        }
+        Ok(runs)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SweepRun {
+    pub point: usize,
+    pub replica: usize,
+    pub population_name: String,
+    pub overrides: Vec<String>,
+}
+
+impl SweepRun {
+    fn command(&self, exe: &Path, base: &Path) -> Command {
+        let mut command = Command::new(exe);
+        command.arg(base).arg(&self.population_name);
+        for assignment in self.overrides.iter() {
+            command.arg("--set").arg(assignment);
+        }
+        command
+    }
+}
+
+/// Runs the sweep, on the base config at `base`, returning the runs and
+/// whether each succeeded. The results are then compared, as by `berbalang
+/// aggregate`, and the comparison written to `summary.tsv`.
+pub fn run(spec: &SweepSpec, base: &Path) -> Result<Vec<(SweepRun, bool)>, Error> {
+    let exe = std::env::current_exe()?;
+    let runs = spec.plan()?;
+    log::info!(
+        "Sweep {}: {} runs, {} at a time",
+        spec.name,
+        runs.len(),
+        spec.parallel
+    );
+    let pool = ThreadPool::new(spec.parallel.max(1));
+    let (tx, rx) = channel();
+    for run in runs.iter().cloned() {
+        let tx = tx.clone();
+        let mut command = run.command(&exe, &base);
+        pool.execute(move || {
+            log::info!("Starting {}: {:?}", run.population_name, run.overrides);
+            let ok = match command.status() {
+                Ok(status) => status.success(),
+                Err(e) => {
+                    log::error!("Failed to start {}: {:?}", run.population_name, e);
+                    false
+                }
+            };
+            log::info!(
+                "Finished {}: {}",
+                run.population_name,
+                if ok { "ok" } else { "failed" }
+            );
+            let _ = tx.send((run, ok));
+        });
+    }
+    drop(tx);
+    let mut results = rx.iter().collect::<Vec<_>>();
+    results.sort_by_key(|(run, _)| (run.point, run.replica));
+
+    let root = spec.root();
+    fs::create_dir_all(&root)?;
+    let mut table = String::from("point\treplica\tpopulation\tstatus\toverrides\n");
+    for (run, ok) in results.iter() {
+        table.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            run.point,
+            run.replica,
+            run.population_name,
+            if *ok { "ok" } else { "failed" },
+            run.overrides.join(" ")
+        ));
+    }
+    fs::write(root.join("sweep.tsv"), table)?;
+
+    let runs = experiments::collect_runs(&root, "window", "min_fitness")?;
+    let summary = experiments::comparison_tables(&experiments::group_runs(&runs));
+    log::info!("Results of {}:\n{}", spec.name, summary);
+    fs::write(root.join("summary.tsv"), summary)?;
+    Ok(results)
+}
+
+fn report(spec: &SweepSpec, results: &[(SweepRun, bool)]) -> Result<(), Error> {
+    let failed = results.iter().filter(|(_, ok)| !ok).count();
+    log::info!(
+        "{} of {} runs succeeded. See {}",
+        results.len() - failed,
+        results.len(),
+        spec.root().join("summary.tsv").display()
+    );
+    if failed > 0 {
+        return Err(Error::Misc(format!("{} runs failed", failed)));
+    }
+    Ok(())
+}
+
+/// Runs the config at `base` `n` times, each with a seed of its own, as
+/// `berbalang --repeat` does. The runs are laid out under `repeats/{name}`,
+/// in the config's data directory, the name being the population's.
+pub fn repeat(
+    base: &Path,
+    format: Option<ConfigFormat>,
+    population_name: Option<String>,
+    overrides: &[String],
+    n: usize,
+    parallel: usize,
+) -> Result<(), Error> {
+    let mut value = configure::read_value(base, format)?;
+    for assignment in overrides {
+        configure::apply_override(&mut value, assignment)?;
+    }
+    let observer = |key: &str| {
+        value
+            .get("observer")
+            .and_then(|o| o.get(key))
+            .and_then(toml::Value::as_str)
+            .map(String::from)
+    };
+    let name = population_name
+        .or_else(|| observer("population_name"))
+        .unwrap_or_else(|| "repeat".to_string());
+    let data_directory = observer("data_directory").unwrap_or_else(|| "~/logs".to_string());
+    let spec = SweepSpec {
+        name,
+        base: base.to_string_lossy().into_owned(),
+        mode: SweepMode::Grid,
+        seeds: n,
+        parallel,
+        data_directory: format!("{}/repeats", data_directory),
+        parameters: BTreeMap::new(),
+        overrides: overrides.to_vec(),
+    };
+    let results = run(&spec, base)?;
+    report(&spec, &results)
+}
+
+/// The `sweep` subcommand:
+///
+/// ```text
+/// berbalang sweep <spec>
+/// ```
+pub fn run_cli(args: &[String]) -> Result<(), Error> {
+    let path = args
+        .get(0)
+        .ok_or_else(|| Error::Misc("Usage: berbalang sweep <spec>".to_string()))?;
+    let path = Path::new(path);
+    let spec = SweepSpec::from_path(path)?;
+    let base = path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(&spec.base);
+    let results = run(&spec, &base)?;
+    report(&spec, &results)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn spec(mode: &str) -> SweepSpec {
+        let text = format!(
+            "name = \"s\"\nbase = \"config.toml\"\nmode = {}\nseeds = 2\n\
+             data_directory = \"/tmp/sweeps\"\n\
+             [parameters]\npop_size = [100, 500]\n\"fitness.weighting\" = [\"a\", \"b\", \"c\"]\n",
+            mode
+        );
+        toml::from_str(&text).unwrap()
+    }
+
+    #[test]
+    fn test_grid_plan() {
+        let runs = spec("\"Grid\"").plan().unwrap();
+        assert_eq!(runs.len(), 2 * 3 * 2);
+        assert_eq!(runs[0].population_name, "s-p000-r0");
+        assert_eq!(
+            &runs[0].overrides[..3],
+            &[
+                "fitness.weighting=\"a\"",
+                "pop_size=100",
+                "observer.data_directory=\"/tmp/sweeps/s\"",
+            ]
+        );
+        assert_eq!(runs[11].overrides[0], "fitness.weighting=\"c\"");
+        assert_eq!(runs[11].overrides[1], "pop_size=500");
+        // the replicas differ only in their seeds
+        assert_eq!(runs[0].overrides[..3], runs[1].overrides[..3]);
+        assert_ne!(runs[0].overrides[3], runs[1].overrides[3]);
+        // and the seeds are the same from one planning to the next
+        assert_eq!(
+            runs[0].overrides[3],
+            spec("\"Grid\"").plan().unwrap()[0].overrides[3]
+        );
+    }
+
+    #[test]
+    fn test_repeat_plan() {
+        let spec: SweepSpec =
+            toml::from_str("name = \"r\"\nbase = \"c.toml\"\nseeds = 3\noverrides = [\"a=1\"]\n")
+                .unwrap();
+        let runs = spec.plan().unwrap();
+        assert_eq!(runs.len(), 3);
+        assert!(runs.iter().all(|r| r.point == 0 && r.overrides[0] == "a=1"));
+    }
+
+    #[test]
+    fn test_random_plan() {
+        let runs = spec("{ Random = 4 }").plan().unwrap();
+        assert_eq!(runs.len(), 4 * 2);
+        assert_eq!(runs[7].population_name, "s-p003-r1");
+    }
+}
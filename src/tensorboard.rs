@@ -0,0 +1,236 @@
+//! Writes the window statistics as TensorBoard scalar summaries, to
+//! `tensorboard/events.out.tfevents.*` in the island's data directory.
+//! Pointing TensorBoard at a population's directory shows each island as a
+//! run, and pointing it at a job's compares the populations.
+//!
+//! The event files are TFRecord files of `Event` protocol buffers, which
+//! are simple enough to encode by hand, rather than pull in a protobuf
+//! library:
+//!
+//! ```text
+//! message Event { double wall_time = 1; int64 step = 2;
+//!                 oneof what { string file_version = 3; Summary summary = 5; } }
+//! message Summary { repeated Value value = 1; }
+//! message Value { string tag = 1; float simple_value = 2; }
+//! ```
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::configure::Config;
+use crate::error::Error;
+use crate::evolution::{Genome, Phenome};
+use crate::observer::{Sink, WindowStats};
+use crate::util::checksum::crc32c;
+
+fn put_varint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn put_bytes(buf: &mut Vec<u8>, key: u8, bytes: &[u8]) {
+    buf.push(key);
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn wall_time() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+fn event_header(buf: &mut Vec<u8>, step: i64) {
+    buf.push(0x09); // wall_time: field 1, 64-bit
+    buf.extend_from_slice(&wall_time().to_le_bytes());
+    buf.push(0x10); // step: field 2, varint
+    put_varint(buf, step as u64);
+}
+
+fn file_version_event() -> Vec<u8> {
+    let mut buf = Vec::new();
+    event_header(&mut buf, 0);
+    put_bytes(&mut buf, 0x1a, b"brain.Event:2");
+    buf
+}
+
+fn summary_event(step: usize, scalars: &[(String, f64)]) -> Vec<u8> {
+    let mut summary = Vec::new();
+    for (tag, x) in scalars {
+        let mut value = Vec::new();
+        put_bytes(&mut value, 0x0a, tag.as_bytes());
+        value.push(0x15); // simple_value: field 2, 32-bit
+        value.extend_from_slice(&(*x as f32).to_le_bytes());
+        put_bytes(&mut summary, 0x0a, &value);
+    }
+    let mut buf = Vec::new();
+    event_header(&mut buf, step as i64);
+    put_bytes(&mut buf, 0x2a, &summary);
+    buf
+}
+
+fn masked_crc(data: &[u8]) -> u32 {
+    let crc = crc32c(data);
+    ((crc >> 15) | (crc << 17)).wrapping_add(0xa282_ead8)
+}
+
+/// Frames the data as a TFRecord.
+fn tf_record(data: &[u8]) -> Vec<u8> {
+    let length = (data.len() as u64).to_le_bytes();
+    let mut record = Vec::with_capacity(data.len() + 16);
+    record.extend_from_slice(&length);
+    record.extend_from_slice(&masked_crc(&length).to_le_bytes());
+    record.extend_from_slice(data);
+    record.extend_from_slice(&masked_crc(data).to_le_bytes());
+    record
+}
+
+pub struct EventWriter {
+    file: BufWriter<File>,
+}
+
+impl EventWriter {
+    pub fn create(dir: &str, suffix: &str) -> Result<Self, Error> {
+        fs::create_dir_all(dir)?;
+        let path = format!(
+            "{}/events.out.tfevents.{}.{}{}",
+            dir,
+            wall_time() as u64,
+            gethostname::gethostname().to_string_lossy(),
+            suffix
+        );
+        let mut writer = Self {
+            file: BufWriter::new(File::create(path)?),
+        };
+        writer.write_event(&file_version_event())?;
+        Ok(writer)
+    }
+
+    fn write_event(&mut self, event: &[u8]) -> Result<(), Error> {
+        self.file.write_all(&tf_record(event))?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    pub fn write_scalars(&mut self, step: usize, scalars: &[(String, f64)]) -> Result<(), Error> {
+        self.write_event(&summary_event(step, scalars))
+    }
+}
+
+/// Writes the best, mean, and percentiles of each objective, the scalar fitness
+/// statistics, the window's genomic uniqueness, and the number of
+/// evaluations per second, stepped by the island's counter.
+pub struct TensorBoardSink {
+    writer: Option<EventWriter>,
+    last: (usize, Instant),
+}
+
+impl TensorBoardSink {
+    pub fn new(config: Arc<Config>) -> Self {
+        let dir = format!("{}/tensorboard", config.data_directory());
+        let suffix = format!(".island_{}", config.island_id);
+        let writer = match EventWriter::create(&dir, &suffix) {
+            Ok(writer) => Some(writer),
+            Err(e) => {
+                log::error!(
+                    "Failed to create TensorBoard event file in {}: {:?}",
+                    dir,
+                    e
+                );
+                None
+            }
+        };
+        Self {
+            writer,
+            last: (0, Instant::now()),
+        }
+    }
+}
+
+fn scalars(stats: &WindowStats, evaluations_per_second: f64) -> Vec<(String, f64)> {
+    let mut scalars = vec![
+        ("fitness/min".to_string(), stats.min_fitness),
+        ("fitness/mean".to_string(), stats.mean_fitness),
+        ("fitness/max".to_string(), stats.max_fitness),
+        ("genome/mean_length".to_string(), stats.mean_length),
+        ("diversity/uniqueness".to_string(), stats.uniqueness),
+        (
+            "diversity/genotypic_entropy".to_string(),
+            stats.genotypic_entropy,
+        ),
+        (
+            "diversity/mean_genome_distance".to_string(),
+            stats.mean_genome_distance,
+        ),
+        ("diversity/path_diversity".to_string(), stats.path_diversity),
+        (
+            "throughput/evaluations_per_second".to_string(),
+            evaluations_per_second,
+        ),
+    ];
+    for (key, best) in stats.best_scores.iter() {
+        scalars.push((format!("objectives/{}/best", key), *best));
+    }
+    for (key, mean) in stats.mean_scores.iter() {
+        scalars.push((format!("objectives/{}/mean", key), *mean));
+    }
+    for (key, p) in stats.score_percentiles.iter() {
+        scalars.push((format!("objectives/{}/p10", key), p.p10));
+        scalars.push((format!("objectives/{}/p50", key), p.p50));
+        scalars.push((format!("objectives/{}/p90", key), p.p90));
+    }
+    for (key, value) in stats.custom.iter() {
+        scalars.push((format!("custom/{}", key), *value));
+    }
+    scalars
+}
+
+impl<O: Phenome + Genome> Sink<O> for TensorBoardSink {
+    fn record_stats(&mut self, stats: &WindowStats) {
+        let (last_counter, last_time) = self.last;
+        let elapsed = last_time.elapsed().as_secs_f64();
+        let throughput = if elapsed > 0.0 {
+            stats.counter.saturating_sub(last_counter) as f64 / elapsed
+        } else {
+            0.0
+        };
+        self.last = (stats.counter, Instant::now());
+        if let Some(ref mut writer) = self.writer {
+            if let Err(e) = writer.write_scalars(stats.counter, &scalars(stats, throughput)) {
+                log::error!("Failed to write TensorBoard summary: {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tf_record() {
+        let data = b"hello";
+        let record = tf_record(data);
+        assert_eq!(record.len(), 8 + 4 + data.len() + 4);
+        assert_eq!(&record[..8], &5_u64.to_le_bytes());
+        assert_eq!(&record[12..17], data);
+    }
+
+    #[test]
+    fn test_summary_event() {
+        let event = summary_event(300, &[("a".to_string(), 1.0)]);
+        // wall_time, then step 300 as a varint
+        assert_eq!(event[0], 0x09);
+        assert_eq!(&event[9..12], &[0x10, 0xac, 0x02]);
+        // the summary, holding one value, with tag "a" and simple_value 1.0
+        assert_eq!(
+            &event[12..],
+            &[0x2a, 10, 0x0a, 8, 0x0a, 1, b'a', 0x15, 0, 0, 0x80, 0x3f]
+        );
+    }
+}
@@ -0,0 +1,860 @@
+//! Lowers evolved programs into Rust closures, so that champions can be
+//! read, and reused outside the virtual machines they were evolved in. The
+//! closures follow the native handling of arithmetic faults.
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::configure::LinearGpConfig;
+use crate::error::Error;
+use crate::examples::linear_gp::machine::{effective_instructions, Inst, Op as LinearOp};
+use crate::roper::push::{MachineState, Op, Type, Val};
+
+/// How a program takes its inputs and gives its answer, following the
+/// entry points of the virtual machines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interface {
+    /// Integer inputs, answered with the return registers, as with
+    /// `Machine::exec`
+    Classification,
+    /// Float inputs and a float answer, as with `Machine::exec_float` and
+    /// `MachineState::exec_numeric`
+    Numeric,
+    /// Bool inputs and a bool answer, as with `MachineState::exec_boolean`,
+    /// or the low bit of the first return register, as with the linear
+    /// backend of the boolean job
+    Boolean,
+}
+
+/// The push programs whose branches multiply past this are left unlowered.
+const MAX_PATHS: usize = 256;
+
+/// Lowers a linear GP program to a closure. A program that neither branches
+/// nor loops is lowered to straight-line code, with its introns left out.
+/// Any other program is lowered to a loop over its instructions, under the
+/// step limit.
+pub fn linear_gp(code: &[Inst], config: &LinearGpConfig, interface: Interface) -> String {
+    let num_registers = config.num_registers.unwrap();
+    let return_registers = config.return_registers.unwrap();
+    let num_float_registers = config.num_float_registers;
+    let mut out = String::new();
+    let (input_type, output_type) = match interface {
+        Interface::Classification => ("i32", "Vec<i32>"),
+        Interface::Numeric => ("f64", "f64"),
+        Interface::Boolean => ("bool", "Option<bool>"),
+    };
+    let _ = writeln!(out, "// Lowered from a linear GP program");
+    let _ = writeln!(out, "|input: &[{}]| -> {} {{", input_type, output_type);
+    let _ = writeln!(out, "    let mut r = [0_i32; {}];", num_registers);
+    if num_float_registers > 0 {
+        let _ = writeln!(out, "    let mut f = [0.0_f64; {}];", num_float_registers);
+    }
+    if config.memory_size > 0 {
+        let _ = writeln!(out, "    let mut mem = [0_i32; {}];", config.memory_size);
+    }
+    // The inputs are loaded as by `Machine::exec` or `Machine::exec_float`
+    let loads = match interface {
+        Interface::Numeric if num_float_registers > 1 => {
+            vec![(num_float_registers - 1, "f[i + 1] = *x;".to_string())]
+        }
+        Interface::Numeric => vec![],
+        Interface::Classification | Interface::Boolean => {
+            let mut loads = vec![(
+                num_registers - return_registers,
+                format!("r[i + {}] = *x as i32;", return_registers),
+            )];
+            if num_float_registers > 0 {
+                loads.push((num_float_registers, "f[i] = *x as i32 as f64;".to_string()));
+            }
+            loads
+        }
+    };
+    for (n, load) in loads {
+        let _ = writeln!(
+            out,
+            "    for (i, x) in input.iter().take({}).enumerate() {{",
+            n
+        );
+        let _ = writeln!(out, "        {}", load);
+        let _ = writeln!(out, "    }}");
+    }
+
+    let end = code.iter().position(|i| i.op == LinearOp::End);
+    let branching = code.iter().any(|i| match i.op {
+        LinearOp::Jle | LinearOp::SkipLt | LinearOp::SkipEq => true,
+        _ => false,
+    });
+    if !branching && (end.is_some() || code.is_empty()) {
+        let effective = effective_instructions(code, config);
+        for (i, inst) in code.iter().enumerate() {
+            if Some(i) == end {
+                break;
+            }
+            let statement = linear_statement(inst, config);
+            if effective[i] && !statement.is_empty() {
+                let _ = writeln!(out, "    {} // [{}]", statement, i);
+            }
+        }
+    } else {
+        let _ = writeln!(out, "    let mut pc = 0;");
+        let _ = writeln!(out, "    for _ in 0..{} {{", config.max_steps);
+        let _ = writeln!(out, "        let i = pc;");
+        let _ = writeln!(out, "        pc += 1;");
+        let _ = writeln!(out, "        match i {{");
+        for (i, inst) in code.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "            {} => {{ {} }}",
+                i,
+                linear_statement(inst, config)
+            );
+        }
+        let _ = writeln!(out, "            _ => unreachable!(),");
+        let _ = writeln!(out, "        }}");
+        let _ = writeln!(out, "        pc %= {};", code.len());
+        let _ = writeln!(out, "    }}");
+    }
+
+    match interface {
+        Interface::Classification => {
+            let _ = writeln!(out, "    r[..{}].to_vec()", return_registers);
+        }
+        Interface::Numeric if num_float_registers > 0 => {
+            let _ = writeln!(out, "    f[0]");
+        }
+        Interface::Numeric => {
+            let _ = writeln!(out, "    0.0");
+        }
+        Interface::Boolean if return_registers > 0 => {
+            let _ = writeln!(out, "    Some(r[0] & 1 == 1)");
+        }
+        Interface::Boolean => {
+            let _ = writeln!(out, "    None");
+        }
+    }
+    let _ = writeln!(out, "}}");
+    out
+}
+
+/// A single instruction, as a statement, mirroring `Machine::eval`. This is
+/// empty for instructions that do nothing in the given configuration.
+fn linear_statement(inst: &Inst, config: &LinearGpConfig) -> String {
+    use LinearOp::*;
+    let n = config.num_registers.unwrap();
+    let nf = config.num_float_registers;
+    let m = config.memory_size;
+    let (a, b) = (format!("r[{}]", inst.a % n), format!("r[{}]", inst.b % n));
+    let (fa, fb) = if nf > 0 {
+        (format!("f[{}]", inst.a % nf), format!("f[{}]", inst.b % nf))
+    } else {
+        (String::new(), "0.0_f64".to_string())
+    };
+    match inst.op {
+        Add => format!("{} = {}.wrapping_add({});", a, a, b),
+        // Division by zero divides by one
+        Div => format!("if {} != 0 {{ {} = {}.wrapping_div({}); }}", b, a, a, b),
+        Mov => format!("{} = {};", a, b),
+        Mult => format!("{} = {}.wrapping_mul({});", a, a, b),
+        Sub => format!("{} = {}.wrapping_sub({});", a, a, b),
+        Xor => format!("{} ^= {};", a, b),
+        Set(x) => format!("{} = {};", a, x),
+        Lsl => format!("{} = {}.wrapping_shl({} as u32);", a, a, b),
+        And => format!("{} &= {};", a, b),
+        Or => format!("{} |= {};", a, b),
+        Not => format!("{} = !{};", a, b),
+        Jle => format!("if {} <= 0 {{ pc = {} as usize; }}", a, b),
+        End => "break;".to_string(),
+        SkipLt => format!("if {} < {} {{ pc += 1; }}", a, b),
+        SkipEq => format!("if {} == {} {{ pc += 1; }}", a, b),
+        FAdd | FSub | FMul | FDiv | FMov | ItoF if nf == 0 => String::new(),
+        FAdd => format!("{} += {};", fa, fb),
+        FSub => format!("{} -= {};", fa, fb),
+        FMul => format!("{} *= {};", fa, fb),
+        FDiv => format!("if {} != 0.0 {{ {} /= {}; }}", fb, fa, fb),
+        FMov => format!("{} = {};", fa, fb),
+        ItoF => format!("{} = {} as f64;", fa, b),
+        FtoI => format!("{} = {}.round() as i32;", a, fb),
+        // Out of bounds loads and stores do nothing
+        Load | Store if m == 0 => String::new(),
+        Load => format!(
+            "if {} >= 0 && ({} as usize) < {} {{ {} = mem[{} as usize]; }}",
+            b, b, m, a, b
+        ),
+        Store => format!(
+            "if {} >= 0 && ({} as usize) < {} {{ mem[{} as usize] = {}; }}",
+            b, b, m, b, a
+        ),
+    }
+}
+
+/// A value on one of the push stacks, either known outright, or named by
+/// the statement that computes it. The exec and code stacks only ever hold
+/// known values.
+#[derive(Clone, Debug, PartialEq)]
+enum Term {
+    Known(Val),
+    Var(String),
+}
+
+impl Term {
+    fn render(&self) -> String {
+        match self {
+            Term::Known(Val::Bool(b)) => b.to_string(),
+            Term::Known(Val::Int(n)) if *n == i64::min_value() => "i64::MIN".to_string(),
+            Term::Known(Val::Int(n)) if *n < 0 => format!("({}_i64)", n),
+            Term::Known(Val::Int(n)) => format!("{}_i64", n),
+            Term::Known(Val::Float(bits)) => {
+                let x = f64::from_bits(*bits);
+                if x.is_nan() {
+                    "f64::NAN".to_string()
+                } else if x == std::f64::INFINITY {
+                    "f64::INFINITY".to_string()
+                } else if x == std::f64::NEG_INFINITY {
+                    "f64::NEG_INFINITY".to_string()
+                } else if x.is_sign_negative() {
+                    format!("({:?}_f64)", x)
+                } else {
+                    format!("{:?}_f64", x)
+                }
+            }
+            Term::Known(v) => format!("{:?}", v),
+            Term::Var(name) => name.clone(),
+        }
+    }
+
+    fn deps(&self) -> Vec<String> {
+        match self {
+            Term::Var(name) => vec![name.clone()],
+            Term::Known(_) => vec![],
+        }
+    }
+}
+
+struct Stmt {
+    name: String,
+    expr: String,
+    deps: Vec<String>,
+}
+
+struct Cond {
+    expr: String,
+    deps: Vec<String>,
+}
+
+enum Block {
+    Leaf {
+        stmts: Vec<Stmt>,
+        result: Option<Term>,
+    },
+    Branch {
+        stmts: Vec<Stmt>,
+        cond: Cond,
+        then: Box<Block>,
+        otherwise: Box<Block>,
+    },
+}
+
+impl Block {
+    /// Drops the statements whose values are never used.
+    fn prune(&mut self, live: &mut HashSet<String>) {
+        let stmts = match self {
+            Block::Leaf { stmts, result } => {
+                if let Some(result) = result {
+                    live.extend(result.deps());
+                }
+                stmts
+            }
+            Block::Branch {
+                stmts,
+                cond,
+                then,
+                otherwise,
+            } => {
+                let mut other_live = live.clone();
+                then.prune(live);
+                otherwise.prune(&mut other_live);
+                live.extend(other_live);
+                live.extend(cond.deps.iter().cloned());
+                stmts
+            }
+        };
+        let mut kept = Vec::new();
+        for stmt in stmts.drain(..).rev() {
+            if live.contains(&stmt.name) {
+                live.extend(stmt.deps.iter().cloned());
+                kept.push(stmt);
+            }
+        }
+        kept.reverse();
+        *stmts = kept;
+    }
+
+    fn render(&self, depth: usize, out: &mut String) {
+        let indent = "    ".repeat(depth);
+        let stmts = match self {
+            Block::Leaf { stmts, .. } | Block::Branch { stmts, .. } => stmts,
+        };
+        for stmt in stmts {
+            let _ = writeln!(out, "{}let {} = {};", indent, stmt.name, stmt.expr);
+        }
+        match self {
+            Block::Leaf {
+                result: Some(result),
+                ..
+            } => {
+                let _ = writeln!(out, "{}Some({})", indent, result.render());
+            }
+            Block::Leaf { result: None, .. } => {
+                let _ = writeln!(out, "{}None", indent);
+            }
+            Block::Branch {
+                cond,
+                then,
+                otherwise,
+                ..
+            } => {
+                let _ = writeln!(out, "{}if {} {{", indent, cond.expr);
+                then.render(depth + 1, out);
+                let _ = writeln!(out, "{}}} else {{", indent);
+                otherwise.render(depth + 1, out);
+                let _ = writeln!(out, "{}}}", indent);
+            }
+        }
+    }
+}
+
+/// The stacks along one path through a push program, and the statements
+/// computed along it, since the last fork.
+#[derive(Default)]
+struct Path {
+    stacks: HashMap<Type, Vec<Term>>,
+    steps: usize,
+    stmts: Vec<Stmt>,
+}
+
+impl Path {
+    fn pop(&mut self, t: Type) -> Option<Term> {
+        self.stacks.entry(t).or_default().pop()
+    }
+
+    fn push(&mut self, t: Type, term: Term) {
+        self.stacks.entry(t).or_default().push(term)
+    }
+
+    /// Pops an op from the exec or code stack.
+    fn pop_op(&mut self, t: Type) -> Option<Op> {
+        match self.pop(t) {
+            Some(Term::Known(Val::Exec(op))) | Some(Term::Known(Val::Code(op))) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn push_exec(&mut self, op: Op) {
+        self.push(Type::Exec, Term::Known(Val::Exec(op)))
+    }
+
+    fn push_code(&mut self, op: Op) {
+        self.push(Type::Code, Term::Known(Val::Code(op)))
+    }
+
+    fn fork(&self) -> Self {
+        Self {
+            stacks: self.stacks.clone(),
+            steps: self.steps,
+            stmts: vec![],
+        }
+    }
+}
+
+/// The pure operations on data: the types of their operands, in the order
+/// they're popped, the type of their result, and the expression for it.
+fn data_op(op: &Op) -> Option<(&'static [Type], Type, &'static str)> {
+    use Op::*;
+    use Type::{Bool as B, Float as F, Int as I};
+    let (inputs, output, expr): (&'static [Type], Type, &'static str) = match op {
+        BoolAnd => (&[B, B], B, "{a} && {b}"),
+        BoolOr => (&[B, B], B, "{a} || {b}"),
+        BoolNot => (&[B], B, "!{a}"),
+        FloatLog => (&[F], F, "{a}.ln()"),
+        FloatSin => (&[F], F, "{a}.sin()"),
+        FloatCos => (&[F], F, "{a}.cos()"),
+        FloatTan => (&[F], F, "{a}.tan()"),
+        FloatTanh => (&[F], F, "{a}.tanh()"),
+        FloatLess => (&[F, F], B, "{a} < {b}"),
+        FloatAdd => (&[F, F], F, "{a} + {b}"),
+        FloatSub => (&[F, F], F, "{a} - {b}"),
+        FloatMul => (&[F, F], F, "{a} * {b}"),
+        FloatDiv => (&[F, F], F, "{a} / {b}"),
+        IntAdd => (&[I, I], I, "{a}.wrapping_add({b})"),
+        IntSub => (&[I, I], I, "{a}.wrapping_sub({b})"),
+        IntMul => (&[I, I], I, "{a}.wrapping_mul({b})"),
+        IntDiv => (&[I, I], I, "{a}.wrapping_div({b})"),
+        IntMod => (&[I, I], I, "{a}.wrapping_rem({b})"),
+        IntNeg => (&[I], I, "{a}.wrapping_neg()"),
+        IntLess => (&[I, I], B, "{a} < {b}"),
+        IntToFloat => (&[I], F, "{a} as f64"),
+        FloatToInt => (&[F], I, "{a} as i64"),
+        IntToBool => (&[I], B, "{a} != 0"),
+        BoolToInt => (&[B], I, "{a} as i64"),
+        // Floats are compared by their bits
+        Eq(Type::Float) => (&[F, F], B, "{a}.to_bits() == {b}.to_bits()"),
+        Eq(Type::Int) => (&[I, I], B, "{a} == {b}"),
+        Eq(Type::Bool) => (&[B, B], B, "{a} == {b}"),
+        _ => return None,
+    };
+    Some((inputs, output, expr))
+}
+
+/// Division by zero pushes nothing, so these ops fork on their divisors.
+fn divides(op: &Op) -> bool {
+    match op {
+        Op::IntDiv | Op::IntMod | Op::FloatDiv => true,
+        _ => false,
+    }
+}
+
+fn is_zero(val: &Val) -> bool {
+    match val {
+        Val::Int(n) => *n == 0,
+        Val::Float(bits) => f64::from_bits(*bits) == 0.0,
+        _ => false,
+    }
+}
+
+/// Applies the op to known operands, on a scratch machine.
+fn fold(op: &Op, operands: &[Val], output: Type) -> Option<Val> {
+    let mut machine = MachineState::default();
+    machine.flush();
+    for val in operands.iter().rev() {
+        machine.push(val.clone())
+    }
+    op.eval(&mut machine);
+    machine.pop_opt(&output)
+}
+
+/// Symbolically executes a push program, running the exec stack as the
+/// machine would, but with named values in place of the data that depends
+/// on the inputs. Where the flow of control depends on the inputs, the
+/// path forks.
+struct PushLowering {
+    interface: Interface,
+    num_inputs: usize,
+    max_steps: usize,
+    num_vars: usize,
+    num_paths: usize,
+}
+
+type Fork = Option<(Cond, Path, Path)>;
+
+impl PushLowering {
+    fn emit(&mut self, path: &mut Path, t: Type, expr: String, deps: Vec<String>) {
+        let name = format!("v{}", self.num_vars);
+        self.num_vars += 1;
+        path.stmts.push(Stmt {
+            name: name.clone(),
+            expr,
+            deps,
+        });
+        path.push(t, Term::Var(name))
+    }
+
+    /// Forks on a named bool, returning the paths for `true` and `false`.
+    fn fork_on(&mut self, path: &Path, cond: &Term) -> Result<(Cond, Path, Path), Error> {
+        self.num_paths += 1;
+        if self.num_paths > MAX_PATHS {
+            return Err(Error::Misc(format!(
+                "More than {} paths through the program",
+                MAX_PATHS
+            )));
+        }
+        let cond = Cond {
+            expr: cond.render(),
+            deps: cond.deps(),
+        };
+        Ok((cond, path.fork(), path.fork()))
+    }
+
+    fn explore(&mut self, mut path: Path) -> Result<Block, Error> {
+        while let Some(op) = path.pop_op(Type::Exec) {
+            path.steps += 1;
+            if path.steps >= self.max_steps {
+                break;
+            }
+            if let Some((cond, then, otherwise)) = self.step(&mut path, &op)? {
+                let then = self.explore(then)?;
+                let otherwise = self.explore(otherwise)?;
+                return Ok(Block::Branch {
+                    stmts: path.stmts,
+                    cond,
+                    then: Box::new(then),
+                    otherwise: Box::new(otherwise),
+                });
+            }
+        }
+        let result = match self.interface {
+            Interface::Boolean => path.pop(Type::Bool),
+            _ => path.pop(Type::Float),
+        };
+        Ok(Block::Leaf {
+            stmts: path.stmts,
+            result,
+        })
+    }
+
+    /// Mirrors `Op::eval`, returning the fork, if the op makes one.
+    fn step(&mut self, path: &mut Path, op: &Op) -> Result<Fork, Error> {
+        use Op::*;
+        match op {
+            Nop => {}
+            Input(i) => {
+                if self.interface == Interface::Boolean && self.num_inputs > 0 {
+                    let name = format!("x{}", i % self.num_inputs);
+                    path.push(Type::Bool, Term::Var(name))
+                }
+            }
+            BoolConst(b) => path.push(Type::Bool, Term::Known(Val::Bool(*b))),
+            IntConst(n) => path.push(Type::Int, Term::Known(Val::Int(*n))),
+            FloatConst(bits) => path.push(Type::Float, Term::Known(Val::Float(*bits))),
+            List(ops) => {
+                for op in ops {
+                    path.push_exec(op.clone())
+                }
+            }
+            ExecK => {
+                if let Some(top) = path.pop_op(Type::Exec) {
+                    let _ = path.pop_op(Type::Exec);
+                    path.push_exec(top)
+                }
+            }
+            ExecS => {
+                if let (Some(a), Some(b), Some(c)) = (
+                    path.pop_op(Type::Exec),
+                    path.pop_op(Type::Exec),
+                    path.pop_op(Type::Exec),
+                ) {
+                    path.push_exec(List(vec![b, c.clone()]));
+                    path.push_exec(c);
+                    path.push_exec(a);
+                }
+            }
+            ExecY => {
+                if let Some(a) = path.pop_op(Type::Exec) {
+                    if a != ExecY {
+                        path.push_exec(List(vec![ExecY, a.clone()]));
+                        path.push_exec(a);
+                    }
+                }
+            }
+            CodeQuote => {
+                if let Some(op) = path.pop_op(Type::Exec) {
+                    path.push_code(op)
+                }
+            }
+            CodeDoAll => {
+                while let Some(op) = path.pop_op(Type::Code) {
+                    path.push_exec(op)
+                }
+            }
+            CodeDo => {
+                if let Some(op) = path.pop_op(Type::Code) {
+                    path.push_exec(op)
+                }
+            }
+            CodeIf | ExecIfElse => {
+                let t = if *op == CodeIf {
+                    Type::Code
+                } else {
+                    Type::Exec
+                };
+                if let (Some(b), Some(a), Some(c)) =
+                    (path.pop(Type::Bool), path.pop_op(t), path.pop_op(t))
+                {
+                    match b {
+                        Term::Known(Val::Bool(b)) => path.push_exec(if b { a } else { c }),
+                        b => {
+                            let (cond, mut then, mut otherwise) = self.fork_on(path, &b)?;
+                            then.push_exec(a);
+                            otherwise.push_exec(c);
+                            return Ok(Some((cond, then, otherwise)));
+                        }
+                    }
+                }
+            }
+            ExecIf => match path.pop(Type::Bool) {
+                Some(Term::Known(Val::Bool(false))) => {
+                    let _ = path.pop_op(Type::Exec);
+                }
+                Some(b @ Term::Var(_)) => {
+                    let (cond, then, mut otherwise) = self.fork_on(path, &b)?;
+                    let _ = otherwise.pop_op(Type::Exec);
+                    return Ok(Some((cond, then, otherwise)));
+                }
+                _ => {}
+            },
+            ExecWhile => {
+                if let (Some(b), Some(body)) = (path.pop(Type::Bool), path.pop_op(Type::Exec)) {
+                    match b {
+                        Term::Known(Val::Bool(true)) => {
+                            path.push_exec(List(vec![body.clone(), ExecWhile]));
+                            path.push_exec(body);
+                        }
+                        b @ Term::Var(_) => {
+                            let (cond, mut then, otherwise) = self.fork_on(path, &b)?;
+                            then.push_exec(List(vec![body.clone(), ExecWhile]));
+                            then.push_exec(body);
+                            return Ok(Some((cond, then, otherwise)));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            ExecDoTimes => {
+                if let (Some(n), Some(body)) = (path.pop(Type::Int), path.pop_op(Type::Exec)) {
+                    let n = match n {
+                        Term::Known(Val::Int(n)) => n,
+                        _ => return Err(Error::Misc("Loop count depends on the inputs".into())),
+                    };
+                    if n > 1 {
+                        path.push_exec(List(vec![body.clone(), ExecDoTimes, IntConst(n - 1)]));
+                    }
+                    if n > 0 {
+                        path.push_exec(body);
+                    }
+                }
+            }
+            ExecDoRange => {
+                if let (Some(dest), Some(start), Some(body)) = (
+                    path.pop(Type::Int),
+                    path.pop(Type::Int),
+                    path.pop_op(Type::Exec),
+                ) {
+                    let (dest, start) = match (dest, start) {
+                        (Term::Known(Val::Int(d)), Term::Known(Val::Int(s))) => (d, s),
+                        _ => return Err(Error::Misc("Loop range depends on the inputs".into())),
+                    };
+                    if start != dest {
+                        let next = if start < dest { start + 1 } else { start - 1 };
+                        path.push_exec(List(vec![
+                            body.clone(),
+                            ExecDoRange,
+                            IntConst(dest),
+                            IntConst(next),
+                        ]));
+                    }
+                    path.push(Type::Int, Term::Known(Val::Int(start)));
+                    path.push_exec(body);
+                }
+            }
+            // The exec and code stacks only hold known ops, which can be
+            // compared outright
+            Eq(t @ Type::Exec) | Eq(t @ Type::Code) => {
+                if let (Some(a), Some(b)) = (path.pop(*t), path.pop(*t)) {
+                    path.push(Type::Bool, Term::Known(Val::Bool(a == b)))
+                }
+            }
+            Rot(t) => {
+                if let (Some(a), Some(b), Some(c)) = (path.pop(*t), path.pop(*t), path.pop(*t)) {
+                    path.push(*t, a);
+                    path.push(*t, c);
+                    path.push(*t, b);
+                }
+            }
+            Swap(t) => {
+                if let (Some(a), Some(b)) = (path.pop(*t), path.pop(*t)) {
+                    path.push(*t, a);
+                    path.push(*t, b);
+                }
+            }
+            Drop(t) => {
+                let _ = path.pop(*t);
+            }
+            Dup(t) => {
+                if let Some(a) = path.pop(*t) {
+                    path.push(*t, a.clone());
+                    path.push(*t, a);
+                }
+            }
+            op => {
+                let (inputs, output, template) =
+                    data_op(op).ok_or_else(|| Error::Misc(format!("Can't lower {:?}", op)))?;
+                // Operands are consumed even if there aren't enough of them
+                let operands = inputs.iter().map(|t| path.pop(*t)).collect::<Vec<_>>();
+                if operands.iter().any(Option::is_none) {
+                    return Ok(None);
+                }
+                let operands = operands.into_iter().flatten().collect::<Vec<Term>>();
+                let known = operands
+                    .iter()
+                    .filter_map(|t| match t {
+                        Term::Known(v) => Some(v.clone()),
+                        Term::Var(_) => None,
+                    })
+                    .collect::<Vec<Val>>();
+                if known.len() == operands.len() {
+                    if let Some(val) = fold(op, &known, output) {
+                        path.push(output, Term::Known(val))
+                    }
+                    return Ok(None);
+                }
+                let mut expr = template.replace("{a}", &operands[0].render());
+                if let Some(b) = operands.get(1) {
+                    expr = expr.replace("{b}", &b.render());
+                }
+                let deps = operands
+                    .iter()
+                    .flat_map(Term::deps)
+                    .collect::<Vec<String>>();
+                if divides(op) {
+                    match &operands[1] {
+                        Term::Known(v) if is_zero(v) => {}
+                        Term::Known(_) => self.emit(path, output, expr, deps),
+                        divisor => {
+                            let zero = if output == Type::Float { "0.0" } else { "0" };
+                            let (mut cond, then, mut otherwise) = self.fork_on(path, divisor)?;
+                            cond.expr = format!("{} == {}", cond.expr, zero);
+                            self.emit(&mut otherwise, output, expr, deps);
+                            return Ok(Some((cond, then, otherwise)));
+                        }
+                    }
+                } else {
+                    self.emit(path, output, expr, deps)
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Lowers a push program to a closure, which answers with `None` where the
+/// program would leave no answer. Programs that touch the emulator or the
+/// memory image can't be lowered, and nor can those whose loop counts, or
+/// whose number of paths, depend on the inputs.
+pub fn push(
+    code: &[Op],
+    num_inputs: usize,
+    max_steps: usize,
+    interface: Interface,
+) -> Result<String, Error> {
+    let (input_type, output_type, t) = match interface {
+        Interface::Numeric => ("f64", "f64", Type::Float),
+        Interface::Boolean => ("bool", "bool", Type::Bool),
+        Interface::Classification => {
+            return Err(Error::Misc(
+                "Push programs are lowered only for numeric and boolean problems".into(),
+            ))
+        }
+    };
+    let mut lowering = PushLowering {
+        interface,
+        num_inputs,
+        max_steps,
+        num_vars: 0,
+        num_paths: 1,
+    };
+    let mut path = Path::default();
+    for i in 0..num_inputs {
+        path.stmts.push(Stmt {
+            name: format!("x{}", i),
+            expr: format!("input[{}]", i),
+            deps: vec![],
+        });
+        // The numeric interface preloads the inputs onto the float stack
+        if interface == Interface::Numeric {
+            path.push(t, Term::Var(format!("x{}", i)));
+        }
+    }
+    // The last op is on top of the exec stack
+    for op in code {
+        path.push_exec(op.clone())
+    }
+    let mut block = lowering.explore(path)?;
+    block.prune(&mut HashSet::new());
+    let mut out = String::new();
+    let _ = writeln!(out, "// Lowered from a push program");
+    let _ = writeln!(
+        out,
+        "|input: &[{}]| -> Option<{}> {{",
+        input_type, output_type
+    );
+    block.render(1, &mut out);
+    let _ = writeln!(out, "}}");
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn inst(op: LinearOp, a: usize, b: usize) -> Inst {
+        Inst { op, a, b }
+    }
+
+    #[test]
+    fn test_linear_gp() {
+        let config = LinearGpConfig {
+            max_steps: 100,
+            num_registers: Some(4),
+            return_registers: Some(1),
+            ..Default::default()
+        };
+        let code = vec![
+            inst(LinearOp::Add, 3, 1), // intron
+            inst(LinearOp::Mov, 0, 2),
+            inst(LinearOp::End, 0, 0),
+        ];
+        let closure = linear_gp(&code, &config, Interface::Classification);
+        assert!(closure.contains("    r[0] = r[2]; // [1]\n"));
+        assert!(!closure.contains("wrapping_add"));
+        assert!(closure.ends_with("    r[..1].to_vec()\n}\n"));
+
+        // R0 = max(R1, R2), which branches
+        let code = vec![
+            inst(LinearOp::Mov, 0, 1),
+            inst(LinearOp::SkipLt, 2, 1),
+            inst(LinearOp::Mov, 0, 2),
+            inst(LinearOp::End, 0, 0),
+        ];
+        let closure = linear_gp(&code, &config, Interface::Boolean);
+        assert!(closure.contains("            1 => { if r[2] < r[1] { pc += 1; } }\n"));
+        assert!(closure.contains("            3 => { break; }\n"));
+        assert!(closure.contains("Some(r[0] & 1 == 1)"));
+    }
+
+    #[test]
+    fn test_push() {
+        // x0 * x0 + x1, with the last op run first
+        let code = vec![
+            Op::FloatAdd,
+            Op::FloatMul,
+            Op::Dup(Type::Float),
+            Op::Swap(Type::Float),
+        ];
+        let closure = push(&code, 2, 100, Interface::Numeric).unwrap();
+        assert_eq!(
+            closure,
+            "// Lowered from a push program\n\
+             |input: &[f64]| -> Option<f64> {\n    \
+             let x0 = input[0];\n    \
+             let x1 = input[1];\n    \
+             let v0 = x0 * x0;\n    \
+             let v1 = v0 + x1;\n    \
+             Some(v1)\n\
+             }\n"
+        );
+
+        // Known values are folded, and branches on inputs fork
+        let code = vec![
+            Op::BoolNot,
+            Op::Input(1),
+            Op::ExecIf,
+            Op::Input(0),
+            Op::BoolAnd,
+            Op::BoolConst(true),
+            Op::BoolConst(true),
+        ];
+        let closure = push(&code, 2, 100, Interface::Boolean).unwrap();
+        assert!(closure.contains("    if x0 {\n        let v0 = !x1;\n        Some(v0)\n"));
+        assert!(closure.contains("    } else {\n        Some(false)\n    }\n"));
+
+        assert!(push(&[Op::WordAdd], 1, 100, Interface::Numeric).is_err());
+    }
+}
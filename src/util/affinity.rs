@@ -0,0 +1,103 @@
+//! Pinning threads to sets of cores, as configured in the `[affinity]`
+//! section. A thread started by a pinned thread inherits its cores, so an
+//! island's observer, evaluator, and emulator pool run on the island's
+//! cores, unless the emulators are given cores of their own.
+use std::cell::Cell;
+
+use crate::error::Error;
+
+/// Parses a set of cores written as a comma-separated list of cores and
+/// inclusive ranges of them, like "0-7,16-23", or "3".
+pub fn parse_core_set(spec: &str) -> Result<Vec<usize>, Error> {
+    let bad = |e: String| Error::Parsing(format!("Bad core set {:?}: {}", spec, e));
+    let mut cores = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let mut bounds = part.splitn(2, '-');
+        let first = bounds
+            .next()
+            .unwrap_or("")
+            .trim()
+            .parse::<usize>()
+            .map_err(|e| bad(e.to_string()))?;
+        let last = match bounds.next() {
+            Some(last) => last
+                .trim()
+                .parse::<usize>()
+                .map_err(|e| bad(e.to_string()))?,
+            None => first,
+        };
+        if last < first {
+            return Err(bad(format!("{} is backwards", part)));
+        }
+        cores.extend(first..=last);
+    }
+    if cores.is_empty() {
+        return Err(bad("no cores are named".to_string()));
+    }
+    cores.sort();
+    cores.dedup();
+    Ok(cores)
+}
+
+/// Pins the calling thread to the given cores.
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cores: &[usize]) -> Result<(), Error> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        for core in cores {
+            libc::CPU_SET(*core, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(Error::Misc(format!(
+                "Failed to pin the thread to cores {:?}: {}",
+                cores,
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(cores: &[usize]) -> Result<(), Error> {
+    Err(Error::Misc(format!(
+        "Can't pin the thread to cores {:?}: CPU affinity is only supported on Linux",
+        cores
+    )))
+}
+
+thread_local! {
+    static PINNED: Cell<bool> = Cell::new(false);
+}
+
+/// Pins the calling thread to the given cores, unless it has already been
+/// pinned by this function, so that it can be called cheaply from jobs on
+/// a thread pool. Failures are logged, not returned: a thread that can't
+/// be pinned just runs where the scheduler puts it.
+pub fn pin_once(cores: &[usize]) {
+    if PINNED.with(|p| p.replace(true)) {
+        return;
+    }
+    if let Err(e) = pin_current_thread(cores) {
+        log::warn!("{:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_core_set() {
+        assert_eq!(parse_core_set("3").unwrap(), vec![3]);
+        assert_eq!(
+            parse_core_set("0-3, 8,10-11").unwrap(),
+            vec![0, 1, 2, 3, 8, 10, 11]
+        );
+        assert_eq!(parse_core_set("2,1-2").unwrap(), vec![1, 2]);
+        assert!(parse_core_set("").is_err());
+        assert!(parse_core_set("4-2").is_err());
+        assert!(parse_core_set("a-b").is_err());
+    }
+}
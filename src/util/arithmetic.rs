@@ -0,0 +1,268 @@
+//! Guarded arithmetic, applying the configured handling of division by
+//! zero, overflow, and NaNs, and counting the faults along the way.
+use rand::Rng;
+use rand_distr::StandardNormal;
+
+use crate::configure::{ArithmeticConfig, FaultHandling};
+
+/// The integer types the backends compute with.
+pub trait Word: Copy + PartialEq + PartialOrd {
+    const ZERO: Self;
+    const ONE: Self;
+    const MIN: Self;
+    const MAX: Self;
+    fn checked_add(self, b: Self) -> Option<Self>;
+    fn checked_sub(self, b: Self) -> Option<Self>;
+    fn checked_mul(self, b: Self) -> Option<Self>;
+    fn checked_div(self, b: Self) -> Option<Self>;
+    fn checked_rem(self, b: Self) -> Option<Self>;
+    fn wrapping_add(self, b: Self) -> Self;
+    fn wrapping_sub(self, b: Self) -> Self;
+    fn wrapping_mul(self, b: Self) -> Self;
+    fn wrapping_div(self, b: Self) -> Self;
+    fn wrapping_rem(self, b: Self) -> Self;
+    fn saturating_add(self, b: Self) -> Self;
+    fn saturating_sub(self, b: Self) -> Self;
+    fn saturating_mul(self, b: Self) -> Self;
+}
+
+macro_rules! impl_word {
+    ($($t:ty),*) => {
+        $(
+            impl Word for $t {
+                const ZERO: Self = 0;
+                const ONE: Self = 1;
+                const MIN: Self = <$t>::min_value();
+                const MAX: Self = <$t>::max_value();
+                fn checked_add(self, b: Self) -> Option<Self> { <$t>::checked_add(self, b) }
+                fn checked_sub(self, b: Self) -> Option<Self> { <$t>::checked_sub(self, b) }
+                fn checked_mul(self, b: Self) -> Option<Self> { <$t>::checked_mul(self, b) }
+                fn checked_div(self, b: Self) -> Option<Self> { <$t>::checked_div(self, b) }
+                fn checked_rem(self, b: Self) -> Option<Self> { <$t>::checked_rem(self, b) }
+                fn wrapping_add(self, b: Self) -> Self { <$t>::wrapping_add(self, b) }
+                fn wrapping_sub(self, b: Self) -> Self { <$t>::wrapping_sub(self, b) }
+                fn wrapping_mul(self, b: Self) -> Self { <$t>::wrapping_mul(self, b) }
+                fn wrapping_div(self, b: Self) -> Self { <$t>::wrapping_div(self, b) }
+                fn wrapping_rem(self, b: Self) -> Self { <$t>::wrapping_rem(self, b) }
+                fn saturating_add(self, b: Self) -> Self { <$t>::saturating_add(self, b) }
+                fn saturating_sub(self, b: Self) -> Self { <$t>::saturating_sub(self, b) }
+                fn saturating_mul(self, b: Self) -> Self { <$t>::saturating_mul(self, b) }
+            }
+        )*
+    };
+}
+
+impl_word!(i32, i64, u64);
+
+#[derive(Clone, Debug, Default)]
+pub struct Guard {
+    config: ArithmeticConfig,
+    faults: usize,
+}
+
+impl Guard {
+    pub fn new(config: &ArithmeticConfig) -> Self {
+        Self {
+            config: config.clone(),
+            faults: 0,
+        }
+    }
+
+    /// The number of faults since the last reset, counting only those of
+    /// the kinds configured to be penalized.
+    pub fn faults(&self) -> usize {
+        self.faults
+    }
+
+    fn fault(&mut self, handling: FaultHandling) {
+        if handling == FaultHandling::Penalize {
+            self.faults += 1
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.faults = 0
+    }
+
+    fn overflow<T: Word>(&mut self, checked: Option<T>, wrapped: T, saturated: T) -> T {
+        match checked {
+            Some(n) => n,
+            None => {
+                self.fault(self.config.overflow);
+                match self.config.overflow {
+                    FaultHandling::Native | FaultHandling::Penalize => wrapped,
+                    FaultHandling::Protect | FaultHandling::Saturate => saturated,
+                }
+            }
+        }
+    }
+
+    /// The result of dividing `a` by zero, or `None` if the backend should
+    /// handle it in its own way.
+    fn division_by_zero<T: Word>(&mut self, a: T) -> Option<T> {
+        self.fault(self.config.division_by_zero);
+        match self.config.division_by_zero {
+            FaultHandling::Native | FaultHandling::Penalize => None,
+            FaultHandling::Protect => Some(T::ONE),
+            FaultHandling::Saturate if a == T::ZERO => Some(T::ZERO),
+            FaultHandling::Saturate if a < T::ZERO => Some(T::MIN),
+            FaultHandling::Saturate => Some(T::MAX),
+        }
+    }
+
+    pub fn add<T: Word>(&mut self, a: T, b: T) -> T {
+        self.overflow(a.checked_add(b), a.wrapping_add(b), a.saturating_add(b))
+    }
+
+    pub fn sub<T: Word>(&mut self, a: T, b: T) -> T {
+        self.overflow(a.checked_sub(b), a.wrapping_sub(b), a.saturating_sub(b))
+    }
+
+    pub fn mul<T: Word>(&mut self, a: T, b: T) -> T {
+        self.overflow(a.checked_mul(b), a.wrapping_mul(b), a.saturating_mul(b))
+    }
+
+    /// Returns `None` if division by zero is left to the backend.
+    pub fn div<T: Word>(&mut self, a: T, b: T) -> Option<T> {
+        if b == T::ZERO {
+            self.division_by_zero(a)
+        } else {
+            // Only MIN / -1 overflows
+            Some(self.overflow(a.checked_div(b), a.wrapping_div(b), T::MAX))
+        }
+    }
+
+    /// Returns `None` if division by zero is left to the backend.
+    pub fn rem<T: Word>(&mut self, a: T, b: T) -> Option<T> {
+        if b == T::ZERO {
+            self.division_by_zero(a)
+        } else {
+            Some(self.overflow(a.checked_rem(b), a.wrapping_rem(b), T::ZERO))
+        }
+    }
+
+    /// Applies the overflow and NaN handling to a float result.
+    pub fn float(&mut self, x: f64) -> f64 {
+        if x.is_nan() {
+            self.fault(self.config.nan);
+            match self.config.nan {
+                FaultHandling::Native | FaultHandling::Penalize => x,
+                FaultHandling::Protect | FaultHandling::Saturate => 0.0,
+            }
+        } else if x.is_infinite() {
+            self.fault(self.config.overflow);
+            match self.config.overflow {
+                FaultHandling::Native | FaultHandling::Penalize => x,
+                FaultHandling::Protect | FaultHandling::Saturate => std::f64::MAX.copysign(x),
+            }
+        } else {
+            x
+        }
+    }
+
+    /// Returns `None` if division by zero is left to the backend.
+    pub fn fdiv(&mut self, a: f64, b: f64) -> Option<f64> {
+        if b == 0.0 {
+            self.fault(self.config.division_by_zero);
+            match self.config.division_by_zero {
+                FaultHandling::Native | FaultHandling::Penalize => None,
+                FaultHandling::Protect => Some(1.0),
+                FaultHandling::Saturate if a == 0.0 || a.is_nan() => Some(0.0),
+                FaultHandling::Saturate => Some(std::f64::MAX.copysign(a)),
+            }
+        } else {
+            Some(self.float(a / b))
+        }
+    }
+}
+
+/// Whether a gene carrying a constant should have it perturbed, rather
+/// than being mutated some other way.
+pub fn mutates_constant<R: Rng>(config: &ArithmeticConfig, rng: &mut R) -> bool {
+    rng.gen_range(0.0, 1.0) < config.constant_mutation_rate
+}
+
+/// Adds gaussian noise to an integer constant, moving it by at least one.
+pub fn perturb_int<R: Rng>(config: &ArithmeticConfig, rng: &mut R, n: i64) -> i64 {
+    let z: f64 = rng.sample(StandardNormal);
+    let delta = (z * config.constant_mutation_scale).round() as i64;
+    if delta == 0 {
+        n.wrapping_add(if z < 0.0 { -1 } else { 1 })
+    } else {
+        n.wrapping_add(delta)
+    }
+}
+
+pub fn perturb_float<R: Rng>(config: &ArithmeticConfig, rng: &mut R, x: f64) -> f64 {
+    let z: f64 = rng.sample(StandardNormal);
+    x + z * config.constant_mutation_scale
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn guard(handling: FaultHandling) -> Guard {
+        Guard::new(&ArithmeticConfig {
+            division_by_zero: handling,
+            overflow: handling,
+            nan: handling,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_fault_handling() {
+        let mut native = guard(FaultHandling::Native);
+        assert_eq!(native.add(i32::max_value(), 1), i32::min_value());
+        assert_eq!(native.div(7_i64, 0), None);
+        assert!(native.float(std::f64::NAN).is_nan());
+        // only penalized faults are counted
+        assert_eq!(native.faults(), 0);
+
+        let mut penalize = guard(FaultHandling::Penalize);
+        assert_eq!(penalize.add(i32::max_value(), 1), i32::min_value());
+        assert_eq!(penalize.div(7_i64, 0), None);
+        assert!(penalize.float(std::f64::NAN).is_nan());
+        assert_eq!(penalize.faults(), 3);
+        penalize.reset();
+        assert_eq!(penalize.mul(3_u64, 4), 12);
+        assert_eq!(penalize.faults(), 0);
+
+        let mut mixed = Guard::new(&ArithmeticConfig {
+            division_by_zero: FaultHandling::Protect,
+            overflow: FaultHandling::Penalize,
+            ..Default::default()
+        });
+        assert_eq!(mixed.div(7_i64, 0), Some(1));
+        assert_eq!(mixed.add(i64::max_value(), 1), i64::min_value());
+        assert_eq!(mixed.faults(), 1);
+
+        let mut protect = guard(FaultHandling::Protect);
+        assert_eq!(protect.div(7_i64, 0), Some(1));
+        assert_eq!(protect.fdiv(7.0, 0.0), Some(1.0));
+        assert_eq!(protect.float(std::f64::NAN), 0.0);
+        assert_eq!(protect.sub(0_u64, 1), 0);
+
+        let mut saturate = guard(FaultHandling::Saturate);
+        assert_eq!(saturate.div(-7_i32, 0), Some(i32::min_value()));
+        assert_eq!(saturate.div(i32::min_value(), -1), Some(i32::max_value()));
+        assert_eq!(saturate.rem(0_i64, 0), Some(0));
+        assert_eq!(saturate.float(std::f64::NEG_INFINITY), std::f64::MIN);
+        assert_eq!(saturate.faults(), 0);
+    }
+
+    #[test]
+    fn test_perturb_constants() {
+        let config = ArithmeticConfig {
+            constant_mutation_scale: 0.0,
+            ..Default::default()
+        };
+        let mut rng = crate::util::random::hash_seed_rng(&0);
+        for _ in 0..10 {
+            assert_eq!((perturb_int(&config, &mut rng, 5) - 5).abs(), 1);
+            assert_eq!(perturb_float(&config, &mut rng, 0.5), 0.5);
+        }
+        assert!(!mutates_constant(&config, &mut rng));
+    }
+}
@@ -2,27 +2,58 @@ use seahash::hash_seeded;
 use std::iter::Iterator;
 
 /// See https://en.wikipedia.org/wiki/MinHash for discussion of algorithm
+///
+/// Each side's signature is computed afresh. When one side is compared
+/// many times, as a target is, its `Signature` should be computed once,
+/// and compared with `Signature::similarity`, instead.
 pub fn jaccard(one: &[u8], two: &[u8], grain: usize, num_hashes: u64) -> f64 {
     // the keys in the profile's maps and the pattern's map
     // should be in an identical order, just because nothing should
     // have disturbed them. But it would be better to verify this.
-    let self_byte_pos = byte_positions(&one, grain);
-    let other_byte_pos = byte_positions(&two, grain);
+    Signature::of(one, grain, num_hashes).similarity(&Signature::of(two, grain, num_hashes))
+}
 
-    (0_u64..num_hashes)
-        .filter(|seed| {
-            let s = self_byte_pos
-                .iter()
-                .map(|b| hash_seeded(b, *seed, 0, 0, 0))
-                .min();
-            let o = other_byte_pos
-                .iter()
-                .map(|b| hash_seeded(b, *seed, 0, 0, 0))
-                .min();
-            s == o
-        })
-        .count() as f64
-        / num_hashes as f64
+/// The MinHash signature of a sequence of bytes: the least hash of its
+/// byte positions under each of `num_hashes` hash functions. The estimated
+/// Jaccard similarity of two sequences is the fraction of the functions
+/// under which their signatures agree.
+///
+/// The functions are derived from two hashes of each position, the `i`th
+/// being `h1 + i * h2`, as Kirsch and Mitzenmacher suggest, so that each
+/// position is hashed twice, rather than `num_hashes` times, and the
+/// signature is updated with a loop over the functions that the compiler
+/// can vectorize.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signature(Vec<u64>);
+
+impl Signature {
+    pub fn of(bytes: &[u8], grain: usize, num_hashes: u64) -> Self {
+        let multipliers = (0..num_hashes).collect::<Vec<u64>>();
+        let mut signature = vec![std::u64::MAX; num_hashes as usize];
+        for position in byte_positions(bytes, grain).iter() {
+            let h1 = hash_seeded(position, 1, 2, 3, 4);
+            // an odd step, so that the functions don't coincide
+            let h2 = hash_seeded(position, 5, 6, 7, 8) | 1;
+            for (least, i) in signature.iter_mut().zip(multipliers.iter()) {
+                *least = (*least).min(h1.wrapping_add(i.wrapping_mul(h2)));
+            }
+        }
+        Signature(signature)
+    }
+
+    /// The estimated Jaccard similarity of the sequences whose signatures
+    /// these are, which should have been taken with the same number of
+    /// hashes.
+    pub fn similarity(&self, other: &Self) -> f64 {
+        debug_assert_eq!(self.0.len(), other.0.len());
+        let agreeing = self
+            .0
+            .iter()
+            .zip(other.0.iter())
+            .filter(|(s, o)| s == o)
+            .count();
+        agreeing as f64 / self.0.len() as f64
+    }
 }
 
 fn byte_positions(bytes: &[u8], grain: usize) -> Vec<[u8; 4]> {
@@ -43,3 +74,21 @@ fn byte_positions(bytes: &[u8], grain: usize) -> Vec<[u8; 4]> {
         })
         .collect::<Vec<[u8; 4]>>()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_jaccard() {
+        let one = b"the quick brown fox jumps over the lazy dog";
+        let two = b"the quick brown cat jumps over the lazy dog";
+        assert_eq!(jaccard(one, one, 4, 64), 1.0);
+        let similarity = jaccard(one, two, 4, 64);
+        assert!(similarity > 0.5 && similarity < 1.0);
+        // a signature taken once agrees with the comparison made afresh
+        let target = Signature::of(one, 4, 64);
+        assert_eq!(target.similarity(&Signature::of(two, 4, 64)), similarity);
+        assert!(jaccard(&[0_u8; 16], &[0xff_u8; 16], 4, 64) < 0.1);
+    }
+}
@@ -0,0 +1,495 @@
+//! Measures of a population's diversity, which tell stagnation through
+//! convergence apart from a plateau in a still-varied population.
+use hashbrown::HashMap;
+use rand::Rng;
+use serde::Serialize;
+
+/// The Shannon entropy, in bits, of the distribution given by the counts.
+pub fn entropy_of_counts<I: IntoIterator<Item = usize>>(counts: I) -> f64 {
+    let counts = counts
+        .into_iter()
+        .filter(|&n| n > 0)
+        .collect::<Vec<usize>>();
+    let total = counts.iter().sum::<usize>() as f64;
+    counts
+        .into_iter()
+        .map(|n| n as f64 / total)
+        .map(|p| -(p * p.log2()))
+        .sum()
+}
+
+/// The Levenshtein distance between the sequences, divided by the length
+/// of the longer, so that it lies between 0 and 1.
+pub fn normalized_edit_distance<T: PartialEq>(a: &[T], b: &[T]) -> f64 {
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 0.0;
+    }
+    let mut row = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, x) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let substitution = diagonal + if x == y { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()] as f64 / longest as f64
+}
+
+/// The mean normalized edit distance between `pairs` randomly chosen pairs
+/// of distinct genomes, or NaN if there are fewer than two.
+pub fn mean_pairwise_distance<T: PartialEq, R: Rng>(
+    genomes: &[&[T]],
+    pairs: usize,
+    rng: &mut R,
+) -> f64 {
+    if genomes.len() < 2 || pairs == 0 {
+        return std::f64::NAN;
+    }
+    let total = (0..pairs)
+        .map(|_| {
+            let i = rng.gen_range(0, genomes.len());
+            let j = (i + rng.gen_range(1, genomes.len())) % genomes.len();
+            normalized_edit_distance(genomes[i], genomes[j])
+        })
+        .sum::<f64>();
+    total / pairs as f64
+}
+
+/// The diversity of the paths, as the number of nodes in the trie they
+/// form, over the number of steps they take altogether: 1 if no two paths
+/// share a prefix, and approaching 0 as they converge on a single path.
+/// Returns NaN if there are no steps.
+pub fn path_trie_diversity<'a, I: Iterator<Item = &'a [u64]>>(paths: I) -> f64 {
+    // the trie's edges, from (parent node, step) to child node
+    let mut edges: HashMap<(usize, u64), usize> = HashMap::new();
+    let mut steps = 0;
+    for path in paths {
+        let mut node = 0;
+        for step in path {
+            steps += 1;
+            let next = edges.len() + 1;
+            node = *edges.entry((node, *step)).or_insert(next);
+        }
+    }
+    if steps == 0 {
+        return std::f64::NAN;
+    }
+    edges.len() as f64 / steps as f64
+}
+
+/// The shape of the trie formed by a set of execution paths, which is
+/// cheap to take, and says more about how the population's behaviour is
+/// spread than `path_trie_diversity` alone.
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct PathTrieStats {
+    pub paths: usize,
+    /// The number of nodes, not counting the root
+    pub node_count: usize,
+    /// The mean depth of the leaves
+    pub mean_depth: f64,
+    /// The mean number of children of the nodes that have any, the root
+    /// included
+    pub branching_factor: f64,
+    /// The number of nodes added since the shape was last taken, which is
+    /// every node, if it's taken from scratch
+    pub novel_prefixes: usize,
+    /// The commonest prefixes of `prefix_len` steps, with the number of
+    /// paths that begin with each, commonest first
+    pub common_prefixes: Vec<(Vec<u64>, usize)>,
+}
+
+impl PathTrieStats {
+    pub fn of<'a, I: Iterator<Item = &'a [u64]>>(
+        paths: I,
+        prefix_len: usize,
+        num_prefixes: usize,
+    ) -> Self {
+        // the trie's edges, from (parent node, step) to child node, and the
+        // depth and number of children of each node, the root being 0
+        let mut edges: HashMap<(usize, u64), usize> = HashMap::new();
+        let mut depths = vec![0];
+        let mut children = vec![0];
+        let mut prefixes: HashMap<&[u64], usize> = HashMap::new();
+        let mut num_paths = 0;
+        for path in paths {
+            num_paths += 1;
+            let mut node = 0;
+            for step in path {
+                let next = depths.len();
+                let child = *edges.entry((node, *step)).or_insert(next);
+                if child == next {
+                    depths.push(depths[node] + 1);
+                    children.push(0);
+                    children[node] += 1;
+                }
+                node = child;
+            }
+            if prefix_len > 0 && path.len() >= prefix_len {
+                *prefixes.entry(&path[..prefix_len]).or_insert(0) += 1;
+            }
+        }
+        let leaf_depths = depths
+            .iter()
+            .zip(children.iter())
+            .filter(|(_, &c)| c == 0)
+            .map(|(&d, _)| d as f64)
+            .collect::<Vec<f64>>();
+        let branch_counts = children
+            .iter()
+            .filter(|&&c| c > 0)
+            .map(|&c| c as f64)
+            .collect::<Vec<f64>>();
+        let mean = |xs: &[f64]| {
+            if xs.is_empty() {
+                std::f64::NAN
+            } else {
+                xs.iter().sum::<f64>() / xs.len() as f64
+            }
+        };
+        let common_prefixes = commonest(prefixes.into_iter(), num_prefixes);
+        Self {
+            paths: num_paths,
+            node_count: edges.len(),
+            mean_depth: if edges.is_empty() {
+                std::f64::NAN
+            } else {
+                mean(&leaf_depths)
+            },
+            branching_factor: mean(&branch_counts),
+            novel_prefixes: edges.len(),
+            common_prefixes,
+        }
+    }
+}
+
+/// The `n` commonest prefixes, with their counts, commonest first.
+fn commonest<'a, I: Iterator<Item = (&'a [u64], usize)>>(
+    prefixes: I,
+    n: usize,
+) -> Vec<(Vec<u64>, usize)> {
+    let mut prefixes = prefixes.collect::<Vec<_>>();
+    // ties broken by the prefix itself, so that the choice is stable
+    prefixes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    prefixes
+        .into_iter()
+        .take(n)
+        .map(|(prefix, count)| (prefix.to_vec(), count))
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    depth: usize,
+    children: usize,
+    /// The number of paths that pass through the node, or end at it
+    paths: usize,
+    /// The number of paths that end at the node
+    ends: usize,
+}
+
+/// The trie of a changing set of execution paths, such as those of the
+/// specimens in an observer's window, whose shape is kept up to date as
+/// paths are inserted and removed, so that `path_trie_diversity` and
+/// `PathTrieStats` can be had without building it afresh from every path.
+pub struct PathTrie {
+    /// The trie's edges, from (parent node, step) to child node
+    edges: HashMap<(usize, u64), usize>,
+    /// The nodes, by number, the root being 0. The numbers of the nodes
+    /// removed are kept in `free`, to be reused.
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    prefix_len: usize,
+    prefixes: HashMap<Vec<u64>, usize>,
+    paths: usize,
+    steps: usize,
+    node_count: usize,
+    /// The number of nodes with children, the root included
+    branching_nodes: usize,
+    /// The sum of the depths of the nodes without children
+    leaf_depths: usize,
+    novel_prefixes: usize,
+}
+
+impl PathTrie {
+    /// An empty trie, which counts the paths that begin with each prefix of
+    /// `prefix_len` steps.
+    pub fn new(prefix_len: usize) -> Self {
+        Self {
+            edges: HashMap::new(),
+            nodes: vec![Node {
+                depth: 0,
+                children: 0,
+                paths: 0,
+                ends: 0,
+            }],
+            free: vec![],
+            prefix_len,
+            prefixes: HashMap::new(),
+            paths: 0,
+            steps: 0,
+            node_count: 0,
+            branching_nodes: 0,
+            leaf_depths: 0,
+            novel_prefixes: 0,
+        }
+    }
+
+    /// The number of paths in the trie.
+    pub fn paths(&self) -> usize {
+        self.paths
+    }
+
+    pub fn insert(&mut self, path: &[u64]) {
+        self.paths += 1;
+        self.steps += path.len();
+        self.nodes[0].paths += 1;
+        let mut node = 0;
+        for step in path {
+            node = match self.edges.get(&(node, *step)) {
+                Some(child) => *child,
+                None => {
+                    let child = self.add_node(node);
+                    self.edges.insert((node, *step), child);
+                    child
+                }
+            };
+            self.nodes[node].paths += 1;
+        }
+        self.nodes[node].ends += 1;
+        if self.prefix_len > 0 && path.len() >= self.prefix_len {
+            let prefix = &path[..self.prefix_len];
+            if let Some(n) = self.prefixes.get_mut(prefix) {
+                *n += 1;
+            } else {
+                self.prefixes.insert(prefix.to_vec(), 1);
+            }
+        }
+    }
+
+    /// Removes a path that was inserted, pruning the nodes that no other
+    /// path passes through. Returns false, and leaves the trie as it was,
+    /// if the path isn't in it.
+    pub fn remove(&mut self, path: &[u64]) -> bool {
+        let mut trail = Vec::with_capacity(path.len());
+        let mut node = 0;
+        for step in path {
+            match self.edges.get(&(node, *step)) {
+                Some(child) => {
+                    trail.push((node, *step, *child));
+                    node = *child;
+                }
+                None => return false,
+            }
+        }
+        if self.nodes[node].ends == 0 {
+            return false;
+        }
+        self.nodes[node].ends -= 1;
+        self.paths -= 1;
+        self.steps -= path.len();
+        self.nodes[0].paths -= 1;
+        // a node is passed through by no more paths than its parent, so
+        // those to be pruned are at the end of the trail
+        for (parent, step, child) in trail.into_iter().rev() {
+            self.nodes[child].paths -= 1;
+            if self.nodes[child].paths == 0 {
+                self.remove_node(parent, step, child);
+            }
+        }
+        if self.prefix_len > 0 && path.len() >= self.prefix_len {
+            let prefix = &path[..self.prefix_len];
+            let emptied = self.prefixes.get_mut(prefix).map_or(false, |n| {
+                *n -= 1;
+                *n == 0
+            });
+            if emptied {
+                self.prefixes.remove(prefix);
+            }
+        }
+        true
+    }
+
+    fn add_node(&mut self, parent: usize) -> usize {
+        let depth = self.nodes[parent].depth + 1;
+        if self.nodes[parent].children == 0 {
+            self.branching_nodes += 1;
+            self.leaf_depths -= self.nodes[parent].depth;
+        }
+        self.nodes[parent].children += 1;
+        self.node_count += 1;
+        self.novel_prefixes += 1;
+        self.leaf_depths += depth;
+        let node = Node {
+            depth,
+            children: 0,
+            paths: 0,
+            ends: 0,
+        };
+        if let Some(id) = self.free.pop() {
+            self.nodes[id] = node;
+            id
+        } else {
+            self.nodes.push(node);
+            self.nodes.len() - 1
+        }
+    }
+
+    fn remove_node(&mut self, parent: usize, step: u64, node: usize) {
+        debug_assert_eq!(self.nodes[node].children, 0);
+        self.edges.remove(&(parent, step));
+        self.leaf_depths -= self.nodes[node].depth;
+        self.node_count -= 1;
+        self.nodes[parent].children -= 1;
+        if self.nodes[parent].children == 0 {
+            self.branching_nodes -= 1;
+            self.leaf_depths += self.nodes[parent].depth;
+        }
+        self.free.push(node);
+    }
+
+    /// As `path_trie_diversity` measures it.
+    pub fn diversity(&self) -> f64 {
+        if self.steps == 0 {
+            return std::f64::NAN;
+        }
+        self.node_count as f64 / self.steps as f64
+    }
+
+    /// The shape of the trie, as `PathTrieStats::of` takes it, counting the
+    /// novel prefixes afresh from here on.
+    pub fn report(&mut self, num_prefixes: usize) -> PathTrieStats {
+        // every node but the root is the child of one other
+        let leaves = self.node_count + 1 - self.branching_nodes;
+        PathTrieStats {
+            paths: self.paths,
+            node_count: self.node_count,
+            mean_depth: if self.node_count == 0 {
+                std::f64::NAN
+            } else {
+                self.leaf_depths as f64 / leaves as f64
+            },
+            branching_factor: if self.branching_nodes == 0 {
+                std::f64::NAN
+            } else {
+                self.node_count as f64 / self.branching_nodes as f64
+            },
+            novel_prefixes: std::mem::replace(&mut self.novel_prefixes, 0),
+            common_prefixes: commonest(
+                self.prefixes.iter().map(|(p, n)| (&p[..], *n)),
+                num_prefixes,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_entropy_of_counts() {
+        assert_eq!(entropy_of_counts(vec![5]), 0.0);
+        assert!((entropy_of_counts(vec![1, 1, 0]) - 1.0).abs() < 1e-12);
+        assert!((entropy_of_counts(vec![2, 2, 2, 2]) - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_normalized_edit_distance() {
+        assert_eq!(normalized_edit_distance(b"kitten", b"sitting"), 3.0 / 7.0);
+        assert_eq!(normalized_edit_distance(b"abc", b"abc"), 0.0);
+        assert_eq!(normalized_edit_distance(b"", b"ab"), 1.0);
+        assert_eq!(normalized_edit_distance::<u8>(&[], &[]), 0.0);
+    }
+
+    #[test]
+    fn test_mean_pairwise_distance() {
+        let mut rng = rand::thread_rng();
+        let same: Vec<&[u8]> = vec![b"abcd", b"abcd", b"abcd"];
+        assert_eq!(mean_pairwise_distance(&same, 10, &mut rng), 0.0);
+        let disjoint: Vec<&[u8]> = vec![b"aaaa", b"bbbb"];
+        assert_eq!(mean_pairwise_distance(&disjoint, 10, &mut rng), 1.0);
+        assert!(mean_pairwise_distance(&disjoint[..1], 10, &mut rng).is_nan());
+    }
+
+    #[test]
+    fn test_path_trie_diversity() {
+        let same = vec![vec![1, 2, 3], vec![1, 2, 3]];
+        assert_eq!(path_trie_diversity(same.iter().map(|p| &p[..])), 0.5);
+        let forked = vec![vec![1, 2, 3], vec![1, 2, 4]];
+        assert_eq!(
+            path_trie_diversity(forked.iter().map(|p| &p[..])),
+            4.0 / 6.0
+        );
+        let disjoint = vec![vec![1, 2], vec![3, 4]];
+        assert_eq!(path_trie_diversity(disjoint.iter().map(|p| &p[..])), 1.0);
+        assert!(path_trie_diversity(std::iter::empty()).is_nan());
+    }
+
+    #[test]
+    fn test_path_trie_stats() {
+        let paths = vec![vec![1, 2, 3], vec![1, 2, 4], vec![1, 5], vec![6]];
+        let stats = PathTrieStats::of(paths.iter().map(|p| &p[..]), 2, 2);
+        assert_eq!(stats.paths, 4);
+        assert_eq!(stats.node_count, 6);
+        // the leaves are 3, 4, 5, and 6, at depths 3, 3, 2, and 1
+        assert_eq!(stats.mean_depth, 9.0 / 4.0);
+        // the root, 1, and 2 have 2 children each
+        assert_eq!(stats.branching_factor, 2.0);
+        assert_eq!(
+            stats.common_prefixes,
+            vec![(vec![1, 2], 2), (vec![1, 5], 1)]
+        );
+        assert_eq!(stats.novel_prefixes, 6);
+        let empty = PathTrieStats::of(std::iter::empty(), 2, 2);
+        assert_eq!(empty.node_count, 0);
+        assert!(empty.mean_depth.is_nan() && empty.branching_factor.is_nan());
+    }
+
+    #[test]
+    fn test_path_trie() {
+        let paths = vec![
+            vec![1, 2, 3],
+            vec![1, 2, 4],
+            vec![1, 5],
+            vec![6],
+            vec![1, 2],
+        ];
+        let batch = |paths: &[Vec<u64>]| PathTrieStats::of(paths.iter().map(|p| &p[..]), 2, 2);
+        let mut trie = PathTrie::new(2);
+        for path in paths.iter() {
+            trie.insert(path);
+        }
+        assert_eq!(trie.report(2), batch(&paths));
+        assert_eq!(
+            trie.diversity(),
+            path_trie_diversity(paths.iter().map(|p| &p[..]))
+        );
+        // [1, 2] is a prefix of other paths, but wasn't inserted twice
+        assert!(trie.remove(&[1, 2]));
+        assert!(!trie.remove(&[1, 2]));
+        assert!(!trie.remove(&[7]));
+        assert!(trie.remove(&[1, 2, 4]));
+        trie.insert(&[6, 7]);
+        let rest = vec![vec![1, 2, 3], vec![1, 5], vec![6], vec![6, 7]];
+        let stats = trie.report(2);
+        // only 7 is new since the last report
+        assert_eq!(stats.novel_prefixes, 1);
+        assert_eq!(
+            PathTrieStats {
+                novel_prefixes: batch(&rest).novel_prefixes,
+                ..stats
+            },
+            batch(&rest)
+        );
+        for path in rest.iter() {
+            assert!(trie.remove(path));
+        }
+        let empty = trie.report(2);
+        assert_eq!((empty.paths, empty.node_count), (0, 0));
+        assert!(empty.mean_depth.is_nan() && trie.diversity().is_nan());
+        assert!(empty.common_prefixes.is_empty());
+    }
+}
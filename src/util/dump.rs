@@ -3,33 +3,56 @@ use std::fs;
 use std::io::{Read, Write};
 use std::path::Path;
 
-use deflate::write::GzEncoder;
-use deflate::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use serde::{de::DeserializeOwned, Serialize};
 
+use crate::configure::DumpCompression;
 use crate::error::Error;
 
 pub fn zip(bytes: &[u8]) -> Result<Vec<u8>, Error> {
-    let mut gz = GzEncoder::new(Vec::new(), Compression::Default);
+    let mut gz = GzEncoder::new(Vec::new(), Compression::default());
     gz.write_all(bytes)?;
     gz.finish().map_err(Error::from)
 }
 
+/// Dumps the thing as gzipped JSON, by way of a temporary file, as
+/// `dump_compressed` does.
 pub fn dump<T: Serialize, P: AsRef<Path> + Debug>(thing: T, path: P) -> Result<(), Error> {
-    let mut file = fs::File::create(&path)?;
-    let mut dumper = || -> Result<(), Error> {
-        let mut gz = GzEncoder::new(Vec::new(), Compression::Default);
-        serde_json::to_writer(&mut gz, &thing)?;
-        let compressed = gz.finish()?;
-        file.write_all(&compressed).map_err(Error::from)
+    dump_compressed(thing, path, DumpCompression::Gzip)
+}
+
+/// Dumps the thing as JSON, compressed as configured. The dump is written
+/// to a temporary file first, and renamed into place, so that a run that's
+/// interrupted mid-dump doesn't leave a corrupt file behind.
+pub fn dump_compressed<T: Serialize, P: AsRef<Path> + Debug>(
+    thing: T,
+    path: P,
+    compression: DumpCompression,
+) -> Result<(), Error> {
+    let json = serde_json::to_vec(&thing)?;
+    let bytes = match compression {
+        DumpCompression::None => json,
+        DumpCompression::Gzip => zip(&json)?,
+        #[cfg(feature = "zstd")]
+        DumpCompression::Zstd => zstd::stream::encode_all(&json[..], 0)?,
+        #[cfg(not(feature = "zstd"))]
+        DumpCompression::Zstd => {
+            return Err(Error::Misc(
+                "Zstd compression requires the \"zstd\" feature".to_string(),
+            ))
+        }
     };
-    if let Err(e) = dumper() {
-        fs::remove_file(&path)?;
-        log::warn!("Failed to dump to {:?}, removed file", path);
-        Err(e)
-    } else {
-        Ok(())
+    let mut tmp_path = path.as_ref().as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let res = fs::write(&tmp_path, &bytes).and_then(|()| fs::rename(&tmp_path, &path));
+    if let Err(e) = res {
+        let _ = fs::remove_file(&tmp_path);
+        log::warn!("Failed to dump to {:?}", path);
+        return Err(e.into());
     }
+    Ok(())
 }
 
 pub fn undump<T: DeserializeOwned, P: AsRef<Path> + Debug>(path: P) -> Result<T, Error> {
@@ -37,15 +60,14 @@ pub fn undump<T: DeserializeOwned, P: AsRef<Path> + Debug>(path: P) -> Result<T,
     let mut bytes = Vec::new();
     let num_bytes = file.read_to_end(&mut bytes)?;
     log::debug!("{} bytes read from {:?}", num_bytes, path);
-    let decompressed: Vec<u8> = deflate::deflate_bytes_gzip(&bytes);
-    let thing = serde_json::de::from_reader::<&[u8], T>(&decompressed)?;
+    let thing = serde_json::de::from_reader(GzDecoder::new(&bytes[..]))?;
     Ok(thing)
 }
 
 pub fn ron_dump<T: Serialize, P: AsRef<Path> + Debug>(thing: T, path: P) -> Result<(), Error> {
     let mut file = fs::File::create(&path)?;
     let mut dumper = || -> Result<(), Error> {
-        let mut gz = GzEncoder::new(Vec::new(), Compression::Default);
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
         let s = ron::ser::to_string(&thing)?;
         gz.write_all(s.as_bytes())?;
         let compressed = gz.finish()?;
@@ -65,7 +87,51 @@ pub fn ron_undump<T: DeserializeOwned, P: AsRef<Path> + Debug>(path: P) -> Resul
     let mut bytes = Vec::new();
     let num_bytes = file.read_to_end(&mut bytes)?;
     log::debug!("{} bytes read from {:?}", num_bytes, path);
-    let decompressed: Vec<u8> = deflate::deflate_bytes_gzip(&bytes);
-    let thing = ron::de::from_reader::<&[u8], T>(&decompressed)?;
+    let thing = ron::de::from_reader(GzDecoder::new(&bytes[..]))?;
     Ok(thing)
 }
+
+#[cfg(test)]
+mod test {
+    use crate::configure::RetentionPolicy;
+
+    use super::*;
+
+    #[test]
+    fn test_dump_compressed() {
+        let path =
+            std::env::temp_dir().join(format!("berbalang_test_{}.json", rand::random::<u64>()));
+        dump_compressed(&vec![1, 2, 3], &path, DumpCompression::None).unwrap();
+        let data = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Vec<u32>>(&data).unwrap(),
+            vec![1, 2, 3]
+        );
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        assert!(!Path::new(&tmp_path).exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_undump() {
+        let path =
+            std::env::temp_dir().join(format!("berbalang_test_{}.json.gz", rand::random::<u64>()));
+        dump(&vec![1, 2, 3], &path).unwrap();
+        let data = undump::<Vec<u32>, _>(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_retention_policy() {
+        let policy = RetentionPolicy {
+            keep_every: 10,
+            keep_last: 3,
+        };
+        let kept = (0..=25)
+            .filter(|e| policy.keeps(*e, 25))
+            .collect::<Vec<usize>>();
+        assert_eq!(kept, vec![0, 10, 20, 23, 24, 25]);
+    }
+}
@@ -1,12 +1,19 @@
+pub mod affinity;
 pub mod architecture;
+pub mod arithmetic;
 pub mod bitwise;
+pub mod checksum;
 pub mod count_min_sketch;
 pub mod distance;
+pub mod diversity;
 pub mod dump;
 pub mod entropy;
 pub mod five_letter_words;
 pub mod ldd;
 pub mod levy_flight;
 pub mod name;
+#[cfg(feature = "parquet")]
+pub mod parquet;
 pub mod random;
+pub mod soup_store;
 pub mod statistics;
@@ -0,0 +1,100 @@
+//! Columnar output, for analysing long runs with pandas or polars.
+use std::fs::File;
+use std::path::Path;
+
+use parquet::column::writer::ColumnWriter;
+use parquet::data_type::ByteArray;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+
+use crate::error::Error;
+
+pub enum Column {
+    Int(Vec<i64>),
+    Float(Vec<f64>),
+    Text(Vec<String>),
+}
+
+impl Column {
+    fn declaration(&self, name: &str) -> String {
+        match self {
+            Column::Int(_) => format!("REQUIRED INT64 {};", name),
+            Column::Float(_) => format!("REQUIRED DOUBLE {};", name),
+            Column::Text(_) => format!("REQUIRED BINARY {} (UTF8);", name),
+        }
+    }
+}
+
+/// Writes the named columns, which should all be of the same length, to a
+/// Parquet file, as a single row group.
+pub fn write_table<P: AsRef<Path>>(path: P, columns: &[(String, Column)]) -> Result<(), Error> {
+    let message = format!(
+        "message table {{ {} }}",
+        columns
+            .iter()
+            .map(|(name, column)| column.declaration(name))
+            .collect::<Vec<String>>()
+            .join(" ")
+    );
+    let schema = parse_message_type(&message)?;
+    let properties = WriterProperties::builder().build();
+    let file = File::create(path)?;
+    let mut writer = SerializedFileWriter::new(file, schema.into(), properties.into())?;
+    let mut row_group = writer.next_row_group()?;
+    for (name, column) in columns {
+        let mut column_writer = row_group
+            .next_column()?
+            .ok_or_else(|| Error::Misc(format!("No column in schema for {}", name)))?;
+        match (column_writer.untyped(), column) {
+            (ColumnWriter::Int64ColumnWriter(w), Column::Int(xs)) => {
+                w.write_batch(xs, None, None)?;
+            }
+            (ColumnWriter::DoubleColumnWriter(w), Column::Float(xs)) => {
+                w.write_batch(xs, None, None)?;
+            }
+            (ColumnWriter::ByteArrayColumnWriter(w), Column::Text(xs)) => {
+                let xs = xs
+                    .iter()
+                    .map(|s| ByteArray::from(s.as_str()))
+                    .collect::<Vec<ByteArray>>();
+                w.write_batch(&xs, None, None)?;
+            }
+            _ => return Err(Error::Misc(format!("Wrong column writer for {}", name))),
+        }
+        column_writer.close()?;
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    use super::*;
+
+    #[test]
+    fn test_write_table() {
+        let path =
+            std::env::temp_dir().join(format!("berbalang_test_{}.parquet", rand::random::<u64>()));
+        let columns = vec![
+            ("epoch".to_string(), Column::Int(vec![1, 2, 3])),
+            (
+                "fitness".to_string(),
+                Column::Float(vec![0.5, 0.25, std::f64::NAN]),
+            ),
+            (
+                "name".to_string(),
+                Column::Text(vec!["a".to_string(), "b".to_string(), "c".to_string()]),
+            ),
+        ];
+        write_table(&path, &columns).expect("Failed to write table");
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        let metadata = reader.metadata().file_metadata();
+        assert_eq!(metadata.num_rows(), 3);
+        assert_eq!(metadata.schema_descr().num_columns(), 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
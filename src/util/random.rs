@@ -1,10 +1,63 @@
+use std::cell::RefCell;
 use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
 
-use rand::SeedableRng;
+use rand::{RngCore, SeedableRng};
 use rand_xoshiro::Xoroshiro64Star;
 
 pub type Prng = Xoroshiro64Star;
 
+thread_local! {
+    static SEEDED_RNG: RefCell<Option<Prng>> = RefCell::new(None);
+}
+
+/// Seeds the generator returned by `thread_rng` in the current thread, so
+/// that the variation operators running in this thread can be replayed.
+/// See `RoperConfig::deterministic`.
+pub fn seed_thread_rng<H: Hash>(seed: &H) {
+    SEEDED_RNG.with(|r| *r.borrow_mut() = Some(hash_seed_rng(seed)))
+}
+
+/// A stand-in for `rand::thread_rng`, which draws from the seeded
+/// generator if `seed_thread_rng` has been called in this thread.
+pub fn thread_rng() -> ThreadRng {
+    ThreadRng {
+        _not_send: PhantomData,
+    }
+}
+
+pub struct ThreadRng {
+    // the generator is thread local, so the handle shouldn't leave the thread
+    _not_send: PhantomData<*const ()>,
+}
+
+impl ThreadRng {
+    fn with<T>(f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+        SEEDED_RNG.with(|r| match r.borrow_mut().as_mut() {
+            Some(rng) => f(rng),
+            None => f(&mut rand::thread_rng()),
+        })
+    }
+}
+
+impl RngCore for ThreadRng {
+    fn next_u32(&mut self) -> u32 {
+        Self::with(|rng| rng.next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        Self::with(|rng| rng.next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        Self::with(|rng| rng.fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        Self::with(|rng| rng.try_fill_bytes(dest))
+    }
+}
+
 /// Takes a hashable value and returns a PRNG seeded with that
 /// value's hash.
 pub fn hash_seed_rng<H: Hash>(thing: &H) -> Prng {
@@ -22,3 +75,69 @@ pub fn hash_seed<H: Hash>(thing: &H) -> [u8; 8] {
     }
     seed
 }
+
+/// The stream an island's own `random_seed` is drawn from.
+pub const ISLAND_STREAM: &str = "island";
+/// The stream an island's migration decisions are drawn from, so that they
+/// don't perturb, and aren't perturbed by, the variation and evaluation.
+pub const MIGRATION_STREAM: &str = "migration";
+
+/// Derives the seed of one of an island's random streams from a parent
+/// seed, as
+///
+/// ```text
+/// splitmix64(fnv1a64(le64(seed) ++ le64(island) ++ utf8(stream)))
+/// ```
+///
+/// where `le64` gives the eight little-endian bytes of its argument, and
+/// `splitmix64` is the finalizer of the SplitMix64 generator, which spreads
+/// the weak low bits of FNV. Each island's `random_seed` is derived from the
+/// run's, with `ISLAND_STREAM`, and its migration seed from its own, with
+/// `MIGRATION_STREAM`, so that either can be recomputed, on any platform,
+/// from the seeds recorded in the manifest.
+pub fn derive_seed(seed: u64, island: usize, stream: &str) -> u64 {
+    let mut h = fnv::FnvHasher::default();
+    h.write(&seed.to_le_bytes());
+    h.write(&(island as u64).to_le_bytes());
+    h.write(stream.as_bytes());
+    let mut z = h.finish();
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_seeded_thread_rng() {
+        let draw = || {
+            std::thread::spawn(|| {
+                seed_thread_rng(&1234_u64);
+                let mut rng = thread_rng();
+                (0..8).map(|_| rng.gen::<u64>()).collect::<Vec<u64>>()
+            })
+            .join()
+            .unwrap()
+        };
+        assert_eq!(draw(), draw());
+    }
+
+    #[test]
+    fn test_derive_seed() {
+        // pinned, so that a change to the derivation is noticed
+        assert_eq!(derive_seed(1234, 0, ISLAND_STREAM), 0x7fa4_a5aa_4e1a_f2a2);
+        assert_eq!(derive_seed(1234, 1, ISLAND_STREAM), 0x16eb_480b_ec48_0bf5);
+        let seeds = (0..4)
+            .map(|i| derive_seed(1234, i, ISLAND_STREAM))
+            .collect::<hashbrown::HashSet<u64>>();
+        assert_eq!(seeds.len(), 4);
+        assert_ne!(
+            derive_seed(1234, 0, ISLAND_STREAM),
+            derive_seed(1234, 0, MIGRATION_STREAM)
+        );
+    }
+}
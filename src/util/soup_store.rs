@@ -0,0 +1,203 @@
+//! Content-addressed storage for the observer's soup dumps. Rather than a
+//! full copy of the soup every epoch, each epoch's dump records the changes
+//! since the previous one. Every so often, or when the changes would be
+//! larger than the soup itself, the soup is stored whole, as a keyframe,
+//! under the hash of its contents, so identical soups are stored only once.
+//!
+//! The layout of the soup directory is:
+//! - `epoch_{n}.json`: a `SoupRecord` for each epoch dumped
+//! - `objects/{hash}.json`: the keyframes, as lists of `[allele, count]`
+//! - `latest.json`: the latest soup, whole, in the same format
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SoupRecord {
+    pub epoch: usize,
+    /// The hash of the soup's contents at this epoch
+    pub hash: String,
+    /// The epoch of the dump that this one records the changes since, or
+    /// `None` if this is a keyframe, stored whole under its hash.
+    pub parent: Option<usize>,
+    /// The alleles whose counts have changed, or that are new
+    pub changed: Vec<(Value, usize)>,
+    /// The alleles no longer in the soup
+    pub removed: Vec<Value>,
+}
+
+/// The soup's entries, keyed by the JSON serialization of their alleles,
+/// which gives us an ordering, and a canonical form to hash.
+type Entries = BTreeMap<String, usize>;
+
+pub struct SoupStore {
+    dir: PathBuf,
+    keyframe_every: usize,
+    since_keyframe: usize,
+    previous: Option<(usize, Entries)>,
+}
+
+impl SoupStore {
+    /// Stores the soups in `dir`, storing one whole at least every
+    /// `keyframe_every` dumps, unless that's 0.
+    pub fn new<P: AsRef<Path>>(dir: P, keyframe_every: usize) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            keyframe_every,
+            since_keyframe: 0,
+            previous: None,
+        }
+    }
+
+    pub fn record<A: Serialize + Hash + Eq>(
+        &mut self,
+        epoch: usize,
+        soup: &HashMap<A, usize>,
+    ) -> Result<SoupRecord, Error> {
+        let mut entries = Entries::new();
+        for (allele, count) in soup.iter() {
+            entries.insert(serde_json::to_string(allele)?, *count);
+        }
+        let hash = content_hash(&entries)?;
+        let due = self.keyframe_every > 0 && self.since_keyframe >= self.keyframe_every;
+        let mut record = SoupRecord {
+            epoch,
+            hash: hash.clone(),
+            parent: None,
+            changed: Vec::new(),
+            removed: Vec::new(),
+        };
+        match self.previous.as_ref() {
+            Some((parent, previous)) if !due => {
+                for (key, count) in entries.iter() {
+                    if previous.get(key) != Some(count) {
+                        record.changed.push((serde_json::from_str(key)?, *count));
+                    }
+                }
+                for key in previous.keys() {
+                    if !entries.contains_key(key) {
+                        record.removed.push(serde_json::from_str(key)?);
+                    }
+                }
+                record.parent = Some(*parent);
+            }
+            _ => {}
+        }
+        if record.parent.is_none() || record.changed.len() + record.removed.len() > entries.len() {
+            record.parent = None;
+            record.changed.clear();
+            record.removed.clear();
+            self.write_object(&hash, &entries)?;
+            self.since_keyframe = 0;
+        }
+        self.since_keyframe += 1;
+
+        fs::write(
+            self.dir.join(format!("epoch_{}.json", epoch)),
+            serde_json::to_vec(&record)?,
+        )?;
+        fs::write(
+            self.dir.join("latest.json"),
+            serde_json::to_vec(&pairs(&entries)?)?,
+        )?;
+        self.previous = Some((epoch, entries));
+        Ok(record)
+    }
+
+    fn write_object(&self, hash: &str, entries: &Entries) -> Result<(), Error> {
+        let objects = self.dir.join("objects");
+        fs::create_dir_all(&objects)?;
+        let path = objects.join(format!("{}.json", hash));
+        if !path.exists() {
+            fs::write(path, serde_json::to_vec(&pairs(entries)?)?)?;
+        }
+        Ok(())
+    }
+}
+
+fn content_hash(entries: &Entries) -> Result<String, Error> {
+    let bytes = serde_json::to_vec(entries)?;
+    Ok(format!("{:016x}", seahash::hash(&bytes)))
+}
+
+fn pairs(entries: &Entries) -> Result<Vec<(Value, usize)>, Error> {
+    entries
+        .iter()
+        .map(|(key, count)| Ok((serde_json::from_str(key)?, *count)))
+        .collect()
+}
+
+fn read_record(dir: &Path, epoch: usize) -> Result<SoupRecord, Error> {
+    let data = fs::read_to_string(dir.join(format!("epoch_{}.json", epoch)))?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+/// Reconstructs the soup at the given epoch from the store in `dir`, as a
+/// list of `[allele, count]` pairs.
+pub fn load<P: AsRef<Path>>(dir: P, epoch: usize) -> Result<Vec<(Value, usize)>, Error> {
+    let dir = dir.as_ref();
+    let mut chain = vec![read_record(dir, epoch)?];
+    while let Some(parent) = chain.last().and_then(|r| r.parent) {
+        chain.push(read_record(dir, parent)?);
+    }
+    let keyframe = chain.pop().expect("The chain can't be empty");
+    let path = dir.join("objects").join(format!("{}.json", keyframe.hash));
+    let data = fs::read_to_string(path)?;
+    let mut entries = Entries::new();
+    for (allele, count) in serde_json::from_str::<Vec<(Value, usize)>>(&data)? {
+        entries.insert(allele.to_string(), count);
+    }
+    for record in chain.into_iter().rev() {
+        for (allele, count) in record.changed {
+            entries.insert(allele.to_string(), count);
+        }
+        for allele in record.removed {
+            entries.remove(&allele.to_string());
+        }
+    }
+    pairs(&entries)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_soup_store() {
+        let dir = std::env::temp_dir().join(format!("berbalang_soup_{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut store = SoupStore::new(&dir, 3);
+        let soups = vec![
+            vec![(1_u64, 10), (2, 5), (3, 1), (4, 1)],
+            vec![(1, 11), (2, 5), (3, 1), (4, 1)],
+            vec![(1, 11), (2, 5), (4, 2)],
+            vec![(1, 12), (2, 5), (4, 2)],
+        ];
+        let mut records = Vec::new();
+        for (epoch, soup) in soups.iter().enumerate() {
+            let soup = soup.iter().cloned().collect::<HashMap<u64, usize>>();
+            records.push(store.record(epoch, &soup).unwrap());
+        }
+        assert_eq!(records[0].parent, None);
+        assert_eq!(records[1].parent, Some(0));
+        assert_eq!(records[1].changed, vec![(Value::from(1), 11)]);
+        assert_eq!(records[2].removed, vec![Value::from(3)]);
+        assert_eq!(records[3].parent, None, "a keyframe is due");
+
+        for (epoch, soup) in soups.iter().enumerate() {
+            let expected = soup
+                .iter()
+                .map(|(a, n)| (Value::from(*a), *n))
+                .collect::<Vec<_>>();
+            assert_eq!(load(&dir, epoch).unwrap(), expected);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1 +1,204 @@
+use serde::Serialize;
 
+/// The result of a two-sided Mann-Whitney U test.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MannWhitney {
+    /// The U statistic of the first sample
+    pub u: f64,
+    /// The standard score of `u`, under the normal approximation
+    pub z: f64,
+    pub p_value: f64,
+}
+
+/// Two-sided Mann-Whitney U test, using the normal approximation with a
+/// correction for ties, which is reasonable once both samples have more than
+/// a handful of observations. Returns `None` if either sample is empty.
+pub fn mann_whitney_u(a: &[f64], b: &[f64]) -> Option<MannWhitney> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+    let n1 = a.len() as f64;
+    let n2 = b.len() as f64;
+    let mut pooled = a
+        .iter()
+        .map(|x| (*x, true))
+        .chain(b.iter().map(|x| (*x, false)))
+        .collect::<Vec<(f64, bool)>>();
+    pooled.sort_by(|x, y| x.0.partial_cmp(&y.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    // assign mid-ranks to ties, accumulating the tie correction term
+    let mut rank_sum_a = 0.0;
+    let mut tie_term = 0.0;
+    let mut i = 0;
+    while i < pooled.len() {
+        let mut j = i;
+        while j + 1 < pooled.len() && pooled[j + 1].0 == pooled[i].0 {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        let t = (j - i + 1) as f64;
+        tie_term += t * t * t - t;
+        rank_sum_a += rank * pooled[i..=j].iter().filter(|p| p.1).count() as f64;
+        i = j + 1;
+    }
+
+    let u = rank_sum_a - n1 * (n1 + 1.0) / 2.0;
+    let n = n1 + n2;
+    let mean = n1 * n2 / 2.0;
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - tie_term / (n * (n - 1.0)));
+    let z = if variance > 0.0 {
+        (u - mean) / variance.sqrt()
+    } else {
+        0.0
+    };
+    let p_value = (erfc(z.abs() / std::f64::consts::SQRT_2)).min(1.0);
+    Some(MannWhitney { u, z, p_value })
+}
+
+/// The complementary error function, after Numerical Recipes' Chebyshev
+/// approximation, which is good to about 1.2e-7.
+pub fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t
+        * (-z * z - 1.265_512_23
+            + t * (1.000_023_68
+                + t * (0.374_091_96
+                    + t * (0.096_784_18
+                        + t * (-0.186_288_06
+                            + t * (0.278_868_07
+                                + t * (-1.135_203_98
+                                    + t * (1.488_515_87
+                                        + t * (-0.822_152_23 + t * 0.170_872_77)))))))))
+            .exp();
+    if x >= 0.0 {
+        r
+    } else {
+        2.0 - r
+    }
+}
+
+pub fn median(xs: &[f64]) -> f64 {
+    if xs.is_empty() {
+        return std::f64::NAN;
+    }
+    let mut xs = xs.to_vec();
+    xs.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mid = xs.len() / 2;
+    if xs.len() % 2 == 0 {
+        (xs[mid - 1] + xs[mid]) / 2.0
+    } else {
+        xs[mid]
+    }
+}
+
+/// The `p`th percentile of the sorted values, interpolating linearly
+/// between the closest ranks.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return std::f64::NAN;
+    }
+    let rank = (p / 100.0).max(0.0).min(1.0) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Histogram {
+    pub min: f64,
+    pub max: f64,
+    /// The counts of the values in each of the equal-width bins spanning
+    /// `min` to `max`, the last bin being closed
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    /// Bins the values, ignoring any NaNs. Returns `None` if there are no
+    /// values, or no bins.
+    pub fn of(xs: &[f64], bins: usize) -> Option<Self> {
+        let xs = xs
+            .iter()
+            .cloned()
+            .filter(|x| !x.is_nan())
+            .collect::<Vec<f64>>();
+        if xs.is_empty() || bins == 0 {
+            return None;
+        }
+        let min = xs.iter().cloned().fold(std::f64::INFINITY, f64::min);
+        let max = xs.iter().cloned().fold(std::f64::NEG_INFINITY, f64::max);
+        let width = (max - min) / bins as f64;
+        let mut counts = vec![0; bins];
+        for x in xs {
+            let bin = if width > 0.0 {
+                (((x - min) / width) as usize).min(bins - 1)
+            } else {
+                0
+            };
+            counts[bin] += 1;
+        }
+        Some(Self { min, max, counts })
+    }
+
+    /// The lower and upper bounds of the `i`th bin.
+    pub fn bounds(&self, i: usize) -> (f64, f64) {
+        let width = (self.max - self.min) / self.counts.len() as f64;
+        let lower = self.min + width * i as f64;
+        (lower, lower + width)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_erfc() {
+        assert!((erfc(0.0) - 1.0).abs() < 1e-6);
+        assert!((erfc(1.0) - 0.157_299_207).abs() < 1e-6);
+        assert!((erfc(-1.0) - 1.842_700_793).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_mann_whitney_u() {
+        // four tied pairs, in the middle of the ranking
+        let a = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+        let b = [5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0];
+        let result = mann_whitney_u(&a, &b).unwrap();
+        assert_eq!(result.u, 8.0);
+        assert!((result.z + 2.527_95).abs() < 1e-4, "{:?}", result);
+        assert!((result.p_value - 0.011_47).abs() < 1e-4, "{:?}", result);
+
+        let same = mann_whitney_u(&a, &a).unwrap();
+        assert_eq!(same.u, 32.0);
+        assert!((same.p_value - 1.0).abs() < 1e-6);
+
+        assert!(mann_whitney_u(&a, &[]).is_none());
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&[3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(&[4.0, 1.0, 2.0, 3.0]), 2.5);
+    }
+
+    #[test]
+    fn test_percentile() {
+        let xs = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(percentile(&xs, 50.0), 3.0);
+        assert!((percentile(&xs, 10.0) - 1.4).abs() < 1e-9);
+        assert!((percentile(&xs, 90.0) - 4.6).abs() < 1e-9);
+        assert_eq!(percentile(&xs, 100.0), 5.0);
+        assert!(percentile(&[], 50.0).is_nan());
+    }
+
+    #[test]
+    fn test_histogram() {
+        let histogram = Histogram::of(&[0.0, 0.1, 0.5, 0.9, 1.0, std::f64::NAN], 4).unwrap();
+        assert_eq!(histogram.counts, vec![2, 0, 1, 2]);
+        assert_eq!(histogram.bounds(1), (0.25, 0.5));
+        let flat = Histogram::of(&[2.0, 2.0], 3).unwrap();
+        assert_eq!(flat.counts, vec![2, 0, 0]);
+        assert!(Histogram::of(&[], 3).is_none());
+    }
+}